@@ -0,0 +1,76 @@
+#import gpubasics::global::bindings::{camera, projection, camera_model, projection_invt};
+#import gpubasics::global::depth::FAR_DEPTH;
+
+// Laid out like `Billboard` on the CPU side.
+struct Billboard {
+    position: vec3<f32>,
+    // Width and height of the quad in world units.
+    size: f32,
+    color: vec4<f32>,
+    sprite: u32,
+};
+
+struct BillboardParams {
+    // Distance over which sprites fade in front of the scene behind them in x,
+    // number of sprites in the sheet in y.
+    params: vec4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> billboard: BillboardParams;
+@group(1) @binding(1) var<storage, read> billboards: array<Billboard>;
+@group(1) @binding(2) var scene_depth: texture_depth_2d;
+@group(1) @binding(3) var sprite_texture: texture_2d<f32>;
+@group(1) @binding(4) var sprite_sampler: sampler;
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    // View space z of the quad, negative in front of the camera.
+    @location(2) view_z: f32,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32, @builtin(instance_index) instance: u32) -> VertexOut {
+    var CORNERS: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+        vec2<f32>(-0.5, -0.5),
+        vec2<f32>(0.5, -0.5),
+        vec2<f32>(-0.5, 0.5),
+        vec2<f32>(-0.5, 0.5),
+        vec2<f32>(0.5, -0.5),
+        vec2<f32>(0.5, 0.5)
+    );
+
+    var b = billboards[instance];
+    var corner = CORNERS[vertex];
+    // Spanned by right and up axes of the camera, so the quad always faces it.
+    var right = camera_model[0].xyz;
+    var up = camera_model[1].xyz;
+    var world = b.position + (right * corner.x + up * corner.y) * b.size;
+    var view = camera * vec4(world, 1.0);
+
+    var sprites = billboard.params.y;
+    var o: VertexOut;
+    o.position = projection * view;
+    o.uv = vec2((f32(b.sprite) + corner.x + 0.5) / sprites, 0.5 - corner.y);
+    o.color = b.color;
+    o.view_z = view.z;
+    return o;
+}
+
+// Sprites aren't cut off where they cross geometry, they fade out as they get closer to it.
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    var color = textureSample(sprite_texture, sprite_sampler, in.uv) * in.color;
+
+    var depth = textureLoad(scene_depth, vec2<i32>(in.position.xy), 0);
+    var fade = 1.0;
+    if depth != FAR_DEPTH {
+        var uv = in.position.xy / vec2<f32>(textureDimensions(scene_depth));
+        var view = projection_invt * vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+        var scene_z = view.z / view.w;
+        fade = clamp((in.view_z - scene_z) / max(billboard.params.x, 0.0001), 0.0, 1.0);
+    }
+
+    return vec4(color.rgb, color.a * fade);
+}
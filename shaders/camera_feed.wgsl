@@ -0,0 +1,75 @@
+#import gpubasics::global::bindings::{camera, projection};
+#import gpubasics::phong::fragment::{fragmentNormal, fragmentDiffuse, fragmentAmbient, fragmentEmissive};
+#import gpubasics::forward::buffers::instance::{Instance, model, model_invt};
+#import gpubasics::forward::buffers::vertex::Vertex;
+#import gpubasics::forward::outputs::vertex::VertexOutput;
+
+// Scene seen by a camera of `CameraFeedPass`, bound in group 0 in place of the main one.
+// Like reflections in water, it's lit only by the sun, without shadows.
+struct Feed {
+    // Direction the sun shines in.
+    sun_direction: vec4<f32>,
+    sun_ambient: vec4<f32>,
+    sun_diffuse: vec4<f32>,
+    // Normal and distance of the plane mirrors reflect through, zeros for cameras.
+    mirror: vec4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> feed: Feed;
+
+fn mirrorDistance(world: vec4<f32>) -> f32 {
+    return dot(feed.mirror.xyz, world.xyz) + feed.mirror.w;
+}
+
+// World position reflected through the mirror plane.
+fn mirrored(world: vec4<f32>) -> vec4<f32> {
+    return vec4(world.xyz - 2.0 * mirrorDistance(world) * feed.mirror.xyz, world.w);
+}
+
+@vertex
+fn vs_main(v: Vertex, i: Instance) -> VertexOutput {
+    var model = model(i);
+    var inv_model_t = model_invt(i);
+
+    var world_v = model * vec4<f32>(v.model_v, 1.0);
+    var camera_v = camera * mirrored(world_v);
+
+    var out: VertexOutput;
+    out.position = projection * camera_v;
+    out.w_pos = world_v;
+    out.c_pos = camera_v;
+    out.material = i.material;
+
+    #ifndef VERTEX_PNTBUV
+    out.normal = normalize(inv_model_t * vec4(v.normal_v, 0.0));
+    #endif
+
+    #ifdef VERTEX_PNTBUV
+    out.t = normalize(inv_model_t * vec4(v.tangent_v, 0.0)).xyz;
+    out.n = normalize(inv_model_t * vec4(v.normal_v, 0.0)).xyz;
+    out.t = normalize(out.t - dot(out.n, out.t) * out.n);
+    out.b = cross(out.n, out.t);
+    #endif
+
+    #ifndef VERTEX_PN
+    out.uv = v.uv;
+    #endif
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Whatever is behind a mirror can't be reflected.
+    if mirrorDistance(in.w_pos) < 0.0 {
+        discard;
+    }
+
+    var normal = fragmentNormal(in);
+    var diffuse = max(dot(normal, -feed.sun_direction.xyz), 0.0);
+    var color = feed.sun_ambient.rgb * fragmentAmbient(in)
+        + feed.sun_diffuse.rgb * diffuse * fragmentDiffuse(in)
+        + fragmentEmissive(in);
+
+    return vec4(color, 1.0);
+}
@@ -6,58 +6,76 @@
 @group(0) @binding(0) var output: texture_storage_2d<rgba8unorm, write>;
 #endif
 
+#ifdef RGBA16FLOAT
+@group(0) @binding(0) var output: texture_storage_2d<rgba16float, write>;
+#endif
+
 #ifdef BGRA8UNORM
 @group(0) @binding(0) var output: texture_storage_2d<bgra8unorm, write>;
 #endif
 
 @group(0) @binding(1) var input: texture_2d<f32>;
-@group(0) @binding(2) var tex_sampler: sampler;
 struct Flip {
     value: u32
 };
-struct FilterSize {
-    value: u32
+
+// Written by `GaussianKernel`, weights are normalized over both sides of the center texel.
+struct Filter {
+    // Radius of the kernel in x.
+    radius: vec4<f32>,
+    // Weights of offsets from the center, four in each element.
+    weights: array<vec4<f32>, #{MAX_WEIGHTS_VEC4}>,
 };
 
-@group(0) @binding(3) var<uniform> flip: Flip;
-@group(0) @binding(4) var<uniform> filter_size: FilterSize;
+@group(0) @binding(2) var<uniform> flip: Flip;
+@group(0) @binding(3) var<uniform> gaussian: Filter;
+
+// Dimensions come from `BlurPass::KERNEL`. Every thread fetches INVOCATION_EXTENT_X x
+// INVOCATION_EXTENT_Y piece of a texture, which makes a WORKGROUP_EXTENT_X x WORKGROUP_EXTENT_Y
+// block for a workgroup.
+var<workgroup> shared_mem: array<array<vec3f, #{WORKGROUP_EXTENT_X}>, #{WORKGROUP_EXTENT_Y}>;
 
-// Every thread is fetching 4x4 piece of a texture.
-// There are 32 threads in a workgroup, so we are having 128x4 pixels fetched.
-var<workgroup> shared_mem: array<array<vec3f, 128>, 4>;
+fn weight(offset: u32) -> f32 {
+    return gaussian.weights[offset / 4u][offset % 4u];
+}
 
-@compute @workgroup_size(32, 1, 1)
+@compute @workgroup_size(#{WORKGROUP_SIZE_X}, #{WORKGROUP_SIZE_Y}, #{WORKGROUP_SIZE_Z})
 fn blur(@builtin(workgroup_id) WorkGroupID: vec3u, @builtin(local_invocation_id) LocalInvocationID: vec3u) {
-    var imageDim = textureDimensions(input);
-    var filterCenter = (filter_size.value - 1) / 2;
-    var baseIndex = WorkGroupID.xy * vec2(128 - filter_size.value - 1, 4) + LocalInvocationID.xy * vec2(4, 1) - vec2(filterCenter, 0);
+    var imageDim = vec2<i32>(textureDimensions(input));
+    var radius = u32(gaussian.radius.x);
+    var invocationExtent = vec2(#{INVOCATION_EXTENT_X}u, #{INVOCATION_EXTENT_Y}u);
+    // Texels within radius of both ends of a row are only read, so workgroups overlap by twice the radius.
+    var stride = vec2(#{WORKGROUP_EXTENT_X}u - 2u * radius, #{WORKGROUP_EXTENT_Y}u);
+    var baseIndex = vec2<i32>(WorkGroupID.xy * stride + LocalInvocationID.xy * invocationExtent) - vec2(i32(radius), 0);
+    var sharedBase = LocalInvocationID.xy * invocationExtent;
 
-    for (var r = 0; r < 4; r += 1) {
-        for (var c = 0; c < 4; c += 1) {
-            var coord = baseIndex + vec2(u32(c), u32(r));
+    for (var r = 0u; r < invocationExtent.y; r += 1u) {
+        for (var c = 0u; c < invocationExtent.x; c += 1u) {
+            var coord = baseIndex + vec2(i32(c), i32(r));
             if flip.value == 1u {
                 coord = coord.yx;
             }
 
-            shared_mem[r][4 * LocalInvocationID.x + u32(c)] = textureSampleLevel(input, tex_sampler, (vec2f(coord) + vec2f(0.25, 0.25)) / vec2f(imageDim), 0.0).rgb;
+            // Texels past the edges repeat the ones on them.
+            shared_mem[sharedBase.y + r][sharedBase.x + c] = textureLoad(input, clamp(coord, vec2(0), imageDim - 1), 0).rgb;
         }
     }
 
     workgroupBarrier();
 
-    for (var r = 0; r < 4; r += 1) {
-        for (var c = 0; c < 4; c += 1) {
-            var writeIndex = baseIndex + vec2(u32(c), u32(r));
+    for (var r = 0u; r < invocationExtent.y; r += 1u) {
+        for (var c = 0u; c < invocationExtent.x; c += 1u) {
+            var writeIndex = baseIndex + vec2(i32(c), i32(r));
             if flip.value == 1u {
                 writeIndex = writeIndex.yx;
             }
 
-            let center = i32(4 * LocalInvocationID.x + u32(c));
-            if center >= i32(filterCenter) && center < 128 - i32(filterCenter) && all(writeIndex < imageDim) {
-                var acc = vec3(0.0, 0.0, 0.0);
-                for (var i = 0; u32(i) < filter_size.value; i += 1) {
-                    var f = center + i - i32(filterCenter);
-                    acc += (1.0 / f32(filter_size.value)) * shared_mem[r][f];
+            let center = sharedBase.x + c;
+            if center >= radius && center < #{WORKGROUP_EXTENT_X}u - radius && all(writeIndex >= vec2(0)) && all(writeIndex < imageDim) {
+                var row = sharedBase.y + r;
+                var acc = weight(0u) * shared_mem[row][center];
+                for (var i = 1u; i <= radius; i += 1u) {
+                    acc += weight(i) * (shared_mem[row][center - i] + shared_mem[row][center + i]);
                 }
                 textureStore(output, writeIndex, vec4(acc, 1.0));
             }
@@ -0,0 +1,52 @@
+const PI: f32 = 3.14159265;
+
+// Rgba32Float isn't filterable everywhere, so it's read with textureLoad and filtered by hand.
+@group(0) @binding(0) var equirect: texture_2d<f32>;
+// Faces in the order of cube map layers: +X, -X, +Y, -Y, +Z, -Z.
+@group(0) @binding(1) var cube: texture_storage_2d_array<rgba16float, write>;
+
+fn faceDirection(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    switch face {
+        case 0u: { return vec3<f32>(1.0, -uv.y, -uv.x); }
+        case 1u: { return vec3<f32>(-1.0, -uv.y, uv.x); }
+        case 2u: { return vec3<f32>(uv.x, 1.0, uv.y); }
+        case 3u: { return vec3<f32>(uv.x, -1.0, -uv.y); }
+        case 4u: { return vec3<f32>(uv.x, -uv.y, 1.0); }
+        default: { return vec3<f32>(-uv.x, -uv.y, -1.0); }
+    }
+}
+
+fn load(texel: vec2<i32>, size: vec2<i32>) -> vec4<f32> {
+    // Wraps around horizontally, clamps at the poles.
+    let wrapped = vec2<i32>((texel.x % size.x + size.x) % size.x, clamp(texel.y, 0, size.y - 1));
+    return textureLoad(equirect, wrapped, 0);
+}
+
+fn sampleBilinear(uv: vec2<f32>) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(equirect));
+    let position = uv * vec2<f32>(size) - 0.5;
+    let base = vec2<i32>(floor(position));
+    let f = fract(position);
+
+    let top = mix(load(base, size), load(base + vec2<i32>(1, 0), size), f.x);
+    let bottom = mix(load(base + vec2<i32>(0, 1), size), load(base + vec2<i32>(1, 1), size), f.x);
+    return mix(top, bottom, f.y);
+}
+
+@compute @workgroup_size(#{WORKGROUP_SIZE_X}, #{WORKGROUP_SIZE_Y}, #{WORKGROUP_SIZE_Z})
+fn convert(@builtin(global_invocation_id) id: vec3<u32>) {
+    let face_size = textureDimensions(cube).x;
+    if id.x >= face_size || id.y >= face_size {
+        return;
+    }
+
+    let uv = (vec2<f32>(id.xy) + 0.5) / f32(face_size) * 2.0 - 1.0;
+    let direction = normalize(faceDirection(id.z, uv));
+
+    let equirect_uv = vec2<f32>(
+        atan2(direction.z, direction.x) / (2.0 * PI) + 0.5,
+        acos(clamp(direction.y, -1.0, 1.0)) / PI
+    );
+
+    textureStore(cube, id.xy, id.z, vec4<f32>(sampleBilinear(equirect_uv).rgb, 1.0));
+}
@@ -0,0 +1,85 @@
+// Kernel dimensions come from `HistogramPass`, a workgroup has an invocation
+// for every bin of the histogram: HISTOGRAM_BINS = WORKGROUP_SIZE_X * WORKGROUP_SIZE_Y.
+
+struct Params {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+};
+
+// Laid out like `Histogram` is read back on the CPU side.
+struct HistogramResult {
+    // Of pixels outside of the first, black bin.
+    average_log_luminance: f32,
+    lit: u32,
+    total: u32,
+    bins: array<u32, #{HISTOGRAM_BINS}>,
+};
+
+@group(0) @binding(0) var input: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> params: Params;
+@group(0) @binding(2) var<storage, read_write> histogram: array<atomic<u32>, #{HISTOGRAM_BINS}>;
+@group(0) @binding(3) var<storage, read_write> result: HistogramResult;
+
+var<workgroup> bins: array<atomic<u32>, #{HISTOGRAM_BINS}>;
+var<workgroup> weighted: array<f32, #{HISTOGRAM_BINS}>;
+
+// Black pixels get the first bin, which is left out of the average.
+fn bin(color: vec3<f32>) -> u32 {
+    var luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    if luminance < 0.0001 {
+        return 0u;
+    }
+
+    var t = saturate((log2(luminance) - params.min_log_luminance) / params.log_luminance_range);
+    return u32(t * f32(#{HISTOGRAM_BINS}u - 2u)) + 1u;
+}
+
+// Pixels are counted into bins shared by the workgroup first, so the global
+// histogram gets a single atomic per bin and workgroup.
+@compute @workgroup_size(#{WORKGROUP_SIZE_X}, #{WORKGROUP_SIZE_Y}, #{WORKGROUP_SIZE_Z})
+fn build_histogram(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) localIdx: u32,
+) {
+    atomicStore(&bins[localIdx], 0u);
+    workgroupBarrier();
+
+    var size = textureDimensions(input);
+    if all(id.xy < size) {
+        var color = textureLoad(input, vec2<i32>(id.xy), 0).rgb;
+        atomicAdd(&bins[bin(color)], 1u);
+    }
+    workgroupBarrier();
+
+    atomicAdd(&histogram[localIdx], atomicLoad(&bins[localIdx]));
+}
+
+// Dispatched as a single workgroup once the histogram is complete. Moves it into
+// the result, leaving it cleared for the next one.
+@compute @workgroup_size(#{WORKGROUP_SIZE_X}, #{WORKGROUP_SIZE_Y}, #{WORKGROUP_SIZE_Z})
+fn reduce_histogram(@builtin(local_invocation_index) localIdx: u32) {
+    var count = atomicExchange(&histogram[localIdx], 0u);
+    result.bins[localIdx] = count;
+    weighted[localIdx] = f32(count) * f32(localIdx);
+    workgroupBarrier();
+
+    for (var stride = #{HISTOGRAM_BINS}u / 2u; stride > 0u; stride /= 2u) {
+        if localIdx < stride {
+            weighted[localIdx] += weighted[localIdx + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if localIdx == 0u {
+        var size = textureDimensions(input);
+        result.total = size.x * size.y;
+        // Count of the first, black bin.
+        result.lit = result.total - count;
+
+        result.average_log_luminance = params.min_log_luminance;
+        if result.lit > 0u {
+            var averageBin = weighted[0] / f32(result.lit) - 1.0;
+            result.average_log_luminance = averageBin / f32(#{HISTOGRAM_BINS}u - 2u) * params.log_luminance_range + params.min_log_luminance;
+        }
+    }
+}
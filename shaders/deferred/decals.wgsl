@@ -0,0 +1,95 @@
+#import gpubasics::global::bindings::{camera, projection, scene_frame};
+#import gpubasics::global::depth::FAR_DEPTH;
+#import gpubasics::materials::atlas::{material, sampleSrgb, sampleLinear};
+
+// Laid out like `Decal` on the CPU side.
+struct Decal {
+    model: mat4x4<f32>,
+    // Takes world space into the box, spanning -0.5..0.5 along every axis.
+    model_inv: mat4x4<f32>,
+    material_index: u32,
+    opacity: f32,
+};
+
+struct DecalParams {
+    // Cosine of the angle between normals of surfaces and the projection axis past which
+    // the decal fades out, in x.
+    fade: vec4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> params: DecalParams;
+@group(1) @binding(1) var<storage, read> decals: array<Decal>;
+@group(1) @binding(2) var g_depth: texture_depth_2d;
+
+// Surfaces past the fade angle are gone this much closer to being parallel to the projection axis.
+const FADE_WIDTH: f32 = 0.15;
+
+struct VolumeOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) @interpolate(flat) decal: u32,
+};
+
+struct DecalOutput {
+    @location(0) g_normal: vec4<f32>,
+    @location(1) g_diffuse: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>, @builtin(instance_index) instance: u32) -> VolumeOutput {
+    var out: VolumeOutput;
+    out.decal = instance;
+    out.position = projection * camera * decals[instance].model * vec4(position, 1.0);
+    // Back faces behind the far plane are kept on it, so boxes bigger than the view still project.
+#ifdef REVERSE_Z
+    out.position.z = max(out.position.z, 0.0);
+#else
+    out.position.z = min(out.position.z, out.position.w);
+#endif
+
+    return out;
+}
+
+// Back faces of the box are drawn, so it covers the screen even with the camera inside.
+// Whatever the depth buffer holds at a pixel gets the decal if it's inside the box.
+@fragment
+fn fs_main(in: VolumeOutput) -> DecalOutput {
+    var decal = decals[in.decal];
+
+    var depth = textureLoad(g_depth, vec2<i32>(in.position.xy), 0);
+    var uv = in.position.xy / scene_frame.viewport;
+    var clip = scene_frame.inverse_view_projection * vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    var world = clip.xyz / clip.w;
+    // Faceted normal of the surface, enough to tell how steeply the decal hits it.
+    var surfaceNormal = normalize(cross(dpdy(world), dpdx(world)));
+
+    var local = (decal.model_inv * vec4(world, 1.0)).xyz;
+    if depth == FAR_DEPTH || any(abs(local) > vec3(0.5)) {
+        discard;
+    }
+
+    var tangent = normalize(decal.model[0].xyz);
+    var normal = normalize(decal.model[1].xyz);
+    var bitangent = cross(normal, tangent);
+
+    var facing = dot(surfaceNormal, normal);
+    var fade = smoothstep(params.fade.x, min(params.fade.x + FADE_WIDTH, 1.0), facing);
+
+    var m = material(decal.material_index);
+    var decalUv = local.xz + 0.5;
+    var color = vec4(m.diffuse.rgb, 1.0);
+    if m.diffuse_t.y >= 0 {
+        color = sampleSrgb(m.diffuse_t, decalUv);
+    }
+    var alpha = color.a * decal.opacity * fade;
+
+    var out: DecalOutput;
+    out.g_diffuse = vec4(color.rgb, alpha);
+    // Decals without a normal map leave normals of the surface alone.
+    out.g_normal = vec4(0.0);
+    if m.normal_t.y >= 0 {
+        var t = sampleLinear(m.normal_t, decalUv).xyz * 2.0 - 1.0;
+        out.g_normal = vec4(normalize(tangent * t.x + bitangent * t.y + normal * t.z), alpha);
+    }
+
+    return out;
+}
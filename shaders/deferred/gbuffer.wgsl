@@ -0,0 +1,14 @@
+#define_import_path gpubasics::deferred::gbuffer
+
+// Shininess is kept in 8-bit alpha of the specular G-Buffer. Storing its logarithm
+// spends the precision where highlights change the most - low exponents - and lets
+// values above 256 survive instead of saturating.
+const MAX_SHININESS: f32 = 2048.0;
+
+fn encodeShininess(shininess: f32) -> f32 {
+    return log2(clamp(shininess, 1.0, MAX_SHININESS)) / log2(MAX_SHININESS);
+}
+
+fn decodeShininess(encoded: f32) -> f32 {
+    return exp2(encoded * log2(MAX_SHININESS));
+}
@@ -0,0 +1,43 @@
+#import gpubasics::deferred::shaders::screen_quad_vs::screenQuad;
+#import gpubasics::deferred::outputs::vertex::VertexOutput;
+#import gpubasics::deferred::geometry::bindings::{g_normal, g_diffuse, g_specular, g_depth};
+
+// Shows a single buffer of the G-Buffer, picked by the fragment entry point.
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    return screenQuad(in_vertex_index);
+}
+
+// Buffers are read with `textureLoad`, at the texel under the pixel of the viewport drawn into.
+fn texel(in: VertexOutput) -> vec2<i32> {
+    var size = vec2<f32>(textureDimensions(g_depth));
+    return vec2<i32>(clamp(in.uv * size, vec2(0.0), size - 1.0));
+}
+
+@fragment
+fn fs_normal(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureLoad(g_normal, texel(in), 0);
+}
+
+@fragment
+fn fs_diffuse(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureLoad(g_diffuse, texel(in), 0);
+}
+
+@fragment
+fn fs_specular(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureLoad(g_specular, texel(in), 0);
+}
+
+@fragment
+fn fs_depth(in: VertexOutput) -> @location(0) vec4<f32> {
+    var depth = textureLoad(g_depth, texel(in), 0);
+    #ifdef REVERSE_Z
+    depth = 1.0 - depth;
+    #endif
+    var linearDepth = (2.0 * 0.1 * 100.0) / (100.0 + 0.1 - depth * (100.0 - 0.1));
+    linearDepth /= 100.0;
+
+    return vec4(linearDepth, linearDepth, linearDepth, 1.0);
+}
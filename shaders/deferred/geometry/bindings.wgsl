@@ -0,0 +1,8 @@
+#define_import_path gpubasics::deferred::geometry::bindings
+
+// Laid out as `BindGroupLayouts::gbuffer`, in the group given by GBUFFER_GROUP.
+@group(#{GBUFFER_GROUP}) @binding(0) var g_normal: texture_2d<f32>;
+@group(#{GBUFFER_GROUP}) @binding(1) var g_diffuse: texture_2d<f32>;
+@group(#{GBUFFER_GROUP}) @binding(2) var g_specular: texture_2d<f32>;
+@group(#{GBUFFER_GROUP}) @binding(3) var g_emissive: texture_2d<f32>;
+@group(#{GBUFFER_GROUP}) @binding(4) var g_depth: texture_depth_2d;
@@ -0,0 +1,93 @@
+#import gpubasics::deferred::shaders::screen_quad_vs::screenQuad;
+#import gpubasics::deferred::outputs::vertex::{VertexOutput};
+#import gpubasics::deferred::ssao::fragment::{cameraPos, normal, noise};
+#import gpubasics::deferred::ssao::bindings::params;
+#import gpubasics::deferred::geometry::bindings::g_depth;
+#import gpubasics::global::bindings::{projection, projection_invt};
+
+const PI: f32 = 3.14159265;
+// Set by `SsaoPass` from settings, without composing the shader again.
+override GTAO_SLICES: u32 = 4u;
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    return screenQuad(in_vertex_index);
+}
+
+fn viewPos(uv: vec2<f32>) -> vec3<f32> {
+    var size = vec2<f32>(textureDimensions(g_depth));
+    var coords = vec2<i32>(clamp(uv, vec2(0.0), vec2(1.0)) * (size - 1.0));
+    var depth = textureLoad(g_depth, coords, 0);
+
+    var view = projection_invt * vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    return view.xyz / view.w;
+}
+
+// Cosine of the highest horizon found marching from `uv` in the given screen direction.
+fn horizonCos(pos: vec3<f32>, viewDir: vec3<f32>, uv: vec2<f32>, dir: vec2<f32>, steps: u32) -> f32 {
+    var horizon = -1.0;
+
+    for (var i = u32(1); i <= steps; i += u32(1)) {
+        var samplePos = viewPos(uv + dir * (f32(i) / f32(steps)));
+        var delta = samplePos - pos;
+        var dist = length(delta);
+
+        if dist > 0.0001 && dist < params.radius {
+            // Fade out occluders near the edge of the radius.
+            var falloff = 1.0 - dist / params.radius;
+            horizon = max(horizon, mix(-1.0, dot(delta / dist, viewDir), falloff));
+        }
+    }
+
+    return horizon;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) f32 {
+    var n = normal(in);
+    var pos = cameraPos(in).xyz + n * params.bias;
+    var viewDir = normalize(-pos);
+    var noise = noise(in).xy;
+
+    var steps = max(u32(params.num_samples) / GTAO_SLICES, u32(1));
+
+    // Radius projected to the screen, in texture coordinates.
+    var center = projection * vec4(pos, 1.0);
+    var offset = projection * vec4(pos + vec3(params.radius, 0.0, 0.0), 1.0);
+    var screenRadius = abs(offset.x / offset.w - center.x / center.w) * 0.5;
+
+    var visibility = 0.0;
+    for (var slice = u32(0); slice < GTAO_SLICES; slice += u32(1)) {
+        var phi = atan2(noise.y, noise.x) + PI * f32(slice) / f32(GTAO_SLICES);
+        var dir = vec2(cos(phi), sin(phi));
+
+        // Slice plane spanned by the screen direction and the view vector.
+        var sliceDir = vec3(dir.x, -dir.y, 0.0);
+        var axis = normalize(cross(sliceDir, viewDir));
+        var orthoDir = cross(viewDir, axis);
+
+        var projN = n - axis * dot(n, axis);
+        var projLen = length(projN);
+        if projLen < 0.0001 {
+            visibility += 1.0;
+            continue;
+        }
+
+        var cosN = clamp(dot(projN, viewDir) / projLen, -1.0, 1.0);
+        var angleN = sign(dot(projN, orthoDir)) * acos(cosN);
+
+        var h0 = -acos(horizonCos(pos, viewDir, in.uv, -dir * screenRadius, steps));
+        var h1 = acos(horizonCos(pos, viewDir, in.uv, dir * screenRadius, steps));
+        h0 = angleN + max(h0 - angleN, -PI * 0.5);
+        h1 = angleN + min(h1 - angleN, PI * 0.5);
+
+        var sinN = sin(angleN);
+        var arc0 = -cos(2.0 * h0 - angleN) + cosN + 2.0 * h0 * sinN;
+        var arc1 = -cos(2.0 * h1 - angleN) + cosN + 2.0 * h1 * sinN;
+
+        visibility += projLen * 0.25 * (arc0 + arc1);
+    }
+
+    visibility = clamp(visibility / f32(GTAO_SLICES), 0.0, 1.0);
+    return pow(visibility, params.intensity);
+}
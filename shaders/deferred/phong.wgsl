@@ -1,15 +1,124 @@
-#import gpubasics::deferred::shaders::screen_quad_vs::screenQuad;
-#import gpubasics::deferred::outputs::vertex::VertexOutput;
-#import gpubasics::phong::functions::fragmentLight;
+#import gpubasics::phong::bindings::{lights, point_ambient};
+#import gpubasics::deferred::geometry::bindings::g_depth;
+#import gpubasics::deferred::phong::bindings::output;
+#import gpubasics::deferred::phong::fragment::{cameraPos, screenInput};
+#import gpubasics::global::bindings::{camera, projection_invt};
+#import gpubasics::global::depth::FAR_DEPTH;
+#import gpubasics::phong::culling::lightReachesAabb;
+#import gpubasics::phong::fragment::{fragmentAmbient, fragmentEmissive, fragmentOcclusion};
+#import gpubasics::phong::functions::{calculateDirectional, calculateSpot, applyReflection};
+#import gpubasics::fog::volume::applyFog;
+#import gpubasics::fog::distance::applyDistanceFog;
 
-@vertex
-fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
-    return screenQuad(in_vertex_index);
+// Tile size and light list capacity come from `PhongPass`: TILE_SIZE, MAX_TILE_LIGHTS.
+// A workgroup covers a single tile.
+const TILE_THREADS: u32 = #{TILE_SIZE}u * #{TILE_SIZE}u;
+
+var<workgroup> tileDepthMin: atomic<u32>;
+var<workgroup> tileDepthMax: atomic<u32>;
+var<workgroup> tileLightCount: atomic<u32>;
+var<workgroup> tileLights: array<u32, #{MAX_TILE_LIGHTS}>;
+
+fn onNearPlane(ndc: vec2<f32>) -> vec3<f32> {
+    var view = projection_invt * vec4(ndc, 0.0, 1.0);
+    return view.xyz / view.w;
 }
 
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    var color = fragmentLight(in);
+// Lights every pixel of a tile using only the spot lights which reach the tile's frustum,
+// bounded by the closest and farthest depth inside the tile. Point lights are added
+// afterwards by rendering their volumes.
+@compute @workgroup_size(#{WORKGROUP_SIZE_X}, #{WORKGROUP_SIZE_Y}, #{WORKGROUP_SIZE_Z})
+fn tiled_lighting(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(workgroup_id) tile: vec3<u32>,
+    @builtin(local_invocation_index) localIdx: u32,
+) {
+    if localIdx == 0u {
+        atomicStore(&tileDepthMin, 0xffffffffu);
+        atomicStore(&tileDepthMax, 0u);
+        atomicStore(&tileLightCount, 0u);
+    }
+    workgroupBarrier();
+
+    var size = textureDimensions(g_depth);
+    var onScreen = all(id.xy < size);
+    var in = screenInput(vec2<f32>(min(id.xy, size - 1u)) + 0.5);
+
+    // Empty background doesn't bound the tile.
+    var depth = textureLoad(g_depth, vec2<i32>(min(id.xy, size - 1u)), 0);
+    if onScreen && depth != FAR_DEPTH {
+        // Positive floats keep their order when compared as unsigned integers.
+        var viewDepth = bitcast<u32>(-cameraPos(in).z);
+        atomicMin(&tileDepthMin, viewDepth);
+        atomicMax(&tileDepthMax, viewDepth);
+    }
+    workgroupBarrier();
+
+    var depthMin = bitcast<f32>(atomicLoad(&tileDepthMin));
+    var depthMax = bitcast<f32>(atomicLoad(&tileDepthMax));
+
+    if depthMin <= depthMax {
+        var pixelMin = vec2<f32>(tile.xy * #{TILE_SIZE}u) / vec2<f32>(size);
+        var pixelMax = vec2<f32>((tile.xy + 1u) * #{TILE_SIZE}u) / vec2<f32>(size);
+        var ndcMin = vec2(pixelMin.x * 2.0 - 1.0, 1.0 - pixelMax.y * 2.0);
+        var ndcMax = vec2(pixelMax.x * 2.0 - 1.0, 1.0 - pixelMin.y * 2.0);
+
+        // View space bounding box of the tile frustum between the depth bounds.
+        var corners = array<vec3<f32>, 4>(
+            onNearPlane(ndcMin),
+            onNearPlane(vec2(ndcMin.x, ndcMax.y)),
+            onNearPlane(vec2(ndcMax.x, ndcMin.y)),
+            onNearPlane(ndcMax),
+        );
+
+        var aabbMin = vec3(1e30);
+        var aabbMax = vec3(-1e30);
+        for (var i = 0; i < 4; i += 1) {
+            var corner = corners[i];
+            var nearCorner = corner * (depthMin / -corner.z);
+            var farCorner = corner * (depthMax / -corner.z);
+
+            aabbMin = min(aabbMin, min(nearCorner, farCorner));
+            aabbMax = max(aabbMax, max(nearCorner, farCorner));
+        }
+
+        var first = lights.num_directional + lights.num_point;
+        var last = first + lights.num_spot;
+        for (var i = first + localIdx; i < last; i += TILE_THREADS) {
+            var light = lights.lights[i];
+            var center = (camera * vec4(light.position.xyz, 1.0)).xyz;
+
+            if lightReachesAabb(light, true, center, aabbMin, aabbMax) {
+                var slot = atomicAdd(&tileLightCount, 1u);
+                if slot < #{MAX_TILE_LIGHTS}u {
+                    tileLights[slot] = i;
+                }
+            }
+        }
+    }
+    workgroupBarrier();
+
+    if !onScreen {
+        return;
+    }
+
+    var color = vec3(0.0, 0.0, 0.0);
+    for (var i = u32(0); i < lights.num_directional; i = i + 1) {
+        color += calculateDirectional(in, lights.lights[i]);
+    }
+
+    color += point_ambient.ambient.xyz * fragmentAmbient(in) * fragmentOcclusion(in);
+
+    var count = min(atomicLoad(&tileLightCount), #{MAX_TILE_LIGHTS}u);
+    for (var i = u32(0); i < count; i = i + 1) {
+        color += calculateSpot(in, lights.lights[tileLights[i]]);
+    }
 
-    return vec4(color, 1.0);
+    color = applyReflection(in, color) + fragmentEmissive(in);
+    // Background gets drawn over by the skybox.
+    if depth != FAR_DEPTH {
+        var position = cameraPos(in).xyz;
+        color = applyFog(applyDistanceFog(color, position), position);
+    }
+    textureStore(output, id.xy, vec4(color, 1.0));
 }
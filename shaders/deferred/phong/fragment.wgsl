@@ -1,19 +1,32 @@
 #define_import_path gpubasics::deferred::phong::fragment
-#import gpubasics::deferred::phong::bindings::{g_sampler, g_normal, g_diffuse, g_specular, g_depth, ssao_tex};
+#import gpubasics::deferred::geometry::bindings::{g_normal, g_diffuse, g_specular, g_emissive, g_depth};
+#import gpubasics::deferred::phong::bindings::ssao_tex;
 #import gpubasics::deferred::outputs::vertex::VertexOutput;
+#import gpubasics::deferred::gbuffer::decodeShininess;
 #import gpubasics::global::bindings::{camera_model, projection_invt};
 
-fn worldPos(in: VertexOutput) -> vec4<f32> {
-    var depth = textureSample(g_depth, g_sampler, in.uv);
-    var ndc = vec4<f32>(in.clip.x, in.clip.y, depth, 1.0);
-    var clip = projection_invt * ndc;
-    clip /= clip.w;
+// G-Buffers are read with `textureLoad`, so lighting can be evaluated outside of fragment shaders.
+fn texel(in: VertexOutput) -> vec2<i32> {
+    var size = vec2<f32>(textureDimensions(g_depth));
+    return vec2<i32>(clamp(in.uv * size, vec2(0.0), size - 1.0));
+}
+
+// Input for reading G-Buffers at the given position in pixels.
+fn screenInput(position: vec2<f32>) -> VertexOutput {
+    var in: VertexOutput;
+    in.position = vec4(position, 0.0, 1.0);
+    in.uv = position / vec2<f32>(textureDimensions(g_depth));
+    in.clip = vec4(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0, 0.0, 1.0);
 
-    return camera_model * clip;
+    return in;
+}
+
+fn worldPos(in: VertexOutput) -> vec4<f32> {
+    return camera_model * cameraPos(in);
 }
 
 fn cameraPos(in: VertexOutput) -> vec4<f32> {
-    var depth = textureSample(g_depth, g_sampler, in.uv);
+    var depth = textureLoad(g_depth, texel(in), 0);
     var ndc = vec4<f32>(in.clip.x, in.clip.y, depth, 1.0);
     var clip = projection_invt * ndc;
     clip /= clip.w;
@@ -22,25 +35,35 @@ fn cameraPos(in: VertexOutput) -> vec4<f32> {
 }
 
 fn normal(in: VertexOutput) -> vec3<f32> {
-    return textureSample(g_normal, g_sampler, in.uv).rgb;
+    return textureLoad(g_normal, texel(in), 0).rgb;
 }
 
 fn ambient(in: VertexOutput) -> vec3<f32> {
-    return textureSample(g_diffuse, g_sampler, in.uv).rgb;
+    return textureLoad(g_diffuse, texel(in), 0).rgb;
 }
 
 fn diffuse(in: VertexOutput) -> vec3<f32> {
-    return textureSample(g_diffuse, g_sampler, in.uv).rgb;
+    return textureLoad(g_diffuse, texel(in), 0).rgb;
+}
+
+// Diffuse alpha is free in G-Buffers, so reflectivity is stored there.
+fn reflectivity(in: VertexOutput) -> f32 {
+    return textureLoad(g_diffuse, texel(in), 0).a;
 }
 
 fn specular(in: VertexOutput) -> vec3<f32> {
-    return textureSample(g_specular, g_sampler, in.uv).rgb;
+    return textureLoad(g_specular, texel(in), 0).rgb;
 }
 
+// Specular alpha is free in G-Buffers, so per-pixel shininess is stored there.
 fn shininess(in: VertexOutput) -> f32 {
-    return textureSample(g_specular, g_sampler, in.uv).a * 256.0;
+    return decodeShininess(textureLoad(g_specular, texel(in), 0).a);
+}
+
+fn emissive(in: VertexOutput) -> vec3<f32> {
+    return textureLoad(g_emissive, texel(in), 0).rgb;
 }
 
 fn ambientOcclusion(in: VertexOutput) -> f32 {
-    return textureSample(ssao_tex, g_sampler, in.uv).r;
+    return textureLoad(ssao_tex, texel(in), 0).r;
 }
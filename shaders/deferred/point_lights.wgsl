@@ -0,0 +1,62 @@
+#import gpubasics::phong::bindings::lights;
+#import gpubasics::deferred::phong::fragment::{screenInput, worldPos, cameraPos};
+#import gpubasics::global::bindings::{camera, projection, camera_model, projection_invt};
+#import gpubasics::global::depth::FAR_DEPTH;
+#import gpubasics::phong::culling::lightRange;
+#import gpubasics::phong::fragment::fragmentReflectivity;
+#import gpubasics::phong::functions::calculatePoint;
+#import gpubasics::fog::volume::fogBetween;
+#import gpubasics::fog::distance::distanceFog;
+
+// Proxy spheres are made of flat faces, so they are enlarged a bit to contain the whole range.
+const VOLUME_SCALE: f32 = 1.1;
+
+struct VolumeOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) @interpolate(flat) light: u32,
+};
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>, @builtin(instance_index) instance: u32) -> VolumeOutput {
+    var out: VolumeOutput;
+    out.light = lights.num_directional + instance;
+
+    var light = lights.lights[out.light];
+
+    // Lights with unbounded range only need to cover everything up to the far plane.
+    // Frustum corners are further away than its center, hence the doubled distance.
+    var far = projection_invt * vec4(0.0, 0.0, FAR_DEPTH, 1.0);
+    var farDistance = -far.z / far.w;
+    var maxRange = distance(camera_model[3].xyz, light.position.xyz) + 2.0 * farDistance;
+    var range = min(lightRange(light, false), maxRange) * VOLUME_SCALE;
+
+    out.position = projection * camera * vec4(light.position.xyz + position * range, 1.0);
+    // Back faces behind the far plane are kept on it, so volumes bigger than the view still shade.
+#ifdef REVERSE_Z
+    out.position.z = max(out.position.z, 0.0);
+#else
+    out.position.z = min(out.position.z, out.position.w);
+#endif
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VolumeOutput) -> @location(0) vec4<f32> {
+    var pixel = screenInput(in.position.xy);
+    var light = lights.lights[in.light];
+
+    if distance(worldPos(pixel).xyz, light.position.xyz) > lightRange(light, false) {
+        discard;
+    }
+
+    // Ambient of point lights is not attenuated, it's applied together with the rest of the scene.
+    light.ambient = vec4(0.0, 0.0, 0.0, light.ambient.w);
+
+    // Reflective surfaces show the environment instead, like after the tiled pass.
+    var color = calculatePoint(pixel, light) * (1.0 - fragmentReflectivity(pixel));
+    // Fog in front of the pixel dims the light, its in-scattering was added with the rest of the scene.
+    var position = cameraPos(pixel).xyz;
+    color *= fogBetween(position).a * distanceFog(position).a;
+    return vec4(color, 0.0);
+}
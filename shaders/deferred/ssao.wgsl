@@ -1,7 +1,7 @@
 #import gpubasics::deferred::shaders::screen_quad_vs::screenQuad;
 #import gpubasics::deferred::outputs::vertex::{VertexOutput};
 #import gpubasics::deferred::ssao::fragment::{cameraPos, normal, noise, depth};
-#import gpubasics::deferred::ssao::bindings::samples;
+#import gpubasics::deferred::ssao::bindings::{samples, params};
 #import gpubasics::global::bindings::{projection};
 
 @vertex
@@ -11,8 +11,7 @@ fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) f32 {
-    var SSAO_SAMPLES_CNT = u32(64);
-
+    var numSamples = u32(params.num_samples);
     var pos = cameraPos(in).xyz;
     var normal = normal(in);
     var noise = noise(in).rgb;
@@ -22,10 +21,10 @@ fn fs_main(in: VertexOutput) -> @location(0) f32 {
     var bitangent = cross(normal, tangent);
 
     var tbn = mat3x3(tangent, bitangent, normal);
-    var radius = 0.5;
+    var radius = params.radius;
 
     var occlusion = 0.0;
-    for (var i = u32(0); i < SSAO_SAMPLES_CNT; i += u32(1)) {
+    for (var i = u32(0); i < numSamples; i += u32(1)) {
         var sample = tbn * samples[i];
         sample = pos + sample * radius;
 
@@ -40,11 +39,11 @@ fn fs_main(in: VertexOutput) -> @location(0) f32 {
         var sampleDepth = cameraPos(sampleOut).z;
         var rangeCheck = smoothstep(0.0, 1.0, radius / abs(pos.z - sampleDepth));
 
-        if sampleDepth >= sample.z + 0.075 {
+        if sampleDepth >= sample.z + params.bias {
             occlusion += 1.0 * rangeCheck;
         }
     }
 
-    occlusion = 1.0 - (occlusion / f32(SSAO_SAMPLES_CNT));
-    return occlusion;
+    occlusion = 1.0 - (occlusion / f32(numSamples));
+    return pow(occlusion, params.intensity);
 }
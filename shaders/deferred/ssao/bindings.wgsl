@@ -1,9 +1,17 @@
 #define_import_path gpubasics::deferred::ssao::bindings
 
-@group(1) @binding(0) var<uniform> samples: array<vec3<f32>, 64>;
-@group(1) @binding(1) var g_sampler: sampler;
-@group(1) @binding(2) var noise_sampler: sampler;
-@group(1) @binding(3) var g_normal: texture_2d<f32>;
-@group(1) @binding(4) var t_noise: texture_2d<f32>;
-@group(1) @binding(5) var g_depth: texture_depth_2d;
+struct SsaoParams {
+    radius: f32,
+    bias: f32,
+    intensity: f32,
+    num_samples: f32,
+    // Turn of the noise around the normal, changed every frame when occlusion is accumulated.
+    rotation: f32,
+};
 
+// The G-Buffer is bound in group 1, see `gpubasics::deferred::geometry::bindings`.
+@group(2) @binding(0) var<uniform> samples: array<vec3<f32>, #{SSAO_SAMPLES_CNT}>;
+@group(2) @binding(1) var g_sampler: sampler;
+@group(2) @binding(2) var noise_sampler: sampler;
+@group(2) @binding(3) var t_noise: texture_2d<f32>;
+@group(2) @binding(4) var<uniform> params: SsaoParams;
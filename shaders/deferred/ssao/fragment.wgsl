@@ -1,5 +1,6 @@
 #define_import_path gpubasics::deferred::ssao::fragment
-#import gpubasics::deferred::ssao::bindings::{g_sampler, g_normal, g_depth, noise_sampler, t_noise};
+#import gpubasics::deferred::ssao::bindings::{g_sampler, noise_sampler, t_noise, params};
+#import gpubasics::deferred::geometry::bindings::{g_normal, g_depth};
 #import gpubasics::global::bindings::{camera_model, projection_invt};
 #import gpubasics::deferred::outputs::vertex::VertexOutput;
 
@@ -30,10 +31,12 @@ fn normal(in: VertexOutput) -> vec3<f32> {
     return textureSample(g_normal, g_sampler, in.uv).rgb;
 }
 
+// Tiles the noise over pixels of the target, which is smaller than the G-Buffer at lower resolutions.
 fn noise(in: VertexOutput) -> vec3<f32> {
-    var noiseSize = textureDimensions(t_noise).xy;
-    var viewSize = textureDimensions(g_normal).xy;
+    var noiseSize = vec2<f32>(textureDimensions(t_noise).xy);
+    var noise = textureSample(t_noise, noise_sampler, in.position.xy / noiseSize).rgb;
 
-    var noiseScale = vec2<f32>(f32(viewSize.x) / f32(noiseSize.x), f32(viewSize.y) / f32(noiseSize.y));
-    return textureSample(t_noise, noise_sampler, noiseScale * in.uv).rgb;
+    var c = cos(params.rotation);
+    var s = sin(params.rotation);
+    return vec3(c * noise.x - s * noise.y, s * noise.x + c * noise.y, noise.z);
 }
@@ -0,0 +1,49 @@
+#import gpubasics::deferred::shaders::screen_quad_vs::screenQuad;
+#import gpubasics::deferred::outputs::vertex::{VertexOutput};
+
+struct TemporalParams {
+    // Part of the history kept every frame, zero when there's no history of the previous frame.
+    history_weight: f32,
+};
+
+@group(0) @binding(0) var occlusion: texture_2d<f32>;
+@group(0) @binding(1) var history: texture_2d<f32>;
+@group(0) @binding(2) var history_sampler: sampler;
+@group(0) @binding(3) var g_velocity: texture_2d<f32>;
+@group(0) @binding(4) var<uniform> params: TemporalParams;
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    return screenQuad(in_vertex_index);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) f32 {
+    var size = vec2<i32>(textureDimensions(occlusion));
+    var texel = vec2<i32>(in.position.xy);
+    var current = textureLoad(occlusion, texel, 0).r;
+
+    // History is clamped to the neighbourhood of the texel, which throws most of it away
+    // where surfaces got disoccluded since the previous frame.
+    var low = current;
+    var high = current;
+    for (var i = 0; i < 9; i += 1) {
+        var neighbour = clamp(texel + vec2(i % 3 - 1, i / 3 - 1), vec2(0), size - 1);
+        var value = textureLoad(occlusion, neighbour, 0).r;
+        low = min(low, value);
+        high = max(high, value);
+    }
+
+    // Velocity comes at the G-Buffer resolution, occlusion can be computed at a lower one.
+    var velocitySize = vec2<i32>(textureDimensions(g_velocity));
+    var velocityTexel = min(vec2<i32>(in.uv * vec2<f32>(velocitySize)), velocitySize - 1);
+    var previousUv = in.uv - textureLoad(g_velocity, velocityTexel, 0).xy;
+
+    var weight = params.history_weight;
+    if any(previousUv < vec2(0.0)) || any(previousUv > vec2(1.0)) {
+        weight = 0.0;
+    }
+
+    var previous = clamp(textureSampleLevel(history, history_sampler, previousUv, 0.0).r, low, high);
+    return mix(current, previous, weight);
+}
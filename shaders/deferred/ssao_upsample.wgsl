@@ -0,0 +1,57 @@
+#import gpubasics::deferred::shaders::screen_quad_vs::screenQuad;
+#import gpubasics::deferred::outputs::vertex::{VertexOutput};
+#import gpubasics::global::bindings::{projection_invt};
+#import gpubasics::deferred::geometry::bindings::g_depth;
+
+@group(2) @binding(0) var occlusion: texture_2d<f32>;
+
+// How quickly low resolution texels stop contributing as their depth moves away from the pixel's.
+const DEPTH_SHARPNESS: f32 = 32.0;
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    return screenQuad(in_vertex_index);
+}
+
+// Inverse of the view space depth at the given texel, it stays finite for the sky with infinite projections.
+fn inverseDepth(coords: vec2<i32>) -> f32 {
+    var size = vec2<f32>(textureDimensions(g_depth));
+    var uv = (vec2<f32>(coords) + 0.5) / size;
+    var view = projection_invt * vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, textureLoad(g_depth, coords, 0), 1.0);
+
+    return -view.w / view.z;
+}
+
+// Bilinear upsampling of the occlusion, with weights of texels lying on other surfaces than the pixel cut down.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) f32 {
+    var depthSize = vec2<i32>(textureDimensions(g_depth));
+    var lowSize = vec2<i32>(textureDimensions(occlusion));
+    var ratio = vec2<f32>(depthSize) / vec2<f32>(lowSize);
+
+    var pixel = vec2<i32>(in.position.xy);
+    var pixelDepth = inverseDepth(pixel);
+
+    var lowPos = in.position.xy / ratio - 0.5;
+    var base = vec2<i32>(floor(lowPos));
+    var f = fract(lowPos);
+
+    var occlusionSum = 0.0;
+    var weightSum = 0.0;
+    for (var i = 0; i < 4; i += 1) {
+        var offset = vec2(i % 2, i / 2);
+        var texel = clamp(base + offset, vec2(0), lowSize - 1);
+
+        // Occlusion of a texel was computed from the depth under its center.
+        var texelDepth = inverseDepth(min(vec2<i32>((vec2<f32>(texel) + 0.5) * ratio), depthSize - 1));
+        var difference = abs(texelDepth - pixelDepth) / max(pixelDepth, 1e-4);
+
+        var bilinear = mix(1.0 - f, f, vec2<f32>(offset));
+        var weight = max(bilinear.x * bilinear.y * exp(-difference * DEPTH_SHARPNESS), 1e-5);
+
+        occlusionSum += textureLoad(occlusion, texel, 0).r * weight;
+        weightSum += weight;
+    }
+
+    return occlusionSum / weightSum;
+}
@@ -0,0 +1,10 @@
+#define_import_path gpubasics::global::depth
+
+// Depth of the near and far planes, swapped when `Gpu` renders with REVERSE_Z.
+#ifdef REVERSE_Z
+const NEAR_DEPTH: f32 = 1.0;
+const FAR_DEPTH: f32 = 0.0;
+#else
+const NEAR_DEPTH: f32 = 0.0;
+const FAR_DEPTH: f32 = 1.0;
+#endif
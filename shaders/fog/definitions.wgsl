@@ -0,0 +1,35 @@
+#define_import_path gpubasics::fog::definitions
+#import gpubasics::global::bindings::projection_invt;
+#import gpubasics::global::depth::NEAR_DEPTH;
+
+struct FogParams {
+    // Density at base height, its falloff above it, the base height and Henyey-Greenstein anisotropy.
+    medium: vec4<f32>,
+    // Direction towards the sun, w is non-zero while fog is enabled.
+    sun_direction: vec4<f32>,
+    // Light scattered from the sun, w is the part of it scattered regardless of shadows.
+    sun_color: vec4<f32>,
+    // Distance the fog volume reaches, in x.
+    range: vec4<f32>,
+};
+
+fn fogEnabled(params: FogParams) -> bool {
+    return params.sun_direction.w != 0.0;
+}
+
+fn nearDistance() -> f32 {
+    var view = projection_invt * vec4(0.0, 0.0, NEAR_DEPTH, 1.0);
+    return -view.z / view.w;
+}
+
+// Depth slices are distributed exponentially between the near plane and the fog range.
+fn sliceDepth(params: FogParams, slice: f32, slices: u32) -> f32 {
+    var near = nearDistance();
+    return near * pow(params.range.x / near, slice / f32(slices));
+}
+
+// Inverse of `sliceDepth`, as a fraction of all the slices.
+fn depthSlice(params: FogParams, depth: f32) -> f32 {
+    var near = nearDistance();
+    return log(max(depth, near) / near) / log(params.range.x / near);
+}
@@ -0,0 +1,30 @@
+#define_import_path gpubasics::fog::distance
+#import gpubasics::global::bindings::{camera_model, scene_fog};
+
+// Fog color in rgb and transmittance in alpha, between the camera and a view space position.
+fn distanceFog(cameraPos: vec3<f32>) -> vec4<f32> {
+    if scene_fog.color.w == 0.0 {
+        return vec4(0.0, 0.0, 0.0, 1.0);
+    }
+
+    var medium = scene_fog.medium;
+    var distance = length(cameraPos);
+    var eye = camera_model[3].xyz;
+    var world = camera_model * vec4(cameraPos, 1.0);
+
+    // Height fog density integrated along the ray, as it thins out exponentially with height.
+    var falloff = max(medium.z, 0.001);
+    var rise = (world.y - eye.y) * falloff;
+    var heightDepth = medium.y * exp(min((medium.w - eye.y) * falloff, 32.0)) * distance;
+    if abs(rise) > 0.0001 {
+        heightDepth *= (1.0 - exp(-rise)) / rise;
+    }
+
+    var transmittance = exp(-(medium.x * distance + heightDepth));
+    return vec4(scene_fog.color.rgb * (1.0 - transmittance), transmittance);
+}
+
+fn applyDistanceFog(color: vec3<f32>, cameraPos: vec3<f32>) -> vec3<f32> {
+    var fog = distanceFog(cameraPos);
+    return color * fog.a + fog.rgb;
+}
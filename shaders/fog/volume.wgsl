@@ -0,0 +1,30 @@
+#define_import_path gpubasics::fog::volume
+#import gpubasics::global::bindings::projection;
+#import gpubasics::fog::definitions::{FogParams, fogEnabled, depthSlice};
+
+// Integrated fog written by `VolumetricFogPass`, next to the lights of lighting passes.
+
+@group(1) @binding(4) var fog_volume: texture_3d<f32>;
+@group(1) @binding(5) var fog_sampler: sampler;
+@group(1) @binding(6) var<uniform> fog_params: FogParams;
+
+// In-scattered light in rgb and transmittance in alpha, between the camera and a view space position.
+fn fogBetween(cameraPos: vec3<f32>) -> vec4<f32> {
+    if !fogEnabled(fog_params) {
+        return vec4(0.0, 0.0, 0.0, 1.0);
+    }
+
+    var clip = projection * vec4(cameraPos, 1.0);
+    var uv = clip.xy / clip.w * vec2(0.5, -0.5) + 0.5;
+
+    // Every slice holds fog accumulated up to its far end.
+    var slices = textureDimensions(fog_volume).z;
+    var slice = depthSlice(fog_params, -cameraPos.z) - 0.5 / f32(slices);
+
+    return textureSampleLevel(fog_volume, fog_sampler, vec3(uv, slice), 0.0);
+}
+
+fn applyFog(color: vec3<f32>, cameraPos: vec3<f32>) -> vec3<f32> {
+    var fog = fogBetween(cameraPos);
+    return color * fog.a + fog.rgb;
+}
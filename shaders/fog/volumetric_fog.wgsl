@@ -0,0 +1,114 @@
+#import gpubasics::global::bindings::{projection_invt, camera_model};
+#import gpubasics::global::depth::NEAR_DEPTH;
+#import gpubasics::fog::definitions::{FogParams, sliceDepth};
+#import gpubasics::shadow::cascaded::bindings::{smap_matrices, smap_result, smap_a, smap_b, smap_c, smap_cmp_sampler};
+
+// Kernel dimensions come from `VolumetricFogPass`, froxels of the volumes are spread over
+// the screen like clusters of lights, but only up to the range of the fog.
+// Shadow maps are bound in group 2, as for deferred lighting.
+
+const PI: f32 = 3.14159265;
+
+@group(1) @binding(0) var<uniform> params: FogParams;
+@group(1) @binding(1) var scattering_out: texture_storage_3d<rgba16float, write>;
+@group(1) @binding(2) var scattering: texture_3d<f32>;
+@group(1) @binding(3) var integrated_out: texture_storage_3d<rgba16float, write>;
+
+// View space direction through the center of a froxel column, with a depth of 1.
+fn viewRay(column: vec2<u32>, size: vec3<u32>) -> vec3<f32> {
+    var uv = (vec2<f32>(column) + 0.5) / vec2<f32>(size.xy);
+    var view = projection_invt * vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, NEAR_DEPTH, 1.0);
+    var onNearPlane = view.xyz / view.w;
+
+    return onNearPlane / -onNearPlane.z;
+}
+
+// Whether the sun reaches a point, 1 beyond the shadowed distance.
+fn sunVisibility(world: vec4<f32>, depth: f32) -> f32 {
+    var cams = array<mat4x4<f32>, 3>(smap_matrices.cam_split_a, smap_matrices.cam_split_b, smap_matrices.cam_split_c);
+    var projs = array<mat4x4<f32>, 3>(smap_matrices.proj_split_a, smap_matrices.proj_split_b, smap_matrices.proj_split_c);
+
+    for (var i = 0; i < i32(smap_result.num_splits); i += 1) {
+        if depth < smap_result.split_depths[i].x {
+            var light = projs[i] * cams[i] * world;
+            var lightPos = light.xyz / light.w;
+            if lightPos.z > 1.0 {
+                return 1.0;
+            }
+
+            var uv = lightPos.xy * vec2(0.5, -0.5) + 0.5;
+            switch i {
+                case 0 {
+                    return textureSampleCompareLevel(smap_a, smap_cmp_sampler, uv, lightPos.z);
+                }
+                case 1 {
+                    return textureSampleCompareLevel(smap_b, smap_cmp_sampler, uv, lightPos.z);
+                }
+                default {
+                    return textureSampleCompareLevel(smap_c, smap_cmp_sampler, uv, lightPos.z);
+                }
+            }
+        }
+    }
+
+    return 1.0;
+}
+
+fn henyeyGreenstein(cosTheta: f32, g: f32) -> f32 {
+    var denominator = 1.0 + g * g - 2.0 * g * cosTheta;
+    return (1.0 - g * g) / (4.0 * PI * pow(denominator, 1.5));
+}
+
+// Light scattered towards the camera in rgb and extinction in alpha, for every froxel.
+@compute @workgroup_size(#{WORKGROUP_SIZE_X}, #{WORKGROUP_SIZE_Y}, #{WORKGROUP_SIZE_Z})
+fn scatter(@builtin(global_invocation_id) id: vec3<u32>) {
+    var size = textureDimensions(scattering_out);
+    if any(id >= size) {
+        return;
+    }
+
+    var ray = viewRay(id.xy, size);
+    var depth = sliceDepth(params, f32(id.z) + 0.5, size.z);
+    var world = camera_model * vec4(ray * depth, 1.0);
+    var eye = camera_model[3].xyz;
+
+    var medium = params.medium;
+    var density = medium.x * exp(-max(world.y - medium.z, 0.0) * medium.y);
+
+    var cosTheta = dot(normalize(eye - world.xyz), -params.sun_direction.xyz);
+    var phase = henyeyGreenstein(cosTheta, medium.w);
+    var lit = sunVisibility(world, depth) * phase + params.sun_color.w;
+
+    textureStore(scattering_out, id, vec4(params.sun_color.rgb * lit * density, density));
+}
+
+// Accumulates scattered light and transmittance front to back along every froxel column.
+@compute @workgroup_size(#{WORKGROUP_SIZE_X}, #{WORKGROUP_SIZE_Y}, #{WORKGROUP_SIZE_Z})
+fn integrate(@builtin(global_invocation_id) id: vec3<u32>) {
+    var size = textureDimensions(integrated_out);
+    if any(id.xy >= size.xy) {
+        return;
+    }
+
+    // Slices are measured along the view axis, while light travels along the ray.
+    var stretch = length(viewRay(id.xy, size));
+
+    var accumulated = vec3(0.0);
+    var transmittance = 1.0;
+    var previous = sliceDepth(params, 0.0, size.z);
+    for (var z = 0u; z < size.z; z += 1u) {
+        var depth = sliceDepth(params, f32(z + 1u), size.z);
+        var thickness = (depth - previous) * stretch;
+        previous = depth;
+
+        var froxel = textureLoad(scattering, vec3(id.xy, z), 0);
+        var extinction = max(froxel.a, 0.000001);
+        var sliceTransmittance = exp(-extinction * thickness);
+
+        // Scattering integrated over the slice, as it's attenuated on the way through.
+        accumulated += transmittance * froxel.rgb * (1.0 - sliceTransmittance) / extinction;
+        transmittance *= sliceTransmittance;
+
+        textureStore(integrated_out, vec3(id.xy, z), vec4(accumulated, transmittance));
+    }
+}
@@ -0,0 +1,49 @@
+#import gpubasics::global::bindings::{camera, projection};
+#import gpubasics::foliage::definitions::{Plant, foliage};
+
+// Kernel dimensions come from `FoliagePass`.
+
+struct DrawIndirect {
+    vertex_count: u32,
+    instance_count: atomic<u32>,
+    first_vertex: u32,
+    first_instance: u32,
+};
+
+@group(1) @binding(1) var<storage, read> plants: array<Plant>;
+@group(1) @binding(2) var<storage, read_write> visible: array<Plant>;
+@group(1) @binding(3) var<storage, read_write> draw: DrawIndirect;
+
+// Whether a view space sphere is on the inner side of the frustum plane through the eye,
+// which has the given slope along `axis`.
+fn insidePlane(center: vec3<f32>, radius: f32, axis: f32, slope: f32) -> bool {
+    return slope * axis - center.z >= -radius * sqrt(slope * slope + 1.0);
+}
+
+// Plants in range and in the view frustum are appended to `visible`,
+// counted by the instance count of the indirect draw.
+@compute @workgroup_size(#{WORKGROUP_SIZE_X}, #{WORKGROUP_SIZE_Y}, #{WORKGROUP_SIZE_Z})
+fn cull(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= arrayLength(&plants) {
+        return;
+    }
+
+    var plant = plants[id.x];
+    // Sphere around the middle of the plant, covering its quads.
+    var center = (camera * vec4(plant.position + vec3(0.0, plant.scale * 0.5, 0.0), 1.0)).xyz;
+    var radius = plant.scale * 0.75;
+
+    if -center.z - radius > foliage.params.x || -center.z + radius < 0.0 {
+        return;
+    }
+
+    var x_slope = projection[0][0];
+    var y_slope = projection[1][1];
+    if !insidePlane(center, radius, center.x, x_slope) || !insidePlane(center, radius, -center.x, x_slope)
+        || !insidePlane(center, radius, center.y, y_slope) || !insidePlane(center, radius, -center.y, y_slope) {
+        return;
+    }
+
+    var slot = atomicAdd(&draw.instance_count, 1u);
+    visible[slot] = plant;
+}
@@ -0,0 +1,20 @@
+#define_import_path gpubasics::foliage::definitions
+
+// Laid out like `FoliageInstance`.
+struct Plant {
+    position: vec3<f32>,
+    scale: f32,
+    rotation: f32,
+    tint: f32,
+};
+
+struct Foliage {
+    // Distance plants are drawn up to, y unused, strength of wind and alpha cutoff.
+    params: vec4<f32>,
+    // Direction the sun shines in.
+    sun_direction: vec4<f32>,
+    sun_ambient: vec4<f32>,
+    sun_diffuse: vec4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> foliage: Foliage;
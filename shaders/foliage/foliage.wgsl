@@ -0,0 +1,68 @@
+#import gpubasics::global::bindings::{camera, projection, scene_frame};
+#import gpubasics::foliage::definitions::{Plant, foliage};
+
+const PI: f32 = 3.14159265;
+// Vertices of a single quad, a plant is two of them crossed at right angles.
+const QUAD_VERTICES: u32 = 6u;
+
+@group(1) @binding(1) var<storage, read> visible: array<Plant>;
+@group(1) @binding(2) var plant_texture: texture_2d<f32>;
+@group(1) @binding(3) var plant_sampler: sampler;
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    // Facing of the quad, lit from both sides.
+    @location(1) normal: vec3<f32>,
+    // Height along the plant, from 0 at the root to 1 at the top.
+    @location(2) height: f32,
+    @location(3) tint: f32,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32, @builtin(instance_index) instance: u32) -> VertexOut {
+    var CORNERS: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+        vec2<f32>(-0.5, 0.0),
+        vec2<f32>(0.5, 0.0),
+        vec2<f32>(-0.5, 1.0),
+        vec2<f32>(-0.5, 1.0),
+        vec2<f32>(0.5, 0.0),
+        vec2<f32>(0.5, 1.0)
+    );
+
+    var plant = visible[instance];
+    var corner = CORNERS[vertex % QUAD_VERTICES];
+    var angle = plant.rotation + f32(vertex / QUAD_VERTICES) * PI * 0.5;
+    var across = vec3(cos(angle), 0.0, sin(angle));
+
+    var world = plant.position + (across * corner.x + vec3(0.0, corner.y, 0.0)) * plant.scale;
+    // Wind bends the tops, roots stay in place.
+    var phase = scene_frame.time * 1.7 + plant.position.x * 0.35 + plant.position.z * 0.27;
+    var sway = sin(phase) * foliage.params.z * corner.y * corner.y * plant.scale;
+    world += vec3(sway, 0.0, sway * 0.5);
+
+    var o: VertexOut;
+    o.position = projection * camera * vec4(world, 1.0);
+    o.uv = vec2(corner.x + 0.5, 1.0 - corner.y);
+    o.normal = vec3(-across.z, 0.0, across.x);
+    o.height = corner.y;
+    o.tint = plant.tint;
+    return o;
+}
+
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    var color = textureSample(plant_texture, plant_sampler, in.uv);
+    if color.a < foliage.params.w {
+        discard;
+    }
+
+    // Blades mostly face up, half of the light comes in as if they did.
+    var to_sun = -foliage.sun_direction.xyz;
+    var diffuse = mix(max(to_sun.y, 0.0), abs(dot(normalize(in.normal), to_sun)), 0.5);
+    // Roots are shaded by the plants around them.
+    var occlusion = mix(0.5, 1.0, in.height);
+    var light = foliage.sun_ambient.rgb + foliage.sun_diffuse.rgb * diffuse;
+
+    return vec4(color.rgb * in.tint * light * occlusion, 1.0);
+}
@@ -11,6 +11,7 @@ struct Instance {
     @location(7) model_invt_cb: vec4<f32>,
     @location(8) model_invt_cc: vec4<f32>,
     @location(9) model_invt_cd: vec4<f32>,
+    @location(10) material: u32,
 };
 #endif
 
@@ -24,6 +25,7 @@ struct Instance {
     @location(8) model_invt_cb: vec4<f32>,
     @location(9) model_invt_cc: vec4<f32>,
     @location(10) model_invt_cd: vec4<f32>,
+    @location(11) material: u32,
 };
 #endif
 
@@ -37,6 +39,7 @@ struct Instance {
     @location(10) model_invt_cb: vec4<f32>,
     @location(11) model_invt_cc: vec4<f32>,
     @location(12) model_invt_cd: vec4<f32>,
+    @location(13) material: u32,
 };
 #endif
 
@@ -1,5 +1,28 @@
 #define_import_path gpubasics::forward::buffers::vertex
 
+#ifdef VERTEX_ATTRIBUTES
+// Built from `VertexLayout` definitions, locations follow the attribute order there.
+struct Vertex {
+    @location(0) model_v: vec3<f32>,
+#ifdef VERTEX_NORMAL
+    @location(#{VERTEX_LOCATION_NORMAL}) normal_v: vec3<f32>,
+#endif
+#ifdef VERTEX_TANGENT_SPACE
+    @location(#{VERTEX_LOCATION_TANGENT}) tangent_v: vec3<f32>,
+    @location(#{VERTEX_LOCATION_BITANGENT}) bitangent_v: vec3<f32>,
+#endif
+#ifdef VERTEX_UV
+    @location(#{VERTEX_LOCATION_UV}) uv: vec2<f32>,
+#endif
+#ifdef VERTEX_COLOR
+    @location(#{VERTEX_LOCATION_COLOR}) color: vec4<f32>,
+#endif
+#ifdef VERTEX_SKIN
+    @location(#{VERTEX_LOCATION_JOINTS}) joints: vec4<u32>,
+    @location(#{VERTEX_LOCATION_WEIGHTS}) weights: vec4<f32>,
+#endif
+};
+#else
 #ifdef VERTEX_PN
 struct Vertex {
     @location(0) model_v: vec3<f32>,
@@ -24,3 +47,4 @@ struct Vertex {
     @location(4) uv: vec2<f32>,
 };
 #endif
+#endif
@@ -1,14 +1,56 @@
 #import gpubasics::forward::buffers::vertex::{Vertex};
 #import gpubasics::forward::buffers::instance::{Instance, model};
 
-#import gpubasics::global::bindings::{camera, projection};
+#ifdef PUSH_CONSTANTS
+// Views of all cascades followed by their projections. The one rendered is picked
+// by the index pushed before drawing it. Array sizes can't be expressions in naga yet,
+// so the pass defines both counts.
+@group(0) @binding(0) var<uniform> cascade_matrices: array<mat4x4<f32>, #{CASCADE_MATRIX_COUNT}>;
 
+struct CascadeConstants {
+    index: u32,
+};
+
+var<push_constant> cascade: CascadeConstants;
+
+fn camera() -> mat4x4<f32> {
+    return cascade_matrices[cascade.index];
+}
+
+fn projection() -> mat4x4<f32> {
+    return cascade_matrices[#{CASCADE_COUNT}u + cascade.index];
+}
+#else
+#import gpubasics::global::bindings::{camera as camera_mat, projection as projection_mat};
+
+fn camera() -> mat4x4<f32> {
+    return camera_mat;
+}
+
+fn projection() -> mat4x4<f32> {
+    return projection_mat;
+}
+#endif
+
+// Transformed the way geometry shaders do it, so depth laid down by the prepass
+// matches theirs exactly.
 @vertex
-fn vs_main(v: Vertex, i: Instance) -> @builtin(position) vec4<f32> {
+fn vs_main(v: Vertex, i: Instance) -> @invariant @builtin(position) vec4<f32> {
     var model = model(i);
 
     var world_v = model * vec4<f32>(v.model_v, 1.0);
-    var camera_v = projection * camera * world_v;
+    var camera_v = camera() * world_v;
+
+    return projection() * camera_v;
+}
+
+// Depth moments for variance shadow maps. Depth variation across the pixel is added
+// to the second moment, so slopes don't shadow themselves.
+@fragment
+fn fs_moments(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    var depth = position.z;
+    var dx = dpdx(depth);
+    var dy = dpdy(depth);
 
-    return camera_v;
+    return vec4(depth, depth * depth + 0.25 * (dx * dx + dy * dy), 0.0, 0.0);
 }
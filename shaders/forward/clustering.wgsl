@@ -0,0 +1,56 @@
+#import gpubasics::global::bindings::camera;
+#import gpubasics::phong::definitions::Lights;
+#import gpubasics::phong::culling::lightReachesAabb;
+#import gpubasics::forward::clusters::definitions::{Cluster, gridSize, sliceDepth, onNearPlane};
+
+@group(1) @binding(0) var<storage, read> lights: Lights;
+@group(1) @binding(1) var<storage, read_write> clusters: array<Cluster>;
+
+@compute @workgroup_size(#{WORKGROUP_SIZE_X}, #{WORKGROUP_SIZE_Y}, #{WORKGROUP_SIZE_Z})
+fn cluster_lights(@builtin(global_invocation_id) id: vec3<u32>) {
+    var grid = gridSize();
+    if any(id >= grid) {
+        return;
+    }
+
+    var ndcMin = vec2<f32>(id.xy) / vec2<f32>(grid.xy) * 2.0 - 1.0;
+    var ndcMax = vec2<f32>(id.xy + 1u) / vec2<f32>(grid.xy) * 2.0 - 1.0;
+    var depthMin = sliceDepth(id.z);
+    var depthMax = sliceDepth(id.z + 1u);
+
+    // View space bounding box of the cluster frustum.
+    var corners = array<vec3<f32>, 4>(
+        onNearPlane(ndcMin),
+        onNearPlane(vec2(ndcMin.x, ndcMax.y)),
+        onNearPlane(vec2(ndcMax.x, ndcMin.y)),
+        onNearPlane(ndcMax),
+    );
+
+    var aabbMin = vec3(1e30);
+    var aabbMax = vec3(-1e30);
+    for (var i = 0; i < 4; i += 1) {
+        var corner = corners[i];
+        var nearCorner = corner * (depthMin / -corner.z);
+        var farCorner = corner * (depthMax / -corner.z);
+
+        aabbMin = min(aabbMin, min(nearCorner, farCorner));
+        aabbMax = max(aabbMax, max(nearCorner, farCorner));
+    }
+
+    var clusterIdx = id.x + id.y * grid.x + id.z * grid.x * grid.y;
+    var count = u32(0);
+
+    var first = lights.num_directional;
+    var last = lights.num_directional + lights.num_point + lights.num_spot;
+    for (var i = first; i < last && count < u32(#{MAX_CLUSTER_LIGHTS}); i += 1u) {
+        var light = lights.lights[i];
+        var center = (camera * vec4(light.position.xyz, 1.0)).xyz;
+
+        if lightReachesAabb(light, i >= first + lights.num_point, center, aabbMin, aabbMax) {
+            clusters[clusterIdx].lights[count] = i;
+            count += 1u;
+        }
+    }
+
+    clusters[clusterIdx].count = count;
+}
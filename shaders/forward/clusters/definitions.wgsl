@@ -0,0 +1,45 @@
+#define_import_path gpubasics::forward::clusters::definitions
+#import gpubasics::global::bindings::projection_invt;
+#import gpubasics::global::depth::{NEAR_DEPTH, FAR_DEPTH};
+
+// Grid dimensions come from `LightClusteringPass`:
+// CLUSTER_GRID_X, CLUSTER_GRID_Y, CLUSTER_GRID_Z, MAX_CLUSTER_LIGHTS.
+struct Cluster {
+    count: u32,
+    lights: array<u32, #{MAX_CLUSTER_LIGHTS}>,
+};
+
+fn gridSize() -> vec3<u32> {
+    return vec3<u32>(u32(#{CLUSTER_GRID_X}), u32(#{CLUSTER_GRID_Y}), u32(#{CLUSTER_GRID_Z}));
+}
+
+// View space position on the near plane at given NDC.
+fn onNearPlane(ndc: vec2<f32>) -> vec3<f32> {
+    var view = projection_invt * vec4(ndc, 0.0, 1.0);
+    return view.xyz / view.w;
+}
+
+// Distance from the camera to a point at given NDC depth.
+fn viewDepth(ndcDepth: f32) -> f32 {
+    var view = projection_invt * vec4(0.0, 0.0, ndcDepth, 1.0);
+    return -view.z / view.w;
+}
+
+// Depth slices are distributed exponentially between near and far planes.
+fn sliceDepth(slice: u32) -> f32 {
+    var near = viewDepth(NEAR_DEPTH);
+    var far = viewDepth(FAR_DEPTH);
+
+    return near * pow(far / near, f32(slice) / f32(gridSize().z));
+}
+
+fn clusterIndex(ndc: vec2<f32>, depth: f32) -> u32 {
+    var grid = gridSize();
+    var near = viewDepth(NEAR_DEPTH);
+    var far = viewDepth(FAR_DEPTH);
+
+    var tile = vec2<u32>(clamp((ndc * 0.5 + 0.5) * vec2<f32>(grid.xy), vec2(0.0), vec2<f32>(grid.xy - 1u)));
+    var slice = u32(clamp(log(depth / near) / log(far / near) * f32(grid.z), 0.0, f32(grid.z - 1u)));
+
+    return tile.x + tile.y * grid.x + slice * grid.x * grid.y;
+}
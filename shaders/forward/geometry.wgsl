@@ -1,17 +1,45 @@
 #import gpubasics::global::bindings::{camera, projection};
-#import gpubasics::phong::fragment::{fragmentNormal, fragmentDiffuse, fragmentSpecular, fragmentShininess};
+#import gpubasics::phong::fragment::{fragmentNormal, fragmentDiffuse, fragmentSpecular, fragmentShininess, fragmentReflectivity, fragmentEmissive};
 #import gpubasics::forward::buffers::instance::{Instance, model, model_invt};
 #import gpubasics::forward::buffers::vertex::Vertex;
 #import gpubasics::forward::outputs::vertex::VertexOutput;
+#import gpubasics::deferred::gbuffer::encodeShininess;
 
 struct GBuffersOutput {
     @location(0) g_normal: vec4<f32>,
     @location(1) g_diffuse: vec4<f32>,
     @location(2) g_specular: vec4<f32>,
+    @location(3) g_emissive: vec4<f32>,
+    // Screen space motion since the previous frame, in UV units.
+    @location(4) g_velocity: vec2<f32>,
 };
 
+// View projection of the previous frame.
+@group(2) @binding(0) var<uniform> previous_view_projection: mat4x4<f32>;
+// Instance buffer of the previous frame, as floats - instances are INSTANCE_STRIDE_FLOATS apart.
+@group(2) @binding(1) var<storage, read> previous_instances: array<f32>;
+
+fn previousModel(instance: u32) -> mat4x4<f32> {
+    var base = instance * #{INSTANCE_STRIDE_FLOATS}u;
+    var model: mat4x4<f32>;
+    for (var column = 0u; column < 4u; column += 1u) {
+        var i = base + column * 4u;
+        model[column] = vec4(
+            previous_instances[i],
+            previous_instances[i + 1u],
+            previous_instances[i + 2u],
+            previous_instances[i + 3u],
+        );
+    }
+    return model;
+}
+
+fn screenUv(clip: vec4<f32>) -> vec2<f32> {
+    return clip.xy / clip.w * vec2(0.5, -0.5);
+}
+
 @vertex
-fn vs_main(v: Vertex, i: Instance) -> VertexOutput {
+fn vs_main(v: Vertex, i: Instance, @builtin(instance_index) instance: u32) -> VertexOutput {
     var model = model(i);
     var inv_model_t = model_invt(i);
 
@@ -23,6 +51,10 @@ fn vs_main(v: Vertex, i: Instance) -> VertexOutput {
     out.position = ndc_v;
     out.w_pos = world_v;
     out.c_pos = camera_v;
+    out.material = i.material;
+    // The previous view projection keeps depth unreversed, which leaves x and y the same.
+    out.clip = ndc_v;
+    out.previous_clip = previous_view_projection * previousModel(instance) * vec4<f32>(v.model_v, 1.0);
 
     #ifndef VERTEX_PNTBUV
     out.normal = normalize(inv_model_t * vec4(v.normal_v, 0.0));
@@ -51,7 +83,9 @@ fn vs_main(v: Vertex, i: Instance) -> VertexOutput {
 fn fs_main(in: VertexOutput) -> GBuffersOutput {
     var out: GBuffersOutput;
     out.g_normal = vec4(fragmentNormal(in), 1.0);
-    out.g_diffuse = vec4(fragmentDiffuse(in), 1.0);
-    out.g_specular = vec4(fragmentSpecular(in), fragmentShininess(in) / 256.0);
+    out.g_diffuse = vec4(fragmentDiffuse(in), fragmentReflectivity(in));
+    out.g_specular = vec4(fragmentSpecular(in), encodeShininess(fragmentShininess(in)));
+    out.g_emissive = vec4(fragmentEmissive(in), 1.0);
+    out.g_velocity = screenUv(in.clip) - screenUv(in.previous_clip);
     return out;
 }
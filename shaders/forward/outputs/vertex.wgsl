@@ -2,32 +2,48 @@
 
 #ifdef VERTEX_PN
 struct VertexOutput {
-    @builtin(position) position: vec4<f32>,
+    @invariant @builtin(position) position: vec4<f32>,
     @location(0) normal: vec4<f32>,
     @location(1) w_pos: vec4<f32>,
     @location(2) c_pos: vec4<f32>,
+    @location(3) @interpolate(flat) material: u32,
+#ifdef GEOMETRY
+    @location(4) clip: vec4<f32>,
+    @location(5) previous_clip: vec4<f32>,
+#endif
 };
 #endif
 
 #ifdef VERTEX_PNUV
 struct VertexOutput {
-    @builtin(position) position: vec4<f32>,
+    @invariant @builtin(position) position: vec4<f32>,
     @location(0) normal: vec4<f32>,
     @location(1) w_pos: vec4<f32>,
     @location(2) c_pos: vec4<f32>,
     @location(3) uv: vec2<f32>,
+    @location(4) @interpolate(flat) material: u32,
+#ifdef GEOMETRY
+    @location(5) clip: vec4<f32>,
+    @location(6) previous_clip: vec4<f32>,
+#endif
 };
 #endif
 
 #ifdef VERTEX_PNTBUV
 struct VertexOutput {
-    @builtin(position) position: vec4<f32>,
+    @invariant @builtin(position) position: vec4<f32>,
     @location(0) w_pos: vec4<f32>,
     @location(1) c_pos: vec4<f32>,
     @location(2) uv: vec2<f32>,
     @location(3) t: vec3<f32>,
     @location(4) b: vec3<f32>,
     @location(5) n: vec3<f32>,
+    @location(6) @interpolate(flat) material: u32,
+#ifdef GEOMETRY
+    // Clip space positions in this and the previous frame, for motion vectors.
+    @location(7) clip: vec4<f32>,
+    @location(8) previous_clip: vec4<f32>,
+#endif
 };
 #endif
 
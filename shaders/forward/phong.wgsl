@@ -1,8 +1,11 @@
 #import gpubasics::global::bindings::{camera, projection};
-#import gpubasics::forward::outputs::vertex::VertexOutput;
-#import gpubasics::phong::functions::fragmentLight;
+#import gpubasics::forward::outputs::vertex::{VertexOutput, cameraPos};
+#import gpubasics::phong::fragment::fragmentEmissive;
+#import gpubasics::phong::functions::{fragmentLight, applyReflection};
 #import gpubasics::forward::buffers::instance::{Instance, model, model_invt};
 #import gpubasics::forward::buffers::vertex::Vertex;
+#import gpubasics::fog::volume::applyFog;
+#import gpubasics::fog::distance::applyDistanceFog;
 
 
 @vertex
@@ -18,6 +21,7 @@ fn vs_main(v: Vertex, i: Instance) -> VertexOutput {
     out.position = ndc_v;
     out.w_pos = world_v;
     out.c_pos = camera_v;
+    out.material = i.material;
 
     #ifndef VERTEX_PNTBUV
     out.normal = normalize(inv_model_t * vec4(v.normal_v, 0.0));
@@ -44,7 +48,9 @@ fn vs_main(v: Vertex, i: Instance) -> VertexOutput {
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    var color = fragmentLight(in);
+    var color = applyReflection(in, fragmentLight(in)) + fragmentEmissive(in);
+    var position = cameraPos(in).xyz;
+    color = applyFog(applyDistanceFog(color, position), position);
 
     return vec4(color, 1.0);
 }
@@ -1,7 +1,18 @@
 #define_import_path gpubasics::forward::phong::bindings
-#import gpubasics::phong::definitions::Lights;
 
-@group(1) @binding(0) var<storage, read> lights: Lights;
+// Lights are bound in `gpubasics::phong::bindings`, light lists of clusters or tiles here.
+#ifdef CLUSTERED
+#import gpubasics::forward::clusters::definitions::Cluster;
+
+@group(4) @binding(0) var<storage, read> clusters: array<Cluster>;
+
+#ifdef TILED
+#import gpubasics::forward::tiles::definitions::TileGrid;
+
+// Light lists in `clusters` belong to screen tiles instead.
+@group(4) @binding(1) var<uniform> tile_grid: TileGrid;
+#endif
+#endif
 
 #ifdef MATERIAL_PHONG_SOLID
 #import gpubasics::materials::phong_solid;
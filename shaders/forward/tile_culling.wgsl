@@ -0,0 +1,96 @@
+#import gpubasics::global::bindings::camera;
+#import gpubasics::global::depth::FAR_DEPTH;
+#import gpubasics::phong::definitions::Lights;
+#import gpubasics::phong::culling::lightReachesAabb;
+#import gpubasics::forward::clusters::definitions::{Cluster, onNearPlane, viewDepth};
+#import gpubasics::forward::tiles::definitions::TileGrid;
+
+// Tile size comes from `TileCullingPass`: TILE_SIZE. A workgroup covers a single tile.
+const TILE_THREADS: u32 = #{TILE_SIZE}u * #{TILE_SIZE}u;
+
+@group(1) @binding(0) var<storage, read> lights: Lights;
+// Light lists of tiles have the layout of clusters, so shading reads them the same way.
+@group(1) @binding(1) var<storage, read_write> tiles: array<Cluster>;
+@group(1) @binding(2) var<uniform> grid: TileGrid;
+@group(1) @binding(3) var depth: texture_depth_2d;
+
+var<workgroup> tileDepthMin: atomic<u32>;
+var<workgroup> tileDepthMax: atomic<u32>;
+var<workgroup> tileLightCount: atomic<u32>;
+
+// Lists point and spot lights reaching the tile's frustum, bounded by the closest
+// and farthest depth the prepass left inside the tile.
+@compute @workgroup_size(#{WORKGROUP_SIZE_X}, #{WORKGROUP_SIZE_Y}, #{WORKGROUP_SIZE_Z})
+fn cull_lights(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(workgroup_id) tile: vec3<u32>,
+    @builtin(local_invocation_index) localIdx: u32,
+) {
+    if localIdx == 0u {
+        atomicStore(&tileDepthMin, 0xffffffffu);
+        atomicStore(&tileDepthMax, 0u);
+        atomicStore(&tileLightCount, 0u);
+    }
+    workgroupBarrier();
+
+    var size = textureDimensions(depth);
+
+    // Empty background doesn't bound the tile.
+    var ndcDepth = textureLoad(depth, vec2<i32>(min(id.xy, size - 1u)), 0);
+    if all(id.xy < size) && ndcDepth != FAR_DEPTH {
+        // Positive floats keep their order when compared as unsigned integers.
+        var distance = bitcast<u32>(viewDepth(ndcDepth));
+        atomicMin(&tileDepthMin, distance);
+        atomicMax(&tileDepthMax, distance);
+    }
+    workgroupBarrier();
+
+    var depthMin = bitcast<f32>(atomicLoad(&tileDepthMin));
+    var depthMax = bitcast<f32>(atomicLoad(&tileDepthMax));
+    var tileIdx = tile.x + tile.y * grid.size.x;
+
+    if depthMin <= depthMax {
+        var pixelMin = vec2<f32>(tile.xy * #{TILE_SIZE}u) / vec2<f32>(size);
+        var pixelMax = vec2<f32>((tile.xy + 1u) * #{TILE_SIZE}u) / vec2<f32>(size);
+        var ndcMin = vec2(pixelMin.x * 2.0 - 1.0, 1.0 - pixelMax.y * 2.0);
+        var ndcMax = vec2(pixelMax.x * 2.0 - 1.0, 1.0 - pixelMin.y * 2.0);
+
+        // View space bounding box of the tile frustum between the depth bounds.
+        var corners = array<vec3<f32>, 4>(
+            onNearPlane(ndcMin),
+            onNearPlane(vec2(ndcMin.x, ndcMax.y)),
+            onNearPlane(vec2(ndcMax.x, ndcMin.y)),
+            onNearPlane(ndcMax),
+        );
+
+        var aabbMin = vec3(1e30);
+        var aabbMax = vec3(-1e30);
+        for (var i = 0; i < 4; i += 1) {
+            var corner = corners[i];
+            var nearCorner = corner * (depthMin / -corner.z);
+            var farCorner = corner * (depthMax / -corner.z);
+
+            aabbMin = min(aabbMin, min(nearCorner, farCorner));
+            aabbMax = max(aabbMax, max(nearCorner, farCorner));
+        }
+
+        var first = lights.num_directional;
+        var last = first + lights.num_point + lights.num_spot;
+        for (var i = first + localIdx; i < last; i += TILE_THREADS) {
+            var light = lights.lights[i];
+            var center = (camera * vec4(light.position.xyz, 1.0)).xyz;
+
+            if lightReachesAabb(light, i >= first + lights.num_point, center, aabbMin, aabbMax) {
+                var slot = atomicAdd(&tileLightCount, 1u);
+                if slot < #{MAX_CLUSTER_LIGHTS}u {
+                    tiles[tileIdx].lights[slot] = i;
+                }
+            }
+        }
+    }
+    workgroupBarrier();
+
+    if localIdx == 0u {
+        tiles[tileIdx].count = min(atomicLoad(&tileLightCount), #{MAX_CLUSTER_LIGHTS}u);
+    }
+}
@@ -0,0 +1,13 @@
+#define_import_path gpubasics::forward::tiles::definitions
+
+// Tile size comes from `TileCullingPass`: TILE_SIZE.
+struct TileGrid {
+    // Number of tiles in x and y.
+    size: vec4<u32>,
+};
+
+// Index of the light list of the tile containing given framebuffer position.
+fn tileIndex(grid: TileGrid, position: vec2<f32>) -> u32 {
+    var tile = vec2<u32>(position) / u32(#{TILE_SIZE});
+    return tile.x + tile.y * grid.size.x;
+}
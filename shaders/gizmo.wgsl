@@ -0,0 +1,30 @@
+#import gpubasics::global::bindings::camera;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+
+    // Only the rotation of the camera matters, the gizmo always sits in its own viewport.
+    var rotation = mat3x3<f32>(camera[0].xyz, camera[1].xyz, camera[2].xyz);
+    var view = rotation * in.position;
+
+    out.position = vec4(view.xy, 0.5 - view.z * 0.5, 1.0);
+    out.color = in.color;
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4(in.color, 1.0);
+}
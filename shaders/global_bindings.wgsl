@@ -4,3 +4,28 @@
 @group(0) @binding(1) var<uniform> projection: mat4x4<f32>;
 @group(0) @binding(2) var<uniform> camera_model: mat4x4<f32>;
 @group(0) @binding(3) var<uniform> projection_invt: mat4x4<f32>;
+
+struct SceneFog {
+    // Distance fog density, height fog density at the base height, its falloff above it and the base height.
+    medium: vec4<f32>,
+    // Color of the fog, w is non-zero while it's enabled.
+    color: vec4<f32>,
+};
+
+@group(0) @binding(4) var<uniform> scene_fog: SceneFog;
+
+// Written by `SceneUniform` every frame. Inverses of the camera and projection alone
+// are `camera_model` and `projection_invt`.
+struct SceneFrame {
+    // World position of what's at clip space coordinates, before dividing by w.
+    inverse_view_projection: mat4x4<f32>,
+    camera_position: vec3<f32>,
+    // Seconds since the renderer started.
+    time: f32,
+    // Size of the viewport in pixels.
+    viewport: vec2<f32>,
+    // Seconds the previous frame took.
+    delta_time: f32,
+};
+
+@group(0) @binding(5) var<uniform> scene_frame: SceneFrame;
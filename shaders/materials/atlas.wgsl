@@ -0,0 +1,75 @@
+#define_import_path gpubasics::materials::atlas
+
+struct Material {
+    ambient: vec4<f32>,
+    diffuse: vec4<f32>,
+    // w = shininess
+    specular: vec4<f32>,
+    emissive: vec4<f32>,
+    // Size class and layer in texture arrays, negative layer if there is no texture.
+    diffuse_t: vec2<i32>,
+    specular_t: vec2<i32>,
+    normal_t: vec2<i32>,
+    // Non-zero if alpha of the specular map scales shininess per texel.
+    gloss_map: u32,
+    // 0 - no reflection, 1 - perfect mirror of the environment.
+    reflectivity: f32,
+    // Weights of diffuse layers in its channels, negative layer for materials other than splat ones.
+    splat_t: vec2<i32>,
+    splat_layers: array<vec2<i32>, 4>,
+    // How many times layers repeat over the UV range.
+    splat_tiling: f32,
+};
+
+@group(#{MATERIAL_GROUP}) @binding(0) var<storage, read> materials: array<Material>;
+@group(#{MATERIAL_GROUP}) @binding(1) var mat_sampler: sampler;
+
+// One array per size class: 256, 512, 1024 and 2048 texels wide.
+@group(#{MATERIAL_GROUP}) @binding(2) var srgb_t0: texture_2d_array<f32>;
+@group(#{MATERIAL_GROUP}) @binding(3) var srgb_t1: texture_2d_array<f32>;
+@group(#{MATERIAL_GROUP}) @binding(4) var srgb_t2: texture_2d_array<f32>;
+@group(#{MATERIAL_GROUP}) @binding(5) var srgb_t3: texture_2d_array<f32>;
+@group(#{MATERIAL_GROUP}) @binding(6) var linear_t0: texture_2d_array<f32>;
+@group(#{MATERIAL_GROUP}) @binding(7) var linear_t1: texture_2d_array<f32>;
+@group(#{MATERIAL_GROUP}) @binding(8) var linear_t2: texture_2d_array<f32>;
+@group(#{MATERIAL_GROUP}) @binding(9) var linear_t3: texture_2d_array<f32>;
+
+fn material(index: u32) -> Material {
+    return materials[index];
+}
+
+// Textures have no mip levels, so sampling level 0 gives the same result as textureSample
+// and unlike it is allowed in branches which aren't uniform, like the ones below.
+fn sampleSrgb(t: vec2<i32>, uv: vec2<f32>) -> vec4<f32> {
+    switch t.x {
+        case 0: {
+            return textureSampleLevel(srgb_t0, mat_sampler, uv, t.y, 0.0);
+        }
+        case 1: {
+            return textureSampleLevel(srgb_t1, mat_sampler, uv, t.y, 0.0);
+        }
+        case 2: {
+            return textureSampleLevel(srgb_t2, mat_sampler, uv, t.y, 0.0);
+        }
+        default: {
+            return textureSampleLevel(srgb_t3, mat_sampler, uv, t.y, 0.0);
+        }
+    }
+}
+
+fn sampleLinear(t: vec2<i32>, uv: vec2<f32>) -> vec4<f32> {
+    switch t.x {
+        case 0: {
+            return textureSampleLevel(linear_t0, mat_sampler, uv, t.y, 0.0);
+        }
+        case 1: {
+            return textureSampleLevel(linear_t1, mat_sampler, uv, t.y, 0.0);
+        }
+        case 2: {
+            return textureSampleLevel(linear_t2, mat_sampler, uv, t.y, 0.0);
+        }
+        default: {
+            return textureSampleLevel(linear_t3, mat_sampler, uv, t.y, 0.0);
+        }
+    }
+}
@@ -1,51 +1,31 @@
 #define_import_path gpubasics::materials::phong_solid
 #import gpubasics::forward::outputs::vertex::VertexOutput;
-
-#ifdef NORMAL_MAP
-#ifdef GEOMETRY
-@group(1) @binding(2) var normal_t: texture_2d<f32>;
-@group(1) @binding(3) var mat_sampler: sampler;
-#else
-@group(2) @binding(2) var normal_t: texture_2d<f32>;
-@group(2) @binding(3) var mat_sampler: sampler;
-#endif
-#endif
-
-struct PhongSolidMat {
-    ambient: vec4<f32>,
-    diffuse: vec4<f32>,
-    specular: vec4<f32>,
-}
-
-#ifdef GEOMETRY
-@group(1) @binding(0) var<uniform> material: PhongSolidMat;
-#else
-@group(2) @binding(0) var<uniform> material: PhongSolidMat;
-#endif
+#import gpubasics::materials::atlas::material;
 
 fn materialDiffuse(in: VertexOutput) -> vec3<f32> {
-    return material.diffuse.xyz;
+    return material(in.material).diffuse.xyz;
 }
 
 fn materialSpecular(in: VertexOutput) -> vec3<f32> {
-    return material.specular.xyz;
+    return material(in.material).specular.xyz;
 }
 
 fn materialAmbient(in: VertexOutput) -> vec3<f32> {
-    return material.ambient.xyz;
+    return material(in.material).ambient.xyz;
 }
 
 fn shininess(in: VertexOutput) -> f32 {
-    return material.specular.w;
+    return material(in.material).specular.w;
 }
 
-#ifdef NORMAL_MAP
-fn normal(in: VertexOutput) -> vec3<f32> {
-    var tbn = mat3x3<f32>(in.t, in.b, in.normal);
-    return normalize(tbn * (textureSample(normal_t, mat_sampler, in.uv).rgb * 2.0 - 1.0));
+fn materialEmissive(in: VertexOutput) -> vec3<f32> {
+    return material(in.material).emissive.rgb;
+}
+
+fn materialReflectivity(in: VertexOutput) -> f32 {
+    return material(in.material).reflectivity;
 }
-#else
+
 fn normal(in: VertexOutput) -> vec3<f32> {
     return in.normal.xyz;
 }
-#endif
@@ -1,50 +1,63 @@
 #define_import_path gpubasics::materials::phong_textured
 #import gpubasics::forward::outputs::vertex::VertexOutput;
+#import gpubasics::materials::atlas::{material, sampleSrgb, sampleLinear};
 
-#ifdef GEOMETRY
-@group(1) @binding(0) var diffuse_t: texture_2d<f32>;
-@group(1) @binding(1) var specular_t: texture_2d<f32>;
-    #ifdef NORMAL_MAP
-    @group(1) @binding(2) var normal_t: texture_2d<f32>;
-    @group(1) @binding(3) var mat_sampler: sampler;
-    @group(1) @binding(4) var<uniform> uShininess: f32;
-    #else
-    @group(1) @binding(2) var mat_sampler: sampler;
-    @group(1) @binding(3) var<uniform> uShininess: f32;
-    #endif
-#else
-@group(2) @binding(0) var diffuse_t: texture_2d<f32>;
-@group(2) @binding(1) var specular_t: texture_2d<f32>;
-    #ifdef NORMAL_MAP
-    @group(2) @binding(2) var normal_t: texture_2d<f32>;
-    @group(2) @binding(3) var mat_sampler: sampler;
-    @group(2) @binding(4) var<uniform> uShininess: f32;
-    #else
-    @group(2) @binding(2) var mat_sampler: sampler;
-    @group(2) @binding(3) var<uniform> uShininess: f32;
-    #endif
-#endif
+// Splat materials blend their layers instead of having a single diffuse map.
+fn diffuseColor(in: VertexOutput) -> vec3<f32> {
+    let mat = material(in.material);
+    if mat.splat_t.y < 0 {
+        return sampleSrgb(mat.diffuse_t, in.uv).rgb;
+    }
+
+    let weights = sampleLinear(mat.splat_t, in.uv);
+    let uv = in.uv * mat.splat_tiling;
+    var color = weights.x * sampleSrgb(mat.splat_layers[0], uv).rgb;
+    color += weights.y * sampleSrgb(mat.splat_layers[1], uv).rgb;
+    color += weights.z * sampleSrgb(mat.splat_layers[2], uv).rgb;
+    color += weights.w * sampleSrgb(mat.splat_layers[3], uv).rgb;
+
+    return color / max(dot(weights, vec4(1.0)), 1e-4);
+}
 
 fn materialDiffuse(in: VertexOutput) -> vec3<f32> {
-    return textureSample(diffuse_t, mat_sampler, in.uv).rgb;
+    return diffuseColor(in);
 }
 
 fn materialSpecular(in: VertexOutput) -> vec3<f32> {
-    return textureSample(specular_t, mat_sampler, in.uv).rgb;
+    let mat = material(in.material);
+    if mat.specular_t.y < 0 {
+        return mat.specular.rgb;
+    }
+
+    return sampleLinear(mat.specular_t, in.uv).rgb;
 }
 
 fn materialAmbient(in: VertexOutput) -> vec3<f32> {
-    return textureSample(diffuse_t, mat_sampler, in.uv).rgb;
+    return diffuseColor(in);
 }
 
 fn shininess(in: VertexOutput) -> f32 {
-    return uShininess;
+    let mat = material(in.material);
+    if mat.gloss_map == 0u {
+        return mat.specular.w;
+    }
+
+    return mat.specular.w * sampleLinear(mat.specular_t, in.uv).a;
+}
+
+fn materialEmissive(in: VertexOutput) -> vec3<f32> {
+    return material(in.material).emissive.rgb;
+}
+
+fn materialReflectivity(in: VertexOutput) -> f32 {
+    return material(in.material).reflectivity;
 }
 
 #ifdef NORMAL_MAP
 fn normal(in: VertexOutput) -> vec3<f32> {
     var tbn = mat3x3<f32>(in.t, in.b, in.n);
-    return normalize(tbn * (textureSample(normal_t, mat_sampler, in.uv).rgb * 2.0 - 1.0));
+    let mapped = sampleLinear(material(in.material).normal_t, in.uv).rgb;
+    return normalize(tbn * (mapped * 2.0 - 1.0));
 }
 #else
 fn normal(in: VertexOutput) -> vec3<f32> {
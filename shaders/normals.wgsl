@@ -0,0 +1,85 @@
+#import gpubasics::global::bindings::{camera, projection};
+#import gpubasics::forward::buffers::vertex::Vertex;
+
+#ifdef PUSH_CONSTANTS
+// Pushed for every object. The inverse transpose is cut to 3x3, so all of it
+// fits into the least push constant space there is.
+struct DrawConstants {
+    model: mat4x4<f32>,
+    normal: mat3x3<f32>,
+    line_length: f32,
+};
+
+var<push_constant> draw: DrawConstants;
+
+fn model() -> mat4x4<f32> {
+    return draw.model;
+}
+
+fn normal_matrix() -> mat3x3<f32> {
+    return draw.normal;
+}
+
+fn line_length() -> f32 {
+    return draw.line_length;
+}
+#else
+struct ObjectTransform {
+    model: mat4x4<f32>,
+    model_invt: mat4x4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> object: ObjectTransform;
+@group(1) @binding(1) var<uniform> object_line_length: f32;
+
+fn model() -> mat4x4<f32> {
+    return object.model;
+}
+
+fn normal_matrix() -> mat3x3<f32> {
+    return mat3x3(object.model_invt[0].xyz, object.model_invt[1].xyz, object.model_invt[2].xyz);
+}
+
+fn line_length() -> f32 {
+    return object_line_length;
+}
+#endif
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+};
+
+// Mesh vertices are read per instance, every one of them expanded into a line
+// for each vector: normal first, then tangent and bitangent when present.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, v: Vertex) -> VertexOutput {
+    let line = index / 2u;
+    let is_end = f32(index % 2u);
+
+    // Vectors are drawn as stored, without re-orthogonalization done by lighting shaders.
+    var direction = normalize(normal_matrix() * v.normal_v);
+    var color = vec3(0.2, 0.4, 0.9);
+#ifdef VERTEX_TANGENT_SPACE
+    if line == 1u {
+        direction = normalize((model() * vec4(v.tangent_v, 0.0)).xyz);
+        color = vec3(0.9, 0.2, 0.2);
+    } else if line == 2u {
+        direction = normalize((model() * vec4(v.bitangent_v, 0.0)).xyz);
+        color = vec3(0.2, 0.8, 0.2);
+    }
+#endif
+
+    let world_v = (model() * vec4(v.model_v, 1.0)).xyz + direction * line_length() * is_end;
+
+    var out: VertexOutput;
+    out.position = projection * camera * vec4(world_v, 1.0);
+    out.color = color;
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4(in.color, 1.0);
+}
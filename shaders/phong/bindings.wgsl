@@ -0,0 +1,17 @@
+#define_import_path gpubasics::phong::bindings
+#import gpubasics::phong::definitions::Lights;
+
+// Laid out as `BindGroupLayouts::lights`, group 1 of forward and deferred lighting.
+// The fog volume follows in `gpubasics::fog::volume`.
+
+struct PointAmbient {
+    // Ambient term of all point lights. It is not attenuated, so lights culled
+    // from a cluster or a tile still add it.
+    ambient: vec4<f32>,
+};
+
+@group(1) @binding(0) var<storage, read> lights: Lights;
+@group(1) @binding(1) var<uniform> point_ambient: PointAmbient;
+// Skybox reflected by materials with non-zero reflectivity.
+@group(1) @binding(2) var environment: texture_cube<f32>;
+@group(1) @binding(3) var environment_sampler: sampler;
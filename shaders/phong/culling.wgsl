@@ -0,0 +1,32 @@
+#define_import_path gpubasics::phong::culling
+#import gpubasics::phong::definitions::Light;
+
+// Distance at which the light stops contributing visibly to the final color.
+fn lightRange(light: Light, isSpot: bool) -> f32 {
+    // Spot light ambient is not attenuated inside the cone.
+    if isSpot && any(light.ambient.xyz > vec3(0.0)) {
+        return 1e30;
+    }
+
+    var intensity = max(max(light.diffuse.x, light.diffuse.y), max(light.diffuse.z, max(light.specular.x, max(light.specular.y, light.specular.z))));
+    var kC = light.ambient.w;
+    var kL = light.diffuse.w;
+    var kQ = light.specular.w;
+    var cutoff = intensity * 256.0;
+
+    if kQ > 0.0 {
+        return (-kL + sqrt(max(kL * kL - 4.0 * kQ * (kC - cutoff), 0.0))) / (2.0 * kQ);
+    } else if kL > 0.0 {
+        return (cutoff - kC) / kL;
+    }
+
+    return 1e30;
+}
+
+// Tests if the light (in view space) reaches the view space bounding box.
+fn lightReachesAabb(light: Light, isSpot: bool, viewPos: vec3<f32>, aabbMin: vec3<f32>, aabbMax: vec3<f32>) -> bool {
+    var range = lightRange(light, isSpot);
+    var delta = viewPos - clamp(viewPos, aabbMin, aabbMax);
+
+    return dot(delta, delta) <= range * range;
+}
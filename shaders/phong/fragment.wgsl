@@ -2,15 +2,15 @@
 
 #ifdef DEFERRED
 #import gpubasics::deferred::outputs::vertex::VertexOutput;
-#import gpubasics::deferred::phong::fragment::{normal, worldPos, cameraPos, diffuse as materialDiffuse, diffuse as materialAmbient, specular as materialSpecular, shininess, ambientOcclusion};
+#import gpubasics::deferred::phong::fragment::{normal, worldPos, cameraPos, diffuse as materialDiffuse, diffuse as materialAmbient, specular as materialSpecular, shininess, reflectivity as materialReflectivity, emissive as materialEmissive, ambientOcclusion};
 #else
 #import gpubasics::forward::outputs::vertex::{worldPos, cameraPos, VertexOutput};
 #ifdef MATERIAL_PHONG_SOLID
-#import gpubasics::materials::phong_solid::{normal, materialDiffuse, materialSpecular, materialAmbient, shininess};
+#import gpubasics::materials::phong_solid::{normal, materialDiffuse, materialSpecular, materialAmbient, shininess, materialReflectivity, materialEmissive};
 #endif
 
 #ifdef MATERIAL_PHONG_TEXTURED
-#import gpubasics::materials::phong_textured::{normal, materialDiffuse, materialSpecular, materialAmbient, shininess};
+#import gpubasics::materials::phong_textured::{normal, materialDiffuse, materialSpecular, materialAmbient, shininess, materialReflectivity, materialEmissive};
 #endif
 #endif
 
@@ -42,6 +42,14 @@ fn fragmentShininess(in: VertexOutput) -> f32 {
     return shininess(in);
 }
 
+fn fragmentReflectivity(in: VertexOutput) -> f32 {
+    return materialReflectivity(in);
+}
+
+fn fragmentEmissive(in: VertexOutput) -> vec3<f32> {
+    return materialEmissive(in);
+}
+
 fn fragmentOcclusion(in: VertexOutput) -> f32 {
     #ifdef DEFERRED
     return ambientOcclusion(in);
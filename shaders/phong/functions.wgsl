@@ -3,18 +3,29 @@
 #import gpubasics::global::bindings::camera_model;
 #import gpubasics::phong::definitions::Light;
 
-#import gpubasics::phong::fragment::{fragmentCameraPos, fragmentWorldPos, fragmentNormal, fragmentAmbient, fragmentDiffuse, fragmentSpecular, fragmentShininess, fragmentOcclusion};
+#import gpubasics::phong::fragment::{fragmentCameraPos, fragmentWorldPos, fragmentNormal, fragmentAmbient, fragmentDiffuse, fragmentSpecular, fragmentShininess, fragmentReflectivity, fragmentOcclusion};
+
+#import gpubasics::phong::bindings::{lights, point_ambient, environment, environment_sampler};
 
 #ifdef DEFERRED
-#import gpubasics::deferred::phong::bindings::lights;
 #import gpubasics::deferred::outputs::vertex::VertexOutput;
 #else
-#import gpubasics::forward::phong::bindings::lights;
 #import gpubasics::forward::outputs::vertex::VertexOutput;
 #endif
 
+#ifdef CLUSTERED
+#import gpubasics::global::bindings::projection;
+#import gpubasics::forward::phong::bindings::clusters;
+#import gpubasics::forward::clusters::definitions::clusterIndex;
+
+#ifdef TILED
+#import gpubasics::forward::phong::bindings::tile_grid;
+#import gpubasics::forward::tiles::definitions::tileIndex;
+#endif
+#endif
+
 #ifdef SHADOW_MAP
-#import gpubasics::shadow::cascaded::functions::calculateShadow;
+#import gpubasics::shadow::cascaded::functions::{calculateShadow, cascadeTint};
 #endif
 
 fn attenuation(lightDistance: f32, light: Light) -> f32 {
@@ -51,17 +62,33 @@ fn phongLighting(in: VertexOutput, lightDirection: vec3<f32>, attenuation: f32,
     return color;
 }
 
+// Blends lit color with the environment seen in the mirrored view direction.
+fn applyReflection(in: VertexOutput, color: vec3<f32>) -> vec3<f32> {
+    var reflectivity = fragmentReflectivity(in);
+    if reflectivity <= 0.0 {
+        return color;
+    }
+
+    var view = normalize(fragmentWorldPos(in).xyz - camera_model[3].xyz);
+    var direction = reflect(view, normalize(fragmentNormal(in)));
+    var reflected = textureSampleLevel(environment, environment_sampler, direction, 0.0).rgb;
+
+    return mix(color, reflected, reflectivity);
+}
+
 fn calculateDirectional(in: VertexOutput, light: Light) -> vec3<f32> {
     var lightDirection = -light.direction.xyz;
     var attenuation = 1.0;
 
     #ifdef SHADOW_MAP
     var notShadowed = 1.0 - calculateShadow(in, lightDirection);
+    var tint = cascadeTint(in);
     #else
     var notShadowed = 1.0;
+    var tint = vec3(1.0, 1.0, 1.0);
     #endif
 
-    return phongLighting(in, lightDirection, attenuation, light, notShadowed);
+    return tint * phongLighting(in, lightDirection, attenuation, light, notShadowed);
 }
 
 fn calculateSpot(in: VertexOutput, light: Light) -> vec3<f32> {
@@ -93,6 +120,42 @@ fn calculatePoint(in: VertexOutput, light: Light) -> vec3<f32> {
     return phongLighting(in, lightDirection, attenuation, light, 1.0);
 }
 
+#ifdef CLUSTERED
+fn fragmentLight(in: VertexOutput) -> vec3<f32> {
+    var color = vec3(0.0, 0.0, 0.0);
+
+    for (var i = u32(0); i < lights.num_directional; i = i + 1) {
+        color += calculateDirectional(in, lights.lights[i]);
+    }
+
+    // Ambient of point lights reaches every fragment, so it's summed up front
+    // and only the attenuated part is evaluated for lights in the cluster.
+    color += point_ambient.ambient.xyz * fragmentAmbient(in) * fragmentOcclusion(in);
+
+#ifdef TILED
+    var cluster = tileIndex(tile_grid, in.position.xy);
+#else
+    var cameraPos = fragmentCameraPos(in);
+    var clip = projection * cameraPos;
+    var cluster = clusterIndex(clip.xy / clip.w, -cameraPos.z);
+#endif
+
+    var firstSpot = lights.num_directional + lights.num_point;
+    for (var i = u32(0); i < clusters[cluster].count; i = i + 1) {
+        var lightIdx = clusters[cluster].lights[i];
+        var light = lights.lights[lightIdx];
+
+        if lightIdx < firstSpot {
+            light.ambient = vec4(0.0, 0.0, 0.0, light.ambient.w);
+            color += calculatePoint(in, light);
+        } else {
+            color += calculateSpot(in, light);
+        }
+    }
+
+    return color;
+}
+#else
 fn fragmentLight(in: VertexOutput) -> vec3<f32> {
     var color = vec3(0.0, 0.0, 0.0);
 
@@ -110,3 +173,4 @@ fn fragmentLight(in: VertexOutput) -> vec3<f32> {
 
     return color;
 }
+#endif
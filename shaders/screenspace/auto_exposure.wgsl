@@ -0,0 +1,38 @@
+// Runs after `HistogramPass` measured the scene color, as a single invocation.
+
+// Middle grey the average luminance is exposed to.
+const KEY: f32 = 0.18;
+
+struct Params {
+    // Part of the way from the adapted luminance to the measured one covered this frame.
+    adaptation: f32,
+    // In stops.
+    compensation: f32,
+};
+
+// Leading fields of `HistogramResult` written by the histogram shader.
+struct Measured {
+    average_log_luminance: f32,
+    lit: u32,
+};
+
+struct Exposure {
+    luminance: f32,
+    scale: f32,
+    tonemap: u32,
+};
+
+@group(0) @binding(0) var<storage, read> measured: Measured;
+@group(0) @binding(1) var<storage, read_write> exposure: Exposure;
+@group(0) @binding(2) var<uniform> params: Params;
+
+@compute @workgroup_size(1, 1, 1)
+fn adapt_exposure() {
+    if measured.lit > 0u {
+        var luminance = exp2(measured.average_log_luminance);
+        exposure.luminance = mix(exposure.luminance, luminance, params.adaptation);
+    }
+
+    exposure.scale = KEY * exp2(params.compensation) / max(exposure.luminance, 0.0001);
+    exposure.tonemap = 1u;
+}
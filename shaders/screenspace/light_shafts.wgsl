@@ -0,0 +1,43 @@
+#import gpubasics::global::depth::FAR_DEPTH;
+
+// Kernel dimensions come from `LightShaftsPass`.
+
+struct Params {
+    // UV of the sun on screen, how visible it is and samples marched towards it.
+    sun: vec4<f32>,
+    // Color of the shafts, with the falloff of every next sample in w.
+    color: vec4<f32>,
+};
+
+@group(0) @binding(0) var depth: texture_depth_2d;
+@group(0) @binding(1) var scene: texture_2d<f32>;
+@group(0) @binding(2) var output: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(3) var<uniform> params: Params;
+
+// Radial blur of the sky left uncovered by geometry, towards the sun.
+@compute @workgroup_size(#{WORKGROUP_SIZE_X}, #{WORKGROUP_SIZE_Y}, #{WORKGROUP_SIZE_Z})
+fn light_shafts(@builtin(global_invocation_id) id: vec3<u32>) {
+    var size = textureDimensions(scene);
+    if any(id.xy >= size) {
+        return;
+    }
+
+    var color = textureLoad(scene, vec2<i32>(id.xy), 0).rgb;
+    var samples = max(u32(params.sun.w), 1u);
+
+    var uv = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size);
+    var delta = (params.sun.xy - uv) / f32(samples);
+    var weight = 1.0;
+    var shafts = 0.0;
+    for (var i = 0u; i < samples; i += 1u) {
+        uv += delta;
+        var texel = clamp(vec2<i32>(uv * vec2<f32>(size)), vec2(0), vec2<i32>(size) - 1);
+        if textureLoad(depth, texel, 0) == FAR_DEPTH {
+            shafts += weight;
+        }
+        weight *= params.color.w;
+    }
+
+    shafts *= params.sun.z / f32(samples);
+    textureStore(output, id.xy, vec4(color + shafts * params.color.rgb, 1.0));
+}
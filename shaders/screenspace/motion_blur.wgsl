@@ -0,0 +1,42 @@
+// Kernel dimensions come from `MotionBlurPass`.
+
+// Longest streak, as a fraction of the screen, so fast objects don't smear across all of it.
+const MAX_BLUR: f32 = 0.05;
+
+struct Params {
+    // Fraction of the motion since the previous frame blurred over, like a shutter angle.
+    intensity: f32,
+    samples: f32,
+};
+
+@group(0) @binding(0) var velocity: texture_2d<f32>;
+@group(0) @binding(1) var scene: texture_2d<f32>;
+@group(0) @binding(2) var output: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(3) var<uniform> params: Params;
+
+// The background has no velocity written, so it stays sharp.
+@compute @workgroup_size(#{WORKGROUP_SIZE_X}, #{WORKGROUP_SIZE_Y}, #{WORKGROUP_SIZE_Z})
+fn motion_blur(@builtin(global_invocation_id) id: vec3<u32>) {
+    var size = textureDimensions(scene);
+    if any(id.xy >= size) {
+        return;
+    }
+
+    var motion = textureLoad(velocity, vec2<i32>(id.xy), 0).xy * params.intensity;
+    var streak = length(motion);
+    if streak > MAX_BLUR {
+        motion *= MAX_BLUR / streak;
+    }
+
+    // Samples are spread over the motion, centered on the pixel.
+    var samples = max(u32(params.samples), 1u);
+    var pixels = motion * vec2<f32>(size);
+    var color = vec3(0.0);
+    for (var i = 0u; i < samples; i += 1u) {
+        var t = (f32(i) + 0.5) / f32(samples) - 0.5;
+        var position = clamp(vec2<i32>(vec2<f32>(id.xy) + 0.5 - pixels * t), vec2(0), vec2<i32>(size) - 1);
+        color += textureLoad(scene, position, 0).rgb;
+    }
+
+    textureStore(output, id.xy, vec4(color / f32(samples), 1.0));
+}
@@ -3,11 +3,24 @@ var texture: texture_2d<f32>;
 @group(0) @binding(1)
 var textureSampler: sampler;
 
-struct PostProcessSettings {
-    b_c_s_g: vec4<f32>
+struct Params {
+    b_c_s_g: vec4<f32>,
+    // Strengths of the vignette, film grain, chromatic aberration and sharpening.
+    effects: vec4<f32>,
+    // Seconds since the pass was created in x, for animated grain.
+    time: vec4<f32>,
 }
 
-@group(0) @binding(2) var<uniform> settings: PostProcessSettings;
+@group(0) @binding(2) var<uniform> params: Params;
+
+// Written by the auto exposure pass for HDR frames, scale 1 without tonemapping otherwise.
+struct Exposure {
+    luminance: f32,
+    scale: f32,
+    tonemap: u32,
+}
+
+@group(0) @binding(3) var<uniform> exposure: Exposure;
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
@@ -38,10 +51,22 @@ fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
     return out;
 }
 
-fn grayscaleAvg(color: vec3<f32>) -> vec3<f32> {
-    let avg = color.x + color.y + color.z;
+fn sampleInput(uv: vec2<f32>) -> vec3<f32> {
+    return textureSample(texture, textureSampler, uv).rgb;
+}
+
+// Fitted ACES filmic curve by Krzysztof Narkowicz.
+fn acesFilmic(x: vec3<f32>) -> vec3<f32> {
+    return saturate((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14));
+}
+
+fn exposed(color: vec3<f32>) -> vec3<f32> {
+    var scaled = color * exposure.scale;
+    if exposure.tonemap != 0u {
+        return acesFilmic(scaled);
+    }
 
-    return vec3<f32>(avg, avg, avg) / 3.0;
+    return scaled;
 }
 
 fn contrastBrightness(c: f32, b: f32, color: vec3<f32>) -> vec3<f32> {
@@ -60,13 +85,65 @@ fn saturation(color: vec3<f32>, s: f32) -> vec3<f32> {
     return saturate(mix(vec3(grayscale, grayscale, grayscale), color, s));
 }
 
+fn hash(p: vec2<f32>) -> f32 {
+    return fract(sin(dot(p, vec2(12.9898, 78.233))) * 43758.5453);
+}
+
+// First stage of the chain, brings the scene color into the displayable range.
+@fragment
+fn resolve(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4(exposed(sampleInput(in.tex_coords)), 1.0);
+}
+
+@fragment
+fn color_correction(in: VertexOutput) -> @location(0) vec4<f32> {
+    var color = sampleInput(in.tex_coords);
+    var brightness = params.b_c_s_g.x;
+    var contrast = params.b_c_s_g.y;
+    var saturation = params.b_c_s_g.z;
+    var gamma = params.b_c_s_g.w;
+
+    return vec4<f32>(gamma(saturation(contrastBrightness(contrast, brightness, color), saturation), gamma), 1.0);
+}
+
+// Darkens corners of the screen, more the further they are from the center.
+@fragment
+fn vignette(in: VertexOutput) -> @location(0) vec4<f32> {
+    var color = sampleInput(in.tex_coords);
+    var distance = length(in.tex_coords - 0.5) * 1.4142135;
+    var falloff = 1.0 - smoothstep(0.4, 1.0, distance);
+
+    return vec4(color * mix(1.0, falloff, params.effects.x), 1.0);
+}
+
+@fragment
+fn film_grain(in: VertexOutput) -> @location(0) vec4<f32> {
+    var color = sampleInput(in.tex_coords);
+    var noise = hash(in.clip_position.xy + fract(params.time.x) * 1000.0) - 0.5;
+
+    return vec4(max(color + noise * params.effects.y, vec3(0.0)), 1.0);
+}
+
+// Splits color channels apart towards the edges of the screen, like a cheap lens would.
+@fragment
+fn chromatic_aberration(in: VertexOutput) -> @location(0) vec4<f32> {
+    var offset = (in.tex_coords - 0.5) * params.effects.z;
+    var red = sampleInput(in.tex_coords + offset).r;
+    var green = sampleInput(in.tex_coords).g;
+    var blue = sampleInput(in.tex_coords - offset).b;
+
+    return vec4(red, green, blue, 1.0);
+}
+
+// Unsharp mask, pushes every pixel away from the average of its neighbours.
 @fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    var color = textureSample(texture, textureSampler, in.tex_coords);
-    var brightness = settings.b_c_s_g.x;
-    var contrast = settings.b_c_s_g.y;
-    var saturation = settings.b_c_s_g.z;
-    var gamma = settings.b_c_s_g.w;
-
-    return vec4<f32>(gamma(saturation(contrastBrightness(contrast, brightness, color.xyz), saturation), gamma), 1.0);
+fn sharpen(in: VertexOutput) -> @location(0) vec4<f32> {
+    var texel = 1.0 / vec2<f32>(textureDimensions(texture));
+    var color = sampleInput(in.tex_coords);
+    var neighbours = sampleInput(in.tex_coords + vec2(texel.x, 0.0))
+        + sampleInput(in.tex_coords - vec2(texel.x, 0.0))
+        + sampleInput(in.tex_coords + vec2(0.0, texel.y))
+        + sampleInput(in.tex_coords - vec2(0.0, texel.y));
+
+    return vec4(max(color + (color * 4.0 - neighbours) * params.effects.w, vec3(0.0)), 1.0);
 }
@@ -1,14 +1,30 @@
 #define_import_path gpubasics::shadow::cascaded::bindings
-#import gpubasics::shadow::cascaded::definitions::{ShadowMapMatrices, ShadowMapResult};
+#import gpubasics::shadow::cascaded::definitions::{ShadowMapMatrices, ShadowMapResult, ShadowFilter};
+
+// Cascades have their own resolutions, so each of them is a separate texture.
 
 #ifdef DEFERRED
 @group(2) @binding(0) var<uniform> smap_matrices: ShadowMapMatrices;
 @group(2) @binding(1) var smap_sampler: sampler;
-@group(2) @binding(2) var smap: texture_depth_2d_array;
+@group(2) @binding(2) var smap_a: texture_depth_2d;
+@group(2) @binding(6) var smap_b: texture_depth_2d;
+@group(2) @binding(7) var smap_c: texture_depth_2d;
 @group(2) @binding(3) var<uniform> smap_result: ShadowMapResult;
+@group(2) @binding(4) var<uniform> smap_filter: ShadowFilter;
+@group(2) @binding(5) var smap_cmp_sampler: sampler_comparison;
+@group(2) @binding(8) var smap_moments_a: texture_2d<f32>;
+@group(2) @binding(9) var smap_moments_b: texture_2d<f32>;
+@group(2) @binding(10) var smap_moments_c: texture_2d<f32>;
 #else
 @group(3) @binding(0) var<uniform> smap_matrices: ShadowMapMatrices;
 @group(3) @binding(1) var smap_sampler: sampler;
-@group(3) @binding(2) var smap: texture_depth_2d_array;
+@group(3) @binding(2) var smap_a: texture_depth_2d;
+@group(3) @binding(6) var smap_b: texture_depth_2d;
+@group(3) @binding(7) var smap_c: texture_depth_2d;
 @group(3) @binding(3) var<uniform> smap_result: ShadowMapResult;
+@group(3) @binding(4) var<uniform> smap_filter: ShadowFilter;
+@group(3) @binding(5) var smap_cmp_sampler: sampler_comparison;
+@group(3) @binding(8) var smap_moments_a: texture_2d<f32>;
+@group(3) @binding(9) var smap_moments_b: texture_2d<f32>;
+@group(3) @binding(10) var smap_moments_c: texture_2d<f32>;
 #endif
@@ -13,3 +13,14 @@ struct ShadowMapMatrices {
     proj_split_b: mat4x4<f32>,
     proj_split_c: mat4x4<f32>,
 };
+
+struct ShadowFilter {
+    // Width of the PCF kernel in texels.
+    kernel_size: f32,
+    // Size of the light in shadow map UV units, drives PCSS penumbra width.
+    light_size: f32,
+    // Lowest part of VSM light amounts cut off against light bleeding.
+    bleeding_reduction: f32,
+    // Non-zero tints fragments by the cascade they sample.
+    debug_cascades: f32,
+};
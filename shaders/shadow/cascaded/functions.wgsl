@@ -1,6 +1,6 @@
 #define_import_path gpubasics::shadow::cascaded::functions
 
-#import gpubasics::shadow::cascaded::bindings::{smap_matrices, smap, smap_sampler, smap_result};
+#import gpubasics::shadow::cascaded::bindings::{smap_matrices, smap_a, smap_b, smap_c, smap_sampler, smap_result, smap_filter, smap_cmp_sampler, smap_moments_a, smap_moments_b, smap_moments_c};
 
 #ifdef DEFERRED
 #import gpubasics::deferred::outputs::vertex::{VertexOutput};
@@ -11,19 +11,190 @@
 
 #import gpubasics::phong::fragment::{fragmentNormal as normal};
 
-fn calculateShadow(in: VertexOutput, lightDir: vec3<f32>) -> f32 {
+// Filtering mode is selected by one of SHADOW_FILTER_HARDWARE, SHADOW_FILTER_PCF,
+// SHADOW_FILTER_PCSS and SHADOW_FILTER_VSM definitions.
+
+const PCSS_BLOCKER_SAMPLES: i32 = 4;
+const PCSS_FILTER_SAMPLES: i32 = 5;
+// Keeps flat surfaces from shadowing themselves with moments stored at half precision.
+const VSM_MIN_VARIANCE: f32 = 0.00002;
+
+fn sampleDepth(uv: vec2<f32>, split: i32) -> f32 {
+    switch split {
+        case 0 {
+            return textureSampleLevel(smap_a, smap_sampler, uv, 0.0);
+        }
+        case 1 {
+            return textureSampleLevel(smap_b, smap_sampler, uv, 0.0);
+        }
+        default {
+            return textureSampleLevel(smap_c, smap_sampler, uv, 0.0);
+        }
+    }
+}
+
+fn compareDepth(uv: vec2<f32>, split: i32, depth: f32) -> f32 {
+    switch split {
+        case 0 {
+            return textureSampleCompareLevel(smap_a, smap_cmp_sampler, uv, depth);
+        }
+        case 1 {
+            return textureSampleCompareLevel(smap_b, smap_cmp_sampler, uv, depth);
+        }
+        default {
+            return textureSampleCompareLevel(smap_c, smap_cmp_sampler, uv, depth);
+        }
+    }
+}
+
+fn sampleMoments(uv: vec2<f32>, split: i32) -> vec2<f32> {
+    switch split {
+        case 0 {
+            return textureSampleLevel(smap_moments_a, smap_sampler, uv, 0.0).xy;
+        }
+        case 1 {
+            return textureSampleLevel(smap_moments_b, smap_sampler, uv, 0.0).xy;
+        }
+        default {
+            return textureSampleLevel(smap_moments_c, smap_sampler, uv, 0.0).xy;
+        }
+    }
+}
+
+fn cascadeSize(split: i32) -> vec2<u32> {
+    switch split {
+        case 0 {
+            return textureDimensions(smap_a);
+        }
+        case 1 {
+            return textureDimensions(smap_b);
+        }
+        default {
+            return textureDimensions(smap_c);
+        }
+    }
+}
+
+// Fraction of `kernel` x `kernel` samples, `spacing` apart, which are in shadow.
+fn pcf(uv: vec2<f32>, depth: f32, split: i32, spacing: vec2<f32>, kernel: i32) -> f32 {
     var shadow = 0.0;
-    var split = -1;
-    var light_cam_mats = array<mat4x4<f32>, 3>(smap_matrices.cam_split_a, smap_matrices.cam_split_b, smap_matrices.cam_split_c);
-    var light_proj_mats = array<mat4x4<f32>, 3>(smap_matrices.proj_split_a, smap_matrices.proj_split_b, smap_matrices.proj_split_c);
+    var center = f32(kernel - 1) * 0.5;
 
+    for (var x = 0; x < kernel; x += 1) {
+        for (var y = 0; y < kernel; y += 1) {
+            var offset = (vec2(f32(x), f32(y)) - center) * spacing;
+            var shadowDepth = sampleDepth(uv + offset, split);
+            if depth > shadowDepth {
+                shadow += 1.0;
+            }
+        }
+    }
+
+    return shadow / f32(kernel * kernel);
+}
+
+#ifdef SHADOW_FILTER_HARDWARE
+fn filterShadow(uv: vec2<f32>, depth: f32, split: i32, texelSize: vec2<f32>) -> f32 {
+    // Linear comparison sampler filters 2x2 texels on its own.
+    return 1.0 - compareDepth(uv, split, depth);
+}
+#endif
+
+#ifdef SHADOW_FILTER_PCF
+fn filterShadow(uv: vec2<f32>, depth: f32, split: i32, texelSize: vec2<f32>) -> f32 {
+    return pcf(uv, depth, split, texelSize, max(i32(smap_filter.kernel_size), 1));
+}
+#endif
+
+#ifdef SHADOW_FILTER_PCSS
+// Penumbra grows with the distance between the receiver and the average blocker,
+// so shadows are sharp where objects touch and soften further away from them.
+fn filterShadow(uv: vec2<f32>, depth: f32, split: i32, texelSize: vec2<f32>) -> f32 {
+    var searchWidth = vec2(smap_filter.light_size);
+    var center = f32(PCSS_BLOCKER_SAMPLES - 1) * 0.5;
+    var blockerDepth = 0.0;
+    var blockers = 0.0;
+
+    for (var x = 0; x < PCSS_BLOCKER_SAMPLES; x += 1) {
+        for (var y = 0; y < PCSS_BLOCKER_SAMPLES; y += 1) {
+            var offset = (vec2(f32(x), f32(y)) - center) / f32(PCSS_BLOCKER_SAMPLES) * searchWidth;
+            var shadowDepth = sampleDepth(uv + offset, split);
+            if depth > shadowDepth {
+                blockerDepth += shadowDepth;
+                blockers += 1.0;
+            }
+        }
+    }
+
+    if blockers == 0.0 {
+        return 0.0;
+    }
+
+    blockerDepth /= blockers;
+    var penumbra = clamp((depth - blockerDepth) / max(blockerDepth, 0.0001), 0.0, 1.0) * searchWidth;
+    var spacing = max(penumbra / f32(PCSS_FILTER_SAMPLES), texelSize);
+
+    return pcf(uv, depth, split, spacing, PCSS_FILTER_SAMPLES);
+}
+#endif
+
+#ifdef SHADOW_FILTER_VSM
+// Chebyshev's inequality bounds the fraction of light passing blurred occluders
+// using the mean depth and its variance around the sampled point.
+fn filterShadow(uv: vec2<f32>, depth: f32, split: i32, texelSize: vec2<f32>) -> f32 {
+    var moments = sampleMoments(uv, split);
+    if depth <= moments.x {
+        return 0.0;
+    }
+
+    var variance = max(moments.y - moments.x * moments.x, VSM_MIN_VARIANCE);
+    var d = depth - moments.x;
+    var pMax = variance / (variance + d * d);
+
+    var reduction = smap_filter.bleeding_reduction;
+    return 1.0 - clamp((pMax - reduction) / (1.0 - reduction), 0.0, 1.0);
+}
+#endif
+
+// Index of the cascade covering the fragment, -1 if it's farther than the last split.
+fn cascadeSplit(in: VertexOutput) -> i32 {
     for (var i = 0; i < i32(smap_result.num_splits); i += 1) {
         if abs(cameraPos(in).z) < smap_result.split_depths[i].x {
-            split = i;
-                break;
+            return i;
         }
     }
 
+    return -1;
+}
+
+// Color multiplier marking the cascade sampled by the fragment, when cascade debugging is on.
+fn cascadeTint(in: VertexOutput) -> vec3<f32> {
+    if smap_filter.debug_cascades == 0.0 {
+        return vec3(1.0, 1.0, 1.0);
+    }
+
+    switch cascadeSplit(in) {
+        case 0 {
+            return vec3(1.0, 0.4, 0.4);
+        }
+        case 1 {
+            return vec3(0.4, 1.0, 0.4);
+        }
+        case 2 {
+            return vec3(0.4, 0.4, 1.0);
+        }
+        default {
+            return vec3(1.0, 1.0, 1.0);
+        }
+    }
+}
+
+fn calculateShadow(in: VertexOutput, lightDir: vec3<f32>) -> f32 {
+    var shadow = 0.0;
+    var split = cascadeSplit(in);
+    var light_cam_mats = array<mat4x4<f32>, 3>(smap_matrices.cam_split_a, smap_matrices.cam_split_b, smap_matrices.cam_split_c);
+    var light_proj_mats = array<mat4x4<f32>, 3>(smap_matrices.proj_split_a, smap_matrices.proj_split_b, smap_matrices.proj_split_c);
+
     if split > -1 {
         var l_pos = light_proj_mats[split] * light_cam_mats[split] * worldPos(in);
         var lightPos = (l_pos.xyz / l_pos.w);
@@ -31,21 +202,11 @@ fn calculateShadow(in: VertexOutput, lightDir: vec3<f32>) -> f32 {
 
         var normal = normal(in);
 
-        var texSize = textureDimensions(smap).xy;
+        var texSize = cascadeSize(split);
         var texelSize = vec2(1.0 / f32(texSize.x), 1.0 / f32(texSize.y));
         var bias = max(0.01 * (1.0 - dot(normal, lightDir)), 0.001);
-        var texelPos = lightPos.xy;
-
-        // Percentage Closer Filtering with 3x3.
-        for (var x = -1; x <= 1; x += 1) {
-            for (var y = -1; y <= 1; y += 1) {
-                var shadowDepth = textureSample(smap, smap_sampler, (texelPos + vec2(f32(x), f32(y)) * texelSize) * vec2(0.5, -0.5) + 0.5, split);
-                if (lightDepth - bias) > shadowDepth {
-                    shadow += 1.0;
-                }
-            }
-        }
-        shadow /= 9.0;
+
+        shadow = filterShadow(lightPos.xy * vec2(0.5, -0.5) + 0.5, lightDepth - bias, split, texelSize);
 
         if lightDepth > 1.0 {
             shadow = 0.0;
@@ -0,0 +1,41 @@
+#import gpubasics::global::bindings::{camera_model, projection_invt};
+#import gpubasics::global::depth::FAR_DEPTH;
+
+struct Gradient {
+    top: vec4<f32>,
+    bottom: vec4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> gradient: Gradient;
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) clip: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOut {
+    var VERTEX: array<vec2<f32>, 4> = array<vec2<f32>, 4>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(-1.0, 1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0)
+    );
+
+    var o: VertexOut;
+    // Placed on the far plane, so only pixels not covered by geometry pass the depth test.
+    o.position = vec4<f32>(VERTEX[in_vertex_index], FAR_DEPTH, 1.0);
+    o.clip = VERTEX[in_vertex_index];
+
+    return o;
+}
+
+@fragment
+fn fs_main(i: VertexOut) -> @location(0) vec4<f32> {
+    var view = projection_invt * vec4<f32>(i.clip, 1.0, 1.0);
+    view /= view.w;
+
+    var world_dir = normalize((camera_model * vec4<f32>(view.xyz, 0.0)).xyz);
+
+    return mix(gradient.bottom, gradient.top, world_dir.y * 0.5 + 0.5);
+}
@@ -1,3 +1,5 @@
+#import gpubasics::global::depth::FAR_DEPTH;
+
 @group(0) @binding(0) var<uniform> camera: mat4x4<f32>;
 @group(0) @binding(1) var<uniform> projection: mat4x4<f32>;
 @group(1) @binding(0) var skybox_texture: texture_cube<f32>;
@@ -26,7 +28,8 @@ fn vs_main(v: VertexIn) -> VertexOut {
     );
 
     var cam_v = projection * camera_mat * vec4<f32>(v.model_v, 1.0);
-    o.position = cam_v.xyww;
+    // Kept on the far plane, behind everything else.
+    o.position = vec4<f32>(cam_v.xy, cam_v.w * FAR_DEPTH, cam_v.w);
     o.tex_coord = v.model_v;
 
     return o;
@@ -0,0 +1,118 @@
+#import gpubasics::global::bindings::{camera_model, projection_invt};
+#import gpubasics::global::depth::FAR_DEPTH;
+
+const PI: f32 = 3.14159265;
+// Cosine of the angular radius of the sun disc, a bit larger than the real one.
+const SUN_DISC_COS: f32 = 0.9995;
+// Seen when the sun is far below the horizon.
+const NIGHT_SKY: vec3<f32> = vec3<f32>(0.004, 0.006, 0.015);
+
+struct Sky {
+    // xyz = direction towards the sun, w = turbidity
+    sun: vec4<f32>,
+    // x = intensity
+    params: vec4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> sky: Sky;
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) clip: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOut {
+    var VERTEX: array<vec2<f32>, 4> = array<vec2<f32>, 4>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(-1.0, 1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0)
+    );
+
+    var o: VertexOut;
+    // Placed on the far plane, so only pixels not covered by geometry pass the depth test.
+    o.position = vec4<f32>(VERTEX[in_vertex_index], FAR_DEPTH, 1.0);
+    o.clip = VERTEX[in_vertex_index];
+
+    return o;
+}
+
+// Perez sky luminance distribution, theta is the zenith angle of the view direction
+// and gamma its angle to the sun.
+fn perez(theta: f32, gamma: f32, a: f32, b: f32, c: f32, d: f32, e: f32) -> f32 {
+    let cos_gamma = cos(gamma);
+    return (1.0 + a * exp(b / max(cos(theta), 0.01))) * (1.0 + c * exp(d * gamma) + e * cos_gamma * cos_gamma);
+}
+
+fn xyYToLinearSrgb(xyY: vec3<f32>) -> vec3<f32> {
+    let Y = xyY.z;
+    let X = xyY.x / xyY.y * Y;
+    let Z = (1.0 - xyY.x - xyY.y) / xyY.y * Y;
+
+    return vec3<f32>(
+        3.2406 * X - 1.5372 * Y - 0.4986 * Z,
+        -0.9689 * X + 1.8758 * Y + 0.0415 * Z,
+        0.0557 * X - 0.2040 * Y + 1.0570 * Z
+    );
+}
+
+// Preetham, Shirley and Smits, "A Practical Analytic Model for Daylight".
+fn preetham(view: vec3<f32>, sun: vec3<f32>, turbidity: f32) -> vec3<f32> {
+    let t = turbidity;
+    // The model only holds with the sun above the horizon.
+    let theta_s = min(acos(clamp(sun.y, -1.0, 1.0)), PI / 2.0 - 0.01);
+    let theta = acos(clamp(view.y, 0.0, 1.0));
+    let gamma = acos(clamp(dot(view, sun), -1.0, 1.0));
+
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+    let zenith_Y = (4.0453 * t - 4.9710) * tan(chi) - 0.2155 * t + 2.4192;
+
+    let ts = vec4<f32>(theta_s * theta_s * theta_s, theta_s * theta_s, theta_s, 1.0);
+    let zenith_x = dot(vec3<f32>(t * t, t, 1.0), vec3<f32>(
+        dot(vec4<f32>(0.00166, -0.00375, 0.00209, 0.0), ts),
+        dot(vec4<f32>(-0.02903, 0.06377, -0.03202, 0.00394), ts),
+        dot(vec4<f32>(0.11693, -0.21196, 0.06052, 0.25886), ts)
+    ));
+    let zenith_y = dot(vec3<f32>(t * t, t, 1.0), vec3<f32>(
+        dot(vec4<f32>(0.00275, -0.00610, 0.00317, 0.0), ts),
+        dot(vec4<f32>(-0.04214, 0.08970, -0.04153, 0.00516), ts),
+        dot(vec4<f32>(0.15346, -0.26756, 0.06670, 0.26688), ts)
+    ));
+
+    let Y = zenith_Y
+        * perez(theta, gamma, 0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703)
+        / perez(0.0, theta_s, 0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703);
+    let x = zenith_x
+        * perez(theta, gamma, -0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452)
+        / perez(0.0, theta_s, -0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452);
+    let y = zenith_y
+        * perez(theta, gamma, -0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529)
+        / perez(0.0, theta_s, -0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529);
+
+    // Luminance relative to the zenith, absolute values are in kcd/m².
+    return max(xyYToLinearSrgb(vec3<f32>(x, y, Y / zenith_Y)), vec3<f32>(0.0));
+}
+
+@fragment
+fn fs_main(i: VertexOut) -> @location(0) vec4<f32> {
+    var view = projection_invt * vec4<f32>(i.clip, 1.0, 1.0);
+    view /= view.w;
+
+    let world_dir = normalize((camera_model * vec4<f32>(view.xyz, 0.0)).xyz);
+    let sun = normalize(sky.sun.xyz);
+
+    // Fades out around sunset, so the night sky takes over.
+    let daylight = smoothstep(-0.1, 0.05, sun.y);
+
+    // Below the horizon the sky is mirrored and darkened, standing in for the ground.
+    let above = vec3<f32>(world_dir.x, abs(world_dir.y), world_dir.z);
+    var color = preetham(above, sun, sky.sun.w) * sky.params.x * daylight;
+    if world_dir.y < 0.0 {
+        color *= 0.3;
+    } else if dot(world_dir, sun) > SUN_DISC_COS {
+        color += vec3<f32>(10.0, 9.0, 8.0) * daylight;
+    }
+
+    return vec4<f32>(max(color, NIGHT_SKY), 1.0);
+}
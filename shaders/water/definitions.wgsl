@@ -0,0 +1,27 @@
+#define_import_path gpubasics::water::definitions
+
+struct Water {
+    // Height of the plane, its extent, z unused, and world units a normal map tile covers.
+    surface: vec4<f32>,
+    // Normal map tiles scrolled per second, screen space distortion of reflection and refraction,
+    // and depth below the surface at which the bottom can't be seen anymore.
+    waves: vec4<f32>,
+    // Color of deep water.
+    color: vec4<f32>,
+    // Direction the sun shines in.
+    sun_direction: vec4<f32>,
+    sun_ambient: vec4<f32>,
+    sun_diffuse: vec4<f32>,
+    sun_specular: vec4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> water: Water;
+
+fn waterHeight() -> f32 {
+    return water.surface.x;
+}
+
+// World position reflected through the water plane.
+fn mirrored(world: vec4<f32>) -> vec4<f32> {
+    return vec4(world.x, 2.0 * waterHeight() - world.y, world.z, world.w);
+}
@@ -0,0 +1,57 @@
+#import gpubasics::global::bindings::{camera, projection};
+#import gpubasics::phong::fragment::{fragmentNormal, fragmentDiffuse, fragmentAmbient, fragmentEmissive};
+#import gpubasics::forward::buffers::instance::{Instance, model, model_invt};
+#import gpubasics::forward::buffers::vertex::Vertex;
+#import gpubasics::forward::outputs::vertex::VertexOutput;
+#import gpubasics::water::definitions::{water, waterHeight, mirrored};
+
+// Scene seen in the water, drawn from below the plane. It's lit only by the sun,
+// without shadows - distortion of the surface hides the difference.
+@vertex
+fn vs_main(v: Vertex, i: Instance) -> VertexOutput {
+    var model = model(i);
+    var inv_model_t = model_invt(i);
+
+    var world_v = model * vec4<f32>(v.model_v, 1.0);
+    var camera_v = camera * mirrored(world_v);
+
+    var out: VertexOutput;
+    out.position = projection * camera_v;
+    out.w_pos = world_v;
+    out.c_pos = camera_v;
+    out.material = i.material;
+
+    #ifndef VERTEX_PNTBUV
+    out.normal = normalize(inv_model_t * vec4(v.normal_v, 0.0));
+    #endif
+
+    #ifdef VERTEX_PNTBUV
+    out.t = normalize(inv_model_t * vec4(v.tangent_v, 0.0)).xyz;
+    out.n = normalize(inv_model_t * vec4(v.normal_v, 0.0)).xyz;
+    out.t = normalize(out.t - dot(out.n, out.t) * out.n);
+    out.b = cross(out.n, out.t);
+    #endif
+
+    #ifndef VERTEX_PN
+    out.uv = v.uv;
+    #endif
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Whatever is under water can't be reflected.
+    if in.w_pos.y < waterHeight() {
+        discard;
+    }
+
+    var normal = fragmentNormal(in);
+    var diffuse = max(dot(normal, -water.sun_direction.xyz), 0.0);
+    var color = water.sun_ambient.rgb * fragmentAmbient(in)
+        + water.sun_diffuse.rgb * diffuse * fragmentDiffuse(in)
+        + fragmentEmissive(in);
+
+    // Alpha tells the surface where the environment map shows through instead.
+    return vec4(color, 1.0);
+}
@@ -0,0 +1,132 @@
+#import gpubasics::global::bindings::{camera, projection, projection_invt, scene_frame};
+#import gpubasics::water::definitions::{water, waterHeight};
+
+// Reflectance of water seen head-on.
+const F0: f32 = 0.02;
+const SUN_SHININESS: f32 = 256.0;
+// Depth of water over which the shore fades into the scene.
+const SHORE_FADE: f32 = 0.2;
+
+@group(1) @binding(1) var scene_color: texture_2d<f32>;
+@group(1) @binding(2) var scene_depth: texture_depth_2d;
+@group(1) @binding(3) var reflection: texture_2d<f32>;
+@group(1) @binding(4) var normal_map: texture_2d<f32>;
+@group(1) @binding(5) var environment: texture_cube<f32>;
+// Repeats, screen space textures are sampled with clamped UVs.
+@group(1) @binding(6) var linear_sampler: sampler;
+
+var<private> QUAD: array<vec2<f32>, 4> = array<vec2<f32>, 4>(
+    vec2<f32>(-1.0, -1.0),
+    vec2<f32>(-1.0, 1.0),
+    vec2<f32>(1.0, -1.0),
+    vec2<f32>(1.0, 1.0)
+);
+
+struct FullscreenOut {
+    @builtin(position) position: vec4<f32>,
+};
+
+// The scene is copied over first, the surface is drawn on top of the copy.
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) in_vertex_index: u32) -> FullscreenOut {
+    var o: FullscreenOut;
+    o.position = vec4<f32>(QUAD[in_vertex_index], 0.0, 1.0);
+    return o;
+}
+
+@fragment
+fn fs_copy(in: FullscreenOut) -> @location(0) vec4<f32> {
+    return textureLoad(scene_color, vec2<i32>(in.position.xy), 0);
+}
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) w_pos: vec3<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOut {
+    var corner = QUAD[in_vertex_index] * water.surface.y * 0.5;
+    var world = vec4<f32>(corner.x, waterHeight(), corner.y, 1.0);
+
+    var o: VertexOut;
+    o.position = projection * camera * world;
+    o.w_pos = world.xyz;
+    return o;
+}
+
+fn screenSize() -> vec2<f32> {
+    return vec2<f32>(textureDimensions(scene_depth));
+}
+
+fn texel(uv: vec2<f32>) -> vec2<i32> {
+    return clamp(vec2<i32>(uv * screenSize()), vec2(0), vec2<i32>(screenSize()) - 1);
+}
+
+// Distance from the camera to the scene behind `uv`, along the view axis.
+fn sceneDistance(uv: vec2<f32>) -> f32 {
+    var depth = textureLoad(scene_depth, texel(uv), 0);
+    var view = projection_invt * vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    return -view.z / view.w;
+}
+
+// Two copies of the normal map scroll across each other in different directions.
+fn surfaceNormal(w_pos: vec3<f32>) -> vec3<f32> {
+    var uv = w_pos.xz / water.surface.w;
+    var scroll = water.waves.x * scene_frame.time;
+
+    var a = textureSampleLevel(normal_map, linear_sampler, uv + vec2(scroll, scroll * 0.4), 0.0).xyz;
+    var b = textureSampleLevel(normal_map, linear_sampler, uv * 1.7 + vec2(-scroll * 0.6, scroll), 0.0).xyz;
+    // Normal maps point along z, the surface faces up.
+    var n = (a + b) - 1.0;
+    return normalize(vec3(n.x, n.z, n.y));
+}
+
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    var uv = in.position.xy / screenSize();
+    var water_distance = -(camera * vec4(in.w_pos, 1.0)).z;
+    // The surface doesn't write depth, so it's tested against the scene here.
+    var scene_distance = sceneDistance(uv);
+    if scene_distance < water_distance {
+        discard;
+    }
+
+    var eye = scene_frame.camera_position;
+    var to_eye = normalize(eye - in.w_pos);
+    var normal = surfaceNormal(in.w_pos);
+    // Seen from below, the surface faces down.
+    if to_eye.y < 0.0 {
+        normal = -normal;
+    }
+
+    var distortion = normal.xz * water.waves.y;
+
+    // Refraction is only bent towards what's below the surface, not objects in front of it.
+    var refracted_uv = uv + distortion;
+    if sceneDistance(refracted_uv) < water_distance {
+        refracted_uv = uv;
+    }
+    var refracted = textureSampleLevel(scene_color, linear_sampler, clamp(refracted_uv, vec2(0.0), vec2(1.0)), 0.0).rgb;
+    var depth_below = sceneDistance(refracted_uv) - water_distance;
+    var transmittance = exp(-depth_below / max(water.waves.z, 1e-3));
+    var below = mix(water.color.rgb, refracted, transmittance);
+
+    var reflected_uv = clamp(uv + distortion, vec2(0.0), vec2(1.0));
+    var mirrored = textureSampleLevel(reflection, linear_sampler, reflected_uv, 0.0);
+    var sky = textureSampleLevel(environment, linear_sampler, reflect(-to_eye, normal), 0.0).rgb;
+    var above = mix(sky, mirrored.rgb, mirrored.a);
+
+    var fresnel = F0 + (1.0 - F0) * pow(1.0 - max(dot(normal, to_eye), 0.0), 5.0);
+    var color = mix(below, above, fresnel);
+
+    var to_sun = -water.sun_direction.xyz;
+    var halfway = normalize(to_sun + to_eye);
+    color += water.sun_specular.rgb * pow(max(dot(normal, halfway), 0.0), SUN_SHININESS);
+
+    // Shallow water at the shore blends into the scene instead of ending with an edge.
+    var scene = textureLoad(scene_color, texel(uv), 0).rgb;
+    var shore = clamp((scene_distance - water_distance) / SHORE_FADE, 0.0, 1.0);
+
+    return vec4(mix(scene, color, shore), 1.0);
+}
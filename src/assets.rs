@@ -0,0 +1,111 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, Sender},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::loader::{ObjLoader, ObjLoaderSettings, ObjModel};
+
+type ModelKey = (PathBuf, ObjLoaderSettings);
+
+/// Key under which files are cached, the same for every path leading to the file
+/// (e.g. `./models/a.obj` and `models/a.obj`).
+pub fn canonical_path(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned())
+}
+
+enum ModelAsset {
+    Loading,
+    Loaded(ObjModel),
+    // Kept as text, so every scene built with the model can report it.
+    Failed(String),
+}
+
+/// Reads models on background threads. Scenes are built right away with placeholders
+/// in place of models which are still loading, and are meant to have them swapped in
+/// once `poll` reports new arrivals, see `GpuScene::replace_model`.
+///
+/// Models stay loaded, so rebuilding a scene doesn't read them again.
+/// Textures are loaded in the background by `MaterialAtlas` on its own.
+pub struct AssetManager {
+    models: HashMap<ModelKey, ModelAsset>,
+    sender: Sender<(ModelKey, Result<ObjModel>)>,
+    receiver: Receiver<(ModelKey, Result<ObjModel>)>,
+    // Of models which arrived since the last `take_warnings`.
+    warnings: Vec<String>,
+}
+
+impl Default for AssetManager {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+
+        Self {
+            models: HashMap::new(),
+            sender,
+            receiver,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+impl AssetManager {
+    /// The model, if it's loaded already. Otherwise starts loading it
+    /// unless that's already happening.
+    pub fn obj_model(
+        &mut self,
+        path: &Path,
+        settings: ObjLoaderSettings,
+    ) -> Result<Option<&ObjModel>> {
+        let key = (canonical_path(path), settings);
+
+        let asset = self.models.entry(key.clone()).or_insert_with(|| {
+            let sender = self.sender.clone();
+            tokio::task::spawn_blocking(move || {
+                let model = ObjLoader::parse(&key.0, key.1);
+                // Receiver is gone only when the manager is dropped, nothing to do then.
+                let _ = sender.send((key, model));
+            });
+
+            ModelAsset::Loading
+        });
+
+        match asset {
+            ModelAsset::Loading => Ok(None),
+            ModelAsset::Loaded(model) => Ok(Some(model)),
+            ModelAsset::Failed(e) => Err(anyhow!("{e}")),
+        }
+    }
+
+    /// Takes in models which finished loading since the last call.
+    /// Returns whether there were any, failed ones included.
+    pub fn poll(&mut self) -> bool {
+        let mut arrived = false;
+
+        for (key, model) in self.receiver.try_iter() {
+            let asset = match model {
+                Ok(model) => {
+                    self.warnings.extend(
+                        model
+                            .warnings()
+                            .iter()
+                            .map(|warning| format!("{}: {}", key.0.display(), warning)),
+                    );
+                    ModelAsset::Loaded(model)
+                }
+                Err(e) => ModelAsset::Failed(format!("{e:#}")),
+            };
+
+            self.models.insert(key, asset);
+            arrived = true;
+        }
+
+        arrived
+    }
+
+    /// Warnings of models which finished loading since the last call, see `ObjModel::warnings`.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+}
@@ -0,0 +1,227 @@
+use std::{sync::Arc, time::Instant};
+
+use anyhow::{Context, Result};
+use encase::{ShaderSize, UniformBuffer};
+use nalgebra as na;
+
+use crate::{
+    compute::{Histogram, HistogramPass, LuminanceRange},
+    postprocess_pass::Exposure,
+    render_context::RenderContext,
+    render_graph::{Binding, GraphResources},
+    render_pass::{FrameContext, RenderPass},
+    settings::{AutoExposureSettings, PipelineType},
+};
+
+/// Measures the average luminance of the HDR scene color with a histogram and
+/// adapts the exposure postprocessing applies towards it over time.
+pub struct AutoExposurePass<'window> {
+    render_ctx: Arc<RenderContext<'window>>,
+    histogram: HistogramPass,
+    bgl: Arc<wgpu::BindGroupLayout>,
+    adapt_pipeline: wgpu::ComputePipeline,
+    params_buf: wgpu::Buffer,
+    exposure_buf: Arc<wgpu::Buffer>,
+    // Exposure jumps straight to the measured luminance on the first frame.
+    last_frame: Option<Instant>,
+}
+
+impl<'window> AutoExposurePass<'window> {
+    pub fn new(render_ctx: Arc<RenderContext<'window>>) -> Result<Self> {
+        let RenderContext {
+            gpu,
+            bind_group_layouts,
+            shader_compiler,
+            ..
+        } = render_ctx.as_ref();
+
+        let histogram = HistogramPass::new(gpu, shader_compiler, bind_group_layouts)?;
+
+        let params_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("AutoExposurePass::Params"),
+            size: na::Vector4::<f32>::SHADER_SIZE.into(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let initial = Exposure {
+            luminance: 0.18,
+            scale: 1.0,
+            tonemap: 1,
+        };
+        use wgpu::util::DeviceExt;
+        let exposure_buf = gpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("AutoExposurePass::Exposure"),
+                contents: initial.contents()?.as_slice(),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::UNIFORM,
+            });
+
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let bgl = bind_group_layouts.get(
+            gpu,
+            "AutoExposurePass::BindGroupLayout",
+            &[
+                storage(0, true),
+                storage(1, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        );
+
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("AutoExposurePass::PipelineLayout"),
+                bind_group_layouts: &[&bgl],
+                push_constant_ranges: &[],
+            });
+
+        let shader = gpu.shader_from_module(
+            shader_compiler
+                .compilation_unit("./shaders/screenspace/auto_exposure.wgsl")?
+                .compile(&[])?,
+        );
+
+        let adapt_pipeline = gpu
+            .device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("AutoExposurePass::Adapt"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "adapt_exposure",
+            });
+
+        Ok(Self {
+            render_ctx,
+            histogram,
+            bgl,
+            adapt_pipeline,
+            params_buf,
+            exposure_buf: Arc::new(exposure_buf),
+            last_frame: None,
+        })
+    }
+
+    /// Luminance histogram of the last frame exposure was measured on.
+    pub fn histogram(&self) -> Result<Histogram> {
+        self.histogram.read(&self.render_ctx.gpu)
+    }
+
+    pub fn render(
+        &mut self,
+        scene_color: &wgpu::TextureView,
+        settings: &AutoExposureSettings,
+        resources: &GraphResources,
+    ) -> Result<()> {
+        let render_ctx = self.render_ctx.clone();
+        let RenderContext { gpu, profiler, .. } = render_ctx.as_ref();
+
+        let now = Instant::now();
+        let adaptation = match self.last_frame.replace(now) {
+            Some(last) => {
+                1.0 - (-now.duration_since(last).as_secs_f32() * settings.adaptation_speed).exp()
+            }
+            None => 1.0,
+        };
+
+        // Fields of `Params` in the shader.
+        let params = na::Vector4::new(adaptation, settings.compensation, 0.0, 0.0);
+        let size: u64 = na::Vector4::<f32>::SHADER_SIZE.into();
+        let mut contents = UniformBuffer::new(Vec::with_capacity(size as usize));
+        contents.write(&params)?;
+        gpu.queue
+            .write_buffer(&self.params_buf, 0, contents.into_inner().as_slice());
+
+        let bg = resources.bind_group(
+            gpu,
+            "AutoExposurePass::BindGroup",
+            &self.bgl,
+            &[
+                Binding::Buffer(self.histogram.result()),
+                Binding::Buffer(&self.exposure_buf),
+                Binding::Buffer(&self.params_buf),
+            ],
+        )?;
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("AutoExposurePass::CommandEncoder"),
+            });
+
+        self.histogram.record(
+            gpu,
+            &mut encoder,
+            scene_color,
+            gpu.viewport_size(),
+            LuminanceRange::new(settings.min_log_luminance, settings.max_log_luminance),
+            profiler.compute_pass_writes("Luminance Histogram"),
+        )?;
+
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("AutoExposurePass::ComputePass"),
+                timestamp_writes: profiler.compute_pass_writes("Auto Exposure"),
+            });
+
+            cpass.set_pipeline(&self.adapt_pipeline);
+            cpass.set_bind_group(0, &bg, &[]);
+            cpass.dispatch_workgroups(1, 1, 1);
+        }
+
+        gpu.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
+
+impl RenderPass for AutoExposurePass<'_> {
+    fn name(&self) -> &'static str {
+        "AutoExposurePass"
+    }
+
+    // Only deferred frames are lit in HDR.
+    fn enabled(&self, ctx: &FrameContext) -> bool {
+        let settings = ctx.settings;
+        settings.auto_exposure.enabled
+            && settings.pipeline_type == PipelineType::Deferred
+            && !settings.postprocess_disabled
+            && !settings.deferred_debug_shown()
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        let (scene_color, _) = ctx
+            .scene_color
+            .as_ref()
+            .filter(|(_, hdr)| *hdr)
+            .context("exposure is measured from a HDR scene color")?;
+        Self::render(
+            self,
+            scene_color,
+            &ctx.settings.auto_exposure,
+            &ctx.resources,
+        )?;
+        ctx.exposure = Some(self.exposure_buf.clone());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,396 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use encase::{ShaderSize, UniformBuffer};
+use nalgebra as na;
+
+use crate::{
+    gpu::Gpu,
+    render_context::RenderContext,
+    render_graph::{Binding, GraphResources},
+    render_pass::{FrameContext, RenderPass},
+    settings::BillboardSettings,
+};
+
+type FVec3 = na::Vector3<f32>;
+type FVec4 = na::Vector4<f32>;
+
+const SPRITE_TEXTURE: &str = "./textures/billboards/sprites.png";
+
+/// Bytes a billboard takes on the GPU, laid out like `Billboard` in the billboard shader.
+pub const BILLBOARD_STRIDE: usize = std::mem::size_of::<[f32; 12]>();
+
+// Fields of `BillboardParams` in the shader.
+type Params = [na::Vector4<f32>; 1];
+
+/// Icons of the sprite sheet, in the order they're laid out left to right.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sprite {
+    PointLight,
+    SpotLight,
+    Marker,
+}
+
+impl Sprite {
+    pub const ALL: [Self; 3] = [Self::PointLight, Self::SpotLight, Self::Marker];
+
+    fn index(&self) -> u32 {
+        *self as u32
+    }
+}
+
+/// Quad centered at `position` that always faces the camera, textured with a sprite
+/// multiplied by `color`.
+#[derive(Clone, Copy, Debug)]
+pub struct Billboard {
+    pub position: FVec3,
+    // Width and height in world units.
+    pub size: f32,
+    pub color: FVec4,
+    pub sprite: Sprite,
+}
+
+impl Billboard {
+    pub fn new(position: FVec3, size: f32, sprite: Sprite) -> Self {
+        Self {
+            position,
+            size,
+            color: FVec4::repeat(1.0),
+            sprite,
+        }
+    }
+
+    pub fn with_color(mut self, color: FVec4) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn copy_to(&self, target: &mut Vec<u8>) {
+        let [x, y, z]: [f32; 3] = self.position.into();
+        let [r, g, b, a]: [f32; 4] = self.color.into();
+        target.extend(bytemuck::cast_slice(&[x, y, z, self.size, r, g, b, a]));
+        target.extend(bytemuck::cast_slice(&[self.sprite.index(), 0, 0, 0]));
+    }
+}
+
+/// Camera facing sprites for lights, markers and the like, blended over the scene color.
+/// Billboards are handed to the pass every frame. They aren't depth tested, but fade out
+/// as they get close to the geometry behind them, so they don't get cut in half by it.
+pub struct BillboardPass<'window> {
+    render_ctx: Arc<RenderContext<'window>>,
+    billboards: Vec<Billboard>,
+    params_buf: wgpu::Buffer,
+    // Grown when there are more billboards than it fits.
+    billboard_buf: wgpu::Buffer,
+    billboard_capacity: usize,
+    bgl: Arc<wgpu::BindGroupLayout>,
+    rgba8_pipeline: wgpu::RenderPipeline,
+    rgba16_pipeline: wgpu::RenderPipeline,
+    texture_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+}
+
+impl<'window> BillboardPass<'window> {
+    pub fn new(render_ctx: Arc<RenderContext<'window>>) -> Result<Self> {
+        let RenderContext {
+            gpu,
+            bind_group_layouts,
+            shader_compiler,
+            scene_uniform,
+            ..
+        } = render_ctx.as_ref();
+
+        let params_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("BillboardPass::Params"),
+            size: Params::SHADER_SIZE.into(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bgl = bind_group_layouts.get(
+            gpu,
+            "BillboardPass::BindGroupLayout",
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        );
+
+        let layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("BillboardPass::PipelineLayout"),
+                bind_group_layouts: &[scene_uniform.layout(), &bgl],
+                push_constant_ranges: &[],
+            });
+
+        let shader = gpu.shader_from_module(
+            gpu.with_depth_defs(
+                shader_compiler.compilation_unit("./shaders/billboard/billboard.wgsl")?,
+            )
+            .compile(&[])?,
+        );
+
+        let pipeline = |format: wgpu::TextureFormat| {
+            gpu.device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("BillboardPass::Pipeline"),
+                    layout: Some(&layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &[],
+                    },
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        cull_mode: None,
+                        ..Default::default()
+                    },
+                    // Depth is read in the shader to fade sprites instead.
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fs_main",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format,
+                            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    multiview: None,
+                })
+        };
+
+        let rgba8_pipeline = pipeline(gpu.swapchain_format());
+        let rgba16_pipeline = pipeline(wgpu::TextureFormat::Rgba16Float);
+
+        let texture_view = Self::load_texture(gpu)?.create_view(&Default::default());
+        let sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("BillboardPass::Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Ok(Self {
+            billboards: Vec::new(),
+            billboard_buf: Self::create_billboards(gpu, 0),
+            billboard_capacity: 0,
+            render_ctx,
+            params_buf,
+            bgl,
+            rgba8_pipeline,
+            rgba16_pipeline,
+            texture_view,
+            sampler,
+        })
+    }
+
+    fn load_texture(gpu: &Gpu) -> Result<wgpu::Texture> {
+        use wgpu::util::DeviceExt;
+
+        let image = image::open(SPRITE_TEXTURE)
+            .with_context(|| format!("failed to load sprite sheet {SPRITE_TEXTURE}"))?
+            .to_rgba8();
+
+        Ok(gpu.device.create_texture_with_data(
+            &gpu.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("BillboardPass::Texture"),
+                size: wgpu::Extent3d {
+                    width: image.width(),
+                    height: image.height(),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            image.as_raw(),
+        ))
+    }
+
+    // Storage buffers can't be empty, so there's always room for a billboard.
+    fn create_billboards(gpu: &Gpu, capacity: usize) -> wgpu::Buffer {
+        gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("BillboardPass::Billboards"),
+            size: (capacity.max(1) * BILLBOARD_STRIDE) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Replaces billboards drawn from the next frame on.
+    pub fn set_billboards(&mut self, billboards: Vec<Billboard>) {
+        self.billboards = billboards;
+    }
+
+    pub fn render(
+        &mut self,
+        scene_color: &wgpu::TextureView,
+        hdr: bool,
+        settings: &BillboardSettings,
+        resources: &GraphResources,
+    ) -> Result<()> {
+        if self.billboards.is_empty() {
+            return Ok(());
+        }
+
+        let render_ctx = self.render_ctx.clone();
+        let RenderContext {
+            gpu,
+            scene_uniform,
+            profiler,
+            ..
+        } = render_ctx.as_ref();
+
+        if self.billboards.len() > self.billboard_capacity {
+            self.billboard_buf = Self::create_billboards(gpu, self.billboards.len());
+            self.billboard_capacity = self.billboards.len();
+        }
+
+        let mut contents = Vec::with_capacity(self.billboards.len() * BILLBOARD_STRIDE);
+        for billboard in self.billboards.iter() {
+            billboard.copy_to(&mut contents);
+        }
+        gpu.queue
+            .write_buffer(&self.billboard_buf, 0, contents.as_slice());
+
+        let params: Params = [na::Vector4::new(
+            settings.softness,
+            Sprite::ALL.len() as f32,
+            0.0,
+            0.0,
+        )];
+        let size: u64 = Params::SHADER_SIZE.into();
+        let mut contents = UniformBuffer::new(Vec::with_capacity(size as usize));
+        contents.write(&params)?;
+        gpu.queue
+            .write_buffer(&self.params_buf, 0, contents.into_inner().as_slice());
+
+        let depth = gpu.depth_texture_view();
+        let bg = resources.bind_group(
+            gpu,
+            "BillboardPass::BindGroup",
+            &self.bgl,
+            &[
+                Binding::Buffer(&self.params_buf),
+                Binding::Buffer(&self.billboard_buf),
+                Binding::View(&depth),
+                Binding::View(&self.texture_view),
+                Binding::Sampler(&self.sampler),
+            ],
+        )?;
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("BillboardPass::CommandEncoder"),
+            });
+
+        {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("BillboardPass::RenderPass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: scene_color,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: profiler.render_pass_writes("Billboards"),
+                occlusion_query_set: None,
+            });
+
+            if hdr {
+                rpass.set_pipeline(&self.rgba16_pipeline);
+            } else {
+                rpass.set_pipeline(&self.rgba8_pipeline);
+            }
+
+            rpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
+            rpass.set_bind_group(1, &bg, &[]);
+            rpass.draw(0..6, 0..self.billboards.len() as u32);
+        }
+
+        gpu.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
+
+impl RenderPass for BillboardPass<'_> {
+    fn name(&self) -> &'static str {
+        "BillboardPass"
+    }
+
+    fn enabled(&self, ctx: &FrameContext) -> bool {
+        ctx.settings.billboards.enabled && !ctx.settings.deferred_debug_shown()
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        let (scene_color, hdr) = ctx
+            .scene_color
+            .as_ref()
+            .context("billboards are drawn over a lit scene")?;
+        Self::render(
+            self,
+            scene_color,
+            *hdr,
+            &ctx.settings.billboards,
+            &ctx.resources,
+        )
+    }
+}
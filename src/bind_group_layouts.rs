@@ -0,0 +1,85 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    deferred::GeometryPass, gpu::Gpu, light_scene::LightBuffers, scene_uniform::SceneUniform,
+    shadow_pass::DirectionalShadowPass,
+};
+
+/// Bind group layouts of all passes, deduplicated by their entries. Passes asking
+/// for the same entries get the same layout, so bind groups made by one of them
+/// can be bound by the others, and `PipelineCache` shares pipelines between them.
+///
+/// Layouts of bind groups shared by many passes have accessors of their own.
+#[derive(Default)]
+pub struct BindGroupLayouts {
+    layouts: Mutex<HashMap<Vec<wgpu::BindGroupLayoutEntry>, Arc<wgpu::BindGroupLayout>>>,
+}
+
+impl BindGroupLayouts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Layout with `entries`, created when there is none yet. It keeps the label
+    /// it was created with first.
+    pub fn get(
+        &self,
+        gpu: &Gpu,
+        label: &str,
+        entries: &[wgpu::BindGroupLayoutEntry],
+    ) -> Arc<wgpu::BindGroupLayout> {
+        let mut layouts = self.layouts.lock().unwrap();
+        layouts
+            .entry(entries.to_vec())
+            .or_insert_with(|| {
+                Arc::new(
+                    gpu.device
+                        .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                            label: Some(label),
+                            entries,
+                        }),
+                )
+            })
+            .clone()
+    }
+
+    /// Camera, projection, fog and frame values of `SceneUniform`, group 0 of most shaders.
+    pub fn scene(&self, gpu: &Gpu) -> Arc<wgpu::BindGroupLayout> {
+        self.get(
+            gpu,
+            "BindGroupLayouts::Scene",
+            &SceneUniform::layout_entries(),
+        )
+    }
+
+    /// Cascades rendered by `DirectionalShadowPass`, sampled by lighting and fog.
+    pub fn shadow(&self, gpu: &Gpu) -> Arc<wgpu::BindGroupLayout> {
+        self.get(
+            gpu,
+            "BindGroupLayouts::Shadow",
+            &DirectionalShadowPass::out_layout_entries(),
+        )
+    }
+
+    /// Lights with the environment and fog they're seen through, bound by forward
+    /// and deferred lighting alike.
+    pub fn lights(&self, gpu: &Gpu) -> Arc<wgpu::BindGroupLayout> {
+        self.get(
+            gpu,
+            "BindGroupLayouts::Lights",
+            &LightBuffers::layout_entries(),
+        )
+    }
+
+    /// Textures written by `GeometryPass`, read by passes working on the G-Buffer.
+    pub fn gbuffer(&self, gpu: &Gpu) -> Arc<wgpu::BindGroupLayout> {
+        self.get(
+            gpu,
+            "BindGroupLayouts::GBuffer",
+            &GeometryPass::gbuffer_layout_entries(),
+        )
+    }
+}
@@ -0,0 +1,143 @@
+use nalgebra as na;
+
+type FVec3 = na::Vector3<f32>;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: na::Point3<f32>,
+    pub max: na::Point3<f32>,
+}
+
+impl Aabb {
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a FVec3>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = na::Point3::from(*points.next()?);
+
+        Some(points.fold(
+            Self {
+                min: first,
+                max: first,
+            },
+            |aabb, p| Self {
+                min: aabb.min.inf(&na::Point3::from(*p)),
+                max: aabb.max.sup(&na::Point3::from(*p)),
+            },
+        ))
+    }
+
+    pub fn center(&self) -> na::Point3<f32> {
+        na::center(&self.min, &self.max)
+    }
+
+    // Corners are indexed by bits of their x, y and z coordinates, set for the max side.
+    pub fn corners(&self) -> [na::Point3<f32>; 8] {
+        std::array::from_fn(|i| {
+            na::Point3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            )
+        })
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max),
+        }
+    }
+
+    /// Box enclosing this one after the transform, so it only ever grows under rotation.
+    pub fn transformed(&self, mat: &na::Matrix4<f32>) -> Self {
+        let corners = self.corners().map(|p| mat.transform_point(&p).coords);
+        // There are always eight corners.
+        Self::from_points(&corners).unwrap()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BoundingSphere {
+    pub center: na::Point3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Sphere around `center` reaching the farthest of `points`.
+    /// Not the smallest one possible, but it's tight enough when centered on their bounding box.
+    pub fn around<'a>(
+        center: na::Point3<f32>,
+        points: impl IntoIterator<Item = &'a FVec3>,
+    ) -> Self {
+        let radius = points
+            .into_iter()
+            .map(|p| na::distance(&center, &na::Point3::from(*p)))
+            .fold(0.0, f32::max);
+
+        Self { center, radius }
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        let offset = other.center - self.center;
+        let distance = offset.norm();
+
+        if distance + other.radius <= self.radius {
+            return *self;
+        }
+        if distance + self.radius <= other.radius {
+            return *other;
+        }
+
+        let radius = (distance + self.radius + other.radius) * 0.5;
+        Self {
+            center: self.center + offset * ((radius - self.radius) / distance),
+            radius,
+        }
+    }
+
+    /// Non-uniform scale grows the radius by the largest of the scaling factors.
+    pub fn transformed(&self, mat: &na::Matrix4<f32>) -> Self {
+        let linear = mat.fixed_view::<3, 3>(0, 0);
+        let scale = linear
+            .column_iter()
+            .map(|column| column.norm())
+            .fold(0.0, f32::max);
+
+        Self {
+            center: mat.transform_point(&self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+/// Bounding volumes of a mesh or a whole object. Boxes are axis aligned
+/// in the space they are given in.
+#[derive(Clone, Copy, Debug)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+}
+
+impl Bounds {
+    pub fn from_points(points: &[FVec3]) -> Option<Self> {
+        let aabb = Aabb::from_points(points)?;
+
+        Some(Self {
+            aabb,
+            sphere: BoundingSphere::around(aabb.center(), points),
+        })
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            aabb: self.aabb.union(&other.aabb),
+            sphere: self.sphere.union(&other.sphere),
+        }
+    }
+
+    pub fn transformed(&self, mat: &na::Matrix4<f32>) -> Self {
+        Self {
+            aabb: self.aabb.transformed(mat),
+            sphere: self.sphere.transformed(mat),
+        }
+    }
+}
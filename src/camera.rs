@@ -1,74 +1,358 @@
+use std::collections::HashSet;
+
 use crate::gpu::GpuMat4;
 use anyhow::Result;
 use nalgebra as na;
 
+// Distance to the focus point when switching to orbiting from free flight.
+const DEFAULT_ORBIT_DISTANCE: f32 = 10.0;
+const MIN_ORBIT_DISTANCE: f32 = 0.1;
+// Fraction of the distance to the focus point covered by one step of zoom.
+const ZOOM_STEP: f32 = 0.1;
+// Units per second squared. With damping it gives a top speed of 10 units per second.
+const ACCELERATION: f32 = 60.0;
+// Fraction of velocity lost per second, applied continuously.
+const DAMPING: f32 = 6.0;
+// Below this speed the camera is considered stopped, so it doesn't drift forever.
+const REST_SPEED: f32 = 0.01;
+const TURN_RATE: f32 = 90.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CameraMode {
+    FreeFly,
+    /// Rotates around a focus point, which moves along with the camera.
+    Orbit,
+}
+
+/// Free flying or orbiting camera. Its orientation is kept as a quaternion turning the
+/// camera's own axes into world ones: forwards along x, up along y and right along z.
+/// Turns are applied to it directly, so looking straight up or down doesn't degenerate
+/// like Euler angles would. Pitch, yaw and roll are worked out of it relative to the up
+/// axis when needed.
 #[derive(Clone, Copy)]
 pub struct Camera {
     position: na::Point3<f32>,
     delta: na::Vector3<f32>,
-    pitch: f32,
-    yaw: f32,
+    orientation: na::UnitQuaternion<f32>,
+    // World axis yaw turns around and flying moves along.
+    up: na::Unit<na::Vector3<f32>>,
+    mode: CameraMode,
+    focus: na::Point3<f32>,
 }
 
 impl Camera {
+    /// Camera with Y as the up axis and no roll.
     pub fn new(position: na::Point3<f32>, pitch: f32, yaw: f32) -> Self {
+        let up = na::Vector3::y_axis();
+
         Self {
             position,
             delta: na::Vector3::zeros(),
-            pitch,
-            yaw,
+            orientation: Self::from_angles(&up, pitch, yaw, 0.0),
+            up,
+            mode: CameraMode::FreeFly,
+            focus: position,
+        }
+    }
+
+    // Rotation taking Y onto `up`, which angles are measured relative to.
+    fn up_frame(up: &na::Unit<na::Vector3<f32>>) -> na::UnitQuaternion<f32> {
+        na::UnitQuaternion::rotation_between_axis(&na::Vector3::y_axis(), up).unwrap_or_else(|| {
+            na::UnitQuaternion::from_axis_angle(&na::Vector3::x_axis(), std::f32::consts::PI)
+        })
+    }
+
+    // Yaw turns around the up axis, then pitch around the right axis and roll around forwards.
+    fn from_angles(
+        up: &na::Unit<na::Vector3<f32>>,
+        pitch: f32,
+        yaw: f32,
+        roll: f32,
+    ) -> na::UnitQuaternion<f32> {
+        Self::up_frame(up)
+            * na::UnitQuaternion::from_axis_angle(&na::Vector3::y_axis(), -yaw)
+            * na::UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), pitch)
+            * na::UnitQuaternion::from_axis_angle(&na::Vector3::x_axis(), roll)
+    }
+
+    // Pitch, yaw and roll of the orientation, see `from_angles`. Pitch stays within ±90°,
+    // cameras turned upside down get yaw and roll turned half way around instead.
+    fn angles(&self) -> (f32, f32, f32) {
+        let local = Self::up_frame(&self.up).inverse() * self.orientation;
+        let forwards = local * na::Vector3::x();
+        let pitch = forwards.y.clamp(-1.0, 1.0).asin();
+
+        // Looking straight up or down, yaw is told by where the top of the view points.
+        let yaw = if forwards.x.hypot(forwards.z) > 1e-6 {
+            forwards.z.atan2(forwards.x)
+        } else {
+            let top = local * na::Vector3::y() * -forwards.y.signum();
+            top.z.atan2(top.x)
+        };
+
+        let unrolled = Self::from_angles(&na::Vector3::y_axis(), pitch, yaw, 0.0);
+        let top = (unrolled.inverse() * local) * na::Vector3::y();
+        let roll = top.z.atan2(top.y);
+
+        (pitch, yaw, roll)
+    }
+
+    pub fn mode(&self) -> CameraMode {
+        self.mode
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.angles().0
+    }
+
+    pub fn yaw(&self) -> f32 {
+        self.angles().1
+    }
+
+    pub fn roll(&self) -> f32 {
+        self.angles().2
+    }
+
+    pub fn orientation(&self) -> na::UnitQuaternion<f32> {
+        self.orientation
+    }
+
+    pub fn up(&self) -> na::Unit<na::Vector3<f32>> {
+        self.up
+    }
+
+    /// Changes the axis yaw turns around, like Z for scenes modelled with Z up.
+    /// The camera keeps looking in the same direction, with no roll relative to it.
+    pub fn set_up(&mut self, up: na::Vector3<f32>) {
+        let direction = self.direction();
+
+        self.up = na::Unit::new_normalize(up);
+        self.look_along(direction);
+    }
+
+    /// Places the camera at `eye` looking in the direction given by the angles.
+    /// An orbiting camera keeps its distance to the focus point.
+    pub fn set_pose(&mut self, eye: na::Point3<f32>, pitch: f32, yaw: f32, roll: f32) {
+        let distance = self.orbit_distance();
+
+        self.position = eye;
+        self.delta = na::Vector3::zeros();
+        self.orientation = Self::from_angles(&self.up, pitch, yaw, roll);
+        self.focus = eye + self.direction() * distance;
+    }
+
+    /// Orbiting starts around a point in front of the camera.
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        if mode == CameraMode::Orbit && self.mode != CameraMode::Orbit {
+            self.focus = self.eye() + self.direction() * DEFAULT_ORBIT_DISTANCE;
+        }
+
+        self.mode = mode;
+    }
+
+    fn direction(&self) -> na::Vector3<f32> {
+        self.orientation * na::Vector3::x()
+    }
+
+    // Top of the view, tilted along with the camera unlike `up`.
+    fn view_up(&self) -> na::Vector3<f32> {
+        self.orientation * na::Vector3::y()
+    }
+
+    fn right(&self) -> na::Vector3<f32> {
+        self.orientation * na::Vector3::z()
+    }
+
+    pub fn eye(&self) -> na::Point3<f32> {
+        self.position + self.delta
+    }
+
+    // Moves the camera, together with the focus point when orbiting.
+    fn translate(&mut self, v: na::Vector3<f32>) {
+        self.delta += v;
+        if self.mode == CameraMode::Orbit {
+            self.focus += v;
+        }
+    }
+
+    // Places the camera on the orbit given by its orientation, looking at the focus point.
+    fn orbit(&mut self, distance: f32) {
+        self.position = self.focus - self.direction() * distance;
+        self.delta = na::Vector3::zeros();
+    }
+
+    fn orbit_distance(&self) -> f32 {
+        (self.focus - self.eye()).norm()
+    }
+
+    // Turns the camera to `orientation`, around the focus point when orbiting.
+    fn rotate(&mut self, orientation: na::UnitQuaternion<f32>) {
+        let distance = self.orbit_distance();
+        // Renormalized, so errors of many small turns don't pile up.
+        self.orientation = na::UnitQuaternion::new_normalize(*orientation.quaternion());
+        if self.mode == CameraMode::Orbit {
+            self.orbit(distance);
         }
     }
 
     pub fn fly(&mut self, d: f32) {
-        self.delta += na::Vector3::y() * d;
+        self.translate(self.up.into_inner() * d);
     }
 
     pub fn strafe(&mut self, d: f32) {
-        let target = na::Vector3::new(
-            self.pitch.cos() * self.yaw.cos(),
-            self.pitch.sin(),
-            self.pitch.cos() * self.yaw.sin(),
-        );
-
-        let right = target.cross(&na::Vector3::y()).normalize();
-        self.delta += right * d;
+        self.translate(self.right() * d);
     }
 
     pub fn forwards(&mut self, d: f32) {
-        let target = na::Vector3::new(
-            self.pitch.cos() * self.yaw.cos(),
-            self.pitch.sin(),
-            self.pitch.cos() * self.yaw.sin(),
-        )
-        .normalize();
+        self.translate(self.direction() * d);
+    }
+
+    /// Moves towards the focus point when orbiting, forwards otherwise.
+    pub fn zoom(&mut self, d: f32) {
+        match self.mode {
+            CameraMode::FreeFly => self.forwards(d),
+            CameraMode::Orbit => {
+                let distance = self.orbit_distance() * (1.0 - d * ZOOM_STEP);
+                self.orbit(distance.max(MIN_ORBIT_DISTANCE));
+            }
+        }
+    }
+
+    /// Moves the camera in its view plane, by fractions of the distance to the focus point.
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        let scale = match self.mode {
+            CameraMode::FreeFly => DEFAULT_ORBIT_DISTANCE,
+            CameraMode::Orbit => self.orbit_distance(),
+        };
 
-        self.delta += target * d;
+        self.translate((self.view_up() * dy - self.right() * dx) * scale);
     }
 
+    /// Turns around the up axis, so the horizon stays level.
     pub fn tilt_horizontally(&mut self, d: f32) {
-        self.yaw += d;
+        self.rotate(na::UnitQuaternion::from_axis_angle(&self.up, -d) * self.orientation);
     }
 
+    /// Turns around the right axis of the camera. It can go over the poles,
+    /// ending up upside down.
     pub fn tilt_vertically(&mut self, d: f32) {
-        self.pitch += d;
+        self.rotate(
+            self.orientation * na::UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), d),
+        );
     }
 
-    pub fn target(&self) -> na::Point3<f32> {
-        let target = na::Vector3::new(
-            self.pitch.cos() * self.yaw.cos(),
-            self.pitch.sin(),
-            self.pitch.cos() * self.yaw.sin(),
+    /// Rolls around the view direction, tilting the top of the view right for positive `d`.
+    pub fn tilt_sideways(&mut self, d: f32) {
+        self.rotate(
+            self.orientation * na::UnitQuaternion::from_axis_angle(&na::Vector3::x_axis(), d),
         );
+    }
+
+    /// Turns the camera to look along `direction` with no roll, keeping its position,
+    /// or keeping the focus point when orbiting. Looking along the up axis keeps yaw.
+    pub fn look_along(&mut self, direction: na::Vector3<f32>) {
+        let local = Self::up_frame(&self.up).inverse() * direction.normalize();
+        let pitch = local.y.clamp(-1.0, 1.0).asin();
+        let yaw = if local.x != 0.0 || local.z != 0.0 {
+            local.z.atan2(local.x)
+        } else {
+            self.yaw()
+        };
 
-        let position_now = self.position + self.delta;
-        position_now + target
+        self.rotate(Self::from_angles(&self.up, pitch, yaw, 0.0));
     }
 
+    pub fn target(&self) -> na::Point3<f32> {
+        self.eye() + self.direction()
+    }
+
+    /// View matrix, with the top of the view tilted along with the camera, so it stays
+    /// well defined looking along the up axis.
     pub fn look_at_matrix(&self) -> na::Matrix4<f32> {
-        let position_now = self.position + self.delta;
+        na::Matrix4::look_at_rh(&self.eye(), &self.target(), &self.view_up())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, serde::Deserialize)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    Forwards,
+    Backwards,
+    TurnLeft,
+    TurnRight,
+    TurnUp,
+    TurnDown,
+    RollLeft,
+    RollRight,
+}
+
+/// Moves the camera while movement keys are held. Velocity builds up and decays over time
+/// instead of jumping, scaled by frame time so speed doesn't depend on the framerate.
+#[derive(Default)]
+pub struct CameraMotion {
+    held: HashSet<Motion>,
+    // Right, up and forwards, relative to the camera.
+    velocity: na::Vector3<f32>,
+}
+
+impl CameraMotion {
+    pub fn set_held(&mut self, motion: Motion, held: bool) {
+        if held {
+            self.held.insert(motion);
+        } else {
+            self.held.remove(&motion);
+        }
+    }
+
+    /// Key releases are not delivered to unfocused windows.
+    pub fn release_all(&mut self) {
+        self.held.clear();
+    }
+
+    fn axis(&self, positive: Motion, negative: Motion) -> f32 {
+        self.held.contains(&positive) as i32 as f32 - self.held.contains(&negative) as i32 as f32
+    }
+
+    // Yaw, pitch and roll.
+    fn turn(&self) -> na::Vector3<f32> {
+        na::Vector3::new(
+            self.axis(Motion::TurnRight, Motion::TurnLeft),
+            self.axis(Motion::TurnUp, Motion::TurnDown),
+            self.axis(Motion::RollRight, Motion::RollLeft),
+        )
+    }
+
+    /// Integrates velocity over `dt` seconds. Returns whether the camera has to be moved.
+    pub fn advance(&mut self, dt: f32) -> bool {
+        let input = na::Vector3::new(
+            self.axis(Motion::Right, Motion::Left),
+            self.axis(Motion::Up, Motion::Down),
+            self.axis(Motion::Forwards, Motion::Backwards),
+        );
+
+        self.velocity += input * ACCELERATION * dt;
+        self.velocity *= (-DAMPING * dt).exp();
+        if input == na::Vector3::zeros() && self.velocity.norm() < REST_SPEED {
+            self.velocity = na::Vector3::zeros();
+        }
+
+        self.velocity != na::Vector3::zeros() || self.turn() != na::Vector3::zeros()
+    }
+
+    /// Moves `camera` by velocity integrated in the last `advance`.
+    pub fn apply(&self, camera: &mut Camera, dt: f32) {
+        let step = self.velocity * dt;
+        camera.strafe(step.x);
+        camera.fly(step.y);
+        camera.forwards(step.z);
 
-        na::Matrix4::look_at_rh(&position_now, &self.target(), &na::Vector3::y())
+        let turn = self.turn() * TURN_RATE.to_radians() * dt;
+        camera.tilt_horizontally(turn.x);
+        camera.tilt_vertically(turn.y);
+        camera.tilt_sideways(turn.z);
     }
 }
 
@@ -91,6 +375,14 @@ impl GpuCamera {
         self.camera.look_at_matrix()
     }
 
+    pub fn mode(&self) -> CameraMode {
+        self.camera.mode()
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
     pub fn buffer(&self) -> &wgpu::Buffer {
         self.gpu_mat.buffer()
     }
@@ -0,0 +1,417 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use encase::ShaderSize;
+use nalgebra as na;
+
+use crate::{
+    camera::{Camera, GpuCamera},
+    material::{MaterialId, MaterialTexture, PhongVariant},
+    pipeline_cache::RenderPipelineDesc,
+    projection::{GpuProjection, Perspective},
+    render_context::RenderContext,
+    render_pass::{FrameContext, RenderPass},
+    scene::Instance,
+    scene_uniform::SceneUniform,
+    shader_permutation::Variants,
+    texture_pool::{TargetSize, TransientDesc},
+    uniform_ring::UniformRing,
+};
+
+// Fields of `Feed` in the shader.
+type Params = [na::Vector4<f32>; 4];
+
+/// Camera of `CameraFeedPass`, handed out when it's added.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CameraFeedId(usize);
+
+/// Plane a feed reflects the scene through, making it a mirror.
+#[derive(Clone, Copy, Debug)]
+pub struct Mirror {
+    /// Middle of the mirror, the camera of the feed looks at it.
+    pub center: na::Point3<f32>,
+    /// Side of the mirror the scene is reflected from.
+    pub normal: na::Unit<na::Vector3<f32>>,
+}
+
+impl Mirror {
+    // Plane as `(normal, distance)`, all zeros leave the scene as is.
+    fn plane(mirror: Option<&Self>) -> na::Vector4<f32> {
+        match mirror {
+            Some(mirror) => mirror
+                .normal
+                .push(-mirror.normal.dot(&mirror.center.coords)),
+            None => na::Vector4::zeros(),
+        }
+    }
+}
+
+struct CameraFeed {
+    camera: GpuCamera,
+    projection: GpuProjection,
+    scene_uniform: SceneUniform,
+    texture: MaterialTexture,
+    mirror: Option<Mirror>,
+}
+
+/// Renders the scene from secondary cameras into textures of materials, so surfaces
+/// using them show what the cameras see, like screens of security cameras. It runs
+/// before the main passes, which sample what it rendered in the same frame.
+///
+/// Every camera has a `SceneUniform` of its own, bound in place of the main one, and
+/// renders into a target of the texture pool copied to its layer of the material atlas.
+/// Like reflections in water, the scene is lit only by the sun.
+///
+/// Mirrors follow the main camera, looking from it at their middle, and reflect the scene
+/// through their plane like `WaterPass` does, with winding of triangles flipped.
+/// Cameras are set up by scene scripts, see `SceneScriptWatcher::add_camera_feeds`.
+pub struct CameraFeedPass<'window> {
+    render_ctx: Arc<RenderContext<'window>>,
+    feeds: Vec<CameraFeed>,
+    params_bg: wgpu::BindGroup,
+    pipelines: Variants<PhongVariant, Arc<wgpu::RenderPipeline>>,
+    mirror_pipelines: Variants<PhongVariant, Arc<wgpu::RenderPipeline>>,
+}
+
+impl<'window> CameraFeedPass<'window> {
+    pub fn new(render_ctx: Arc<RenderContext<'window>>) -> Result<Self> {
+        let RenderContext {
+            gpu,
+            bind_group_layouts,
+            shader_compiler,
+            scene_uniform,
+            material_atlas,
+            pipeline_cache,
+            uniform_ring,
+            ..
+        } = render_ctx.as_ref();
+
+        let params_size: u64 = Params::SHADER_SIZE.into();
+        let params_bgl = bind_group_layouts.get(
+            gpu,
+            "CameraFeedPass::ParamsBindGroupLayout",
+            &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: UniformRing::binding_type(params_size),
+                count: None,
+            }],
+        );
+
+        let params_bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("CameraFeedPass::ParamsBindGroup"),
+            layout: &params_bgl,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_ring.binding(params_size),
+            }],
+        });
+
+        let module = shader_compiler
+            .compilation_unit("./shaders/camera_feed.wgsl")?
+            .with_integer_def("MATERIAL_GROUP", 2);
+
+        // Feeds have scene uniforms of their own, sharing the layout of the main one.
+        let atlas = material_atlas.read().unwrap();
+        let bind_group_layouts = [scene_uniform.layout(), &params_bgl, atlas.layout()];
+        // Mirrors reflect the scene, which turns its triangles the other way around.
+        let create_pipelines = |front_face| {
+            Variants::try_new(|variant @ PhongVariant(ty)| {
+                pipeline_cache.render_pipeline(
+                    gpu,
+                    &RenderPipelineDesc {
+                        label: Some("CameraFeedPass::Pipeline"),
+                        shader: &module.variant(variant),
+                        variant_defs: &[],
+                        constants: &[],
+                        bind_group_layouts: &bind_group_layouts,
+                        push_constant_ranges: &[],
+                        vertex_entry: "vs_main",
+                        vertex_buffers: &Instance::draw_buffer_layouts(ty),
+                        fragment_entry: Some("fs_main"),
+                        targets: &[Some(MaterialTexture::RENDER_TARGET_FORMAT.into())],
+                        primitive: wgpu::PrimitiveState {
+                            topology: wgpu::PrimitiveTopology::TriangleList,
+                            front_face,
+                            cull_mode: Some(wgpu::Face::Back),
+                            ..Default::default()
+                        },
+                        depth_stencil: Some(wgpu::DepthStencilState {
+                            format: wgpu::TextureFormat::Depth32Float,
+                            depth_write_enabled: true,
+                            depth_compare: gpu.depth_compare(wgpu::CompareFunction::Less),
+                            stencil: Default::default(),
+                            bias: Default::default(),
+                        }),
+                        multisample: wgpu::MultisampleState::default(),
+                    },
+                )
+            })
+        };
+        let pipelines = create_pipelines(wgpu::FrontFace::Ccw)?;
+        let mirror_pipelines = create_pipelines(wgpu::FrontFace::Cw)?;
+        drop(atlas);
+
+        Ok(Self {
+            render_ctx,
+            feeds: Vec::new(),
+            params_bg,
+            pipelines,
+            mirror_pipelines,
+        })
+    }
+
+    /// Adds a camera rendering into a new material of the atlas, at least `size` texels
+    /// wide, see `MaterialAtlas::add_phong_render_target`. Layers are square, so
+    /// perspectives with an aspect ratio of 1 keep proportions of the scene.
+    pub fn add_feed(
+        &mut self,
+        camera: Camera,
+        perspective: Perspective,
+        size: u32,
+    ) -> Result<(CameraFeedId, MaterialId)> {
+        self.add(camera, perspective, size, None)
+    }
+
+    /// Adds a mirror, like `add_feed` does with cameras. Its camera is moved every frame
+    /// to the main one, so what it shows depends on where it's seen from.
+    pub fn add_mirror(
+        &mut self,
+        mirror: Mirror,
+        perspective: Perspective,
+        size: u32,
+    ) -> Result<(CameraFeedId, MaterialId)> {
+        let camera = Camera::new(mirror.center + mirror.normal.into_inner(), 0.0, 0.0);
+        self.add(camera, perspective, size, Some(mirror))
+    }
+
+    fn add(
+        &mut self,
+        camera: Camera,
+        perspective: Perspective,
+        size: u32,
+        mirror: Option<Mirror>,
+    ) -> Result<(CameraFeedId, MaterialId)> {
+        let RenderContext {
+            gpu,
+            bind_group_layouts,
+            material_atlas,
+            ..
+        } = self.render_ctx.as_ref();
+
+        let (material_id, texture) = material_atlas
+            .write()
+            .unwrap()
+            .add_phong_render_target(gpu, size)?;
+
+        let camera = GpuCamera::new(camera, &gpu.device)?;
+        let projection = GpuProjection::new(perspective, gpu)?;
+        let scene_uniform = SceneUniform::new(gpu, bind_group_layouts, &camera, &projection);
+
+        self.feeds.push(CameraFeed {
+            camera,
+            projection,
+            scene_uniform,
+            texture,
+            mirror,
+        });
+
+        Ok((CameraFeedId(self.feeds.len() - 1), material_id))
+    }
+
+    fn feed(feeds: &mut [CameraFeed], feed: CameraFeedId) -> Result<&mut CameraFeed> {
+        feeds
+            .get_mut(feed.0)
+            .ok_or_else(|| anyhow!("no camera feed {:?}", feed))
+    }
+
+    pub fn update_camera<F>(&mut self, feed: CameraFeedId, updater: F) -> Result<()>
+    where
+        F: Fn(&mut Camera),
+    {
+        Self::feed(&mut self.feeds, feed)?
+            .camera
+            .update(&self.render_ctx.gpu.queue, updater)
+    }
+
+    pub fn update_projection<F>(&mut self, feed: CameraFeedId, updater: F) -> Result<()>
+    where
+        F: Fn(&mut Perspective),
+    {
+        Self::feed(&mut self.feeds, feed)?
+            .projection
+            .update(&self.render_ctx.gpu.queue, updater)
+    }
+
+    /// Removes all feeds. Their materials belong to the atlas they were added to,
+    /// so they have to go when the scene, along with its atlas, is replaced.
+    pub fn clear(&mut self) {
+        self.feeds.clear();
+    }
+}
+
+impl RenderPass for CameraFeedPass<'_> {
+    fn name(&self) -> &'static str {
+        "CameraFeedPass"
+    }
+
+    fn enabled(&self, _ctx: &FrameContext) -> bool {
+        !self.feeds.is_empty()
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        let eye = ctx.camera.camera().eye();
+        for feed in &mut self.feeds {
+            if let Some(mirror) = feed.mirror {
+                feed.camera.update(&self.render_ctx.gpu.queue, |camera| {
+                    camera.set_pose(eye, 0.0, 0.0, 0.0);
+                    camera.look_along(mirror.center - eye);
+                })?;
+            }
+        }
+
+        let RenderContext {
+            gpu,
+            gpu_scene,
+            material_atlas,
+            uniform_ring,
+            texture_pool,
+            profiler,
+            ..
+        } = self.render_ctx.as_ref();
+
+        let sun = &ctx.sun;
+
+        let [r, g, b] = ctx.settings.background.clear_color;
+        let clear_color = wgpu::Color {
+            r: r as f64,
+            g: g as f64,
+            b: b as f64,
+            a: 1.0,
+        };
+
+        let scene = gpu_scene.read().unwrap();
+        let atlas = material_atlas.read().unwrap();
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("CameraFeedPass::CommandEncoder"),
+            });
+
+        // Targets go back to the pool only once the copies out of them are submitted.
+        let mut targets = Vec::with_capacity(self.feeds.len());
+        for feed in &self.feeds {
+            let params: Params = [
+                sun.direction,
+                sun.ambient,
+                sun.diffuse,
+                Mirror::plane(feed.mirror.as_ref()),
+            ];
+            let params_offset = uniform_ring.write_uniform(gpu, &params)?;
+            let pipelines = match feed.mirror {
+                Some(_) => &self.mirror_pipelines,
+                None => &self.pipelines,
+            };
+
+            let size = TargetSize::Fixed(wgpu::Extent3d {
+                width: feed.texture.size(),
+                height: feed.texture.size(),
+                depth_or_array_layers: 1,
+            });
+            let color = texture_pool.acquire(
+                gpu,
+                "CameraFeedPass::Color",
+                &TransientDesc {
+                    size,
+                    format: MaterialTexture::RENDER_TARGET_FORMAT,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                },
+            );
+            let depth = texture_pool.acquire(
+                gpu,
+                "CameraFeedPass::Depth",
+                &TransientDesc {
+                    size,
+                    format: wgpu::TextureFormat::Depth32Float,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                },
+            );
+
+            {
+                let color_view = color.create_view(&Default::default());
+                let depth_view = depth.create_view(&Default::default());
+                let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("CameraFeedPass::RenderPass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &color_view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(clear_color),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &depth_view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(gpu.far_depth()),
+                            store: wgpu::StoreOp::Discard,
+                        }),
+                        stencil_ops: None,
+                    }),
+                    timestamp_writes: profiler.render_pass_writes("Camera Feed"),
+                    occlusion_query_set: None,
+                });
+
+                rpass.set_bind_group(0, feed.scene_uniform.bind_group(), &[]);
+                rpass.set_bind_group(1, &self.params_bg, &[params_offset]);
+                rpass.set_bind_group(2, atlas.bind_group(), &[]);
+
+                for batch in scene.draw_batches() {
+                    let first = &batch[0];
+                    rpass.set_pipeline(&pipelines[PhongVariant(first.vertex_array_type)]);
+
+                    rpass.set_vertex_buffer(
+                        0,
+                        scene
+                            .vertex_buffer_by_type(first.vertex_array_type)
+                            .slice(..),
+                    );
+                    rpass.set_vertex_buffer(
+                        1,
+                        scene.instance_buffer_by_type(first.instance_type).slice(..),
+                    );
+
+                    if first.indexed {
+                        rpass.set_index_buffer(
+                            scene.index_buffer().slice(..),
+                            wgpu::IndexFormat::Uint32,
+                        );
+                    }
+
+                    for draw_call in batch {
+                        if draw_call.indexed {
+                            rpass.draw_indexed_indirect(
+                                scene.indexed_draw_buffer(),
+                                draw_call.draw_buffer_offset,
+                            );
+                        } else {
+                            rpass.draw_indirect(
+                                scene.non_indexed_draw_buffer(),
+                                draw_call.draw_buffer_offset,
+                            );
+                        }
+                    }
+                }
+            }
+
+            atlas.copy_to_texture(&mut encoder, &color, feed.texture);
+            targets.push((color, depth));
+        }
+
+        gpu.queue.submit(Some(encoder.finish()));
+        drop(targets);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,236 @@
+use std::f32::consts::{PI, TAU};
+
+use nalgebra as na;
+
+use crate::camera::Camera;
+
+#[derive(Clone, Copy)]
+pub struct Keyframe {
+    pub position: na::Point3<f32>,
+    pub pitch: f32,
+    pub yaw: f32,
+    pub roll: f32,
+}
+
+impl Keyframe {
+    pub fn from_camera(camera: &Camera) -> Self {
+        Self {
+            position: camera.eye(),
+            pitch: camera.pitch(),
+            yaw: camera.yaw(),
+            roll: camera.roll(),
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.set_pose(self.position, self.pitch, self.yaw, self.roll);
+    }
+
+    fn as_vector(&self) -> na::Vector6<f32> {
+        na::Vector6::new(
+            self.position.x,
+            self.position.y,
+            self.position.z,
+            self.pitch,
+            self.yaw,
+            self.roll,
+        )
+    }
+}
+
+/// Frames rendered during a playback, for comparing performance of the same flythrough.
+pub struct PlaybackSummary {
+    pub frames: u32,
+    pub seconds: f32,
+}
+
+struct Playback {
+    time: f32,
+    frames: u32,
+}
+
+/// Flythrough going through recorded keyframes on a Catmull-Rom spline,
+/// spending the same time between every pair of them.
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+    segment_seconds: f32,
+    looping: bool,
+    playback: Option<Playback>,
+    finished: Option<PlaybackSummary>,
+}
+
+impl Default for CameraPath {
+    fn default() -> Self {
+        Self {
+            keyframes: Vec::new(),
+            segment_seconds: 2.0,
+            looping: false,
+            playback: None,
+            finished: None,
+        }
+    }
+}
+
+impl CameraPath {
+    pub fn is_playing(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    fn duration(&self) -> f32 {
+        let segments = if self.looping {
+            self.keyframes.len()
+        } else {
+            self.keyframes.len().saturating_sub(1)
+        };
+
+        segments as f32 * self.segment_seconds
+    }
+
+    // Angle of `angle` turned the short way around from `previous`.
+    fn unwrap_angle(previous: f32, angle: f32) -> f32 {
+        previous + (angle - previous + PI).rem_euclid(TAU) - PI
+    }
+
+    fn catmull_rom(
+        p0: na::Vector6<f32>,
+        p1: na::Vector6<f32>,
+        p2: na::Vector6<f32>,
+        p3: na::Vector6<f32>,
+        t: f32,
+    ) -> na::Vector6<f32> {
+        let t2 = t * t;
+        let t3 = t2 * t;
+
+        (p1 * 2.0
+            + (p2 - p0) * t
+            + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+            + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+            * 0.5
+    }
+
+    /// Pose of the path `time` seconds into the playback.
+    pub fn sample(&self, time: f32) -> Option<Keyframe> {
+        let count = self.keyframes.len() as isize;
+        if count < 2 {
+            return None;
+        }
+
+        let last_segment = if self.looping { count - 1 } else { count - 2 };
+        let position = time.max(0.0) / self.segment_seconds;
+        let segment = (position.floor() as isize).min(last_segment);
+        let t = (position - segment as f32).min(1.0);
+
+        // Open paths are extended at their ends by repeating the first and the last keyframe.
+        let keyframe = |i: isize| {
+            let i = if self.looping {
+                i.rem_euclid(count)
+            } else {
+                i.clamp(0, count - 1)
+            };
+
+            self.keyframes[i as usize].as_vector()
+        };
+
+        let mut points = [-1, 0, 1, 2].map(|offset| keyframe(segment + offset));
+        for i in 1..points.len() {
+            // Yaw and roll.
+            for angle in [4, 5] {
+                points[i][angle] = Self::unwrap_angle(points[i - 1][angle], points[i][angle]);
+            }
+        }
+
+        let [p0, p1, p2, p3] = points;
+        let v = Self::catmull_rom(p0, p1, p2, p3, t);
+
+        Some(Keyframe {
+            position: na::Point3::new(v[0], v[1], v[2]),
+            pitch: v[3],
+            yaw: v[4],
+            roll: v[5],
+        })
+    }
+
+    /// Advances the playback by `dt` seconds, returning the pose for the current frame.
+    pub fn advance(&mut self, dt: f32) -> Option<Keyframe> {
+        let duration = self.duration();
+        let playback = self.playback.as_mut()?;
+        playback.time += dt;
+        playback.frames += 1;
+
+        let time = playback.time;
+        if time >= duration {
+            if self.looping {
+                playback.time = time % duration;
+            } else {
+                self.finished = Some(PlaybackSummary {
+                    frames: playback.frames,
+                    seconds: time,
+                });
+                self.playback = None;
+            }
+        }
+
+        self.sample(time.min(duration))
+    }
+
+    /// Summary of the last playback which ran to the end, once.
+    pub fn take_finished(&mut self) -> Option<PlaybackSummary> {
+        self.finished.take()
+    }
+
+    pub fn render(&mut self, ctx: &egui::Context, camera: &Camera) {
+        egui::Window::new("Camera Path")
+            .default_open(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!("Keyframes: {}", self.keyframes.len()));
+
+                ui.add_enabled_ui(!self.is_playing(), |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button("Add Keyframe").clicked() {
+                            self.keyframes.push(Keyframe::from_camera(camera));
+                        }
+
+                        if ui.button("Remove Last").clicked() {
+                            self.keyframes.pop();
+                        }
+
+                        if ui.button("Clear").clicked() {
+                            self.keyframes.clear();
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::DragValue::new(&mut self.segment_seconds)
+                                .speed(0.05)
+                                .clamp_range(0.1..=60.0)
+                                .suffix(" s"),
+                        );
+                        ui.label("Between Keyframes");
+                    });
+
+                    ui.checkbox(&mut self.looping, "Loop");
+                });
+
+                ui.separator();
+
+                if self.is_playing() {
+                    if ui.button("Stop").clicked() {
+                        self.playback = None;
+                    }
+                } else {
+                    let can_play = self.keyframes.len() >= 2;
+                    if ui
+                        .add_enabled(can_play, egui::Button::new("Play"))
+                        .clicked()
+                    {
+                        self.playback = Some(Playback {
+                            time: 0.0,
+                            frames: 0,
+                        });
+                    }
+                }
+            });
+    }
+}
@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use nalgebra as na;
+
+use crate::{
+    render_context::RenderContext,
+    render_pass::{FrameContext, RenderPass},
+    shadow_pass::SPLIT_COUNT,
+};
+
+// Matches tints of the cascade debug mode in shaders.
+const CASCADE_COLORS: [[f32; 3]; SPLIT_COUNT] = [[0.9, 0.2, 0.2], [0.2, 0.8, 0.2], [0.2, 0.4, 0.9]];
+
+// Every corner of the light clip space box is indexed by bits of its x, y and z coordinates.
+// Edges connect corners differing in a single bit.
+const BOX_EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (2, 3),
+    (4, 5),
+    (6, 7),
+    (0, 2),
+    (1, 3),
+    (4, 6),
+    (5, 7),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+const VERTEX_COUNT: usize = SPLIT_COUNT * BOX_EDGES.len() * 2;
+
+/// Outlines of the volumes rendered into every shadow cascade, drawn over the frame.
+pub struct CascadeBoundsPass<'window> {
+    render_ctx: Arc<RenderContext<'window>>,
+    pipeline: wgpu::RenderPipeline,
+    vbuf: wgpu::Buffer,
+}
+
+impl<'window> CascadeBoundsPass<'window> {
+    pub fn new(render_ctx: Arc<RenderContext<'window>>) -> Result<Self> {
+        let RenderContext {
+            gpu,
+            shader_compiler,
+            scene_uniform,
+            ..
+        } = render_ctx.as_ref();
+
+        let vbuf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("CascadeBoundsPass::VertexBuffer"),
+            size: (VERTEX_COUNT * std::mem::size_of::<[f32; 6]>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let shader = gpu.shader_from_module(
+            shader_compiler
+                .compilation_unit("./shaders/cascade_bounds.wgsl")?
+                .compile(&[])?,
+        );
+
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("CascadeBoundsPass::PipelineLayout"),
+                bind_group_layouts: &[scene_uniform.layout()],
+                push_constant_ranges: &[],
+            });
+
+        let pipeline = gpu
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("CascadeBoundsPass::Pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<[f32; 6]>() as u64,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3],
+                    }],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: gpu.swapchain_format(),
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                depth_stencil: None,
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    ..Default::default()
+                },
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            });
+
+        Ok(Self {
+            render_ctx,
+            pipeline,
+            vbuf,
+        })
+    }
+
+    // World space corners of the box which a light view projection maps to its clip space.
+    fn box_corners(light_mat: &na::Matrix4<f32>) -> Option<[na::Point3<f32>; 8]> {
+        let inverse = light_mat.try_inverse()?;
+
+        Some(std::array::from_fn(|i| {
+            let x = if i & 1 == 0 { -1.0 } else { 1.0 };
+            let y = if i & 2 == 0 { -1.0 } else { 1.0 };
+            let z = if i & 4 == 0 { 0.0 } else { 1.0 };
+
+            inverse.transform_point(&na::Point3::new(x, y, z))
+        }))
+    }
+
+    pub fn render(
+        &self,
+        frame: &wgpu::SurfaceTexture,
+        light_mats: &[na::Matrix4<f32>; SPLIT_COUNT],
+    ) {
+        let RenderContext {
+            gpu, scene_uniform, ..
+        } = self.render_ctx.as_ref();
+
+        let mut vertices: Vec<[f32; 6]> = Vec::with_capacity(VERTEX_COUNT);
+        for (light_mat, [r, g, b]) in light_mats.iter().zip(CASCADE_COLORS) {
+            let Some(corners) = Self::box_corners(light_mat) else {
+                continue;
+            };
+
+            for (from, to) in BOX_EDGES {
+                for p in [corners[from], corners[to]] {
+                    vertices.push([p.x, p.y, p.z, r, g, b]);
+                }
+            }
+        }
+
+        gpu.queue
+            .write_buffer(&self.vbuf, 0, bytemuck::cast_slice(&vertices));
+
+        let view = frame.texture.create_view(&Default::default());
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("CascadeBoundsPass::CommandEncoder"),
+            });
+
+        {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("CascadeBoundsPass::RenderPass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            rpass.set_pipeline(&self.pipeline);
+            rpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
+            rpass.set_vertex_buffer(0, self.vbuf.slice(..));
+            rpass.draw(0..vertices.len() as u32, 0..1);
+        }
+
+        gpu.queue.submit(Some(encoder.finish()));
+    }
+}
+
+impl RenderPass for CascadeBoundsPass<'_> {
+    fn name(&self) -> &'static str {
+        "CascadeBoundsPass"
+    }
+
+    fn enabled(&self, ctx: &FrameContext) -> bool {
+        ctx.settings.shadows.debug_cascade_bounds
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        Self::render(self, &ctx.frame, &ctx.light_matrices);
+        Ok(())
+    }
+}
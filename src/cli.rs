@@ -0,0 +1,139 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use gpu_basics::{
+    gpu::{AdapterOptions, AdapterPreference, Backend, PresentMode},
+    settings::{AppSettings, PipelineType},
+};
+
+/// Renders test scenes with forward or deferred shading.
+#[derive(Parser)]
+pub struct Args {
+    /// Built-in scene (teapot, blinn-phong) or path to a scene script.
+    /// Defaults to the teapot script when present, the built-in teapot otherwise.
+    #[arg(long)]
+    scene: Option<String>,
+    /// Overrides the pipeline from saved settings for this run.
+    #[arg(long, value_enum)]
+    pub pipeline: Option<PipelineType>,
+    #[arg(long, default_value_t = 1366)]
+    pub width: u32,
+    #[arg(long, default_value_t = 768)]
+    pub height: u32,
+    /// Overrides the present mode from saved settings for this run. Immediate presents frames
+    /// as soon as they're ready, even if it tears.
+    #[arg(long, value_enum)]
+    pub present_mode: Option<PresentMode>,
+    /// Part of the name of the GPU to render with, case insensitive, or the preferred one
+    /// when none matches. Overrides the adapter from saved settings for this run,
+    /// like the two options below.
+    #[arg(long)]
+    pub adapter: Option<String>,
+    /// Kind of GPU to pick when no adapter is named.
+    #[arg(long, value_enum)]
+    pub adapter_preference: Option<AdapterPreference>,
+    #[arg(long, value_enum)]
+    pub backend: Option<Backend>,
+    /// Renders with depth going from 1 at the near plane to 0 at the far one,
+    /// even when saved settings don't.
+    #[arg(long)]
+    pub reverse_z: bool,
+    /// Equirectangular panorama, like an `.hdr` file, replacing the skybox and
+    /// the environment reflected by lighting passes.
+    #[arg(long)]
+    pub environment: Option<PathBuf>,
+    /// Composes every shader from its sources, without reading or writing
+    /// the shader cache.
+    #[arg(long)]
+    pub no_shader_cache: bool,
+}
+
+pub enum SceneChoice {
+    Teapot,
+    BlinnPhong,
+    Script(PathBuf),
+}
+
+/// Saved settings replaced by command line options. They're put back before settings
+/// are saved, so options only last for the run they're given to.
+pub struct Overrides {
+    pipeline_type: Option<Override<PipelineType>>,
+    present_mode: Option<Override<PresentMode>>,
+    adapter: Option<Override<AdapterOptions>>,
+    reverse_z: Option<Override<bool>>,
+}
+
+impl Overrides {
+    /// Puts back saved values of overridden settings, unless they were changed while running.
+    pub fn restore(&self, settings: &mut AppSettings) {
+        fn restore<T: Clone + PartialEq>(overridden: &Option<Override<T>>, setting: &mut T) {
+            if let Some(overridden) = overridden {
+                if *setting == overridden.value {
+                    *setting = overridden.saved.clone();
+                }
+            }
+        }
+
+        restore(&self.pipeline_type, &mut settings.pipeline_type);
+        restore(&self.present_mode, &mut settings.present_mode);
+        restore(&self.adapter, &mut settings.adapter);
+        restore(&self.reverse_z, &mut settings.reverse_z);
+    }
+}
+
+struct Override<T> {
+    saved: T,
+    value: T,
+}
+
+impl<T: Clone> Override<T> {
+    fn new(setting: &mut T, value: T) -> Self {
+        Self {
+            saved: std::mem::replace(setting, value.clone()),
+            value,
+        }
+    }
+}
+
+impl Args {
+    /// Applies options given on the command line to `settings`.
+    pub fn override_settings(&self, settings: &mut AppSettings) -> Overrides {
+        let mut adapter = settings.adapter.clone();
+        if let Some(name) = &self.adapter {
+            adapter.name = Some(name.clone());
+        }
+        if let Some(preference) = self.adapter_preference {
+            adapter.preference = preference;
+        }
+        if let Some(backend) = self.backend {
+            adapter.backend = Some(backend);
+        }
+
+        Overrides {
+            pipeline_type: self
+                .pipeline
+                .map(|pipeline| Override::new(&mut settings.pipeline_type, pipeline)),
+            present_mode: self
+                .present_mode
+                .map(|present_mode| Override::new(&mut settings.present_mode, present_mode)),
+            adapter: (adapter != settings.adapter)
+                .then(|| Override::new(&mut settings.adapter, adapter)),
+            reverse_z: self
+                .reverse_z
+                .then(|| Override::new(&mut settings.reverse_z, true)),
+        }
+    }
+
+    pub fn scene(&self, default_script: &str) -> SceneChoice {
+        match self.scene.as_deref() {
+            Some("teapot") => SceneChoice::Teapot,
+            Some("blinn-phong") => SceneChoice::BlinnPhong,
+            Some(path) => SceneChoice::Script(path.into()),
+            None if std::path::Path::new(default_script).exists() => {
+                SceneChoice::Script(default_script.into())
+            }
+            None => SceneChoice::Teapot,
+        }
+    }
+}
@@ -1,38 +1,84 @@
+use std::sync::Arc;
+
 use anyhow::Result;
+use encase::{ShaderSize, UniformBuffer};
+use nalgebra as na;
+
+use super::{dispatch, Kernel};
+use crate::{
+    bind_group_layouts::BindGroupLayouts,
+    gpu::Gpu,
+    shader_compiler::ShaderCompiler,
+    texture_pool::{TargetSize, TexturePool, TransientDesc},
+};
+
+// Weights of offsets from 0 to `GaussianKernel::MAX_RADIUS`, four in a vector.
+const MAX_WEIGHTS_VEC4: usize = (GaussianKernel::MAX_RADIUS as usize + 1).div_ceil(4);
+
+// Fields of `Filter` in the shader: the radius, then the weights.
+type Filter = [na::Vector4<f32>; 1 + MAX_WEIGHTS_VEC4];
+
+/// Normal distribution cut off at `radius` texels from the center.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GaussianKernel {
+    pub radius: u32,
+    pub sigma: f32,
+}
+
+impl GaussianKernel {
+    pub const MAX_RADIUS: u32 = 31;
+
+    pub fn new(radius: u32, sigma: f32) -> Self {
+        Self { radius, sigma }
+    }
+
+    /// Weights of offsets from the center, summing up to 1 over both of its sides.
+    pub fn weights(&self) -> Vec<f32> {
+        let sigma = self.sigma.max(0.01);
+        let weights: Vec<f32> = (0..=self.radius.min(Self::MAX_RADIUS))
+            .map(|offset| (-((offset * offset) as f32) / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let total = weights[0] + 2.0 * weights[1..].iter().sum::<f32>();
+
+        weights.into_iter().map(|w| w / total).collect()
+    }
 
-use crate::{gpu::Gpu, shader_compiler::ShaderCompiler};
+    fn filter(&self) -> Filter {
+        let weights = self.weights();
+        let mut filter = [na::Vector4::zeros(); 1 + MAX_WEIGHTS_VEC4];
+        filter[0].x = (weights.len() - 1) as f32;
+        for (i, weight) in weights.into_iter().enumerate() {
+            filter[1 + i / 4][i % 4] = weight;
+        }
+
+        filter
+    }
+}
 
+/// Separable Gaussian blur, ping-ponging between a horizontal and a vertical pass.
+/// Horizontal passes write to a transient texture of `TexturePool`, vertical ones
+/// to the output, which is kept.
 pub struct BlurPass {
     compute_pipeline: wgpu::ComputePipeline,
-    blur_tex_x: wgpu::Texture,
-    bg_x: wgpu::BindGroup,
-    bg_y: wgpu::BindGroup,
+    bgl: Arc<wgpu::BindGroupLayout>,
+    blur_tex_y: wgpu::Texture,
     flip_x: wgpu::Buffer,
-    sampler: wgpu::Sampler,
-    filter_size_buf: wgpu::Buffer,
+    flip_y: wgpu::Buffer,
+    filter_buf: wgpu::Buffer,
 }
 
 impl BlurPass {
+    // Rows of 128 texels, 4 of them per workgroup. Wide enough for both sides of the
+    // largest kernel to fit next to texels it's written to.
+    const KERNEL: Kernel = Kernel::new([32, 1, 1]).with_invocation_extent([4, 4, 1]);
+
     pub fn new(
         gpu: &Gpu,
         shader_compiler: &ShaderCompiler,
+        bind_group_layouts: &BindGroupLayouts,
         input_size: wgpu::Extent3d,
         input_format: wgpu::TextureFormat,
     ) -> Result<Self> {
-        let blur_tex_x = gpu.device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("BlurPass::TextureX"),
-            size: input_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: input_format,
-            usage: wgpu::TextureUsages::STORAGE_BINDING
-                | wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::COPY_DST
-                | wgpu::TextureUsages::COPY_SRC,
-            view_formats: &[],
-        });
-
         let blur_tex_y = gpu.device.create_texture(&wgpu::TextureDescriptor {
             label: Some("BlurPass::TextureY"),
             size: input_size,
@@ -63,9 +109,9 @@ impl BlurPass {
                 usage: wgpu::BufferUsages::UNIFORM,
             });
 
-        let filter_size_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("BlurPass::FilterSizeBuffer"),
-            size: std::mem::size_of::<u32>() as u64,
+        let filter_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("BlurPass::FilterBuffer"),
+            size: Filter::SHADER_SIZE.into(),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
@@ -79,134 +125,58 @@ impl BlurPass {
         };
 
         let shader = gpu.shader_from_module(
-            shader_compiler
-                .compilation_unit("./shaders/compute/blur.wgsl")?
+            Self::KERNEL
+                .with_defs(shader_compiler.compilation_unit("./shaders/compute/blur.wgsl")?)
+                .with_integer_def("MAX_WEIGHTS_VEC4", MAX_WEIGHTS_VEC4 as u32)
                 .compile(&[variant])?,
         );
 
-        let sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
-            label: Some("BlurPass::Sampler"),
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            ..Default::default()
-        });
-
-        let bgl = gpu
-            .device
-            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("BlurPass::BindGroupLayout"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::StorageTexture {
-                            access: wgpu::StorageTextureAccess::WriteOnly,
-                            format: input_format,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 3,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 4,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                ],
-            });
-
-        let blur_x_tv = blur_tex_x.create_view(&Default::default());
-        let blur_y_tv = blur_tex_y.create_view(&Default::default());
-
-        let bg_y = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("BlurPass::BindGroupY"),
-            layout: &bgl,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    // dst
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&blur_y_tv),
-                },
-                wgpu::BindGroupEntry {
-                    // src
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&blur_x_tv),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: wgpu::BindingResource::Buffer(flip_y_buf.as_entire_buffer_binding()),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 4,
-                    resource: wgpu::BindingResource::Buffer(
-                        filter_size_buf.as_entire_buffer_binding(),
-                    ),
-                },
-            ],
-        });
-
-        let bg_x = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("BlurPass::BindGroupX"),
-            layout: &bgl,
-            entries: &[
-                wgpu::BindGroupEntry {
+        let bgl = bind_group_layouts.get(
+            gpu,
+            "BlurPass::BindGroupLayout",
+            &[
+                wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&blur_x_tv),
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: input_format,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
                 },
-                wgpu::BindGroupEntry {
+                wgpu::BindGroupLayoutEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&blur_y_tv),
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
                 },
-                wgpu::BindGroupEntry {
+                wgpu::BindGroupLayoutEntry {
                     binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
-                wgpu::BindGroupEntry {
+                wgpu::BindGroupLayoutEntry {
                     binding: 3,
-                    resource: wgpu::BindingResource::Buffer(flip_x_buf.as_entire_buffer_binding()),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 4,
-                    resource: wgpu::BindingResource::Buffer(
-                        filter_size_buf.as_entire_buffer_binding(),
-                    ),
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
             ],
-        });
+        );
 
         let compute_layout = gpu
             .device
@@ -227,69 +197,94 @@ impl BlurPass {
 
         Ok(Self {
             compute_pipeline,
+            bgl,
             flip_x: flip_x_buf,
-            blur_tex_x,
-            bg_x,
-            sampler,
-            bg_y,
-            filter_size_buf,
+            flip_y: flip_y_buf,
+            blur_tex_y,
+            filter_buf,
         })
     }
 
-    pub fn perform(
+    /// Result of the last `perform`. Every iteration ends with the vertical pass.
+    pub fn output(&self) -> &wgpu::Texture {
+        &self.blur_tex_y
+    }
+
+    fn bind_group(
         &self,
         gpu: &Gpu,
-        input: &wgpu::Texture,
-        iterations: u32,
-        filter_size: u32,
-    ) -> &wgpu::Texture {
-        let mut encoder = gpu
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("BlurPass::CommandEncoder"),
-            });
-
-        gpu.queue.write_buffer(
-            &self.filter_size_buf,
-            0,
-            bytemuck::cast_slice(&[filter_size]),
-        );
-        let wgpu::Extent3d {
-            width: image_width,
-            height: image_height,
-            ..
-        } = self.blur_tex_x.size();
-
-        let source_tv = input.create_view(&Default::default());
-        let tex_x_tv = self.blur_tex_x.create_view(&Default::default());
-        let bg_source = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &self.compute_pipeline.get_bind_group_layout(0),
+        dst: &wgpu::TextureView,
+        src: &wgpu::TextureView,
+        flip: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("BlurPass::BindGroup"),
+            layout: &self.bgl,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&tex_x_tv),
+                    resource: wgpu::BindingResource::TextureView(dst),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&source_tv),
+                    resource: wgpu::BindingResource::TextureView(src),
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    resource: wgpu::BindingResource::Buffer(flip.as_entire_buffer_binding()),
                 },
                 wgpu::BindGroupEntry {
                     binding: 3,
-                    resource: wgpu::BindingResource::Buffer(self.flip_x.as_entire_buffer_binding()),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 4,
                     resource: wgpu::BindingResource::Buffer(
-                        self.filter_size_buf.as_entire_buffer_binding(),
+                        self.filter_buf.as_entire_buffer_binding(),
                     ),
                 },
             ],
-        });
+        })
+    }
+
+    pub fn perform(
+        &self,
+        gpu: &Gpu,
+        texture_pool: &TexturePool,
+        input: &wgpu::Texture,
+        iterations: u32,
+        kernel: &GaussianKernel,
+    ) -> Result<&wgpu::Texture> {
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("BlurPass::CommandEncoder"),
+            });
+
+        let filter = kernel.filter();
+        let size: u64 = Filter::SHADER_SIZE.into();
+        let mut contents = UniformBuffer::new(Vec::with_capacity(size as usize));
+        contents.write(&filter)?;
+        gpu.queue
+            .write_buffer(&self.filter_buf, 0, contents.into_inner().as_slice());
+
+        let blur_tex_x = texture_pool.acquire(
+            gpu,
+            "BlurPass::TextureX",
+            &TransientDesc {
+                size: TargetSize::Fixed(self.blur_tex_y.size()),
+                format: self.blur_tex_y.format(),
+                usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            },
+        );
+        let wgpu::Extent3d {
+            width: image_width,
+            height: image_height,
+            ..
+        } = blur_tex_x.size();
+
+        let source_tv = input.create_view(&Default::default());
+        let blur_x_tv = blur_tex_x.create_view(&Default::default());
+        let blur_y_tv = self.blur_tex_y.create_view(&Default::default());
+        let bg_source = self.bind_group(gpu, &blur_x_tv, &source_tv, &self.flip_x);
+        let bg_x = self.bind_group(gpu, &blur_x_tv, &blur_y_tv, &self.flip_x);
+        let bg_y = self.bind_group(gpu, &blur_y_tv, &blur_x_tv, &self.flip_y);
 
         {
             let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
@@ -299,39 +294,27 @@ impl BlurPass {
 
             cpass.set_pipeline(&self.compute_pipeline);
 
+            // Vertical passes read the texture transposed.
+            let radius = filter[0].x as u32;
+            let kernel = Self::KERNEL.with_apron([2 * radius, 0, 0]);
+            let rows = [image_width, image_height, 1];
+            let columns = [image_height, image_width, 1];
+
             cpass.set_bind_group(0, &bg_source, &[]);
-            cpass.dispatch_workgroups(
-                ((image_width as f64) / (128 - filter_size - 1) as f64).ceil() as u32,
-                (image_height as f32 / 4.0).ceil() as u32,
-                1,
-            );
-
-            cpass.set_bind_group(0, &self.bg_y, &[]);
-            cpass.dispatch_workgroups(
-                ((image_height as f64) / (128 - filter_size - 1) as f64).ceil() as u32,
-                (image_width as f32 / 4.0).ceil() as u32,
-                1,
-            );
-
-            for _ in 0..iterations - 1 {
-                let (bg_x, bg_y) = (&self.bg_x, &self.bg_y);
-
-                cpass.set_bind_group(0, bg_x, &[]);
-                cpass.dispatch_workgroups(
-                    ((image_width as f64) / (128 - filter_size - 1) as f64).ceil() as u32,
-                    (image_height as f32 / 4.0).ceil() as u32,
-                    1,
-                );
-                cpass.set_bind_group(0, bg_y, &[]);
-                cpass.dispatch_workgroups(
-                    ((image_height as f64) / (128 - filter_size - 1) as f64).ceil() as u32,
-                    (image_width as f32 / 4.0).ceil() as u32,
-                    1,
-                );
+            dispatch(&mut cpass, &kernel, rows);
+
+            cpass.set_bind_group(0, &bg_y, &[]);
+            dispatch(&mut cpass, &kernel, columns);
+
+            for _ in 1..iterations {
+                cpass.set_bind_group(0, &bg_x, &[]);
+                dispatch(&mut cpass, &kernel, rows);
+                cpass.set_bind_group(0, &bg_y, &[]);
+                dispatch(&mut cpass, &kernel, columns);
             }
         }
 
         gpu.queue.submit(Some(encoder.finish()));
-        &self.blur_tex_x
+        Ok(self.output())
     }
 }
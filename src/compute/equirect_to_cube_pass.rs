@@ -0,0 +1,179 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::{Context, Result};
+
+use super::{dispatch, Kernel};
+use crate::{bind_group_layouts::BindGroupLayouts, gpu::Gpu, shader_compiler::ShaderCompiler};
+
+/// Turns equirectangular panoramas, like `.hdr` environments, into cube maps usable
+/// by `SkyboxPass` and for reflections of lighting passes.
+pub struct EquirectToCubePass {
+    compute_pipeline: wgpu::ComputePipeline,
+    bgl: Arc<wgpu::BindGroupLayout>,
+}
+
+impl EquirectToCubePass {
+    // One texel of a single face per invocation, faces are the z dimension.
+    const KERNEL: Kernel = Kernel::new([8, 8, 1]);
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    pub fn new(
+        gpu: &Gpu,
+        shader_compiler: &ShaderCompiler,
+        bind_group_layouts: &BindGroupLayouts,
+    ) -> Result<Self> {
+        let shader = gpu.shader_from_module(
+            Self::KERNEL
+                .with_defs(
+                    shader_compiler.compilation_unit("./shaders/compute/equirect_to_cube.wgsl")?,
+                )
+                .compile(&[])?,
+        );
+
+        let bgl = bind_group_layouts.get(
+            gpu,
+            "EquirectToCubePass::BindGroupLayout",
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: Self::FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                    },
+                    count: None,
+                },
+            ],
+        );
+
+        let compute_layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("EquirectToCubePass::PipelineLayout"),
+                bind_group_layouts: &[&bgl],
+                push_constant_ranges: &[],
+            });
+
+        let compute_pipeline =
+            gpu.device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("EquirectToCubePass::Pipeline"),
+                    layout: Some(&compute_layout),
+                    module: &shader,
+                    entry_point: "convert",
+                });
+
+        Ok(Self {
+            compute_pipeline,
+            bgl,
+        })
+    }
+
+    /// Reads a panorama and converts it to a cube map with faces a quarter of its width wide.
+    pub fn load(&self, gpu: &Gpu, path: impl AsRef<Path>) -> Result<wgpu::Texture> {
+        let path = path.as_ref();
+        let image = image::open(path)
+            .with_context(|| format!("failed to read environment {}", path.display()))?
+            .to_rgba32f();
+
+        let size = wgpu::Extent3d {
+            width: image.width(),
+            height: image.height(),
+            depth_or_array_layers: 1,
+        };
+
+        let equirect = gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("EquirectToCubePass::Equirect"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        gpu.queue.write_texture(
+            equirect.as_image_copy(),
+            bytemuck::cast_slice(image.as_raw()),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(16 * size.width),
+                rows_per_image: Some(size.height),
+            },
+            size,
+        );
+
+        Ok(self.convert(gpu, &equirect, (size.width / 4).max(1)))
+    }
+
+    pub fn convert(&self, gpu: &Gpu, equirect: &wgpu::Texture, face_size: u32) -> wgpu::Texture {
+        let cube = gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("EquirectToCubePass::Cube"),
+            size: wgpu::Extent3d {
+                width: face_size,
+                height: face_size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let equirect_tv = equirect.create_view(&Default::default());
+        let cube_tv = cube.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        let bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("EquirectToCubePass::BindGroup"),
+            layout: &self.bgl,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&equirect_tv),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&cube_tv),
+                },
+            ],
+        });
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("EquirectToCubePass::CommandEncoder"),
+            });
+
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("EquirectToCubePass::ComputePass"),
+                timestamp_writes: None,
+            });
+
+            cpass.set_pipeline(&self.compute_pipeline);
+            cpass.set_bind_group(0, &bg, &[]);
+            dispatch(&mut cpass, &Self::KERNEL, [face_size, face_size, 6]);
+        }
+
+        gpu.queue.submit(Some(encoder.finish()));
+
+        cube
+    }
+}
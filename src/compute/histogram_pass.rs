@@ -0,0 +1,309 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use encase::{ShaderSize, UniformBuffer};
+use nalgebra as na;
+
+use super::{dispatch, Kernel};
+use crate::{bind_group_layouts::BindGroupLayouts, gpu::Gpu, shader_compiler::ShaderCompiler};
+
+// Average log luminance, lit and total pixel counts in front of the bins.
+const RESULT_HEADER: usize = 3;
+
+/// Range of luminance spread over bins of a histogram, in stops. Pixels darker or brighter
+/// than that are counted into the bin at either end, black ones into the first bin of all.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LuminanceRange {
+    pub min_log: f32,
+    pub max_log: f32,
+}
+
+impl LuminanceRange {
+    pub fn new(min_log: f32, max_log: f32) -> Self {
+        Self { min_log, max_log }
+    }
+
+    fn log_range(&self) -> f32 {
+        (self.max_log - self.min_log).max(0.01)
+    }
+}
+
+/// Luminance histogram read back from the GPU.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    pub range: LuminanceRange,
+    /// Pixels in each of `HistogramPass::BINS`, the first of them are black.
+    pub bins: Vec<u32>,
+    /// Average log2 luminance of pixels which aren't black, computed on the GPU.
+    pub average_log_luminance: f32,
+    pub lit: u32,
+    pub total: u32,
+}
+
+impl Histogram {
+    fn from_result(range: LuminanceRange, words: &[u32]) -> Self {
+        let (header, bins) = words.split_at(RESULT_HEADER);
+
+        Self {
+            range,
+            bins: bins.to_vec(),
+            average_log_luminance: f32::from_bits(header[0]),
+            lit: header[1],
+            total: header[2],
+        }
+    }
+
+    // Log luminance in the middle of a bin past the black one.
+    fn bin_log_luminance(&self, bin: usize) -> f32 {
+        let lit_bins = (self.bins.len() - 2) as f32;
+        (bin as f32 - 0.5) / lit_bins * self.range.log_range() + self.range.min_log
+    }
+
+    /// Log2 luminance below which `fraction` of pixels which aren't black are,
+    /// `None` when all of them are.
+    pub fn percentile(&self, fraction: f32) -> Option<f32> {
+        if self.lit == 0 {
+            return None;
+        }
+
+        let target = (fraction.clamp(0.0, 1.0) * self.lit as f32).ceil().max(1.0) as u32;
+        let mut seen = 0;
+        for (bin, count) in self.bins.iter().enumerate().skip(1) {
+            seen += count;
+            if seen >= target {
+                return Some(self.bin_log_luminance(bin));
+            }
+        }
+
+        Some(self.range.max_log)
+    }
+}
+
+/// Counts pixels of a texture into bins of their log luminance, and reduces the counts
+/// into their average. Workgroups count into shared memory before adding into the
+/// histogram, which is then reduced by a single workgroup in parallel.
+///
+/// The histogram and the average are left in `result()` for later passes on the GPU,
+/// and can be read back with `read`.
+pub struct HistogramPass {
+    bgl: Arc<wgpu::BindGroupLayout>,
+    build_pipeline: wgpu::ComputePipeline,
+    reduce_pipeline: wgpu::ComputePipeline,
+    params_buf: wgpu::Buffer,
+    histogram_buf: wgpu::Buffer,
+    result_buf: wgpu::Buffer,
+    // Range of the last recorded histogram, for reading it back.
+    range: LuminanceRange,
+}
+
+impl HistogramPass {
+    pub const BINS: u32 = 256;
+    // An invocation for every bin of the histogram.
+    const KERNEL: Kernel = Kernel::new([16, 16, 1]);
+
+    pub fn new(
+        gpu: &Gpu,
+        shader_compiler: &ShaderCompiler,
+        bind_group_layouts: &BindGroupLayouts,
+    ) -> Result<Self> {
+        let params_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("HistogramPass::Params"),
+            size: na::Vector4::<f32>::SHADER_SIZE.into(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // Bins are cleared by the reduction, so they start and stay at zero between histograms.
+        let histogram_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("HistogramPass::Histogram"),
+            size: Self::BINS as u64 * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let result_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("HistogramPass::Result"),
+            size: Self::result_size(),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let storage = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let bgl = bind_group_layouts.get(
+            gpu,
+            "HistogramPass::BindGroupLayout",
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(2),
+                storage(3),
+            ],
+        );
+
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("HistogramPass::PipelineLayout"),
+                bind_group_layouts: &[&bgl],
+                push_constant_ranges: &[],
+            });
+
+        let shader = gpu.shader_from_module(
+            Self::KERNEL
+                .with_defs(shader_compiler.compilation_unit("./shaders/compute/histogram.wgsl")?)
+                .with_integer_def("HISTOGRAM_BINS", Self::BINS)
+                .compile(&[])?,
+        );
+
+        let pipeline = |label, entry_point| {
+            gpu.device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                })
+        };
+
+        Ok(Self {
+            build_pipeline: pipeline("HistogramPass::Build", "build_histogram"),
+            reduce_pipeline: pipeline("HistogramPass::Reduce", "reduce_histogram"),
+            bgl,
+            params_buf,
+            histogram_buf,
+            result_buf,
+            range: LuminanceRange::new(0.0, 1.0),
+        })
+    }
+
+    fn result_size() -> u64 {
+        ((RESULT_HEADER + Self::BINS as usize) * std::mem::size_of::<u32>()) as u64
+    }
+
+    /// Average log2 luminance, lit and total pixel counts, followed by the bins.
+    /// Laid out like `HistogramResult` in the histogram shader.
+    pub fn result(&self) -> &wgpu::Buffer {
+        &self.result_buf
+    }
+
+    /// Records building the histogram of `input` of `size` texels and reducing it,
+    /// as a compute pass of its own.
+    pub fn record(
+        &mut self,
+        gpu: &Gpu,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        size: wgpu::Extent3d,
+        range: LuminanceRange,
+        timestamp_writes: Option<wgpu::ComputePassTimestampWrites>,
+    ) -> Result<()> {
+        self.range = range;
+
+        // Fields of `Params` in the shader.
+        let params = na::Vector4::new(range.min_log, range.log_range(), 0.0, 0.0);
+        let params_size: u64 = na::Vector4::<f32>::SHADER_SIZE.into();
+        let mut contents = UniformBuffer::new(Vec::with_capacity(params_size as usize));
+        contents.write(&params)?;
+        gpu.queue
+            .write_buffer(&self.params_buf, 0, contents.into_inner().as_slice());
+
+        let bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("HistogramPass::BindGroup"),
+            layout: &self.bgl,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.params_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.histogram_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.result_buf.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("HistogramPass::ComputePass"),
+            timestamp_writes,
+        });
+
+        cpass.set_bind_group(0, &bg, &[]);
+
+        cpass.set_pipeline(&self.build_pipeline);
+        dispatch(&mut cpass, &Self::KERNEL, [size.width, size.height, 1]);
+
+        cpass.set_pipeline(&self.reduce_pipeline);
+        cpass.dispatch_workgroups(1, 1, 1);
+
+        Ok(())
+    }
+
+    /// Waits for the last recorded histogram and reads it back. Stalls the GPU,
+    /// so it's meant for debugging and statistics rather than every frame.
+    pub fn read(&self, gpu: &Gpu) -> Result<Histogram> {
+        let buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("HistogramPass::ReadbackBuffer"),
+            size: Self::result_size(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("HistogramPass::CommandEncoder"),
+            });
+        encoder.copy_buffer_to_buffer(&self.result_buf, 0, &buffer, 0, Self::result_size());
+        gpu.queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).ok();
+        });
+        gpu.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let histogram =
+            Histogram::from_result(self.range, bytemuck::cast_slice(&slice.get_mapped_range()));
+        buffer.unmap();
+
+        Ok(histogram)
+    }
+}
@@ -0,0 +1,77 @@
+use crate::shader_compiler::CompilationUnit;
+
+/// Shape of a compute kernel, declared next to the pass using it.
+///
+/// Shaders take their dimensions from definitions added by `with_defs` instead of
+/// repeating them, so dispatch sizes always match what the kernel was compiled with:
+/// `WORKGROUP_SIZE_*` for `@workgroup_size`, `INVOCATION_EXTENT_*` for elements handled
+/// by a single invocation and `WORKGROUP_EXTENT_*` for elements handled by a workgroup.
+#[derive(Clone, Copy)]
+pub struct Kernel {
+    workgroup_size: [u32; 3],
+    invocation_extent: [u32; 3],
+    // Elements shared by neighbouring workgroups, only read by one of them.
+    apron: [u32; 3],
+}
+
+impl Kernel {
+    pub const fn new(workgroup_size: [u32; 3]) -> Self {
+        Self {
+            workgroup_size,
+            invocation_extent: [1, 1, 1],
+            apron: [0, 0, 0],
+        }
+    }
+
+    pub const fn with_invocation_extent(mut self, invocation_extent: [u32; 3]) -> Self {
+        self.invocation_extent = invocation_extent;
+        self
+    }
+
+    /// Kernels reading a border around the elements they write, like convolutions,
+    /// need workgroups overlapping by the size of that border.
+    pub const fn with_apron(mut self, apron: [u32; 3]) -> Self {
+        self.apron = apron;
+        self
+    }
+
+    pub fn workgroup_extent(&self) -> [u32; 3] {
+        std::array::from_fn(|i| self.workgroup_size[i] * self.invocation_extent[i])
+    }
+
+    pub fn with_defs(&self, unit: CompilationUnit) -> CompilationUnit {
+        let workgroup_extent = self.workgroup_extent();
+
+        ["X", "Y", "Z"]
+            .into_iter()
+            .enumerate()
+            .fold(unit, |unit, (i, axis)| {
+                unit.with_integer_def(format!("WORKGROUP_SIZE_{axis}"), self.workgroup_size[i])
+                    .with_integer_def(
+                        format!("INVOCATION_EXTENT_{axis}"),
+                        self.invocation_extent[i],
+                    )
+                    .with_integer_def(format!("WORKGROUP_EXTENT_{axis}"), workgroup_extent[i])
+            })
+    }
+
+    /// Number of workgroups needed to cover `extent` elements.
+    pub fn workgroups(&self, extent: [u32; 3]) -> [u32; 3] {
+        let workgroup_extent = self.workgroup_extent();
+
+        std::array::from_fn(|i| {
+            let stride = workgroup_extent[i]
+                .checked_sub(self.apron[i])
+                .filter(|stride| *stride > 0)
+                .expect("apron has to be smaller than the workgroup extent");
+
+            extent[i].div_ceil(stride)
+        })
+    }
+}
+
+/// Dispatches enough workgroups of `kernel` to cover `extent` elements.
+pub fn dispatch(cpass: &mut wgpu::ComputePass, kernel: &Kernel, extent: [u32; 3]) {
+    let [x, y, z] = kernel.workgroups(extent);
+    cpass.dispatch_workgroups(x, y, z);
+}
@@ -0,0 +1,150 @@
+use anyhow::Result;
+
+use super::{dispatch, Kernel};
+use crate::{
+    bind_group_layouts::BindGroupLayouts,
+    gpu::Gpu,
+    scene_uniform::SceneUniform,
+    shader_compiler::{CompilationUnit, ShaderCompiler},
+};
+
+const CLUSTER_GRID: [u32; 3] = [16, 9, 24];
+// Also the capacity of light lists of `TileCullingPass`.
+pub(super) const MAX_CLUSTER_LIGHTS: u32 = 128;
+const KERNEL: Kernel = Kernel::new([4, 4, 4]);
+
+// Bins point and spot lights into a view space froxel grid, so shading only
+// has to consider lights which can reach the fragment's cluster.
+pub struct LightClusteringPass {
+    compute_pipeline: wgpu::ComputePipeline,
+    clusters_buf: wgpu::Buffer,
+    bg: wgpu::BindGroup,
+}
+
+impl LightClusteringPass {
+    pub fn new(
+        gpu: &Gpu,
+        shader_compiler: &ShaderCompiler,
+        bind_group_layouts: &BindGroupLayouts,
+        scene_uniform: &SceneUniform,
+        lights_buf: &wgpu::Buffer,
+    ) -> Result<Self> {
+        let num_clusters = CLUSTER_GRID.iter().product::<u32>() as u64;
+        let cluster_size = (1 + MAX_CLUSTER_LIGHTS as u64) * std::mem::size_of::<u32>() as u64;
+
+        let clusters_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("LightClusteringPass::ClustersBuffer"),
+            size: num_clusters * cluster_size,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let bgl = bind_group_layouts.get(
+            gpu,
+            "LightClusteringPass::BindGroupLayout",
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        );
+
+        let bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("LightClusteringPass::BindGroup"),
+            layout: &bgl,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: lights_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: clusters_buf.as_entire_binding(),
+                },
+            ],
+        });
+
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("LightClusteringPass::PipelineLayout"),
+                bind_group_layouts: &[scene_uniform.layout(), &bgl],
+                push_constant_ranges: &[],
+            });
+
+        let module = KERNEL
+            .with_defs(Self::with_cluster_defs(gpu.with_depth_defs(
+                shader_compiler.compilation_unit("./shaders/forward/clustering.wgsl")?,
+            )))
+            .compile(&[])?;
+        let shader = gpu.shader_from_module(module);
+
+        let compute_pipeline =
+            gpu.device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("LightClusteringPass::ComputePipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point: "cluster_lights",
+                });
+
+        Ok(Self {
+            compute_pipeline,
+            clusters_buf,
+            bg,
+        })
+    }
+
+    /// Adds definitions needed by shaders importing `gpubasics::forward::clusters`.
+    pub fn with_cluster_defs(unit: CompilationUnit) -> CompilationUnit {
+        unit.with_integer_def("CLUSTER_GRID_X", CLUSTER_GRID[0])
+            .with_integer_def("CLUSTER_GRID_Y", CLUSTER_GRID[1])
+            .with_integer_def("CLUSTER_GRID_Z", CLUSTER_GRID[2])
+            .with_integer_def("MAX_CLUSTER_LIGHTS", MAX_CLUSTER_LIGHTS)
+            .with_def("CLUSTERED")
+    }
+
+    pub fn clusters_buffer(&self) -> &wgpu::Buffer {
+        &self.clusters_buf
+    }
+
+    pub fn perform(&self, gpu: &Gpu, scene_uniform: &SceneUniform) {
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("LightClusteringPass::CommandEncoder"),
+            });
+
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("LightClusteringPass::ComputePass"),
+                timestamp_writes: None,
+            });
+
+            cpass.set_pipeline(&self.compute_pipeline);
+            cpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
+            cpass.set_bind_group(1, &self.bg, &[]);
+
+            dispatch(&mut cpass, &KERNEL, CLUSTER_GRID);
+        }
+
+        gpu.queue.submit(Some(encoder.finish()));
+    }
+}
@@ -1,3 +1,13 @@
 mod blur_pass;
+mod equirect_to_cube_pass;
+mod histogram_pass;
+mod kernel;
+mod light_clustering_pass;
+mod tile_culling_pass;
 
-pub use blur_pass::BlurPass;
+pub use blur_pass::{BlurPass, GaussianKernel};
+pub use equirect_to_cube_pass::EquirectToCubePass;
+pub use histogram_pass::{Histogram, HistogramPass, LuminanceRange};
+pub use kernel::{dispatch, Kernel};
+pub use light_clustering_pass::LightClusteringPass;
+pub use tile_culling_pass::TileCullingPass;
@@ -0,0 +1,196 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use super::{dispatch, light_clustering_pass::MAX_CLUSTER_LIGHTS, Kernel, LightClusteringPass};
+use crate::{
+    bind_group_layouts::BindGroupLayouts,
+    gpu::Gpu,
+    scene_uniform::SceneUniform,
+    shader_compiler::{CompilationUnit, ShaderCompiler},
+};
+
+const TILE_SIZE: u32 = 16;
+// One invocation per pixel of a tile.
+const KERNEL: Kernel = Kernel::new([TILE_SIZE, TILE_SIZE, 1]);
+
+// Culls point and spot lights against screen tiles, bounded by the depth range
+// the prepass left in every tile. Light lists have the layout of clusters of
+// `LightClusteringPass`, so forward shading can use either of them.
+pub struct TileCullingPass {
+    compute_pipeline: wgpu::ComputePipeline,
+    bgl: Arc<wgpu::BindGroupLayout>,
+    tiles_buf: wgpu::Buffer,
+    // `vec4(tiles in x, tiles in y, 0, 0)`.
+    grid_buf: wgpu::Buffer,
+}
+
+fn grid_size(gpu: &Gpu) -> [u32; 2] {
+    let viewport = gpu.viewport_size();
+    [
+        viewport.width.div_ceil(TILE_SIZE),
+        viewport.height.div_ceil(TILE_SIZE),
+    ]
+}
+
+impl TileCullingPass {
+    pub fn new(
+        gpu: &Gpu,
+        shader_compiler: &ShaderCompiler,
+        bind_group_layouts: &BindGroupLayouts,
+        scene_uniform: &SceneUniform,
+    ) -> Result<Self> {
+        let grid_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("TileCullingPass::GridBuffer"),
+            size: std::mem::size_of::<[u32; 4]>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let bgl = bind_group_layouts.get(
+            gpu,
+            "TileCullingPass::BindGroupLayout",
+            &[
+                buffer_entry(0, wgpu::BufferBindingType::Storage { read_only: true }),
+                buffer_entry(1, wgpu::BufferBindingType::Storage { read_only: false }),
+                buffer_entry(2, wgpu::BufferBindingType::Uniform),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        );
+
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("TileCullingPass::PipelineLayout"),
+                bind_group_layouts: &[scene_uniform.layout(), &bgl],
+                push_constant_ranges: &[],
+            });
+
+        let module = KERNEL
+            .with_defs(Self::with_tile_defs(
+                LightClusteringPass::with_cluster_defs(gpu.with_depth_defs(
+                    shader_compiler.compilation_unit("./shaders/forward/tile_culling.wgsl")?,
+                )),
+            ))
+            .compile(&[])?;
+        let shader = gpu.shader_from_module(module);
+
+        let compute_pipeline =
+            gpu.device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("TileCullingPass::ComputePipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point: "cull_lights",
+                });
+
+        Ok(Self {
+            compute_pipeline,
+            bgl,
+            tiles_buf: Self::create_tiles_buffer(gpu, &grid_buf),
+            grid_buf,
+        })
+    }
+
+    /// Adds definitions needed by shaders importing `gpubasics::forward::tiles`.
+    pub fn with_tile_defs(unit: CompilationUnit) -> CompilationUnit {
+        unit.with_integer_def("TILE_SIZE", TILE_SIZE)
+    }
+
+    // Light lists for every tile of the viewport, with the grid written for shading.
+    fn create_tiles_buffer(gpu: &Gpu, grid_buf: &wgpu::Buffer) -> wgpu::Buffer {
+        let [x, y] = grid_size(gpu);
+        gpu.queue
+            .write_buffer(grid_buf, 0, bytemuck::cast_slice(&[x, y, 0, 0]));
+
+        let tile_size = (1 + MAX_CLUSTER_LIGHTS as u64) * std::mem::size_of::<u32>() as u64;
+
+        gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("TileCullingPass::TilesBuffer"),
+            size: (x * y) as u64 * tile_size,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn tiles_buffer(&self) -> &wgpu::Buffer {
+        &self.tiles_buf
+    }
+
+    pub fn grid_buffer(&self) -> &wgpu::Buffer {
+        &self.grid_buf
+    }
+
+    /// Tiles follow the viewport, so bind groups using the tiles buffer have to be recreated.
+    pub fn on_resize(&mut self, gpu: &Gpu) {
+        self.tiles_buf = Self::create_tiles_buffer(gpu, &self.grid_buf);
+    }
+
+    pub fn perform(&self, gpu: &Gpu, scene_uniform: &SceneUniform, lights_buf: &wgpu::Buffer) {
+        let depth_view = gpu.depth_texture_view();
+        let bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("TileCullingPass::BindGroup"),
+            layout: &self.bgl,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: lights_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.tiles_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.grid_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&depth_view),
+                },
+            ],
+        });
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("TileCullingPass::CommandEncoder"),
+            });
+
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("TileCullingPass::ComputePass"),
+                timestamp_writes: None,
+            });
+
+            cpass.set_pipeline(&self.compute_pipeline);
+            cpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
+            cpass.set_bind_group(1, &bg, &[]);
+
+            let viewport = gpu.viewport_size();
+            dispatch(&mut cpass, &KERNEL, [viewport.width, viewport.height, 1]);
+        }
+
+        gpu.queue.submit(Some(encoder.finish()));
+    }
+}
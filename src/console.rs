@@ -0,0 +1,278 @@
+use std::{collections::VecDeque, path::PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+
+use gpu_basics::settings::AppSettings;
+
+// Lines of output kept in the console.
+const HISTORY_SIZE: usize = 256;
+
+/// Runtime command, executed by the main loop.
+pub enum Command {
+    LoadScene(PathBuf),
+    SaveScene(PathBuf),
+    Set(String, String),
+    Toggle(String),
+    Spawn {
+        model: String,
+        translation: [f32; 3],
+        material: Option<String>,
+    },
+    Screenshot(PathBuf),
+    // `None` stops the recording.
+    Record(Option<PathBuf>),
+    Histogram,
+}
+
+type CommandParser = fn(&[&str]) -> Result<Command>;
+
+struct CommandSpec {
+    name: &'static str,
+    usage: &'static str,
+    help: &'static str,
+    parse: CommandParser,
+}
+
+/// Commands known to the console by name. Besides typing them in, anything able to produce
+/// a command line (benchmarks, test runs) can drive the application through it.
+pub struct CommandRegistry {
+    commands: Vec<CommandSpec>,
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            commands: Vec::new(),
+        };
+
+        registry.register(
+            "load_scene",
+            "load_scene <path>",
+            "Loads and watches a scene script",
+            |args| match args {
+                [path] => Ok(Command::LoadScene(path.into())),
+                _ => bail!("expected a path"),
+            },
+        );
+
+        registry.register(
+            "save_scene",
+            "save_scene <path>",
+            "Saves the scene script with current objects, materials, lights and camera",
+            |args| match args {
+                [path] => Ok(Command::SaveScene(path.into())),
+                _ => bail!("expected a path"),
+            },
+        );
+
+        registry.register(
+            "set",
+            "set <setting> <value>",
+            "Changes a setting",
+            |args| match args {
+                [name, value] => Ok(Command::Set(name.to_string(), value.to_string())),
+                _ => bail!(
+                    "expected a setting and a value, settings: {}",
+                    AppSettings::SETTING_NAMES.join(", ")
+                ),
+            },
+        );
+
+        registry.register(
+            "toggle",
+            "toggle <name>",
+            "Enables or disables an optional pass, or fullscreen",
+            |args| match args {
+                [name] => Ok(Command::Toggle(name.to_string())),
+                _ => bail!("expected one of: {}", AppSettings::TOGGLE_NAMES.join(", ")),
+            },
+        );
+
+        registry.register(
+            "spawn",
+            "spawn <model> [<x> <y> <z>] [<material>]",
+            "Adds a model declared in the scene script",
+            |args| {
+                let (model, rest) = args
+                    .split_first()
+                    .ok_or_else(|| anyhow!("expected a model"))?;
+                let (translation, material) = match rest {
+                    [x, y, z, material @ ..] => ([*x, *y, *z], material),
+                    material => (["0", "0", "0"], material),
+                };
+
+                let translation = translation.map(|v| {
+                    v.parse::<f32>()
+                        .map_err(|_| anyhow!("invalid coordinate {v}"))
+                });
+                let [x, y, z] = translation;
+
+                Ok(Command::Spawn {
+                    model: model.to_string(),
+                    translation: [x?, y?, z?],
+                    material: match material {
+                        [] => None,
+                        [material] => Some(material.to_string()),
+                        _ => bail!("too many arguments"),
+                    },
+                })
+            },
+        );
+
+        registry.register(
+            "screenshot",
+            "screenshot <path>",
+            "Saves the next frame without UI",
+            |args| match args {
+                [path] => Ok(Command::Screenshot(path.into())),
+                _ => bail!("expected a path"),
+            },
+        );
+
+        registry.register(
+            "record",
+            "record <directory|video.mp4>|stop",
+            "Records frames without UI as PNGs or a video",
+            |args| match args {
+                ["stop"] => Ok(Command::Record(None)),
+                [path] => Ok(Command::Record(Some(path.into()))),
+                _ => bail!("expected a path or stop"),
+            },
+        );
+
+        registry.register(
+            "histogram",
+            "histogram",
+            "Prints luminance statistics of the last frame measured by auto exposure",
+            |args| match args {
+                [] => Ok(Command::Histogram),
+                _ => bail!("expected no arguments"),
+            },
+        );
+
+        registry
+    }
+}
+
+impl CommandRegistry {
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        help: &'static str,
+        parse: CommandParser,
+    ) {
+        self.commands.push(CommandSpec {
+            name,
+            usage,
+            help,
+            parse,
+        });
+    }
+
+    pub fn parse(&self, line: &str) -> Result<Command> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, args) = words
+            .split_first()
+            .ok_or_else(|| anyhow!("empty command"))?;
+
+        let spec = self
+            .commands
+            .iter()
+            .find(|spec| spec.name == *name)
+            .ok_or_else(|| anyhow!("unknown command {name}, try help"))?;
+
+        (spec.parse)(args).map_err(|e| anyhow!("{e}\nusage: {}", spec.usage))
+    }
+
+    pub fn help(&self) -> impl Iterator<Item = String> + '_ {
+        self.commands
+            .iter()
+            .map(|spec| format!("{} - {}", spec.usage, spec.help))
+    }
+}
+
+/// Drop down console, opened with the backtick key.
+#[derive(Default)]
+pub struct Console {
+    open: bool,
+    focus_input: bool,
+    input: String,
+    output: VecDeque<String>,
+    registry: CommandRegistry,
+    pending: VecDeque<Command>,
+}
+
+impl Console {
+    pub fn log(&mut self, line: impl Into<String>) {
+        if self.output.len() == HISTORY_SIZE {
+            self.output.pop_front();
+        }
+
+        self.output.push_back(line.into());
+    }
+
+    /// Parses a command line and queues it for execution.
+    pub fn submit(&mut self, line: &str) {
+        self.log(format!("> {line}"));
+
+        match line.trim() {
+            "help" => {
+                let help: Vec<String> = self.registry.help().collect();
+                help.into_iter().for_each(|line| self.log(line));
+            }
+            "clear" => self.output.clear(),
+            line => match self.registry.parse(line) {
+                Ok(command) => self.pending.push_back(command),
+                Err(e) => e.to_string().lines().for_each(|line| self.log(line)),
+            },
+        }
+    }
+
+    /// Commands submitted since the last call.
+    pub fn drain(&mut self) -> Vec<Command> {
+        self.pending.drain(..).collect()
+    }
+
+    pub fn render(&mut self, ctx: &egui::Context) {
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Backtick)) {
+            self.open = !self.open;
+            self.focus_input = self.open;
+        }
+
+        if !self.open {
+            return;
+        }
+
+        egui::TopBottomPanel::top("console").show(ctx, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .stick_to_bottom(true)
+                .auto_shrink([false, true])
+                .show(ui, |ui| {
+                    for line in &self.output {
+                        ui.monospace(line);
+                    }
+                });
+
+            let input = ui.add(
+                egui::TextEdit::singleline(&mut self.input)
+                    .font(egui::TextStyle::Monospace)
+                    .desired_width(f32::INFINITY),
+            );
+            if std::mem::take(&mut self.focus_input) {
+                input.request_focus();
+            }
+            // The key opening the console shouldn't end up in the command.
+            self.input.retain(|c| c != '`');
+
+            if input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                let line = std::mem::take(&mut self.input);
+                if !line.trim().is_empty() {
+                    self.submit(&line);
+                }
+                input.request_focus();
+            }
+        });
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    panic::PanicHookInfo,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use gpu_basics::gpu::Gpu;
+
+// Validation messages kept for the report.
+const RECENT_ERRORS: usize = 16;
+
+struct CrashState {
+    adapter: Option<wgpu::AdapterInfo>,
+    current_pass: &'static str,
+    errors: VecDeque<String>,
+    // Errors not shown to the user yet.
+    unseen: Vec<String>,
+}
+
+static STATE: Mutex<CrashState> = Mutex::new(CrashState {
+    adapter: None,
+    current_pass: "none",
+    errors: VecDeque::new(),
+    unseen: Vec::new(),
+});
+
+// A panic while the lock is held shouldn't prevent writing the report.
+fn state() -> std::sync::MutexGuard<'static, CrashState> {
+    STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Installs a panic hook writing a `crash-<timestamp>.log` file with the adapter,
+/// the pass being recorded and recent validation errors, before the default hook runs.
+///
+/// Uncaptured wgpu errors are recorded instead of panicking, see `take_errors`.
+pub fn install(gpu: &Gpu) {
+    state().adapter = Some(gpu.adapter.get_info());
+
+    gpu.device.on_uncaptured_error(Box::new(|error| {
+        let mut state = state();
+        let message = format!("{} (in {})", error, state.current_pass);
+        if state.errors.len() == RECENT_ERRORS {
+            state.errors.pop_front();
+        }
+        state.errors.push_back(message.clone());
+        state.unseen.push(message);
+    }));
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        match write_report(info) {
+            Ok(path) => eprintln!("Crash report written to {}", path),
+            Err(e) => eprintln!("Failed to write crash report: {}", e),
+        }
+
+        default_hook(info);
+    }));
+}
+
+/// wgpu errors since the last call, to be shown to the user.
+pub fn take_errors() -> Vec<String> {
+    std::mem::take(&mut state().unseen)
+}
+
+/// Marks the pass which is about to record commands, so a crash can be attributed to it.
+pub fn enter_pass(name: &'static str) {
+    state().current_pass = name;
+}
+
+fn write_report(info: &PanicHookInfo) -> std::io::Result<String> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|t| t.as_secs())
+        .unwrap_or_default();
+
+    let state = state();
+    let mut report = String::new();
+
+    writeln!(report, "{}", info).ok();
+    writeln!(report, "Current pass: {}", state.current_pass).ok();
+
+    match &state.adapter {
+        Some(adapter) => {
+            writeln!(
+                report,
+                "Adapter: {} ({:?})",
+                adapter.name, adapter.device_type
+            )
+            .ok();
+            writeln!(report, "Backend: {:?}", adapter.backend).ok();
+            writeln!(report, "Driver: {} {}", adapter.driver, adapter.driver_info).ok();
+            writeln!(
+                report,
+                "Vendor: {:#06x}, device: {:#06x}",
+                adapter.vendor, adapter.device
+            )
+            .ok();
+        }
+        None => {
+            writeln!(report, "Adapter: not initialized").ok();
+        }
+    }
+
+    writeln!(report, "Recent validation errors:").ok();
+    if state.errors.is_empty() {
+        writeln!(report, "  none").ok();
+    }
+    for error in &state.errors {
+        writeln!(report, "  {}", error).ok();
+    }
+
+    let path = format!("crash-{}.log", timestamp);
+    std::fs::write(&path, report)?;
+
+    Ok(path)
+}
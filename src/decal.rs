@@ -0,0 +1,46 @@
+use nalgebra as na;
+
+use crate::material::MaterialId;
+
+type FMat4x4 = na::Matrix4<f32>;
+
+/// Bytes a decal takes on the GPU, laid out like `Decal` in the decal shader.
+pub const DECAL_STRIDE: usize =
+    std::mem::size_of::<[FMat4x4; 2]>() + std::mem::size_of::<[f32; 4]>();
+
+/// Box projecting a material onto whatever lies inside it. The box spans `-0.5..0.5`
+/// along every axis of `model` and projects down its Y axis, with the material's
+/// diffuse and normal maps stretched over its XZ face.
+#[derive(Clone, Copy, Debug)]
+pub struct Decal {
+    pub model: FMat4x4,
+    pub material: MaterialId,
+    // Scales alpha of the diffuse map, decals of solid materials are opaque otherwise.
+    pub opacity: f32,
+}
+
+impl Decal {
+    pub fn new(model: FMat4x4, material: MaterialId) -> Self {
+        Self {
+            model,
+            material,
+            opacity: 1.0,
+        }
+    }
+
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity;
+        self
+    }
+
+    pub fn copy_to(&self, target: &mut Vec<u8>) {
+        // Flattened boxes cover nothing, so everything is moved to a corner outside of them.
+        let model_inv = self.model.try_inverse().unwrap_or_else(|| {
+            FMat4x4::new_translation(&na::Vector3::repeat(1.0)) * FMat4x4::new_scaling(0.0)
+        });
+
+        target.extend(bytemuck::cast_slice(&[self.model, model_inv]));
+        target.extend(bytemuck::cast_slice(&[self.material.index()]));
+        target.extend(bytemuck::cast_slice(&[self.opacity, 0.0, 0.0]));
+    }
+}
@@ -1,11 +1,19 @@
 use std::sync::Arc;
 
-use crate::{gpu::Gpu, render_context::RenderContext, shader_compiler::ShaderCompiler};
-use anyhow::Result;
+use crate::{
+    render_context::RenderContext,
+    render_graph::{Binding, GraphResources, PassIo},
+    render_pass::{FrameContext, RenderPass},
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
 
-use super::geometry_pass::GBuffers;
+use super::{
+    geometry_pass::{G_DIFFUSE, G_NORMAL, G_SPECULAR},
+    ssao_pass::AMBIENT_OCCLUSION,
+};
 
-#[derive(Default, PartialEq, Eq)]
+#[derive(Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DeferredDebug {
     #[default]
     Normals,
@@ -13,11 +21,24 @@ pub enum DeferredDebug {
     Specular,
     Depth,
     AmbientOcclusion,
+    // Several buffers at once, one in each quadrant of the screen.
+    QuadSplit,
 }
 
+// Buffers of the split view, from the top left quadrant in reading order.
+const QUAD_SPLIT: [DeferredDebug; 4] = [
+    DeferredDebug::Normals,
+    DeferredDebug::Diffuse,
+    DeferredDebug::Depth,
+    DeferredDebug::AmbientOcclusion,
+];
+
 pub struct DebugPass<'window> {
     render_ctx: Arc<RenderContext<'window>>,
-    pipeline_depth: wgpu::RenderPipeline,
+    // G-Buffer views, drawn straight from the G-Buffer bind group, in order of
+    // normals, diffuse, specular and depth.
+    gbuffer_pipelines: [wgpu::RenderPipeline; 4],
+    // Ambient occlusion isn't a part of the G-Buffer, so it's sampled on its own.
     pipeline: wgpu::RenderPipeline,
     sampler: wgpu::Sampler,
 }
@@ -26,6 +47,7 @@ impl<'window> DebugPass<'window> {
     pub fn new(render_ctx: Arc<RenderContext<'window>>) -> Result<Self> {
         let RenderContext {
             gpu,
+            bind_group_layouts,
             shader_compiler,
             ..
         } = render_ctx.as_ref();
@@ -41,88 +63,63 @@ impl<'window> DebugPass<'window> {
             ..Default::default()
         });
 
-        let bgl = gpu
-            .device
-            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
-                        count: None,
+        let bgl = bind_group_layouts.get(
+            gpu,
+            "DebugPass::BindGroupLayout",
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
                     },
-                ],
-            });
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                    count: None,
+                },
+            ],
+        );
 
-        let bgl_depth = gpu
-            .device
-            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Depth,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
-                        count: None,
-                    },
-                ],
-            });
+        let gbuffer_bgl = bind_group_layouts.gbuffer(gpu);
 
-        let module = shader_compiler.compilation_unit("./shaders/showTexture.wgsl")?;
-        let shader = gpu.shader_from_module(module.compile(&[])?);
-        let depth_shader = gpu.shader_from_module(module.compile(&["DEPTH_TEXTURE"])?);
+        let texture_module = shader_compiler.compilation_unit("./shaders/showTexture.wgsl")?;
+        let texture_shader = gpu.shader_from_module(texture_module.compile(&[])?);
 
-        let pipeline_l = gpu
-            .device
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[&bgl],
-                push_constant_ranges: &[],
-            });
+        let gbuffer_module = gpu
+            .with_depth_defs(
+                shader_compiler.compilation_unit("./shaders/deferred/gbuffer_debug.wgsl")?,
+            )
+            .with_integer_def("GBUFFER_GROUP", 0);
+        let gbuffer_shader = gpu.shader_from_module(gbuffer_module.compile(&[])?);
 
-        let pipeline_depth_l = gpu
-            .device
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[&bgl_depth],
-                push_constant_ranges: &[],
-            });
+        let create_pipeline =
+            |bgl: &wgpu::BindGroupLayout, shader: &wgpu::ShaderModule, entry_point: &str| {
+                let layout = gpu
+                    .device
+                    .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: None,
+                        bind_group_layouts: &[bgl],
+                        push_constant_ranges: &[],
+                    });
 
-        let [pipeline, pipeline_depth] = [(shader, pipeline_l), (depth_shader, pipeline_depth_l)]
-            .map(|(shader, layout)| {
                 gpu.device
                     .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
                         label: None,
                         layout: Some(&layout),
                         vertex: wgpu::VertexState {
-                            module: &shader,
+                            module: shader,
                             entry_point: "vs_main",
                             buffers: &[],
                         },
                         fragment: Some(wgpu::FragmentState {
-                            module: &shader,
-                            entry_point: "fs_main",
+                            module: shader,
+                            entry_point,
                             targets: &[Some(wgpu::ColorTargetState {
                                 format: gpu.swapchain_format(),
                                 blend: Some(wgpu::BlendState::REPLACE),
@@ -137,124 +134,84 @@ impl<'window> DebugPass<'window> {
                         multisample: wgpu::MultisampleState::default(),
                         multiview: None,
                     })
-            });
+            };
+
+        let gbuffer_pipelines = ["fs_normal", "fs_diffuse", "fs_specular", "fs_depth"]
+            .map(|entry_point| create_pipeline(&gbuffer_bgl, &gbuffer_shader, entry_point));
+        let pipeline = create_pipeline(&bgl, &texture_shader, "fs_main");
 
         Ok(Self {
             render_ctx,
-            pipeline_depth,
+            gbuffer_pipelines,
             pipeline,
             sampler,
         })
     }
 
-    pub fn render(
+    // Pipeline drawing a single buffer, with its own bind group unless it reads the G-Buffer one.
+    fn source(
         &self,
-        g_bufs: &GBuffers,
-        frame: &wgpu::SurfaceTexture,
-        ssao_tv: &wgpu::TextureView,
+        resources: &GraphResources,
         debug_type: &DeferredDebug,
-    ) {
-        let gpu = &self.render_ctx.gpu;
-
-        let bg = match debug_type {
-            DeferredDebug::Normals => {
-                let tv = g_bufs
-                    .g_normal
-                    .create_view(&wgpu::TextureViewDescriptor::default());
-
-                gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: Some("DeferredDebug::NormalsBG"),
-                    layout: &self.pipeline.get_bind_group_layout(0),
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(&tv),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::Sampler(&self.sampler),
-                        },
+    ) -> Result<(&wgpu::RenderPipeline, Option<wgpu::BindGroup>)> {
+        let gbuffer_pipeline = match debug_type {
+            DeferredDebug::Normals => &self.gbuffer_pipelines[0],
+            DeferredDebug::Diffuse => &self.gbuffer_pipelines[1],
+            DeferredDebug::Specular => &self.gbuffer_pipelines[2],
+            DeferredDebug::Depth => &self.gbuffer_pipelines[3],
+            DeferredDebug::AmbientOcclusion => {
+                let bg = resources.bind_group(
+                    &self.render_ctx.gpu,
+                    "DeferredDebug::BindGroup",
+                    &self.pipeline.get_bind_group_layout(0),
+                    &[
+                        Binding::Resource(AMBIENT_OCCLUSION),
+                        Binding::Sampler(&self.sampler),
                     ],
-                })
+                )?;
+                return Ok((&self.pipeline, Some(bg)));
             }
-            DeferredDebug::Diffuse => {
-                let tv = g_bufs
-                    .g_diffuse
-                    .create_view(&wgpu::TextureViewDescriptor::default());
+            DeferredDebug::QuadSplit => unreachable!("split view is made of other buffers"),
+        };
 
-                gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: Some("DeferredDebug::DiffuseBG"),
-                    layout: &self.pipeline.get_bind_group_layout(0),
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(&tv),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::Sampler(&self.sampler),
-                        },
-                    ],
-                })
-            }
-            DeferredDebug::Specular => {
-                let tv = g_bufs
-                    .g_specular
-                    .create_view(&wgpu::TextureViewDescriptor::default());
+        Ok((gbuffer_pipeline, None))
+    }
 
-                gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: Some("DeferredDebug::SpecularBG"),
-                    layout: &self.pipeline.get_bind_group_layout(0),
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(&tv),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::Sampler(&self.sampler),
-                        },
-                    ],
-                })
-            }
-            DeferredDebug::Depth => {
-                let tv = gpu.depth_texture_view();
+    pub fn render(
+        &self,
+        resources: &GraphResources,
+        gbuffer_bg: &wgpu::BindGroup,
+        frame: &wgpu::SurfaceTexture,
+        debug_type: &DeferredDebug,
+        clear_color: wgpu::Color,
+    ) -> Result<()> {
+        let gpu = &self.render_ctx.gpu;
 
-                gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: Some("DeferredDebug::DepthBG"),
-                    layout: &self.pipeline_depth.get_bind_group_layout(0),
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(&tv),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::Sampler(&self.sampler),
-                        },
-                    ],
-                })
-            }
-            DeferredDebug::AmbientOcclusion => {
-                let tv = ssao_tv;
+        let width = frame.texture.width() as f32;
+        let height = frame.texture.height() as f32;
 
-                gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: Some("DeferredDebug::AOBG"),
-                    layout: &self.pipeline.get_bind_group_layout(0),
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(tv),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::Sampler(&self.sampler),
-                        },
-                    ],
+        // Every buffer is drawn into its own viewport, as `[x, y, width, height]`.
+        let views = match debug_type {
+            DeferredDebug::QuadSplit => QUAD_SPLIT
+                .iter()
+                .enumerate()
+                .map(|(i, debug_type)| {
+                    let x = (i % 2) as f32 * width / 2.0;
+                    let y = (i / 2) as f32 * height / 2.0;
+                    (debug_type, [x, y, width / 2.0, height / 2.0])
                 })
-            }
+                .collect(),
+            debug_type => vec![(debug_type, [0.0, 0.0, width, height])],
         };
 
+        let draws = views
+            .into_iter()
+            .map(|(debug_type, viewport)| {
+                let (pipeline, bg) = self.source(resources, debug_type)?;
+                Ok((pipeline, bg, viewport))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
         let mut encoder = gpu
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
@@ -270,7 +227,7 @@ impl<'window> DebugPass<'window> {
                     view: &frame_view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        load: wgpu::LoadOp::Clear(clear_color),
                         store: wgpu::StoreOp::Store,
                     },
                 })],
@@ -279,18 +236,46 @@ impl<'window> DebugPass<'window> {
                 occlusion_query_set: None,
             });
 
-            match debug_type {
-                DeferredDebug::Depth => {
-                    rpass.set_pipeline(&self.pipeline_depth);
-                }
-                _ => {
-                    rpass.set_pipeline(&self.pipeline);
-                }
+            for (pipeline, bg, [x, y, width, height]) in &draws {
+                rpass.set_viewport(*x, *y, *width, *height, 0.0, 1.0);
+                rpass.set_pipeline(pipeline);
+                rpass.set_bind_group(0, bg.as_ref().unwrap_or(gbuffer_bg), &[]);
+                rpass.draw(0..4, 0..1);
             }
-
-            rpass.set_bind_group(0, &bg, &[]);
-            rpass.draw(0..4, 0..1);
         }
         gpu.queue.submit(Some(encoder.finish()));
+
+        Ok(())
+    }
+}
+
+impl RenderPass for DebugPass<'_> {
+    fn name(&self) -> &'static str {
+        "deferred::DebugPass"
+    }
+
+    fn enabled(&self, ctx: &FrameContext) -> bool {
+        ctx.settings.deferred_debug_shown()
+    }
+
+    fn io(&self) -> PassIo {
+        [G_NORMAL, G_DIFFUSE, G_SPECULAR, AMBIENT_OCCLUSION]
+            .into_iter()
+            .fold(PassIo::default(), PassIo::read)
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        let gbuffer = ctx
+            .gbuffer
+            .as_ref()
+            .context("G-Buffer has to be rendered before it's shown")?;
+        Self::render(
+            self,
+            &ctx.resources,
+            gbuffer,
+            &ctx.frame,
+            &ctx.settings.deferred_dbg.debug_type,
+            ctx.settings.background.clear_color(),
+        )
     }
 }
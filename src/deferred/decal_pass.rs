@@ -0,0 +1,283 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use encase::{ShaderSize, UniformBuffer};
+use nalgebra as na;
+
+use crate::{
+    mesh::{Mesh, MeshBuilder},
+    render_context::RenderContext,
+    render_graph::{Binding, GraphResources, PassIo},
+    render_pass::{FrameContext, RenderPass},
+    settings::{DecalSettings, PipelineType},
+    shapes::Cube,
+};
+
+use super::{G_DIFFUSE, G_NORMAL};
+
+// Fields of `DecalParams` in the shader.
+type Params = [na::Vector4<f32>; 1];
+
+/// Projects decals of the scene onto G-Buffers, between laying them down and lighting.
+/// Boxes of decals are rasterized, and every pixel with depth inside of one has its
+/// diffuse color, and normal for normal mapped materials, blended with the decal's.
+pub struct DecalPass<'window> {
+    render_ctx: Arc<RenderContext<'window>>,
+    params_buf: wgpu::Buffer,
+    bgl: Arc<wgpu::BindGroupLayout>,
+    pipeline: wgpu::RenderPipeline,
+    vbuf: wgpu::Buffer,
+    ibuf: wgpu::Buffer,
+    num_indices: u32,
+}
+
+impl<'window> DecalPass<'window> {
+    pub fn new(render_ctx: Arc<RenderContext<'window>>) -> Result<Self> {
+        let RenderContext {
+            gpu,
+            bind_group_layouts,
+            shader_compiler,
+            scene_uniform,
+            material_atlas,
+            ..
+        } = render_ctx.as_ref();
+
+        use wgpu::util::DeviceExt;
+
+        let cube_mesh = MeshBuilder::new().with_geometry(Cube::geometry()).build()?;
+        let mut cube_vbuf = vec![];
+        let mut cube_index = vec![];
+        cube_mesh.copy_to_mesh_bank(&mut cube_vbuf);
+        cube_mesh.copy_to_index_buffer(&mut cube_index);
+
+        let vbuf = gpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("DecalPass::Vertices"),
+                contents: cube_vbuf.as_slice(),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+
+        let ibuf = gpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("DecalPass::Indices"),
+                contents: bytemuck::cast_slice(cube_index.as_slice()),
+                usage: wgpu::BufferUsages::INDEX,
+            });
+
+        let params_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("DecalPass::Params"),
+            size: Params::SHADER_SIZE.into(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bgl = bind_group_layouts.get(
+            gpu,
+            "DecalPass::BindGroupLayout",
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        );
+
+        let layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("DecalPass::PipelineLayout"),
+                bind_group_layouts: &[
+                    scene_uniform.layout(),
+                    &bgl,
+                    material_atlas.read().unwrap().layout(),
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let shader = gpu.shader_from_module(
+            gpu.with_depth_defs(
+                shader_compiler
+                    .compilation_unit("./shaders/deferred/decals.wgsl")?
+                    .with_integer_def("MATERIAL_GROUP", 2),
+            )
+            .compile(&[])?,
+        );
+
+        // Reflectivity in alpha of the diffuse G-Buffer is left as it is.
+        let blended = |format, write_mask| {
+            Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask,
+            })
+        };
+
+        let pipeline = gpu
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("DecalPass::Pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[Mesh::pn_vertex_layout()],
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(wgpu::Face::Front),
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[
+                        blended(wgpu::TextureFormat::Rgba16Float, wgpu::ColorWrites::COLOR),
+                        blended(wgpu::TextureFormat::Rgba8Unorm, wgpu::ColorWrites::COLOR),
+                    ],
+                }),
+                multiview: None,
+            });
+
+        Ok(Self {
+            num_indices: cube_index.len() as u32,
+            render_ctx,
+            params_buf,
+            bgl,
+            pipeline,
+            vbuf,
+            ibuf,
+        })
+    }
+
+    pub fn render(&self, resources: &GraphResources, settings: &DecalSettings) -> Result<()> {
+        let RenderContext {
+            gpu,
+            gpu_scene,
+            scene_uniform,
+            material_atlas,
+            profiler,
+            ..
+        } = self.render_ctx.as_ref();
+
+        let scene = gpu_scene.read().unwrap();
+        let Some((decals, num_decals)) = scene.decals() else {
+            return Ok(());
+        };
+        let atlas = material_atlas.read().unwrap();
+
+        let params: Params = [na::Vector4::new(
+            settings.fade_angle.to_radians().cos(),
+            0.0,
+            0.0,
+            0.0,
+        )];
+        let size: u64 = Params::SHADER_SIZE.into();
+        let mut contents = UniformBuffer::new(Vec::with_capacity(size as usize));
+        contents.write(&params)?;
+        gpu.queue
+            .write_buffer(&self.params_buf, 0, contents.into_inner().as_slice());
+
+        let depth = gpu.depth_texture_view();
+        let bg = resources.bind_group(
+            gpu,
+            "DecalPass::BindGroup",
+            &self.bgl,
+            &[
+                Binding::Buffer(&self.params_buf),
+                Binding::Buffer(decals),
+                Binding::View(&depth),
+            ],
+        )?;
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("DecalPass::CommandEncoder"),
+            });
+
+        {
+            let target = |view| {
+                Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })
+            };
+
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("DecalPass::RenderPass"),
+                color_attachments: &[
+                    target(resources.view(G_NORMAL)?),
+                    target(resources.view(G_DIFFUSE)?),
+                ],
+                depth_stencil_attachment: None,
+                timestamp_writes: profiler.render_pass_writes("Decals"),
+                occlusion_query_set: None,
+            });
+
+            rpass.set_pipeline(&self.pipeline);
+            rpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
+            rpass.set_bind_group(1, &bg, &[]);
+            rpass.set_bind_group(2, atlas.bind_group(), &[]);
+            rpass.set_vertex_buffer(0, self.vbuf.slice(..));
+            rpass.set_index_buffer(self.ibuf.slice(..), wgpu::IndexFormat::Uint32);
+            rpass.draw_indexed(0..self.num_indices, 0, 0..num_decals as u32);
+        }
+
+        gpu.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
+
+impl RenderPass for DecalPass<'_> {
+    fn name(&self) -> &'static str {
+        "DecalPass"
+    }
+
+    fn enabled(&self, ctx: &FrameContext) -> bool {
+        ctx.settings.pipeline_type == PipelineType::Deferred && ctx.settings.decals.enabled
+    }
+
+    // Blends into G-Buffers in place, reading them keeps it after `GeometryPass`.
+    fn io(&self) -> PassIo {
+        PassIo::default().read(G_NORMAL).read(G_DIFFUSE)
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        Self::render(self, &ctx.resources, &ctx.settings.decals)
+    }
+}
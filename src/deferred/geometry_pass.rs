@@ -1,171 +1,88 @@
 use std::sync::Arc;
 
 use anyhow::Result;
+use nalgebra as na;
 
 use crate::{
-    gpu::Gpu,
-    material::MaterialAtlas,
-    mesh::{Mesh, MeshVertexArrayType},
+    gpu::{Gpu, GpuMat4},
+    material::PhongVariant,
+    mesh::MeshVertexArrayType,
+    pipeline_cache::{PipelineCache, RenderPipelineDesc},
     render_context::RenderContext,
-    scene::Instance,
-    scene_uniform::SceneUniform,
-    shader_compiler::ShaderCompiler,
+    render_graph::{Binding, GraphResources, PassIo, TextureDesc},
+    render_pass::{FrameContext, RenderPass},
+    scene::{Instance, MODEL_INSTANCE_STRIDE},
+    settings::PipelineType,
+    shader_compiler::CompilationUnit,
+    shader_permutation::Variants,
 };
 
-pub struct GBuffers {
-    pub g_normal: wgpu::Texture,
-    pub g_diffuse: wgpu::Texture,
-    pub g_specular: wgpu::Texture,
-}
+pub const G_NORMAL: &str = "GeometryPass::Normal";
+pub const G_DIFFUSE: &str = "GeometryPass::Diffuse";
+pub const G_SPECULAR: &str = "GeometryPass::Specular";
+pub const G_EMISSIVE: &str = "GeometryPass::Emissive";
+pub const G_VELOCITY: &str = "GeometryPass::Velocity";
 
-struct Pipelines {
-    solid: wgpu::RenderPipeline,
-    textured: wgpu::RenderPipeline,
-    textured_normal: wgpu::RenderPipeline,
-}
+// Emission is float, so it can go past white. Alpha of the specular target
+// carries shininess, encoded by `gpubasics::deferred::gbuffer`.
+const TARGETS: [(&str, wgpu::TextureFormat); 5] = [
+    (G_NORMAL, wgpu::TextureFormat::Rgba16Float),
+    (G_DIFFUSE, wgpu::TextureFormat::Rgba8Unorm),
+    (G_SPECULAR, wgpu::TextureFormat::Rgba8Unorm),
+    (G_EMISSIVE, wgpu::TextureFormat::Rgba16Float),
+    (G_VELOCITY, wgpu::TextureFormat::Rg16Float),
+];
+
+type Pipelines = Variants<PhongVariant, Arc<wgpu::RenderPipeline>>;
 
 pub struct GeometryPass<'window> {
     render_ctx: Arc<RenderContext<'window>>,
-    g_buffers: GBuffers,
     pipelines: Pipelines,
+    prepassed_pipelines: Pipelines,
+    motion_bgl: Arc<wgpu::BindGroupLayout>,
+    gbuffer_bgl: Arc<wgpu::BindGroupLayout>,
+    previous_view_projection: GpuMat4,
+    // View projection of the frame before, `None` until something gets rendered.
+    last_view_projection: Option<na::Matrix4<f32>>,
 }
 
-impl GBuffers {
-    fn new(gpu: &Gpu) -> Self {
-        let viewport_size = gpu.viewport_size();
-
-        let t_normal = gpu.device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("GeometryPass::Normal"),
-            size: viewport_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba16Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
-
-        let t_diffuse = gpu.device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("GeometryPass::Diffuse"),
-            size: viewport_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
-
-        let t_specular = gpu.device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("GeometryPass::Specular"),
-            size: viewport_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
-
-        Self {
-            g_normal: t_normal,
-            g_diffuse: t_diffuse,
-            g_specular: t_specular,
-        }
-    }
-
-    fn color_target_spec() -> &'static [Option<wgpu::ColorTargetState>] {
-        &[
-            Some(wgpu::ColorTargetState {
-                format: wgpu::TextureFormat::Rgba16Float,
-                blend: None,
-                write_mask: wgpu::ColorWrites::ALL,
-            }),
-            Some(wgpu::ColorTargetState {
-                format: wgpu::TextureFormat::Rgba8Unorm,
-                blend: None,
-                write_mask: wgpu::ColorWrites::ALL,
-            }),
-            Some(wgpu::ColorTargetState {
-                format: wgpu::TextureFormat::Rgba8Unorm,
-                blend: None,
-                write_mask: wgpu::ColorWrites::ALL,
-            }),
-        ]
-    }
+fn color_target_spec() -> [Option<wgpu::ColorTargetState>; 5] {
+    TARGETS.map(|(_, format)| {
+        Some(wgpu::ColorTargetState {
+            format,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+        })
+    })
 }
 
-impl Pipelines {
-    pub fn new(
-        gpu: &Gpu,
-        shader_compiler: &ShaderCompiler,
-        material_atlas: &MaterialAtlas,
-        scene_uniform: &SceneUniform,
-    ) -> Result<Self> {
-        let solid_layout = gpu
-            .device
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("GeometryPass::SolidPipelineLayout"),
-                bind_group_layouts: &[scene_uniform.layout(), &material_atlas.layouts.phong_solid],
-                push_constant_ranges: &[],
-            });
-
-        let textured_layout = gpu
-            .device
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("GeometryPass::TexturedPipelineLayout"),
-                bind_group_layouts: &[
-                    scene_uniform.layout(),
-                    &material_atlas.layouts.phong_textured,
-                ],
-                push_constant_ranges: &[],
-            });
-
-        let textured_normal_layout =
-            gpu.device
-                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("GeometryPass::TexturedNormalPipelineLayout"),
-                    bind_group_layouts: &[
-                        scene_uniform.layout(),
-                        &material_atlas.layouts.phong_textured_normal,
-                    ],
-                    push_constant_ranges: &[],
-                });
-
-        let module = shader_compiler
-            .compilation_unit("./shaders/forward/geometry.wgsl")?
-            .with_def("GEOMETRY");
-
-        let solid_shader =
-            gpu.shader_from_module(module.compile(&["VERTEX_PN", "MATERIAL_PHONG_SOLID"])?);
-
-        let textured_shader =
-            gpu.shader_from_module(module.compile(&["VERTEX_PNUV", "MATERIAL_PHONG_TEXTURED"])?);
-
-        let textured_normal_shader = gpu.shader_from_module(module.compile(&[
-            "VERTEX_PNTBUV",
-            "MATERIAL_PHONG_TEXTURED",
-            "NORMAL_MAP",
-        ])?);
-
-        let solid_pipeline = gpu
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("GeometryPass::SolidPipeline"),
-                layout: Some(&solid_layout),
-                vertex: wgpu::VertexState {
-                    module: &solid_shader,
-                    entry_point: "vs_main",
-                    buffers: &[
-                        Mesh::pn_vertex_layout(),
-                        Instance::pn_model_instance_layout(),
-                    ],
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &solid_shader,
-                    entry_point: "fs_main",
-                    targets: GBuffers::color_target_spec(),
+/// Pipelines drawing over depth laid down by `DepthPrepass` only shade fragments
+/// which ended up visible, without writing depth again.
+fn create_pipelines(
+    gpu: &Gpu,
+    pipeline_cache: &PipelineCache,
+    bind_group_layouts: &[&wgpu::BindGroupLayout],
+    module: &CompilationUnit,
+    prepassed: bool,
+) -> Result<Pipelines> {
+    Variants::try_new(|variant @ PhongVariant(ty)| {
+        pipeline_cache.render_pipeline(
+            gpu,
+            &RenderPipelineDesc {
+                label: Some(match ty {
+                    MeshVertexArrayType::PN => "GeometryPass::SolidPipeline",
+                    MeshVertexArrayType::PNUV => "GeometryPass::TexturedPipeline",
+                    MeshVertexArrayType::PNTBUV => "GeometryPass::TexturedNormalPipeline",
                 }),
+                shader: &module.variant(variant),
+                variant_defs: &[],
+                constants: &[],
+                bind_group_layouts,
+                push_constant_ranges: &[],
+                vertex_entry: "vs_main",
+                vertex_buffers: &Instance::draw_buffer_layouts(ty),
+                fragment_entry: Some("fs_main"),
+                targets: &color_target_spec(),
                 primitive: wgpu::PrimitiveState {
                     topology: wgpu::PrimitiveTopology::TriangleList,
                     front_face: wgpu::FrontFace::Ccw,
@@ -174,121 +91,166 @@ impl Pipelines {
                 },
                 depth_stencil: Some(wgpu::DepthStencilState {
                     format: wgpu::TextureFormat::Depth32Float,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::LessEqual,
+                    depth_write_enabled: !prepassed,
+                    depth_compare: if prepassed {
+                        wgpu::CompareFunction::Equal
+                    } else {
+                        gpu.depth_compare(wgpu::CompareFunction::LessEqual)
+                    },
                     stencil: Default::default(),
                     bias: Default::default(),
                 }),
                 multisample: wgpu::MultisampleState::default(),
-                multiview: None,
-            });
-
-        let textured_pipeline =
-            gpu.device
-                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                    label: Some("GeometryPass::TexturedPipeline"),
-                    layout: Some(&textured_layout),
-                    vertex: wgpu::VertexState {
-                        module: &textured_shader,
-                        entry_point: "vs_main",
-                        buffers: &[
-                            Mesh::pnuv_vertex_layout(),
-                            Instance::pnuv_model_instance_layout(),
-                        ],
-                    },
-                    fragment: Some(wgpu::FragmentState {
-                        module: &textured_shader,
-                        entry_point: "fs_main",
-                        targets: GBuffers::color_target_spec(),
-                    }),
-                    primitive: wgpu::PrimitiveState {
-                        topology: wgpu::PrimitiveTopology::TriangleList,
-                        front_face: wgpu::FrontFace::Ccw,
-                        cull_mode: Some(wgpu::Face::Back),
-                        ..Default::default()
-                    },
-                    depth_stencil: Some(wgpu::DepthStencilState {
-                        format: wgpu::TextureFormat::Depth32Float,
-                        depth_write_enabled: true,
-                        depth_compare: wgpu::CompareFunction::LessEqual,
-                        stencil: Default::default(),
-                        bias: Default::default(),
-                    }),
-                    multisample: wgpu::MultisampleState::default(),
-                    multiview: None,
-                });
-
-        let textured_normal_pipeline =
-            gpu.device
-                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                    label: Some("GeometryPass::TexturedNormalPipeline"),
-                    layout: Some(&textured_normal_layout),
-                    vertex: wgpu::VertexState {
-                        module: &textured_normal_shader,
-                        entry_point: "vs_main",
-                        buffers: &[
-                            Mesh::pntbuv_vertex_layout(),
-                            Instance::pntbuv_model_instance_layout(),
-                        ],
-                    },
-                    fragment: Some(wgpu::FragmentState {
-                        module: &textured_normal_shader,
-                        entry_point: "fs_main",
-                        targets: GBuffers::color_target_spec(),
-                    }),
-                    primitive: wgpu::PrimitiveState {
-                        topology: wgpu::PrimitiveTopology::TriangleList,
-                        front_face: wgpu::FrontFace::Ccw,
-                        cull_mode: Some(wgpu::Face::Back),
-                        ..Default::default()
-                    },
-                    depth_stencil: Some(wgpu::DepthStencilState {
-                        format: wgpu::TextureFormat::Depth32Float,
-                        depth_write_enabled: true,
-                        depth_compare: wgpu::CompareFunction::LessEqual,
-                        stencil: Default::default(),
-                        bias: Default::default(),
-                    }),
-                    multisample: wgpu::MultisampleState::default(),
-                    multiview: None,
-                });
-
-        Ok(Self {
-            solid: solid_pipeline,
-            textured: textured_pipeline,
-            textured_normal: textured_normal_pipeline,
-        })
-    }
+            },
+        )
+    })
 }
 
 impl<'window> GeometryPass<'window> {
     pub fn new(render_ctx: Arc<RenderContext<'window>>) -> Result<Self> {
         let RenderContext {
             gpu,
+            bind_group_layouts,
             shader_compiler,
             scene_uniform,
             material_atlas,
+            pipeline_cache,
             ..
         } = render_ctx.as_ref();
 
-        let g_buffers = GBuffers::new(gpu);
-        let pipelines = Pipelines::new(gpu, shader_compiler, material_atlas, scene_uniform)?;
+        let buffer = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        // Transforms of the previous frame, for motion vectors.
+        let motion_bgl = bind_group_layouts.get(
+            gpu,
+            "GeometryPass::MotionBindGroupLayout",
+            &[
+                buffer(0, wgpu::BufferBindingType::Uniform),
+                buffer(1, wgpu::BufferBindingType::Storage { read_only: true }),
+            ],
+        );
+
+        let gbuffer_bgl = bind_group_layouts.gbuffer(gpu);
+
+        let module = shader_compiler
+            .compilation_unit("./shaders/forward/geometry.wgsl")?
+            .with_def("GEOMETRY")
+            .with_integer_def("MATERIAL_GROUP", 1)
+            .with_integer_def(
+                "INSTANCE_STRIDE_FLOATS",
+                (MODEL_INSTANCE_STRIDE / std::mem::size_of::<f32>()) as u32,
+            );
+
+        let atlas = material_atlas.read().unwrap();
+        let bind_group_layouts = [scene_uniform.layout(), atlas.layout(), &motion_bgl];
+        let pipelines = create_pipelines(gpu, pipeline_cache, &bind_group_layouts, &module, false)?;
+        let prepassed_pipelines =
+            create_pipelines(gpu, pipeline_cache, &bind_group_layouts, &module, true)?;
+        drop(atlas);
 
         Ok(Self {
-            render_ctx,
-            g_buffers,
             pipelines,
+            prepassed_pipelines,
+            previous_view_projection: GpuMat4::new(na::Matrix4::identity(), &gpu.device)?,
+            last_view_projection: None,
+            motion_bgl,
+            gbuffer_bgl,
+            render_ctx,
         })
     }
 
-    pub fn render(&self) -> &GBuffers {
+    /// Entries of the G-Buffer bind group, see `BindGroupLayouts::gbuffer`. Targets
+    /// lighting reads come in the order of `TARGETS`, followed by depth.
+    pub(crate) fn gbuffer_layout_entries() -> Vec<wgpu::BindGroupLayoutEntry> {
+        let texture = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let color = wgpu::TextureSampleType::Float { filterable: false };
+
+        vec![
+            texture(0, color),
+            texture(1, color),
+            texture(2, color),
+            texture(3, color),
+            texture(4, wgpu::TextureSampleType::Depth),
+        ]
+    }
+
+    // Bound by passes reading the G-Buffer, instead of each of them binding its textures.
+    fn gbuffer_bind_group(&self, resources: &GraphResources) -> Result<wgpu::BindGroup> {
+        let gpu = &self.render_ctx.gpu;
+        let depth = gpu.depth_texture_view();
+
+        resources.bind_group(
+            gpu,
+            "GeometryPass::GBufferBindGroup",
+            &self.gbuffer_bgl,
+            &[
+                Binding::Resource(G_NORMAL),
+                Binding::Resource(G_DIFFUSE),
+                Binding::Resource(G_SPECULAR),
+                Binding::Resource(G_EMISSIVE),
+                Binding::View(&depth),
+            ],
+        )
+    }
+
+    /// With `depth_prepass`, depth of the scene is already there and kept.
+    /// `view_projection` of this frame is what motion vectors of the next one are measured from.
+    pub fn render(
+        &mut self,
+        resources: &GraphResources,
+        depth_prepass: bool,
+        view_projection: na::Matrix4<f32>,
+    ) -> Result<()> {
         let RenderContext {
             gpu,
-            gpu_scene: scene,
+            gpu_scene,
             scene_uniform,
-            material_atlas: atlas,
+            material_atlas,
+            profiler,
             ..
         } = self.render_ctx.as_ref();
+        let scene = gpu_scene.read().unwrap();
+        let atlas = material_atlas.read().unwrap();
+
+        let previous = self
+            .last_view_projection
+            .replace(view_projection)
+            .unwrap_or(view_projection);
+        self.previous_view_projection.update(&gpu.queue, previous)?;
+
+        // Without instances there is nothing to draw, nor a previous instance buffer.
+        let motion_bg = scene
+            .previous_instance_buffer()
+            .map(|previous_instances| {
+                resources.bind_group(
+                    gpu,
+                    "GeometryPass::MotionBindGroup",
+                    &self.motion_bgl,
+                    &[
+                        Binding::Buffer(self.previous_view_projection.buffer()),
+                        Binding::Buffer(previous_instances),
+                    ],
+                )
+            })
+            .transpose()?;
 
         let mut encoder = gpu
             .device
@@ -296,22 +258,18 @@ impl<'window> GeometryPass<'window> {
                 label: Some("GeometryPass::CommandEncoder"),
             });
 
-        let tv_normal = self
-            .g_buffers
-            .g_normal
-            .create_view(&wgpu::TextureViewDescriptor::default());
-
-        let tv_diffuse = self
-            .g_buffers
-            .g_diffuse
-            .create_view(&wgpu::TextureViewDescriptor::default());
-
-        let tv_specular = self
-            .g_buffers
-            .g_specular
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let tv_normal = resources.view(G_NORMAL)?;
+        let tv_diffuse = resources.view(G_DIFFUSE)?;
+        let tv_specular = resources.view(G_SPECULAR)?;
+        let tv_emissive = resources.view(G_EMISSIVE)?;
+        let tv_velocity = resources.view(G_VELOCITY)?;
 
         let tv_depth = gpu.depth_texture_view();
+        let pipelines = if depth_prepass {
+            &self.prepassed_pipelines
+        } else {
+            &self.pipelines
+        };
 
         {
             let mut rpass: wgpu::RenderPass<'_> =
@@ -319,7 +277,23 @@ impl<'window> GeometryPass<'window> {
                     label: Some("GeometryPass::RenderPass"),
                     color_attachments: &[
                         Some(wgpu::RenderPassColorAttachment {
-                            view: &tv_normal,
+                            view: tv_normal,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                                store: wgpu::StoreOp::Store,
+                            },
+                        }),
+                        Some(wgpu::RenderPassColorAttachment {
+                            view: tv_diffuse,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                                store: wgpu::StoreOp::Store,
+                            },
+                        }),
+                        Some(wgpu::RenderPassColorAttachment {
+                            view: tv_specular,
                             resolve_target: None,
                             ops: wgpu::Operations {
                                 load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
@@ -327,7 +301,7 @@ impl<'window> GeometryPass<'window> {
                             },
                         }),
                         Some(wgpu::RenderPassColorAttachment {
-                            view: &tv_diffuse,
+                            view: tv_emissive,
                             resolve_target: None,
                             ops: wgpu::Operations {
                                 load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
@@ -335,7 +309,7 @@ impl<'window> GeometryPass<'window> {
                             },
                         }),
                         Some(wgpu::RenderPassColorAttachment {
-                            view: &tv_specular,
+                            view: tv_velocity,
                             resolve_target: None,
                             ops: wgpu::Operations {
                                 load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
@@ -346,60 +320,97 @@ impl<'window> GeometryPass<'window> {
                     depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                         view: &tv_depth,
                         depth_ops: Some(wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(1.0),
+                            load: if depth_prepass {
+                                wgpu::LoadOp::Load
+                            } else {
+                                wgpu::LoadOp::Clear(gpu.far_depth())
+                            },
                             store: wgpu::StoreOp::Store,
                         }),
                         stencil_ops: None,
                     }),
                     occlusion_query_set: None,
-                    timestamp_writes: None,
+                    timestamp_writes: profiler.render_pass_writes("Geometry"),
                 });
 
-            for draw_call in scene.draw_calls() {
-                match draw_call.vertex_array_type {
-                    MeshVertexArrayType::PNUV => rpass.set_pipeline(&self.pipelines.textured),
-                    MeshVertexArrayType::PNTBUV => {
-                        rpass.set_pipeline(&self.pipelines.textured_normal)
-                    }
-                    MeshVertexArrayType::PN => rpass.set_pipeline(&self.pipelines.solid),
-                };
+            rpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
+            rpass.set_bind_group(1, atlas.bind_group(), &[]);
+            if let Some(motion_bg) = &motion_bg {
+                rpass.set_bind_group(2, motion_bg, &[]);
+            }
 
-                rpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
-                rpass.set_bind_group(1, atlas.bind_group(draw_call.material_id), &[]);
+            for batch in scene.draw_batches() {
+                let first = &batch[0];
+                rpass.set_pipeline(&pipelines[PhongVariant(first.vertex_array_type)]);
 
                 rpass.set_vertex_buffer(
                     0,
                     scene
-                        .vertex_buffer_by_type(draw_call.vertex_array_type)
+                        .vertex_buffer_by_type(first.vertex_array_type)
                         .slice(..),
                 );
                 rpass.set_vertex_buffer(
                     1,
-                    scene
-                        .instance_buffer_by_type(draw_call.instance_type)
-                        .slice(..),
+                    scene.instance_buffer_by_type(first.instance_type).slice(..),
                 );
 
-                if draw_call.indexed {
+                if first.indexed {
                     rpass.set_index_buffer(
                         scene.index_buffer().slice(..),
                         wgpu::IndexFormat::Uint32,
                     );
+                }
 
-                    rpass.draw_indexed_indirect(
-                        scene.indexed_draw_buffer(),
-                        draw_call.draw_buffer_offset,
-                    );
-                } else {
-                    rpass.draw_indirect(
-                        scene.non_indexed_draw_buffer(),
-                        draw_call.draw_buffer_offset,
-                    );
+                for draw_call in batch {
+                    if draw_call.indexed {
+                        rpass.draw_indexed_indirect(
+                            scene.indexed_draw_buffer(),
+                            draw_call.draw_buffer_offset,
+                        );
+                    } else {
+                        rpass.draw_indirect(
+                            scene.non_indexed_draw_buffer(),
+                            draw_call.draw_buffer_offset,
+                        );
+                    }
                 }
             }
         }
 
+        scene.copy_to_previous_instances(&mut encoder);
         gpu.queue.submit(Some(encoder.finish()));
-        &self.g_buffers
+
+        Ok(())
+    }
+}
+
+impl RenderPass for GeometryPass<'_> {
+    fn name(&self) -> &'static str {
+        "GeometryPass"
+    }
+
+    fn enabled(&self, ctx: &FrameContext) -> bool {
+        ctx.settings.pipeline_type == PipelineType::Deferred
+    }
+
+    fn io(&self) -> PassIo {
+        TARGETS
+            .into_iter()
+            .fold(PassIo::default(), |io, (name, format)| {
+                io.write(name, TextureDesc::target(format))
+            })
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        let view_projection = ctx.projection * ctx.camera.look_at_matrix();
+        Self::render(
+            self,
+            &ctx.resources,
+            ctx.settings.depth_prepass_enabled,
+            view_projection,
+        )?;
+        ctx.gbuffer = Some(self.gbuffer_bind_group(&ctx.resources)?);
+
+        Ok(())
     }
 }
@@ -1,9 +1,11 @@
 mod debug_pass;
+mod decal_pass;
 mod geometry_pass;
 mod phong_pass;
 mod ssao_pass;
 
 pub use debug_pass::{DebugPass, DeferredDebug};
-pub use geometry_pass::GeometryPass;
+pub use decal_pass::DecalPass;
+pub use geometry_pass::{GeometryPass, G_DIFFUSE, G_EMISSIVE, G_NORMAL, G_SPECULAR, G_VELOCITY};
 pub use phong_pass::PhongPass;
-pub use ssao_pass::SsaoPass;
+pub use ssao_pass::{SsaoPass, AMBIENT_OCCLUSION};
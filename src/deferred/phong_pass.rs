@@ -1,187 +1,219 @@
 use std::sync::Arc;
 
-use crate::render_context::RenderContext;
-use anyhow::Result;
-use encase::{ShaderType, StorageBuffer};
+use crate::{
+    compute::{dispatch, Kernel},
+    gpu::Gpu,
+    mesh::{Mesh, MeshBuilder},
+    render_context::RenderContext,
+    render_graph::{Binding, GraphResources, PassIo},
+    render_pass::{FrameContext, RenderPass},
+    settings::{PipelineType, ShadowFiltering},
+    shader_permutation::Variants,
+    shapes::UVSphere,
+};
+use anyhow::{Context, Result};
 
-use super::geometry_pass::GBuffers;
+use super::{
+    geometry_pass::{G_DIFFUSE, G_EMISSIVE, G_NORMAL, G_SPECULAR},
+    ssao_pass::AMBIENT_OCCLUSION,
+};
 
+const TILE_SIZE: u32 = 16;
+// One invocation per pixel of a tile.
+const KERNEL: Kernel = Kernel::new([TILE_SIZE, TILE_SIZE, 1]);
+// Lights above the limit are dropped from the tile.
+const MAX_TILE_LIGHTS: u32 = 256;
+
+// Lights G-Buffers in a compute pass working on screen tiles. Each tile culls
+// spot lights against its depth bounds before shading its pixels. Point lights
+// are then added by drawing spheres covering their range with additive blending.
 pub struct PhongPass<'window> {
     render_ctx: Arc<RenderContext<'window>>,
-    pipeline: wgpu::RenderPipeline,
-    light_buf: wgpu::Buffer,
-    g_sampler: wgpu::Sampler,
+    pipelines: Variants<ShadowFiltering, wgpu::ComputePipeline>,
+    volume_pipeline: wgpu::RenderPipeline,
+    volume_vbuf: wgpu::Buffer,
+    volume_ibuf: wgpu::Buffer,
+    volume_index_count: u32,
     output_tex: wgpu::Texture,
-    fill_bgl: wgpu::BindGroupLayout,
+    // Shared with forward lighting, see `LightBuffers::bind_group`.
+    lights_bg: Arc<wgpu::BindGroup>,
+    output_bgl: Arc<wgpu::BindGroupLayout>,
+    occlusion_bgl: Arc<wgpu::BindGroupLayout>,
 }
 
 impl<'window> PhongPass<'window> {
     pub fn new(
         render_ctx: Arc<RenderContext<'window>>,
-        shadow_bgl: &wgpu::BindGroupLayout,
+        lights_bg: Arc<wgpu::BindGroup>,
     ) -> Result<Self> {
         let RenderContext {
             gpu,
+            bind_group_layouts,
             shader_compiler,
             scene_uniform,
-            light_scene: lights,
             ..
         } = render_ctx.as_ref();
+        let shadow_bgl = bind_group_layouts.shadow(gpu);
+        let lights_bgl = bind_group_layouts.lights(gpu);
+        let gbuffer_bgl = bind_group_layouts.gbuffer(gpu);
 
-        let fill_bgl = gpu
-            .device
-            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: true },
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
-                        count: None,
-                    },
-                    // g_Normal
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    // g_Diffuse
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 3,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    // g_Specular
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 4,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    // Depth texture
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 5,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Depth,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    // Ssao tex
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 6,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                ],
-            });
+        let occlusion_entry = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
 
-        let gpu_lights = lights.into_gpu();
-        let gpu_lights_size: u64 = gpu_lights.size().into();
-        let mut light_contents = StorageBuffer::new(Vec::with_capacity(gpu_lights_size as usize));
-        light_contents.write(&gpu_lights)?;
+        // Ambient occlusion and the lit scene written by the compute pass.
+        let output_bgl = bind_group_layouts.get(
+            gpu,
+            "PhongPass::OutputBindGroupLayout",
+            &[
+                occlusion_entry,
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba16Float,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ],
+        );
+        // Point light volumes draw into the output, so they only bind the occlusion.
+        let occlusion_bgl = bind_group_layouts.get(
+            gpu,
+            "PhongPass::OcclusionBindGroupLayout",
+            &[occlusion_entry],
+        );
 
-        let output = gpu.device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: gpu.viewport_size(),
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba16Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
+        let output = Self::create_output(gpu);
 
         use wgpu::util::DeviceExt;
 
-        let light_buf = gpu
+        let module = KERNEL
+            .with_defs(gpu.with_depth_defs(
+                shader_compiler.compilation_unit("./shaders/deferred/phong.wgsl")?,
+            ))
+            .with_def("DEFERRED")
+            .with_def("SHADOW_MAP")
+            .with_integer_def("GBUFFER_GROUP", 3)
+            .with_integer_def("TILE_SIZE", TILE_SIZE)
+            .with_integer_def("MAX_TILE_LIGHTS", MAX_TILE_LIGHTS);
+
+        let fill_pipeline_layout =
+            gpu.device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &[
+                        scene_uniform.layout(),
+                        &lights_bgl,
+                        &shadow_bgl,
+                        &gbuffer_bgl,
+                        &output_bgl,
+                    ],
+                    push_constant_ranges: &[],
+                });
+
+        let pipelines = gpu
+            .shader_variants::<ShadowFiltering>(&module)?
+            .map(|_, fill_shader| {
+                gpu.device
+                    .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("PhongPass::ComputePipeline"),
+                        layout: Some(&fill_pipeline_layout),
+                        module: &fill_shader,
+                        entry_point: "tiled_lighting",
+                    })
+            });
+
+        let sphere = MeshBuilder::new()
+            .with_geometry(UVSphere::geometry(16, 12))
+            .build()?;
+        let mut sphere_vertices = vec![];
+        let mut sphere_indices = vec![];
+        sphere.copy_to_mesh_bank(&mut sphere_vertices);
+        sphere.copy_to_index_buffer(&mut sphere_indices);
+
+        let volume_vbuf = gpu
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: None,
-                contents: light_contents.into_inner().as_slice(),
-                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                label: Some("PhongPass::VolumeVertexBuffer"),
+                contents: sphere_vertices.as_slice(),
+                usage: wgpu::BufferUsages::VERTEX,
             });
 
-        let g_sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
-            label: None,
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
-
-        let module = shader_compiler
-            .compilation_unit("./shaders/deferred/phong.wgsl")?
-            .with_def("DEFERRED")
-            .with_def("SHADOW_MAP")
-            .compile(&[])?;
+        let volume_ibuf = gpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("PhongPass::VolumeIndexBuffer"),
+                contents: bytemuck::cast_slice(&sphere_indices),
+                usage: wgpu::BufferUsages::INDEX,
+            });
 
-        let fill_shader = gpu.shader_from_module(module);
+        let volume_shader = gpu.shader_from_module(
+            gpu.with_depth_defs(
+                shader_compiler.compilation_unit("./shaders/deferred/point_lights.wgsl")?,
+            )
+            .with_def("DEFERRED")
+            .with_integer_def("GBUFFER_GROUP", 3)
+            .compile(&[])?,
+        );
 
-        let fill_pipeline_layout =
+        let volume_pipeline_layout =
             gpu.device
                 .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: None,
-                    bind_group_layouts: &[scene_uniform.layout(), &fill_bgl, shadow_bgl],
+                    label: Some("PhongPass::VolumePipelineLayout"),
+                    bind_group_layouts: &[
+                        scene_uniform.layout(),
+                        &lights_bgl,
+                        &shadow_bgl,
+                        &gbuffer_bgl,
+                        &occlusion_bgl,
+                    ],
                     push_constant_ranges: &[],
                 });
 
-        let fill_pipeline = gpu
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+
+        // Only back faces are drawn, so every pixel is lit once,
+        // even with the camera inside the volume.
+        let volume_pipeline = gpu
             .device
             .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                layout: Some(&fill_pipeline_layout),
+                label: Some("PhongPass::VolumePipeline"),
+                layout: Some(&volume_pipeline_layout),
                 vertex: wgpu::VertexState {
-                    module: &fill_shader,
+                    module: &volume_shader,
                     entry_point: "vs_main",
-                    buffers: &[],
+                    buffers: &[Mesh::pn_vertex_layout()],
                 },
                 fragment: Some(wgpu::FragmentState {
-                    module: &fill_shader,
+                    module: &volume_shader,
                     entry_point: "fs_main",
                     targets: &[Some(wgpu::ColorTargetState {
                         format: wgpu::TextureFormat::Rgba16Float,
-                        blend: Some(wgpu::BlendState::REPLACE),
+                        blend: Some(wgpu::BlendState {
+                            color: additive,
+                            alpha: additive,
+                        }),
                         write_mask: wgpu::ColorWrites::ALL,
                     })],
                 }),
                 depth_stencil: None,
                 primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    cull_mode: Some(wgpu::Face::Front),
                     ..Default::default()
                 },
                 multisample: wgpu::MultisampleState::default(),
@@ -190,11 +222,30 @@ impl<'window> PhongPass<'window> {
 
         Ok(Self {
             render_ctx,
-            fill_bgl,
-            light_buf,
-            g_sampler,
-            pipeline: fill_pipeline,
+            lights_bg,
+            pipelines,
+            volume_pipeline,
+            volume_vbuf,
+            volume_ibuf,
+            volume_index_count: sphere_indices.len() as u32,
             output_tex: output,
+            output_bgl,
+            occlusion_bgl,
+        })
+    }
+
+    fn create_output(gpu: &Gpu) -> wgpu::Texture {
+        gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: gpu.viewport_size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
         })
     }
 
@@ -204,85 +255,138 @@ impl<'window> PhongPass<'window> {
 
     pub fn render(
         &self,
-        g_buffers: &GBuffers,
+        resources: &GraphResources,
+        gbuffer_bg: &wgpu::BindGroup,
         spass_bg: &wgpu::BindGroup,
-        ssao_tex: &wgpu::TextureView,
-    ) {
+        shadow_filtering: ShadowFiltering,
+    ) -> Result<()> {
         let RenderContext {
-            gpu, scene_uniform, ..
+            gpu,
+            scene_uniform,
+            profiler,
+            light_scene,
+            ..
         } = self.render_ctx.as_ref();
 
         let mut encoder = gpu
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
 
-        let (g_normal, g_diffuse, g_specular) = (
-            g_buffers.g_normal.create_view(&Default::default()),
-            g_buffers.g_diffuse.create_view(&Default::default()),
-            g_buffers.g_specular.create_view(&Default::default()),
-        );
+        let output_tv = self.output_tex.create_view(&Default::default());
 
-        let fill_bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &self.fill_bgl,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: self.light_buf.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&self.g_sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::TextureView(&g_normal),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: wgpu::BindingResource::TextureView(&g_diffuse),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 4,
-                    resource: wgpu::BindingResource::TextureView(&g_specular),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 5,
-                    resource: wgpu::BindingResource::TextureView(&gpu.depth_texture_view()),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 6,
-                    resource: wgpu::BindingResource::TextureView(ssao_tex),
-                },
+        let output_bg = resources.bind_group(
+            gpu,
+            "PhongPass::OutputBindGroup",
+            &self.output_bgl,
+            &[
+                Binding::Resource(AMBIENT_OCCLUSION),
+                Binding::View(&output_tv),
             ],
-        });
-
-        let output_tv = self.output_tex.create_view(&Default::default());
+        )?;
+        let occlusion_bg = resources.bind_group(
+            gpu,
+            "PhongPass::OcclusionBindGroup",
+            &self.occlusion_bgl,
+            &[Binding::Resource(AMBIENT_OCCLUSION)],
+        )?;
 
         {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("PhongPass::ComputePass"),
+                timestamp_writes: profiler.compute_pass_writes("Lighting"),
+            });
+
+            cpass.set_pipeline(&self.pipelines[shadow_filtering]);
+            cpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
+            cpass.set_bind_group(1, &self.lights_bg, &[]);
+            cpass.set_bind_group(2, spass_bg, &[]);
+            cpass.set_bind_group(3, gbuffer_bg, &[]);
+            cpass.set_bind_group(4, &output_bg, &[]);
+
+            let size = self.output_tex.size();
+            dispatch(&mut cpass, &KERNEL, [size.width, size.height, 1]);
+        }
+
+        let num_point_lights = light_scene.read().unwrap().point.len() as u32;
+        if num_point_lights > 0 {
             let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
+                label: Some("PhongPass::PointLightVolumes"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                     view: &output_tv,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        load: wgpu::LoadOp::Load,
                         store: wgpu::StoreOp::Store,
                     },
                 })],
                 depth_stencil_attachment: None,
-                timestamp_writes: None,
+                timestamp_writes: profiler.render_pass_writes("Lighting"),
                 occlusion_query_set: None,
             });
 
-            rpass.set_pipeline(&self.pipeline);
+            rpass.set_pipeline(&self.volume_pipeline);
             rpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
-            rpass.set_bind_group(1, &fill_bg, &[]);
+            rpass.set_bind_group(1, &self.lights_bg, &[]);
             rpass.set_bind_group(2, spass_bg, &[]);
-
-            rpass.draw(0..4, 0..1);
+            rpass.set_bind_group(3, gbuffer_bg, &[]);
+            rpass.set_bind_group(4, &occlusion_bg, &[]);
+            rpass.set_vertex_buffer(0, self.volume_vbuf.slice(..));
+            rpass.set_index_buffer(self.volume_ibuf.slice(..), wgpu::IndexFormat::Uint32);
+            rpass.draw_indexed(0..self.volume_index_count, 0, 0..num_point_lights);
         }
 
         gpu.queue.submit(Some(encoder.finish()));
+
+        Ok(())
+    }
+}
+
+impl RenderPass for PhongPass<'_> {
+    fn name(&self) -> &'static str {
+        "deferred::PhongPass"
+    }
+
+    fn enabled(&self, ctx: &FrameContext) -> bool {
+        ctx.settings.pipeline_type == PipelineType::Deferred
+    }
+
+    fn io(&self) -> PassIo {
+        [
+            G_NORMAL,
+            G_DIFFUSE,
+            G_SPECULAR,
+            G_EMISSIVE,
+            AMBIENT_OCCLUSION,
+        ]
+        .into_iter()
+        .fold(PassIo::default(), PassIo::read)
+    }
+
+    fn resize(&mut self, _size: (u32, u32)) -> Result<()> {
+        self.output_tex = Self::create_output(&self.render_ctx.gpu);
+
+        Ok(())
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        let shadows = ctx
+            .shadows
+            .as_deref()
+            .context("shadows have to be rendered before lighting")?;
+        let gbuffer = ctx
+            .gbuffer
+            .as_ref()
+            .context("G-Buffer has to be rendered before lighting")?;
+
+        Self::render(
+            self,
+            &ctx.resources,
+            gbuffer,
+            shadows,
+            ctx.settings.shadows.filtering,
+        )?;
+        ctx.scene_color = Some((self.output_tex_view(), true));
+
+        Ok(())
     }
 }
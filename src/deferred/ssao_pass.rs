@@ -1,31 +1,66 @@
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use encase::{ShaderType, UniformBuffer};
 use nalgebra as na;
 use rand::distributions::Uniform;
 
 use crate::{
-    compute::BlurPass, gpu::Gpu, render_context::RenderContext, scene_uniform::SceneUniform,
+    compute::{BlurPass, GaussianKernel},
+    gpu::Gpu,
+    pipeline_cache::RenderPipelineDesc,
+    render_context::RenderContext,
+    render_graph::{Binding, GraphResources, PassIo},
+    render_pass::{FrameContext, RenderPass},
+    settings::{AoBackend, PipelineType, SsaoSettings},
+    shader_compiler::CompilationUnit,
+    texture_pool::{TargetSize, TransientDesc},
 };
 
-use super::geometry_pass::GBuffers;
+use super::geometry_pass::{G_NORMAL, G_VELOCITY};
+
+/// Blurred occlusion at the viewport resolution, exported from a texture of the pass.
+pub const AMBIENT_OCCLUSION: &str = "SsaoPass::AmbientOcclusion";
 
 pub struct SsaoPass<'window> {
     render_ctx: Arc<RenderContext<'window>>,
-    ssao_bgl: wgpu::BindGroupLayout,
+    ssao_bgl: Arc<wgpu::BindGroupLayout>,
     samples_buf: wgpu::Buffer,
-    output_tex: wgpu::Texture,
+    params_buf: wgpu::Buffer,
+    // Viewport dimensions are divided by it for the output texture.
+    divisor: u32,
+    // Output upsampled to the viewport, used when it's computed at a lower resolution.
+    upsampled_tex: wgpu::Texture,
+    upsample_bgl: Arc<wgpu::BindGroupLayout>,
+    upsample_pipeline: wgpu::RenderPipeline,
+    // Occlusion accumulated over frames at the output resolution, the last written one comes first.
+    history: [wgpu::Texture; 2],
+    // Cleared whenever the history doesn't hold occlusion of the previous frame.
+    history_valid: bool,
+    history_sampler: wgpu::Sampler,
+    temporal_bgl: Arc<wgpu::BindGroupLayout>,
+    temporal_params_buf: wgpu::Buffer,
+    temporal_pipeline: wgpu::RenderPipeline,
+    // Rotates the sample kernel, so accumulated frames don't repeat the same samples.
+    frame: u32,
     g_sampler: wgpu::Sampler,
     noise_sampler: wgpu::Sampler,
     noise_tex: wgpu::Texture,
     ssao_pipeline: wgpu::RenderPipeline,
+    gtao_module: CompilationUnit,
+    // Specialized for the slice count of settings, which is overridden in the shader.
+    gtao_pipeline: Arc<wgpu::RenderPipeline>,
+    gtao_slices: u32,
     blur_pass: BlurPass,
 }
 
-const NUM_SAMPLES: usize = 64;
+const NUM_SAMPLES: usize = SsaoPass::MAX_SAMPLES as usize;
 const NOISE_TEX_SIZE: usize = 16;
 const NOISE_TEX_DIM: usize = 4;
+// Turn of the kernel between frames, golden angle spreads them evenly.
+const GOLDEN_ANGLE: f32 = 2.399_963;
+// Occlusion as computed, before it's blurred and upsampled.
+const OUTPUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
 
 fn generate_samples() -> [na::Vector3<f32>; NUM_SAMPLES] {
     use rand::distributions::Distribution;
@@ -72,10 +107,34 @@ fn generate_noise() -> [na::Vector4<f32>; NOISE_TEX_SIZE] {
     result
 }
 
+impl SsaoPass<'_> {
+    /// Size of the sample kernel. Settings can only use a part of it.
+    pub const MAX_SAMPLES: u32 = 64;
+    pub const MAX_GTAO_SLICES: u32 = 16;
+}
+
+// Packs settings as `vec4(radius, bias, intensity, sample count)`, followed by `vec4(kernel rotation, 0, 0, 0)`.
+fn params_contents(settings: &SsaoSettings, rotation: f32) -> [f32; 8] {
+    [
+        settings.radius,
+        settings.bias,
+        settings.intensity,
+        settings.num_samples.min(SsaoPass::MAX_SAMPLES) as f32,
+        rotation,
+        0.0,
+        0.0,
+        0.0,
+    ]
+}
+
+// Format of the history, accumulating in 8 bits would get stuck a few steps away from the target.
+const HISTORY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R16Float;
+
 impl<'window> SsaoPass<'window> {
     pub fn new(render_ctx: Arc<RenderContext<'window>>) -> Result<Self> {
         let RenderContext {
             gpu,
+            bind_group_layouts,
             shader_compiler,
             scene_uniform,
             ..
@@ -124,6 +183,14 @@ impl<'window> SsaoPass<'window> {
                 usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             });
 
+        let params_buf = gpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("SsaoPass::ParamsBuffer"),
+                contents: bytemuck::cast_slice(&params_contents(&SsaoSettings::default(), 0.0)),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
         let g_sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
             label: Some("SsaoPass::GSampler"),
             address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -146,187 +213,446 @@ impl<'window> SsaoPass<'window> {
             ..Default::default()
         });
 
-        let output_tex = gpu.device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("SsaoPass::OutputTexture"),
-            size: gpu.viewport_size(),
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::R8Unorm,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-                | wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::COPY_SRC,
-            view_formats: &[],
+        // Reprojected positions fall between texels of the history.
+        let history_sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("SsaoPass::HistorySampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
         });
 
-        let ssao_bgl = gpu
-            .device
-            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("SsaoPass::SsaoBindGroupLayout"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 3,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
+        let temporal_params_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("SsaoPass::TemporalParamsBuffer"),
+            size: std::mem::size_of::<[f32; 4]>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let divisor = SsaoSettings::default().resolution.divisor();
+        let output_size = TargetSize::Viewport(divisor).resolve(gpu);
+        let upsampled_tex = Self::create_upsampled_texture(gpu);
+        let history = Self::create_history(gpu, output_size);
+
+        let gbuffer_bgl = bind_group_layouts.gbuffer(gpu);
+        let ssao_bgl = bind_group_layouts.get(
+            gpu,
+            "SsaoPass::SsaoBindGroupLayout",
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
                     },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 4,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
                     },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 5,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Depth,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
                     },
-                ],
-            });
+                    count: None,
+                },
+            ],
+        );
 
         let pipeline_layout = gpu
             .device
             .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("SsaoPass::PipelineLayout"),
-                bind_group_layouts: &[scene_uniform.layout(), &ssao_bgl],
+                bind_group_layouts: &[scene_uniform.layout(), &gbuffer_bgl, &ssao_bgl],
                 push_constant_ranges: &[],
             });
 
-        let module = shader_compiler
-            .compilation_unit("./shaders/deferred/ssao.wgsl")?
-            .with_integer_def("SSAO_SAMPLES_CNT", NUM_SAMPLES as u32)
-            .compile(&[])?;
-
-        let ssao_shader = gpu.shader_from_module(module);
+        let upsample_bgl = bind_group_layouts.get(
+            gpu,
+            "SsaoPass::UpsampleBindGroupLayout",
+            &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        );
 
-        let pipeline = gpu
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("SsaoPass::RenderPipeline"),
-                layout: Some(&pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &ssao_shader,
-                    entry_point: "vs_main",
-                    buffers: &[],
+        let temporal_bgl = bind_group_layouts.get(
+            gpu,
+            "SsaoPass::TemporalBindGroupLayout",
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
                 },
-                fragment: Some(wgpu::FragmentState {
-                    module: &ssao_shader,
-                    entry_point: "fs_main",
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: wgpu::TextureFormat::R8Unorm,
-                        blend: Some(wgpu::BlendState::REPLACE),
-                        write_mask: wgpu::ColorWrites::RED,
-                    })],
-                }),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleStrip,
-                    ..Default::default()
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
                 },
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState::default(),
-                multiview: None,
-            });
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        );
 
-        let blur_pass =
-            BlurPass::new(gpu, shader_compiler, output_tex.size(), output_tex.format())?;
+        let temporal_pipeline_layout =
+            gpu.device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("SsaoPass::TemporalPipelineLayout"),
+                    bind_group_layouts: &[&temporal_bgl],
+                    push_constant_ranges: &[],
+                });
+
+        let upsample_pipeline_layout =
+            gpu.device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("SsaoPass::UpsamplePipelineLayout"),
+                    bind_group_layouts: &[scene_uniform.layout(), &gbuffer_bgl, &upsample_bgl],
+                    push_constant_ranges: &[],
+                });
+
+        let create_pipeline = |label, path, layout, format| -> Result<wgpu::RenderPipeline> {
+            let module = shader_compiler
+                .compilation_unit(path)?
+                .with_integer_def("SSAO_SAMPLES_CNT", Self::MAX_SAMPLES)
+                .with_integer_def("GBUFFER_GROUP", 1)
+                .compile(&[])?;
+
+            let shader = gpu.shader_from_module(module);
+
+            Ok(gpu
+                .device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &[],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fs_main",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format,
+                            blend: Some(wgpu::BlendState::REPLACE),
+                            write_mask: wgpu::ColorWrites::RED,
+                        })],
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleStrip,
+                        ..Default::default()
+                    },
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                }))
+        };
+
+        let ssao_pipeline = create_pipeline(
+            "SsaoPass::RenderPipeline",
+            "./shaders/deferred/ssao.wgsl",
+            &pipeline_layout,
+            wgpu::TextureFormat::R8Unorm,
+        )?;
+        let gtao_module = shader_compiler
+            .compilation_unit("./shaders/deferred/gtao.wgsl")?
+            .with_integer_def("SSAO_SAMPLES_CNT", Self::MAX_SAMPLES)
+            .with_integer_def("GBUFFER_GROUP", 1);
+        let gtao_slices = SsaoSettings::default().gtao_slices;
+        let gtao_pipeline =
+            Self::create_gtao_pipeline(&render_ctx, &gtao_module, &ssao_bgl, gtao_slices)?;
+        let temporal_pipeline = create_pipeline(
+            "SsaoPass::TemporalRenderPipeline",
+            "./shaders/deferred/ssao_temporal.wgsl",
+            &temporal_pipeline_layout,
+            HISTORY_FORMAT,
+        )?;
+        let upsample_pipeline = create_pipeline(
+            "SsaoPass::UpsampleRenderPipeline",
+            "./shaders/deferred/ssao_upsample.wgsl",
+            &upsample_pipeline_layout,
+            wgpu::TextureFormat::R8Unorm,
+        )?;
+
+        let blur_pass = BlurPass::new(
+            gpu,
+            shader_compiler,
+            bind_group_layouts,
+            output_size,
+            OUTPUT_FORMAT,
+        )?;
 
         Ok(Self {
             render_ctx,
             ssao_bgl,
-            output_tex,
             samples_buf,
+            params_buf,
+            divisor,
+            upsampled_tex,
+            upsample_bgl,
+            upsample_pipeline,
+            history,
+            history_valid: false,
+            history_sampler,
+            temporal_bgl,
+            temporal_params_buf,
+            temporal_pipeline,
+            frame: 0,
             g_sampler,
             noise_sampler,
             noise_tex,
-            ssao_pipeline: pipeline,
+            ssao_pipeline,
+            gtao_module,
+            gtao_pipeline,
+            gtao_slices,
             blur_pass,
         })
     }
 
-    pub fn render(&self, g_buffers: &GBuffers) -> wgpu::TextureView {
+    fn create_gtao_pipeline(
+        render_ctx: &RenderContext,
+        module: &CompilationUnit,
+        ssao_bgl: &wgpu::BindGroupLayout,
+        slices: u32,
+    ) -> Result<Arc<wgpu::RenderPipeline>> {
+        let RenderContext {
+            gpu,
+            bind_group_layouts,
+            scene_uniform,
+            pipeline_cache,
+            ..
+        } = render_ctx;
+        let gbuffer_bgl = bind_group_layouts.gbuffer(gpu);
+
+        pipeline_cache.render_pipeline(
+            gpu,
+            &RenderPipelineDesc {
+                label: Some("SsaoPass::GtaoRenderPipeline"),
+                shader: module,
+                variant_defs: &[],
+                constants: &[("GTAO_SLICES", slices as f64)],
+                bind_group_layouts: &[scene_uniform.layout(), &gbuffer_bgl, ssao_bgl],
+                push_constant_ranges: &[],
+                vertex_entry: "vs_main",
+                vertex_buffers: &[],
+                fragment_entry: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: wgpu::TextureFormat::R8Unorm,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::RED,
+                })],
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+            },
+        )
+    }
+
+    fn create_texture(
+        gpu: &Gpu,
+        label: &str,
+        size: wgpu::Extent3d,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::Texture {
+        gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        })
+    }
+
+    fn create_upsampled_texture(gpu: &Gpu) -> wgpu::Texture {
+        Self::create_texture(
+            gpu,
+            "SsaoPass::UpsampledTexture",
+            gpu.viewport_size(),
+            wgpu::TextureFormat::R8Unorm,
+        )
+    }
+
+    fn create_history(gpu: &Gpu, size: wgpu::Extent3d) -> [wgpu::Texture; 2] {
+        std::array::from_fn(|_| {
+            Self::create_texture(gpu, "SsaoPass::HistoryTexture", size, HISTORY_FORMAT)
+        })
+    }
+
+    // The blur and the history follow the resolution of the output.
+    fn create_targets(&mut self) -> Result<()> {
         let RenderContext {
-            gpu, scene_uniform, ..
+            gpu,
+            shader_compiler,
+            bind_group_layouts,
+            ..
         } = self.render_ctx.as_ref();
 
+        let output_size = TargetSize::Viewport(self.divisor).resolve(gpu);
+        self.blur_pass = BlurPass::new(
+            gpu,
+            shader_compiler,
+            bind_group_layouts,
+            output_size,
+            OUTPUT_FORMAT,
+        )?;
+        self.history = Self::create_history(gpu, output_size);
+        self.history_valid = false;
+
+        Ok(())
+    }
+
+    pub fn render(
+        &mut self,
+        resources: &GraphResources,
+        gbuffer_bg: &wgpu::BindGroup,
+        settings: &SsaoSettings,
+    ) -> Result<wgpu::TextureView> {
+        let divisor = settings.resolution.divisor();
+        if divisor != self.divisor {
+            self.divisor = divisor;
+            self.create_targets()?;
+        }
+
+        if settings.backend == AoBackend::Gtao && settings.gtao_slices != self.gtao_slices {
+            self.gtao_pipeline = Self::create_gtao_pipeline(
+                &self.render_ctx,
+                &self.gtao_module,
+                &self.ssao_bgl,
+                settings.gtao_slices,
+            )?;
+            self.gtao_slices = settings.gtao_slices;
+        }
+
+        // Without accumulation every frame uses the same kernel, so the occlusion doesn't flicker.
+        let rotation = if settings.temporal {
+            self.frame = self.frame.wrapping_add(1);
+            (self.frame as f32 * GOLDEN_ANGLE) % std::f32::consts::TAU
+        } else {
+            0.0
+        };
+
+        let render_ctx = self.render_ctx.clone();
+        let RenderContext {
+            gpu,
+            scene_uniform,
+            profiler,
+            texture_pool,
+            ..
+        } = render_ctx.as_ref();
+
+        gpu.queue.write_buffer(
+            &self.params_buf,
+            0,
+            bytemuck::cast_slice(&params_contents(settings, rotation)),
+        );
+
         let mut encoder = gpu
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
 
-        let output_tv = self
-            .output_tex
-            .create_view(&wgpu::TextureViewDescriptor::default());
-        let g_normal = g_buffers.g_normal.create_view(&Default::default());
-
-        let depth_tv = gpu.depth_texture_view();
+        // Only read by the blur, its output is what's kept.
+        let output_tex = texture_pool.acquire(
+            gpu,
+            "SsaoPass::OutputTexture",
+            &TransientDesc {
+                size: TargetSize::Viewport(self.divisor),
+                format: OUTPUT_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+            },
+        );
+        let output_tv = output_tex.create_view(&wgpu::TextureViewDescriptor::default());
         let noise_tv = self.noise_tex.create_view(&Default::default());
 
-        let bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("SsaoPass::BindGroup"),
-            layout: &self.ssao_bgl,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::Buffer(
-                        self.samples_buf.as_entire_buffer_binding(),
-                    ),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&self.g_sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&self.noise_sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: wgpu::BindingResource::TextureView(&g_normal),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 4,
-                    resource: wgpu::BindingResource::TextureView(&noise_tv),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 5,
-                    resource: wgpu::BindingResource::TextureView(&depth_tv),
-                },
+        let bg = resources.bind_group(
+            gpu,
+            "SsaoPass::BindGroup",
+            &self.ssao_bgl,
+            &[
+                Binding::Buffer(&self.samples_buf),
+                Binding::Sampler(&self.g_sampler),
+                Binding::Sampler(&self.noise_sampler),
+                Binding::View(&noise_tv),
+                Binding::Buffer(&self.params_buf),
             ],
-        });
+        )?;
 
         {
             let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -340,20 +666,204 @@ impl<'window> SsaoPass<'window> {
                     },
                 })],
                 depth_stencil_attachment: None,
-                timestamp_writes: None,
+                timestamp_writes: profiler.render_pass_writes("SSAO"),
+                occlusion_query_set: None,
+            });
+
+            rpass.set_pipeline(match settings.backend {
+                AoBackend::Ssao => &self.ssao_pipeline,
+                AoBackend::Gtao => &self.gtao_pipeline,
+            });
+            rpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
+            rpass.set_bind_group(1, gbuffer_bg, &[]);
+            rpass.set_bind_group(2, &bg, &[]);
+            rpass.draw(0..4, 0..1);
+        }
+
+        gpu.queue.submit(Some(encoder.finish()));
+
+        let blurred_tv = self
+            .blur_pass
+            .perform(
+                gpu,
+                texture_pool,
+                &output_tex,
+                settings.blur_iterations,
+                &GaussianKernel::new(settings.blur_radius, settings.blur_sigma),
+            )?
+            .create_view(&Default::default());
+
+        let occlusion_tv = if settings.temporal {
+            self.accumulate(resources, &blurred_tv, settings.history_weight)?
+        } else {
+            self.history_valid = false;
+            blurred_tv
+        };
+
+        if self.divisor == 1 {
+            return Ok(occlusion_tv);
+        }
+
+        self.upsample(resources, gbuffer_bg, &occlusion_tv)?;
+        Ok(self.upsampled_tex.create_view(&Default::default()))
+    }
+
+    // Blends the occlusion with the history reprojected by G-Buffer velocity, into the other history
+    // texture. Returns a view of the blended one.
+    fn accumulate(
+        &mut self,
+        resources: &GraphResources,
+        occlusion: &wgpu::TextureView,
+        history_weight: f32,
+    ) -> Result<wgpu::TextureView> {
+        let RenderContext { gpu, profiler, .. } = self.render_ctx.as_ref();
+
+        let history_weight = if self.history_valid {
+            history_weight
+        } else {
+            0.0
+        };
+        gpu.queue.write_buffer(
+            &self.temporal_params_buf,
+            0,
+            bytemuck::cast_slice(&[history_weight, 0.0, 0.0, 0.0]),
+        );
+
+        self.history.swap(0, 1);
+        let [current, previous] = &self.history;
+        let current_tv = current.create_view(&Default::default());
+        let previous_tv = previous.create_view(&Default::default());
+
+        let bg = resources.bind_group(
+            gpu,
+            "SsaoPass::TemporalBindGroup",
+            &self.temporal_bgl,
+            &[
+                Binding::View(occlusion),
+                Binding::View(&previous_tv),
+                Binding::Sampler(&self.history_sampler),
+                Binding::Resource(G_VELOCITY),
+                Binding::Buffer(&self.temporal_params_buf),
+            ],
+        )?;
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+
+        {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("SsaoPass::TemporalRenderPass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &current_tv,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: profiler.render_pass_writes("SSAO Temporal"),
+                occlusion_query_set: None,
+            });
+
+            rpass.set_pipeline(&self.temporal_pipeline);
+            rpass.set_bind_group(0, &bg, &[]);
+            rpass.draw(0..4, 0..1);
+        }
+
+        gpu.queue.submit(Some(encoder.finish()));
+        self.history_valid = true;
+
+        Ok(current_tv)
+    }
+
+    // Brings the occlusion to the viewport resolution, weighting texels by how close their depth is
+    // to the one of the pixel, so it doesn't bleed over edges of geometry.
+    fn upsample(
+        &self,
+        resources: &GraphResources,
+        gbuffer_bg: &wgpu::BindGroup,
+        occlusion: &wgpu::TextureView,
+    ) -> Result<()> {
+        let RenderContext {
+            gpu,
+            scene_uniform,
+            profiler,
+            ..
+        } = self.render_ctx.as_ref();
+
+        let upsampled_tv = self.upsampled_tex.create_view(&Default::default());
+
+        let bg = resources.bind_group(
+            gpu,
+            "SsaoPass::UpsampleBindGroup",
+            &self.upsample_bgl,
+            &[Binding::View(occlusion)],
+        )?;
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+
+        {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("SsaoPass::UpsampleRenderPass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &upsampled_tv,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: profiler.render_pass_writes("SSAO Upsample"),
                 occlusion_query_set: None,
             });
 
-            rpass.set_pipeline(&self.ssao_pipeline);
+            rpass.set_pipeline(&self.upsample_pipeline);
             rpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
-            rpass.set_bind_group(1, &bg, &[]);
+            rpass.set_bind_group(1, gbuffer_bg, &[]);
+            rpass.set_bind_group(2, &bg, &[]);
             rpass.draw(0..4, 0..1);
         }
 
         gpu.queue.submit(Some(encoder.finish()));
 
-        self.blur_pass
-            .perform(gpu, &self.output_tex, 8, 4)
-            .create_view(&Default::default())
+        Ok(())
+    }
+}
+
+impl RenderPass for SsaoPass<'_> {
+    fn name(&self) -> &'static str {
+        "SsaoPass"
+    }
+
+    fn enabled(&self, ctx: &FrameContext) -> bool {
+        ctx.settings.pipeline_type == PipelineType::Deferred
+    }
+
+    fn io(&self) -> PassIo {
+        PassIo::default()
+            .read(G_NORMAL)
+            .read(G_VELOCITY)
+            .export(AMBIENT_OCCLUSION)
+    }
+
+    fn resize(&mut self, _size: (u32, u32)) -> Result<()> {
+        self.upsampled_tex = Self::create_upsampled_texture(&self.render_ctx.gpu);
+        self.create_targets()
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        let gbuffer = ctx
+            .gbuffer
+            .as_ref()
+            .context("G-Buffer has to be rendered before SSAO")?;
+        let occlusion = Self::render(self, &ctx.resources, gbuffer, &ctx.settings.ssao)?;
+        ctx.resources.export(AMBIENT_OCCLUSION, occlusion);
+
+        Ok(())
     }
 }
@@ -0,0 +1,146 @@
+use anyhow::Result;
+use gpu_basics::{render_context::RenderContext, shader_compiler::ShaderError, ui_pass::UiPass};
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::EventLoop,
+};
+
+const BACKGROUND: wgpu::Color = wgpu::Color {
+    r: 0.02,
+    g: 0.02,
+    b: 0.03,
+    a: 1.0,
+};
+
+fn error_ui(ctx: &egui::Context, error: &anyhow::Error) {
+    let shader_error = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<ShaderError>());
+
+    let title = if shader_error.is_some() {
+        "Shader Error"
+    } else {
+        "Startup Error"
+    };
+
+    egui::Window::new(title)
+        .collapsible(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .default_width(720.0)
+        .show(ctx, |ui| {
+            match shader_error {
+                Some(shader_error) => {
+                    let location = match (shader_error.line, shader_error.column) {
+                        (Some(line), Some(column)) => {
+                            format!("{}:{}:{}", shader_error.path, line, column)
+                        }
+                        (Some(line), None) => format!("{}:{}", shader_error.path, line),
+                        _ => shader_error.path.clone(),
+                    };
+
+                    ui.strong(&shader_error.message);
+                    ui.monospace(location);
+                    if shader_error.defs.is_empty() {
+                        ui.label("No definitions.");
+                    } else {
+                        ui.label(format!("Definitions: {}", shader_error.defs.join(", ")));
+                    }
+
+                    ui.separator();
+                    egui::ScrollArea::both().max_height(360.0).show(ui, |ui| {
+                        ui.monospace(&shader_error.report);
+                    });
+                }
+                None => {
+                    ui.strong(error.to_string());
+                }
+            }
+
+            // Context added on the way up, e.g. which shader was being compiled.
+            let causes = error.chain().skip(1).collect::<Vec<_>>();
+            if !causes.is_empty() {
+                ui.separator();
+                for cause in causes {
+                    ui.label(format!("caused by: {}", cause));
+                }
+            }
+
+            ui.separator();
+            ui.label("Fix it and restart, closing the window exits.");
+        });
+}
+
+/// Shows `error` in a window over a blank frame until it's closed, for failures
+/// to set up passes which would otherwise only end up in the terminal.
+/// Returns `error` afterwards, so the application still exits with it.
+pub fn run(
+    event_loop: EventLoop<()>,
+    render_ctx: &RenderContext,
+    ui: &mut UiPass,
+    error: anyhow::Error,
+) -> Result<()> {
+    eprintln!("{:#}", error);
+
+    let RenderContext { gpu, window, .. } = render_ctx;
+
+    event_loop.run(|event, target| {
+        let Event::WindowEvent { event, .. } = event else {
+            return;
+        };
+
+        if ui.handle_input(window, &event) {
+            return;
+        }
+
+        match event {
+            WindowEvent::Resized(new_size) => {
+                if new_size.width > 0 && new_size.height > 0 {
+                    gpu.on_resize((new_size.width, new_size.height));
+                }
+                window.request_redraw();
+            }
+            WindowEvent::CloseRequested => target.exit(),
+            WindowEvent::RedrawRequested => {
+                let output = ui.update(window, |ctx| error_ui(ctx, &error));
+                let frame = match gpu.current_texture() {
+                    Ok(Some(frame)) => frame,
+                    _ => {
+                        ui.skip(output);
+                        window.request_redraw();
+                        return;
+                    }
+                };
+
+                let view = frame
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                let mut encoder =
+                    gpu.device
+                        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                            label: Some("ErrorOverlay::CommandEncoder"),
+                        });
+                encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("ErrorOverlay::Clear"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(BACKGROUND),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                gpu.queue.submit(Some(encoder.finish()));
+
+                ui.render(frame, output).present();
+                window.request_redraw();
+            }
+            _ => {}
+        }
+    })?;
+
+    Err(error)
+}
@@ -0,0 +1,160 @@
+use nalgebra as na;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::mesh::Mesh;
+
+type FVec3 = na::Vector3<f32>;
+type FMat4x4 = na::Matrix4<f32>;
+
+/// Bytes a plant takes on the GPU, laid out like `Plant` in the foliage shaders.
+pub const FOLIAGE_INSTANCE_STRIDE: usize = std::mem::size_of::<[f32; 8]>();
+
+/// A single plant, drawn as crossed quads standing on `position`.
+#[derive(Clone, Copy, Debug)]
+pub struct FoliageInstance {
+    pub position: FVec3,
+    // Height of the plant in world units.
+    pub scale: f32,
+    // Radians around the vertical axis.
+    pub rotation: f32,
+    // Brightness of the plant relative to its texture, so neighbours don't look the same.
+    pub tint: f32,
+}
+
+impl FoliageInstance {
+    pub fn new(position: FVec3, scale: f32, rotation: f32, tint: f32) -> Self {
+        Self {
+            position,
+            scale,
+            rotation,
+            tint,
+        }
+    }
+
+    pub fn copy_to(&self, target: &mut Vec<u8>) {
+        let [x, y, z]: [f32; 3] = self.position.into();
+        target.extend(bytemuck::cast_slice(&[
+            x,
+            y,
+            z,
+            self.scale,
+            self.rotation,
+            self.tint,
+            0.0,
+            0.0,
+        ]));
+    }
+}
+
+/// How plants are spread over a surface.
+#[derive(Clone, Debug)]
+pub struct FoliageScatter {
+    pub count: usize,
+    // Same seed gives the same plants on the same surface.
+    pub seed: u64,
+    // Smallest and largest height of a plant.
+    pub scale: [f32; 2],
+    // Degrees from the vertical, triangles steeper than that are left bare.
+    pub max_slope: f32,
+    // Range of world space heights plants grow at.
+    pub heights: Option<[f32; 2]>,
+}
+
+impl Default for FoliageScatter {
+    fn default() -> Self {
+        Self {
+            count: 10000,
+            seed: 0,
+            scale: [0.4, 0.8],
+            max_slope: 30.0,
+            heights: None,
+        }
+    }
+}
+
+struct Triangle([FVec3; 3]);
+
+impl Triangle {
+    fn area_normal(&self) -> FVec3 {
+        let [a, b, c] = self.0;
+        (b - a).cross(&(c - a))
+    }
+
+    // Uniformly distributed over the triangle.
+    fn sample(&self, rng: &mut StdRng) -> FVec3 {
+        let [a, b, c] = self.0;
+        let (r1, r2): (f32, f32) = (rng.gen(), rng.gen());
+        let s = r1.sqrt();
+
+        a * (1.0 - s) + b * (s * (1.0 - r2)) + c * (s * r2)
+    }
+}
+
+/// Places plants on triangles of `surface` meshes, transformed by their model matrices.
+/// Triangles get plants in proportion to their area, as long as they face up
+/// and aren't too steep.
+pub fn scatter<'a>(
+    surface: impl IntoIterator<Item = (&'a Mesh, FMat4x4)>,
+    settings: &FoliageScatter,
+) -> Vec<FoliageInstance> {
+    let min_up = settings.max_slope.to_radians().cos();
+    let [min_height, max_height] = settings.heights.unwrap_or([f32::MIN, f32::MAX]);
+
+    let triangles = surface
+        .into_iter()
+        .flat_map(|(mesh, model)| {
+            let positions = mesh
+                .positions()
+                .iter()
+                .map(|p| model.transform_point(&na::Point3::from(*p)).coords)
+                .collect::<Vec<_>>();
+            let indices = match mesh.indices() {
+                Some(indices) => indices.to_vec(),
+                None => (0..positions.len() as u32).collect(),
+            };
+
+            indices
+                .chunks_exact(3)
+                .map(|face| Triangle([face[0], face[1], face[2]].map(|i| positions[i as usize])))
+                .collect::<Vec<_>>()
+        })
+        .filter(|triangle| {
+            let normal = triangle.area_normal().normalize();
+            let height = triangle.0.iter().map(|p| p.y).sum::<f32>() / 3.0;
+
+            normal.y >= min_up && (min_height..=max_height).contains(&height)
+        })
+        .collect::<Vec<_>>();
+
+    // Running total of areas, triangles are picked by where a random area falls in it.
+    let mut total_area = 0.0;
+    let cumulative_areas = triangles
+        .iter()
+        .map(|triangle| {
+            total_area += triangle.area_normal().norm() * 0.5;
+            total_area
+        })
+        .collect::<Vec<_>>();
+    if total_area <= 0.0 {
+        return Vec::new();
+    }
+
+    let mut rng = StdRng::seed_from_u64(settings.seed);
+    let [min_scale, max_scale] = settings.scale;
+
+    (0..settings.count)
+        .map(|_| {
+            let area = rng.gen_range(0.0..total_area);
+            let picked = cumulative_areas
+                .partition_point(|&cumulative| cumulative <= area)
+                .min(triangles.len() - 1);
+
+            FoliageInstance::new(
+                triangles[picked].sample(&mut rng),
+                rng.gen_range(min_scale..=max_scale.max(min_scale)),
+                rng.gen_range(0.0..std::f32::consts::TAU),
+                rng.gen_range(0.8..1.2),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,435 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use encase::{ShaderSize, UniformBuffer};
+use nalgebra as na;
+
+use crate::{
+    compute::{dispatch, Kernel},
+    foliage::FOLIAGE_INSTANCE_STRIDE,
+    gpu::Gpu,
+    light_scene::Light,
+    render_context::RenderContext,
+    render_graph::{Binding, GraphResources},
+    render_pass::{FrameContext, RenderPass},
+    settings::FoliageSettings,
+};
+
+const KERNEL: Kernel = Kernel::new([64, 1, 1]);
+
+const PLANT_TEXTURE: &str = "./textures/foliage/grass.png";
+
+// Two crossed quads.
+const PLANT_VERTICES: u32 = 12;
+
+// Fields of `Foliage` in the shader.
+type Params = [na::Vector4<f32>; 4];
+
+/// Plants of the scene, drawn as two crossed alpha tested quads each into the scene color.
+/// A compute pass first keeps plants in range and in the view frustum, writing them
+/// into a buffer drawn with instance count left by the culling.
+pub struct FoliagePass<'window> {
+    render_ctx: Arc<RenderContext<'window>>,
+    params_buf: wgpu::Buffer,
+    // Plants surviving culling, grown when the scene has more plants than it fits.
+    visible_buf: wgpu::Buffer,
+    visible_capacity: usize,
+    draw_buf: wgpu::Buffer,
+    cull_bgl: Arc<wgpu::BindGroupLayout>,
+    cull_pipeline: wgpu::ComputePipeline,
+    draw_bgl: Arc<wgpu::BindGroupLayout>,
+    rgba8_pipeline: wgpu::RenderPipeline,
+    rgba16_pipeline: wgpu::RenderPipeline,
+    texture_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+}
+
+impl<'window> FoliagePass<'window> {
+    pub fn new(render_ctx: Arc<RenderContext<'window>>) -> Result<Self> {
+        let RenderContext {
+            gpu,
+            bind_group_layouts,
+            shader_compiler,
+            scene_uniform,
+            ..
+        } = render_ctx.as_ref();
+
+        let params_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("FoliagePass::Params"),
+            size: Params::SHADER_SIZE.into(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let draw_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("FoliagePass::Draw"),
+            size: std::mem::size_of::<wgpu::util::DrawIndirectArgs>() as u64,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let params_entry = |visibility| wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let storage_entry = |binding, visibility, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let cull_bgl = bind_group_layouts.get(
+            gpu,
+            "FoliagePass::CullBindGroupLayout",
+            &[
+                params_entry(wgpu::ShaderStages::COMPUTE),
+                storage_entry(1, wgpu::ShaderStages::COMPUTE, true),
+                storage_entry(2, wgpu::ShaderStages::COMPUTE, false),
+                storage_entry(3, wgpu::ShaderStages::COMPUTE, false),
+            ],
+        );
+
+        let draw_bgl = bind_group_layouts.get(
+            gpu,
+            "FoliagePass::DrawBindGroupLayout",
+            &[
+                params_entry(wgpu::ShaderStages::VERTEX_FRAGMENT),
+                storage_entry(1, wgpu::ShaderStages::VERTEX, true),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        );
+
+        let cull_pipeline_layout =
+            gpu.device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("FoliagePass::CullPipelineLayout"),
+                    bind_group_layouts: &[scene_uniform.layout(), &cull_bgl],
+                    push_constant_ranges: &[],
+                });
+
+        let cull_shader = gpu.shader_from_module(
+            KERNEL
+                .with_defs(shader_compiler.compilation_unit("./shaders/foliage/cull.wgsl")?)
+                .compile(&[])?,
+        );
+
+        let cull_pipeline = gpu
+            .device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("FoliagePass::CullPipeline"),
+                layout: Some(&cull_pipeline_layout),
+                module: &cull_shader,
+                entry_point: "cull",
+            });
+
+        let draw_pipeline_layout =
+            gpu.device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("FoliagePass::PipelineLayout"),
+                    bind_group_layouts: &[scene_uniform.layout(), &draw_bgl],
+                    push_constant_ranges: &[],
+                });
+
+        let shader = gpu.shader_from_module(
+            shader_compiler
+                .compilation_unit("./shaders/foliage/foliage.wgsl")?
+                .compile(&[])?,
+        );
+
+        let pipeline = |format: wgpu::TextureFormat| {
+            gpu.device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("FoliagePass::Pipeline"),
+                    layout: Some(&draw_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &[],
+                    },
+                    // Quads are seen from both sides.
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        cull_mode: None,
+                        ..Default::default()
+                    },
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: wgpu::TextureFormat::Depth32Float,
+                        depth_write_enabled: true,
+                        depth_compare: gpu.depth_compare(wgpu::CompareFunction::Less),
+                        stencil: Default::default(),
+                        bias: Default::default(),
+                    }),
+                    multisample: wgpu::MultisampleState::default(),
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fs_main",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format,
+                            blend: Some(wgpu::BlendState::REPLACE),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    multiview: None,
+                })
+        };
+
+        let rgba8_pipeline = pipeline(gpu.swapchain_format());
+        let rgba16_pipeline = pipeline(wgpu::TextureFormat::Rgba16Float);
+
+        let texture_view = Self::load_texture(gpu)?.create_view(&Default::default());
+        let sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("FoliagePass::Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Ok(Self {
+            visible_buf: Self::create_visible(gpu, 0),
+            visible_capacity: 0,
+            render_ctx,
+            params_buf,
+            draw_buf,
+            cull_bgl,
+            cull_pipeline,
+            draw_bgl,
+            rgba8_pipeline,
+            rgba16_pipeline,
+            texture_view,
+            sampler,
+        })
+    }
+
+    fn load_texture(gpu: &Gpu) -> Result<wgpu::Texture> {
+        use wgpu::util::DeviceExt;
+
+        let image = image::open(PLANT_TEXTURE)
+            .with_context(|| format!("failed to load foliage texture {PLANT_TEXTURE}"))?
+            .to_rgba8();
+
+        Ok(gpu.device.create_texture_with_data(
+            &gpu.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("FoliagePass::Texture"),
+                size: wgpu::Extent3d {
+                    width: image.width(),
+                    height: image.height(),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            image.as_raw(),
+        ))
+    }
+
+    // Storage buffers can't be empty, so there's always room for a plant.
+    fn create_visible(gpu: &Gpu, capacity: usize) -> wgpu::Buffer {
+        gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("FoliagePass::Visible"),
+            size: (capacity.max(1) * FOLIAGE_INSTANCE_STRIDE) as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn write_params(&self, gpu: &Gpu, settings: &FoliageSettings, sun: &Light) -> Result<()> {
+        let params: Params = [
+            na::Vector4::new(settings.distance, 0.0, settings.wind, settings.alpha_cutoff),
+            sun.direction,
+            sun.ambient,
+            sun.diffuse,
+        ];
+
+        let size: u64 = Params::SHADER_SIZE.into();
+        let mut contents = UniformBuffer::new(Vec::with_capacity(size as usize));
+        contents.write(&params)?;
+        gpu.queue
+            .write_buffer(&self.params_buf, 0, contents.into_inner().as_slice());
+
+        Ok(())
+    }
+
+    pub fn render(
+        &mut self,
+        scene_color: &wgpu::TextureView,
+        hdr: bool,
+        settings: &FoliageSettings,
+        sun: &Light,
+        resources: &GraphResources,
+    ) -> Result<()> {
+        let render_ctx = self.render_ctx.clone();
+        let RenderContext {
+            gpu,
+            scene_uniform,
+            gpu_scene,
+            profiler,
+            ..
+        } = render_ctx.as_ref();
+
+        let scene = gpu_scene.read().unwrap();
+        let Some((plants, num_plants)) = scene.foliage() else {
+            return Ok(());
+        };
+
+        if num_plants > self.visible_capacity {
+            self.visible_buf = Self::create_visible(gpu, num_plants);
+            self.visible_capacity = num_plants;
+        }
+
+        self.write_params(gpu, settings, sun)?;
+        // Culling counts plants it keeps up from zero.
+        gpu.queue.write_buffer(
+            &self.draw_buf,
+            0,
+            wgpu::util::DrawIndirectArgs {
+                vertex_count: PLANT_VERTICES,
+                instance_count: 0,
+                first_vertex: 0,
+                first_instance: 0,
+            }
+            .as_bytes(),
+        );
+
+        let cull_bg = resources.bind_group(
+            gpu,
+            "FoliagePass::CullBindGroup",
+            &self.cull_bgl,
+            &[
+                Binding::Buffer(&self.params_buf),
+                Binding::Buffer(plants),
+                Binding::Buffer(&self.visible_buf),
+                Binding::Buffer(&self.draw_buf),
+            ],
+        )?;
+        let draw_bg = resources.bind_group(
+            gpu,
+            "FoliagePass::DrawBindGroup",
+            &self.draw_bgl,
+            &[
+                Binding::Buffer(&self.params_buf),
+                Binding::Buffer(&self.visible_buf),
+                Binding::View(&self.texture_view),
+                Binding::Sampler(&self.sampler),
+            ],
+        )?;
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("FoliagePass::CommandEncoder"),
+            });
+
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("FoliagePass::CullPass"),
+                timestamp_writes: profiler.compute_pass_writes("Foliage Culling"),
+            });
+
+            cpass.set_pipeline(&self.cull_pipeline);
+            cpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
+            cpass.set_bind_group(1, &cull_bg, &[]);
+            dispatch(&mut cpass, &KERNEL, [num_plants as u32, 1, 1]);
+        }
+
+        {
+            let depth_view = gpu.depth_texture_view();
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("FoliagePass::RenderPass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: scene_color,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: profiler.render_pass_writes("Foliage"),
+                occlusion_query_set: None,
+            });
+
+            if hdr {
+                rpass.set_pipeline(&self.rgba16_pipeline);
+            } else {
+                rpass.set_pipeline(&self.rgba8_pipeline);
+            }
+
+            rpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
+            rpass.set_bind_group(1, &draw_bg, &[]);
+            rpass.draw_indirect(&self.draw_buf, 0);
+        }
+
+        gpu.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
+
+impl RenderPass for FoliagePass<'_> {
+    fn name(&self) -> &'static str {
+        "FoliagePass"
+    }
+
+    fn enabled(&self, ctx: &FrameContext) -> bool {
+        ctx.settings.foliage.enabled && !ctx.settings.deferred_debug_shown()
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        let (scene_color, hdr) = ctx
+            .scene_color
+            .as_ref()
+            .context("foliage is drawn into a lit scene")?;
+        Self::render(
+            self,
+            scene_color,
+            *hdr,
+            &ctx.settings.foliage,
+            &ctx.sun,
+            &ctx.resources,
+        )?;
+
+        Ok(())
+    }
+}
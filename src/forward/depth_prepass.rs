@@ -1,17 +1,18 @@
 use std::sync::Arc;
 
 use crate::{
-    mesh::{Mesh, MeshVertexArrayType},
+    mesh::MeshVertexArrayType,
+    pipeline_cache::RenderPipelineDesc,
     render_context::RenderContext,
+    render_pass::{FrameContext, RenderPass},
     scene::Instance,
+    shader_permutation::Variants,
 };
 use anyhow::Result;
 
 pub struct DepthPrepass<'window> {
     render_ctx: Arc<RenderContext<'window>>,
-    pn_pipeline: wgpu::RenderPipeline,
-    pnuv_pipeline: wgpu::RenderPipeline,
-    pntbuv_pipeline: wgpu::RenderPipeline,
+    pipelines: Variants<MeshVertexArrayType, Arc<wgpu::RenderPipeline>>,
 }
 
 impl<'window> DepthPrepass<'window> {
@@ -20,129 +21,59 @@ impl<'window> DepthPrepass<'window> {
             gpu,
             shader_compiler,
             scene_uniform,
+            pipeline_cache,
             ..
         } = render_ctx.as_ref();
 
         let module =
             shader_compiler.compilation_unit("./shaders/forward/cascaded_shadow_map.wgsl")?;
-        let (shader, pnuv_shader, pntbuv_shader) = gpu.shader_per_vertex_type(&module)?;
 
-        let pipelinel = gpu
-            .device
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[scene_uniform.layout()],
-                push_constant_ranges: &[],
-            });
-
-        let pn_pipeline = gpu
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                layout: Some(&pipelinel),
-                vertex: wgpu::VertexState {
-                    module: &shader,
-                    entry_point: "vs_main",
-                    buffers: &[
-                        Mesh::pn_vertex_layout(),
-                        Instance::pn_model_instance_layout(),
-                    ],
-                },
-                fragment: None,
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    front_face: wgpu::FrontFace::Ccw,
-                    cull_mode: Some(wgpu::Face::Back),
-                    ..Default::default()
-                },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: wgpu::TextureFormat::Depth32Float,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::Less,
-                    stencil: Default::default(),
-                    bias: Default::default(),
-                }),
-                multisample: wgpu::MultisampleState::default(),
-                multiview: None,
-            });
-
-        let pnuv_pipeline = gpu
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                layout: Some(&pipelinel),
-                vertex: wgpu::VertexState {
-                    module: &pnuv_shader,
-                    entry_point: "vs_main",
-                    buffers: &[
-                        Mesh::pnuv_vertex_layout(),
-                        Instance::pnuv_model_instance_layout(),
-                    ],
-                },
-                fragment: None,
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    front_face: wgpu::FrontFace::Ccw,
-                    cull_mode: Some(wgpu::Face::Back),
-                    ..Default::default()
-                },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: wgpu::TextureFormat::Depth32Float,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::Less,
-                    stencil: Default::default(),
-                    bias: Default::default(),
-                }),
-                multisample: wgpu::MultisampleState::default(),
-                multiview: None,
-            });
-
-        let pntbuv_pipeline = gpu
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                layout: Some(&pipelinel),
-                vertex: wgpu::VertexState {
-                    module: &pntbuv_shader,
-                    entry_point: "vs_main",
-                    buffers: &[
-                        Mesh::pntbuv_vertex_layout(),
-                        Instance::pntbuv_model_instance_layout(),
-                    ],
-                },
-                fragment: None,
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    front_face: wgpu::FrontFace::Ccw,
-                    cull_mode: Some(wgpu::Face::Back),
-                    ..Default::default()
+        let pipelines = Variants::try_new(|ty: MeshVertexArrayType| {
+            pipeline_cache.render_pipeline(
+                gpu,
+                &RenderPipelineDesc {
+                    label: None,
+                    shader: &module.variant(ty),
+                    variant_defs: &[],
+                    constants: &[],
+                    bind_group_layouts: &[scene_uniform.layout()],
+                    push_constant_ranges: &[],
+                    vertex_entry: "vs_main",
+                    vertex_buffers: &Instance::draw_buffer_layouts(ty),
+                    fragment_entry: None,
+                    targets: &[],
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        front_face: wgpu::FrontFace::Ccw,
+                        cull_mode: Some(wgpu::Face::Back),
+                        ..Default::default()
+                    },
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: wgpu::TextureFormat::Depth32Float,
+                        depth_write_enabled: true,
+                        depth_compare: gpu.depth_compare(wgpu::CompareFunction::Less),
+                        stencil: Default::default(),
+                        bias: Default::default(),
+                    }),
+                    multisample: wgpu::MultisampleState::default(),
                 },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: wgpu::TextureFormat::Depth32Float,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::Less,
-                    stencil: Default::default(),
-                    bias: Default::default(),
-                }),
-                multisample: wgpu::MultisampleState::default(),
-                multiview: None,
-            });
+            )
+        })?;
 
         Ok(Self {
             render_ctx,
-            pn_pipeline,
-            pnuv_pipeline,
-            pntbuv_pipeline,
+            pipelines,
         })
     }
 
     pub fn render(&self) {
         let RenderContext {
             gpu,
-            gpu_scene: scene,
+            gpu_scene,
             scene_uniform,
             ..
         } = self.render_ctx.as_ref();
+        let scene = gpu_scene.read().unwrap();
 
         let depth_view = gpu.depth_texture_view();
         let mut encoder = gpu
@@ -156,7 +87,7 @@ impl<'window> DepthPrepass<'window> {
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                     view: &depth_view,
                     depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
+                        load: wgpu::LoadOp::Clear(gpu.far_depth()),
                         store: wgpu::StoreOp::Store,
                     }),
                     stencil_ops: None,
@@ -167,41 +98,40 @@ impl<'window> DepthPrepass<'window> {
 
             rpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
 
-            for draw_call in scene.draw_calls() {
-                match draw_call.vertex_array_type {
-                    MeshVertexArrayType::PNUV => rpass.set_pipeline(&self.pnuv_pipeline),
-                    MeshVertexArrayType::PNTBUV => rpass.set_pipeline(&self.pntbuv_pipeline),
-                    MeshVertexArrayType::PN => rpass.set_pipeline(&self.pn_pipeline),
-                };
+            for batch in scene.draw_batches() {
+                let first = &batch[0];
+                rpass.set_pipeline(&self.pipelines[first.vertex_array_type]);
 
                 rpass.set_vertex_buffer(
                     0,
                     scene
-                        .vertex_buffer_by_type(draw_call.vertex_array_type)
+                        .vertex_buffer_by_type(first.vertex_array_type)
                         .slice(..),
                 );
                 rpass.set_vertex_buffer(
                     1,
-                    scene
-                        .instance_buffer_by_type(draw_call.instance_type)
-                        .slice(..),
+                    scene.instance_buffer_by_type(first.instance_type).slice(..),
                 );
 
-                if draw_call.indexed {
+                if first.indexed {
                     rpass.set_index_buffer(
                         scene.index_buffer().slice(..),
                         wgpu::IndexFormat::Uint32,
                     );
+                }
 
-                    rpass.draw_indexed_indirect(
-                        scene.indexed_draw_buffer(),
-                        draw_call.draw_buffer_offset,
-                    );
-                } else {
-                    rpass.draw_indirect(
-                        scene.non_indexed_draw_buffer(),
-                        draw_call.draw_buffer_offset,
-                    );
+                for draw_call in batch {
+                    if draw_call.indexed {
+                        rpass.draw_indexed_indirect(
+                            scene.indexed_draw_buffer(),
+                            draw_call.draw_buffer_offset,
+                        );
+                    } else {
+                        rpass.draw_indirect(
+                            scene.non_indexed_draw_buffer(),
+                            draw_call.draw_buffer_offset,
+                        );
+                    }
                 }
             }
         }
@@ -209,3 +139,18 @@ impl<'window> DepthPrepass<'window> {
         gpu.queue.submit(Some(encoder.finish()));
     }
 }
+
+impl RenderPass for DepthPrepass<'_> {
+    fn name(&self) -> &'static str {
+        "DepthPrepass"
+    }
+
+    fn enabled(&self, ctx: &FrameContext) -> bool {
+        ctx.settings.depth_prepass_enabled
+    }
+
+    fn render(&mut self, _ctx: &mut FrameContext) -> Result<()> {
+        Self::render(self);
+        Ok(())
+    }
+}
@@ -1,226 +1,124 @@
 use std::sync::Arc;
 
 use crate::{
-    mesh::{Mesh, MeshVertexArrayType},
+    compute::{LightClusteringPass, TileCullingPass},
+    gpu::Gpu,
+    material::PhongVariant,
+    pipeline_cache::RenderPipelineDesc,
     render_context::RenderContext,
+    render_pass::{FrameContext, RenderPass},
     scene::Instance,
+    settings::{LightCulling, PipelineType, ShadowFiltering},
+    shader_permutation::Variants,
 };
-use anyhow::Result;
-use encase::{ShaderType, StorageBuffer};
+use anyhow::{Context, Result};
 
 pub struct PhongPass<'window> {
     render_ctx: Arc<RenderContext<'window>>,
-    lights_bg: wgpu::BindGroup,
-    #[allow(dead_code)]
-    lights_buf: wgpu::Buffer,
-    pipelines: PhongPipelines,
-}
-
-struct PhongPipelines {
-    solid: wgpu::RenderPipeline,
-    textured: wgpu::RenderPipeline,
-    textured_normal: wgpu::RenderPipeline,
+    // Shared with deferred lighting, see `LightBuffers::bind_group`.
+    lights_bg: Arc<wgpu::BindGroup>,
+    light_lists_bgl: Arc<wgpu::BindGroupLayout>,
+    // Light lists of clusters and of tiles.
+    cluster_lists_bg: wgpu::BindGroup,
+    tile_lists_bg: wgpu::BindGroup,
+    clustering_pass: LightClusteringPass,
+    tile_culling_pass: TileCullingPass,
+    pipelines: Variants<(PhongVariant, ShadowFiltering, LightCulling), Arc<wgpu::RenderPipeline>>,
 }
 
 impl<'window> PhongPass<'window> {
     pub fn new(
         render_ctx: Arc<RenderContext<'window>>,
-        shadow_bgl: &wgpu::BindGroupLayout,
+        lights_bg: Arc<wgpu::BindGroup>,
     ) -> Result<Self> {
         let RenderContext {
             gpu,
+            bind_group_layouts,
             shader_compiler,
             scene_uniform,
-            light_scene: lights,
+            light_buffers,
             material_atlas,
-            gpu_scene,
+            pipeline_cache,
             ..
         } = render_ctx.as_ref();
+        let material_atlas = material_atlas.read().unwrap();
+        let shadow_bgl = bind_group_layouts.shadow(gpu);
 
-        use wgpu::util::DeviceExt;
-
-        let gpu_lights = lights.into_gpu();
-        let gpu_lights_size: u64 = gpu_lights.size().into();
-        let mut light_contents = StorageBuffer::new(Vec::with_capacity(gpu_lights_size as usize));
-        light_contents.write(&gpu_lights)?;
-
-        let light_buf = gpu
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: None,
-                contents: light_contents.into_inner().as_slice(),
-                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-            });
-
-        let module = shader_compiler
-            .compilation_unit("./shaders/forward/phong.wgsl")?
-            .with_def("SHADOW_MAP");
-
-        let solid_shader =
-            gpu.shader_from_module(module.compile(&["VERTEX_PN", "MATERIAL_PHONG_SOLID"])?);
-
-        let textured_shader =
-            gpu.shader_from_module(module.compile(&["VERTEX_PNUV", "MATERIAL_PHONG_TEXTURED"])?);
-
-        let textured_normal_shader = gpu.shader_from_module(module.compile(&[
-            "VERTEX_PNTBUV",
-            "MATERIAL_PHONG_TEXTURED",
-            "NORMAL_MAP",
-        ])?);
-
-        // Lights buffer:
-        let lights_bgl = gpu
-            .device
-            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-            });
-
-        let lights_bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &lights_bgl,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: light_buf.as_entire_binding(),
-            }],
-        });
-
-        let solid_layout = gpu
-            .device
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[
-                    scene_uniform.layout(),
-                    &lights_bgl,
-                    &material_atlas.layouts.phong_solid,
-                    &shadow_bgl,
-                ],
-                push_constant_ranges: &[],
-            });
-
-        let textured_layout = gpu
-            .device
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[
-                    scene_uniform.layout(),
-                    &lights_bgl,
-                    &material_atlas.layouts.phong_textured,
-                    &shadow_bgl,
-                ],
-                push_constant_ranges: &[],
-            });
+        let clustering_pass = LightClusteringPass::new(
+            gpu,
+            shader_compiler,
+            bind_group_layouts,
+            scene_uniform,
+            light_buffers.lights(),
+        )?;
+        let tile_culling_pass =
+            TileCullingPass::new(gpu, shader_compiler, bind_group_layouts, scene_uniform)?;
+
+        let module = TileCullingPass::with_tile_defs(LightClusteringPass::with_cluster_defs(
+            gpu.with_depth_defs(shader_compiler.compilation_unit("./shaders/forward/phong.wgsl")?),
+        ))
+        .with_def("SHADOW_MAP")
+        .with_integer_def("MATERIAL_GROUP", 2);
+
+        let buffer = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
 
-        let textured_normal_layout =
-            gpu.device
-                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        // Lights of clusters or tiles, along with the grid of tiles.
+        let light_lists_bgl = bind_group_layouts.get(
+            gpu,
+            "PhongPass::LightListsBindGroupLayout",
+            &[
+                buffer(0, wgpu::BufferBindingType::Storage { read_only: true }),
+                buffer(1, wgpu::BufferBindingType::Uniform),
+            ],
+        );
+        let cluster_lists_bg = Self::light_lists_bind_group(
+            gpu,
+            &light_lists_bgl,
+            clustering_pass.clusters_buffer(),
+            tile_culling_pass.grid_buffer(),
+        );
+        let tile_lists_bg = Self::light_lists_bind_group(
+            gpu,
+            &light_lists_bgl,
+            tile_culling_pass.tiles_buffer(),
+            tile_culling_pass.grid_buffer(),
+        );
+
+        // Materials are indexed per instance, so pipelines of every vertex layout share one.
+        let lights_bgl = bind_group_layouts.lights(gpu);
+        let bind_group_layouts = [
+            scene_uniform.layout(),
+            &lights_bgl,
+            material_atlas.layout(),
+            &shadow_bgl,
+            &light_lists_bgl,
+        ];
+
+        // Shadow filtering and light culling are selected with shader definitions,
+        // so there is a pipeline for each combination of them with every material.
+        let pipelines = Variants::try_new(|variant @ (PhongVariant(ty), _, _)| {
+            pipeline_cache.render_pipeline(
+                gpu,
+                &RenderPipelineDesc {
                     label: None,
-                    bind_group_layouts: &[
-                        scene_uniform.layout(),
-                        &lights_bgl,
-                        &material_atlas.layouts.phong_textured_normal,
-                        &shadow_bgl,
-                    ],
+                    shader: &module.variant(variant),
+                    variant_defs: &[],
+                    constants: &[],
+                    bind_group_layouts: &bind_group_layouts,
                     push_constant_ranges: &[],
-                });
-
-        let pipeline_solid = gpu
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                layout: Some(&solid_layout),
-                vertex: wgpu::VertexState {
-                    module: &solid_shader,
-                    entry_point: "vs_main",
-                    buffers: &[
-                        Mesh::pn_vertex_layout(),
-                        Instance::pn_model_instance_layout(),
-                    ],
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &solid_shader,
-                    entry_point: "fs_main",
+                    vertex_entry: "vs_main",
+                    vertex_buffers: &Instance::draw_buffer_layouts(ty),
+                    fragment_entry: Some("fs_main"),
                     targets: &[Some(gpu.swapchain_format().into())],
-                }),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    front_face: wgpu::FrontFace::Ccw,
-                    cull_mode: Some(wgpu::Face::Back),
-                    ..Default::default()
-                },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: wgpu::TextureFormat::Depth32Float,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::LessEqual,
-                    stencil: Default::default(),
-                    bias: Default::default(),
-                }),
-                multisample: wgpu::MultisampleState::default(),
-                multiview: None,
-            });
-
-        let pipeline_textured =
-            gpu.device
-                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                    label: None,
-                    layout: Some(&textured_layout),
-                    vertex: wgpu::VertexState {
-                        module: &textured_shader,
-                        entry_point: "vs_main",
-                        buffers: &[
-                            Mesh::pnuv_vertex_layout(),
-                            Instance::pnuv_model_instance_layout(),
-                        ],
-                    },
-                    fragment: Some(wgpu::FragmentState {
-                        module: &textured_shader,
-                        entry_point: "fs_main",
-                        targets: &[Some(gpu.swapchain_format().into())],
-                    }),
-                    primitive: wgpu::PrimitiveState {
-                        topology: wgpu::PrimitiveTopology::TriangleList,
-                        front_face: wgpu::FrontFace::Ccw,
-                        cull_mode: Some(wgpu::Face::Back),
-                        ..Default::default()
-                    },
-                    depth_stencil: Some(wgpu::DepthStencilState {
-                        format: wgpu::TextureFormat::Depth32Float,
-                        depth_write_enabled: true,
-                        depth_compare: wgpu::CompareFunction::LessEqual,
-                        stencil: Default::default(),
-                        bias: Default::default(),
-                    }),
-                    multisample: wgpu::MultisampleState::default(),
-                    multiview: None,
-                });
-
-        let pipeline_textured_normal =
-            gpu.device
-                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                    label: None,
-                    layout: Some(&textured_normal_layout),
-                    vertex: wgpu::VertexState {
-                        module: &textured_normal_shader,
-                        entry_point: "vs_main",
-                        buffers: &[
-                            Mesh::pntbuv_vertex_layout(),
-                            Instance::pntbuv_model_instance_layout(),
-                        ],
-                    },
-                    fragment: Some(wgpu::FragmentState {
-                        module: &textured_normal_shader,
-                        entry_point: "fs_main",
-                        targets: &[Some(gpu.swapchain_format().into())],
-                    }),
                     primitive: wgpu::PrimitiveState {
                         topology: wgpu::PrimitiveTopology::TriangleList,
                         front_face: wgpu::FrontFace::Ccw,
@@ -230,42 +128,87 @@ impl<'window> PhongPass<'window> {
                     depth_stencil: Some(wgpu::DepthStencilState {
                         format: wgpu::TextureFormat::Depth32Float,
                         depth_write_enabled: true,
-                        depth_compare: wgpu::CompareFunction::LessEqual,
+                        depth_compare: gpu.depth_compare(wgpu::CompareFunction::LessEqual),
                         stencil: Default::default(),
                         bias: Default::default(),
                     }),
                     multisample: wgpu::MultisampleState::default(),
-                    multiview: None,
-                });
-
-        let pipelines = PhongPipelines {
-            solid: pipeline_solid,
-            textured: pipeline_textured,
-            textured_normal: pipeline_textured_normal,
-        };
+                },
+            )
+        })?;
+        drop(material_atlas);
 
         Ok(Self {
             render_ctx,
             lights_bg,
-            lights_buf: light_buf,
+            light_lists_bgl,
+            cluster_lists_bg,
+            tile_lists_bg,
+            clustering_pass,
+            tile_culling_pass,
             pipelines,
         })
     }
 
-    pub fn render(&self, shadow_bg: &wgpu::BindGroup, with_prepass: bool) -> wgpu::SurfaceTexture {
+    fn light_lists_bind_group(
+        gpu: &Gpu,
+        layout: &wgpu::BindGroupLayout,
+        light_lists: &wgpu::Buffer,
+        tile_grid: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("PhongPass::LightListsBindGroup"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: light_lists.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: tile_grid.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    pub fn render(
+        &self,
+        frame: &wgpu::SurfaceTexture,
+        shadow_bg: &wgpu::BindGroup,
+        shadow_filtering: ShadowFiltering,
+        light_culling: LightCulling,
+        with_prepass: bool,
+        clear_color: wgpu::Color,
+    ) {
         let RenderContext {
             gpu,
             scene_uniform,
-            gpu_scene: scene,
-            material_atlas: atlas,
+            light_buffers,
+            gpu_scene,
+            material_atlas,
+            profiler,
             ..
         } = self.render_ctx.as_ref();
+        let scene = gpu_scene.read().unwrap();
+        let atlas = material_atlas.read().unwrap();
+
+        let light_lists_bg = match light_culling {
+            LightCulling::Clustered => {
+                self.clustering_pass.perform(gpu, scene_uniform);
+                &self.cluster_lists_bg
+            }
+            LightCulling::Tiled => {
+                self.tile_culling_pass
+                    .perform(gpu, scene_uniform, light_buffers.lights());
+                &self.tile_lists_bg
+            }
+        };
 
         let mut encoder = gpu
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
 
-        let frame = gpu.current_texture();
         {
             let frame_view = frame
                 .texture
@@ -278,7 +221,7 @@ impl<'window> PhongPass<'window> {
                     view: &frame_view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        load: wgpu::LoadOp::Clear(clear_color),
                         store: wgpu::StoreOp::Store,
                     },
                 })],
@@ -288,64 +231,111 @@ impl<'window> PhongPass<'window> {
                         load: if with_prepass {
                             wgpu::LoadOp::Load
                         } else {
-                            wgpu::LoadOp::Clear(1.0)
+                            wgpu::LoadOp::Clear(gpu.far_depth())
                         },
                         store: wgpu::StoreOp::Store,
                     }),
                     stencil_ops: None,
                 }),
-                timestamp_writes: None,
+                timestamp_writes: profiler.render_pass_writes("Lighting"),
                 occlusion_query_set: None,
             });
 
             rpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
             rpass.set_bind_group(1, &self.lights_bg, &[]);
+            rpass.set_bind_group(2, atlas.bind_group(), &[]);
             rpass.set_bind_group(3, shadow_bg, &[]);
-
-            for draw_call in scene.draw_calls() {
-                match draw_call.vertex_array_type {
-                    MeshVertexArrayType::PNUV => rpass.set_pipeline(&self.pipelines.textured),
-                    MeshVertexArrayType::PNTBUV => {
-                        rpass.set_pipeline(&self.pipelines.textured_normal)
-                    }
-                    MeshVertexArrayType::PN => rpass.set_pipeline(&self.pipelines.solid),
-                };
-
-                rpass.set_bind_group(2, atlas.bind_group(draw_call.material_id), &[]);
+            rpass.set_bind_group(4, light_lists_bg, &[]);
+
+            for batch in scene.draw_batches() {
+                let first = &batch[0];
+                rpass.set_pipeline(
+                    &self.pipelines[(
+                        PhongVariant(first.vertex_array_type),
+                        shadow_filtering,
+                        light_culling,
+                    )],
+                );
 
                 rpass.set_vertex_buffer(
                     0,
                     scene
-                        .vertex_buffer_by_type(draw_call.vertex_array_type)
+                        .vertex_buffer_by_type(first.vertex_array_type)
                         .slice(..),
                 );
                 rpass.set_vertex_buffer(
                     1,
-                    scene
-                        .instance_buffer_by_type(draw_call.instance_type)
-                        .slice(..),
+                    scene.instance_buffer_by_type(first.instance_type).slice(..),
                 );
 
-                if draw_call.indexed {
+                if first.indexed {
                     rpass.set_index_buffer(
                         scene.index_buffer().slice(..),
                         wgpu::IndexFormat::Uint32,
                     );
+                }
 
-                    rpass.draw_indexed_indirect(
-                        scene.indexed_draw_buffer(),
-                        draw_call.draw_buffer_offset,
-                    );
-                } else {
-                    rpass.draw_indirect(
-                        scene.non_indexed_draw_buffer(),
-                        draw_call.draw_buffer_offset,
-                    );
+                for draw_call in batch {
+                    if draw_call.indexed {
+                        rpass.draw_indexed_indirect(
+                            scene.indexed_draw_buffer(),
+                            draw_call.draw_buffer_offset,
+                        );
+                    } else {
+                        rpass.draw_indirect(
+                            scene.non_indexed_draw_buffer(),
+                            draw_call.draw_buffer_offset,
+                        );
+                    }
                 }
             }
         }
 
         gpu.queue.submit(Some(encoder.finish()));
-        frame
+    }
+}
+
+impl RenderPass for PhongPass<'_> {
+    fn name(&self) -> &'static str {
+        "forward::PhongPass"
+    }
+
+    fn enabled(&self, ctx: &FrameContext) -> bool {
+        ctx.settings.pipeline_type == PipelineType::Forward
+    }
+
+    fn resize(&mut self, _size: (u32, u32)) -> Result<()> {
+        let gpu = &self.render_ctx.gpu;
+
+        self.tile_culling_pass.on_resize(gpu);
+        self.tile_lists_bg = Self::light_lists_bind_group(
+            gpu,
+            &self.light_lists_bgl,
+            self.tile_culling_pass.tiles_buffer(),
+            self.tile_culling_pass.grid_buffer(),
+        );
+
+        Ok(())
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        let settings = ctx.settings;
+        let shadows = ctx
+            .shadows
+            .as_deref()
+            .context("shadows have to be rendered before lighting")?;
+
+        Self::render(
+            self,
+            &ctx.frame,
+            shadows,
+            settings.shadows.filtering,
+            settings.active_light_culling(),
+            settings.depth_prepass_enabled,
+            settings.background.clear_color(),
+        );
+        ctx.scene_color = Some((ctx.frame_view(), false));
+
+        Ok(())
     }
 }
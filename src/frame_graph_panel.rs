@@ -0,0 +1,146 @@
+use gpu_basics::render_graph::GraphPass;
+
+const ROW_HEIGHT: f32 = 24.0;
+const NODE_HEIGHT: f32 = 18.0;
+const PASS_WIDTH: f32 = 200.0;
+const RESOURCE_WIDTH: f32 = 150.0;
+// Room between passes and resources, which edges run across.
+const GAP: f32 = 100.0;
+const WRITE_COLOR: egui::Color32 = egui::Color32::from_rgb(230, 150, 60);
+const READ_COLOR: egui::Color32 = egui::Color32::from_rgb(90, 160, 230);
+const TIME_COLOR: egui::Color32 = egui::Color32::from_rgb(200, 70, 70);
+
+/// Passes of the render graph in the order they ran in the last frame, with resources
+/// they write next to them. Edges run from passes to what they write and from resources
+/// to passes reading them. Every pass shows the GPU time it took, with a bar of its share
+/// of the whole frame behind its name.
+///
+/// `timings` are milliseconds of passes by name, see `GpuProfiler::pass_timings`.
+pub fn render(ctx: &egui::Context, passes: &[GraphPass], timings: &[(&'static str, f32)]) {
+    egui::Window::new("Frame Graph")
+        .default_open(false)
+        .show(ctx, |ui| {
+            if passes.is_empty() {
+                ui.label("No frame has been rendered through the graph yet.");
+                return;
+            }
+
+            ui.horizontal(|ui| {
+                ui.colored_label(WRITE_COLOR, "writes");
+                ui.colored_label(READ_COLOR, "reads");
+                if timings.is_empty() {
+                    ui.label("No GPU timings, timestamp queries are not supported.");
+                } else {
+                    let total: f32 = timings.iter().map(|(_, ms)| ms).sum();
+                    ui.label(format!("GPU Total: {:.3} ms", total));
+                }
+            });
+
+            egui::ScrollArea::both().show(ui, |ui| graph(ui, passes, timings));
+        });
+}
+
+fn graph(ui: &mut egui::Ui, passes: &[GraphPass], timings: &[(&'static str, f32)]) {
+    // Every resource has a single writer, so they come in the order they're written.
+    let resources: Vec<&str> = passes
+        .iter()
+        .flat_map(|pass| pass.writes.iter().copied())
+        .collect();
+
+    let rows = passes.len().max(resources.len());
+    let size = egui::vec2(PASS_WIDTH + GAP + RESOURCE_WIDTH, rows as f32 * ROW_HEIGHT);
+    let (response, painter) = ui.allocate_painter(size, egui::Sense::hover());
+    let origin = response.rect.left_top();
+    let visuals = ui.visuals();
+    let stroke = visuals.widgets.noninteractive.bg_stroke;
+
+    let node = |column: f32, width: f32, row: usize| {
+        egui::Rect::from_min_size(
+            origin + egui::vec2(column, row as f32 * ROW_HEIGHT),
+            egui::vec2(width, NODE_HEIGHT),
+        )
+    };
+    let pass_rect = |row| node(0.0, PASS_WIDTH, row);
+    let resource_rect = |row| node(PASS_WIDTH + GAP, RESOURCE_WIDTH, row);
+
+    // Edges of the pass under the cursor stand out, the rest fade.
+    let hovered = response
+        .hover_pos()
+        .and_then(|pos| (0..passes.len()).find(|&i| pass_rect(i).contains(pos)));
+
+    for (i, pass) in passes.iter().enumerate() {
+        let opacity = match hovered {
+            Some(hovered) if hovered != i => 0.15,
+            _ => 1.0,
+        };
+
+        for (names, color, into_pass) in [
+            (&pass.writes, WRITE_COLOR, false),
+            (&pass.reads, READ_COLOR, true),
+        ] {
+            let color = color.gamma_multiply(opacity);
+            for name in names {
+                let Some(row) = resources.iter().position(|resource| resource == name) else {
+                    continue;
+                };
+
+                let from = pass_rect(i).right_center();
+                let to = resource_rect(row).left_center();
+                let bend = egui::vec2(GAP / 2.0, 0.0);
+                painter.add(egui::epaint::CubicBezierShape::from_points_stroke(
+                    [from, from + bend, to - bend, to],
+                    false,
+                    egui::Color32::TRANSPARENT,
+                    egui::Stroke::new(1.5, color),
+                ));
+                // Marks the end the data flows into.
+                painter.circle_filled(if into_pass { from } else { to }, 3.0, color);
+            }
+        }
+    }
+
+    let total: f32 = timings.iter().map(|(_, ms)| ms).sum();
+    for (i, pass) in passes.iter().enumerate() {
+        let rect = pass_rect(i);
+        painter.rect_filled(rect, 3.0, visuals.faint_bg_color);
+
+        let ms = timings
+            .iter()
+            .find(|(name, _)| *name == pass.name)
+            .map(|(_, ms)| *ms);
+        if let Some(ms) = ms {
+            let share = ms / total.max(f32::EPSILON);
+            let mut bar = rect;
+            bar.set_width(rect.width() * share.clamp(0.0, 1.0));
+            painter.rect_filled(bar, 3.0, TIME_COLOR.gamma_multiply(0.6));
+            painter.text(
+                rect.right_center() - egui::vec2(4.0, 0.0),
+                egui::Align2::RIGHT_CENTER,
+                format!("{:.3} ms", ms),
+                egui::FontId::monospace(10.0),
+                visuals.text_color(),
+            );
+        }
+
+        painter.rect_stroke(rect, 3.0, stroke);
+        painter.text(
+            rect.left_center() + egui::vec2(4.0, 0.0),
+            egui::Align2::LEFT_CENTER,
+            pass.name,
+            egui::FontId::proportional(12.0),
+            visuals.strong_text_color(),
+        );
+    }
+
+    for (i, name) in resources.iter().enumerate() {
+        let rect = resource_rect(i);
+        painter.rect(rect, 3.0, visuals.extreme_bg_color, stroke);
+        painter.text(
+            rect.left_center() + egui::vec2(4.0, 0.0),
+            egui::Align2::LEFT_CENTER,
+            *name,
+            egui::FontId::monospace(11.0),
+            visuals.text_color(),
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    collections::VecDeque,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::mpsc::{channel, Receiver},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{gpu::Gpu, screenshot::Readback};
+
+// Frames which can be in flight before recording has to wait for the oldest one.
+const RING_SIZE: usize = 3;
+const FFMPEG_FRAMERATE: u32 = 60;
+
+enum Sink {
+    // Numbered PNGs in a directory.
+    Images(PathBuf),
+    Ffmpeg(Child),
+}
+
+struct InFlight {
+    frame: u64,
+    slot: usize,
+    mapped: Receiver<Result<(), wgpu::BufferAsyncError>>,
+    result: Option<Result<(), wgpu::BufferAsyncError>>,
+}
+
+impl InFlight {
+    fn is_mapped(&mut self) -> bool {
+        if self.result.is_none() {
+            self.result = self.mapped.try_recv().ok();
+        }
+
+        self.result.is_some()
+    }
+}
+
+/// Copies every recorded frame into a ring of readback buffers and writes them out
+/// once mapped, so the GPU doesn't have to finish the frame before the next one starts.
+pub struct FrameRecorder {
+    readback: Readback,
+    buffers: Vec<wgpu::Buffer>,
+    in_flight: VecDeque<InFlight>,
+    next_frame: u64,
+    sink: Sink,
+}
+
+impl FrameRecorder {
+    /// Starts recording frames of the size of `texture`. Paths ending with `.mp4`, `.mkv`
+    /// or `.webm` are encoded by ffmpeg, which has to be on `PATH`, anything else is a directory
+    /// for numbered PNGs.
+    pub fn new(gpu: &Gpu, texture: &wgpu::Texture, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let readback = Readback::new(texture)?;
+
+        let is_video = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ["mp4", "mkv", "webm"].contains(&ext));
+
+        let sink = if is_video {
+            let ffmpeg = Command::new("ffmpeg")
+                .args(["-y", "-loglevel", "error", "-f", "rawvideo"])
+                .args(["-pixel_format", "rgba"])
+                .args([
+                    "-video_size",
+                    &format!("{}x{}", readback.width, readback.height),
+                ])
+                .args(["-framerate", &FFMPEG_FRAMERATE.to_string()])
+                .args(["-i", "-", "-pix_fmt", "yuv420p"])
+                .arg(path)
+                .stdin(Stdio::piped())
+                .spawn()
+                .context("Failed to start ffmpeg")?;
+
+            Sink::Ffmpeg(ffmpeg)
+        } else {
+            std::fs::create_dir_all(path)?;
+            Sink::Images(path.to_owned())
+        };
+
+        let buffers = (0..RING_SIZE)
+            .map(|_| {
+                gpu.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("FrameRecorder::ReadbackBuffer"),
+                    size: readback.buffer_size(),
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect();
+
+        Ok(Self {
+            readback,
+            buffers,
+            in_flight: VecDeque::new(),
+            next_frame: 0,
+            sink,
+        })
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.next_frame
+    }
+
+    /// Queues a copy of the frame and writes out frames whose copies already finished.
+    pub fn record(&mut self, gpu: &Gpu, texture: &wgpu::Texture) -> Result<()> {
+        if (texture.width(), texture.height()) != (self.readback.width, self.readback.height) {
+            bail!("frame size changed while recording");
+        }
+
+        if self.in_flight.len() == RING_SIZE {
+            gpu.device.poll(wgpu::Maintain::Wait);
+            self.write_oldest()?;
+        }
+
+        let slot = (self.next_frame % RING_SIZE as u64) as usize;
+        let buffer = &self.buffers[slot];
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("FrameRecorder::CommandEncoder"),
+            });
+        self.readback.copy(&mut encoder, texture, buffer);
+        gpu.queue.submit(Some(encoder.finish()));
+
+        let (sender, mapped) = channel();
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                sender.send(result).ok();
+            });
+
+        self.in_flight.push_back(InFlight {
+            frame: self.next_frame,
+            slot,
+            mapped,
+            result: None,
+        });
+        self.next_frame += 1;
+
+        gpu.device.poll(wgpu::Maintain::Poll);
+        // Frames are written in order, so a finished copy waits for older ones.
+        while self.in_flight.front_mut().is_some_and(InFlight::is_mapped) {
+            self.write_oldest()?;
+        }
+
+        Ok(())
+    }
+
+    // The device has to be polled before, so the oldest frame is mapped.
+    fn write_oldest(&mut self) -> Result<()> {
+        let mut oldest = self
+            .in_flight
+            .pop_front()
+            .ok_or_else(|| anyhow!("no frames in flight"))?;
+
+        oldest.is_mapped();
+        match oldest.result.take() {
+            Some(result) => result?,
+            None => bail!("frame {} wasn't copied yet", oldest.frame),
+        }
+        let InFlight { frame, slot, .. } = oldest;
+
+        let buffer = &self.buffers[slot];
+        let pixels = self.readback.pixels(&buffer.slice(..).get_mapped_range());
+        buffer.unmap();
+
+        match &mut self.sink {
+            Sink::Images(dir) => self
+                .readback
+                .save(pixels, dir.join(format!("frame-{:05}.png", frame)))?,
+            Sink::Ffmpeg(ffmpeg) => ffmpeg
+                .stdin
+                .as_mut()
+                .ok_or_else(|| anyhow!("ffmpeg input is closed"))?
+                .write_all(&pixels)
+                .context("Failed to pipe frame to ffmpeg")?,
+        }
+
+        Ok(())
+    }
+
+    /// Writes out remaining frames and waits for ffmpeg to finish encoding.
+    pub fn finish(mut self, gpu: &Gpu) -> Result<()> {
+        gpu.device.poll(wgpu::Maintain::Wait);
+        while !self.in_flight.is_empty() {
+            self.write_oldest()?;
+        }
+
+        if let Sink::Ffmpeg(mut ffmpeg) = self.sink {
+            drop(ffmpeg.stdin.take());
+            let status = ffmpeg.wait()?;
+            if !status.success() {
+                bail!("ffmpeg failed with {}", status);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use gpu_basics::render_context::RenderContext;
+
+// Frames averaged in displayed times.
+const HISTORY: usize = 120;
+
+/// CPU side statistics of recent frames and of the scene they render.
+pub struct FrameStats {
+    frame_times: VecDeque<Duration>,
+    encode_times: VecDeque<Duration>,
+    encode_start: Option<Instant>,
+    draw_calls: usize,
+    instances: usize,
+    buffer_size: u64,
+    texture_size: u64,
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self {
+            frame_times: VecDeque::with_capacity(HISTORY),
+            encode_times: VecDeque::with_capacity(HISTORY),
+            encode_start: None,
+            draw_calls: 0,
+            instances: 0,
+            buffer_size: 0,
+            texture_size: 0,
+        }
+    }
+}
+
+impl FrameStats {
+    fn push(times: &mut VecDeque<Duration>, time: Duration) {
+        if times.len() == HISTORY {
+            times.pop_front();
+        }
+        times.push_back(time);
+    }
+
+    fn average_ms(times: &VecDeque<Duration>) -> f32 {
+        if times.is_empty() {
+            return 0.0;
+        }
+
+        times.iter().sum::<Duration>().as_secs_f32() * 1000.0 / times.len() as f32
+    }
+
+    pub fn frame_time(&mut self, time: Duration) {
+        Self::push(&mut self.frame_times, time);
+    }
+
+    /// Marks the point where passes start recording commands for the frame.
+    pub fn begin_encode(&mut self) {
+        self.encode_start = Some(Instant::now());
+    }
+
+    /// Marks the point where the frame is ready to be presented and samples the scene.
+    pub fn end_encode(&mut self, render_ctx: &RenderContext) {
+        if let Some(start) = self.encode_start.take() {
+            Self::push(&mut self.encode_times, start.elapsed());
+        }
+
+        let gpu_scene = render_ctx.gpu_scene.read().unwrap();
+        self.draw_calls = gpu_scene.draw_calls().len();
+        self.instances = gpu_scene.num_instances();
+        self.buffer_size = gpu_scene.buffer_size();
+        self.texture_size = render_ctx.material_atlas.read().unwrap().texture_size();
+    }
+
+    pub fn render(&self, ctx: &egui::Context) {
+        fn megabytes(bytes: u64) -> String {
+            format!("{:.2} MB", bytes as f64 / (1024.0 * 1024.0))
+        }
+
+        let frame_ms = Self::average_ms(&self.frame_times);
+        let max_frame_ms = self
+            .frame_times
+            .iter()
+            .max()
+            .map_or(0.0, |time| time.as_secs_f32() * 1000.0);
+
+        egui::Window::new("Frame Stats")
+            .default_open(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("frame_stats").show(ui, |ui| {
+                    let rows = [
+                        ("Frame time", format!("{:.2} ms", frame_ms)),
+                        ("Slowest frame", format!("{:.2} ms", max_frame_ms)),
+                        (
+                            "CPU encode time",
+                            format!("{:.2} ms", Self::average_ms(&self.encode_times)),
+                        ),
+                        ("Draw calls", self.draw_calls.to_string()),
+                        ("Instances", self.instances.to_string()),
+                        ("Scene buffers", megabytes(self.buffer_size)),
+                        ("Material textures", megabytes(self.texture_size)),
+                    ];
+
+                    for (name, value) in rows {
+                        ui.label(name);
+                        ui.label(value);
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+}
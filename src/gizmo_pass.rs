@@ -0,0 +1,205 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use nalgebra as na;
+use winit::dpi::PhysicalPosition;
+
+use crate::{
+    render_context::RenderContext,
+    render_pass::{FrameContext, RenderPass},
+};
+
+// Size of the gizmo viewport and its distance from the top right corner, in pixels.
+const GIZMO_SIZE: u32 = 96;
+const GIZMO_MARGIN: u32 = 16;
+// Length of axes in gizmo clip space.
+const AXIS_EXTENT: f32 = 0.8;
+// How close the cursor has to be to the end of an axis to pick it, in pixels.
+const PICK_RADIUS: f64 = 12.0;
+
+const AXES: [([f32; 3], [f32; 3]); 3] = [
+    ([1.0, 0.0, 0.0], [0.9, 0.2, 0.2]),
+    ([0.0, 1.0, 0.0], [0.2, 0.8, 0.2]),
+    ([0.0, 0.0, 1.0], [0.2, 0.4, 0.9]),
+];
+
+// Both ends of every axis with their colors. Negative ends are dimmed.
+fn axis_ends() -> impl Iterator<Item = (na::Vector3<f32>, [f32; 3])> {
+    AXES.into_iter().flat_map(|(axis, [r, g, b])| {
+        let axis = na::Vector3::from(axis);
+        [(axis, [r, g, b]), (-axis, [r * 0.4, g * 0.4, b * 0.4])]
+    })
+}
+
+/// Axes showing the camera orientation, drawn in a small viewport in the corner of the screen.
+/// Clicking an end of an axis snaps the camera to look along it.
+pub struct GizmoPass<'window> {
+    render_ctx: Arc<RenderContext<'window>>,
+    pipeline: wgpu::RenderPipeline,
+    vbuf: wgpu::Buffer,
+    vertex_count: u32,
+}
+
+impl<'window> GizmoPass<'window> {
+    pub fn new(render_ctx: Arc<RenderContext<'window>>) -> Result<Self> {
+        let RenderContext {
+            gpu,
+            shader_compiler,
+            scene_uniform,
+            ..
+        } = render_ctx.as_ref();
+
+        let vertices: Vec<[f32; 6]> = axis_ends()
+            .flat_map(|(end, [r, g, b])| {
+                let end = end * AXIS_EXTENT;
+                [[0.0, 0.0, 0.0, r, g, b], [end.x, end.y, end.z, r, g, b]]
+            })
+            .collect();
+
+        use wgpu::util::DeviceExt;
+        let vbuf = gpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("GizmoPass::VertexBuffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+
+        let shader = gpu.shader_from_module(
+            shader_compiler
+                .compilation_unit("./shaders/gizmo.wgsl")?
+                .compile(&[])?,
+        );
+
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("GizmoPass::PipelineLayout"),
+                bind_group_layouts: &[scene_uniform.layout()],
+                push_constant_ranges: &[],
+            });
+
+        let pipeline = gpu
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("GizmoPass::Pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<[f32; 6]>() as u64,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3],
+                    }],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: gpu.swapchain_format(),
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                depth_stencil: None,
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    ..Default::default()
+                },
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            });
+
+        Ok(Self {
+            render_ctx,
+            pipeline,
+            vbuf,
+            vertex_count: vertices.len() as u32,
+        })
+    }
+
+    // Top left corner and size of the gizmo viewport.
+    fn viewport(&self) -> (f32, f32, f32) {
+        let size = self.render_ctx.gpu.viewport_size();
+
+        (
+            size.width.saturating_sub(GIZMO_SIZE + GIZMO_MARGIN) as f32,
+            GIZMO_MARGIN as f32,
+            GIZMO_SIZE as f32,
+        )
+    }
+
+    /// Returns the axis whose end is under the cursor.
+    /// If ends overlap, the one closer to the viewer wins.
+    pub fn axis_at(
+        &self,
+        cursor: PhysicalPosition<f64>,
+        view: &na::Matrix4<f32>,
+    ) -> Option<na::Vector3<f32>> {
+        let (x, y, size) = self.viewport();
+        let rotation = view.fixed_view::<3, 3>(0, 0);
+
+        axis_ends()
+            .filter_map(|(end, _)| {
+                let projected = rotation * end * AXIS_EXTENT;
+                let screen_x = x + (projected.x * 0.5 + 0.5) * size;
+                let screen_y = y + (0.5 - projected.y * 0.5) * size;
+
+                let distance = (cursor.x - screen_x as f64).hypot(cursor.y - screen_y as f64);
+                (distance <= PICK_RADIUS).then_some((end, projected.z))
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(end, _)| end)
+    }
+
+    pub fn render(&self, frame: &wgpu::SurfaceTexture) {
+        let RenderContext {
+            gpu, scene_uniform, ..
+        } = self.render_ctx.as_ref();
+
+        let view = frame.texture.create_view(&Default::default());
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("GizmoPass::CommandEncoder"),
+            });
+
+        {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("GizmoPass::RenderPass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            let (x, y, size) = self.viewport();
+            rpass.set_viewport(x, y, size, size, 0.0, 1.0);
+            rpass.set_pipeline(&self.pipeline);
+            rpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
+            rpass.set_vertex_buffer(0, self.vbuf.slice(..));
+            rpass.draw(0..self.vertex_count, 0..1);
+        }
+
+        gpu.queue.submit(Some(encoder.finish()));
+    }
+}
+
+impl RenderPass for GizmoPass<'_> {
+    fn name(&self) -> &'static str {
+        "GizmoPass"
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        Self::render(self, &ctx.frame);
+        Ok(())
+    }
+}
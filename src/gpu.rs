@@ -1,9 +1,24 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
+use clap::ValueEnum;
 use encase::{ShaderSize, UniformBuffer};
 use nalgebra as na;
-use std::{borrow::Cow, num::NonZeroU64, path::Path};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    num::NonZeroU64,
+    path::Path,
+    sync::{Arc, Mutex, RwLock},
+};
 
 const MAT4_SIZE: NonZeroU64 = na::Matrix4::<f32>::SHADER_SIZE;
+/// Bytes of push constants requested from adapters supporting them, the least
+/// Vulkan guarantees. Enough for a model matrix and a few scalars.
+pub const PUSH_CONSTANT_SIZE: u32 = 128;
+// Deferred lighting binds the scene, lights, shadows, the G-Buffer and its output,
+// one more than the default limit. Desktop adapters support at least 8.
+const MAX_BIND_GROUPS: u32 = 5;
+// Staging memory allocated at once, bigger uploads get a chunk of their own.
+const UPLOAD_BELT_CHUNK_SIZE: u64 = 1024 * 1024;
 
 pub struct Gpu<'window> {
     pub instance: wgpu::Instance,
@@ -11,34 +26,155 @@ pub struct Gpu<'window> {
     pub adapter: wgpu::Adapter,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
-    pub surface_config: wgpu::SurfaceConfiguration,
-    pub depth_tex: wgpu::Texture,
+    // Behind locks, so the surface can be reconfigured while the Gpu is shared.
+    surface_config: RwLock<wgpu::SurfaceConfiguration>,
+    depth_tex: RwLock<wgpu::Texture>,
+    // Set by the device lost callback, with the reason.
+    lost: Arc<Mutex<Option<String>>>,
+    reverse_z: bool,
+    uploads: Mutex<Uploads>,
+}
+
+// Copies staged since the last `Gpu::flush_uploads`, recorded into an encoder of their own.
+struct Uploads {
+    belt: wgpu::util::StagingBelt,
+    encoder: Option<wgpu::CommandEncoder>,
+}
+
+/// How finished frames are handed over to the display.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, ValueEnum)]
+pub enum PresentMode {
+    /// Waits for the vertical blank, frames never tear. Supported everywhere.
+    #[default]
+    Fifo,
+    /// Waits for the vertical blank too, but newer frames replace the queued one.
+    Mailbox,
+    /// Presents frames as soon as they're ready, even if it tears.
+    Immediate,
+}
+
+impl PresentMode {
+    pub const ALL: [Self; 3] = [Self::Fifo, Self::Mailbox, Self::Immediate];
+
+    fn wgpu(self) -> wgpu::PresentMode {
+        match self {
+            Self::Fifo => wgpu::PresentMode::Fifo,
+            Self::Mailbox => wgpu::PresentMode::Mailbox,
+            Self::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, ValueEnum)]
+pub enum AdapterPreference {
+    #[default]
+    Discrete,
+    Integrated,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, ValueEnum)]
+pub enum Backend {
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+}
+
+impl Backend {
+    fn wgpu(self) -> wgpu::Backends {
+        match self {
+            Self::Vulkan => wgpu::Backends::VULKAN,
+            Self::Metal => wgpu::Backends::METAL,
+            Self::Dx12 => wgpu::Backends::DX12,
+            Self::Gl => wgpu::Backends::GL,
+        }
+    }
+}
+
+/// Which GPU to render with, taking effect on the next start.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdapterOptions {
+    // Case insensitive part of the adapter name, chosen over the preference.
+    // The preference picks the adapter when none of them matches.
+    pub name: Option<String>,
+    pub preference: AdapterPreference,
+    // Every backend of the platform is tried when missing.
+    pub backend: Option<Backend>,
+}
+
+#[derive(Default)]
+pub struct GpuOptions {
+    // Falls back to Fifo when the surface doesn't support it.
+    pub present_mode: PresentMode,
+    pub adapter: AdapterOptions,
+    // Pipelines are built for it once, so it can't change while running.
+    pub reverse_z: bool,
 }
 
 use winit::window::Window;
 
-use crate::shader_compiler::CompilationUnit;
+use crate::{
+    shader_compiler::CompilationUnit,
+    shader_permutation::{ShaderPermutation, Variants},
+};
 
 impl<'window> Gpu<'window> {
-    pub async fn from_window(window: &'window Window) -> Result<Self> {
-        let instance = wgpu::Instance::default();
+    pub async fn from_window(window: &'window Window, options: &GpuOptions) -> Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: options
+                .adapter
+                .backend
+                .map_or(wgpu::Backends::all(), Backend::wgpu),
+            ..Default::default()
+        });
 
         let surface = instance.create_surface(window)?;
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .ok_or(anyhow::anyhow!("No adapter found"))?;
+        let named = options
+            .adapter
+            .name
+            .as_deref()
+            .and_then(|name| Self::find_adapter(&instance, &surface, name));
+        let adapter = match named {
+            Some(adapter) => adapter,
+            None => instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: match options.adapter.preference {
+                        AdapterPreference::Discrete => wgpu::PowerPreference::HighPerformance,
+                        AdapterPreference::Integrated => wgpu::PowerPreference::LowPower,
+                    },
+                    compatible_surface: Some(&surface),
+                    force_fallback_adapter: false,
+                })
+                .await
+                .ok_or(anyhow::anyhow!("No adapter found"))?,
+        };
+
+        let max_bind_groups = adapter.limits().max_bind_groups;
+        if max_bind_groups < MAX_BIND_GROUPS {
+            bail!(
+                "{} supports {max_bind_groups} bind groups, deferred lighting needs {MAX_BIND_GROUPS}",
+                adapter.get_info().name
+            );
+        }
+
+        let push_constants = adapter.features().contains(wgpu::Features::PUSH_CONSTANTS)
+            && adapter.limits().max_push_constant_size >= PUSH_CONSTANT_SIZE;
 
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
                     required_features: adapter.features(),
-                    required_limits: wgpu::Limits::default(),
+                    required_limits: wgpu::Limits {
+                        max_bind_groups: MAX_BIND_GROUPS,
+                        max_push_constant_size: if push_constants {
+                            PUSH_CONSTANT_SIZE
+                        } else {
+                            0
+                        },
+                        ..Default::default()
+                    },
                 },
                 None,
             )
@@ -62,49 +198,55 @@ impl<'window> Gpu<'window> {
             format: swapchain_format,
             width: window.inner_size().width,
             height: window.inner_size().height,
-            present_mode: wgpu::PresentMode::Fifo,
+            present_mode: if swapchain_capabilities
+                .present_modes
+                .contains(&options.present_mode.wgpu())
+            {
+                options.present_mode.wgpu()
+            } else {
+                wgpu::PresentMode::Fifo
+            },
             alpha_mode: swapchain_capabilities.alpha_modes[0],
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
         };
 
-        let depth_tex = device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: wgpu::Extent3d {
-                width: surface_config.width,
-                height: surface_config.height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth32Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
+        let depth_tex =
+            Self::create_depth_texture(&device, (surface_config.width, surface_config.height));
 
         surface.configure(&device, &surface_config);
 
+        let lost = Arc::new(Mutex::new(None));
+        device.set_device_lost_callback({
+            let lost = lost.clone();
+            move |reason, message| {
+                *lost.lock().unwrap() = Some(format!("{message} ({reason:?})"));
+            }
+        });
+
         Ok(Gpu {
             instance,
             surface,
             adapter,
             device,
             queue,
-            surface_config,
-            depth_tex,
+            surface_config: RwLock::new(surface_config),
+            depth_tex: RwLock::new(depth_tex),
+            lost,
+            reverse_z: options.reverse_z,
+            uploads: Mutex::new(Uploads {
+                belt: wgpu::util::StagingBelt::new(UPLOAD_BELT_CHUNK_SIZE),
+                encoder: None,
+            }),
         })
     }
 
-    pub fn on_resize(&mut self, new_size: (u32, u32)) {
-        self.surface_config.width = new_size.0;
-        self.surface_config.height = new_size.1;
-        self.surface.configure(&self.device, &self.surface_config);
-        self.depth_tex = self.device.create_texture(&wgpu::TextureDescriptor {
+    fn create_depth_texture(device: &wgpu::Device, size: (u32, u32)) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
             label: None,
             size: wgpu::Extent3d {
-                width: new_size.0,
-                height: new_size.1,
+                width: size.0,
+                height: size.1,
                 depth_or_array_layers: 1,
             },
             mip_level_count: 1,
@@ -113,17 +255,61 @@ impl<'window> Gpu<'window> {
             format: wgpu::TextureFormat::Depth32Float,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[],
-        });
+        })
+    }
+
+    fn find_adapter(
+        instance: &wgpu::Instance,
+        surface: &wgpu::Surface,
+        name: &str,
+    ) -> Option<wgpu::Adapter> {
+        let needle = name.to_lowercase();
+        instance
+            .enumerate_adapters(wgpu::Backends::all())
+            .into_iter()
+            .filter(|adapter| adapter.is_surface_supported(surface))
+            .find(|adapter| adapter.get_info().name.to_lowercase().contains(&needle))
+    }
+
+    /// Like `set_present_mode`, has to be called with no frame acquired from the surface.
+    pub fn on_resize(&self, new_size: (u32, u32)) {
+        let mut surface_config = self.surface_config.write().unwrap();
+        surface_config.width = new_size.0;
+        surface_config.height = new_size.1;
+        self.surface.configure(&self.device, &surface_config);
+        *self.depth_tex.write().unwrap() = Self::create_depth_texture(&self.device, new_size);
     }
 
     pub fn viewport_size(&self) -> wgpu::Extent3d {
+        let surface_config = self.surface_config.read().unwrap();
         wgpu::Extent3d {
-            width: self.surface_config.width,
-            height: self.surface_config.height,
+            width: surface_config.width,
+            height: surface_config.height,
             depth_or_array_layers: 1,
         }
     }
 
+    pub fn present_mode(&self) -> PresentMode {
+        match self.surface_config.read().unwrap().present_mode {
+            wgpu::PresentMode::Mailbox => PresentMode::Mailbox,
+            wgpu::PresentMode::Immediate => PresentMode::Immediate,
+            _ => PresentMode::Fifo,
+        }
+    }
+
+    /// Reconfigures the surface. Frames acquired from it have to be presented before.
+    pub fn set_present_mode(&self, present_mode: PresentMode) -> Result<()> {
+        let supported = self.surface.get_capabilities(&self.adapter).present_modes;
+        if !supported.contains(&present_mode.wgpu()) {
+            bail!("{present_mode:?} isn't supported by the surface, available: {supported:?}");
+        }
+
+        let mut surface_config = self.surface_config.write().unwrap();
+        surface_config.present_mode = present_mode.wgpu();
+        self.surface.configure(&self.device, &surface_config);
+        Ok(())
+    }
+
     pub fn shader_from_code(&self, code: &str) -> wgpu::ShaderModule {
         self.device
             .create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -140,29 +326,110 @@ impl<'window> Gpu<'window> {
             })
     }
 
-    pub fn shader_per_vertex_type(
+    /// Shader module of every variant of `module` in the permutation space `P`.
+    pub fn shader_variants<P: ShaderPermutation>(
         &self,
         module: &CompilationUnit,
-    ) -> Result<(wgpu::ShaderModule, wgpu::ShaderModule, wgpu::ShaderModule)> {
-        Ok((
-            self.shader_from_module(module.compile(&["VERTEX_PN"])?),
-            self.shader_from_module(module.compile(&["VERTEX_PNUV"])?),
-            self.shader_from_module(module.compile(&["VERTEX_PNTBUV"])?),
-        ))
+    ) -> Result<Variants<P, wgpu::ShaderModule>> {
+        Ok(module
+            .compile_variants::<P>(&[])?
+            .map(|_, module| self.shader_from_module(module)))
     }
 
     pub fn aspect_ratio(&self) -> f32 {
-        self.surface_config.width as f32 / self.surface_config.height as f32
+        let surface_config = self.surface_config.read().unwrap();
+        surface_config.width as f32 / surface_config.height as f32
+    }
+
+    /// Next frame to render to, `None` when this one has to be skipped. Outdated
+    /// and lost surfaces are reconfigured before trying again.
+    pub fn current_texture(&self) -> Result<Option<wgpu::SurfaceTexture>> {
+        match self.surface.get_current_texture() {
+            Ok(frame) => Ok(Some(frame)),
+            Err(wgpu::SurfaceError::Timeout) => Ok(None),
+            Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                self.surface
+                    .configure(&self.device, &self.surface_config.read().unwrap());
+
+                match self.surface.get_current_texture() {
+                    Ok(frame) => Ok(Some(frame)),
+                    Err(wgpu::SurfaceError::OutOfMemory) => {
+                        bail!("out of memory acquiring a frame")
+                    }
+                    // Given another go with the next frame.
+                    Err(_) => Ok(None),
+                }
+            }
+            Err(wgpu::SurfaceError::OutOfMemory) => bail!("out of memory acquiring a frame"),
+        }
+    }
+
+    /// Why the device was lost, if it was. Nothing can be rendered with it anymore.
+    pub fn lost(&self) -> Option<String> {
+        self.lost.lock().unwrap().clone()
+    }
+
+    /// Whether depth goes from 1 at the near plane to 0 at the far one, spreading
+    /// the float precision more evenly over the view distance.
+    pub fn reverse_z(&self) -> bool {
+        self.reverse_z
+    }
+
+    /// Depth test written for the standard depth range, flipped for reverse-Z.
+    pub fn depth_compare(&self, compare: wgpu::CompareFunction) -> wgpu::CompareFunction {
+        use wgpu::CompareFunction::*;
+
+        if !self.reverse_z {
+            return compare;
+        }
+
+        match compare {
+            Less => Greater,
+            LessEqual => GreaterEqual,
+            Greater => Less,
+            GreaterEqual => LessEqual,
+            other => other,
+        }
     }
 
-    pub fn current_texture(&self) -> wgpu::SurfaceTexture {
-        self.surface
-            .get_current_texture()
-            .expect("Failed to acquire next swap chain texture!")
+    /// Depth of the far plane, which depth buffers are cleared to.
+    pub fn far_depth(&self) -> f32 {
+        if self.reverse_z {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    /// Adds `REVERSE_Z` for shaders placing things on the far plane or testing against it.
+    pub fn with_depth_defs(&self, unit: CompilationUnit) -> CompilationUnit {
+        if self.reverse_z {
+            unit.with_def("REVERSE_Z")
+        } else {
+            unit
+        }
+    }
+
+    /// Whether per-draw data can be pushed with `set_push_constants` instead of being
+    /// written to buffers, up to `PUSH_CONSTANT_SIZE` bytes of it.
+    pub fn push_constants(&self) -> bool {
+        self.device.limits().max_push_constant_size >= PUSH_CONSTANT_SIZE
+    }
+
+    /// Adds `PUSH_CONSTANTS` for shaders reading per-draw data from push constants,
+    /// when the device supports them.
+    pub fn with_push_constant_defs(&self, unit: CompilationUnit) -> CompilationUnit {
+        if self.push_constants() {
+            unit.with_def("PUSH_CONSTANTS")
+        } else {
+            unit
+        }
     }
 
     pub fn depth_texture_view(&self) -> wgpu::TextureView {
         self.depth_tex
+            .read()
+            .unwrap()
             .create_view(&wgpu::TextureViewDescriptor::default())
     }
 
@@ -173,7 +440,103 @@ impl<'window> Gpu<'window> {
     }
 
     pub fn swapchain_format(&self) -> wgpu::TextureFormat {
-        self.surface_config.format
+        self.surface_config.read().unwrap().format
+    }
+
+    fn with_upload_encoder<T>(
+        &self,
+        record: impl FnOnce(&mut wgpu::util::StagingBelt, &mut wgpu::CommandEncoder) -> T,
+    ) -> T {
+        let mut uploads = self.uploads.lock().unwrap();
+        let Uploads { belt, encoder } = &mut *uploads;
+        let encoder = encoder.get_or_insert_with(|| {
+            self.device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Gpu::Uploads"),
+                })
+        });
+
+        record(belt, encoder)
+    }
+
+    /// Stages `contents` to be copied to `buffer` at `offset` by the next `flush_uploads`.
+    /// Unlike `Queue::write_buffer` it goes through a staging belt, so staging memory is
+    /// reused between frames instead of being allocated for every write.
+    /// Both `offset` and the size must be multiples of `wgpu::COPY_BUFFER_ALIGNMENT`.
+    pub fn upload_buffer(&self, buffer: &wgpu::Buffer, offset: u64, contents: &[u8]) {
+        let Some(size) = NonZeroU64::new(contents.len() as u64) else {
+            return;
+        };
+
+        self.with_upload_encoder(|belt, encoder| {
+            belt.write_buffer(encoder, buffer, offset, size, &self.device)
+                .copy_from_slice(contents);
+        });
+    }
+
+    /// Stages tightly packed rows of `data` to be copied to `destination` by the next
+    /// `flush_uploads`, along with buffer uploads. Rows are padded for the copy
+    /// in a staging buffer of its own. Only for formats without blocks.
+    pub fn upload_texture(
+        &self,
+        destination: wgpu::ImageCopyTexture,
+        data: &[u8],
+        bytes_per_row: u32,
+        size: wgpu::Extent3d,
+    ) {
+        let rows = (size.height * size.depth_or_array_layers) as usize;
+        if rows == 0 || bytes_per_row == 0 {
+            return;
+        }
+
+        let padded_bytes_per_row =
+            wgpu::util::align_to(bytes_per_row, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Gpu::TextureUpload"),
+            size: padded_bytes_per_row as u64 * rows as u64,
+            usage: wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: true,
+        });
+        {
+            let mut mapped = staging.slice(..).get_mapped_range_mut();
+            for (src, dst) in data
+                .chunks_exact(bytes_per_row as usize)
+                .zip(mapped.chunks_exact_mut(padded_bytes_per_row as usize))
+            {
+                dst[..src.len()].copy_from_slice(src);
+            }
+        }
+        staging.unmap();
+
+        self.with_upload_encoder(|_, encoder| {
+            encoder.copy_buffer_to_texture(
+                wgpu::ImageCopyBuffer {
+                    buffer: &staging,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(padded_bytes_per_row),
+                        rows_per_image: Some(size.height),
+                    },
+                },
+                destination,
+                size,
+            );
+        });
+    }
+
+    /// Submits copies staged since the last call, before commands reading them are.
+    /// Called once a frame ahead of its passes, and by anything copying out of
+    /// resources which might have uploads pending.
+    pub fn flush_uploads(&self) {
+        let mut uploads = self.uploads.lock().unwrap();
+        let Some(encoder) = uploads.encoder.take() else {
+            return;
+        };
+
+        uploads.belt.finish();
+        self.queue.submit(Some(encoder.finish()));
+        uploads.belt.recall();
     }
 }
 
@@ -3,7 +3,7 @@ use fnv::FnvHashMap;
 use std::sync::RwLock;
 
 use crate::camera::GpuCamera;
-use crate::gpu::Gpu;
+use crate::gpu::{Gpu, GpuOptions};
 use crate::material::MaterialAtlas;
 use crate::projection::GpuProjection;
 use crate::scene::GpuScene;
@@ -41,7 +41,7 @@ impl<'window> RenderContext<'window> {
         projection: GpuProjection,
         scene: GpuScene,
     ) -> Result<Self> {
-        let gpu = Gpu::from_window(window).await?;
+        let gpu = Gpu::from_window(window, &GpuOptions::default()).await?;
         let shader_compiler = ShaderCompiler::new("./shaders")?;
         let scene_uniform = SceneUniform::new(&gpu, &camera, &projection);
         let material_atlas = MaterialAtlas::new(&gpu);
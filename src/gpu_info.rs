@@ -0,0 +1,132 @@
+use gpu_basics::gpu::Gpu;
+
+/// "About GPU" window, describing the adapter frames are rendered with and what
+/// else could be chosen instead.
+pub struct GpuInfo {
+    adapter: wgpu::AdapterInfo,
+    limits: Vec<(&'static str, String)>,
+    features: Vec<&'static str>,
+    // Adapters able to present to the window, the one in use among them.
+    available: Vec<String>,
+}
+
+impl GpuInfo {
+    pub fn new(gpu: &Gpu) -> Self {
+        let limits = gpu.device.limits();
+        let limits = vec![
+            (
+                "Texture size 2D",
+                limits.max_texture_dimension_2d.to_string(),
+            ),
+            (
+                "Texture layers",
+                limits.max_texture_array_layers.to_string(),
+            ),
+            ("Bind groups", limits.max_bind_groups.to_string()),
+            (
+                "Sampled textures per stage",
+                limits.max_sampled_textures_per_shader_stage.to_string(),
+            ),
+            (
+                "Storage textures per stage",
+                limits.max_storage_textures_per_shader_stage.to_string(),
+            ),
+            (
+                "Uniform buffer binding",
+                limits.max_uniform_buffer_binding_size.to_string(),
+            ),
+            (
+                "Storage buffer binding",
+                limits.max_storage_buffer_binding_size.to_string(),
+            ),
+            (
+                "Workgroup size",
+                format!(
+                    "{}x{}x{} ({} invocations)",
+                    limits.max_compute_workgroup_size_x,
+                    limits.max_compute_workgroup_size_y,
+                    limits.max_compute_workgroup_size_z,
+                    limits.max_compute_invocations_per_workgroup
+                ),
+            ),
+            ("Push constants", limits.max_push_constant_size.to_string()),
+        ];
+
+        let available = gpu
+            .instance
+            .enumerate_adapters(wgpu::Backends::all())
+            .into_iter()
+            .filter(|adapter| adapter.is_surface_supported(&gpu.surface))
+            .map(|adapter| {
+                let info = adapter.get_info();
+                format!("{} ({:?}, {:?})", info.name, info.backend, info.device_type)
+            })
+            .collect();
+
+        Self {
+            adapter: gpu.adapter.get_info(),
+            limits,
+            features: gpu
+                .device
+                .features()
+                .iter_names()
+                .map(|(name, _)| name)
+                .collect(),
+            available,
+        }
+    }
+
+    pub fn render(&self, ctx: &egui::Context) {
+        egui::Window::new("About GPU")
+            .default_open(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("gpu_info_adapter").show(ui, |ui| {
+                    let rows = [
+                        ("Adapter", self.adapter.name.clone()),
+                        ("Type", format!("{:?}", self.adapter.device_type)),
+                        ("Backend", format!("{:?}", self.adapter.backend)),
+                        (
+                            "Driver",
+                            format!("{} {}", self.adapter.driver, self.adapter.driver_info),
+                        ),
+                    ];
+
+                    for (name, value) in rows {
+                        ui.label(name);
+                        ui.label(value);
+                        ui.end_row();
+                    }
+                });
+
+                ui.collapsing("Limits", |ui| {
+                    egui::Grid::new("gpu_info_limits").show(ui, |ui| {
+                        for (name, value) in &self.limits {
+                            ui.label(*name);
+                            ui.label(value);
+                            ui.end_row();
+                        }
+                    });
+                });
+
+                ui.collapsing(format!("Features ({})", self.features.len()), |ui| {
+                    egui::ScrollArea::vertical()
+                        .max_height(200.0)
+                        .show(ui, |ui| {
+                            for feature in &self.features {
+                                ui.label(*feature);
+                            }
+                        });
+                });
+
+                ui.collapsing("Available Adapters", |ui| {
+                    for adapter in &self.available {
+                        ui.label(adapter);
+                    }
+                    ui.label(
+                        "Picked on start with --adapter, --adapter-preference and --backend, \
+                         or the adapter in saved settings.",
+                    );
+                });
+            });
+    }
+}
@@ -0,0 +1,268 @@
+use std::sync::{
+    mpsc::{channel, Receiver},
+    Mutex,
+};
+
+use crate::gpu::Gpu;
+
+// Every scope takes two queries, one at the beginning and one at the end of a pass.
+const MAX_SCOPES: u32 = 32;
+// Weight of the newest sample in displayed timings, so they don't flicker every frame.
+const SMOOTHING: f32 = 0.1;
+
+struct Queries {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    // Nanoseconds per timestamp tick.
+    period: f32,
+}
+
+#[derive(Clone, Copy)]
+struct Scope {
+    name: &'static str,
+    // Render graph pass which wrote the scope, see `GpuProfiler::enter_pass`.
+    pass: Option<&'static str>,
+}
+
+struct InFlight {
+    scopes: Vec<Scope>,
+    mapped: Receiver<Result<(), wgpu::BufferAsyncError>>,
+}
+
+#[derive(Default)]
+struct ProfilerState {
+    // Scopes written in the current frame, in order of their queries.
+    scopes: Vec<Scope>,
+    current_pass: Option<&'static str>,
+    in_flight: Option<InFlight>,
+    timings: Vec<(&'static str, f32)>,
+    pass_timings: Vec<(&'static str, f32)>,
+}
+
+/// Measures GPU time of passes with timestamp queries written at their beginning and end.
+/// Passes sharing a name are summed up. Results are read back a few frames later,
+/// frames rendered while a readback is in flight are not measured.
+pub struct GpuProfiler {
+    queries: Option<Queries>,
+    state: Mutex<ProfilerState>,
+}
+
+impl GpuProfiler {
+    pub fn new(gpu: &Gpu) -> Self {
+        let queries = gpu
+            .device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+            .then(|| {
+                let size = (MAX_SCOPES * 2) as u64 * std::mem::size_of::<u64>() as u64;
+
+                Queries {
+                    query_set: gpu.device.create_query_set(&wgpu::QuerySetDescriptor {
+                        label: Some("GpuProfiler::QuerySet"),
+                        ty: wgpu::QueryType::Timestamp,
+                        count: MAX_SCOPES * 2,
+                    }),
+                    resolve_buffer: gpu.device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("GpuProfiler::ResolveBuffer"),
+                        size,
+                        usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                        mapped_at_creation: false,
+                    }),
+                    readback_buffer: gpu.device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("GpuProfiler::ReadbackBuffer"),
+                        size,
+                        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                        mapped_at_creation: false,
+                    }),
+                    period: gpu.queue.get_timestamp_period(),
+                }
+            });
+
+        Self {
+            queries,
+            state: Mutex::new(ProfilerState::default()),
+        }
+    }
+
+    // Query set and index of the first of two queries of a new scope.
+    fn begin_scope(&self, name: &'static str) -> Option<(&wgpu::QuerySet, u32)> {
+        let queries = self.queries.as_ref()?;
+        let mut state = self.state.lock().unwrap();
+        if state.in_flight.is_some() || state.scopes.len() as u32 == MAX_SCOPES {
+            return None;
+        }
+
+        let index = state.scopes.len() as u32 * 2;
+        let pass = state.current_pass;
+        state.scopes.push(Scope { name, pass });
+
+        Some((&queries.query_set, index))
+    }
+
+    pub fn render_pass_writes(
+        &self,
+        name: &'static str,
+    ) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        self.begin_scope(name)
+            .map(|(query_set, index)| wgpu::RenderPassTimestampWrites {
+                query_set,
+                beginning_of_pass_write_index: Some(index),
+                end_of_pass_write_index: Some(index + 1),
+            })
+    }
+
+    pub fn compute_pass_writes(
+        &self,
+        name: &'static str,
+    ) -> Option<wgpu::ComputePassTimestampWrites<'_>> {
+        self.begin_scope(name)
+            .map(|(query_set, index)| wgpu::ComputePassTimestampWrites {
+                query_set,
+                beginning_of_pass_write_index: Some(index),
+                end_of_pass_write_index: Some(index + 1),
+            })
+    }
+
+    /// Marks the render graph pass about to write scopes, which are summed up
+    /// in `pass_timings` under its name. Lasts until the end of the frame.
+    pub fn enter_pass(&self, name: &'static str) {
+        self.state.lock().unwrap().current_pass = Some(name);
+    }
+
+    /// Called once all passes of a frame are submitted. Reads back timings of an earlier
+    /// frame if they're ready and starts reading back the current one.
+    pub fn end_frame(&self, gpu: &Gpu) {
+        let Some(queries) = self.queries.as_ref() else {
+            return;
+        };
+        let mut state = self.state.lock().unwrap();
+        state.current_pass = None;
+
+        gpu.device.poll(wgpu::Maintain::Poll);
+        if let Some(in_flight) = state.in_flight.take() {
+            match in_flight.mapped.try_recv() {
+                Ok(Ok(())) => {
+                    Self::update_timings(&mut state, queries, &in_flight.scopes);
+                    queries.readback_buffer.unmap();
+                }
+                Ok(Err(e)) => eprintln!("Failed to read back GPU timings: {}", e),
+                Err(_) => {
+                    state.in_flight = Some(in_flight);
+                    return;
+                }
+            }
+        }
+
+        if state.scopes.is_empty() {
+            return;
+        }
+        let scopes = std::mem::take(&mut state.scopes);
+        let query_count = scopes.len() as u32 * 2;
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("GpuProfiler::CommandEncoder"),
+            });
+        encoder.resolve_query_set(
+            &queries.query_set,
+            0..query_count,
+            &queries.resolve_buffer,
+            0,
+        );
+        encoder.copy_buffer_to_buffer(
+            &queries.resolve_buffer,
+            0,
+            &queries.readback_buffer,
+            0,
+            query_count as u64 * std::mem::size_of::<u64>() as u64,
+        );
+        gpu.queue.submit(Some(encoder.finish()));
+
+        let (sender, mapped) = channel();
+        queries
+            .readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                sender.send(result).ok();
+            });
+
+        state.in_flight = Some(InFlight { scopes, mapped });
+    }
+
+    fn update_timings(state: &mut ProfilerState, queries: &Queries, scopes: &[Scope]) {
+        fn add(frame: &mut Vec<(&'static str, f32)>, name: &'static str, ms: f32) {
+            match frame.iter_mut().find(|(scope, _)| *scope == name) {
+                Some((_, total)) => *total += ms,
+                None => frame.push((name, ms)),
+            }
+        }
+
+        // Passes not rendered anymore, like the ones of the other pipeline, are dropped.
+        fn smooth(timings: &mut Vec<(&'static str, f32)>, frame: Vec<(&'static str, f32)>) {
+            *timings = frame
+                .into_iter()
+                .map(|(name, ms)| {
+                    let previous = timings.iter().find(|(scope, _)| *scope == name);
+                    match previous {
+                        Some((_, previous)) => (name, previous + (ms - previous) * SMOOTHING),
+                        None => (name, ms),
+                    }
+                })
+                .collect();
+        }
+
+        let mapped = queries.readback_buffer.slice(..).get_mapped_range();
+        let ticks: &[u64] = bytemuck::cast_slice(&mapped);
+
+        let mut frame = Vec::new();
+        let mut passes = Vec::new();
+        for (scope, ticks) in scopes.iter().zip(ticks.chunks_exact(2)) {
+            let ms = ticks[1].saturating_sub(ticks[0]) as f32 * queries.period / 1_000_000.0;
+            add(&mut frame, scope.name, ms);
+            if let Some(pass) = scope.pass {
+                add(&mut passes, pass, ms);
+            }
+        }
+
+        smooth(&mut state.timings, frame);
+        smooth(&mut state.pass_timings, passes);
+    }
+
+    /// Smoothed milliseconds of every pass measured recently, in the order they ran.
+    pub fn timings(&self) -> Vec<(&'static str, f32)> {
+        self.state.lock().unwrap().timings.clone()
+    }
+
+    /// Smoothed milliseconds of render graph passes measured recently, by their names.
+    pub fn pass_timings(&self) -> Vec<(&'static str, f32)> {
+        self.state.lock().unwrap().pass_timings.clone()
+    }
+
+    pub fn render(&self, ctx: &egui::Context) {
+        egui::Window::new("GPU Timings")
+            .default_open(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                if self.queries.is_none() {
+                    ui.label("Timestamp queries are not supported by the adapter.");
+                    return;
+                }
+
+                let state = self.state.lock().unwrap();
+                egui::Grid::new("gpu_timings").striped(true).show(ui, |ui| {
+                    for (name, ms) in &state.timings {
+                        ui.label(*name);
+                        ui.label(format!("{:.3} ms", ms));
+                        ui.end_row();
+                    }
+
+                    let total: f32 = state.timings.iter().map(|(_, ms)| ms).sum();
+                    ui.strong("Total");
+                    ui.strong(format!("{:.3} ms", total));
+                    ui.end_row();
+                });
+            });
+    }
+}
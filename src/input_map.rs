@@ -0,0 +1,131 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use winit::keyboard::KeyCode;
+
+use gpu_basics::camera::Motion;
+
+/// What a key does when it's pressed.
+#[derive(Clone, Deserialize, Debug)]
+pub enum Action {
+    /// Moves the camera while the key is held.
+    Move(Motion),
+    ToggleCameraMode,
+    /// Console command line, executed like it was typed into the console.
+    Command(String),
+}
+
+/// Keys bound to actions, read from a RON map of key names to actions, like:
+///
+/// ```ron
+/// {
+///     "W": Move(Forwards),
+///     "O": ToggleCameraMode,
+///     "F12": Command("screenshot screenshot.png"),
+/// }
+/// ```
+///
+/// Keys are named after letters and digits they type, `F1` to `F12`, arrows
+/// (`Left`, `Right`, `Up`, `Down`) or `Space`, `Tab`, `Enter`, `Backspace`, `Delete`,
+/// `Home`, `End`, `PageUp`, `PageDown`, `Shift`, `Ctrl` and `Alt`.
+pub struct InputMap {
+    bindings: HashMap<KeyCode, Action>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let bindings = [
+            (KeyCode::KeyA, Action::Move(Motion::Left)),
+            (KeyCode::KeyD, Action::Move(Motion::Right)),
+            (KeyCode::KeyQ, Action::Move(Motion::Up)),
+            (KeyCode::KeyZ, Action::Move(Motion::Down)),
+            (KeyCode::KeyW, Action::Move(Motion::Forwards)),
+            (KeyCode::KeyS, Action::Move(Motion::Backwards)),
+            (KeyCode::ArrowLeft, Action::Move(Motion::TurnLeft)),
+            (KeyCode::ArrowRight, Action::Move(Motion::TurnRight)),
+            (KeyCode::ArrowUp, Action::Move(Motion::TurnUp)),
+            (KeyCode::ArrowDown, Action::Move(Motion::TurnDown)),
+            (KeyCode::KeyX, Action::Move(Motion::RollLeft)),
+            (KeyCode::KeyC, Action::Move(Motion::RollRight)),
+            (KeyCode::KeyO, Action::ToggleCameraMode),
+            (KeyCode::F11, Action::Command("toggle fullscreen".into())),
+        ];
+
+        Self {
+            bindings: bindings.into_iter().collect(),
+        }
+    }
+}
+
+impl InputMap {
+    /// Bindings in the file replace the default ones entirely.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read keybindings {}", path.display()))?;
+
+        let named: HashMap<String, Action> = ron::from_str(&source)
+            .with_context(|| format!("failed to parse keybindings {}", path.display()))?;
+
+        let bindings = named
+            .into_iter()
+            .map(|(name, action)| {
+                let key = Self::key_code(&name).ok_or_else(|| anyhow!("unknown key {name}"))?;
+                Ok((key, action))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { bindings })
+    }
+
+    pub fn action(&self, key: KeyCode) -> Option<&Action> {
+        self.bindings.get(&key)
+    }
+
+    fn key_code(name: &str) -> Option<KeyCode> {
+        use KeyCode::*;
+
+        const LETTERS: [KeyCode; 26] = [
+            KeyA, KeyB, KeyC, KeyD, KeyE, KeyF, KeyG, KeyH, KeyI, KeyJ, KeyK, KeyL, KeyM, KeyN,
+            KeyO, KeyP, KeyQ, KeyR, KeyS, KeyT, KeyU, KeyV, KeyW, KeyX, KeyY, KeyZ,
+        ];
+        const DIGITS: [KeyCode; 10] = [
+            Digit0, Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9,
+        ];
+        const FUNCTION_KEYS: [KeyCode; 12] = [F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12];
+
+        let mut chars = name.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return match c.to_ascii_uppercase() {
+                c @ 'A'..='Z' => Some(LETTERS[c as usize - 'A' as usize]),
+                c @ '0'..='9' => Some(DIGITS[c as usize - '0' as usize]),
+                _ => None,
+            };
+        }
+
+        if let Some(n) = name.strip_prefix('F').and_then(|n| n.parse::<usize>().ok()) {
+            return FUNCTION_KEYS.get(n.checked_sub(1)?).copied();
+        }
+
+        Some(match name {
+            "Left" => ArrowLeft,
+            "Right" => ArrowRight,
+            "Up" => ArrowUp,
+            "Down" => ArrowDown,
+            "Space" => Space,
+            "Tab" => Tab,
+            "Enter" => Enter,
+            "Backspace" => Backspace,
+            "Delete" => Delete,
+            "Home" => Home,
+            "End" => End,
+            "PageUp" => PageUp,
+            "PageDown" => PageDown,
+            "Shift" => ShiftLeft,
+            "Ctrl" => ControlLeft,
+            "Alt" => AltLeft,
+            _ => return None,
+        })
+    }
+}
@@ -0,0 +1,59 @@
+//! Forward and deferred renderers built on wgpu, along with the scene, materials,
+//! loaders and passes they're made of. The `gpu-basics` binary puts them together
+//! with an editor UI.
+
+pub mod assets;
+pub mod auto_exposure_pass;
+pub mod billboard_pass;
+pub mod bind_group_layouts;
+pub mod bounds;
+pub mod camera;
+pub mod camera_feed_pass;
+pub mod camera_path;
+pub mod cascade_bounds_pass;
+pub mod compute;
+pub mod decal;
+pub mod deferred;
+pub mod foliage;
+pub mod foliage_pass;
+pub mod forward;
+pub mod frame_recorder;
+pub mod gizmo_pass;
+pub mod gpu;
+pub mod gpu_profiler;
+pub mod light_scene;
+pub mod light_shafts_pass;
+pub mod loader;
+pub mod material;
+pub mod mesh;
+pub mod motion_blur_pass;
+pub mod normals_pass;
+pub mod perf_graph;
+pub mod picking;
+pub mod pipeline_cache;
+pub mod postprocess_pass;
+pub mod projection;
+pub mod render_context;
+pub mod render_graph;
+pub mod render_pass;
+pub mod scene;
+pub mod scene_script;
+pub mod scene_uniform;
+pub mod scene_validation;
+pub mod screenshot;
+pub mod settings;
+pub mod shader_cache;
+pub mod shader_compiler;
+pub mod shader_permutation;
+pub mod shadow_pass;
+pub mod shapes;
+pub mod skybox_pass;
+pub mod terrain;
+pub mod test_scenes;
+pub mod texture_pool;
+pub mod ui_pass;
+pub mod uniform_ring;
+pub mod upload;
+pub mod vertex_layout;
+pub mod volumetric_fog_pass;
+pub mod water_pass;
@@ -0,0 +1,181 @@
+use nalgebra as na;
+
+use gpu_basics::light_scene::{Light, LightScene};
+
+/// Changes a light of the scene over time. Lights are referred to by their index
+/// in `LightScene`, animations of lights which are gone do nothing.
+#[derive(Clone, Copy)]
+enum LightAnimation {
+    // Circles a point light in the horizontal plane, starting from where it was.
+    Orbit {
+        light: usize,
+        center: na::Vector3<f32>,
+        radius: f32,
+        // Radians per second.
+        speed: f32,
+    },
+    // Dims diffuse and specular of a point light irregularly, like a candle.
+    Flicker {
+        light: usize,
+        base: Light,
+        // Part of the light taken away at most.
+        amount: f32,
+        speed: f32,
+    },
+    // Turns a directional light around the vertical axis.
+    RotateSun {
+        light: usize,
+        base: na::Vector3<f32>,
+        // Radians per second.
+        speed: f32,
+    },
+}
+
+impl LightAnimation {
+    fn name(&self) -> String {
+        match self {
+            Self::Orbit { light, .. } => format!("Orbit Point {light}"),
+            Self::Flicker { light, .. } => format!("Flicker Point {light}"),
+            Self::RotateSun { light, .. } => format!("Rotate Directional {light}"),
+        }
+    }
+
+    fn speed_mut(&mut self) -> &mut f32 {
+        match self {
+            Self::Orbit { speed, .. }
+            | Self::Flicker { speed, .. }
+            | Self::RotateSun { speed, .. } => speed,
+        }
+    }
+
+    fn apply(&self, time: f32, lights: &mut LightScene) {
+        match *self {
+            Self::Orbit {
+                light,
+                center,
+                radius,
+                speed,
+            } => {
+                if let Some(light) = lights.point.get_mut(light) {
+                    let angle = time * speed;
+                    light.position.x = center.x + radius * angle.cos();
+                    light.position.y = center.y;
+                    light.position.z = center.z + radius * angle.sin();
+                }
+            }
+            Self::Flicker {
+                light,
+                base,
+                amount,
+                speed,
+            } => {
+                if let Some(light) = lights.point.get_mut(light) {
+                    // Sines of unrelated frequencies don't line up, so there's no visible period.
+                    let t = time * speed;
+                    let noise = (t.sin() + (2.7 * t + 1.3).sin() + (5.3 * t + 0.7).sin()) / 3.0;
+                    let factor = 1.0 - amount * (0.5 + 0.5 * noise);
+
+                    light.diffuse = (base.diffuse.xyz() * factor).push(base.diffuse.w);
+                    light.specular = (base.specular.xyz() * factor).push(base.specular.w);
+                }
+            }
+            Self::RotateSun { light, base, speed } => {
+                if let Some(light) = lights.directional.get_mut(light) {
+                    let rotation =
+                        na::Rotation3::from_axis_angle(&na::Vector3::y_axis(), time * speed);
+                    light.direction = (rotation * base).push(0.0);
+                }
+            }
+        }
+    }
+}
+
+/// Animates lights every frame, to exercise passes with lights changing all the time.
+#[derive(Default)]
+pub struct LightAnimator {
+    // Along with the time they were added at, so they start from where lights are.
+    animations: Vec<(LightAnimation, f32)>,
+    paused: bool,
+    // Seconds of animation played, stands still while paused.
+    time: f32,
+}
+
+impl LightAnimator {
+    /// Advances animations by `dt` seconds. Returns whether lights changed
+    /// and have to be uploaded.
+    pub fn update(&mut self, dt: f32, lights: &mut LightScene) -> bool {
+        if self.paused || self.animations.is_empty() {
+            return false;
+        }
+
+        self.time += dt;
+        for (animation, started) in &self.animations {
+            animation.apply(self.time - started, lights);
+        }
+
+        true
+    }
+
+    pub fn render(&mut self, ctx: &egui::Context, lights: &LightScene) {
+        egui::Window::new("Light Animation")
+            .default_open(false)
+            .show(ctx, |ui| {
+                ui.checkbox(&mut self.paused, "Pause");
+
+                let mut removed = None;
+                for (i, (animation, _)) in self.animations.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(animation.name());
+                        ui.add(egui::DragValue::new(animation.speed_mut()).speed(0.05));
+                        if ui.button("Remove").clicked() {
+                            removed = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = removed {
+                    self.animations.remove(i);
+                }
+
+                ui.separator();
+
+                for (i, light) in lights.point.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("Point {i}"));
+                        if ui.button("Orbit").clicked() {
+                            let radius = 2.0;
+                            let animation = LightAnimation::Orbit {
+                                light: i,
+                                center: light.position.xyz() - na::Vector3::x() * radius,
+                                radius,
+                                speed: 1.0,
+                            };
+                            self.animations.push((animation, self.time));
+                        }
+                        if ui.button("Flicker").clicked() {
+                            let animation = LightAnimation::Flicker {
+                                light: i,
+                                base: *light,
+                                amount: 0.5,
+                                speed: 8.0,
+                            };
+                            self.animations.push((animation, self.time));
+                        }
+                    });
+                }
+
+                for (i, light) in lights.directional.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("Directional {i}"));
+                        if ui.button("Rotate").clicked() {
+                            let animation = LightAnimation::RotateSun {
+                                light: i,
+                                base: light.direction.xyz(),
+                                speed: 0.2,
+                            };
+                            self.animations.push((animation, self.time));
+                        }
+                    });
+                }
+            });
+    }
+}
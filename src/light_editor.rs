@@ -0,0 +1,248 @@
+use std::sync::RwLock;
+
+use nalgebra as na;
+
+use gpu_basics::{
+    billboard_pass::{Billboard, Sprite},
+    gpu::Gpu,
+    light_scene::{Light, LightBuffers, LightScene, MAX_LIGHTS},
+};
+
+use crate::material_editor::color_row;
+
+// World size of light icons.
+const ICON_SIZE: f32 = 0.4;
+
+pub struct LightEditor {
+    error: Option<String>,
+    show_icons: bool,
+}
+
+impl Default for LightEditor {
+    fn default() -> Self {
+        Self {
+            error: None,
+            show_icons: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LightKind {
+    Directional,
+    Point,
+    Spot,
+}
+
+impl LightKind {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Directional => "Directional",
+            Self::Point => "Point",
+            Self::Spot => "Spot",
+        }
+    }
+}
+
+// Edits xyz of the vector, w often holds something else.
+fn vector_row(ui: &mut egui::Ui, label: &str, v: &mut na::Vector4<f32>, speed: f32) -> bool {
+    ui.horizontal(|ui| {
+        let mut changed = false;
+        for component in v.iter_mut().take(3) {
+            changed |= ui
+                .add(egui::DragValue::new(component).speed(speed))
+                .changed();
+        }
+        ui.label(label);
+
+        changed
+    })
+    .inner
+}
+
+fn light_ui(ui: &mut egui::Ui, kind: LightKind, light: &mut Light) -> bool {
+    let mut changed = false;
+
+    if kind != LightKind::Directional {
+        changed |= vector_row(ui, "Position", &mut light.position, 0.05);
+    }
+
+    if kind != LightKind::Point && vector_row(ui, "Direction", &mut light.direction, 0.01) {
+        let direction = light.direction.xyz();
+        if direction.norm() > 0.0 {
+            light.direction = direction.normalize().push(0.0);
+        }
+        changed = true;
+    }
+
+    changed |= color_row(ui, "Ambient", &mut light.ambient);
+    changed |= color_row(ui, "Diffuse", &mut light.diffuse);
+    changed |= color_row(ui, "Specular", &mut light.specular);
+
+    if kind == LightKind::Spot {
+        let mut angle = light.position.w.to_degrees();
+        ui.label("Cutoff Angle");
+        if ui
+            .add(
+                egui::DragValue::new(&mut angle)
+                    .speed(0.5)
+                    .clamp_range(1.0..=89.0),
+            )
+            .changed()
+        {
+            light.position.w = angle.to_radians();
+            changed = true;
+        }
+    }
+
+    if kind != LightKind::Directional {
+        // Constant, linear and quadratic terms are kept in w of light colors.
+        ui.label("Attenuation");
+        ui.horizontal(|ui| {
+            for (term, speed) in [
+                (&mut light.ambient.w, 0.01),
+                (&mut light.diffuse.w, 0.001),
+                (&mut light.specular.w, 0.0005),
+            ] {
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(term)
+                            .speed(speed)
+                            .clamp_range(0.0..=f32::MAX),
+                    )
+                    .changed();
+            }
+        });
+    }
+
+    changed
+}
+
+impl LightEditor {
+    /// Icons at positions of point and spot lights, colored like their light.
+    /// Directional lights are nowhere in particular, so they don't get one.
+    pub fn light_icons(&self, lights: &LightScene) -> Vec<Billboard> {
+        if !self.show_icons {
+            return Vec::new();
+        }
+
+        let icon = |light: &Light, sprite| {
+            // Dim lights get as bright an icon as any other.
+            let color = light.diffuse.xyz();
+            let color = if color.max() > 0.0 {
+                color / color.max()
+            } else {
+                na::Vector3::repeat(1.0)
+            };
+
+            Billboard::new(light.position.xyz(), ICON_SIZE, sprite).with_color(color.push(1.0))
+        };
+
+        lights
+            .point
+            .iter()
+            .map(|light| icon(light, Sprite::PointLight))
+            .chain(
+                lights
+                    .spot
+                    .iter()
+                    .map(|light| icon(light, Sprite::SpotLight)),
+            )
+            .collect()
+    }
+
+    pub fn render(
+        &mut self,
+        ctx: &egui::Context,
+        gpu: &Gpu,
+        lights: &RwLock<LightScene>,
+        light_buffers: &LightBuffers,
+    ) {
+        egui::Window::new("Lights")
+            .default_open(false)
+            .show(ctx, |ui| {
+                let mut lights = lights.write().unwrap();
+                let mut changed = false;
+
+                ui.add_enabled_ui(lights.count() < MAX_LIGHTS, |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button("Add Directional").clicked() {
+                            lights.new_directional(
+                                na::Vector3::new(-0.5, -1.0, -0.5).normalize(),
+                                na::Vector3::new(0.1, 0.1, 0.1),
+                                na::Vector3::new(0.5, 0.5, 0.5),
+                                na::Vector3::new(0.3, 0.3, 0.3),
+                            );
+                            changed = true;
+                        }
+                        if ui.button("Add Point").clicked() {
+                            lights.new_point(
+                                na::Vector3::new(0.0, 1.0, 0.0),
+                                na::Vector3::new(0.0, 0.0, 0.0),
+                                na::Vector3::new(0.8, 0.8, 0.8),
+                                na::Vector3::new(0.8, 0.8, 0.8),
+                                na::Vector3::new(1.0, 0.09, 0.032),
+                            );
+                            changed = true;
+                        }
+                        if ui.button("Add Spot").clicked() {
+                            lights.new_spot(
+                                na::Vector3::new(0.0, 5.0, 0.0),
+                                na::Vector3::new(0.0, -1.0, 0.0),
+                                na::Vector3::new(0.0, 0.0, 0.0),
+                                na::Vector3::new(0.8, 0.8, 0.8),
+                                na::Vector3::new(0.8, 0.8, 0.8),
+                                30.0f32.to_radians(),
+                                na::Vector3::new(1.0, 0.09, 0.032),
+                            );
+                            changed = true;
+                        }
+                    });
+                });
+
+                ui.checkbox(&mut self.show_icons, "Show Icons");
+
+                let LightScene {
+                    directional,
+                    point,
+                    spot,
+                } = &mut *lights;
+
+                for (kind, group) in [
+                    (LightKind::Directional, directional),
+                    (LightKind::Point, point),
+                    (LightKind::Spot, spot),
+                ] {
+                    let mut removed = None;
+
+                    for (i, light) in group.iter_mut().enumerate() {
+                        egui::CollapsingHeader::new(format!("{} {}", kind.name(), i)).show(
+                            ui,
+                            |ui| {
+                                changed |= light_ui(ui, kind, light);
+                                if ui.button("Remove").clicked() {
+                                    removed = Some(i);
+                                }
+                            },
+                        );
+                    }
+
+                    if let Some(i) = removed {
+                        group.remove(i);
+                        changed = true;
+                    }
+                }
+
+                if changed {
+                    self.error = light_buffers
+                        .upload(gpu, &lights)
+                        .err()
+                        .map(|e| e.to_string());
+                }
+
+                if let Some(error) = &self.error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+            });
+    }
+}
@@ -1,6 +1,12 @@
-use encase::{ArrayLength, ShaderType};
+use anyhow::{bail, Result};
+use encase::{ArrayLength, ShaderSize, ShaderType, StorageBuffer};
 use nalgebra as na;
 
+use crate::{bind_group_layouts::BindGroupLayouts, gpu::Gpu, volumetric_fog_pass::FogVolume};
+
+/// Lights of all kinds together which fit into `LightBuffers`.
+pub const MAX_LIGHTS: usize = 256;
+
 // We reuse w component of the structure, because:
 // * According to Mario, GPU is aligning to vec4s anyway.
 // * We can tightly pack the structure this way, avoiding unnecessary padding.
@@ -86,6 +92,17 @@ impl LightScene {
         ));
     }
 
+    /// Summed ambient term of point lights, padded to `vec4`. It doesn't fade with distance,
+    /// so passes culling lights apply it separately to every fragment.
+    pub fn point_ambient(&self) -> [f32; 4] {
+        self.point.iter().fold([0.0; 4], |mut ambient, light| {
+            ambient[0] += light.ambient.x;
+            ambient[1] += light.ambient.y;
+            ambient[2] += light.ambient.z;
+            ambient
+        })
+    }
+
     pub fn into_gpu(&self) -> GpuLightScene {
         GpuLightScene {
             num_directional: self.directional.len() as u32,
@@ -103,6 +120,172 @@ impl LightScene {
     }
 }
 
+impl LightScene {
+    pub fn count(&self) -> usize {
+        self.directional.len() + self.point.len() + self.spot.len()
+    }
+}
+
+/// Lights on the GPU, shared by lighting passes. Buffers have room for `MAX_LIGHTS`,
+/// so they're rewritten in place whenever lights change and bind groups stay valid.
+pub struct LightBuffers {
+    lights: wgpu::Buffer,
+    point_ambient: wgpu::Buffer,
+}
+
+impl LightBuffers {
+    pub fn new(gpu: &Gpu, lights: &LightScene) -> Result<Self> {
+        // Light counts and the array length come before the lights.
+        let header_size = 4 * std::mem::size_of::<u32>() as u64;
+
+        let buffers = Self {
+            lights: gpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("LightBuffers::Lights"),
+                size: header_size + MAX_LIGHTS as u64 * Light::SHADER_SIZE.get(),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            point_ambient: gpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("LightBuffers::PointAmbient"),
+                size: std::mem::size_of::<[f32; 4]>() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+        };
+        buffers.upload(gpu, lights)?;
+
+        Ok(buffers)
+    }
+
+    pub fn upload(&self, gpu: &Gpu, lights: &LightScene) -> Result<()> {
+        if lights.count() > MAX_LIGHTS {
+            bail!(
+                "{} lights, at most {MAX_LIGHTS} are supported",
+                lights.count()
+            );
+        }
+
+        let gpu_lights = lights.into_gpu();
+        let mut contents = StorageBuffer::new(Vec::with_capacity(gpu_lights.size().get() as usize));
+        contents.write(&gpu_lights)?;
+
+        gpu.upload_buffer(&self.lights, 0, contents.into_inner().as_slice());
+        gpu.upload_buffer(
+            &self.point_ambient,
+            0,
+            bytemuck::cast_slice(&lights.point_ambient()),
+        );
+
+        Ok(())
+    }
+
+    /// Storage buffer laid out like `Lights` in `phong/definitions.wgsl`.
+    pub fn lights(&self) -> &wgpu::Buffer {
+        &self.lights
+    }
+
+    /// Uniform with the summed ambient of point lights, see `LightScene::point_ambient`.
+    pub fn point_ambient(&self) -> &wgpu::Buffer {
+        &self.point_ambient
+    }
+
+    /// Entries of the lights bind group, see `BindGroupLayouts::lights`. Lights come
+    /// first, followed by the skybox reflective materials show and the fog volume.
+    pub(crate) fn layout_entries() -> Vec<wgpu::BindGroupLayoutEntry> {
+        let visibility = wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE;
+        let buffer = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        [
+            buffer(0, wgpu::BufferBindingType::Storage { read_only: true }),
+            buffer(1, wgpu::BufferBindingType::Uniform),
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::Cube,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ]
+        .into_iter()
+        .chain(FogVolume::layout_entries(4, visibility))
+        .collect()
+    }
+
+    /// Bind group of the lights, shared by lighting passes. Buffers are rewritten
+    /// in place and the fog volume keeps its texture, so it never has to be made again.
+    pub fn bind_group(
+        &self,
+        gpu: &Gpu,
+        bind_group_layouts: &BindGroupLayouts,
+        environment: &wgpu::Texture,
+        fog_volume: &FogVolume,
+    ) -> wgpu::BindGroup {
+        let environment_view = environment.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let environment_sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("LightBuffers::EnvironmentSampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("LightBuffers::BindGroup"),
+            layout: &bind_group_layouts.lights(gpu),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.lights.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.point_ambient.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&environment_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&environment_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(fog_volume.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(fog_volume.sampler()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: fog_volume.params_buffer().as_entire_binding(),
+                },
+            ],
+        })
+    }
+}
+
 impl Light {
     pub fn new_point(
         position: na::Vector3<f32>,
@@ -0,0 +1,262 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use encase::{ShaderSize, UniformBuffer};
+use nalgebra as na;
+
+use crate::{
+    compute::{dispatch, Kernel},
+    gpu::Gpu,
+    light_scene::Light,
+    render_context::RenderContext,
+    render_graph::{Binding, GraphResources},
+    render_pass::{FrameContext, RenderPass},
+    settings::{LightShaftsSettings, PipelineType},
+};
+
+const KERNEL: Kernel = Kernel::new([16, 16, 1]);
+
+type Params = [na::Vector4<f32>; 2];
+
+/// Adds shafts of sunlight to the HDR scene color, blurring the sky seen between
+/// geometry radially towards the sun.
+pub struct LightShaftsPass<'window> {
+    render_ctx: Arc<RenderContext<'window>>,
+    bgl: Arc<wgpu::BindGroupLayout>,
+    pipeline: wgpu::ComputePipeline,
+    params_buf: wgpu::Buffer,
+    output_tex: wgpu::Texture,
+}
+
+impl<'window> LightShaftsPass<'window> {
+    pub const MAX_SAMPLES: u32 = 128;
+
+    pub fn new(render_ctx: Arc<RenderContext<'window>>) -> Result<Self> {
+        let RenderContext {
+            gpu,
+            bind_group_layouts,
+            shader_compiler,
+            ..
+        } = render_ctx.as_ref();
+
+        let params_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("LightShaftsPass::Params"),
+            size: Params::SHADER_SIZE.into(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bgl = bind_group_layouts.get(
+            gpu,
+            "LightShaftsPass::BindGroupLayout",
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba16Float,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        );
+
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("LightShaftsPass::PipelineLayout"),
+                bind_group_layouts: &[&bgl],
+                push_constant_ranges: &[],
+            });
+
+        let module = KERNEL
+            .with_defs(gpu.with_depth_defs(
+                shader_compiler.compilation_unit("./shaders/screenspace/light_shafts.wgsl")?,
+            ))
+            .compile(&[])?;
+        let shader = gpu.shader_from_module(module);
+
+        let pipeline = gpu
+            .device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("LightShaftsPass::Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "light_shafts",
+            });
+
+        Ok(Self {
+            output_tex: Self::create_output(gpu),
+            render_ctx,
+            bgl,
+            pipeline,
+            params_buf,
+        })
+    }
+
+    fn create_output(gpu: &Gpu) -> wgpu::Texture {
+        gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("LightShaftsPass::Output"),
+            size: gpu.viewport_size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        })
+    }
+
+    pub fn output_tex_view(&self) -> wgpu::TextureView {
+        self.output_tex.create_view(&Default::default())
+    }
+
+    /// UV of the sun on screen and how visible it is: fully while it's on screen,
+    /// fading out until it's a screen away from the edge.
+    fn sun_on_screen(sun: &Light, view_projection: &na::Matrix4<f32>) -> (na::Vector2<f32>, f32) {
+        // Directional lights are infinitely far away, so only the direction towards them matters.
+        let clip = view_projection
+            * -na::Vector4::new(sun.direction.x, sun.direction.y, sun.direction.z, 0.0);
+        if clip.w <= 0.0 {
+            return (na::Vector2::zeros(), 0.0);
+        }
+
+        let ndc = clip.xy() / clip.w;
+        let uv = na::Vector2::new(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        let visibility = (2.0 - ndc.x.abs().max(ndc.y.abs())).clamp(0.0, 1.0);
+
+        (uv, visibility)
+    }
+
+    pub fn render(
+        &self,
+        scene_color: &wgpu::TextureView,
+        settings: &LightShaftsSettings,
+        sun: &Light,
+        view_projection: &na::Matrix4<f32>,
+        resources: &GraphResources,
+    ) -> Result<()> {
+        let RenderContext { gpu, profiler, .. } = self.render_ctx.as_ref();
+
+        let (sun_uv, visibility) = Self::sun_on_screen(sun, view_projection);
+        let color = sun.diffuse.xyz() * settings.intensity;
+
+        // Fields of `Params` in the shader.
+        let params: Params = [
+            na::Vector4::new(sun_uv.x, sun_uv.y, visibility, settings.samples as f32),
+            na::Vector4::new(color.x, color.y, color.z, settings.decay),
+        ];
+        let size: u64 = Params::SHADER_SIZE.into();
+        let mut contents = UniformBuffer::new(Vec::with_capacity(size as usize));
+        contents.write(&params)?;
+        gpu.queue
+            .write_buffer(&self.params_buf, 0, contents.into_inner().as_slice());
+
+        let depth = gpu.depth_texture_view();
+        let output = self.output_tex_view();
+        let bg = resources.bind_group(
+            gpu,
+            "LightShaftsPass::BindGroup",
+            &self.bgl,
+            &[
+                Binding::View(&depth),
+                Binding::View(scene_color),
+                Binding::View(&output),
+                Binding::Buffer(&self.params_buf),
+            ],
+        )?;
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("LightShaftsPass::CommandEncoder"),
+            });
+
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("LightShaftsPass::ComputePass"),
+                timestamp_writes: profiler.compute_pass_writes("Light Shafts"),
+            });
+
+            cpass.set_pipeline(&self.pipeline);
+            cpass.set_bind_group(0, &bg, &[]);
+
+            let viewport = gpu.viewport_size();
+            dispatch(&mut cpass, &KERNEL, [viewport.width, viewport.height, 1]);
+        }
+
+        gpu.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
+
+impl RenderPass for LightShaftsPass<'_> {
+    fn name(&self) -> &'static str {
+        "LightShaftsPass"
+    }
+
+    fn enabled(&self, ctx: &FrameContext) -> bool {
+        let settings = ctx.settings;
+        settings.light_shafts.enabled
+            && settings.pipeline_type == PipelineType::Deferred
+            && !settings.deferred_debug_shown()
+    }
+
+    fn resize(&mut self, _size: (u32, u32)) -> Result<()> {
+        self.output_tex = Self::create_output(&self.render_ctx.gpu);
+
+        Ok(())
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        let (scene_color, _) = ctx
+            .scene_color
+            .as_ref()
+            .filter(|(_, hdr)| *hdr)
+            .context("light shafts are added to a HDR scene color")?;
+        let view_projection = ctx.projection * ctx.camera.look_at_matrix();
+        Self::render(
+            self,
+            scene_color,
+            &ctx.settings.light_shafts,
+            &ctx.sun,
+            &view_projection,
+            &ctx.resources,
+        )?;
+        ctx.scene_color = Some((self.output_tex_view(), true));
+
+        Ok(())
+    }
+}
@@ -1,6 +1,12 @@
 use anyhow::{Context, Result};
 use nalgebra as na;
-use std::path::Path;
+use rayon::prelude::*;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::BufRead,
+    path::{Path, PathBuf},
+};
 
 use crate::{
     gpu::Gpu,
@@ -10,6 +16,9 @@ use crate::{
 
 pub struct ObjLoader;
 
+// Files store attributes as decimals, so vertices written per face rarely match exactly.
+const WELD_EPSILON: f32 = 1e-5;
+
 fn flat_to_v3(v: &[f32]) -> Vec<na::Vector3<f32>> {
     v.chunks(3)
         .map(|c| na::Vector3::new(c[0], c[1], c[2]))
@@ -20,167 +29,515 @@ fn flat_to_v2(v: &[f32]) -> Vec<na::Vector2<f32>> {
     v.chunks(2).map(|c| na::Vector2::new(c[0], c[1])).collect()
 }
 
+// Vertices are only shared between faces of the same smoothing group.
+// Faces with smoothing turned off get vertices of their own.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum SmoothingKey {
+    Group(u32),
+    Face(usize),
+}
+
+// Smoothing group of every face in the file, in the order tobj emits them.
+// Returns `None` if the file has no `s` statements at all.
+fn face_smoothing_groups(mut reader: impl BufRead) -> Result<Option<Vec<u32>>> {
+    let mut groups = vec![];
+    let mut current = 0;
+    let mut has_groups = false;
+
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 {
+        let mut words = line.split_whitespace();
+
+        match words.next() {
+            Some("s") => {
+                has_groups = true;
+                current = words.next().and_then(|g| g.parse().ok()).unwrap_or(0);
+            }
+            // tobj keeps lines in the same face list as polygons.
+            Some("f") | Some("l") => groups.push(current),
+            _ => {}
+        }
+
+        line.clear();
+    }
+
+    Ok(has_groups.then_some(groups))
+}
+
+struct SmoothedMesh {
+    positions: Vec<na::Vector3<f32>>,
+    texture_uvs: Vec<na::Vector2<f32>>,
+    normals: Vec<na::Vector3<f32>>,
+    indices: Vec<u32>,
+}
+
+// Splits vertices shared by faces of different smoothing groups and
+// averages face normals within each group, so group borders become hard edges.
+fn smooth_by_groups(
+    positions: &[na::Vector3<f32>],
+    texture_uvs: &[na::Vector2<f32>],
+    indices: &[u32],
+    groups: &[u32],
+) -> SmoothedMesh {
+    let mut vertex_map: HashMap<(u32, SmoothingKey), u32> = HashMap::new();
+    let mut mesh = SmoothedMesh {
+        positions: vec![],
+        texture_uvs: vec![],
+        normals: vec![],
+        indices: Vec::with_capacity(indices.len()),
+    };
+
+    for (face, (triangle, &group)) in indices.chunks(3).zip(groups).enumerate() {
+        let key = if group == 0 {
+            SmoothingKey::Face(face)
+        } else {
+            SmoothingKey::Group(group)
+        };
+
+        let [v0, v1, v2] = [0, 1, 2].map(|i| positions[triangle[i] as usize]);
+        // Not normalized, so bigger faces contribute more to the vertex normal.
+        let normal = (v1 - v0).cross(&(v2 - v0));
+
+        for &idx in triangle {
+            let vertex = *vertex_map.entry((idx, key)).or_insert_with(|| {
+                mesh.positions.push(positions[idx as usize]);
+                if !texture_uvs.is_empty() {
+                    mesh.texture_uvs.push(texture_uvs[idx as usize]);
+                }
+                mesh.normals.push(na::Vector3::zeros());
+
+                (mesh.positions.len() - 1) as u32
+            });
+
+            mesh.normals[vertex as usize] += normal;
+            mesh.indices.push(vertex);
+        }
+    }
+
+    for normal in mesh.normals.iter_mut() {
+        *normal = normal
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(na::Vector3::y);
+    }
+
+    mesh
+}
+
+// Splits a polygon into triangles by clipping ears, so concave faces keep their shape.
+// Polygons which aren't simple (i.e. self-intersecting) fall back to a fan
+// once no ear can be found.
+fn triangulate_polygon(positions: &[na::Vector3<f32>], polygon: &[u32], triangles: &mut Vec<u32>) {
+    let position = |idx: u32| positions[idx as usize];
+
+    // Newell's method gives a normal which works for concave polygons too.
+    let mut normal = na::Vector3::zeros();
+    for (i, &idx) in polygon.iter().enumerate() {
+        let (current, next) = (position(idx), position(polygon[(i + 1) % polygon.len()]));
+        normal += (current - next).cross(&(current + next));
+    }
+
+    let mut remaining = polygon.to_vec();
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let ear = (0..count).find(|&i| {
+            let [a, b, c] = [count + i - 1, i, i + 1].map(|j| remaining[j % count]);
+            let [pa, pb, pc] = [a, b, c].map(position);
+
+            if (pb - pa).cross(&(pc - pb)).dot(&normal) <= 0.0 {
+                return false;
+            }
+
+            // Vertices on the edges count as inside, otherwise the ear could overlap the
+            // rest of the polygon. Corners can be repeated by vertices with another index.
+            let inside = |p: na::Vector3<f32>| {
+                p != pa
+                    && p != pb
+                    && p != pc
+                    && [(pa, pb), (pb, pc), (pc, pa)]
+                        .iter()
+                        .all(|(from, to)| (to - from).cross(&(p - from)).dot(&normal) >= 0.0)
+            };
+
+            !remaining.iter().any(|&idx| inside(position(idx)))
+        });
+
+        let Some(ear) = ear else {
+            break;
+        };
+
+        triangles.extend([(ear + count - 1) % count, ear, (ear + 1) % count].map(|j| remaining[j]));
+        remaining.remove(ear);
+    }
+
+    for i in 1..remaining.len() - 1 {
+        triangles.extend([remaining[0], remaining[i], remaining[i + 1]]);
+    }
+}
+
+// tobj keeps polygons as they are in the file. Turns them into triangles,
+// repeating the smoothing group of a polygon for every triangle made out of it.
+// Points and lines have no area to render, so they are dropped.
+fn triangulate(
+    positions: &[na::Vector3<f32>],
+    indices: &[u32],
+    face_arities: &[u32],
+    groups: Option<&[u32]>,
+) -> (Vec<u32>, Option<Vec<u32>>) {
+    let mut triangles = Vec::with_capacity(indices.len());
+    let mut triangle_groups = groups.map(|_| Vec::with_capacity(face_arities.len()));
+
+    let mut start = 0;
+    for (face, &arity) in face_arities.iter().enumerate() {
+        let polygon = &indices[start..start + arity as usize];
+        start += arity as usize;
+
+        if polygon.len() < 3 {
+            continue;
+        }
+
+        let triangle_count = triangles.len() / 3;
+        triangulate_polygon(positions, polygon, &mut triangles);
+
+        if let Some((triangle_groups, groups)) = triangle_groups.as_mut().zip(groups) {
+            let added = triangles.len() / 3 - triangle_count;
+            triangle_groups.extend(std::iter::repeat_n(groups[face], added));
+        }
+    }
+
+    (triangles, triangle_groups)
+}
+
+// Models from 3 up add reflections of the scene to Phong lighting.
+const MTL_REFLECTIVE_ILLUMINATION: u8 = 3;
+
+// Texture statements can have options (e.g. `-bm 0.5`) before the file name.
+fn mtl_texture_path(base_path: &Path, statement: &str) -> PathBuf {
+    let file = if statement.starts_with('-') {
+        statement.split_whitespace().last().unwrap_or(statement)
+    } else {
+        statement
+    };
+
+    base_path.join(file)
+}
+
+fn parse_mtl_color(value: &str) -> Option<na::Vector4<f32>> {
+    let channels = value
+        .split_whitespace()
+        .map(|c| c.parse().ok())
+        .collect::<Option<Vec<f32>>>()?;
+
+    match channels[..] {
+        [r, g, b] => Some(na::Vector4::new(r, g, b, 0.0)),
+        _ => None,
+    }
+}
+
+// Statements which have no counterpart in materials of the renderer.
+fn unsupported_mtl_statements(material: &tobj::Material) -> Vec<&'static str> {
+    let mut unsupported = vec![];
+
+    if material.ambient_texture.is_some() {
+        unsupported.push("ambient map (map_Ka)");
+    }
+    if material.shininess_texture.is_some() {
+        unsupported.push("shininess map (map_Ns)");
+    }
+    if material.unknown_param.contains_key("map_Ke") {
+        unsupported.push("emissive map (map_Ke)");
+    }
+    if material.unknown_param.contains_key("disp") {
+        unsupported.push("displacement map (disp)");
+    }
+
+    unsupported
+}
+
+// Bump maps are taken for normal maps, the same as `norm` statements.
+fn normal_texture(material: &tobj::Material) -> Option<&String> {
+    material
+        .normal_texture
+        .as_ref()
+        .or_else(|| material.unknown_param.get("norm"))
+}
+
+// Normal maps are only used together with diffuse maps.
+fn is_normal_mapped(material: &tobj::Material) -> bool {
+    material.diffuse_texture.is_some() && normal_texture(material).is_some()
+}
+
+// Parts of the material which are left out when it's loaded.
+fn material_warnings(material: &tobj::Material) -> Vec<String> {
+    let mut warnings = unsupported_mtl_statements(material)
+        .into_iter()
+        .map(|statement| {
+            format!(
+                "material {}: {} is not supported, ignoring it",
+                material.name, statement
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // Materials have no alpha, neither tested nor blended, so dissolve can't be mapped to anything.
+    if material.dissolve.is_some_and(|d| d < 1.0)
+        || material.dissolve_texture.is_some()
+        || material.unknown_param.contains_key("Tr")
+    {
+        warnings.push(format!(
+            "material {}: transparency (d, Tr, map_d) is not supported, drawing it opaque",
+            material.name
+        ));
+    }
+    if normal_texture(material).is_some() && material.diffuse_texture.is_none() {
+        warnings.push(format!(
+            "material {}: normal maps need a diffuse map, ignoring it",
+            material.name
+        ));
+    }
+
+    warnings
+}
+
+fn load_material(
+    gpu: &Gpu,
+    material_atlas: &mut MaterialAtlas,
+    material: &tobj::Material,
+    base_path: &Path,
+) -> Result<Option<MaterialId>> {
+    let shininess = material.shininess.unwrap_or(32.0);
+    // Models 0 and 1 have no specular highlights.
+    let highlights = material.illumination_model.is_none_or(|illum| illum >= 2);
+    let normal_texture =
+        normal_texture(material).map(|statement| mtl_texture_path(base_path, statement));
+
+    let material_id = if let Some(diffuse_texture) = &material.diffuse_texture {
+        let diffuse_texture = mtl_texture_path(base_path, diffuse_texture);
+        let specular = match &material.specular_texture {
+            Some(statement) if highlights => SpecularTexture::Provided(
+                mtl_texture_path(base_path, statement)
+                    .to_str()
+                    .context("specular map path is not valid UTF-8")?
+                    .to_owned(),
+                shininess,
+            ),
+            _ => SpecularTexture::FullDiffuse,
+        };
+
+        match normal_texture {
+            Some(normal) => material_atlas.add_phong_textured_normal(
+                gpu,
+                &diffuse_texture,
+                specular,
+                &normal,
+            )?,
+            None => material_atlas.add_phong_textured(gpu, &diffuse_texture, specular)?,
+        }
+    } else if let (Some(ambient), Some(diffuse)) = (material.ambient, material.diffuse) {
+        let ambient = na::Vector4::new(ambient[0], ambient[1], ambient[2], 0.0);
+        let specular = match material.specular.unwrap_or(diffuse) {
+            _ if !highlights => na::Vector4::zeros(),
+            specular => na::Vector4::new(specular[0], specular[1], specular[2], shininess),
+        };
+        let diffuse = na::Vector4::new(diffuse[0], diffuse[1], diffuse[2], 0.0);
+
+        material_atlas.add_phong_solid(gpu, ambient, diffuse, specular)?
+    } else {
+        return Ok(None);
+    };
+
+    if let Some(emissive) = material.unknown_param.get("Ke") {
+        let emissive = parse_mtl_color(emissive).context("invalid emissive color (Ke)")?;
+        material_atlas.set_emissive(gpu, material_id, emissive)?;
+    }
+
+    // Reflection is tinted by the specular color in MTL, here only its strength is kept.
+    if material
+        .illumination_model
+        .is_some_and(|illum| illum >= MTL_REFLECTIVE_ILLUMINATION)
+    {
+        let reflectivity = material
+            .specular
+            .map_or(1.0, |specular| specular.iter().sum::<f32>() / 3.0);
+        material_atlas.set_reflectivity(gpu, material_id, reflectivity.clamp(0.0, 1.0))?;
+    }
+
+    Ok(Some(material_id))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ObjLoaderSettings {
     pub calculate_tangent_space: bool,
 }
 
+/// Meshes and materials of an OBJ file. Reading it doesn't touch the GPU,
+/// so it can happen on a background thread, materials are created by `upload`.
+#[derive(Clone)]
+pub struct ObjModel {
+    // Textures are looked up relative to it.
+    base_path: PathBuf,
+    meshes: Vec<Mesh>,
+    // Index into `materials`, for every mesh.
+    mesh_materials: Vec<Option<usize>>,
+    materials: Vec<tobj::Material>,
+    warnings: Vec<String>,
+}
+
+impl ObjModel {
+    /// Parts of materials which are left out, as they have no counterpart in the renderer.
+    /// Materials are always opaque, transparency included.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Adds materials of the model to `material_atlas`. Returns meshes of the model
+    /// together with materials of meshes which have one.
+    pub fn upload(
+        &self,
+        gpu: &Gpu,
+        material_atlas: &mut MaterialAtlas,
+    ) -> Result<(Vec<Mesh>, Vec<MaterialId>)> {
+        let local_materials = self
+            .materials
+            .iter()
+            .map(|material| {
+                load_material(gpu, material_atlas, material, &self.base_path)
+                    .with_context(|| format!("failed to load material {}", material.name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mesh_materials = self
+            .mesh_materials
+            .iter()
+            .filter_map(|mat_idx| mat_idx.and_then(|mat_idx| local_materials[mat_idx]))
+            .collect();
+
+        Ok((self.meshes.clone(), mesh_materials))
+    }
+}
+
 impl ObjLoader {
+    /// Reads the model and adds its materials to `material_atlas`.
+    /// Its warnings are dropped, use `parse` to get them.
     pub fn load(
         path: impl AsRef<Path>,
         gpu: &Gpu,
         material_atlas: &mut MaterialAtlas,
         settings: ObjLoaderSettings,
     ) -> Result<(Vec<Mesh>, Vec<MaterialId>)> {
-        let (models, materials) = tobj::load_obj(path.as_ref(), &tobj::LoadOptions::default())
-            .context("failed to load obj file")?;
+        Self::parse(path, settings)?.upload(gpu, material_atlas)
+    }
+
+    /// Reads the model without creating its materials.
+    pub fn parse(path: impl AsRef<Path>, settings: ObjLoaderSettings) -> Result<ObjModel> {
+        let file = File::open(path.as_ref()).context("failed to open obj file")?;
+        // SAFETY: The file is only read during loading and models aren't expected to be
+        // modified on disk while that happens.
+        let obj_data = unsafe { memmap2::Mmap::map(&file) }.context("failed to map obj file")?;
+
+        let base_path = path.as_ref().parent().unwrap_or(Path::new("")).to_owned();
+        let (models, materials) = tobj::load_obj_buf(
+            &mut &obj_data[..],
+            &tobj::LoadOptions::default(),
+            |mtl_path| tobj::load_mtl(base_path.join(mtl_path)),
+        )
+        .context("failed to load obj file")?;
 
         let materials = materials?;
 
-        let mut local_materials = Vec::with_capacity(materials.len());
-
-        for material in materials.iter() {
-            let is_phong_solid = material.diffuse.is_some() && material.ambient.is_some();
-            let is_phong_textured = material.diffuse_texture.is_some();
-            let is_phong_textured_normal = is_phong_textured && material.normal_texture.is_some();
-
-            if is_phong_solid {
-                let ambient = material.ambient.unwrap();
-                let ambient = na::Vector4::new(ambient[0], ambient[1], ambient[2], 0.0);
-                let diffuse_f = material.diffuse.unwrap();
-                let diffuse = na::Vector4::new(diffuse_f[0], diffuse_f[1], diffuse_f[2], 0.0);
-                let specular = material.specular.unwrap_or(diffuse_f);
-                let specular = na::Vector4::new(specular[0], specular[1], specular[2], 0.0);
-
-                local_materials.push((
-                    material.name.clone(),
-                    material_atlas.add_phong_solid(gpu, ambient, diffuse, specular)?,
-                ));
-            } else if is_phong_textured_normal {
-                let diffuse_texture = material
-                    .diffuse_texture
-                    .as_ref()
-                    .map(|tex_path| {
-                        let base_path = path.as_ref().parent().unwrap_or(path.as_ref());
-                        base_path.join(tex_path)
-                    })
-                    .unwrap();
-
-                let specular = material
-                    .specular_texture
-                    .as_ref()
-                    .map(|tex_path| {
-                        let base_path = path.as_ref().parent().unwrap_or(path.as_ref());
-                        SpecularTexture::Provided(
-                            base_path.join(tex_path).to_str().unwrap().to_owned(),
-                            32.0,
-                        )
-                    })
-                    .unwrap_or(SpecularTexture::FullDiffuse);
-
-                let normal = material
-                    .normal_texture
-                    .as_ref()
-                    .map(|tex_path| {
-                        let base_path = path.as_ref().parent().unwrap_or(path.as_ref());
-                        base_path.join(tex_path)
-                    })
-                    .unwrap();
-
-                local_materials.push((
-                    material.name.clone(),
-                    material_atlas.add_phong_textured_normal(
-                        gpu,
-                        &diffuse_texture,
-                        specular,
-                        &normal,
-                    )?,
-                ));
-            } else if is_phong_textured {
-                let diffuse_texture = material
-                    .diffuse_texture
-                    .as_ref()
-                    .map(|tex_path| {
-                        let base_path = path.as_ref().parent().unwrap_or(path.as_ref());
-                        base_path.join(tex_path)
-                    })
-                    .unwrap();
-
-                let specular = material
-                    .specular_texture
+        let smoothing_groups = face_smoothing_groups(&obj_data[..])?;
+        drop(obj_data);
+        // Face ranges are known up front, so each model can be processed independently.
+        let mut face_offset = 0;
+        let model_faces = models
+            .iter()
+            .map(|model| {
+                let face_count = if model.mesh.face_arities.is_empty() {
+                    model.mesh.indices.len() / 3
+                } else {
+                    model.mesh.face_arities.len()
+                };
+                face_offset += face_count;
+
+                face_offset - face_count..face_offset
+            })
+            .collect::<Vec<_>>();
+
+        let loaded = models
+            .into_par_iter()
+            .zip(model_faces)
+            .map(|(model, faces)| {
+                let mut model_groups = smoothing_groups
                     .as_ref()
-                    .map(|tex_path| {
-                        let base_path = path.as_ref().parent().unwrap_or(path.as_ref());
-                        SpecularTexture::Provided(
-                            base_path.join(tex_path).to_str().unwrap().to_owned(),
-                            32.0,
-                        )
-                    })
-                    .unwrap_or(SpecularTexture::FullDiffuse);
-
-                local_materials.push((
-                    material.name.clone(),
-                    material_atlas.add_phong_textured(gpu, &diffuse_texture, specular)?,
-                ));
-            }
-        }
+                    .map(|groups| groups[faces].to_vec());
 
-        let mut mesh_materials = vec![];
-        let mut meshes = vec![];
-
-        for (idx, model) in models.into_iter().enumerate() {
-            let mut tan_space_info = None;
-            if settings.calculate_tangent_space
-                && material_atlas.is_normal_mapped(local_materials[idx].1)
-            {
-                tan_space_info = Some(TangentSpaceInformation {
-                    texture_uvs: flat_to_v2(&model.mesh.texcoords),
-                });
-            }
+                let indexed = !model.mesh.indices.is_empty();
+                let mut positions = flat_to_v3(&model.mesh.positions);
+                let mut texture_uvs = flat_to_v2(&model.mesh.texcoords);
+                let mut indices = model.mesh.indices;
 
-            let indexed = !model.mesh.indices.is_empty();
-            let textured = !model.mesh.texcoords.is_empty();
-            let normal_source = if !model.mesh.normals.is_empty() {
-                NormalSource::Provided(flat_to_v3(&model.mesh.normals))
-            } else {
-                NormalSource::ComputedFlat
-            };
+                if !model.mesh.face_arities.is_empty() {
+                    (indices, model_groups) = triangulate(
+                        &positions,
+                        &indices,
+                        &model.mesh.face_arities,
+                        model_groups.as_deref(),
+                    );
+                }
 
-            let geometry = if indexed {
-                Geometry::new_indexed(
-                    flat_to_v3(&model.mesh.positions),
-                    normal_source,
-                    model.mesh.indices,
-                    tan_space_info,
-                )
-            } else {
-                Geometry::new_non_indexed(
-                    flat_to_v3(&model.mesh.positions),
-                    normal_source,
-                    tan_space_info,
-                )
-            };
+                let normal_source = if !model.mesh.normals.is_empty() {
+                    NormalSource::Provided(flat_to_v3(&model.mesh.normals))
+                } else if let Some(groups) = model_groups.filter(|_| indexed) {
+                    let smoothed = smooth_by_groups(&positions, &texture_uvs, &indices, &groups);
+                    positions = smoothed.positions;
+                    texture_uvs = smoothed.texture_uvs;
+                    indices = smoothed.indices;
 
-            let mut builder = MeshBuilder::new().with_geometry(geometry);
+                    NormalSource::Provided(smoothed.normals)
+                } else {
+                    NormalSource::ComputedFlat
+                };
 
-            if textured {
-                builder = builder.with_texture_uvs(flat_to_v2(&model.mesh.texcoords));
-            }
+                let mut tan_space_info = None;
+                if settings.calculate_tangent_space
+                    && model
+                        .mesh
+                        .material_id
+                        .is_some_and(|mat_idx| is_normal_mapped(&materials[mat_idx]))
+                {
+                    tan_space_info = Some(TangentSpaceInformation {
+                        texture_uvs: texture_uvs.clone(),
+                    });
+                }
 
-            if let Some(mat_idx) = model.mesh.material_id {
-                let material = &materials[mat_idx].name;
+                let textured = !texture_uvs.is_empty();
+                let geometry = if indexed {
+                    Geometry::new_indexed(positions, normal_source, indices, tan_space_info)
+                } else {
+                    Geometry::new_non_indexed(positions, normal_source, tan_space_info)
+                };
 
-                let material_id = local_materials
-                    .iter()
-                    .find(|(name, _)| name == material)
-                    .map(|o| o.1)
-                    .unwrap();
+                let mut builder = MeshBuilder::new()
+                    .with_geometry(geometry)
+                    .with_welding(WELD_EPSILON)
+                    .with_name(model.name);
 
-                mesh_materials.push(material_id);
-            }
+                if textured {
+                    builder = builder.with_texture_uvs(texture_uvs);
+                }
 
-            meshes.push(builder.build()?);
-        }
+                Ok((builder.build()?, model.mesh.material_id))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let (meshes, mesh_materials) = loaded.into_iter().unzip();
+        let warnings = materials.iter().flat_map(material_warnings).collect();
 
-        Ok((meshes, mesh_materials))
+        Ok(ObjModel {
+            base_path,
+            meshes,
+            mesh_materials,
+            materials,
+            warnings,
+        })
     }
 }
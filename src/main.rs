@@ -1,16 +1,54 @@
 use std::sync::Arc;
 
-use anyhow::Result;
-
-use postprocess_pass::PostprocessPass;
-use render_context::RenderContext;
-use scene::GpuScene;
-use scene_uniform::SceneUniform;
-use settings::AppSettings;
-use shader_compiler::ShaderCompiler;
-use shadow_pass::DirectionalShadowPass;
-use skybox_pass::SkyboxPass;
-use ui_pass::UiPass;
+use anyhow::{Context, Result};
+use clap::Parser;
+
+use cli::{Args, SceneChoice};
+use console::{Command, Console};
+use frame_stats::FrameStats;
+use gpu_basics::{
+    auto_exposure_pass::AutoExposurePass,
+    billboard_pass::BillboardPass,
+    camera::{CameraMode, CameraMotion},
+    camera_feed_pass::CameraFeedPass,
+    camera_path::CameraPath,
+    cascade_bounds_pass::CascadeBoundsPass,
+    compute::EquirectToCubePass,
+    deferred::{self, DecalPass, GeometryPass, SsaoPass},
+    foliage_pass::FoliagePass,
+    forward::{self, DepthPrepass},
+    frame_recorder::FrameRecorder,
+    gizmo_pass::GizmoPass,
+    gpu::{Gpu, GpuOptions},
+    light_shafts_pass::LightShaftsPass,
+    motion_blur_pass::MotionBlurPass,
+    normals_pass::NormalsPass,
+    picking::{self, Ray},
+    postprocess_pass::PostprocessPass,
+    render_context::RenderContext,
+    render_graph::RenderGraph,
+    render_pass::{FrameContext, RenderPass},
+    scene::GpuScene,
+    scene_script::SceneScriptWatcher,
+    scene_validation::ValidationReport,
+    screenshot,
+    settings::AppSettings,
+    shader_cache::ShaderCache,
+    shader_compiler::ShaderCompiler,
+    shadow_pass::DirectionalShadowPass,
+    skybox_pass::{BackgroundMode, SkyboxPass},
+    test_scenes,
+    ui_pass::UiPass,
+    volumetric_fog_pass::VolumetricFogPass,
+    water_pass::WaterPass,
+};
+use gpu_info::GpuInfo;
+use input_map::{Action, InputMap};
+use light_animation::LightAnimator;
+use light_editor::LightEditor;
+use material_editor::MaterialEditor;
+use projection_editor::ProjectionEditor;
+use scene_inspector::SceneInspector;
 use winit::{
     dpi::{LogicalSize, PhysicalPosition},
     event::*,
@@ -19,89 +57,218 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 
-mod camera;
-mod compute;
-mod deferred;
-mod forward;
-mod gpu;
-mod light_scene;
-mod loader;
-mod material;
-mod mesh;
-mod postprocess_pass;
-mod projection;
-mod render_context;
-mod scene;
-mod scene_uniform;
-mod settings;
-mod shader_compiler;
-mod shadow_pass;
-mod shapes;
-mod skybox_pass;
-mod test_scenes;
-mod ui_pass;
-
-use forward::DepthPrepass;
-
-const MOVE_DELTA: f32 = 1.0;
-const TILT_DELTA: f32 = 1.0;
-
-use gpu::Gpu;
-
-use crate::{light_scene::Light, settings::PipelineType};
-use deferred::{GeometryPass, SsaoPass};
-
-async fn run(event_loop: EventLoop<()>, window: Window) -> Result<()> {
-    let mut gpu = Gpu::from_window(&window).await?;
-
-    let (scene, material_atlas, lights, mut camera, projection, projection_mat, _) =
-        test_scenes::teapot_scene(&gpu)?;
+mod cli;
+mod console;
+mod crash_report;
+mod error_overlay;
+mod frame_graph_panel;
+mod frame_stats;
+mod gpu_info;
+mod input_map;
+mod light_animation;
+mod light_editor;
+mod material_editor;
+mod projection_editor;
+mod scene_inspector;
+
+// Loaded instead of the built-in test scene when present. Edits are picked up while running.
+const SCENE_SCRIPT: &str = "./scenes/teapot.ron";
+// Saving and spawning need the scene to come from a script.
+const NO_SCENE_SCRIPT: &str = "scene isn't built from a script";
+// Built-in bindings are used when missing.
+const KEYBINDINGS: &str = "./keybindings.ron";
+// Written on exit, so tweaks made in the UI survive restarts.
+const SETTINGS: &str = "./settings.ron";
+// Composed shaders, entries are replaced once their sources change.
+const SHADER_CACHE: &str = "./shader_cache";
+
+async fn run(event_loop: EventLoop<()>, window: Window, args: Args) -> Result<()> {
+    let mut console = Console::default();
+    let mut settings = if std::path::Path::new(SETTINGS).exists() {
+        AppSettings::load(SETTINGS).unwrap_or_else(|e| {
+            console.log(format!("{:#}, using default settings", e));
+            AppSettings::default()
+        })
+    } else {
+        AppSettings::default()
+    };
+    let overrides = args.override_settings(&mut settings);
+
+    let gpu_options = GpuOptions {
+        present_mode: settings.present_mode,
+        adapter: settings.adapter.clone(),
+        reverse_z: settings.reverse_z,
+    };
+    let gpu = Gpu::from_window(&window, &gpu_options).await?;
+    if gpu.present_mode() != settings.present_mode {
+        console.log(format!(
+            "{:?} isn't supported, presenting with {:?}",
+            settings.present_mode,
+            gpu.present_mode()
+        ));
+        settings.present_mode = gpu.present_mode();
+    }
+    if let Some(name) = &settings.adapter.name {
+        let adapter = gpu.adapter.get_info().name;
+        if !adapter.to_lowercase().contains(&name.to_lowercase()) {
+            console.log(format!(
+                "No adapter matching {name}, rendering with {adapter}"
+            ));
+        }
+    }
+    crash_report::install(&gpu);
+
+    let scene_choice = args.scene(SCENE_SCRIPT);
+    // Built-in scenes have no script to watch until one is loaded from the console.
+    let mut scene_watcher: Option<SceneScriptWatcher> = None;
+    let (mut scene, mut material_atlas, lights, mut camera, mut projection, _) = match scene_choice
+    {
+        SceneChoice::Script(path) => scene_watcher
+            .insert(SceneScriptWatcher::new(path))
+            .build(&gpu)?,
+        SceneChoice::Teapot => test_scenes::teapot_scene(&gpu)?,
+        SceneChoice::BlinnPhong => test_scenes::blinn_phong_scene(&gpu)?,
+    };
+    scene.assign_fallback_materials(&gpu, &mut material_atlas)?;
+    let mut scene_report = ValidationReport::new(&scene, &material_atlas);
+    for issue in &scene_report.issues {
+        console.log(format!("warning: {}", issue));
+    }
     let gpu_scene = GpuScene::new(&gpu, scene)?;
-    let scene_uniform = SceneUniform::new(&gpu, &camera, &projection);
+
+    let mut shader_compiler = ShaderCompiler::new("./shaders")?;
+    if !args.no_shader_cache {
+        match ShaderCache::new(SHADER_CACHE) {
+            Ok(cache) => shader_compiler = shader_compiler.with_cache(cache),
+            Err(e) => console.log(format!("{:#}, compiling shaders without it", e)),
+        }
+    }
 
     let render_ctx = Arc::new(RenderContext::new(
         &window,
         gpu,
-        ShaderCompiler::new("./shaders")?,
-        scene_uniform,
+        shader_compiler,
+        &camera,
+        &projection,
         gpu_scene,
         material_atlas,
         lights,
-    ));
+    )?);
 
     let mut ui_pass: UiPass = UiPass::new(render_ctx.clone())?;
-    let mut settings: AppSettings = AppSettings::default();
+    let mut material_editor = MaterialEditor::default();
+    let mut light_editor = LightEditor::default();
+    let mut light_animator = LightAnimator::default();
+    let mut scene_inspector = SceneInspector::default();
+    let mut projection_editor = ProjectionEditor::default();
+    let mut frame_stats = FrameStats::default();
+    let gpu_info = GpuInfo::new(&render_ctx.gpu);
+    let mut screenshot_path = None;
+    // Recording starts with the next frame, its size is needed to set it up.
+    let mut recording_path = None;
+    let mut recorder: Option<FrameRecorder> = None;
+
+    // Passes failing to set up, mostly on shader errors, are reported in the window.
+    macro_rules! or_overlay {
+        ($result:expr) => {
+            match $result {
+                Ok(value) => value,
+                Err(e) => return error_overlay::run(event_loop, &render_ctx, &mut ui_pass, e),
+            }
+        };
+    }
+
+    let skybox_texture = or_overlay!(match &args.environment {
+        Some(path) => EquirectToCubePass::new(
+            &render_ctx.gpu,
+            &render_ctx.shader_compiler,
+            &render_ctx.bind_group_layouts,
+        )
+        .and_then(|pass| pass.load(&render_ctx.gpu, path)),
+        None => test_scenes::load_skybox(&render_ctx.gpu),
+    });
+
+    let mut camera_feed_pass = or_overlay!(CameraFeedPass::new(render_ctx.clone()));
+    if let Some(scene_watcher) = &scene_watcher {
+        if let Err(e) = scene_watcher.add_camera_feeds(&render_ctx, &mut camera_feed_pass) {
+            console.log(format!("{:#}", e));
+        }
+    }
+
+    let mut shadow_pass = or_overlay!(DirectionalShadowPass::new(
+        render_ctx.clone(),
+        [0.2, 0.5, 1.0],
+        &projection.matrix(),
+        settings.shadows.cascade_resolutions,
+    ));
+    let mut depth_prepass = or_overlay!(DepthPrepass::new(render_ctx.clone()));
 
-    let skybox_texture = test_scenes::load_skybox(&render_ctx.gpu)?;
+    let mut volumetric_fog_pass = or_overlay!(VolumetricFogPass::new(render_ctx.clone()));
 
-    let shadow_pass =
-        DirectionalShadowPass::new(render_ctx.clone(), [0.2, 0.5, 1.0], &projection_mat)?;
-    let depth_prepass = DepthPrepass::new(render_ctx.clone())?;
+    // Both lighting paths read lights, the environment and fog through the same bind group.
+    let lights_bg = Arc::new(render_ctx.light_buffers.bind_group(
+        &render_ctx.gpu,
+        &render_ctx.bind_group_layouts,
+        &skybox_texture,
+        &volumetric_fog_pass.volume(),
+    ));
 
-    let forward_phong_pass =
-        forward::PhongPass::new(render_ctx.clone(), shadow_pass.out_bind_group_layout())?;
+    let mut forward_phong_pass = or_overlay!(forward::PhongPass::new(
+        render_ctx.clone(),
+        lights_bg.clone(),
+    ));
 
-    let skybox_pass = SkyboxPass::new(render_ctx.clone(), skybox_texture)?;
+    let mut geometry_pass = or_overlay!(GeometryPass::new(render_ctx.clone()));
 
-    let geometry_pass = GeometryPass::new(render_ctx.clone())?;
+    let mut decal_pass = or_overlay!(DecalPass::new(render_ctx.clone()));
 
-    let deferred_debug_pass = deferred::DebugPass::new(render_ctx.clone())?;
+    let mut deferred_debug_pass = or_overlay!(deferred::DebugPass::new(render_ctx.clone()));
 
-    let ssao_pass: SsaoPass = SsaoPass::new(render_ctx.clone())?;
+    let mut ssao_pass: SsaoPass = or_overlay!(SsaoPass::new(render_ctx.clone()));
 
-    let deferred_phong_pass =
-        deferred::PhongPass::new(render_ctx.clone(), shadow_pass.out_bind_group_layout())?;
+    let mut deferred_phong_pass =
+        or_overlay!(deferred::PhongPass::new(render_ctx.clone(), lights_bg));
 
-    let postprocess_pass = PostprocessPass::new(
-        render_ctx.clone(),
-        &deferred_phong_pass.output_tex_view(),
-        settings.postprocess_settings(),
-    )?;
+    let mut foliage_pass = or_overlay!(FoliagePass::new(render_ctx.clone()));
+
+    let mut water_pass = or_overlay!(WaterPass::new(render_ctx.clone(), &skybox_texture));
+
+    // Lighting passes reflect the skybox, so it's handed over only after they're set up.
+    let mut skybox_pass = or_overlay!(SkyboxPass::new(render_ctx.clone(), skybox_texture));
+
+    let mut light_shafts_pass = or_overlay!(LightShaftsPass::new(render_ctx.clone()));
+    let mut billboard_pass = or_overlay!(BillboardPass::new(render_ctx.clone()));
+    let mut motion_blur_pass = or_overlay!(MotionBlurPass::new(render_ctx.clone()));
+    let mut auto_exposure_pass = or_overlay!(AutoExposurePass::new(render_ctx.clone()));
+    let mut postprocess_pass = or_overlay!(PostprocessPass::new(render_ctx.clone()));
+
+    let mut gizmo_pass = or_overlay!(GizmoPass::new(render_ctx.clone()));
+    let mut render_graph = RenderGraph::default();
+    let mut cascade_bounds_pass = or_overlay!(CascadeBoundsPass::new(render_ctx.clone()));
+    let mut normals_pass = or_overlay!(NormalsPass::new(render_ctx.clone()));
 
     let window: &Window = &window;
 
     let mut dragging = false;
     let mut drag_origin: Option<(f64, f64)> = None;
+    let mut panning = false;
+    // Clicks which didn't turn the camera select objects.
+    let mut drag_turned = false;
+    let mut camera_motion = CameraMotion::default();
+    let input_map = if std::path::Path::new(KEYBINDINGS).exists() {
+        InputMap::load(KEYBINDINGS).unwrap_or_else(|e| {
+            console.log(format!("{:#}, using default keybindings", e));
+            InputMap::default()
+        })
+    } else {
+        InputMap::default()
+    };
+    let mut camera_path = CameraPath::default();
+    let mut cursor_position = PhysicalPosition::new(0.0, 0.0);
+    // Surfaces are reconfigured before the next frame, resizes come in bursts.
+    let mut pending_resize = None;
+    // Fullscreen is applied whenever display settings differ, on the first frame too.
+    let mut applied_display = None;
 
     let time = std::time::Instant::now();
     let mut last_time = time.elapsed();
@@ -110,7 +277,6 @@ async fn run(event_loop: EventLoop<()>, window: Window) -> Result<()> {
     let render_ctx = render_ctx.clone();
     event_loop
         .run(move |event, target| {
-            use winit::keyboard::KeyCode;
             let gpu = &render_ctx.gpu;
             let lights = &render_ctx.light_scene;
 
@@ -122,121 +288,446 @@ async fn run(event_loop: EventLoop<()>, window: Window) -> Result<()> {
                 if !ui.handle_input(window, &event) {
                     match event {
                         WindowEvent::Resized(new_size) => {
-                            // Reconfigure the surface with the new size
-                            // gpu.on_resize((new_size.width, new_size.height));
-                            // postprocess_pass.on_resize(gpu, (new_size.width, new_size.height));
+                            // Minimized windows have no size to render at.
+                            if new_size.width > 0 && new_size.height > 0 {
+                                pending_resize = Some((new_size.width, new_size.height));
+                            }
                             window.request_redraw();
                         }
                         WindowEvent::CloseRequested => {
+                            if let Some(recorder) = recorder.take() {
+                                if let Err(e) = recorder.finish(gpu) {
+                                    eprintln!("Failed to finish recording: {:#}", e);
+                                }
+                            }
+
+                            overrides.restore(&mut settings);
+                            if let Err(e) = settings.save(SETTINGS) {
+                                eprintln!("Failed to save settings: {:#}", e);
+                            }
+
                             target.exit();
                         }
                         WindowEvent::RedrawRequested => {
-                            use nalgebra as na;
+                            // There's no recovering from it, everything on the GPU is gone.
+                            if let Some(reason) = gpu.lost() {
+                                eprintln!("GPU device lost: {}", reason);
+                                overrides.restore(&mut settings);
+                                if let Err(e) = settings.save(SETTINGS) {
+                                    eprintln!("Failed to save settings: {:#}", e);
+                                }
+
+                                target.exit();
+                                return;
+                            }
+
                             let time = time.elapsed();
 
                             let time_ms = (time - last_time).as_secs_f32();
-                            let ui_update = ui.update(window, |ctx| settings.render(ctx, time_ms));
-
-                            let spass_bg = shadow_pass
-                                .render(
-                                    lights
-                                        .directional
-                                        .first()
-                                        .unwrap_or(&Light::new_directional(
-                                            na::Vector3::zeros(),
-                                            na::Vector3::zeros(),
-                                            na::Vector3::zeros(),
-                                            na::Vector3::zeros(),
-                                        )),
-                                    &camera,
-                                    &projection_mat,
-                                )
-                                .unwrap();
-
-                            match settings.pipeline_type {
-                                PipelineType::Deferred => {
-                                    let mut frame = gpu.current_texture();
-
-                                    let g_bufs = geometry_pass.render();
-
-                                    let ssao_tex = ssao_pass.render(g_bufs);
-
-                                    deferred_phong_pass.render(g_bufs, spass_bg, &ssao_tex);
-
-                                    if settings.deferred_dbg.enabled {
-                                        deferred_debug_pass.render(
-                                            g_bufs,
-                                            &frame,
-                                            &ssao_tex,
-                                            &settings.deferred_dbg.debug_type,
-                                        )
-                                    } else {
-                                        if !settings.skybox_disabled {
-                                            skybox_pass.render(
-                                                deferred_phong_pass.output_tex_view(),
-                                                true,
-                                            );
-                                        }
+                            frame_stats.frame_time(time - last_time);
+
+                            let mut light_scene = lights.write().unwrap();
+                            let mut lights_changed =
+                                light_animator.update(time_ms, &mut light_scene);
+                            if settings.background.mode == BackgroundMode::ProceduralSky {
+                                settings.background.sky.advance(time_ms);
+                                if let Some(sun) = light_scene.directional.first_mut() {
+                                    settings.background.sky.light_sun(sun);
+                                    lights_changed = true;
+                                }
+                            }
+                            if lights_changed {
+                                if let Err(e) = render_ctx.light_buffers.upload(gpu, &light_scene) {
+                                    console.log(format!("{:#}", e));
+                                }
+                            }
+                            drop(light_scene);
+
+                            // Playing back a camera path takes over the camera.
+                            if let Some(pose) = camera_path.advance(time_ms) {
+                                camera.update(&gpu.queue, |c| pose.apply(c)).unwrap();
+                            } else if camera_motion.advance(time_ms) {
+                                camera
+                                    .update(&gpu.queue, |c| camera_motion.apply(c, time_ms))
+                                    .unwrap();
+                            }
+
+                            if let Some(summary) = camera_path.take_finished() {
+                                console.log(format!(
+                                    "Camera path: {} frames in {:.2} s, {:.2} ms per frame",
+                                    summary.frames,
+                                    summary.seconds,
+                                    summary.seconds * 1000.0 / summary.frames.max(1) as f32
+                                ));
+                            }
 
-                                        if !settings.postprocess_disabled {
-                                            frame = postprocess_pass.render(
-                                                settings.postprocess_settings(),
-                                                frame,
-                                                settings.pipeline_type == PipelineType::Deferred,
-                                            );
+                            let monitors: Vec<_> = window
+                                .available_monitors()
+                                .filter_map(|monitor| monitor.name())
+                                .collect();
+                            let gpu_timings = render_ctx.profiler.timings();
+                            let pass_timings = render_ctx.profiler.pass_timings();
+                            let ui_update = ui.update(window, |ctx| {
+                                settings.render(ctx, time_ms, &monitors, &gpu_timings);
+                                material_editor.render(ctx, gpu, &render_ctx.material_atlas);
+                                light_editor.render(
+                                    ctx,
+                                    gpu,
+                                    &render_ctx.light_scene,
+                                    &render_ctx.light_buffers,
+                                );
+                                light_animator.render(ctx, &lights.read().unwrap());
+                                scene_inspector.render(
+                                    ctx,
+                                    gpu,
+                                    &render_ctx.gpu_scene,
+                                    &render_ctx.material_atlas,
+                                );
+                                camera_path.render(ctx, camera.camera());
+                                projection_editor.render(ctx, projection.perspective());
+                                frame_stats.render(ctx);
+                                gpu_info.render(ctx);
+                                render_ctx.profiler.render(ctx);
+                                frame_graph_panel::render(
+                                    ctx,
+                                    render_graph.passes(),
+                                    &pass_timings,
+                                );
+                                scene_report.render(ctx);
+                                console.render(ctx);
+                            });
+
+                            let mut scene_replaced = false;
+                            for command in console.drain() {
+                                let result = match command {
+                                    Command::LoadScene(path) => {
+                                        scene_watcher
+                                            .get_or_insert_with(|| SceneScriptWatcher::new(&path))
+                                            .watch(path);
+                                        Ok(())
+                                    }
+                                    Command::SaveScene(path) => scene_watcher.as_mut().context(NO_SCENE_SCRIPT)
+                                        .and_then(|scene_watcher| scene_watcher.save(&path, &render_ctx, camera.camera())
+                                        .map(|_| console.log(format!("Saved {}", path.display())))),
+                                    Command::Set(name, value) => settings.set(&name, &value),
+                                    Command::Toggle(name) => {
+                                        settings.toggle(&name).map(|enabled| {
+                                            console.log(format!(
+                                                "{} {}",
+                                                name,
+                                                if enabled { "enabled" } else { "disabled" }
+                                            ))
+                                        })
+                                    }
+                                    Command::Spawn {
+                                        model,
+                                        translation,
+                                        material,
+                                    } => scene_watcher.as_mut().context(NO_SCENE_SCRIPT)
+                                        .and_then(|scene_watcher| {
+                                            scene_watcher.spawn(&render_ctx, model, translation, material)
+                                        })
+                                        .map(|_| scene_replaced = true),
+                                    Command::Screenshot(path) => {
+                                        screenshot_path = Some(path);
+                                        Ok(())
+                                    }
+                                    Command::Record(Some(path)) => {
+                                        recording_path = Some(path);
+                                        Ok(())
+                                    }
+                                    Command::Record(None) => match recorder.take() {
+                                        Some(recorder) => {
+                                            let frames = recorder.frames();
+                                            recorder.finish(gpu).map(|()| {
+                                                console.log(format!("Recorded {} frames", frames))
+                                            })
                                         }
+                                        None => Err(anyhow::anyhow!("not recording")),
+                                    },
+                                    Command::Histogram => {
+                                        auto_exposure_pass.histogram().map(|histogram| {
+                                            // In stops, log2 of luminance.
+                                            let stops = |fraction| {
+                                                histogram
+                                                    .percentile(fraction)
+                                                    .map_or("-".to_string(), |l| format!("{:.2}", l))
+                                            };
+                                            console.log(format!(
+                                                "Luminance of {} of {} pixels lit: average {:.2}, 5% {}, median {}, 95% {} stops",
+                                                histogram.lit,
+                                                histogram.total,
+                                                histogram.average_log_luminance,
+                                                stops(0.05),
+                                                stops(0.5),
+                                                stops(0.95)
+                                            ))
+                                        })
                                     }
+                                };
 
-                                    let frame = ui.render(frame, ui_update);
-                                    frame.present();
+                                if let Err(e) = result {
+                                    console.log(format!("{:#}", e));
                                 }
-                                PipelineType::Forward => {
-                                    if settings.depth_prepass_enabled {
-                                        depth_prepass.render();
+                            }
+
+                            if let Some(scene_watcher) = &mut scene_watcher {
+                                match scene_watcher.poll(&render_ctx) {
+                                    Ok(replaced) => scene_replaced |= replaced,
+                                    Err(e) => console.log(format!("{:#}", e)),
+                                }
+                                for warning in scene_watcher.take_warnings() {
+                                    console.log(format!("warning: {}", warning));
+                                }
+                            }
+
+                            for e in render_ctx
+                                .material_atlas
+                                .read()
+                                .unwrap()
+                                .upload_loaded_textures(gpu)
+                            {
+                                console.log(format!("{:#}", e));
+                            }
+                            for e in render_ctx.shader_compiler.take_cache_errors() {
+                                console.log(format!("{:#}", e));
+                            }
+
+                            if let Some(report) =
+                                scene_watcher.as_mut().and_then(|scene_watcher| scene_watcher.take_report())
+                            {
+                                for issue in &report.issues {
+                                    console.log(format!("warning: {}", issue));
+                                }
+                                scene_report = report;
+                            }
+
+                            // Selected objects and materials, along with camera feeds rendering
+                            // into materials, are gone with the old scene.
+                            if scene_replaced {
+                                material_editor = MaterialEditor::default();
+                                scene_inspector = SceneInspector::default();
+                                camera_feed_pass.clear();
+                                if let Some(scene_watcher) = &scene_watcher {
+                                    if let Err(e) = scene_watcher
+                                        .add_camera_feeds(&render_ctx, &mut camera_feed_pass)
+                                    {
+                                        console.log(format!("{:#}", e));
                                     }
+                                }
+                            }
 
-                                    let mut frame = forward_phong_pass
-                                        .render(spass_bg, settings.depth_prepass_enabled);
+                            if let Err(e) = render_ctx.gpu_scene.write().unwrap().update_lods(
+                                gpu,
+                                &camera.camera().eye(),
+                                projection.perspective(),
+                            ) {
+                                console.log(format!("{:#}", e));
+                            }
 
-                                    if !settings.skybox_disabled {
-                                        skybox_pass.render(
-                                            frame.texture.create_view(&Default::default()),
-                                            false,
-                                        );
+                            if applied_display.as_ref() != Some(&settings.display) {
+                                window.set_fullscreen(settings.display.fullscreen(window));
+                                applied_display = Some(settings.display.clone());
+                            }
+
+                            // The surface can only be reconfigured with no frame acquired.
+                            let resized = pending_resize.take();
+                            if let Some(size) = resized {
+                                gpu.on_resize(size);
+                                if let Err(e) = projection
+                                    .on_resize(&gpu.queue, size)
+                                    .and_then(|()| shadow_pass.on_resize(&projection.matrix()))
+                                {
+                                    console.log(format!("{:#}", e));
+                                }
+                            }
+                            if let Some(edited) = projection_editor.take_edited() {
+                                if let Err(e) = projection
+                                    .update(&gpu.queue, |p| *p = edited)
+                                    .and_then(|()| shadow_pass.on_resize(&projection.matrix()))
+                                {
+                                    console.log(format!("{:#}", e));
+                                }
+                            }
+                            if settings.present_mode != gpu.present_mode() {
+                                if let Err(e) = gpu.set_present_mode(settings.present_mode) {
+                                    console.log(format!("{:#}", e));
+                                    settings.present_mode = gpu.present_mode();
+                                }
+                            }
+
+                            if let Err(e) = render_ctx
+                                .scene_uniform
+                                .write_fog(&gpu.queue, &settings.fog)
+                            {
+                                console.log(format!("{:#}", e));
+                            }
+                            if let Err(e) = render_ctx.scene_uniform.write_frame(
+                                gpu,
+                                &camera,
+                                &projection,
+                                time,
+                                time - last_time,
+                            ) {
+                                console.log(format!("{:#}", e));
+                            }
+                            billboard_pass
+                                .set_billboards(light_editor.light_icons(&lights.read().unwrap()));
+
+                            // Passes of the other pipeline, or turned off in settings, sit out.
+                            // The graph runs the rest in order of textures they pass along.
+                            let mut passes: [&mut dyn RenderPass; 21] = [
+                                &mut camera_feed_pass,
+                                &mut shadow_pass,
+                                &mut volumetric_fog_pass,
+                                &mut depth_prepass,
+                                &mut forward_phong_pass,
+                                &mut geometry_pass,
+                                &mut decal_pass,
+                                &mut ssao_pass,
+                                &mut deferred_phong_pass,
+                                &mut deferred_debug_pass,
+                                &mut foliage_pass,
+                                &mut skybox_pass,
+                                &mut water_pass,
+                                &mut light_shafts_pass,
+                                &mut billboard_pass,
+                                &mut motion_blur_pass,
+                                &mut auto_exposure_pass,
+                                &mut postprocess_pass,
+                                &mut cascade_bounds_pass,
+                                &mut normals_pass,
+                                &mut gizmo_pass,
+                            ];
+                            if let Some(size) = resized {
+                                for pass in passes.iter_mut() {
+                                    if let Err(e) = pass.resize(size) {
+                                        console.log(format!("{}: {:#}", pass.name(), e));
                                     }
+                                }
+                            }
 
-                                    if !settings.postprocess_disabled {
-                                        frame = postprocess_pass.render(
-                                            settings.postprocess_settings(),
-                                            frame,
-                                            settings.pipeline_type == PipelineType::Deferred,
-                                        );
+                            for e in crash_report::take_errors() {
+                                console.log(format!("wgpu error: {}", e));
+                            }
+
+                            frame_stats.begin_encode();
+                            let frame = match gpu.current_texture() {
+                                Ok(Some(frame)) => frame,
+                                skipped => {
+                                    if let Err(e) = skipped {
+                                        console.log(format!("{:#}", e));
                                     }
 
-                                    let frame = ui.render(frame, ui_update);
-                                    frame.present();
+                                    ui.skip(ui_update);
+                                    last_time = time;
+                                    window.request_redraw();
+                                    return;
+                                }
+                            };
+                            let sun = lights.read().unwrap().directional.first().copied();
+                            let mut frame_ctx = FrameContext::new(
+                                frame,
+                                &settings,
+                                &camera,
+                                projection.matrix(),
+                                sun,
+                            );
+
+                            gpu.flush_uploads();
+                            match render_graph.prepare(gpu, &passes, &mut frame_ctx) {
+                                Ok(order) => {
+                                    for i in order {
+                                        let pass = &mut passes[i];
+                                        crash_report::enter_pass(pass.name());
+                                        render_ctx.profiler.enter_pass(pass.name());
+                                        if let Err(e) = pass.render(&mut frame_ctx) {
+                                            console.log(format!("{}: {:#}", pass.name(), e));
+                                        }
+                                    }
+                                }
+                                Err(e) => console.log(format!("{:#}", e)),
+                            }
+                            let frame = frame_ctx.frame;
+
+                            if let Some(path) = screenshot_path.take() {
+                                match screenshot::save(gpu, &frame.texture, &path) {
+                                    Ok(()) => console.log(format!("Saved {}", path.display())),
+                                    Err(e) => console.log(format!("{:#}", e)),
+                                }
+                            }
+
+                            if let Some(path) = recording_path.take() {
+                                match FrameRecorder::new(gpu, &frame.texture, &path) {
+                                    Ok(started) => {
+                                        console.log(format!("Recording to {}", path.display()));
+                                        recorder = Some(started);
+                                    }
+                                    Err(e) => console.log(format!("{:#}", e)),
                                 }
                             }
 
+                            if let Some(active) = recorder.as_mut() {
+                                if let Err(e) = active.record(gpu, &frame.texture) {
+                                    console.log(format!("Recording stopped: {:#}", e));
+                                    recorder = None;
+                                }
+                            }
+
+                            crash_report::enter_pass("UiPass");
+                            let frame = ui.render(frame, ui_update);
+                            frame_stats.end_encode(&render_ctx);
+                            frame.present();
+                            render_ctx.profiler.end_frame(gpu);
+                            render_ctx.uniform_ring.end_frame();
+                            render_ctx.texture_pool.end_frame();
+
                             last_time = time;
                             window.request_redraw();
                         }
                         WindowEvent::MouseInput { state, button, .. } => {
                             if state.is_pressed() {
                                 if let MouseButton::Left = button {
+                                    let view = camera.look_at_matrix();
+                                    if let Some(axis) = gizmo_pass.axis_at(cursor_position, &view) {
+                                        camera.update(&gpu.queue, |c| c.look_along(-axis)).unwrap();
+                                        return;
+                                    }
+
                                     window
                                         .set_cursor_grab(winit::window::CursorGrabMode::Confined)
                                         .ok();
                                     window.set_cursor_visible(false);
                                     dragging = true;
+                                    drag_turned = false;
+                                }
+
+                                if let MouseButton::Middle = button {
+                                    panning = true;
                                 }
                             } else {
+                                if dragging && !drag_turned {
+                                    let size = window.inner_size();
+                                    let picked = Ray::from_cursor(
+                                        (cursor_position.x as f32, cursor_position.y as f32),
+                                        (size.width as f32, size.height as f32),
+                                        &camera.look_at_matrix(),
+                                        &projection.matrix(),
+                                    )
+                                    .and_then(|ray| {
+                                        picking::pick(&render_ctx.gpu_scene.read().unwrap(), &ray)
+                                    });
+
+                                    scene_inspector.select(picked);
+                                }
+
                                 window
                                     .set_cursor_grab(winit::window::CursorGrabMode::None)
                                     .ok();
                                 window.set_cursor_visible(true);
                                 dragging = false;
                                 drag_origin = None;
+                                panning = false;
                             }
                         }
                         WindowEvent::MouseWheel {
@@ -245,10 +736,21 @@ async fn run(event_loop: EventLoop<()>, window: Window) -> Result<()> {
                             ..
                         } => {
                             if phase == TouchPhase::Moved {
-                                camera.update(&gpu.queue, |c| c.forwards(y)).unwrap();
+                                camera.update(&gpu.queue, |c| c.zoom(y)).unwrap();
                             }
                         }
                         WindowEvent::CursorMoved { position, .. } => {
+                            if panning {
+                                let full_size = window.inner_size();
+                                let dx = (position.x - cursor_position.x) / full_size.width as f64;
+                                let dy = (position.y - cursor_position.y) / full_size.height as f64;
+
+                                camera
+                                    .update(&gpu.queue, |c| c.pan(dx as f32, dy as f32))
+                                    .unwrap();
+                            }
+                            cursor_position = position;
+
                             if dragging {
                                 match drag_origin {
                                     Some(origin) => {
@@ -259,6 +761,7 @@ async fn run(event_loop: EventLoop<()>, window: Window) -> Result<()> {
                                         );
 
                                         let delta = (pos.0 - origin.0, pos.1 - origin.1);
+                                        drag_turned |= delta != (0.0, 0.0);
 
                                         camera
                                             .update(&gpu.queue, |c| {
@@ -290,65 +793,30 @@ async fn run(event_loop: EventLoop<()>, window: Window) -> Result<()> {
                                 }
                             }
                         }
+                        WindowEvent::Focused(false) => camera_motion.release_all(),
                         WindowEvent::KeyboardInput { event, .. } => {
-                            if event.state.is_pressed() {
-                                match event.physical_key {
-                                    PhysicalKey::Code(KeyCode::KeyA) => {
-                                        camera
-                                            .update(&gpu.queue, |c| c.strafe(-MOVE_DELTA))
-                                            .unwrap();
-                                    }
-                                    PhysicalKey::Code(KeyCode::KeyD) => {
-                                        camera
-                                            .update(&gpu.queue, |c| c.strafe(MOVE_DELTA))
-                                            .unwrap();
-                                    }
-                                    PhysicalKey::Code(KeyCode::KeyQ) => {
-                                        camera.update(&gpu.queue, |c| c.fly(MOVE_DELTA)).unwrap();
-                                    }
-                                    PhysicalKey::Code(KeyCode::KeyZ) => {
-                                        camera.update(&gpu.queue, |c| c.fly(-MOVE_DELTA)).unwrap();
-                                    }
-                                    PhysicalKey::Code(KeyCode::KeyW) => {
-                                        camera
-                                            .update(&gpu.queue, |c| c.forwards(MOVE_DELTA))
-                                            .unwrap();
-                                    }
-                                    PhysicalKey::Code(KeyCode::KeyS) => {
-                                        camera
-                                            .update(&gpu.queue, |c| c.forwards(-MOVE_DELTA))
-                                            .unwrap();
-                                    }
-                                    PhysicalKey::Code(KeyCode::ArrowLeft) => {
-                                        camera
-                                            .update(&gpu.queue, |c| {
-                                                c.tilt_horizontally(-TILT_DELTA.to_radians())
-                                            })
-                                            .unwrap();
-                                    }
-                                    PhysicalKey::Code(KeyCode::ArrowRight) => {
-                                        camera
-                                            .update(&gpu.queue, |c| {
-                                                c.tilt_horizontally(TILT_DELTA.to_radians())
-                                            })
-                                            .unwrap();
-                                    }
-                                    PhysicalKey::Code(KeyCode::ArrowUp) => {
-                                        camera
-                                            .update(&gpu.queue, |c| {
-                                                c.tilt_vertically(TILT_DELTA.to_radians())
-                                            })
-                                            .unwrap();
-                                    }
-                                    PhysicalKey::Code(KeyCode::ArrowDown) => {
-                                        camera
-                                            .update(&gpu.queue, |c| {
-                                                c.tilt_vertically(-TILT_DELTA.to_radians())
-                                            })
-                                            .unwrap();
-                                    }
-                                    _ => {}
+                            let PhysicalKey::Code(key) = event.physical_key else {
+                                return;
+                            };
+                            let pressed = event.state.is_pressed();
+
+                            match input_map.action(key) {
+                                Some(Action::Move(motion)) => {
+                                    camera_motion.set_held(*motion, pressed);
+                                }
+                                Some(Action::ToggleCameraMode) if pressed && !event.repeat => {
+                                    let mode = match camera.mode() {
+                                        CameraMode::FreeFly => CameraMode::Orbit,
+                                        CameraMode::Orbit => CameraMode::FreeFly,
+                                    };
+
+                                    camera.update(&gpu.queue, |c| c.set_mode(mode)).unwrap();
+                                    console.log(format!("Camera mode: {:?}", mode));
                                 }
+                                Some(Action::Command(line)) if pressed && !event.repeat => {
+                                    console.submit(line);
+                                }
+                                _ => {}
                             }
                         }
                         _ => {}
@@ -363,12 +831,14 @@ async fn run(event_loop: EventLoop<()>, window: Window) -> Result<()> {
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
+
     let event_loop = EventLoop::new()?;
     let window = WindowBuilder::new()
-        .with_inner_size(LogicalSize::new(1366, 768))
+        .with_inner_size(LogicalSize::new(args.width, args.height))
         .build(&event_loop)?;
 
-    run(event_loop, window).await?;
+    run(event_loop, window, args).await?;
 
     Ok(())
 }
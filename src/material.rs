@@ -1,16 +1,109 @@
-use std::path::Path;
+use std::{
+    collections::HashMap,
+    num::NonZeroU64,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use encase::{ShaderSize, ShaderType, UniformBuffer};
 use nalgebra as na;
 
-use crate::gpu::Gpu;
+use crate::{
+    assets::canonical_path, gpu::Gpu, mesh::MeshVertexArrayType, shader_compiler::CompilationUnit,
+    shader_permutation::ShaderPermutation,
+};
 
 type FVec4 = na::Vector4<f32>;
+type IVec2 = na::Vector2<i32>;
+
+const FALLBACK_COLOR: [u8; 4] = [255, 0, 255, 255];
+// Shown in place of diffuse maps which are still loading, alternating with the fallback color.
+const CHECKER_COLOR: [u8; 4] = [0, 0, 0, 255];
+const CHECKER_CELLS: u32 = 8;
+const CHECKER_CELL_SIZE: u32 = 8;
+// Normal maps which are still loading keep surface normals unchanged.
+const FLAT_NORMAL: [u8; 4] = [128, 128, 255, 255];
+// Specular maps which are still loading give no highlights.
+const NO_SPECULAR: [u8; 4] = [0, 0, 0, 0];
+// Render targets show nothing until they're first copied to.
+const RENDER_TARGET_CLEAR: [u8; 4] = [0, 0, 0, 255];
+
+/// Side lengths of square textures packed together into texture arrays, one array
+/// per size and color space. Images are resized to the smallest size they fit in,
+/// larger ones are scaled down to the last.
+const TEXTURE_CLASSES: [u32; 4] = [256, 512, 1024, 2048];
+const COLOR_SPACES: [ColorSpace; 2] = [ColorSpace::Srgb, ColorSpace::Linear];
+const INITIAL_TEXTURE_LAYERS: u32 = 4;
+const INITIAL_MATERIAL_CAPACITY: usize = 16;
+/// Diffuse layers blended by splat materials, one per channel of the splat map.
+pub const SPLAT_LAYERS: usize = 4;
+/// Highest shininess the G-Buffer keeps, see `MAX_SHININESS` in `deferred/gbuffer.wgsl`.
+/// Its logarithm is stored, so anything above gets clamped.
+pub const MAX_SHININESS: f32 = 2048.0;
+
+// Materials buffer and sampler come first, texture arrays follow ordered by color space and size.
+const TEXTURE_ARRAYS_BINDING: u32 = 2;
 
 #[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Copy, Debug, Hash)]
 pub struct MaterialId(usize);
 
+impl MaterialId {
+    /// Index of the material in the atlas buffer, passed to shaders with every instance.
+    pub fn index(&self) -> u32 {
+        self.0 as u32
+    }
+}
+
+/// How texel values of a material texture are encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    /// Colors authored for display, like diffuse maps. Decoded to linear when sampled,
+    /// so lighting is computed with linear values.
+    Srgb,
+    /// Data stored as is, like normal or specular maps.
+    Linear,
+}
+
+impl ColorSpace {
+    const fn texture_format(&self) -> wgpu::TextureFormat {
+        match self {
+            Self::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            Self::Linear => wgpu::TextureFormat::Rgba8Unorm,
+        }
+    }
+}
+
+/// Layer of one of the atlas texture arrays holding a material texture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MaterialTexture {
+    class: usize,
+    color_space: ColorSpace,
+    layer: u32,
+}
+
+impl MaterialTexture {
+    // Size class and layer as read by shaders, a negative layer stands for no texture.
+    fn shader_ref(texture: Option<&Self>) -> IVec2 {
+        match texture {
+            Some(texture) => IVec2::new(texture.class as i32, texture.layer as i32),
+            None => IVec2::new(0, -1),
+        }
+    }
+
+    /// Format of textures made by `MaterialAtlas::add_phong_render_target`.
+    pub const RENDER_TARGET_FORMAT: wgpu::TextureFormat = ColorSpace::Srgb.texture_format();
+
+    /// Side length of the layer.
+    pub fn size(&self) -> u32 {
+        TEXTURE_CLASSES[self.class]
+    }
+}
+
 #[allow(clippy::enum_variant_names)]
 pub enum Material {
     PhongSolid {
@@ -20,261 +113,333 @@ pub enum Material {
         diffuse: FVec4,
         // w = shininess
         specular: FVec4,
+        reflectivity: f32,
+        // w unused
+        emissive: FVec4,
     },
     PhongTextured {
-        diffuse: wgpu::Texture,
+        diffuse: MaterialTexture,
         specular: SpecularTextureResult,
+        reflectivity: f32,
+        emissive: FVec4,
     },
     PhongTexturedNormal {
-        diffuse: wgpu::Texture,
-        normal: wgpu::Texture,
+        diffuse: MaterialTexture,
+        normal: MaterialTexture,
+        specular: SpecularTextureResult,
+        reflectivity: f32,
+        emissive: FVec4,
+    },
+    /// Diffuse layers blended by weights in channels of `splat`, which covers
+    /// the whole UV range. Layers repeat `tiling` times over the same range.
+    PhongSplat {
+        splat: MaterialTexture,
+        layers: [MaterialTexture; SPLAT_LAYERS],
+        tiling: f32,
         specular: SpecularTextureResult,
+        reflectivity: f32,
+        emissive: FVec4,
     },
 }
 
+/// Variant of shaders drawing Phong materials. Materials are drawn with meshes
+/// of one vertex layout, which decides what the material is sampled with:
+/// solid colors without UVs, textures with them and normal maps with tangents too.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct PhongVariant(pub MeshVertexArrayType);
+
+impl PhongVariant {
+    fn shader_defs(&self) -> &'static [&'static str] {
+        match self.0 {
+            MeshVertexArrayType::PN => &["MATERIAL_PHONG_SOLID"],
+            MeshVertexArrayType::PNUV => &["MATERIAL_PHONG_TEXTURED"],
+            MeshVertexArrayType::PNTBUV => &["MATERIAL_PHONG_TEXTURED", "NORMAL_MAP"],
+        }
+    }
+}
+
+impl ShaderPermutation for PhongVariant {
+    fn all() -> Vec<Self> {
+        MeshVertexArrayType::ALL.map(Self).to_vec()
+    }
+
+    fn apply(&self, unit: CompilationUnit) -> CompilationUnit {
+        self.shader_defs()
+            .iter()
+            .fold(self.0.apply(unit), |unit, def| unit.with_def(*def))
+    }
+}
+
+impl Material {
+    /// Vertex layout of meshes this material can be drawn with.
+    pub fn vertex_array_type(&self) -> MeshVertexArrayType {
+        match self {
+            Self::PhongSolid { .. } => MeshVertexArrayType::PN,
+            Self::PhongTextured { .. } | Self::PhongSplat { .. } => MeshVertexArrayType::PNUV,
+            Self::PhongTexturedNormal { .. } => MeshVertexArrayType::PNTBUV,
+        }
+    }
+
+    /// How much of the environment is mirrored by the surface,
+    /// from 0 (none) to 1 (a perfect mirror).
+    pub fn reflectivity(&self) -> f32 {
+        match self {
+            Self::PhongSolid { reflectivity, .. }
+            | Self::PhongTextured { reflectivity, .. }
+            | Self::PhongTexturedNormal { reflectivity, .. }
+            | Self::PhongSplat { reflectivity, .. } => *reflectivity,
+        }
+    }
+
+    pub fn reflectivity_mut(&mut self) -> &mut f32 {
+        match self {
+            Self::PhongSolid { reflectivity, .. }
+            | Self::PhongTextured { reflectivity, .. }
+            | Self::PhongTexturedNormal { reflectivity, .. }
+            | Self::PhongSplat { reflectivity, .. } => reflectivity,
+        }
+    }
+
+    /// Light given off by the surface itself, added regardless of scene lights.
+    pub fn emissive(&self) -> FVec4 {
+        match self {
+            Self::PhongSolid { emissive, .. }
+            | Self::PhongTextured { emissive, .. }
+            | Self::PhongTexturedNormal { emissive, .. }
+            | Self::PhongSplat { emissive, .. } => *emissive,
+        }
+    }
+
+    pub fn emissive_mut(&mut self) -> &mut FVec4 {
+        match self {
+            Self::PhongSolid { emissive, .. }
+            | Self::PhongTextured { emissive, .. }
+            | Self::PhongTexturedNormal { emissive, .. }
+            | Self::PhongSplat { emissive, .. } => emissive,
+        }
+    }
+}
+
+// Every kind of material shares the representation, solid ones leave textures out
+// and textured ones take only the specular color used in place of a missing map.
 #[derive(ShaderType)]
-struct GpuPhongSolidRepr {
+struct GpuMaterialRepr {
     ambient: FVec4,
     diffuse: FVec4,
+    // w = shininess
     specular: FVec4,
+    emissive: FVec4,
+    diffuse_t: IVec2,
+    specular_t: IVec2,
+    normal_t: IVec2,
+    gloss_map: u32,
+    reflectivity: f32,
+    // Negative layer for materials other than splat ones.
+    splat_t: IVec2,
+    splat_layers: [IVec2; SPLAT_LAYERS],
+    splat_tiling: f32,
 }
 
-#[allow(clippy::enum_variant_names)]
-enum GpuMaterial {
-    PhongSolid {
-        buffer: wgpu::Buffer,
-        bind_group: wgpu::BindGroup,
-    },
-    PhongTextured {
-        bind_group: wgpu::BindGroup,
-    },
-    PhongTexturedNormal {
-        bind_group: wgpu::BindGroup,
-    },
-}
-
-impl GpuMaterial {
-    pub fn new(
-        gpu: &Gpu,
-        material: &Material,
-        default_textures: &MaterialAtlasTextureDefaults,
-        layouts: &MaterialAtlasLayouts,
-    ) -> Result<Self> {
-        use wgpu::util::DeviceExt;
-
+impl GpuMaterialRepr {
+    fn new(material: &Material) -> Self {
         match material {
             Material::PhongSolid {
                 ambient,
                 diffuse,
                 specular,
-            } => {
-                let repr_size: u64 = GpuPhongSolidRepr::SHADER_SIZE.into();
-                let mut contents = UniformBuffer::new(Vec::with_capacity(repr_size as usize));
-                contents.write(&GpuPhongSolidRepr {
-                    ambient: *ambient,
-                    diffuse: *diffuse,
-                    specular: *specular,
-                })?;
-
-                let buffer = gpu
-                    .device
-                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                        label: Some("Material::PhongSolid"),
-                        contents: contents.into_inner().as_slice(),
-                        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-                    });
-
-                let bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: Some("Material::PhongSolidBindGroup"),
-                    layout: &layouts.phong_solid,
-                    entries: &[wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: buffer.as_entire_binding(),
-                    }],
-                });
-
-                Ok(Self::PhongSolid {
-                    buffer,
-                    bind_group: bg,
-                })
-            }
-            Material::PhongTextured { diffuse, specular } => {
-                let diffuse_view = diffuse.create_view(&wgpu::TextureViewDescriptor::default());
-                let mut shininess_contents: Vec<u8> =
-                    Vec::with_capacity(std::mem::size_of::<f32>());
-
-                let specular_view = match specular {
-                    SpecularTextureResult::Ideal(shininess) => {
-                        shininess_contents.extend(bytemuck::cast_slice(&[*shininess]));
-                        default_textures
-                            .white
-                            .create_view(&wgpu::TextureViewDescriptor::default())
-                    }
-                    SpecularTextureResult::FullDiffuse => {
-                        shininess_contents.extend(bytemuck::cast_slice(&[0.0]));
-                        default_textures
-                            .black
-                            .create_view(&wgpu::TextureViewDescriptor::default())
-                    }
-                    SpecularTextureResult::Provided(texture, shininess) => {
-                        shininess_contents.extend(bytemuck::cast_slice(&[*shininess]));
-                        texture.create_view(&wgpu::TextureViewDescriptor::default())
-                    }
-                };
-
-                let shininess_buf =
-                    gpu.device
-                        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                            label: Some("Material::PhongTexturedShininess"),
-                            contents: &shininess_contents,
-                            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-                        });
-
-                let bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: Some("Material::PhongTexturedBindGroup"),
-                    layout: &layouts.phong_textured,
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(&diffuse_view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::TextureView(&specular_view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 2,
-                            resource: wgpu::BindingResource::Sampler(&default_textures.sampler),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 3,
-                            resource: wgpu::BindingResource::Buffer(
-                                shininess_buf.as_entire_buffer_binding(),
-                            ),
-                        },
-                    ],
-                });
-
-                Ok(Self::PhongTextured { bind_group: bg })
-            }
-            Material::PhongTexturedNormal {
+                reflectivity,
+                emissive,
+            } => Self {
+                ambient: *ambient,
+                diffuse: *diffuse,
+                specular: *specular,
+                emissive: *emissive,
+                diffuse_t: MaterialTexture::shader_ref(None),
+                specular_t: MaterialTexture::shader_ref(None),
+                normal_t: MaterialTexture::shader_ref(None),
+                gloss_map: 0,
+                reflectivity: *reflectivity,
+                splat_t: MaterialTexture::shader_ref(None),
+                splat_layers: [MaterialTexture::shader_ref(None); SPLAT_LAYERS],
+                splat_tiling: 1.0,
+            },
+            Material::PhongTextured {
                 diffuse,
                 specular,
+                reflectivity,
+                emissive,
+            } => Self::textured(diffuse, None, specular, *reflectivity, *emissive),
+            Material::PhongTexturedNormal {
+                diffuse,
                 normal,
-            } => {
-                let diffuse_view = diffuse.create_view(&wgpu::TextureViewDescriptor::default());
-                let normal_view = normal.create_view(&wgpu::TextureViewDescriptor::default());
-                let mut shininess_contents: Vec<u8> =
-                    Vec::with_capacity(std::mem::size_of::<f32>());
-
-                let specular_view = match specular {
-                    SpecularTextureResult::Ideal(shininess) => {
-                        shininess_contents.extend(bytemuck::cast_slice(&[*shininess]));
-                        default_textures
-                            .white
-                            .create_view(&wgpu::TextureViewDescriptor::default())
-                    }
-                    SpecularTextureResult::FullDiffuse => {
-                        shininess_contents.extend(bytemuck::cast_slice(&[0.0]));
-                        default_textures
-                            .black
-                            .create_view(&wgpu::TextureViewDescriptor::default())
-                    }
-                    SpecularTextureResult::Provided(texture, shininess) => {
-                        shininess_contents.extend(bytemuck::cast_slice(&[*shininess]));
-                        texture.create_view(&wgpu::TextureViewDescriptor::default())
-                    }
-                };
-
-                let shininess_buf =
-                    gpu.device
-                        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                            label: Some("Material::PhongTexturedShininess"),
-                            contents: &shininess_contents,
-                            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-                        });
-
-                let bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: Some("Material::PhongTexturedNormalBindGroup"),
-                    layout: &layouts.phong_textured_normal,
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(&diffuse_view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::TextureView(&specular_view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 2,
-                            resource: wgpu::BindingResource::TextureView(&normal_view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 3,
-                            resource: wgpu::BindingResource::Sampler(&default_textures.sampler),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 4,
-                            resource: wgpu::BindingResource::Buffer(
-                                shininess_buf.as_entire_buffer_binding(),
-                            ),
-                        },
-                    ],
-                });
+                specular,
+                reflectivity,
+                emissive,
+            } => Self::textured(diffuse, Some(normal), specular, *reflectivity, *emissive),
+            Material::PhongSplat {
+                splat,
+                layers,
+                tiling,
+                specular,
+                reflectivity,
+                emissive,
+            } => Self {
+                splat_t: MaterialTexture::shader_ref(Some(splat)),
+                splat_layers: layers.map(|layer| MaterialTexture::shader_ref(Some(&layer))),
+                splat_tiling: *tiling,
+                ..Self::textured(&layers[0], None, specular, *reflectivity, *emissive)
+            },
+        }
+    }
 
-                Ok(Self::PhongTextured { bind_group: bg })
+    fn textured(
+        diffuse: &MaterialTexture,
+        normal: Option<&MaterialTexture>,
+        specular: &SpecularTextureResult,
+        reflectivity: f32,
+        emissive: FVec4,
+    ) -> Self {
+        let (specular_color, gloss_map) = specular.constants();
+
+        Self {
+            ambient: FVec4::zeros(),
+            diffuse: FVec4::zeros(),
+            specular: specular_color,
+            emissive,
+            diffuse_t: MaterialTexture::shader_ref(Some(diffuse)),
+            specular_t: MaterialTexture::shader_ref(specular.texture()),
+            normal_t: MaterialTexture::shader_ref(normal),
+            gloss_map,
+            reflectivity,
+            splat_t: MaterialTexture::shader_ref(None),
+            splat_layers: [MaterialTexture::shader_ref(None); SPLAT_LAYERS],
+            splat_tiling: 1.0,
+        }
+    }
+
+    fn contents(material: &Material) -> Result<Vec<u8>> {
+        let repr_size: u64 = Self::SHADER_SIZE.into();
+        let mut contents = UniformBuffer::new(Vec::with_capacity(repr_size as usize));
+        contents.write(&Self::new(material))?;
+
+        Ok(contents.into_inner())
+    }
+}
+
+pub enum SpecularTexture {
+    Ideal(f32),
+    FullDiffuse,
+    Provided(String, f32),
+    /// Specular map with glossiness in its alpha channel, scaling shininess
+    /// per texel up to the given value.
+    Glossy(String, f32),
+}
+
+pub enum SpecularTextureResult {
+    Ideal(f32),
+    FullDiffuse,
+    Provided(MaterialTexture, f32),
+    Glossy(MaterialTexture, f32),
+}
+
+impl SpecularTextureResult {
+    // Specular color used without a map (w = shininess) and whether alpha of the map is glossiness.
+    fn constants(&self) -> (FVec4, u32) {
+        match self {
+            Self::Ideal(shininess) => (FVec4::new(1.0, 1.0, 1.0, *shininess), 0),
+            Self::FullDiffuse => (FVec4::zeros(), 0),
+            Self::Provided(_, shininess) => (FVec4::new(0.0, 0.0, 0.0, *shininess), 0),
+            Self::Glossy(_, shininess) => (FVec4::new(0.0, 0.0, 0.0, *shininess), 1),
+        }
+    }
+
+    pub fn texture(&self) -> Option<&MaterialTexture> {
+        match self {
+            Self::Provided(texture, _) | Self::Glossy(texture, _) => Some(texture),
+            Self::Ideal(_) | Self::FullDiffuse => None,
+        }
+    }
+
+    pub fn shininess(&self) -> Option<f32> {
+        match self {
+            Self::Ideal(shininess) | Self::Provided(_, shininess) | Self::Glossy(_, shininess) => {
+                Some(*shininess)
             }
+            Self::FullDiffuse => None,
         }
     }
 
-    pub fn bind_group(&self) -> &wgpu::BindGroup {
+    pub fn shininess_mut(&mut self) -> Option<&mut f32> {
         match self {
-            Self::PhongSolid { bind_group, .. } => bind_group,
-            Self::PhongTextured { bind_group, .. } => bind_group,
-            Self::PhongTexturedNormal { bind_group, .. } => bind_group,
+            Self::Ideal(shininess) | Self::Provided(_, shininess) | Self::Glossy(_, shininess) => {
+                Some(shininess)
+            }
+            Self::FullDiffuse => None,
         }
     }
 }
 
+struct TextureArray {
+    texture: wgpu::Texture,
+    len: u32,
+}
+
+/// All materials of a scene behind a single bind group. Material parameters live in
+/// a storage buffer indexed per instance, textures are layers of shared texture arrays,
+/// so draws don't need to switch bind groups between materials.
 pub struct MaterialAtlas {
     materials: Vec<Material>,
-    gpu_materials: Vec<GpuMaterial>,
-    pub textures: MaterialAtlasTextureDefaults,
-    pub layouts: MaterialAtlasLayouts,
+    // Created on first use, one for every vertex layout.
+    fallback_materials: HashMap<MeshVertexArrayType, MaterialId>,
+    // Indexed by color space, then size class. Created once the first texture of the kind is added.
+    texture_arrays: [[Option<TextureArray>; TEXTURE_CLASSES.len()]; COLOR_SPACES.len()],
+    materials_buffer: wgpu::Buffer,
+    // Textures loaded from files, so materials using the same file share its layer.
+    texture_cache: HashMap<(PathBuf, ColorSpace), MaterialTexture>,
+    // Shared with atlases created by `sharing_layouts`, see `DecodedImages`.
+    decoded_images: DecodedImages,
+    // Bound in place of texture arrays which weren't created yet.
+    placeholder: wgpu::Texture,
+    sampler: wgpu::Sampler,
+    bind_group: wgpu::BindGroup,
+    layout: Arc<wgpu::BindGroupLayout>,
+    // Images decoded on background threads, waiting to be written to their layers.
+    // Behind a mutex, so the atlas can be shared with the rest of the render context.
+    loaded_sender: Sender<LoadedTexture>,
+    loaded_textures: Mutex<Receiver<LoadedTexture>>,
 }
 
-pub struct MaterialAtlasLayouts {
-    pub phong_solid: wgpu::BindGroupLayout,
-    pub phong_textured: wgpu::BindGroupLayout,
-    pub phong_textured_normal: wgpu::BindGroupLayout,
-}
+type LoadedTexture = (MaterialTexture, Result<image::RgbaImage>);
 
-pub struct MaterialAtlasTextureDefaults {
-    pub white: wgpu::Texture,
-    pub black: wgpu::Texture,
-    sampler: wgpu::Sampler,
-}
+/// Images decoded from files, scaled to their size class. They outlive atlases, so scenes
+/// rebuilt into new ones don't decode their textures again and show no placeholders.
+/// Keyed by modification time along with the path, files changed since are read anew.
+type DecodedImages = Arc<Mutex<HashMap<(PathBuf, Option<SystemTime>), image::RgbaImage>>>;
 
-impl MaterialAtlasTextureDefaults {
+impl MaterialAtlas {
     pub fn new(gpu: &Gpu) -> Self {
-        let white = gpu.device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("MaterialAtlas::WhiteTexture"),
-            size: wgpu::Extent3d {
-                width: 1,
-                height: 1,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
+        Self::with_layout(
+            gpu,
+            Arc::new(Self::create_layout(gpu)),
+            DecodedImages::default(),
+        )
+    }
 
-        let black = gpu.device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("MaterialAtlas::BlackTexture"),
+    /// Empty atlas whose materials can be bound by pipelines created for `other`.
+    /// Images `other` decoded are reused by textures loaded from the same files.
+    pub fn sharing_layouts(gpu: &Gpu, other: &MaterialAtlas) -> Self {
+        Self::with_layout(gpu, other.layout.clone(), other.decoded_images.clone())
+    }
+
+    fn with_layout(
+        gpu: &Gpu,
+        layout: Arc<wgpu::BindGroupLayout>,
+        decoded_images: DecodedImages,
+    ) -> Self {
+        let materials_buffer = Self::create_materials_buffer(gpu, INITIAL_MATERIAL_CAPACITY);
+
+        let placeholder = gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("MaterialAtlas::PlaceholderTexture"),
             size: wgpu::Extent3d {
                 width: 1,
                 height: 1,
@@ -283,8 +448,8 @@ impl MaterialAtlasTextureDefaults {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[],
         });
 
@@ -299,188 +464,148 @@ impl MaterialAtlasTextureDefaults {
             ..Default::default()
         });
 
-        gpu.queue.write_texture(
-            black.as_image_copy(),
-            &[0, 0, 0, 255],
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4),
-                rows_per_image: Some(1),
-            },
-            wgpu::Extent3d {
-                width: 1,
-                height: 1,
-                depth_or_array_layers: 1,
-            },
+        let texture_arrays = Default::default();
+        let bind_group = Self::create_bind_group(
+            gpu,
+            &layout,
+            &materials_buffer,
+            &sampler,
+            &texture_arrays,
+            &placeholder,
         );
 
-        gpu.queue.write_texture(
-            white.as_image_copy(),
-            &[255, 255, 255, 255],
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4),
-                rows_per_image: Some(1),
-            },
-            wgpu::Extent3d {
-                width: 1,
-                height: 1,
-                depth_or_array_layers: 1,
-            },
-        );
+        let (loaded_sender, loaded_textures) = channel();
 
         Self {
-            white,
-            black,
+            materials: Vec::new(),
+            fallback_materials: HashMap::new(),
+            texture_arrays,
+            texture_cache: HashMap::new(),
+            decoded_images,
+            materials_buffer,
+            placeholder,
             sampler,
+            bind_group,
+            layout,
+            loaded_sender,
+            loaded_textures: Mutex::new(loaded_textures),
         }
     }
-}
 
-impl MaterialAtlasLayouts {
-    fn new(gpu: &Gpu) -> Self {
-        let phong_solid = gpu
-            .device
+    fn create_layout(gpu: &Gpu) -> wgpu::BindGroupLayout {
+        let texture_arrays = (0..(COLOR_SPACES.len() * TEXTURE_CLASSES.len()) as u32).map(|i| {
+            wgpu::BindGroupLayoutEntry {
+                binding: TEXTURE_ARRAYS_BINDING + i,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                    multisampled: false,
+                },
+                count: None,
+            }
+        });
+
+        let entries: Vec<wgpu::BindGroupLayoutEntry> = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: NonZeroU64::new(GpuMaterialRepr::SHADER_SIZE.into()),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ]
+        .into_iter()
+        .chain(texture_arrays)
+        .collect();
+
+        gpu.device
             .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("MaterialAtlas::PhongSolidLayout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-            });
-
-        let phong_textured =
-            gpu.device
-                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    label: Some("MaterialAtlas::PhongTexturedLayout"),
-                    entries: &[
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 0,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Texture {
-                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                                multisampled: false,
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 1,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Texture {
-                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                                multisampled: false,
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 2,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 3,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Buffer {
-                                ty: wgpu::BufferBindingType::Uniform,
-                                has_dynamic_offset: false,
-                                min_binding_size: None,
-                            },
-                            count: None,
-                        },
-                    ],
-                });
+                label: Some("MaterialAtlas::Layout"),
+                entries: &entries,
+            })
+    }
 
-        let phong_textured_normal =
-            gpu.device
-                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    label: Some("MaterialAtlas::PhongTexturedNormalLayout"),
-                    entries: &[
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 0,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Texture {
-                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                                multisampled: false,
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 1,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Texture {
-                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                                multisampled: false,
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 2,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Texture {
-                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                                multisampled: false,
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 3,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 4,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Buffer {
-                                ty: wgpu::BufferBindingType::Uniform,
-                                has_dynamic_offset: false,
-                                min_binding_size: None,
-                            },
-                            count: None,
-                        },
-                    ],
-                });
+    fn create_materials_buffer(gpu: &Gpu, capacity: usize) -> wgpu::Buffer {
+        let repr_size: u64 = GpuMaterialRepr::SHADER_SIZE.into();
 
-        Self {
-            phong_solid,
-            phong_textured,
-            phong_textured_normal,
-        }
+        gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("MaterialAtlas::Materials"),
+            size: capacity as u64 * repr_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
     }
-}
 
-pub enum SpecularTexture {
-    Ideal(f32),
-    FullDiffuse,
-    Provided(String, f32),
-}
+    fn create_bind_group(
+        gpu: &Gpu,
+        layout: &wgpu::BindGroupLayout,
+        materials_buffer: &wgpu::Buffer,
+        sampler: &wgpu::Sampler,
+        texture_arrays: &[[Option<TextureArray>; TEXTURE_CLASSES.len()]; COLOR_SPACES.len()],
+        placeholder: &wgpu::Texture,
+    ) -> wgpu::BindGroup {
+        let array_view = wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        };
 
-pub enum SpecularTextureResult {
-    Ideal(f32),
-    FullDiffuse,
-    Provided(wgpu::Texture, f32),
-}
+        let views: Vec<wgpu::TextureView> = texture_arrays
+            .iter()
+            .flatten()
+            .map(|array| match array {
+                Some(array) => array.texture.create_view(&array_view),
+                None => placeholder.create_view(&array_view),
+            })
+            .collect();
 
-impl MaterialAtlas {
-    pub fn new(gpu: &Gpu) -> Self {
-        Self {
-            layouts: MaterialAtlasLayouts::new(gpu),
-            textures: MaterialAtlasTextureDefaults::new(gpu),
-            materials: Vec::new(),
-            gpu_materials: Vec::new(),
-        }
+        let entries: Vec<wgpu::BindGroupEntry> = [
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: materials_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ]
+        .into_iter()
+        .chain(
+            views
+                .iter()
+                .zip(TEXTURE_ARRAYS_BINDING..)
+                .map(|(view, binding)| wgpu::BindGroupEntry {
+                    binding,
+                    resource: wgpu::BindingResource::TextureView(view),
+                }),
+        )
+        .collect();
+
+        gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("MaterialAtlas::BindGroup"),
+            layout,
+            entries: &entries,
+        })
+    }
+
+    fn rebuild_bind_group(&mut self, gpu: &Gpu) {
+        self.bind_group = Self::create_bind_group(
+            gpu,
+            &self.layout,
+            &self.materials_buffer,
+            &self.sampler,
+            &self.texture_arrays,
+            &self.placeholder,
+        );
     }
 
     pub fn add_phong_solid(
@@ -494,6 +619,8 @@ impl MaterialAtlas {
             ambient,
             diffuse,
             specular,
+            reflectivity: 0.0,
+            emissive: FVec4::zeros(),
         };
 
         self.add_material(gpu, material)
@@ -505,17 +632,18 @@ impl MaterialAtlas {
         diffuse: impl AsRef<Path>,
         specular: SpecularTexture,
     ) -> Result<MaterialId> {
-        let diffuse = Self::gpu_texture(gpu, Self::load_texture(diffuse)?, false);
-        let specular = match specular {
-            SpecularTexture::FullDiffuse => SpecularTextureResult::FullDiffuse,
-            SpecularTexture::Ideal(f32) => SpecularTextureResult::Ideal(f32),
-            SpecularTexture::Provided(path, shininess) => {
-                let texture = Self::gpu_texture(gpu, Self::load_texture(path)?, false);
-                SpecularTextureResult::Provided(texture, shininess)
-            }
-        };
+        let diffuse = self.add_diffuse_texture(gpu, diffuse)?;
+        let specular = self.add_specular_texture(gpu, specular)?;
 
-        self.add_material(gpu, Material::PhongTextured { diffuse, specular })
+        self.add_material(
+            gpu,
+            Material::PhongTextured {
+                diffuse,
+                specular,
+                reflectivity: 0.0,
+                emissive: FVec4::zeros(),
+            },
+        )
     }
 
     pub fn add_phong_textured_normal(
@@ -525,16 +653,14 @@ impl MaterialAtlas {
         specular: SpecularTexture,
         normal: impl AsRef<Path>,
     ) -> Result<MaterialId> {
-        let diffuse = Self::gpu_texture(gpu, Self::load_texture(diffuse)?, false);
-        let normal = Self::gpu_texture(gpu, Self::load_texture(normal)?, true);
-        let specular = match specular {
-            SpecularTexture::FullDiffuse => SpecularTextureResult::FullDiffuse,
-            SpecularTexture::Ideal(f32) => SpecularTextureResult::Ideal(f32),
-            SpecularTexture::Provided(path, shininess) => {
-                let texture = Self::gpu_texture(gpu, Self::load_texture(path)?, false);
-                SpecularTextureResult::Provided(texture, shininess)
-            }
-        };
+        let diffuse = self.add_diffuse_texture(gpu, diffuse)?;
+        let normal = self.load_texture_async(
+            gpu,
+            normal,
+            ColorSpace::Linear,
+            Self::solid_image(FLAT_NORMAL),
+        )?;
+        let specular = self.add_specular_texture(gpu, specular)?;
 
         self.add_material(
             gpu,
@@ -542,10 +668,175 @@ impl MaterialAtlas {
                 diffuse,
                 specular,
                 normal,
+                reflectivity: 0.0,
+                emissive: FVec4::zeros(),
             },
         )
     }
 
+    /// Splat material blending diffuse maps at `layers` by weights in channels of `splat`,
+    /// e.g. one painted for a terrain. Layers repeat `tiling` times over the UV range.
+    pub fn add_phong_splat(
+        &mut self,
+        gpu: &Gpu,
+        splat: image::RgbaImage,
+        layers: &[PathBuf; SPLAT_LAYERS],
+        tiling: f32,
+        specular: SpecularTexture,
+    ) -> Result<MaterialId> {
+        let splat = self.add_texture(gpu, splat, ColorSpace::Linear)?;
+        let layers = layers
+            .iter()
+            .map(|layer| self.add_diffuse_texture(gpu, layer))
+            .collect::<Result<Vec<_>>>()?
+            .try_into()
+            .expect("a texture is added for every layer");
+        let specular = self.add_specular_texture(gpu, specular)?;
+
+        self.add_material(
+            gpu,
+            Material::PhongSplat {
+                splat,
+                layers,
+                tiling,
+                specular,
+                reflectivity: 0.0,
+                emissive: FVec4::zeros(),
+            },
+        )
+    }
+
+    /// Textured material with a diffuse texture rendered to at runtime, like the screen
+    /// of a security camera. It gets a layer of the smallest size `size` fits in, which
+    /// stays black until something is copied to it with `copy_to_texture`.
+    pub fn add_phong_render_target(
+        &mut self,
+        gpu: &Gpu,
+        size: u32,
+    ) -> Result<(MaterialId, MaterialTexture)> {
+        let diffuse =
+            self.reserve_texture(gpu, Self::texture_class(size, size), ColorSpace::Srgb)?;
+        self.write_texture(gpu, diffuse, Self::solid_image(RENDER_TARGET_CLEAR));
+
+        let material_id = self.add_material(
+            gpu,
+            Material::PhongTextured {
+                diffuse,
+                specular: SpecularTextureResult::FullDiffuse,
+                reflectivity: 0.0,
+                emissive: FVec4::zeros(),
+            },
+        )?;
+
+        Ok((material_id, diffuse))
+    }
+
+    fn add_specular_texture(
+        &mut self,
+        gpu: &Gpu,
+        specular: SpecularTexture,
+    ) -> Result<SpecularTextureResult> {
+        Ok(match specular {
+            SpecularTexture::FullDiffuse => SpecularTextureResult::FullDiffuse,
+            SpecularTexture::Ideal(f32) => SpecularTextureResult::Ideal(f32),
+            SpecularTexture::Provided(path, shininess) => {
+                let texture = self.add_specular_map(gpu, path)?;
+                SpecularTextureResult::Provided(texture, shininess)
+            }
+            SpecularTexture::Glossy(path, shininess) => {
+                let texture = self.add_specular_map(gpu, path)?;
+                SpecularTextureResult::Glossy(texture, shininess)
+            }
+        })
+    }
+
+    fn add_diffuse_texture(
+        &mut self,
+        gpu: &Gpu,
+        path: impl AsRef<Path>,
+    ) -> Result<MaterialTexture> {
+        let placeholder = Self::checker_image(FALLBACK_COLOR, CHECKER_COLOR);
+        self.load_texture_async(gpu, path, ColorSpace::Srgb, placeholder)
+    }
+
+    fn add_specular_map(&mut self, gpu: &Gpu, path: impl AsRef<Path>) -> Result<MaterialTexture> {
+        let placeholder = Self::solid_image(NO_SPECULAR);
+        self.load_texture_async(gpu, path, ColorSpace::Linear, placeholder)
+    }
+
+    /// Flat magenta material which can be drawn with meshes of `vertex_array_type`,
+    /// assigned to meshes which ended up without a material.
+    pub fn fallback_material(
+        &mut self,
+        gpu: &Gpu,
+        vertex_array_type: MeshVertexArrayType,
+    ) -> Result<MaterialId> {
+        if let Some(material_id) = self.fallback_materials.get(&vertex_array_type) {
+            return Ok(*material_id);
+        }
+
+        let [r, g, b, _] = FALLBACK_COLOR.map(|c| c as f32 / 255.0);
+        let material = match vertex_array_type {
+            MeshVertexArrayType::PN => Material::PhongSolid {
+                ambient: FVec4::new(r, g, b, 0.0),
+                diffuse: FVec4::new(r, g, b, 0.0),
+                specular: FVec4::zeros(),
+                reflectivity: 0.0,
+                emissive: FVec4::zeros(),
+            },
+            MeshVertexArrayType::PNUV => Material::PhongTextured {
+                diffuse: self.add_texture(
+                    gpu,
+                    Self::solid_image(FALLBACK_COLOR),
+                    ColorSpace::Srgb,
+                )?,
+                specular: SpecularTextureResult::FullDiffuse,
+                reflectivity: 0.0,
+                emissive: FVec4::zeros(),
+            },
+            MeshVertexArrayType::PNTBUV => Material::PhongTexturedNormal {
+                diffuse: self.add_texture(
+                    gpu,
+                    Self::solid_image(FALLBACK_COLOR),
+                    ColorSpace::Srgb,
+                )?,
+                normal: self.add_texture(
+                    gpu,
+                    Self::solid_image([128, 128, 255, 255]),
+                    ColorSpace::Linear,
+                )?,
+                specular: SpecularTextureResult::FullDiffuse,
+                reflectivity: 0.0,
+                emissive: FVec4::zeros(),
+            },
+        };
+
+        let material_id = self.add_material(gpu, material)?;
+        self.fallback_materials
+            .insert(vertex_array_type, material_id);
+
+        Ok(material_id)
+    }
+
+    pub fn is_fallback(&self, material_id: MaterialId) -> bool {
+        self.fallback_materials
+            .values()
+            .any(|id| *id == material_id)
+    }
+
+    fn solid_image(color: [u8; 4]) -> image::RgbaImage {
+        image::RgbaImage::from_pixel(1, 1, image::Rgba(color))
+    }
+
+    // Cells are a few texels wide, so the pattern survives scaling up to the texture size.
+    fn checker_image(first: [u8; 4], second: [u8; 4]) -> image::RgbaImage {
+        let size = CHECKER_CELLS * CHECKER_CELL_SIZE;
+        image::RgbaImage::from_fn(size, size, |x, y| {
+            let cell = (x / CHECKER_CELL_SIZE + y / CHECKER_CELL_SIZE) % 2;
+            image::Rgba(if cell == 0 { first } else { second })
+        })
+    }
+
     pub fn is_normal_mapped(&self, material_id: MaterialId) -> bool {
         matches!(
             self.materials[material_id.0],
@@ -553,73 +844,388 @@ impl MaterialAtlas {
         )
     }
 
+    /// Memory taken by texture arrays, including layers reserved for textures added later.
+    pub fn texture_size(&self) -> u64 {
+        self.texture_arrays
+            .iter()
+            .flatten()
+            .flatten()
+            .map(|array| {
+                let wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers,
+                } = array.texture.size();
+                let texel_size = array.texture.format().block_copy_size(None).unwrap_or(4);
+
+                width as u64 * height as u64 * depth_or_array_layers as u64 * texel_size as u64
+            })
+            .sum()
+    }
+
+    pub fn material_ids(&self) -> impl Iterator<Item = MaterialId> {
+        (0..self.materials.len()).map(MaterialId)
+    }
+
+    pub fn material(&self, material_id: MaterialId) -> &Material {
+        &self.materials[material_id.0]
+    }
+
+    /// Overwrites the texture with an image loaded from `path`, resized to the size of the
+    /// texture so it keeps its place. Materials using it pick up the change right away,
+    /// including ones sharing it because they were created from the same file.
+    pub fn replace_texture(
+        &mut self,
+        gpu: &Gpu,
+        texture: MaterialTexture,
+        path: impl AsRef<Path>,
+    ) -> Result<()> {
+        self.write_texture(gpu, texture, Self::load_texture(path)?);
+        // The layer doesn't hold the file it was loaded from anymore.
+        self.texture_cache.retain(|_, cached| *cached != texture);
+
+        Ok(())
+    }
+
     fn load_texture(path: impl AsRef<Path>) -> Result<image::RgbaImage> {
         let img = image::open(path)?;
 
         Ok(img.to_rgba8())
     }
 
-    fn gpu_texture(gpu: &Gpu, image: image::RgbaImage, is_normal: bool) -> wgpu::Texture {
-        use image::EncodableLayout;
+    /// Reserves a layer for the image at `path`, filled with `placeholder` until the image
+    /// is decoded on a background thread. Only the header is read up front, to find out
+    /// the size class. Decoded images are written by `upload_loaded_textures`.
+    ///
+    /// Files which were loaded before in the same color space give back the same texture.
+    /// Files decoded before by this atlas or ones sharing its layouts are written right away.
+    fn load_texture_async(
+        &mut self,
+        gpu: &Gpu,
+        path: impl AsRef<Path>,
+        color_space: ColorSpace,
+        placeholder: image::RgbaImage,
+    ) -> Result<MaterialTexture> {
+        let path = canonical_path(path.as_ref());
+        let cache_key = (path.clone(), color_space);
+        if let Some(texture) = self.texture_cache.get(&cache_key) {
+            return Ok(*texture);
+        }
+
+        let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        let decoded_key = (path.clone(), modified);
+        let decoded = self
+            .decoded_images
+            .lock()
+            .unwrap()
+            .get(&decoded_key)
+            .cloned();
+        if let Some(image) = decoded {
+            let texture = self.add_texture(gpu, image, color_space)?;
+            self.texture_cache.insert(cache_key, texture);
+            return Ok(texture);
+        }
+
+        let (width, height) = image::image_dimensions(&path)
+            .with_context(|| format!("failed to read texture {}", path.display()))?;
+
+        let texture = self.reserve_texture(gpu, Self::texture_class(width, height), color_space)?;
+        self.write_texture(gpu, texture, placeholder);
+        self.texture_cache.insert(cache_key, texture);
+
+        let sender = self.loaded_sender.clone();
+        let decoded_images = self.decoded_images.clone();
+        tokio::task::spawn_blocking(move || {
+            let image = Self::load_texture(&path)
+                .map(|image| Self::fit_to_class(image, texture.class))
+                .with_context(|| format!("failed to load texture {}", path.display()));
+            if let Ok(image) = &image {
+                decoded_images
+                    .lock()
+                    .unwrap()
+                    .insert(decoded_key, image.clone());
+            }
+
+            // Nobody waits for the image anymore if the atlas was dropped in the meantime,
+            // e.g. replaced by a reloaded scene.
+            let _ = sender.send((texture, image));
+        });
+
+        Ok(texture)
+    }
+
+    /// Writes textures decoded since the last call over their placeholders.
+    /// Textures which failed to load keep the placeholder and are returned as errors.
+    pub fn upload_loaded_textures(&self, gpu: &Gpu) -> Vec<anyhow::Error> {
+        self.loaded_textures
+            .lock()
+            .unwrap()
+            .try_iter()
+            .filter_map(|(texture, image)| match image {
+                Ok(image) => {
+                    self.write_texture(gpu, texture, image);
+                    None
+                }
+                Err(e) => Some(e),
+            })
+            .collect()
+    }
+
+    // Smallest size class the image fits in without scaling down, or the largest one.
+    fn texture_class(width: u32, height: u32) -> usize {
+        let size = width.max(height);
+
+        TEXTURE_CLASSES
+            .iter()
+            .position(|class_size| size <= *class_size)
+            .unwrap_or(TEXTURE_CLASSES.len() - 1)
+    }
+
+    fn add_texture(
+        &mut self,
+        gpu: &Gpu,
+        image: image::RgbaImage,
+        color_space: ColorSpace,
+    ) -> Result<MaterialTexture> {
         let (width, height) = image.dimensions();
+        let texture = self.reserve_texture(gpu, Self::texture_class(width, height), color_space)?;
 
-        let tex_size = wgpu::Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
-        };
+        self.write_texture(gpu, texture, image);
+        Ok(texture)
+    }
 
+    fn reserve_texture(
+        &mut self,
+        gpu: &Gpu,
+        class: usize,
+        color_space: ColorSpace,
+    ) -> Result<MaterialTexture> {
+        let layer = self.allocate_layer(gpu, class, color_space)?;
+
+        Ok(MaterialTexture {
+            class,
+            color_space,
+            layer,
+        })
+    }
+
+    // Arrays grow by doubling their layers. Existing layers are copied over on the GPU
+    // and the bind group is rebuilt to point at the new array.
+    fn allocate_layer(&mut self, gpu: &Gpu, class: usize, color_space: ColorSpace) -> Result<u32> {
+        let array = &mut self.texture_arrays[color_space as usize][class];
+        if let Some(array) = array {
+            if array.len < array.texture.depth_or_array_layers() {
+                array.len += 1;
+                return Ok(array.len - 1);
+            }
+        }
+
+        let capacity = array
+            .as_ref()
+            .map_or(0, |array| array.texture.depth_or_array_layers());
+        let max_layers = gpu.device.limits().max_texture_array_layers;
+        if capacity >= max_layers {
+            anyhow::bail!(
+                "no space left for {:?} textures of size {}",
+                color_space,
+                TEXTURE_CLASSES[class]
+            );
+        }
+
+        let size = TEXTURE_CLASSES[class];
         let texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: tex_size,
+            label: Some("MaterialAtlas::TextureArray"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: (capacity * 2).clamp(INITIAL_TEXTURE_LAYERS, max_layers),
+            },
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: if is_normal {
-                wgpu::TextureFormat::Rgba8Unorm
-            } else {
-                wgpu::TextureFormat::Rgba8UnormSrgb
-            },
-            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+            format: color_space.texture_format(),
+            usage: wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[],
         });
 
-        gpu.queue.write_texture(
-            texture.as_image_copy(),
+        if let Some(old) = array.take() {
+            // Layers still waiting to be uploaded have to land before they're copied.
+            gpu.flush_uploads();
+            let mut encoder = gpu
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("MaterialAtlas::GrowTextureArray"),
+                });
+            encoder.copy_texture_to_texture(
+                old.texture.as_image_copy(),
+                texture.as_image_copy(),
+                old.texture.size(),
+            );
+            gpu.queue.submit(Some(encoder.finish()));
+        }
+
+        *array = Some(TextureArray {
+            texture,
+            len: capacity + 1,
+        });
+        self.rebuild_bind_group(gpu);
+
+        Ok(capacity)
+    }
+
+    fn fit_to_class(image: image::RgbaImage, class: usize) -> image::RgbaImage {
+        let size = TEXTURE_CLASSES[class];
+        if image.dimensions() == (size, size) {
+            image
+        } else {
+            image::imageops::resize(&image, size, size, image::imageops::FilterType::Triangle)
+        }
+    }
+
+    fn write_texture(&self, gpu: &Gpu, texture: MaterialTexture, image: image::RgbaImage) {
+        use image::EncodableLayout;
+
+        let size = TEXTURE_CLASSES[texture.class];
+        let image = Self::fit_to_class(image, texture.class);
+
+        let array = self.texture_arrays[texture.color_space as usize][texture.class]
+            .as_ref()
+            .expect("texture arrays are created before their layers are handed out");
+
+        gpu.upload_texture(
+            wgpu::ImageCopyTexture {
+                texture: &array.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: texture.layer,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
             image.as_bytes(),
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * width),
-                rows_per_image: Some(height),
+            4 * size,
+            wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
             },
-            tex_size,
         );
+    }
+
+    /// Records a copy of `source` over `texture`. The source has to be as big as
+    /// the texture, with `MaterialTexture::RENDER_TARGET_FORMAT` for render targets.
+    pub fn copy_to_texture(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::Texture,
+        texture: MaterialTexture,
+    ) {
+        let array = self.texture_arrays[texture.color_space as usize][texture.class]
+            .as_ref()
+            .expect("texture arrays are created before their layers are handed out");
 
-        texture
+        encoder.copy_texture_to_texture(
+            source.as_image_copy(),
+            wgpu::ImageCopyTexture {
+                texture: &array.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: texture.layer,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::Extent3d {
+                width: texture.size(),
+                height: texture.size(),
+                depth_or_array_layers: 1,
+            },
+        );
     }
 
     fn add_material(&mut self, gpu: &Gpu, material: Material) -> Result<MaterialId> {
-        let material_idx = self.materials.len();
+        let material_id = MaterialId(self.materials.len());
         self.materials.push(material);
-        self.gpu_materials.push(GpuMaterial::new(
-            gpu,
-            &self.materials[material_idx],
-            &self.textures,
-            &self.layouts,
-        )?);
 
-        Ok(MaterialId(material_idx))
+        let repr_size: u64 = GpuMaterialRepr::SHADER_SIZE.into();
+        let capacity = (self.materials_buffer.size() / repr_size) as usize;
+        if self.materials.len() <= capacity {
+            self.write_material(gpu, material_id)?;
+            return Ok(material_id);
+        }
+
+        // Buffer is full, so all materials get uploaded to a new one twice the size.
+        self.materials_buffer = Self::create_materials_buffer(gpu, capacity * 2);
+        for material_id in self.material_ids() {
+            self.write_material(gpu, material_id)?;
+        }
+        self.rebuild_bind_group(gpu);
+
+        Ok(material_id)
+    }
+
+    fn write_material(&self, gpu: &Gpu, material_id: MaterialId) -> Result<()> {
+        let contents = GpuMaterialRepr::contents(&self.materials[material_id.0])?;
+        let offset = material_id.0 as u64 * u64::from(GpuMaterialRepr::SHADER_SIZE);
+        gpu.queue
+            .write_buffer(&self.materials_buffer, offset, contents.as_slice());
+
+        Ok(())
+    }
+
+    pub fn set_reflectivity(
+        &mut self,
+        gpu: &Gpu,
+        material_id: MaterialId,
+        reflectivity: f32,
+    ) -> Result<()> {
+        self.update_material(gpu, material_id, |material| {
+            *material.reflectivity_mut() = reflectivity;
+        })
+    }
+
+    pub fn set_emissive(
+        &mut self,
+        gpu: &Gpu,
+        material_id: MaterialId,
+        emissive: FVec4,
+    ) -> Result<()> {
+        self.update_material(gpu, material_id, |material| {
+            *material.emissive_mut() = emissive;
+        })
+    }
+
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
     }
 
-    pub fn bind_group(&self, material_id: MaterialId) -> &wgpu::BindGroup {
-        self.gpu_materials[material_id.0].bind_group()
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
     }
 
-    // pub fn update_material<F>(&mut self, material_id: MaterialId, updater: F)
-    // where
-    //     F: Fn(&mut Material),
-    // {
-    //     let material = &mut self.materials[material_id.0];
-    //     updater(material);
-    // }
+    /// Materials are re-uploaded in place to the atlas buffer.
+    /// Pipelines are picked per mesh layout, so the updater must not change the variant.
+    pub fn update_material<F>(
+        &mut self,
+        gpu: &Gpu,
+        material_id: MaterialId,
+        updater: F,
+    ) -> Result<()>
+    where
+        F: FnOnce(&mut Material),
+    {
+        let material = &mut self.materials[material_id.0];
+        let kind = std::mem::discriminant(material);
+        updater(material);
+
+        if std::mem::discriminant(material) != kind {
+            anyhow::bail!("material {material_id:?} cannot change its kind at runtime");
+        }
+
+        self.write_material(gpu, material_id)
+    }
 }
@@ -0,0 +1,204 @@
+use std::sync::RwLock;
+
+use anyhow::Result;
+use egui::ComboBox;
+use nalgebra as na;
+
+use gpu_basics::{
+    gpu::Gpu,
+    material::{Material, MaterialAtlas, MaterialId, SpecularTextureResult, MAX_SHININESS},
+};
+
+#[derive(Default)]
+pub struct MaterialEditor {
+    selected: Option<MaterialId>,
+    texture_path: String,
+    error: Option<String>,
+}
+
+fn specular_mut(material: &mut Material) -> Option<&mut SpecularTextureResult> {
+    match material {
+        Material::PhongTextured { specular, .. }
+        | Material::PhongTexturedNormal { specular, .. }
+        | Material::PhongSplat { specular, .. } => Some(specular),
+        Material::PhongSolid { .. } => None,
+    }
+}
+
+fn shininess(material: &Material) -> Option<f32> {
+    match material {
+        Material::PhongTextured { specular, .. }
+        | Material::PhongTexturedNormal { specular, .. }
+        | Material::PhongSplat { specular, .. } => match specular {
+            SpecularTextureResult::Ideal(shininess)
+            | SpecularTextureResult::Provided(_, shininess)
+            | SpecularTextureResult::Glossy(_, shininess) => Some(*shininess),
+            SpecularTextureResult::FullDiffuse => None,
+        },
+        Material::PhongSolid { .. } => None,
+    }
+}
+
+pub fn color_row(ui: &mut egui::Ui, label: &str, color: &mut na::Vector4<f32>) -> bool {
+    let mut rgb = [color.x, color.y, color.z];
+
+    let changed = ui
+        .horizontal(|ui| {
+            let response = ui.color_edit_button_rgb(&mut rgb);
+            ui.label(label);
+            response.changed()
+        })
+        .inner;
+
+    color.x = rgb[0];
+    color.y = rgb[1];
+    color.z = rgb[2];
+
+    changed
+}
+
+impl MaterialEditor {
+    pub fn render(&mut self, ctx: &egui::Context, gpu: &Gpu, atlas: &RwLock<MaterialAtlas>) {
+        egui::Window::new("Materials")
+            .default_open(false)
+            .show(ctx, |ui| {
+                let mut atlas = atlas.write().unwrap();
+
+                ComboBox::from_label("Material")
+                    .selected_text(
+                        self.selected
+                            .map(|id| format!("{:?}", id))
+                            .unwrap_or_else(|| "None".to_owned()),
+                    )
+                    .show_ui(ui, |ui| {
+                        for id in atlas.material_ids() {
+                            ui.selectable_value(&mut self.selected, Some(id), format!("{:?}", id));
+                        }
+                    });
+
+                if let Some(id) = self.selected {
+                    if let Some(result) = self.material_ui(ui, gpu, &mut atlas, id) {
+                        self.error = result.err().map(|e| e.to_string());
+                    }
+                }
+
+                if let Some(error) = &self.error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+            });
+    }
+
+    // Returns the outcome of an edit, if one was made this frame.
+    fn material_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        gpu: &Gpu,
+        atlas: &mut MaterialAtlas,
+        id: MaterialId,
+    ) -> Option<Result<()>> {
+        let mut edit = None;
+
+        let mut reflectivity = atlas.material(id).reflectivity();
+        ui.label("Reflectivity");
+        if ui
+            .add(
+                egui::DragValue::new(&mut reflectivity)
+                    .speed(0.01)
+                    .clamp_range(0.0..=1.0),
+            )
+            .changed()
+        {
+            edit = Some(atlas.set_reflectivity(gpu, id, reflectivity));
+        }
+
+        let mut emissive = atlas.material(id).emissive();
+        if color_row(ui, "Emissive", &mut emissive) {
+            edit = Some(atlas.set_emissive(gpu, id, emissive));
+        }
+
+        if let Material::PhongSolid {
+            mut ambient,
+            mut diffuse,
+            mut specular,
+            reflectivity,
+            emissive,
+        } = *atlas.material(id)
+        {
+            let mut changed = color_row(ui, "Ambient", &mut ambient);
+            changed |= color_row(ui, "Diffuse", &mut diffuse);
+            changed |= color_row(ui, "Specular", &mut specular);
+
+            ui.label("Shininess");
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut specular.w)
+                        .speed(0.5)
+                        .clamp_range(0.0..=MAX_SHININESS),
+                )
+                .changed();
+
+            if changed {
+                edit = Some(atlas.update_material(gpu, id, |material| {
+                    *material = Material::PhongSolid {
+                        ambient,
+                        diffuse,
+                        specular,
+                        reflectivity,
+                        emissive,
+                    };
+                }));
+            }
+
+            return edit;
+        }
+
+        if let Some(mut value) = shininess(atlas.material(id)) {
+            ui.label("Shininess");
+            let changed = ui
+                .add(
+                    egui::DragValue::new(&mut value)
+                        .speed(0.5)
+                        .clamp_range(0.0..=MAX_SHININESS),
+                )
+                .changed();
+
+            if changed {
+                edit = Some(atlas.update_material(gpu, id, |material| {
+                    if let Some(shininess) = specular_mut(material).and_then(|s| s.shininess_mut())
+                    {
+                        *shininess = value;
+                    }
+                }));
+            }
+        }
+
+        ui.label("Texture Path");
+        ui.text_edit_singleline(&mut self.texture_path);
+
+        let mut replaced_normal = None;
+        ui.horizontal(|ui| {
+            if ui.button("Set Diffuse").clicked() {
+                replaced_normal = Some(false);
+            }
+            if atlas.is_normal_mapped(id) && ui.button("Set Normal").clicked() {
+                replaced_normal = Some(true);
+            }
+        });
+
+        if let Some(is_normal) = replaced_normal {
+            let texture = match atlas.material(id) {
+                Material::PhongTexturedNormal { normal, .. } if is_normal => Some(*normal),
+                Material::PhongTextured { diffuse, .. }
+                | Material::PhongTexturedNormal { diffuse, .. } => Some(*diffuse),
+                // Splat materials have a diffuse map for every layer.
+                Material::PhongSplat { .. } | Material::PhongSolid { .. } => None,
+            };
+
+            if let Some(texture) = texture {
+                edit = Some(atlas.replace_texture(gpu, texture, &self.texture_path));
+            }
+        }
+
+        edit
+    }
+}
@@ -1,15 +1,26 @@
+use std::{collections::HashMap, sync::OnceLock};
+
 use anyhow::Result;
 use nalgebra as na;
+use rayon::prelude::*;
+
+use crate::{
+    bounds::Bounds,
+    shader_compiler::CompilationUnit,
+    shader_permutation::ShaderPermutation,
+    vertex_layout::{VertexAttributes, VertexLayout},
+};
 type FVec3 = na::Vector3<f32>;
 type FVec2 = na::Vector2<f32>;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 struct MeshVertexAttributes {
     texture: Option<TextureUV>,
 }
 
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug)]
+// Ordered like draw calls of the scene are.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, Debug)]
 pub enum MeshVertexArrayType {
     PN,
     PNUV,
@@ -17,15 +28,59 @@ pub enum MeshVertexArrayType {
 }
 
 impl MeshVertexArrayType {
+    pub const ALL: [Self; 3] = [Self::PN, Self::PNUV, Self::PNTBUV];
+
     pub fn stride(&self) -> usize {
+        self.layout().stride()
+    }
+
+    pub fn vertex_attributes(&self) -> VertexAttributes {
+        match self {
+            Self::PN => VertexAttributes::NORMAL,
+            Self::PNUV => VertexAttributes::NORMAL | VertexAttributes::UV,
+            Self::PNTBUV => {
+                VertexAttributes::NORMAL | VertexAttributes::TANGENT_SPACE | VertexAttributes::UV
+            }
+        }
+    }
+
+    pub fn layout(&self) -> &'static VertexLayout {
+        static LAYOUTS: OnceLock<[VertexLayout; 3]> = OnceLock::new();
+
+        let layouts =
+            LAYOUTS.get_or_init(|| Self::ALL.map(|ty| VertexLayout::new(ty.vertex_attributes())));
+
+        match self {
+            Self::PN => &layouts[0],
+            Self::PNUV => &layouts[1],
+            Self::PNTBUV => &layouts[2],
+        }
+    }
+
+    // Shader definition used by variants written for this vertex type only.
+    pub fn shader_def(&self) -> &'static str {
         match self {
-            Self::PN => PN_STRIDE,
-            Self::PNUV => PNUV_STRIDE,
-            Self::PNTBUV => PNTBUV_STRIDE,
+            Self::PN => "VERTEX_PN",
+            Self::PNUV => "VERTEX_PNUV",
+            Self::PNTBUV => "VERTEX_PNTBUV",
         }
     }
 }
 
+// Vertex inputs are generated from the layout, the definition of the type
+// selects code written for it only.
+impl ShaderPermutation for MeshVertexArrayType {
+    fn all() -> Vec<Self> {
+        Self::ALL.to_vec()
+    }
+
+    fn apply(&self, unit: CompilationUnit) -> CompilationUnit {
+        self.layout()
+            .with_shader_defs(unit)
+            .with_def(self.shader_def())
+    }
+}
+
 impl MeshVertexAttributes {
     fn has_texture_uvs(&self) -> bool {
         self.texture.is_some()
@@ -40,6 +95,7 @@ impl MeshVertexAttributes {
     }
 }
 
+#[derive(Clone)]
 struct TextureUV {
     uv: Vec<FVec2>,
 }
@@ -50,50 +106,26 @@ impl TextureUV {
     }
 }
 
+#[derive(Clone)]
 pub struct Mesh {
     geometry: Geometry,
     vertex_attributes: MeshVertexAttributes,
+    name: Option<String>,
+    // Model space, `None` for meshes without vertices.
+    bounds: Option<Bounds>,
 }
 
 impl Mesh {
-    const PN_VERTEX_LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
-        step_mode: wgpu::VertexStepMode::Vertex,
-        array_stride: PN_STRIDE as wgpu::BufferAddress,
-        attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3],
-    };
-
-    const PNUV_VERTEX_LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
-        step_mode: wgpu::VertexStepMode::Vertex,
-        array_stride: PNUV_STRIDE as wgpu::BufferAddress,
-        attributes: &wgpu::vertex_attr_array![
-            0 => Float32x3,
-            1 => Float32x3,
-            2 => Float32x2,
-        ],
-    };
-
-    const PNTBUV_VERTEX_LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
-        step_mode: wgpu::VertexStepMode::Vertex,
-        array_stride: PNTBUV_STRIDE as wgpu::BufferAddress,
-        attributes: &wgpu::vertex_attr_array![
-            0 => Float32x3,
-            1 => Float32x3,
-            2 => Float32x3,
-            3 => Float32x3,
-            4 => Float32x2,
-        ],
-    };
-
     pub fn pntbuv_vertex_layout() -> wgpu::VertexBufferLayout<'static> {
-        Self::PNTBUV_VERTEX_LAYOUT
+        MeshVertexArrayType::PNTBUV.layout().buffer_layout()
     }
 
     pub fn pnuv_vertex_layout() -> wgpu::VertexBufferLayout<'static> {
-        Self::PNUV_VERTEX_LAYOUT
+        MeshVertexArrayType::PNUV.layout().buffer_layout()
     }
 
     pub fn pn_vertex_layout() -> wgpu::VertexBufferLayout<'static> {
-        Self::PN_VERTEX_LAYOUT
+        MeshVertexArrayType::PN.layout().buffer_layout()
     }
 
     pub fn vertex_array_type(&self) -> MeshVertexArrayType {
@@ -112,11 +144,8 @@ impl Mesh {
         }
     }
 
-    pub fn is_indexed(&self) -> bool {
-        match &self.geometry {
-            Geometry::Indexed { .. } => true,
-            Geometry::NonIndexed { .. } => false,
-        }
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
     }
 
     pub fn copy_to_index_buffer(&self, index_buffer: &mut Vec<u32>) {
@@ -129,10 +158,46 @@ impl Mesh {
         index_buffer.extend_from_slice(faces);
     }
 
+    pub fn positions(&self) -> &[FVec3] {
+        match &self.geometry {
+            Geometry::Indexed { mesh, .. } => mesh,
+            Geometry::NonIndexed { mesh, .. } => mesh,
+        }
+    }
+
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.bounds
+    }
+
     pub fn num_vertices(&self) -> usize {
         self.geometry.vertex_count()
     }
 
+    /// Vertices whose tangent or bitangent is not a unit vector off the normal,
+    /// e.g. because of triangles with collapsed UVs.
+    pub fn degenerate_tangents(&self) -> usize {
+        let normals = match &self.geometry {
+            Geometry::Indexed { normals, .. } => normals,
+            Geometry::NonIndexed { normals, .. } => normals,
+        };
+
+        let NormalInformation::TangentSpace(normals, t_vectors, bt_vectors) = normals else {
+            return 0;
+        };
+
+        let is_degenerate = |v: &FVec3, n: &FVec3| {
+            !v.iter().all(|c| c.is_finite())
+                || (v.norm() - 1.0).abs() > 0.01
+                || v.dot(n).abs() > 0.99
+        };
+
+        normals
+            .iter()
+            .zip(t_vectors.iter().zip(bt_vectors))
+            .filter(|(n, (t, bt))| is_degenerate(t, n) || is_degenerate(bt, n))
+            .count()
+    }
+
     pub fn num_indices(&self) -> Option<usize> {
         match &self.geometry {
             Geometry::Indexed { faces, .. } => Some(faces.len()),
@@ -140,16 +205,81 @@ impl Mesh {
         }
     }
 
-    pub fn copy_to_mesh_bank(&self, vertex_array: &mut Vec<u8>) {
-        let vertex_count = self.geometry.vertex_count();
-        let mesh_size = match self.vertex_array_type() {
-            MeshVertexArrayType::PNUV => vertex_count * PNUV_STRIDE,
-            MeshVertexArrayType::PN => vertex_count * PN_STRIDE,
-            MeshVertexArrayType::PNTBUV => vertex_count * PNTBUV_STRIDE,
+    // Raw attribute data, used to find meshes with identical contents.
+    fn contents(&self) -> [&[u8]; 6] {
+        let (mesh, normals, faces) = match &self.geometry {
+            Geometry::Indexed {
+                mesh,
+                normals,
+                faces,
+            } => (mesh, normals, faces.as_slice()),
+            Geometry::NonIndexed { mesh, normals } => (mesh, normals, [].as_slice()),
         };
 
-        vertex_array.reserve(mesh_size);
+        let (normals, t_vectors, bt_vectors) = match normals {
+            NormalInformation::ModelNormals(normals) => {
+                (normals.as_slice(), [].as_slice(), [].as_slice())
+            }
+            NormalInformation::TangentSpace(normals, t_vectors, bt_vectors) => (
+                normals.as_slice(),
+                t_vectors.as_slice(),
+                bt_vectors.as_slice(),
+            ),
+        };
+
+        let texture_uvs = self
+            .vertex_attributes
+            .texture
+            .as_ref()
+            .map(|texture| texture.uv.as_slice())
+            .unwrap_or_default();
+
+        [
+            bytemuck::cast_slice(mesh),
+            bytemuck::cast_slice(normals),
+            bytemuck::cast_slice(t_vectors),
+            bytemuck::cast_slice(bt_vectors),
+            bytemuck::cast_slice(texture_uvs),
+            bytemuck::cast_slice(faces),
+        ]
+    }
 
+    pub fn content_hash(&self) -> u64 {
+        use std::hash::{Hash, Hasher};
+
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.contents().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn same_contents(&self, other: &Mesh) -> bool {
+        self.contents() == other.contents()
+    }
+
+    pub fn indices(&self) -> Option<&[u32]> {
+        match &self.geometry {
+            Geometry::Indexed { faces, .. } => Some(faces),
+            Geometry::NonIndexed { .. } => None,
+        }
+    }
+
+    pub fn vertex_stride(&self) -> usize {
+        self.vertex_array_type().stride()
+    }
+
+    pub fn copy_to_mesh_bank(&self, vertex_array: &mut Vec<u8>) {
+        let bank_offset = vertex_array.len();
+        vertex_array.resize(
+            bank_offset + self.geometry.vertex_count() * self.vertex_stride(),
+            0,
+        );
+
+        self.write_vertices(0, &mut vertex_array[bank_offset..]);
+    }
+
+    // Interleaves vertices starting from `first_vertex` until `target` is filled.
+    // This allows uploading a mesh in parts without keeping a copy of the whole vertex data.
+    pub fn write_vertices(&self, first_vertex: usize, target: &mut [u8]) {
         let mesh = match &self.geometry {
             Geometry::Indexed { mesh, .. } => mesh,
             Geometry::NonIndexed { mesh, .. } => mesh,
@@ -160,42 +290,47 @@ impl Mesh {
             Geometry::NonIndexed { normals, .. } => normals,
         };
 
-        for i in 0..vertex_count {
-            let vertex = mesh[i];
-            match normals {
-                NormalInformation::ModelNormals(normals) => {
-                    let normal = normals[i];
-
-                    vertex_array.extend_from_slice(bytemuck::cast_slice(&[vertex]));
-                    vertex_array.extend_from_slice(bytemuck::cast_slice(&[normal]));
-                }
-                NormalInformation::TangentSpace(normals, t_vectors, bt_vectors) => {
-                    let normal = normals[i];
-                    let t_vector = t_vectors[i];
-                    let bt_vector = bt_vectors[i];
-
-                    vertex_array.extend_from_slice(bytemuck::cast_slice(&[vertex]));
-                    vertex_array.extend_from_slice(bytemuck::cast_slice(&[normal]));
-                    vertex_array.extend_from_slice(bytemuck::cast_slice(&[t_vector]));
-                    vertex_array.extend_from_slice(bytemuck::cast_slice(&[bt_vector]));
+        let texture = self.vertex_attributes.texture.as_ref();
+
+        // Each vertex owns a stride-sized part of `target`, so they are interleaved in parallel.
+        target
+            .par_chunks_exact_mut(self.vertex_stride())
+            .enumerate()
+            .for_each(|(n, target)| {
+                let i = first_vertex + n;
+                let mut offset = 0;
+                let mut write = |data: &[u8]| {
+                    target[offset..offset + data.len()].copy_from_slice(data);
+                    offset += data.len();
+                };
+
+                write(bytemuck::cast_slice(&[mesh[i]]));
+                match normals {
+                    NormalInformation::ModelNormals(normals) => {
+                        write(bytemuck::cast_slice(&[normals[i]]));
+                    }
+                    NormalInformation::TangentSpace(normals, t_vectors, bt_vectors) => {
+                        write(bytemuck::cast_slice(&[normals[i]]));
+                        write(bytemuck::cast_slice(&[t_vectors[i]]));
+                        write(bytemuck::cast_slice(&[bt_vectors[i]]));
+                    }
                 }
-            }
 
-            if let Some(texture) = &self.vertex_attributes.texture {
-                vertex_array.extend_from_slice(bytemuck::cast_slice(&[texture.uv[i]]));
-            }
-        }
+                if let Some(texture) = texture {
+                    write(bytemuck::cast_slice(&[texture.uv[i]]));
+                }
+            });
     }
 }
 
+#[derive(Default)]
 pub struct MeshBuilder {
     geometry: Option<Geometry>,
     vertex_attributes: MeshVertexAttributes,
+    name: Option<String>,
+    weld_epsilon: Option<f32>,
 }
 
-pub const PNTBUV_STRIDE: usize = std::mem::size_of::<FVec3>() * 4 + std::mem::size_of::<FVec2>();
-pub const PNUV_STRIDE: usize = std::mem::size_of::<FVec3>() * 2 + std::mem::size_of::<FVec2>();
-pub const PN_STRIDE: usize = std::mem::size_of::<FVec3>() * 2;
 pub const PNUV_SLOTS: u32 = 3;
 pub const PN_SLOTS: u32 = 2;
 pub const PNTBUV_SLOTS: u32 = 5;
@@ -205,6 +340,8 @@ impl MeshBuilder {
         Self {
             geometry: None,
             vertex_attributes: MeshVertexAttributes::default(),
+            name: None,
+            weld_epsilon: None,
         }
     }
 
@@ -218,23 +355,54 @@ impl MeshBuilder {
         self
     }
 
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Merges vertices whose position, normal and texture UV snap to the same point of a grid
+    /// with `epsilon` sized cells. Vertices closer than `epsilon` on both sides of a cell
+    /// boundary stay apart. Non-indexed geometry becomes indexed.
+    pub fn with_welding(mut self, epsilon: f32) -> Self {
+        self.weld_epsilon = Some(epsilon);
+        self
+    }
+
     pub fn build(self) -> Result<Mesh> {
+        let mut geometry = self
+            .geometry
+            .ok_or_else(|| anyhow::anyhow!("Mesh geometry not provided"))?;
+        let mut vertex_attributes = self.vertex_attributes;
+
+        if let Some(epsilon) = self.weld_epsilon {
+            let texture_uvs = vertex_attributes.texture.take().map(|texture| texture.uv);
+            let (welded, texture_uvs) = weld_vertices(geometry, texture_uvs, epsilon);
+            geometry = welded;
+            vertex_attributes.texture = texture_uvs.map(TextureUV::new);
+        }
+
+        let positions = match &geometry {
+            Geometry::Indexed { mesh, .. } => mesh,
+            Geometry::NonIndexed { mesh, .. } => mesh,
+        };
+        let bounds = Bounds::from_points(positions);
+
         Ok(Mesh {
-            geometry: self
-                .geometry
-                .ok_or_else(|| anyhow::anyhow!("Mesh geometry not provided"))?,
-            vertex_attributes: self.vertex_attributes,
+            geometry,
+            vertex_attributes,
+            name: self.name,
+            bounds,
         })
     }
 }
 
-#[derive(Debug)]
-enum NormalInformation {
+#[derive(Debug, Clone)]
+pub enum NormalInformation {
     ModelNormals(Vec<FVec3>),
     TangentSpace(Vec<FVec3>, Vec<FVec3>, Vec<FVec3>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Geometry {
     Indexed {
         mesh: Vec<FVec3>,
@@ -330,6 +498,113 @@ impl Geometry {
     }
 }
 
+// Attributes are snapped to a grid of `epsilon` sized cells and vertices in the same cell
+// are merged, so close vertices on both sides of a cell boundary stay apart.
+// Tangent space vectors of merged vertices are averaged.
+fn weld_vertices(
+    geometry: Geometry,
+    texture_uvs: Option<Vec<FVec2>>,
+    epsilon: f32,
+) -> (Geometry, Option<Vec<FVec2>>) {
+    let (mesh, normals, faces) = match geometry {
+        Geometry::Indexed {
+            mesh,
+            normals,
+            faces,
+        } => (mesh, normals, faces),
+        Geometry::NonIndexed { mesh, normals } => {
+            let faces = (0..mesh.len() as u32).collect();
+            (mesh, normals, faces)
+        }
+    };
+
+    let (normals, tangent_space) = match normals {
+        NormalInformation::ModelNormals(normals) => (normals, None),
+        NormalInformation::TangentSpace(normals, t_vectors, bt_vectors) => {
+            (normals, Some((t_vectors, bt_vectors)))
+        }
+    };
+
+    let epsilon = epsilon.max(f32::EPSILON);
+    let snap = |value: f32| (value / epsilon).round() as i64;
+
+    let mut vertex_map: HashMap<[i64; 8], u32> = HashMap::new();
+    let mut remap = Vec::with_capacity(mesh.len());
+    // Original vertex each welded one was created from.
+    let mut first_vertex = vec![];
+    let mut welded_mesh = vec![];
+    let mut welded_normals = vec![];
+    let mut welded_uvs = texture_uvs.as_ref().map(|_| vec![]);
+    let mut welded_tangents = tangent_space.as_ref().map(|_| (vec![], vec![]));
+
+    for (i, (position, normal)) in mesh.iter().zip(&normals).enumerate() {
+        let uv = texture_uvs.as_ref().map_or(FVec2::zeros(), |uvs| uvs[i]);
+        let key = [
+            snap(position.x),
+            snap(position.y),
+            snap(position.z),
+            snap(normal.x),
+            snap(normal.y),
+            snap(normal.z),
+            snap(uv.x),
+            snap(uv.y),
+        ];
+
+        let vertex = *vertex_map.entry(key).or_insert_with(|| {
+            first_vertex.push(i);
+            welded_mesh.push(*position);
+            welded_normals.push(*normal);
+            if let Some(welded_uvs) = welded_uvs.as_mut() {
+                welded_uvs.push(uv);
+            }
+            if let Some((t_vectors, bt_vectors)) = welded_tangents.as_mut() {
+                t_vectors.push(FVec3::zeros());
+                bt_vectors.push(FVec3::zeros());
+            }
+
+            (welded_mesh.len() - 1) as u32
+        });
+
+        if let Some(((t_vectors, bt_vectors), (welded_t, welded_bt))) =
+            tangent_space.as_ref().zip(welded_tangents.as_mut())
+        {
+            welded_t[vertex as usize] += t_vectors[i];
+            welded_bt[vertex as usize] += bt_vectors[i];
+        }
+
+        remap.push(vertex);
+    }
+
+    let normals = match welded_tangents {
+        Some((mut t_vectors, mut bt_vectors)) => {
+            // Vectors of merged vertices can cancel out, the first one is kept then.
+            let (original_t, original_bt) = tangent_space.unwrap_or_default();
+            for (vertex, &i) in first_vertex.iter().enumerate() {
+                if t_vectors[vertex].try_normalize_mut(f32::EPSILON).is_none() {
+                    t_vectors[vertex] = original_t[i];
+                }
+                if bt_vectors[vertex].try_normalize_mut(f32::EPSILON).is_none() {
+                    bt_vectors[vertex] = original_bt[i];
+                }
+            }
+
+            NormalInformation::TangentSpace(welded_normals, t_vectors, bt_vectors)
+        }
+        None => NormalInformation::ModelNormals(welded_normals),
+    };
+
+    let faces = faces.iter().map(|&idx| remap[idx as usize]).collect();
+
+    (
+        Geometry::Indexed {
+            mesh: welded_mesh,
+            normals,
+            faces,
+        },
+        welded_uvs,
+    )
+}
+
 fn flat_normals(mesh: &[FVec3], mut idx_iter: impl Iterator<Item = usize>) -> Vec<FVec3> {
     let mut normals = vec![FVec3::zeros(); mesh.len()];
 
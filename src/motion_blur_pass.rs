@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use encase::{ShaderSize, UniformBuffer};
+use nalgebra as na;
+
+use crate::{
+    compute::{dispatch, Kernel},
+    deferred::G_VELOCITY,
+    gpu::Gpu,
+    render_context::RenderContext,
+    render_graph::{Binding, GraphResources, PassIo},
+    render_pass::{FrameContext, RenderPass},
+    settings::{MotionBlurSettings, PipelineType},
+};
+
+const KERNEL: Kernel = Kernel::new([16, 16, 1]);
+
+/// Blurs the HDR scene color along motion vectors written by `GeometryPass`.
+pub struct MotionBlurPass<'window> {
+    render_ctx: Arc<RenderContext<'window>>,
+    bgl: Arc<wgpu::BindGroupLayout>,
+    pipeline: wgpu::ComputePipeline,
+    params_buf: wgpu::Buffer,
+    output_tex: wgpu::Texture,
+}
+
+impl<'window> MotionBlurPass<'window> {
+    pub const MAX_SAMPLES: u32 = 32;
+
+    pub fn new(render_ctx: Arc<RenderContext<'window>>) -> Result<Self> {
+        let RenderContext {
+            gpu,
+            bind_group_layouts,
+            shader_compiler,
+            ..
+        } = render_ctx.as_ref();
+
+        let params_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("MotionBlurPass::Params"),
+            size: na::Vector2::<f32>::SHADER_SIZE.into(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        let bgl = bind_group_layouts.get(
+            gpu,
+            "MotionBlurPass::BindGroupLayout",
+            &[
+                texture(0),
+                texture(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba16Float,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        );
+
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("MotionBlurPass::PipelineLayout"),
+                bind_group_layouts: &[&bgl],
+                push_constant_ranges: &[],
+            });
+
+        let module = KERNEL
+            .with_defs(shader_compiler.compilation_unit("./shaders/screenspace/motion_blur.wgsl")?)
+            .compile(&[])?;
+        let shader = gpu.shader_from_module(module);
+
+        let pipeline = gpu
+            .device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("MotionBlurPass::Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "motion_blur",
+            });
+
+        Ok(Self {
+            output_tex: Self::create_output(gpu),
+            render_ctx,
+            bgl,
+            pipeline,
+            params_buf,
+        })
+    }
+
+    fn create_output(gpu: &Gpu) -> wgpu::Texture {
+        gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("MotionBlurPass::Output"),
+            size: gpu.viewport_size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        })
+    }
+
+    pub fn output_tex_view(&self) -> wgpu::TextureView {
+        self.output_tex.create_view(&Default::default())
+    }
+
+    pub fn render(
+        &self,
+        scene_color: &wgpu::TextureView,
+        settings: &MotionBlurSettings,
+        resources: &GraphResources,
+    ) -> Result<()> {
+        let RenderContext { gpu, profiler, .. } = self.render_ctx.as_ref();
+
+        // Fields of `Params` in the shader.
+        let params = na::Vector2::new(settings.intensity, settings.samples as f32);
+        let size: u64 = na::Vector2::<f32>::SHADER_SIZE.into();
+        let mut contents = UniformBuffer::new(Vec::with_capacity(size as usize));
+        contents.write(&params)?;
+        gpu.queue
+            .write_buffer(&self.params_buf, 0, contents.into_inner().as_slice());
+
+        let output = self.output_tex_view();
+        let bg = resources.bind_group(
+            gpu,
+            "MotionBlurPass::BindGroup",
+            &self.bgl,
+            &[
+                Binding::Resource(G_VELOCITY),
+                Binding::View(scene_color),
+                Binding::View(&output),
+                Binding::Buffer(&self.params_buf),
+            ],
+        )?;
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("MotionBlurPass::CommandEncoder"),
+            });
+
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("MotionBlurPass::ComputePass"),
+                timestamp_writes: profiler.compute_pass_writes("Motion Blur"),
+            });
+
+            cpass.set_pipeline(&self.pipeline);
+            cpass.set_bind_group(0, &bg, &[]);
+
+            let viewport = gpu.viewport_size();
+            dispatch(&mut cpass, &KERNEL, [viewport.width, viewport.height, 1]);
+        }
+
+        gpu.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
+
+impl RenderPass for MotionBlurPass<'_> {
+    fn name(&self) -> &'static str {
+        "MotionBlurPass"
+    }
+
+    // Motion vectors only come out of the geometry pass.
+    fn enabled(&self, ctx: &FrameContext) -> bool {
+        let settings = ctx.settings;
+        settings.motion_blur.enabled
+            && settings.pipeline_type == PipelineType::Deferred
+            && !settings.deferred_debug_shown()
+    }
+
+    fn io(&self) -> PassIo {
+        PassIo::default().read(G_VELOCITY)
+    }
+
+    fn resize(&mut self, _size: (u32, u32)) -> Result<()> {
+        self.output_tex = Self::create_output(&self.render_ctx.gpu);
+
+        Ok(())
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        let (scene_color, _) = ctx
+            .scene_color
+            .as_ref()
+            .filter(|(_, hdr)| *hdr)
+            .context("motion blur is applied to a HDR scene color")?;
+        Self::render(self, scene_color, &ctx.settings.motion_blur, &ctx.resources)?;
+        ctx.scene_color = Some((self.output_tex_view(), true));
+
+        Ok(())
+    }
+}
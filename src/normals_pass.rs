@@ -0,0 +1,331 @@
+use std::{num::NonZeroU64, sync::Arc};
+
+use anyhow::Result;
+
+use crate::{
+    bind_group_layouts::BindGroupLayouts,
+    gpu::Gpu,
+    mesh::MeshVertexArrayType,
+    render_context::RenderContext,
+    render_pass::{FrameContext, RenderPass},
+    scene::{GpuScene, Instance, MODEL_TRANSFORM_SIZE},
+    shader_permutation::Variants,
+};
+
+const MIN_UNIFORM_BUFFER_OFFSET_ALIGNMENT: u64 = 256;
+// `DrawConstants` of the shader: the model matrix, the upper 3x3 of its inverse
+// transpose with columns padded to vec4, then the line length padded to 16 bytes.
+const DRAW_CONSTANTS_LEN: usize = 16 + 12 + 4;
+const DRAW_CONSTANTS_RANGE: wgpu::PushConstantRange = wgpu::PushConstantRange {
+    stages: wgpu::ShaderStages::VERTEX,
+    range: 0..(DRAW_CONSTANTS_LEN * std::mem::size_of::<f32>()) as u32,
+};
+
+// Lines drawn for every mesh vertex: its normal, tangent and bitangent.
+fn lines_per_vertex(vertex_array_type: MeshVertexArrayType) -> u32 {
+    match vertex_array_type {
+        MeshVertexArrayType::PNTBUV => 3,
+        MeshVertexArrayType::PN | MeshVertexArrayType::PNUV => 1,
+    }
+}
+
+fn draw_constants(instance: &Instance, line_length: f32) -> [f32; DRAW_CONSTANTS_LEN] {
+    let mut constants = [0.0; DRAW_CONSTANTS_LEN];
+    constants[..16].copy_from_slice(instance.model().as_slice());
+
+    let model_invt = instance.model_invt();
+    for i in 0..3 {
+        let column = model_invt.column(i);
+        constants[16 + i * 4..16 + i * 4 + 3].copy_from_slice(&[column.x, column.y, column.z]);
+    }
+
+    constants[28] = line_length;
+    constants
+}
+
+struct ObjectTransforms {
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    capacity: usize,
+}
+
+/// Per-object data written to buffers, when the device has no push constants for it.
+struct DrawUniforms {
+    bgl: Arc<wgpu::BindGroupLayout>,
+    length_buffer: wgpu::Buffer,
+    transforms: Option<ObjectTransforms>,
+}
+
+impl DrawUniforms {
+    fn new(gpu: &Gpu, bind_group_layouts: &BindGroupLayouts) -> Self {
+        let bgl = bind_group_layouts.get(
+            gpu,
+            "NormalsPass::BindGroupLayout",
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: NonZeroU64::new(MODEL_TRANSFORM_SIZE as u64),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        );
+
+        let length_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("NormalsPass::LineLength"),
+            size: std::mem::size_of::<f32>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            bgl,
+            length_buffer,
+            transforms: None,
+        }
+    }
+
+    // Transforms of every object get their own aligned slot, picked with a dynamic offset.
+    // The buffer only grows, scenes rebuilt with fewer objects keep using it.
+    fn write(&mut self, gpu: &Gpu, gpu_scene: &GpuScene, line_length: f32) -> &wgpu::BindGroup {
+        gpu.queue
+            .write_buffer(&self.length_buffer, 0, bytemuck::bytes_of(&line_length));
+
+        let num_objects = gpu_scene.object_ids().count();
+        let transforms = match self.transforms.take() {
+            Some(transforms) if transforms.capacity >= num_objects => transforms,
+            _ => {
+                let buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("NormalsPass::ObjectTransforms"),
+                    size: num_objects.max(1) as u64 * MIN_UNIFORM_BUFFER_OFFSET_ALIGNMENT,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+
+                let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("NormalsPass::BindGroup"),
+                    layout: &self.bgl,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                                buffer: &buffer,
+                                offset: 0,
+                                size: NonZeroU64::new(MODEL_TRANSFORM_SIZE as u64),
+                            }),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: self.length_buffer.as_entire_binding(),
+                        },
+                    ],
+                });
+
+                ObjectTransforms {
+                    buffer,
+                    bind_group,
+                    capacity: num_objects.max(1),
+                }
+            }
+        };
+
+        let mut contents = Vec::with_capacity(num_objects * MODEL_TRANSFORM_SIZE);
+        for (slot, id) in gpu_scene.object_ids().enumerate() {
+            contents.resize(slot * MIN_UNIFORM_BUFFER_OFFSET_ALIGNMENT as usize, 0);
+            gpu_scene.object_instance(id).copy_to(&mut contents);
+        }
+        gpu.queue.write_buffer(&transforms.buffer, 0, &contents);
+
+        &self.transforms.insert(transforms).bind_group
+    }
+}
+
+/// Per-vertex normals, tangents (red) and bitangents (green) of visible objects,
+/// drawn as lines over the frame to check tangent space generated for meshes.
+///
+/// Vertex buffers of the scene are read per instance, so every mesh vertex is
+/// expanded into its lines without copying the vertex data. Transforms of objects
+/// are pushed with every draw where push constants are supported.
+pub struct NormalsPass<'window> {
+    render_ctx: Arc<RenderContext<'window>>,
+    pipelines: Variants<MeshVertexArrayType, wgpu::RenderPipeline>,
+    // `None` when per-object data is pushed instead.
+    uniforms: Option<DrawUniforms>,
+}
+
+impl<'window> NormalsPass<'window> {
+    pub fn new(render_ctx: Arc<RenderContext<'window>>) -> Result<Self> {
+        let RenderContext {
+            gpu,
+            shader_compiler,
+            scene_uniform,
+            bind_group_layouts,
+            ..
+        } = render_ctx.as_ref();
+
+        let module = gpu
+            .with_push_constant_defs(shader_compiler.compilation_unit("./shaders/normals.wgsl")?);
+        let shaders = gpu.shader_variants::<MeshVertexArrayType>(&module)?;
+
+        let uniforms = (!gpu.push_constants()).then(|| DrawUniforms::new(gpu, bind_group_layouts));
+
+        let pipeline_layout = match &uniforms {
+            Some(uniforms) => gpu
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("NormalsPass::PipelineLayout"),
+                    bind_group_layouts: &[scene_uniform.layout(), &uniforms.bgl],
+                    push_constant_ranges: &[],
+                }),
+            None => gpu
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("NormalsPass::PipelineLayout"),
+                    bind_group_layouts: &[scene_uniform.layout()],
+                    push_constant_ranges: &[DRAW_CONSTANTS_RANGE],
+                }),
+        };
+
+        let pipelines = shaders.map(|vertex_array_type, shader| {
+            let mut vertices = vertex_array_type.layout().buffer_layout();
+            vertices.step_mode = wgpu::VertexStepMode::Instance;
+
+            gpu.device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("NormalsPass::Pipeline"),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &[vertices],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fs_main",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: gpu.swapchain_format(),
+                            blend: Some(wgpu::BlendState::REPLACE),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    depth_stencil: None,
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::LineList,
+                        ..Default::default()
+                    },
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                })
+        });
+
+        Ok(Self {
+            render_ctx,
+            pipelines,
+            uniforms,
+        })
+    }
+
+    pub fn render(&mut self, frame: &wgpu::SurfaceTexture, line_length: f32) {
+        let render_ctx = self.render_ctx.clone();
+        let RenderContext {
+            gpu,
+            scene_uniform,
+            gpu_scene,
+            ..
+        } = render_ctx.as_ref();
+        let gpu_scene = gpu_scene.read().unwrap();
+
+        let transforms = self
+            .uniforms
+            .as_mut()
+            .map(|uniforms| uniforms.write(gpu, &gpu_scene, line_length));
+
+        let view = frame.texture.create_view(&Default::default());
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("NormalsPass::CommandEncoder"),
+            });
+
+        {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("NormalsPass::RenderPass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            rpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
+
+            for (slot, id) in gpu_scene.object_ids().enumerate() {
+                if !gpu_scene.is_visible(id) {
+                    continue;
+                }
+
+                match transforms {
+                    Some(bind_group) => {
+                        let offset = slot as u32 * MIN_UNIFORM_BUFFER_OFFSET_ALIGNMENT as u32;
+                        rpass.set_bind_group(1, bind_group, &[offset]);
+                    }
+                    None => rpass.set_push_constants(
+                        wgpu::ShaderStages::VERTEX,
+                        0,
+                        bytemuck::cast_slice(&draw_constants(
+                            gpu_scene.object_instance(id),
+                            line_length,
+                        )),
+                    ),
+                }
+
+                for (vertex_array_type, vertices) in gpu_scene.mesh_vertex_ranges(id) {
+                    rpass.set_pipeline(&self.pipelines[vertex_array_type]);
+
+                    rpass.set_vertex_buffer(
+                        0,
+                        gpu_scene.vertex_buffer_by_type(vertex_array_type).slice(..),
+                    );
+                    rpass.draw(0..lines_per_vertex(vertex_array_type) * 2, vertices);
+                }
+            }
+        }
+
+        gpu.queue.submit(Some(encoder.finish()));
+    }
+}
+
+impl RenderPass for NormalsPass<'_> {
+    fn name(&self) -> &'static str {
+        "NormalsPass"
+    }
+
+    fn enabled(&self, ctx: &FrameContext) -> bool {
+        ctx.settings.normals_dbg.enabled
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        Self::render(self, &ctx.frame, ctx.settings.normals_dbg.length);
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::VecDeque;
+
+// Frames shown in the graph, the oldest scroll out on the left.
+const HISTORY: usize = 240;
+const GRAPH_SIZE: egui::Vec2 = egui::vec2(280.0, 80.0);
+// Frame times of 60 and 30 FPS, marked across the graph.
+const BUDGETS_MS: [f32; 2] = [1000.0 / 60.0, 1000.0 / 30.0];
+
+/// Scrolling plot of recent frame times, with time every pass took on the GPU
+/// drawn as bars under it.
+pub struct PerfGraph {
+    frame_times: VecDeque<f32>,
+}
+
+impl Default for PerfGraph {
+    fn default() -> Self {
+        Self {
+            frame_times: VecDeque::with_capacity(HISTORY),
+        }
+    }
+}
+
+impl PerfGraph {
+    pub fn push(&mut self, frame_ms: f32) {
+        if self.frame_times.len() == HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_ms);
+    }
+
+    /// `gpu_timings` are milliseconds passes took, empty without timestamp queries.
+    pub fn ui(&self, ui: &mut egui::Ui, gpu_timings: &[(&'static str, f32)]) {
+        self.frame_graph(ui);
+
+        if gpu_timings.is_empty() {
+            ui.label("No GPU timings, timestamp queries are not supported.");
+            return;
+        }
+
+        // Bars are relative to the whole GPU frame, so they add up to a full one.
+        let total: f32 = gpu_timings.iter().map(|(_, ms)| ms).sum();
+        egui::Grid::new("perf_graph_passes").show(ui, |ui| {
+            for (name, ms) in gpu_timings {
+                ui.label(*name);
+                ui.add(
+                    egui::ProgressBar::new(ms / total.max(f32::EPSILON))
+                        .desired_width(GRAPH_SIZE.x * 0.6)
+                        .text(format!("{:.3} ms", ms)),
+                );
+                ui.end_row();
+            }
+
+            ui.strong("GPU Total");
+            ui.strong(format!("{:.3} ms", total));
+            ui.end_row();
+        });
+    }
+
+    fn frame_graph(&self, ui: &mut egui::Ui) {
+        let (response, painter) = ui.allocate_painter(GRAPH_SIZE, egui::Sense::hover());
+        let rect = response.rect;
+        let visuals = ui.visuals();
+        painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+
+        // Scaled so the slowest recent frame and the 30 FPS mark still fit.
+        let slowest = self.frame_times.iter().copied().fold(0.0, f32::max);
+        let scale_ms = slowest.max(BUDGETS_MS[1]) * 1.1;
+        let y = |ms: f32| rect.bottom() - ms / scale_ms * rect.height();
+
+        for budget in BUDGETS_MS {
+            let stroke = egui::Stroke::new(1.0, visuals.weak_text_color());
+            painter.hline(rect.x_range(), y(budget), stroke);
+            painter.text(
+                egui::pos2(rect.left() + 2.0, y(budget)),
+                egui::Align2::LEFT_BOTTOM,
+                format!("{:.1} ms", budget),
+                egui::FontId::monospace(9.0),
+                visuals.weak_text_color(),
+            );
+        }
+
+        // Newest frame sits at the right edge.
+        let step = rect.width() / (HISTORY - 1) as f32;
+        let offset = HISTORY - self.frame_times.len();
+        let points = self
+            .frame_times
+            .iter()
+            .enumerate()
+            .map(|(i, ms)| egui::pos2(rect.left() + (offset + i) as f32 * step, y(*ms)))
+            .collect::<Vec<_>>();
+        painter.add(egui::Shape::line(
+            points,
+            egui::Stroke::new(1.5, egui::Color32::LIGHT_GREEN),
+        ));
+
+        if let Some(last) = self.frame_times.back() {
+            painter.text(
+                rect.right_top() + egui::vec2(-2.0, 2.0),
+                egui::Align2::RIGHT_TOP,
+                format!("{:.2} ms", last),
+                egui::FontId::monospace(10.0),
+                visuals.text_color(),
+            );
+        }
+    }
+}
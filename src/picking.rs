@@ -0,0 +1,154 @@
+use nalgebra as na;
+
+use crate::{
+    bounds::Aabb,
+    mesh::Mesh,
+    scene::{GpuScene, SceneObjectId},
+};
+
+type FVec3 = na::Vector3<f32>;
+
+pub struct Ray {
+    pub origin: na::Point3<f32>,
+    // Unit length in world space. Rays transformed to model space aren't renormalized,
+    // so distances along them are still world space distances.
+    pub direction: FVec3,
+}
+
+impl Ray {
+    /// Ray going from the near plane through the pixel at `cursor`.
+    pub fn from_cursor(
+        cursor: (f32, f32),
+        viewport: (f32, f32),
+        view: &na::Matrix4<f32>,
+        projection: &na::Matrix4<f32>,
+    ) -> Option<Self> {
+        let ndc_x = cursor.0 / viewport.0 * 2.0 - 1.0;
+        let ndc_y = 1.0 - cursor.1 / viewport.1 * 2.0;
+        let inverse = (projection * view).try_inverse()?;
+
+        let near = inverse.transform_point(&na::Point3::new(ndc_x, ndc_y, 0.0));
+        let far = inverse.transform_point(&na::Point3::new(ndc_x, ndc_y, 1.0));
+
+        Some(Self {
+            origin: near,
+            direction: (far - near).normalize(),
+        })
+    }
+
+    fn transformed(&self, mat: &na::Matrix4<f32>) -> Self {
+        Self {
+            origin: mat.transform_point(&self.origin),
+            direction: mat.transform_vector(&self.direction),
+        }
+    }
+}
+
+// Slab test, returns distance along the ray to the entry point.
+fn aabb_hit(aabb: &Aabb, ray: &Ray) -> Option<f32> {
+    let mut t_min = 0.0f32;
+    let mut t_max = f32::INFINITY;
+
+    for axis in 0..3 {
+        let inv = 1.0 / ray.direction[axis];
+        let t0 = (aabb.min[axis] - ray.origin[axis]) * inv;
+        let t1 = (aabb.max[axis] - ray.origin[axis]) * inv;
+
+        t_min = t_min.max(t0.min(t1));
+        t_max = t_max.min(t0.max(t1));
+    }
+
+    (t_min <= t_max).then_some(t_min)
+}
+
+/// CPU copy of mesh positions, kept after the mesh is uploaded so it can be picked.
+pub struct PickShape {
+    aabb: Option<Aabb>,
+    positions: Vec<FVec3>,
+    indices: Option<Vec<u32>>,
+}
+
+impl PickShape {
+    pub fn new(mesh: &Mesh) -> Self {
+        Self {
+            aabb: mesh.bounds().map(|bounds| bounds.aabb),
+            positions: mesh.positions().to_vec(),
+            indices: mesh.indices().map(<[u32]>::to_vec),
+        }
+    }
+
+    fn triangles(&self) -> Box<dyn Iterator<Item = [FVec3; 3]> + '_> {
+        match &self.indices {
+            Some(indices) => Box::new(
+                indices
+                    .chunks_exact(3)
+                    .map(|tri| [0, 1, 2].map(|i| self.positions[tri[i] as usize])),
+            ),
+            None => Box::new(
+                self.positions
+                    .chunks_exact(3)
+                    .map(|tri| [tri[0], tri[1], tri[2]]),
+            ),
+        }
+    }
+
+    // Möller-Trumbore, both sides of triangles are hit.
+    fn triangle_hit(ray: &Ray, [a, b, c]: [FVec3; 3]) -> Option<f32> {
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let p = ray.direction.cross(&edge2);
+        let det = edge1.dot(&p);
+        if det.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let to_origin = ray.origin.coords - a;
+        let u = to_origin.dot(&p) / det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = to_origin.cross(&edge1);
+        let v = ray.direction.dot(&q) / det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(&q) / det;
+        (t >= 0.0).then_some(t)
+    }
+
+    /// Distance along a ray in the space of the mesh to the closest triangle it hits.
+    /// Triangles are only tested when the ray hits the bounding box.
+    fn ray_hit(&self, ray: &Ray) -> Option<f32> {
+        aabb_hit(&self.aabb?, ray)?;
+
+        self.triangles()
+            .filter_map(|triangle| Self::triangle_hit(ray, triangle))
+            .min_by(f32::total_cmp)
+    }
+}
+
+/// Closest visible object hit by `ray`.
+pub fn pick(scene: &GpuScene, ray: &Ray) -> Option<SceneObjectId> {
+    scene
+        .object_ids()
+        .filter(|id| scene.is_visible(*id))
+        .filter(|id| {
+            scene
+                .object_bounds(*id)
+                .is_some_and(|bounds| aabb_hit(&bounds.aabb, ray).is_some())
+        })
+        .filter_map(|id| {
+            let model = scene.object_instance(id).model();
+            let local_ray = ray.transformed(&model.try_inverse()?);
+
+            scene
+                .pick_shapes(id)
+                .filter_map(|shape| shape.ray_hit(&local_ray))
+                .min_by(f32::total_cmp)
+                .map(|t| (id, t))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(id, _)| id)
+}
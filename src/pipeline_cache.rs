@@ -0,0 +1,274 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use wgpu::naga;
+
+use crate::{
+    gpu::Gpu,
+    shader_compiler::{self, CompilationUnit, ShaderKey},
+};
+
+/// Render pipeline to get out of `PipelineCache`. Unlike `wgpu::RenderPipelineDescriptor`
+/// it names the shader by its compilation unit, which is only compiled when there is
+/// no such pipeline yet.
+pub struct RenderPipelineDesc<'a> {
+    pub label: Option<&'a str>,
+    pub shader: &'a CompilationUnit,
+    pub variant_defs: &'a [&'a str],
+    /// Values of `override` declarations of the shader, see `shader_compiler::specialize`.
+    pub constants: &'a [(&'a str, f64)],
+    pub bind_group_layouts: &'a [&'a wgpu::BindGroupLayout],
+    /// Only for devices supporting push constants, see `Gpu::push_constants`.
+    pub push_constant_ranges: &'a [wgpu::PushConstantRange],
+    pub vertex_entry: &'a str,
+    pub vertex_buffers: &'a [wgpu::VertexBufferLayout<'a>],
+    /// `None` for depth only pipelines.
+    pub fragment_entry: Option<&'a str>,
+    pub targets: &'a [Option<wgpu::ColorTargetState>],
+    pub primitive: wgpu::PrimitiveState,
+    pub depth_stencil: Option<wgpu::DepthStencilState>,
+    pub multisample: wgpu::MultisampleState,
+}
+
+/// Compute pipeline to get out of `PipelineCache`.
+pub struct ComputePipelineDesc<'a> {
+    pub label: Option<&'a str>,
+    pub shader: &'a CompilationUnit,
+    pub variant_defs: &'a [&'a str],
+    pub constants: &'a [(&'a str, f64)],
+    pub bind_group_layouts: &'a [&'a wgpu::BindGroupLayout],
+    pub push_constant_ranges: &'a [wgpu::PushConstantRange],
+    pub entry_point: &'a str,
+}
+
+type LayoutKey = (
+    Vec<wgpu::Id<wgpu::BindGroupLayout>>,
+    Vec<wgpu::PushConstantRange>,
+);
+
+// Composed module specialized with override values, compared bit for bit.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct ShaderModuleKey {
+    shader: ShaderKey,
+    constants: Vec<(String, u64)>,
+}
+
+impl ShaderModuleKey {
+    fn new(unit: &CompilationUnit, variant_defs: &[&str], constants: &[(&str, f64)]) -> Self {
+        Self {
+            shader: unit.key(variant_defs),
+            constants: constants
+                .iter()
+                .map(|(name, value)| ((*name).to_owned(), value.to_bits()))
+                .collect(),
+        }
+    }
+}
+
+fn layout_key(
+    bind_group_layouts: &[&wgpu::BindGroupLayout],
+    push_constant_ranges: &[wgpu::PushConstantRange],
+) -> LayoutKey {
+    (
+        bind_group_layouts
+            .iter()
+            .map(|bgl| bgl.global_id())
+            .collect(),
+        push_constant_ranges.to_vec(),
+    )
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct VertexBufferKey {
+    array_stride: wgpu::BufferAddress,
+    step_mode: wgpu::VertexStepMode,
+    attributes: Vec<wgpu::VertexAttribute>,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct RenderPipelineKey {
+    shader: ShaderModuleKey,
+    layout: LayoutKey,
+    vertex_entry: String,
+    vertex_buffers: Vec<VertexBufferKey>,
+    fragment_entry: Option<String>,
+    targets: Vec<Option<wgpu::ColorTargetState>>,
+    primitive: wgpu::PrimitiveState,
+    depth_stencil: Option<wgpu::DepthStencilState>,
+    multisample: wgpu::MultisampleState,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct ComputePipelineKey {
+    shader: ShaderModuleKey,
+    layout: LayoutKey,
+    entry_point: String,
+}
+
+fn get_or_create<K: Eq + Hash, V>(
+    cache: &Mutex<HashMap<K, Arc<V>>>,
+    key: K,
+    create: impl FnOnce() -> Result<V>,
+) -> Result<Arc<V>> {
+    let mut cache = cache.lock().unwrap();
+    if let Some(value) = cache.get(&key) {
+        return Ok(value.clone());
+    }
+
+    let value = Arc::new(create()?);
+    cache.insert(key, value.clone());
+    Ok(value)
+}
+
+/// Pipelines shared between passes, deduplicated by the shader with its definitions,
+/// bind group layouts, and the rest of the pipeline state with target formats.
+/// Shader modules are kept too, so pipelines differing in state only compile theirs once,
+/// and so are composed modules, so ones differing in override values only specialize them.
+///
+/// Layouts are told apart by identity, so passes share pipelines when they share
+/// bind group layouts too, which they do by getting them from `BindGroupLayouts`.
+#[derive(Default)]
+pub struct PipelineCache {
+    modules: Mutex<HashMap<ShaderKey, Arc<naga::Module>>>,
+    shaders: Mutex<HashMap<ShaderModuleKey, Arc<wgpu::ShaderModule>>>,
+    layouts: Mutex<HashMap<LayoutKey, Arc<wgpu::PipelineLayout>>>,
+    render_pipelines: Mutex<HashMap<RenderPipelineKey, Arc<wgpu::RenderPipeline>>>,
+    compute_pipelines: Mutex<HashMap<ComputePipelineKey, Arc<wgpu::ComputePipeline>>>,
+}
+
+impl PipelineCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn shader(
+        &self,
+        gpu: &Gpu,
+        key: ShaderModuleKey,
+        unit: &CompilationUnit,
+        variant_defs: &[&str],
+        constants: &[(&str, f64)],
+    ) -> Result<Arc<wgpu::ShaderModule>> {
+        get_or_create(&self.shaders, key.clone(), || {
+            let module = get_or_create(&self.modules, key.shader, || unit.compile(variant_defs))?;
+            let mut module = naga::Module::clone(&module);
+            shader_compiler::specialize(&mut module, constants)?;
+
+            Ok(gpu.shader_from_module(module))
+        })
+    }
+
+    fn layout(
+        &self,
+        gpu: &Gpu,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        push_constant_ranges: &[wgpu::PushConstantRange],
+    ) -> Result<Arc<wgpu::PipelineLayout>> {
+        let key = layout_key(bind_group_layouts, push_constant_ranges);
+        get_or_create(&self.layouts, key, || {
+            Ok(gpu
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("PipelineCache::PipelineLayout"),
+                    bind_group_layouts,
+                    push_constant_ranges,
+                }))
+        })
+    }
+
+    pub fn render_pipeline(
+        &self,
+        gpu: &Gpu,
+        desc: &RenderPipelineDesc,
+    ) -> Result<Arc<wgpu::RenderPipeline>> {
+        let shader_key = ShaderModuleKey::new(desc.shader, desc.variant_defs, desc.constants);
+        let key = RenderPipelineKey {
+            shader: shader_key.clone(),
+            layout: layout_key(desc.bind_group_layouts, desc.push_constant_ranges),
+            vertex_entry: desc.vertex_entry.to_owned(),
+            vertex_buffers: desc
+                .vertex_buffers
+                .iter()
+                .map(|buffer| VertexBufferKey {
+                    array_stride: buffer.array_stride,
+                    step_mode: buffer.step_mode,
+                    attributes: buffer.attributes.to_vec(),
+                })
+                .collect(),
+            fragment_entry: desc.fragment_entry.map(str::to_owned),
+            targets: desc.targets.to_vec(),
+            primitive: desc.primitive,
+            depth_stencil: desc.depth_stencil.clone(),
+            multisample: desc.multisample,
+        };
+
+        get_or_create(&self.render_pipelines, key, || {
+            let layout = self.layout(gpu, desc.bind_group_layouts, desc.push_constant_ranges)?;
+            let shader = self.shader(
+                gpu,
+                shader_key,
+                desc.shader,
+                desc.variant_defs,
+                desc.constants,
+            )?;
+
+            Ok(gpu
+                .device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: desc.label,
+                    layout: Some(&layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: desc.vertex_entry,
+                        buffers: desc.vertex_buffers,
+                    },
+                    fragment: desc.fragment_entry.map(|entry_point| wgpu::FragmentState {
+                        module: &shader,
+                        entry_point,
+                        targets: desc.targets,
+                    }),
+                    primitive: desc.primitive,
+                    depth_stencil: desc.depth_stencil.clone(),
+                    multisample: desc.multisample,
+                    multiview: None,
+                }))
+        })
+    }
+
+    pub fn compute_pipeline(
+        &self,
+        gpu: &Gpu,
+        desc: &ComputePipelineDesc,
+    ) -> Result<Arc<wgpu::ComputePipeline>> {
+        let shader_key = ShaderModuleKey::new(desc.shader, desc.variant_defs, desc.constants);
+        let key = ComputePipelineKey {
+            shader: shader_key.clone(),
+            layout: layout_key(desc.bind_group_layouts, desc.push_constant_ranges),
+            entry_point: desc.entry_point.to_owned(),
+        };
+
+        get_or_create(&self.compute_pipelines, key, || {
+            let layout = self.layout(gpu, desc.bind_group_layouts, desc.push_constant_ranges)?;
+            let shader = self.shader(
+                gpu,
+                shader_key,
+                desc.shader,
+                desc.variant_defs,
+                desc.constants,
+            )?;
+
+            Ok(gpu
+                .device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: desc.label,
+                    layout: Some(&layout),
+                    module: &shader,
+                    entry_point: desc.entry_point,
+                }))
+        })
+    }
+}
@@ -1,26 +1,114 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc, time::Instant};
 
 use crate::{
-    compute::BlurPass, gpu::Gpu, render_context::RenderContext, shader_compiler::ShaderCompiler,
+    render_context::RenderContext,
+    render_pass::{FrameContext, RenderPass},
+    texture_pool::{TargetSize, TransientDesc},
+    uniform_ring::UniformRing,
 };
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use encase::{ShaderSize, ShaderType, UniformBuffer};
 use nalgebra as na;
+use serde::{Deserialize, Serialize};
 
+// Fields of `Params` in the shader.
+type Params = [na::Vector4<f32>; 3];
+
+const INTERMEDIATE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Brings the scene color into the displayable range, then runs it through a chain of
+/// effects ping-ponging between two textures. The last stage draws into the frame.
 pub struct PostprocessPass<'window> {
     render_ctx: Arc<RenderContext<'window>>,
-    forward_bg: wgpu::BindGroup,
-    deferred_bg: wgpu::BindGroup,
-    bgl: wgpu::BindGroupLayout,
-    pipeline: wgpu::RenderPipeline,
-    settings_buf: wgpu::Buffer,
+    bgl: Arc<wgpu::BindGroupLayout>,
+    resolve_pipelines: StagePipelines,
+    effect_pipelines: HashMap<PostprocessEffect, StagePipelines>,
+    // Used when nothing measured the exposure, leaves colors as they are.
+    unit_exposure_buf: wgpu::Buffer,
     sampler: wgpu::Sampler,
-    texture: wgpu::Texture,
+    // Film grain changes every frame.
+    started: Instant,
+}
+
+// Stages write one of the ping-pong textures, or the frame when they're the last one.
+struct StagePipelines {
+    intermediate: wgpu::RenderPipeline,
+    frame: wgpu::RenderPipeline,
+}
+
+/// How HDR colors are brought into the displayable range, written by `AutoExposurePass`.
+#[derive(ShaderType)]
+pub struct Exposure {
+    /// Average scene luminance the exposure has adapted to so far.
+    pub luminance: f32,
+    pub scale: f32,
+    /// Whether scaled colors are tonemapped, otherwise they're clamped.
+    pub tonemap: u32,
+}
+
+impl Exposure {
+    pub fn contents(&self) -> Result<Vec<u8>> {
+        let size: u64 = Self::SHADER_SIZE.into();
+        let mut contents = UniformBuffer::new(Vec::with_capacity(size as usize));
+        contents.write(self)?;
+
+        Ok(contents.into_inner())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum PostprocessEffect {
+    ColorCorrection,
+    Vignette,
+    FilmGrain,
+    ChromaticAberration,
+    Sharpen,
+}
+
+impl PostprocessEffect {
+    pub const ALL: [Self; 5] = [
+        Self::ColorCorrection,
+        Self::Vignette,
+        Self::FilmGrain,
+        Self::ChromaticAberration,
+        Self::Sharpen,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::ColorCorrection => "Color Correction",
+            Self::Vignette => "Vignette",
+            Self::FilmGrain => "Film Grain",
+            Self::ChromaticAberration => "Chromatic Aberration",
+            Self::Sharpen => "Sharpen",
+        }
+    }
+
+    /// Name used in the console, also the entry point of the effect in the shader.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ColorCorrection => "color_correction",
+            Self::Vignette => "vignette",
+            Self::FilmGrain => "film_grain",
+            Self::ChromaticAberration => "chromatic_aberration",
+            Self::Sharpen => "sharpen",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct PostprocessStage {
+    pub effect: PostprocessEffect,
+    pub enabled: bool,
 }
 
-#[derive(ShaderType, PartialEq)]
+#[derive(PartialEq, Clone, Serialize, Deserialize)]
+#[serde(from = "PostprocessValues", into = "PostprocessValues")]
 pub struct PostprocessSettings {
     bcsg: na::Vector4<f32>,
+    // Vignette, film grain, chromatic aberration and sharpen strengths.
+    effects: na::Vector4<f32>,
+    chain: Vec<PostprocessStage>,
 }
 
 impl PostprocessSettings {
@@ -39,6 +127,82 @@ impl PostprocessSettings {
     pub fn gamma_mut(&mut self) -> &mut f32 {
         &mut self.bcsg.w
     }
+
+    pub fn vignette_mut(&mut self) -> &mut f32 {
+        &mut self.effects.x
+    }
+
+    pub fn film_grain_mut(&mut self) -> &mut f32 {
+        &mut self.effects.y
+    }
+
+    pub fn chromatic_aberration_mut(&mut self) -> &mut f32 {
+        &mut self.effects.z
+    }
+
+    pub fn sharpen_mut(&mut self) -> &mut f32 {
+        &mut self.effects.w
+    }
+
+    /// Effects in the order they're applied, turned off ones included.
+    pub fn chain(&self) -> &[PostprocessStage] {
+        &self.chain
+    }
+
+    pub fn chain_mut(&mut self) -> &mut [PostprocessStage] {
+        &mut self.chain
+    }
+
+    /// Turns on the effects named in `names` in that order, every other one is turned off.
+    pub fn set_chain(&mut self, names: &str) -> Result<()> {
+        let mut chain = Vec::with_capacity(PostprocessEffect::ALL.len());
+        for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let Some(effect) = PostprocessEffect::ALL
+                .into_iter()
+                .find(|e| e.name() == name)
+            else {
+                bail!("unknown effect {name}");
+            };
+            if chain.iter().any(|s: &PostprocessStage| s.effect == effect) {
+                bail!("effect {name} is listed twice");
+            }
+            chain.push(PostprocessStage {
+                effect,
+                enabled: true,
+            });
+        }
+        self.chain = Self::complete_chain(chain);
+
+        Ok(())
+    }
+
+    // Effects missing from the chain are added turned off, duplicates are dropped.
+    fn complete_chain(stages: Vec<PostprocessStage>) -> Vec<PostprocessStage> {
+        let mut chain: Vec<PostprocessStage> = Vec::with_capacity(PostprocessEffect::ALL.len());
+        for stage in stages {
+            if !chain.iter().any(|s| s.effect == stage.effect) {
+                chain.push(stage);
+            }
+        }
+        for effect in PostprocessEffect::ALL {
+            if !chain.iter().any(|s| s.effect == effect) {
+                chain.push(PostprocessStage {
+                    effect,
+                    enabled: false,
+                });
+            }
+        }
+
+        chain
+    }
+
+    fn params(&self, time: f32) -> Params {
+        [
+            self.bcsg,
+            self.effects,
+            na::Vector4::new(time, 0.0, 0.0, 0.0),
+        ]
+    }
 }
 
 impl Default for PostprocessSettings {
@@ -47,145 +211,150 @@ impl Default for PostprocessSettings {
     }
 }
 
+// How settings are saved, spelled out instead of packed into vectors.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct PostprocessValues {
+    brightness: f32,
+    contrast: f32,
+    saturation: f32,
+    gamma: f32,
+    vignette: f32,
+    film_grain: f32,
+    chromatic_aberration: f32,
+    sharpen: f32,
+    chain: Vec<PostprocessStage>,
+}
+
+impl Default for PostprocessValues {
+    fn default() -> Self {
+        PostprocessSettings::default().into()
+    }
+}
+
+impl From<PostprocessValues> for PostprocessSettings {
+    fn from(v: PostprocessValues) -> Self {
+        let mut settings = Self::new(v.brightness, v.contrast, v.saturation, v.gamma);
+        settings.effects =
+            na::Vector4::new(v.vignette, v.film_grain, v.chromatic_aberration, v.sharpen);
+        // Saved before effects were added or with some of them missing.
+        settings.chain = Self::complete_chain(v.chain);
+
+        settings
+    }
+}
+
+impl From<PostprocessSettings> for PostprocessValues {
+    fn from(s: PostprocessSettings) -> Self {
+        Self {
+            brightness: s.bcsg.x,
+            contrast: s.bcsg.y,
+            saturation: s.bcsg.z,
+            gamma: s.bcsg.w,
+            vignette: s.effects.x,
+            film_grain: s.effects.y,
+            chromatic_aberration: s.effects.z,
+            sharpen: s.effects.w,
+            chain: s.chain,
+        }
+    }
+}
+
 impl PostprocessSettings {
+    /// Only color correction is turned on, other effects wait in the chain turned off.
     pub fn new(brightness: f32, contrast: f32, saturation: f32, gamma: f32) -> Self {
         Self {
             bcsg: na::Vector4::new(brightness, contrast, saturation, gamma),
+            effects: na::Vector4::new(0.5, 0.05, 0.01, 0.3),
+            chain: PostprocessEffect::ALL
+                .into_iter()
+                .map(|effect| PostprocessStage {
+                    effect,
+                    enabled: effect == PostprocessEffect::ColorCorrection,
+                })
+                .collect(),
         }
     }
 }
 
 impl<'window> PostprocessPass<'window> {
-    pub fn new(
-        render_ctx: Arc<RenderContext<'window>>,
-        deferred_texture: &wgpu::TextureView,
-        settings: &PostprocessSettings,
-    ) -> Result<Self> {
+    pub fn new(render_ctx: Arc<RenderContext<'window>>) -> Result<Self> {
         let RenderContext {
             gpu,
+            bind_group_layouts,
             shader_compiler,
             ..
         } = render_ctx.as_ref();
 
-        let tex_size = gpu.viewport_size();
-
-        let texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: tex_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: gpu.swapchain_format(),
-            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
-
-        let bgl: wgpu::BindGroupLayout =
-            gpu.device
-                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    label: None,
-                    entries: &[
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 0,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Texture {
-                                sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                                multisampled: false,
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 1,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 2,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Buffer {
-                                ty: wgpu::BufferBindingType::Uniform,
-                                has_dynamic_offset: false,
-                                min_binding_size: None,
-                            },
-                            count: None,
-                        },
-                    ],
-                });
+        let bgl: Arc<wgpu::BindGroupLayout> = bind_group_layouts.get(
+            gpu,
+            "PostprocessPass::BindGroupLayout",
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: UniformRing::binding_type(Params::SHADER_SIZE.into()),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        );
 
+        // Chromatic aberration samples between texels.
         let sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
-            label: None,
+            label: Some("PostprocessPass::Sampler"),
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
             mipmap_filter: wgpu::FilterMode::Nearest,
             ..Default::default()
         });
 
-        let vec4_size: u64 = na::Vector4::<f32>::SHADER_SIZE.into();
-        let mut settings_contents = UniformBuffer::new(Vec::with_capacity(vec4_size as usize));
-        settings_contents.write(&settings)?;
-
         use wgpu::util::DeviceExt;
-        let settings_buf = gpu
+        let unit_exposure = Exposure {
+            luminance: 0.0,
+            scale: 1.0,
+            tonemap: 0,
+        };
+        let unit_exposure_buf = gpu
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: None,
-                contents: settings_contents.into_inner().as_slice(),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                label: Some("PostprocessPass::UnitExposure"),
+                contents: unit_exposure.contents()?.as_slice(),
+                usage: wgpu::BufferUsages::UNIFORM,
             });
 
-        let forward_bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &bgl,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(
-                        &texture.create_view(&wgpu::TextureViewDescriptor::default()),
-                    ),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Buffer(
-                        settings_buf.as_entire_buffer_binding(),
-                    ),
-                },
-            ],
-        });
-
-        let deferred_bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &bgl,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(deferred_texture),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Buffer(
-                        settings_buf.as_entire_buffer_binding(),
-                    ),
-                },
-            ],
-        });
-
         let pipeline_layout = gpu
             .device
             .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
+                label: Some("PostprocessPass::PipelineLayout"),
                 bind_group_layouts: &[&bgl],
                 push_constant_ranges: &[],
             });
@@ -193,146 +362,217 @@ impl<'window> PostprocessPass<'window> {
         let module = shader_compiler.compilation_unit("./shaders/screenspace/postprocess.wgsl")?;
         let shader = gpu.shader_from_module(module.compile(Default::default())?);
 
-        let pipeline = gpu
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                vertex: wgpu::VertexState {
-                    module: &shader,
-                    entry_point: "vs_main",
-                    buffers: &[],
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &shader,
-                    entry_point: "fs_main",
-                    targets: &[Some(gpu.swapchain_format().into())],
-                }),
-                layout: Some(&pipeline_layout),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleStrip,
-                    ..Default::default()
-                },
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState::default(),
-                multiview: None,
-            });
+        let pipeline = |entry_point, format: wgpu::TextureFormat| {
+            gpu.device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("PostprocessPass::Pipeline"),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &[],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point,
+                        targets: &[Some(format.into())],
+                    }),
+                    layout: Some(&pipeline_layout),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleStrip,
+                        ..Default::default()
+                    },
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                })
+        };
+        let stage_pipelines = |entry_point| StagePipelines {
+            intermediate: pipeline(entry_point, INTERMEDIATE_FORMAT),
+            frame: pipeline(entry_point, gpu.swapchain_format()),
+        };
 
         Ok(Self {
+            resolve_pipelines: stage_pipelines("resolve"),
+            effect_pipelines: PostprocessEffect::ALL
+                .into_iter()
+                .map(|effect| (effect, stage_pipelines(effect.name())))
+                .collect(),
+            started: Instant::now(),
             render_ctx,
             sampler,
             bgl,
-            forward_bg,
-            deferred_bg,
-            pipeline,
-            settings_buf,
-            texture,
+            unit_exposure_buf,
         })
     }
 
-    pub fn on_resize(&mut self, gpu: &Gpu, new_size: (u32, u32)) {
-        let tex_size = wgpu::Extent3d {
-            width: new_size.0,
-            height: new_size.1,
-            depth_or_array_layers: 1,
-        };
-
-        let texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: tex_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: gpu.swapchain_format(),
-            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
-
-        let bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &self.bgl,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(
-                        &texture.create_view(&wgpu::TextureViewDescriptor::default()),
-                    ),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&self.sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Buffer(
-                        self.settings_buf.as_entire_buffer_binding(),
-                    ),
-                },
-            ],
-        });
-
-        self.texture = texture;
-        self.forward_bg = bg;
-    }
-
+    /// Forward rendered frames are copied out of the surface, deferred ones are read
+    /// from the lit `deferred` texture and scaled by `exposure` when it's measured.
     pub fn render(
         &self,
         settings: &PostprocessSettings,
-        frame: wgpu::SurfaceTexture,
-        deferred: bool,
-    ) -> wgpu::SurfaceTexture {
-        let RenderContext { gpu, .. } = self.render_ctx.as_ref();
+        frame: &wgpu::SurfaceTexture,
+        deferred: Option<&wgpu::TextureView>,
+        exposure: Option<&wgpu::Buffer>,
+        clear_color: wgpu::Color,
+    ) -> Result<()> {
+        let RenderContext {
+            gpu,
+            profiler,
+            uniform_ring,
+            texture_pool,
+            ..
+        } = self.render_ctx.as_ref();
 
         let mut encoder = gpu
             .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
-
-        let settings_size: u64 = PostprocessSettings::SHADER_SIZE.into();
-        let mut contents = UniformBuffer::new(Vec::with_capacity(settings_size as usize));
-
-        contents.write(settings).unwrap();
-
-        gpu.queue
-            .write_buffer(&self.settings_buf, 0, contents.into_inner().as_slice());
-
-        if !deferred {
-            encoder.copy_texture_to_texture(
-                frame.texture.as_image_copy(),
-                self.texture.as_image_copy(),
-                gpu.viewport_size(),
-            );
-        }
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("PostprocessPass::CommandEncoder"),
+            });
 
+        let params_offset = uniform_ring
+            .write_uniform(gpu, &settings.params(self.started.elapsed().as_secs_f32()))?;
+
+        // Forward rendered frames are copied out of the surface, to be read while drawing over it.
+        let (frame_copy, frame_copy_view);
+        let source = match deferred {
+            Some(view) => view,
+            None => {
+                frame_copy = texture_pool.acquire(
+                    gpu,
+                    "PostprocessPass::FrameCopy",
+                    &TransientDesc {
+                        size: TargetSize::Viewport(1),
+                        format: gpu.swapchain_format(),
+                        usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+                    },
+                );
+                encoder.copy_texture_to_texture(
+                    frame.texture.as_image_copy(),
+                    frame_copy.as_image_copy(),
+                    gpu.viewport_size(),
+                );
+                frame_copy_view = frame_copy.create_view(&Default::default());
+                &frame_copy_view
+            }
+        };
         let frame_view = frame
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
+        let targets = ["PostprocessPass::TargetA", "PostprocessPass::TargetB"].map(|label| {
+            texture_pool.acquire(
+                gpu,
+                label,
+                &TransientDesc {
+                    size: TargetSize::Viewport(1),
+                    format: INTERMEDIATE_FORMAT,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                },
+            )
+        });
+        let target_views = targets
+            .each_ref()
+            .map(|t| t.create_view(&Default::default()));
+
+        let mut stages = vec![(
+            &self.resolve_pipelines,
+            exposure.unwrap_or(&self.unit_exposure_buf),
+        )];
+        for stage in settings.chain().iter().filter(|s| s.enabled) {
+            let pipelines = self
+                .effect_pipelines
+                .get(&stage.effect)
+                .with_context(|| format!("no pipeline for {:?}", stage.effect))?;
+            stages.push((pipelines, &self.unit_exposure_buf));
+        }
 
-        {
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &frame_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                        store: wgpu::StoreOp::Store,
+        let mut input = source;
+        for (i, (pipelines, exposure)) in stages.iter().enumerate() {
+            let last = i + 1 == stages.len();
+            let (output, pipeline) = if last {
+                (&frame_view, &pipelines.frame)
+            } else {
+                (&target_views[i % 2], &pipelines.intermediate)
+            };
+
+            let bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("PostprocessPass::BindGroup"),
+                layout: &self.bgl,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(input),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
                     },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: uniform_ring.binding(Params::SHADER_SIZE.into()),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: exposure.as_entire_binding(),
+                    },
+                ],
             });
 
-            rpass.set_pipeline(&self.pipeline);
-            if deferred {
-                rpass.set_bind_group(0, &self.deferred_bg, &[]);
-            } else {
-                rpass.set_bind_group(0, &self.forward_bg, &[]);
+            {
+                let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("PostprocessPass::RenderPass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: output,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(clear_color),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    // Timings of stages add up under one scope.
+                    timestamp_writes: profiler.render_pass_writes("Postprocess"),
+                    occlusion_query_set: None,
+                });
+
+                rpass.set_pipeline(pipeline);
+                rpass.set_bind_group(0, &bg, &[params_offset]);
+
+                rpass.draw(0..4, 0..1);
             }
 
-            rpass.draw(0..4, 0..1);
+            input = output;
         }
+
         gpu.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
+
+impl RenderPass for PostprocessPass<'_> {
+    fn name(&self) -> &'static str {
+        "PostprocessPass"
+    }
+
+    fn enabled(&self, ctx: &FrameContext) -> bool {
+        !ctx.settings.postprocess_disabled && !ctx.settings.deferred_debug_shown()
+    }
 
-        frame
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        let settings = ctx.settings;
+        // Lit deferred frames are the only HDR scene color.
+        let deferred = ctx
+            .scene_color
+            .as_ref()
+            .filter(|(_, hdr)| *hdr)
+            .map(|(view, _)| view);
+        Self::render(
+            self,
+            settings.postprocess_settings(),
+            &ctx.frame,
+            deferred,
+            ctx.exposure.as_deref(),
+            settings.background.clear_color(),
+        )
     }
 }
@@ -1,4 +1,4 @@
-use crate::gpu::GpuMat4;
+use crate::gpu::{Gpu, GpuMat4};
 use anyhow::Result;
 use nalgebra as na;
 
@@ -10,41 +10,126 @@ const OPENGL_TO_WGPU_MATRIX: na::Matrix4<f32> = na::Matrix4::new(
     0.0, 0.0, 0.0, 1.0,
 );
 
+// Maps depth `z` to `1 - z`, so the near plane ends up at 1 and the far one at 0.
+#[rustfmt::skip]
+const REVERSE_Z_MATRIX: na::Matrix4<f32> = na::Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, -1.0, 1.0,
+    0.0, 0.0, 0.0, 1.0,
+);
+
 pub fn wgpu_projection(proj_mat: na::Matrix4<f32>) -> na::Matrix4<f32> {
     OPENGL_TO_WGPU_MATRIX * proj_mat
 }
 
-pub struct GpuProjection(GpuMat4, GpuMat4);
+#[derive(Clone, Copy, Debug)]
+pub struct Perspective {
+    pub aspect: f32,
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+impl Perspective {
+    pub fn new(aspect: f32, fovy: f32, znear: f32, zfar: f32) -> Self {
+        Self {
+            aspect,
+            fovy,
+            znear,
+            zfar,
+        }
+    }
+
+    pub fn matrix(&self) -> na::Matrix4<f32> {
+        wgpu_projection(na::Matrix4::new_perspective(
+            self.aspect,
+            self.fovy,
+            self.znear,
+            self.zfar,
+        ))
+    }
+}
+
+/// Shaders get the projection with depth reversed when the Gpu uses reverse-Z,
+/// `matrix()` always keeps the standard depth range for the CPU side.
+pub struct GpuProjection {
+    perspective: Perspective,
+    reverse_z: bool,
+    gpu_mat: GpuMat4,
+    gpu_inv_mat: GpuMat4,
+}
 
 impl GpuProjection {
-    pub fn new(mat: na::Matrix4<f32>, device: &wgpu::Device) -> Result<Self> {
-        let projection = OPENGL_TO_WGPU_MATRIX * mat;
+    pub fn new(perspective: Perspective, gpu: &Gpu) -> Result<Self> {
+        let reverse_z = gpu.reverse_z();
+        let (projection, projection_inv) = Self::gpu_matrices(&perspective, reverse_z)?;
+
+        Ok(Self {
+            perspective,
+            reverse_z,
+            gpu_mat: GpuMat4::new(projection, &gpu.device)?,
+            gpu_inv_mat: GpuMat4::new(projection_inv, &gpu.device)?,
+        })
+    }
+
+    fn gpu_matrix_of(perspective: &Perspective, reverse_z: bool) -> na::Matrix4<f32> {
+        if reverse_z {
+            REVERSE_Z_MATRIX * perspective.matrix()
+        } else {
+            perspective.matrix()
+        }
+    }
+
+    fn gpu_matrices(
+        perspective: &Perspective,
+        reverse_z: bool,
+    ) -> Result<(na::Matrix4<f32>, na::Matrix4<f32>)> {
+        let projection = Self::gpu_matrix_of(perspective, reverse_z);
         let projection_inv = projection
             .try_inverse()
             .ok_or_else(|| anyhow::anyhow!("failed to invert projection matrix"))?;
 
-        Ok(Self(
-            GpuMat4::new(projection, device)?,
-            GpuMat4::new(projection_inv, device)?,
-        ))
+        Ok((projection, projection_inv))
+    }
+
+    pub fn matrix(&self) -> na::Matrix4<f32> {
+        self.perspective.matrix()
+    }
+
+    /// The matrix shaders get, see `GpuProjection`.
+    pub fn gpu_matrix(&self) -> na::Matrix4<f32> {
+        Self::gpu_matrix_of(&self.perspective, self.reverse_z)
+    }
+
+    pub fn perspective(&self) -> &Perspective {
+        &self.perspective
     }
 
     pub fn buffer(&self) -> &wgpu::Buffer {
-        self.0.buffer()
+        self.gpu_mat.buffer()
     }
 
     pub fn inverse_buffer(&self) -> &wgpu::Buffer {
-        self.1.buffer()
+        self.gpu_inv_mat.buffer()
     }
 
-    pub fn update(&mut self, queue: &wgpu::Queue, mat: na::Matrix4<f32>) -> Result<()> {
-        let projection = OPENGL_TO_WGPU_MATRIX * mat;
-        let projection_inv = projection
-            .try_inverse()
-            .ok_or_else(|| anyhow::anyhow!("failed to invert projection matrix"))?;
+    /// Rewrites the buffers in place, so bind groups using them, like the
+    /// `SceneUniform` one, see the new projection without being recreated.
+    pub fn update<F>(&mut self, queue: &wgpu::Queue, updater: F) -> Result<()>
+    where
+        F: Fn(&mut Perspective),
+    {
+        updater(&mut self.perspective);
 
-        self.0.update(queue, projection)?;
-        self.1.update(queue, projection_inv)?;
+        let (projection, projection_inv) = Self::gpu_matrices(&self.perspective, self.reverse_z)?;
+        self.gpu_mat.update(queue, projection)?;
+        self.gpu_inv_mat.update(queue, projection_inv)?;
         Ok(())
     }
+
+    /// Only the aspect ratio depends on the viewport, fovy and clip planes are kept.
+    pub fn on_resize(&mut self, queue: &wgpu::Queue, new_size: (u32, u32)) -> Result<()> {
+        self.update(queue, |p| p.aspect = new_size.0 as f32 / new_size.1 as f32)
+    }
 }
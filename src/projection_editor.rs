@@ -0,0 +1,44 @@
+use gpu_basics::projection::Perspective;
+
+/// "Projection" window, editing the field of view and clip planes of the camera.
+#[derive(Default)]
+pub struct ProjectionEditor {
+    // Applied by the frame loop, together with everything derived from the projection.
+    edited: Option<Perspective>,
+}
+
+impl ProjectionEditor {
+    pub fn render(&mut self, ctx: &egui::Context, perspective: &Perspective) {
+        egui::Window::new("Projection")
+            .default_open(false)
+            .show(ctx, |ui| {
+                let mut edited = self.edited.unwrap_or(*perspective);
+                let mut fovy = edited.fovy.to_degrees();
+
+                let changed = [
+                    ui.add(egui::Slider::new(&mut fovy, 10.0..=120.0).text("FOV (vertical)")),
+                    ui.add(
+                        egui::Slider::new(&mut edited.znear, 0.01..=5.0)
+                            .logarithmic(true)
+                            .text("Near Plane"),
+                    ),
+                    ui.add(
+                        egui::Slider::new(&mut edited.zfar, 10.0..=1000.0)
+                            .logarithmic(true)
+                            .text("Far Plane"),
+                    ),
+                ]
+                .iter()
+                .any(|response| response.changed());
+
+                if changed {
+                    edited.fovy = fovy.to_radians();
+                    self.edited = Some(edited);
+                }
+            });
+    }
+
+    pub fn take_edited(&mut self) -> Option<Perspective> {
+        self.edited.take()
+    }
+}
@@ -1,38 +1,77 @@
+use std::sync::RwLock;
+
+use anyhow::Result;
+
 use winit::window::Window;
 
 use crate::{
-    gpu::Gpu, light_scene::LightScene, material::MaterialAtlas, scene::GpuScene,
-    scene_uniform::SceneUniform, shader_compiler::ShaderCompiler,
+    bind_group_layouts::BindGroupLayouts,
+    camera::GpuCamera,
+    gpu::Gpu,
+    gpu_profiler::GpuProfiler,
+    light_scene::{LightBuffers, LightScene},
+    material::MaterialAtlas,
+    pipeline_cache::PipelineCache,
+    projection::GpuProjection,
+    scene::GpuScene,
+    scene_uniform::SceneUniform,
+    shader_compiler::ShaderCompiler,
+    texture_pool::TexturePool,
+    uniform_ring::UniformRing,
 };
 
+// Plenty for uniforms of a frame, slots are aligned to 256 bytes on most devices.
+const UNIFORM_RING_SIZE: u64 = 64 * 1024;
+
 pub struct RenderContext<'window> {
     pub gpu: Gpu<'window>,
     pub shader_compiler: ShaderCompiler,
-    pub gpu_scene: GpuScene,
-    pub light_scene: LightScene,
+    pub gpu_scene: RwLock<GpuScene>,
+    // Edited at runtime, `light_buffers` have to be uploaded again after changes.
+    pub light_scene: RwLock<LightScene>,
+    pub light_buffers: LightBuffers,
     pub scene_uniform: SceneUniform,
-    pub material_atlas: MaterialAtlas,
+    pub material_atlas: RwLock<MaterialAtlas>,
+    pub pipeline_cache: PipelineCache,
+    pub bind_group_layouts: BindGroupLayouts,
+    pub uniform_ring: UniformRing,
+    pub texture_pool: TexturePool,
+    pub profiler: GpuProfiler,
     pub window: &'window Window,
 }
 
 impl<'window> RenderContext<'window> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         window: &'window Window,
         gpu: Gpu<'window>,
         shader_compiler: ShaderCompiler,
-        scene_uniform: SceneUniform,
+        camera: &GpuCamera,
+        projection: &GpuProjection,
         gpu_scene: GpuScene,
         material_atlas: MaterialAtlas,
         light_scene: LightScene,
-    ) -> Self {
-        Self {
+    ) -> Result<Self> {
+        let profiler = GpuProfiler::new(&gpu);
+        let light_buffers = LightBuffers::new(&gpu, &light_scene)?;
+        let bind_group_layouts = BindGroupLayouts::new();
+        let scene_uniform = SceneUniform::new(&gpu, &bind_group_layouts, camera, projection);
+        let uniform_ring = UniformRing::new(&gpu, UNIFORM_RING_SIZE);
+
+        Ok(Self {
             window,
             gpu,
             shader_compiler,
             scene_uniform,
-            gpu_scene,
-            material_atlas,
-            light_scene,
-        }
+            gpu_scene: RwLock::new(gpu_scene),
+            material_atlas: RwLock::new(material_atlas),
+            light_scene: RwLock::new(light_scene),
+            light_buffers,
+            pipeline_cache: PipelineCache::new(),
+            bind_group_layouts,
+            uniform_ring,
+            texture_pool: TexturePool::new(),
+            profiler,
+        })
     }
 }
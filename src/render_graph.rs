@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    gpu::Gpu,
+    render_pass::{FrameContext, RenderPass},
+};
+
+/// Texture allocated by the graph for a single frame, as big as the viewport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureDesc {
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+}
+
+impl TextureDesc {
+    /// Rendered to by one pass and sampled by the ones after it.
+    pub fn target(format: wgpu::TextureFormat) -> Self {
+        Self {
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        }
+    }
+}
+
+/// Resources a pass reads and writes, by name. Passes reading a resource run after
+/// the pass writing it.
+#[derive(Default)]
+pub struct PassIo {
+    reads: Vec<&'static str>,
+    // Textures of the graph come with their description, passes export their own without one.
+    writes: Vec<(&'static str, Option<TextureDesc>)>,
+}
+
+impl PassIo {
+    pub fn read(mut self, name: &'static str) -> Self {
+        self.reads.push(name);
+        self
+    }
+
+    /// Has the graph allocate `name`, available to the pass while it renders.
+    pub fn write(mut self, name: &'static str, desc: TextureDesc) -> Self {
+        self.writes.push((name, Some(desc)));
+        self
+    }
+
+    /// The pass renders into a texture of its own and hands its view over
+    /// with `GraphResources::export`.
+    pub fn export(mut self, name: &'static str) -> Self {
+        self.writes.push((name, None));
+        self
+    }
+}
+
+/// Bind group entry, numbered by its position.
+pub enum Binding<'a> {
+    /// Texture of the graph.
+    Resource(&'static str),
+    View(&'a wgpu::TextureView),
+    Sampler(&'a wgpu::Sampler),
+    Buffer(&'a wgpu::Buffer),
+}
+
+/// Views of textures passes share during a frame.
+#[derive(Default)]
+pub struct GraphResources {
+    views: HashMap<&'static str, wgpu::TextureView>,
+}
+
+impl GraphResources {
+    pub fn view(&self, name: &'static str) -> Result<&wgpu::TextureView> {
+        self.views
+            .get(name)
+            .ok_or_else(|| anyhow!("{name} hasn't been written this frame"))
+    }
+
+    pub fn export(&mut self, name: &'static str, view: wgpu::TextureView) {
+        self.views.insert(name, view);
+    }
+
+    pub fn bind_group(
+        &self,
+        gpu: &Gpu,
+        label: &str,
+        layout: &wgpu::BindGroupLayout,
+        bindings: &[Binding],
+    ) -> Result<wgpu::BindGroup> {
+        let entries = bindings
+            .iter()
+            .enumerate()
+            .map(|(i, binding)| {
+                let resource = match binding {
+                    Binding::Resource(name) => wgpu::BindingResource::TextureView(self.view(name)?),
+                    Binding::View(view) => wgpu::BindingResource::TextureView(view),
+                    Binding::Sampler(sampler) => wgpu::BindingResource::Sampler(sampler),
+                    Binding::Buffer(buffer) => buffer.as_entire_binding(),
+                };
+
+                Ok(wgpu::BindGroupEntry {
+                    binding: i as u32,
+                    resource,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout,
+            entries: &entries,
+        }))
+    }
+}
+
+/// Pass of the last prepared frame, with names of resources it reads and writes.
+pub struct GraphPass {
+    pub name: &'static str,
+    pub reads: Vec<&'static str>,
+    pub writes: Vec<&'static str>,
+}
+
+/// Works out the order passes of a frame run in from resources they declare,
+/// and keeps the textures they write to.
+///
+/// Textures live as long as a pass writes them and follow the size of the viewport.
+#[derive(Default)]
+pub struct RenderGraph {
+    textures: HashMap<&'static str, (TextureDesc, wgpu::Texture)>,
+    passes: Vec<GraphPass>,
+}
+
+impl RenderGraph {
+    /// Indices of enabled passes in the order to render them. Passes keep the order
+    /// they're listed in, unless one reads what a later one writes.
+    ///
+    /// Views of textures written by the passes are put in `ctx.resources`.
+    pub fn prepare(
+        &mut self,
+        gpu: &Gpu,
+        passes: &[&mut dyn RenderPass],
+        ctx: &mut FrameContext,
+    ) -> Result<Vec<usize>> {
+        let enabled = passes
+            .iter()
+            .enumerate()
+            .filter(|(_, pass)| pass.enabled(ctx))
+            .map(|(i, pass)| (i, pass.io()))
+            .collect::<Vec<_>>();
+
+        let mut writers = HashMap::new();
+        for (i, io) in &enabled {
+            for (name, _) in &io.writes {
+                if let Some(other) = writers.insert(*name, *i) {
+                    bail!(
+                        "{name} is written by both {} and {}",
+                        passes[other].name(),
+                        passes[*i].name()
+                    );
+                }
+            }
+        }
+
+        let dependencies = enabled
+            .iter()
+            .map(|(i, io)| {
+                io.reads
+                    .iter()
+                    .map(|name| {
+                        writers.get(name).copied().ok_or_else(|| {
+                            anyhow!("{} reads {name}, which nothing writes", passes[*i].name())
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut order = Vec::with_capacity(enabled.len());
+        while order.len() < enabled.len() {
+            let next = enabled
+                .iter()
+                .zip(&dependencies)
+                .find(|((i, _), deps)| {
+                    !order.contains(i) && deps.iter().all(|dep| order.contains(dep))
+                })
+                .map(|((i, _), _)| *i)
+                .ok_or_else(|| anyhow!("passes read resources from each other in a cycle"))?;
+            order.push(next);
+        }
+
+        self.allocate(gpu, enabled.iter().flat_map(|(_, io)| &io.writes));
+
+        self.passes = order
+            .iter()
+            .map(|&i| {
+                let (_, io) = enabled.iter().find(|(pass, _)| *pass == i).unwrap();
+                GraphPass {
+                    name: passes[i].name(),
+                    reads: io.reads.clone(),
+                    writes: io.writes.iter().map(|(name, _)| *name).collect(),
+                }
+            })
+            .collect();
+
+        ctx.resources = GraphResources::default();
+        for (name, (_, texture)) in &self.textures {
+            ctx.resources
+                .export(name, texture.create_view(&Default::default()));
+        }
+
+        Ok(order)
+    }
+
+    /// Passes of the last frame, in the order they rendered.
+    pub fn passes(&self) -> &[GraphPass] {
+        &self.passes
+    }
+
+    fn allocate<'a>(
+        &mut self,
+        gpu: &Gpu,
+        writes: impl Iterator<Item = &'a (&'static str, Option<TextureDesc>)>,
+    ) {
+        let size = gpu.viewport_size();
+        let mut textures = HashMap::new();
+
+        for (name, desc) in writes {
+            let Some(desc) = desc else { continue };
+
+            let texture = match self.textures.remove(name) {
+                Some((old_desc, texture)) if old_desc == *desc && texture.size() == size => texture,
+                _ => gpu.device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some(name),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: desc.format,
+                    usage: desc.usage,
+                    view_formats: &[],
+                }),
+            };
+
+            textures.insert(*name, (*desc, texture));
+        }
+
+        // Whatever no pass writes anymore is let go.
+        self.textures = textures;
+    }
+}
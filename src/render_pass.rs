@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use nalgebra as na;
+
+use crate::{
+    camera::GpuCamera,
+    light_scene::Light,
+    render_graph::{GraphResources, PassIo},
+    settings::AppSettings,
+    shadow_pass::SPLIT_COUNT,
+};
+
+/// What passes of a single frame read, and what they leave for passes running after them.
+pub struct FrameContext<'a> {
+    pub frame: wgpu::SurfaceTexture,
+    pub settings: &'a AppSettings,
+    pub camera: &'a GpuCamera,
+    pub projection: na::Matrix4<f32>,
+    /// Light casting shadows, a black one when the scene has no directional lights.
+    pub sun: Light,
+
+    pub shadows: Option<Arc<wgpu::BindGroup>>,
+    /// G-Buffer written by `GeometryPass`, laid out as `BindGroupLayouts::gbuffer`.
+    pub gbuffer: Option<wgpu::BindGroup>,
+    pub light_matrices: [na::Matrix4<f32>; SPLIT_COUNT],
+    /// Textures declared by passes, set up by `RenderGraph::prepare`.
+    pub resources: GraphResources,
+    /// Lit scene the background is drawn behind, along with whether it's HDR.
+    pub scene_color: Option<(wgpu::TextureView, bool)>,
+    /// `Exposure` measured from the HDR scene color, for postprocessing to apply.
+    pub exposure: Option<Arc<wgpu::Buffer>>,
+}
+
+impl<'a> FrameContext<'a> {
+    pub fn new(
+        frame: wgpu::SurfaceTexture,
+        settings: &'a AppSettings,
+        camera: &'a GpuCamera,
+        projection: na::Matrix4<f32>,
+        sun: Option<Light>,
+    ) -> Self {
+        let sun = sun.unwrap_or(Light::new_directional(
+            na::Vector3::zeros(),
+            na::Vector3::zeros(),
+            na::Vector3::zeros(),
+            na::Vector3::zeros(),
+        ));
+
+        Self {
+            frame,
+            settings,
+            camera,
+            projection,
+            sun,
+            shadows: None,
+            gbuffer: None,
+            light_matrices: [na::Matrix4::identity(); SPLIT_COUNT],
+            resources: GraphResources::default(),
+            scene_color: None,
+            exposure: None,
+        }
+    }
+
+    pub fn frame_view(&self) -> wgpu::TextureView {
+        self.frame.texture.create_view(&Default::default())
+    }
+}
+
+/// A step of rendering a frame. Frames are rendered by running a list of passes,
+/// each of them deciding whether it takes part from settings of the frame.
+/// `RenderGraph` orders them by textures they declare in `io`.
+///
+/// Passes are set up by their own constructors, as they need different things to start.
+/// They record and submit their own command buffers too: some of them write uniforms
+/// between draws, which only works with a submit in between.
+pub trait RenderPass {
+    /// Shown in crash reports and used to tell passes apart.
+    fn name(&self) -> &'static str;
+
+    fn enabled(&self, _ctx: &FrameContext) -> bool {
+        true
+    }
+
+    fn io(&self) -> PassIo {
+        PassIo::default()
+    }
+
+    /// Called with the new size of the surface, for passes with targets of its size.
+    fn resize(&mut self, _size: (u32, u32)) -> Result<()> {
+        Ok(())
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()>;
+}
@@ -1,31 +1,54 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::Range};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use nalgebra as na;
 
 type FMat4x4 = na::Matrix4<f32>;
 
 use crate::{
+    bounds::Bounds,
+    decal::{Decal, DECAL_STRIDE},
+    foliage::{FoliageInstance, FOLIAGE_INSTANCE_STRIDE},
     gpu::Gpu,
-    material::MaterialId,
-    mesh::{
-        Mesh, MeshVertexArrayType, PNTBUV_SLOTS, PNTBUV_STRIDE, PNUV_SLOTS, PNUV_STRIDE, PN_SLOTS,
-        PN_STRIDE,
-    },
+    material::{MaterialAtlas, MaterialId},
+    mesh::{Mesh, MeshVertexArrayType, PNTBUV_SLOTS, PNUV_SLOTS, PN_SLOTS},
+    picking::PickShape,
+    projection::Perspective,
+    upload::ChunkedUpload,
 };
 
 const MAX_INSTANCE_BUFFER_GROWTH: usize = 128;
+// Objects switch LODs once their screen size is this fraction of a threshold past it,
+// so ones sitting right at a threshold don't flicker between LODs as the camera moves.
+const LOD_HYSTERESIS: f32 = 0.1;
 
 struct ModelDescriptor {
     mesh_r: (usize, usize),
     local_material_r: Option<(usize, usize)>,
+    // Sorted from the most detailed one, used in place of `mesh_r` by objects at LOD 1 and up.
+    lods: Vec<ModelLod>,
 }
 
-pub const MODEL_INSTANCE_STRIDE: usize = std::mem::size_of::<FMat4x4>() * 2;
+struct ModelLod {
+    mesh_r: (usize, usize),
+    max_screen_size: f32,
+}
+
+impl ModelDescriptor {
+    fn lod_mesh_r(&self, lod: usize) -> (usize, usize) {
+        match lod {
+            0 => self.mesh_r,
+            lod => self.lods[lod - 1].mesh_r,
+        }
+    }
+}
 
-#[derive(Clone, Copy, Debug)]
+pub const MODEL_TRANSFORM_SIZE: usize = std::mem::size_of::<FMat4x4>() * 2;
+pub const MODEL_INSTANCE_STRIDE: usize = MODEL_TRANSFORM_SIZE + std::mem::size_of::<u32>();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum InstanceArrayType {
-    // Model = Mat4x4 model matrix + Mat4x4 inverse transpose model matrix
+    // Model = Mat4x4 model matrix + Mat4x4 inverse transpose model matrix + u32 material index
     Model,
 }
 
@@ -39,7 +62,11 @@ impl InstanceArrayType {
 
 #[derive(Default)]
 pub struct SceneStorage {
+    // Unique meshes only. Models refer to them through `mesh_refs`.
     meshes: Vec<Mesh>,
+    mesh_refs: Vec<usize>,
+    mesh_names: Vec<Option<String>>,
+    mesh_lookup: HashMap<u64, Vec<usize>>,
     instances: Vec<Instance>,
     local_materials: Vec<MaterialId>,
     model_descriptors: Vec<ModelDescriptor>,
@@ -49,6 +76,8 @@ pub struct SceneStorage {
 pub struct Scene {
     storage: SceneStorage,
     objects: Vec<SceneObject>,
+    foliage: Vec<FoliageInstance>,
+    decals: Vec<Decal>,
 }
 
 #[derive(Clone, Copy)]
@@ -76,6 +105,7 @@ impl Instance {
             PN_SLOTS + 5 => Float32x4,
             PN_SLOTS + 6 => Float32x4,
             PN_SLOTS + 7 => Float32x4,
+            PN_SLOTS + 8 => Uint32,
         ],
     };
 
@@ -91,6 +121,7 @@ impl Instance {
             PNUV_SLOTS + 5 => Float32x4,
             PNUV_SLOTS + 6 => Float32x4,
             PNUV_SLOTS + 7 => Float32x4,
+            PNUV_SLOTS + 8 => Uint32,
         ],
     };
 
@@ -106,6 +137,7 @@ impl Instance {
             PNTBUV_SLOTS + 5 => Float32x4,
             PNTBUV_SLOTS + 6 => Float32x4,
             PNTBUV_SLOTS + 7 => Float32x4,
+            PNTBUV_SLOTS + 8 => Uint32,
         ],
     };
 
@@ -121,6 +153,10 @@ impl Instance {
         self.model
     }
 
+    pub fn model_invt(&self) -> FMat4x4 {
+        self.model_invt
+    }
+
     pub fn set_model(&mut self, v: FMat4x4) {
         self.model = v;
         self.model_invt = v.try_inverse().unwrap().transpose();
@@ -134,21 +170,23 @@ impl Instance {
         target.extend(bytemuck::cast_slice(&[self.model, self.model_invt]));
     }
 
-    pub fn pn_model_instance_layout() -> wgpu::VertexBufferLayout<'static> {
-        Self::PN_MODEL_LAYOUT
-    }
-
-    pub fn pnuv_model_instance_layout() -> wgpu::VertexBufferLayout<'static> {
-        Self::PNUV_MODEL_LAYOUT
-    }
+    /// Buffers scene draws of `vertex_array_type` are made with, mesh vertices
+    /// followed by model instances.
+    pub fn draw_buffer_layouts(
+        vertex_array_type: MeshVertexArrayType,
+    ) -> [wgpu::VertexBufferLayout<'static>; 2] {
+        let instances = match vertex_array_type {
+            MeshVertexArrayType::PN => Self::PN_MODEL_LAYOUT,
+            MeshVertexArrayType::PNUV => Self::PNUV_MODEL_LAYOUT,
+            MeshVertexArrayType::PNTBUV => Self::PNTBUV_MODEL_LAYOUT,
+        };
 
-    pub fn pntbuv_model_instance_layout() -> wgpu::VertexBufferLayout<'static> {
-        Self::PNTBUV_MODEL_LAYOUT
+        [vertex_array_type.layout().buffer_layout(), instances]
     }
 }
 
 impl Scene {
-    pub fn load_model(&mut self, model_builder: SceneModelBuilder) -> SceneModel {
+    pub fn load_model(&mut self, model_builder: SceneModelBuilder) -> Result<SceneModel> {
         self.storage.load_model(model_builder)
     }
 
@@ -161,8 +199,12 @@ impl Scene {
         let object = SceneObject {
             instance_idx,
             material_idx: None,
+            material_overrides: HashMap::new(),
             mesh_instances_r: mesh_transforms_r,
             model_idx: model.0,
+            name: None,
+            visible: true,
+            lod: 0,
         };
 
         let object_idx = self.objects.len();
@@ -201,8 +243,12 @@ impl Scene {
         let object = SceneObject {
             instance_idx: transform_idx,
             material_idx: Some(material),
+            material_overrides: HashMap::new(),
             mesh_instances_r: mesh_transforms_r,
             model_idx: model.0,
+            name: None,
+            visible: true,
+            lod: 0,
         };
 
         let object_idx: usize = self.objects.len();
@@ -210,23 +256,154 @@ impl Scene {
 
         SceneObjectId(object_idx)
     }
+
+    /// Assigns fallback materials of `atlas` to meshes which have no material at all,
+    /// so they show up in the scene instead of failing to build it.
+    pub fn assign_fallback_materials(
+        &mut self,
+        gpu: &Gpu,
+        atlas: &mut MaterialAtlas,
+    ) -> Result<()> {
+        let missing: Vec<_> = self
+            .mesh_uses()
+            .filter(|mesh_use| mesh_use.material_id.is_none())
+            .map(|mesh_use| {
+                (
+                    mesh_use.object_id,
+                    mesh_use.slot,
+                    mesh_use.mesh.vertex_array_type(),
+                )
+            })
+            .collect();
+
+        for (object_id, slot, vertex_array_type) in missing {
+            let material_id = atlas.fallback_material(gpu, vertex_array_type)?;
+            self.objects[object_id.0]
+                .material_overrides
+                .insert(slot, material_id);
+        }
+
+        Ok(())
+    }
+
+    pub fn set_object_name(&mut self, scene_object_id: SceneObjectId, name: impl Into<String>) {
+        self.objects[scene_object_id.0].name = Some(name.into());
+    }
+
+    pub fn num_objects(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Base meshes of an object, each with the model matrix it's placed with.
+    pub fn object_meshes(
+        &self,
+        scene_object_id: SceneObjectId,
+    ) -> impl Iterator<Item = (&Mesh, FMat4x4)> {
+        let object = &self.objects[scene_object_id.0];
+        let (start, end) = self.storage.model_descriptors[object.model_idx].mesh_r;
+
+        (start..end)
+            .zip(object.mesh_instances_r.0..)
+            .map(|(mesh_ref, instance_idx)| {
+                (
+                    &self.storage.meshes[self.storage.mesh_refs[mesh_ref]],
+                    self.storage.instances[instance_idx].model(),
+                )
+            })
+    }
+
+    /// Plants drawn by `FoliagePass`, they aren't objects and can't be picked.
+    pub fn add_foliage(&mut self, plants: impl IntoIterator<Item = FoliageInstance>) {
+        self.foliage.extend(plants);
+    }
+
+    /// Decals are drawn by `DecalPass` over whatever objects are inside them.
+    pub fn add_decal(&mut self, decal: Decal) -> DecalId {
+        self.decals.push(decal);
+        DecalId(self.decals.len() - 1)
+    }
+
+    /// Meshes after deduplication.
+    pub fn unique_meshes(&self) -> &[Mesh] {
+        &self.storage.meshes
+    }
+
+    pub fn mesh_uses(&self) -> impl Iterator<Item = SceneMeshUse<'_>> {
+        self.objects
+            .iter()
+            .enumerate()
+            .flat_map(move |(object_idx, object)| {
+                let descriptor = &self.storage.model_descriptors[object.model_idx];
+                let mut material_r = descriptor
+                    .local_material_r
+                    .map(|(s, e)| s..e)
+                    .unwrap_or(0..0);
+
+                (descriptor.mesh_r.0..descriptor.mesh_r.1).enumerate().map(
+                    move |(slot, mesh_ref)| {
+                        let local_material = material_r
+                            .next()
+                            .map(|idx| self.storage.local_materials[idx]);
+
+                        SceneMeshUse {
+                            object_id: SceneObjectId(object_idx),
+                            slot,
+                            name: self.storage.mesh_names[mesh_ref].as_deref(),
+                            mesh: &self.storage.meshes[self.storage.mesh_refs[mesh_ref]],
+                            material_id: object
+                                .material_overrides
+                                .get(&slot)
+                                .copied()
+                                .or(local_material)
+                                .or(object.material_idx),
+                        }
+                    },
+                )
+            })
+    }
 }
 
 #[derive(Debug)]
 struct SceneObject {
     instance_idx: usize,
     material_idx: Option<MaterialId>,
+    // Keyed by mesh index local to the model, takes precedence over model and object materials.
+    material_overrides: HashMap<usize, MaterialId>,
     mesh_instances_r: (usize, usize),
     model_idx: usize,
+    name: Option<String>,
+    visible: bool,
+    // Index into LODs of the model, 0 for its base meshes.
+    lod: usize,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct SceneObjectId(usize);
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DecalId(usize);
+
+pub struct MeshSlot<'a> {
+    pub name: Option<&'a str>,
+    pub vertex_array_type: MeshVertexArrayType,
+    pub material_id: Option<MaterialId>,
+    pub overridden: bool,
+}
+
+/// Mesh of an object in a `Scene`, with the material it's going to be drawn with.
+pub struct SceneMeshUse<'a> {
+    pub object_id: SceneObjectId,
+    pub slot: usize,
+    pub name: Option<&'a str>,
+    pub mesh: &'a Mesh,
+    pub material_id: Option<MaterialId>,
+}
+
 #[derive(Default)]
 pub struct SceneModelBuilder {
     meshes: Vec<Mesh>,
     local_materials: Option<Vec<MaterialId>>,
+    lods: Vec<(Vec<Mesh>, f32)>,
 }
 
 impl SceneModelBuilder {
@@ -239,18 +416,58 @@ impl SceneModelBuilder {
         self.local_materials = Some(materials);
         self
     }
+
+    /// Meshes drawn instead of the base ones when the object takes up less than
+    /// `max_screen_size` of the viewport height. They replace base meshes slot by slot
+    /// and keep their materials, so they need to match them in number and vertex types.
+    pub fn with_lod(mut self, meshes: Vec<Mesh>, max_screen_size: f32) -> Self {
+        self.lods.push((meshes, max_screen_size));
+        self
+    }
 }
 
 #[derive(Clone, Copy)]
 pub struct SceneModel(usize);
 
 impl SceneStorage {
-    fn load_model(&mut self, builder: SceneModelBuilder) -> SceneModel {
-        let mesh_r = (self.meshes.len(), self.meshes.len() + builder.meshes.len());
-        for mesh in builder.meshes {
-            self.meshes.push(mesh);
+    fn load_model(&mut self, mut builder: SceneModelBuilder) -> Result<SceneModel> {
+        for (lod_meshes, max_screen_size) in &builder.lods {
+            if lod_meshes.len() != builder.meshes.len() {
+                bail!(
+                    "LOD below {} of the screen has {} meshes, the model has {}",
+                    max_screen_size,
+                    lod_meshes.len(),
+                    builder.meshes.len()
+                );
+            }
+
+            let mismatch = lod_meshes
+                .iter()
+                .zip(&builder.meshes)
+                .position(|(lod, base)| lod.vertex_array_type() != base.vertex_array_type());
+            if let Some(slot) = mismatch {
+                bail!(
+                    "LOD below {} of the screen has {:?} vertices in slot {}, the model has {:?}",
+                    max_screen_size,
+                    lod_meshes[slot].vertex_array_type(),
+                    slot,
+                    builder.meshes[slot].vertex_array_type()
+                );
+            }
         }
 
+        let mesh_r = self.load_meshes(builder.meshes);
+
+        builder.lods.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        let lods = builder
+            .lods
+            .into_iter()
+            .map(|(meshes, max_screen_size)| ModelLod {
+                mesh_r: self.load_meshes(meshes),
+                max_screen_size,
+            })
+            .collect();
+
         let mut local_material_r = None;
         if let Some(materials) = builder.local_materials {
             local_material_r = Some((
@@ -267,9 +484,38 @@ impl SceneStorage {
         self.model_descriptors.push(ModelDescriptor {
             mesh_r,
             local_material_r,
+            lods,
         });
 
-        SceneModel(model_idx)
+        Ok(SceneModel(model_idx))
+    }
+
+    fn load_meshes(&mut self, meshes: Vec<Mesh>) -> (usize, usize) {
+        let start = self.mesh_refs.len();
+        for mesh in meshes {
+            let mesh_idx = self.deduplicate_mesh(mesh);
+            self.mesh_refs.push(mesh_idx);
+        }
+
+        (start, self.mesh_refs.len())
+    }
+
+    // Identical meshes (e.g. the same prop loaded from separate model files)
+    // share a single copy of their vertex and index data.
+    fn deduplicate_mesh(&mut self, mesh: Mesh) -> usize {
+        self.mesh_names.push(mesh.name().map(str::to_owned));
+
+        let candidates = self.mesh_lookup.entry(mesh.content_hash()).or_default();
+        if let Some(&mesh_idx) = candidates
+            .iter()
+            .find(|&&mesh_idx| self.meshes[mesh_idx].same_contents(&mesh))
+        {
+            return mesh_idx;
+        }
+
+        candidates.push(self.meshes.len());
+        self.meshes.push(mesh);
+        self.meshes.len() - 1
     }
 }
 
@@ -282,26 +528,35 @@ struct VertexBuffers {
 // This representation works assuming that Features::FIRST_INSTANCE is present on the device.
 struct InstanceBuffers {
     model_ib: Option<wgpu::Buffer>,
+    // Instances as they were drawn last frame, read as storage for motion vectors.
+    previous_model_ib: Option<wgpu::Buffer>,
 }
 
 pub struct GpuScene {
     instances: Vec<Instance>,
     materials: Vec<MaterialId>,
     scene_objects: Vec<SceneObject>,
+    model_descriptors: Vec<ModelDescriptor>,
+    mesh_names: Vec<Option<String>>,
+    // Unique meshes, referred to by `mesh_refs` like in `SceneStorage`.
+    pick_shapes: Vec<PickShape>,
+    mesh_refs: Vec<usize>,
     vertex_buffers: VertexBuffers,
-    instance_buffers: InstanceBuffers,
     index_buffer: wgpu::Buffer,
-    draw_buffers: DrawBuffers,
     mesh_descriptors: Vec<MeshDescriptor>,
-    instance_offsets: Vec<Vec<wgpu::BufferAddress>>,
-    draw_calls: Vec<DrawCall>,
+    draws: SceneDraws,
+    // Storage buffer of `FoliageInstance`s, missing when the scene has no plants.
+    foliage_buffer: Option<wgpu::Buffer>,
+    num_plants: usize,
+    decals: Vec<Decal>,
+    // Storage buffer of `decals`, missing when the scene has none.
+    decal_buffer: Option<wgpu::Buffer>,
 }
 
 #[derive(Debug)]
 pub struct DrawCall {
     pub indexed: bool,
     pub draw_buffer_offset: wgpu::BufferAddress,
-    pub material_id: MaterialId,
     pub vertex_array_type: MeshVertexArrayType,
     pub instance_type: InstanceArrayType,
 }
@@ -313,109 +568,778 @@ struct DrawBuffers {
     non_indexed_buffer_count: usize,
 }
 
+// Bytes taken in vertex buffers of every type, and indices in the index buffer.
+#[derive(Clone, Copy, Default)]
+struct BankSizes {
+    pntbuv: usize,
+    pnuv: usize,
+    pn: usize,
+    indices: usize,
+}
+
+impl BankSizes {
+    fn vertex_bank(&mut self, vertex_type: MeshVertexArrayType) -> &mut usize {
+        match vertex_type {
+            MeshVertexArrayType::PN => &mut self.pn,
+            MeshVertexArrayType::PNUV => &mut self.pnuv,
+            MeshVertexArrayType::PNTBUV => &mut self.pntbuv,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
 struct MeshDescriptor {
     vertex_array_type: MeshVertexArrayType,
     mesh_bank_vertex_no: usize,
     num_vertices: usize,
     index_buffer_index_no: Option<usize>,
     num_indices: Option<usize>,
+    bounds: Option<Bounds>,
 }
 
 impl GpuScene {
     pub fn new(gpu: &Gpu, scene: Scene) -> Result<Self> {
-        let mut index_buffer_contents = vec![];
-        let mut mesh_descriptors = Vec::with_capacity(scene.storage.meshes.len());
-
-        let mut pnuv_vertices = vec![];
-        let mut pn_vertices = vec![];
-        let mut pntbuv_vertices = vec![];
-
-        for mesh in scene.storage.meshes.iter() {
-            let mesh_bank = match mesh.vertex_array_type() {
-                MeshVertexArrayType::PN => &mut pn_vertices,
-                MeshVertexArrayType::PNUV => &mut pnuv_vertices,
-                MeshVertexArrayType::PNTBUV => &mut pntbuv_vertices,
-            };
+        let mut sizes = BankSizes::default();
+        let mesh_descriptors = Self::place_meshes(&scene.storage.meshes, &mut sizes);
 
-            let vertex_stride = match mesh.vertex_array_type() {
-                MeshVertexArrayType::PN => PN_STRIDE,
-                MeshVertexArrayType::PNUV => PNUV_STRIDE,
-                MeshVertexArrayType::PNTBUV => PNTBUV_STRIDE,
-            };
+        let index_buffer = Self::create_index_buffer(gpu, sizes.indices);
+        let vertex_buffers = VertexBuffers {
+            pntbuv_buffer: Self::create_vertex_buffer(gpu, "PNTBUV Vertex Buffer", sizes.pntbuv),
+            pnuv_buffer: Self::create_vertex_buffer(gpu, "PNUV Vertex Buffer", sizes.pnuv),
+            pn_buffer: Self::create_vertex_buffer(gpu, "PN Vertex Buffer", sizes.pn),
+        };
+        Self::upload_meshes(
+            gpu,
+            &scene.storage.meshes,
+            &mesh_descriptors,
+            &vertex_buffers,
+            &index_buffer,
+        );
 
-            let mesh_bank_offset = mesh_bank.len();
-            let num_vertices = mesh.num_vertices();
+        let pick_shapes = scene.storage.meshes.iter().map(PickShape::new).collect();
+
+        let mesh_descriptors = scene
+            .storage
+            .mesh_refs
+            .iter()
+            .map(|&mesh_idx| mesh_descriptors[mesh_idx])
+            .collect::<Vec<_>>();
+
+        let draws = SceneDraws::new(
+            gpu,
+            &scene.storage.instances,
+            &scene.objects,
+            &scene.storage.model_descriptors,
+            &scene.storage.local_materials,
+            &mesh_descriptors,
+        )?;
+
+        let foliage_buffer = (!scene.foliage.is_empty()).then(|| {
+            use wgpu::util::DeviceExt;
+
+            let mut contents = Vec::with_capacity(scene.foliage.len() * FOLIAGE_INSTANCE_STRIDE);
+            for plant in scene.foliage.iter() {
+                plant.copy_to(&mut contents);
+            }
 
-            mesh.copy_to_mesh_bank(mesh_bank);
+            gpu.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Foliage Buffer"),
+                    contents: &contents,
+                    usage: wgpu::BufferUsages::STORAGE,
+                })
+        });
 
-            let num_indices = mesh.num_indices();
-            let mut index_buffer_offset = None;
-            if mesh.is_indexed() {
-                index_buffer_offset = Some(index_buffer_contents.len());
-                mesh.copy_to_index_buffer(&mut index_buffer_contents);
+        let decal_buffer = (!scene.decals.is_empty()).then(|| {
+            use wgpu::util::DeviceExt;
+
+            let mut contents = Vec::with_capacity(scene.decals.len() * DECAL_STRIDE);
+            for decal in scene.decals.iter() {
+                decal.copy_to(&mut contents);
             }
 
-            mesh_descriptors.push(MeshDescriptor {
-                vertex_array_type: mesh.vertex_array_type(),
-                mesh_bank_vertex_no: mesh_bank_offset / vertex_stride,
-                num_vertices,
-                index_buffer_index_no: index_buffer_offset,
-                num_indices,
-            });
+            gpu.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Decal Buffer"),
+                    contents: &contents,
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                })
+        });
+
+        Ok(Self {
+            decals: scene.decals,
+            decal_buffer,
+            foliage_buffer,
+            num_plants: scene.foliage.len(),
+            scene_objects: scene.objects,
+            instances: scene.storage.instances,
+            materials: scene.storage.local_materials,
+            model_descriptors: scene.storage.model_descriptors,
+            mesh_names: scene.storage.mesh_names,
+            pick_shapes,
+            mesh_refs: scene.storage.mesh_refs,
+            vertex_buffers,
+            index_buffer,
+            mesh_descriptors,
+            draws,
+        })
+    }
+
+    // Places meshes after what `sizes` already holds, growing them by what the meshes take.
+    fn place_meshes(meshes: &[Mesh], sizes: &mut BankSizes) -> Vec<MeshDescriptor> {
+        meshes
+            .iter()
+            .map(|mesh| {
+                let mesh_bank_size = sizes.vertex_bank(mesh.vertex_array_type());
+                let mesh_bank_offset = *mesh_bank_size;
+                let num_vertices = mesh.num_vertices();
+                *mesh_bank_size += num_vertices * mesh.vertex_stride();
+
+                let num_indices = mesh.num_indices();
+                let mut index_buffer_offset = None;
+                if let Some(num_indices) = num_indices {
+                    index_buffer_offset = Some(sizes.indices);
+                    sizes.indices += num_indices;
+                }
+
+                MeshDescriptor {
+                    vertex_array_type: mesh.vertex_array_type(),
+                    mesh_bank_vertex_no: mesh_bank_offset / mesh.vertex_stride(),
+                    num_vertices,
+                    index_buffer_index_no: index_buffer_offset,
+                    num_indices,
+                    bounds: mesh.bounds(),
+                }
+            })
+            .collect()
+    }
+
+    // Buffers are copied over to bigger ones when models are replaced, hence `COPY_SRC`.
+    fn create_vertex_buffer(gpu: &Gpu, label: &str, size: usize) -> Option<wgpu::Buffer> {
+        (size > 0).then(|| {
+            gpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: size as u64,
+                usage: wgpu::BufferUsages::VERTEX
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        })
+    }
+
+    fn create_index_buffer(gpu: &Gpu, index_count: usize) -> wgpu::Buffer {
+        gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("IndexBuffer"),
+            size: (index_count * std::mem::size_of::<u32>()) as u64,
+            usage: wgpu::BufferUsages::INDEX
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn upload_meshes(
+        gpu: &Gpu,
+        meshes: &[Mesh],
+        mesh_descriptors: &[MeshDescriptor],
+        vertex_buffers: &VertexBuffers,
+        index_buffer: &wgpu::Buffer,
+    ) {
+        // Vertex data is interleaved straight into staging memory, a chunk at a time,
+        // instead of building whole mesh banks on the CPU first.
+        let mut upload = ChunkedUpload::new(gpu);
+        for (mesh, descriptor) in meshes.iter().zip(mesh_descriptors.iter()) {
+            let mesh_bank = match descriptor.vertex_array_type {
+                MeshVertexArrayType::PN => vertex_buffers.pn_buffer.as_ref(),
+                MeshVertexArrayType::PNUV => vertex_buffers.pnuv_buffer.as_ref(),
+                MeshVertexArrayType::PNTBUV => vertex_buffers.pntbuv_buffer.as_ref(),
+            };
+
+            let stride = mesh.vertex_stride();
+            let vertices_per_chunk = (ChunkedUpload::chunk_size() as usize / stride).max(1);
+            if let Some(mesh_bank) = mesh_bank {
+                for first_vertex in (0..descriptor.num_vertices).step_by(vertices_per_chunk) {
+                    let count = vertices_per_chunk.min(descriptor.num_vertices - first_vertex);
+
+                    upload.write(
+                        mesh_bank,
+                        ((descriptor.mesh_bank_vertex_no + first_vertex) * stride) as u64,
+                        (count * stride) as u64,
+                        |target| mesh.write_vertices(first_vertex, target),
+                    );
+                }
+            }
+
+            if let Some((indices, offset)) = mesh.indices().zip(descriptor.index_buffer_index_no) {
+                let index_size = std::mem::size_of::<u32>();
+                let indices_per_chunk = ChunkedUpload::chunk_size() as usize / index_size;
+
+                for (chunk_no, chunk) in indices.chunks(indices_per_chunk).enumerate() {
+                    upload.write(
+                        index_buffer,
+                        ((offset + chunk_no * indices_per_chunk) * index_size) as u64,
+                        std::mem::size_of_val(chunk) as u64,
+                        |target| target.copy_from_slice(bytemuck::cast_slice(chunk)),
+                    );
+                }
+            }
         }
+        upload.flush();
+    }
+
+    /// Replaces meshes and local materials of `model` with the ones of `builder` for all
+    /// objects using it, e.g. once a model drawn with a placeholder finished loading.
+    /// Objects keep their transforms, visibility and material overrides of slots the new
+    /// model still has. Slots left without a material get a fallback one from `atlas`.
+    ///
+    /// New meshes are appended to vertex and index buffers, which are grown on the GPU.
+    /// The old meshes stay in them until the scene is built again.
+    pub fn replace_model(
+        &mut self,
+        gpu: &Gpu,
+        atlas: &mut MaterialAtlas,
+        model: SceneModel,
+        builder: SceneModelBuilder,
+    ) -> Result<()> {
+        let mut storage = SceneStorage::default();
+        let SceneModel(loaded_idx) = storage.load_model(builder)?;
+        let mut descriptor = storage.model_descriptors.swap_remove(loaded_idx);
+
+        let buffer_size = |buffer: &Option<wgpu::Buffer>| buffer.as_ref().map_or(0, |b| b.size());
+        let old_sizes = BankSizes {
+            pntbuv: buffer_size(&self.vertex_buffers.pntbuv_buffer) as usize,
+            pnuv: buffer_size(&self.vertex_buffers.pnuv_buffer) as usize,
+            pn: buffer_size(&self.vertex_buffers.pn_buffer) as usize,
+            indices: self.index_buffer.size() as usize / std::mem::size_of::<u32>(),
+        };
+        let mut sizes = old_sizes;
+        let mesh_descriptors = Self::place_meshes(&storage.meshes, &mut sizes);
 
-        let index_buffer = gpu
+        let mut encoder = gpu
             .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("IndexBuffer"),
-                contents: bytemuck::cast_slice(&index_buffer_contents),
-                usage: wgpu::BufferUsages::INDEX,
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("GpuScene::GrowBuffers"),
             });
+        let mut grow = |buffer: &mut Option<wgpu::Buffer>, label, old_size, size| {
+            if size == old_size {
+                return;
+            }
 
-        let mut pnuv_buffer = None;
-        let mut pn_buffer = None;
-        let mut pntbuv_buffer = None;
-
-        use wgpu::util::DeviceExt;
-        if !pnuv_vertices.is_empty() {
-            pnuv_buffer = Some(
-                gpu.device
-                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                        label: Some("PNUV Vertex Buffer"),
-                        contents: bytemuck::cast_slice(&pnuv_vertices),
-                        usage: wgpu::BufferUsages::VERTEX,
-                    }),
+            let grown = Self::create_vertex_buffer(gpu, label, size);
+            if let (Some(old), Some(grown)) = (buffer.as_ref(), grown.as_ref()) {
+                encoder.copy_buffer_to_buffer(old, 0, grown, 0, old.size());
+            }
+            *buffer = grown;
+        };
+        let VertexBuffers {
+            pntbuv_buffer,
+            pnuv_buffer,
+            pn_buffer,
+        } = &mut self.vertex_buffers;
+        grow(
+            pntbuv_buffer,
+            "PNTBUV Vertex Buffer",
+            old_sizes.pntbuv,
+            sizes.pntbuv,
+        );
+        grow(
+            pnuv_buffer,
+            "PNUV Vertex Buffer",
+            old_sizes.pnuv,
+            sizes.pnuv,
+        );
+        grow(pn_buffer, "PN Vertex Buffer", old_sizes.pn, sizes.pn);
+
+        if sizes.indices > old_sizes.indices {
+            let index_buffer = Self::create_index_buffer(gpu, sizes.indices);
+            encoder.copy_buffer_to_buffer(
+                &self.index_buffer,
+                0,
+                &index_buffer,
+                0,
+                self.index_buffer.size(),
             );
+            self.index_buffer = index_buffer;
+        }
+        gpu.queue.submit(Some(encoder.finish()));
+
+        Self::upload_meshes(
+            gpu,
+            &storage.meshes,
+            &mesh_descriptors,
+            &self.vertex_buffers,
+            &self.index_buffer,
+        );
+
+        // Indices of the loaded model are shifted past what the scene already has.
+        let shift = |(start, end): (usize, usize), offset: usize| (start + offset, end + offset);
+        let unique_offset = self.pick_shapes.len();
+        let ref_offset = self.mesh_refs.len();
+        let material_offset = self.materials.len();
+
+        self.pick_shapes
+            .extend(storage.meshes.iter().map(PickShape::new));
+        for (&mesh_idx, name) in storage.mesh_refs.iter().zip(storage.mesh_names) {
+            self.mesh_refs.push(unique_offset + mesh_idx);
+            self.mesh_descriptors.push(mesh_descriptors[mesh_idx]);
+            self.mesh_names.push(name);
+        }
+        self.materials.extend(storage.local_materials);
+
+        descriptor.mesh_r = shift(descriptor.mesh_r, ref_offset);
+        descriptor.local_material_r = descriptor
+            .local_material_r
+            .map(|material_r| shift(material_r, material_offset));
+        for lod in &mut descriptor.lods {
+            lod.mesh_r = shift(lod.mesh_r, ref_offset);
+        }
+        let mesh_count = descriptor.mesh_r.1 - descriptor.mesh_r.0;
+        self.model_descriptors[model.0] = descriptor;
+
+        let affected: Vec<_> = self
+            .object_ids()
+            .filter(|id| self.scene_objects[id.0].model_idx == model.0)
+            .collect();
+        for &id in &affected {
+            let object = &mut self.scene_objects[id.0];
+            let instance = self.instances[object.instance_idx];
+
+            let start = self.instances.len();
+            self.instances
+                .extend(std::iter::repeat_n(instance, mesh_count));
+            object.mesh_instances_r = (start, self.instances.len());
+            object.lod = 0;
+            // Fallbacks stood in for materials the placeholder had no way of knowing.
+            object
+                .material_overrides
+                .retain(|&slot, material| slot < mesh_count && !atlas.is_fallback(*material));
         }
 
-        if !pn_vertices.is_empty() {
-            pn_buffer = Some(
-                gpu.device
-                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                        label: Some("PN Vertex Buffer"),
-                        contents: bytemuck::cast_slice(&pn_vertices),
-                        usage: wgpu::BufferUsages::VERTEX,
-                    }),
+        for id in affected {
+            let missing: Vec<_> = self
+                .mesh_slots(id)
+                .into_iter()
+                .enumerate()
+                .filter(|(_, slot)| slot.material_id.is_none())
+                .map(|(slot_idx, slot)| (slot_idx, slot.vertex_array_type))
+                .collect();
+
+            for (slot, vertex_array_type) in missing {
+                let material_id = atlas.fallback_material(gpu, vertex_array_type)?;
+                self.scene_objects[id.0]
+                    .material_overrides
+                    .insert(slot, material_id);
+            }
+        }
+
+        self.rebuild_draws(gpu)
+    }
+
+    pub fn instance_buffer_by_type(&self, instance_type: InstanceArrayType) -> &wgpu::Buffer {
+        match instance_type {
+            InstanceArrayType::Model => self.draws.instance_buffers.model_ib.as_ref().unwrap(),
+        }
+    }
+
+    /// Instances of the previous frame, in the layout of `InstanceArrayType::Model`.
+    pub fn previous_instance_buffer(&self) -> Option<&wgpu::Buffer> {
+        self.draws.instance_buffers.previous_model_ib.as_ref()
+    }
+
+    /// Records a copy of the current instances into the previous frame ones,
+    /// once everything reading the previous transforms is done with them.
+    pub fn copy_to_previous_instances(&self, encoder: &mut wgpu::CommandEncoder) {
+        let InstanceBuffers {
+            model_ib: Some(model_ib),
+            previous_model_ib: Some(previous_model_ib),
+        } = &self.draws.instance_buffers
+        else {
+            return;
+        };
+
+        encoder.copy_buffer_to_buffer(model_ib, 0, previous_model_ib, 0, model_ib.size());
+    }
+
+    pub fn vertex_buffer_by_type(&self, vertex_type: MeshVertexArrayType) -> &wgpu::Buffer {
+        match vertex_type {
+            MeshVertexArrayType::PN => self.vertex_buffers.pn_buffer.as_ref().unwrap(),
+            MeshVertexArrayType::PNUV => self.vertex_buffers.pnuv_buffer.as_ref().unwrap(),
+            MeshVertexArrayType::PNTBUV => self.vertex_buffers.pntbuv_buffer.as_ref().unwrap(),
+        }
+    }
+
+    pub fn update_instance<F>(&mut self, gpu: &Gpu, scene_object_id: SceneObjectId, updater: F)
+    where
+        F: Fn(&mut Instance),
+    {
+        let object = &self.scene_objects[scene_object_id.0];
+
+        let instance_idx = object.instance_idx;
+        updater(&mut self.instances[instance_idx]);
+
+        // Meshes get copies of the object instance, kept in sync for rebuilds of draws.
+        let object_instance = self.instances[instance_idx];
+        self.instances[object.mesh_instances_r.0..object.mesh_instances_r.1].fill(object_instance);
+
+        // Only transforms are written, material indices following them stay as they are.
+        let mut update = Vec::new();
+        self.instances[instance_idx].copy_to(&mut update);
+
+        // Hidden objects have no place in the instance buffer.
+        for offset in &self.draws.instance_offsets[scene_object_id.0] {
+            if *offset == u64::MAX {
+                continue;
+            }
+
+            gpu.upload_buffer(
+                self.draws.instance_buffers.model_ib.as_ref().unwrap(),
+                *offset,
+                &update,
             );
         }
+    }
 
-        if !pntbuv_vertices.is_empty() {
-            pntbuv_buffer = Some(gpu.device.create_buffer_init(
-                &wgpu::util::BufferInitDescriptor {
-                    label: Some("PNTBUV Vertex Buffer"),
-                    contents: bytemuck::cast_slice(&pntbuv_vertices),
-                    usage: wgpu::BufferUsages::VERTEX,
-                },
-            ));
+    pub fn object_ids(&self) -> impl Iterator<Item = SceneObjectId> {
+        (0..self.scene_objects.len()).map(SceneObjectId)
+    }
+
+    pub fn object_name(&self, scene_object_id: SceneObjectId) -> Option<&str> {
+        self.scene_objects[scene_object_id.0].name.as_deref()
+    }
+
+    pub fn object_instance(&self, scene_object_id: SceneObjectId) -> &Instance {
+        &self.instances[self.scene_objects[scene_object_id.0].instance_idx]
+    }
+
+    /// Material the object was added with, if any.
+    pub fn object_material(&self, scene_object_id: SceneObjectId) -> Option<MaterialId> {
+        self.scene_objects[scene_object_id.0].material_idx
+    }
+
+    /// Shapes of the object's meshes, in model space.
+    pub fn pick_shapes(&self, scene_object_id: SceneObjectId) -> impl Iterator<Item = &PickShape> {
+        let object = &self.scene_objects[scene_object_id.0];
+        let (start, end) = self.model_descriptors[object.model_idx].mesh_r;
+
+        self.mesh_refs[start..end]
+            .iter()
+            .map(|&mesh_idx| &self.pick_shapes[mesh_idx])
+    }
+
+    /// Vertex types of the object's meshes and ranges of vertices they take
+    /// in vertex buffers of their type.
+    pub fn mesh_vertex_ranges(
+        &self,
+        scene_object_id: SceneObjectId,
+    ) -> impl Iterator<Item = (MeshVertexArrayType, Range<u32>)> + '_ {
+        let object = &self.scene_objects[scene_object_id.0];
+        let (start, end) = self.model_descriptors[object.model_idx].mesh_r;
+
+        self.mesh_descriptors[start..end].iter().map(|descriptor| {
+            let first = descriptor.mesh_bank_vertex_no as u32;
+            (
+                descriptor.vertex_array_type,
+                first..first + descriptor.num_vertices as u32,
+            )
+        })
+    }
+
+    /// World space bounds of all meshes of the object together.
+    pub fn object_bounds(&self, scene_object_id: SceneObjectId) -> Option<Bounds> {
+        let object = &self.scene_objects[scene_object_id.0];
+        let (start, end) = self.model_descriptors[object.model_idx].mesh_r;
+        let model = self.object_instance(scene_object_id).model();
+
+        self.mesh_descriptors[start..end]
+            .iter()
+            .filter_map(|descriptor| descriptor.bounds)
+            .map(|bounds| bounds.transformed(&model))
+            .reduce(|a, b| a.union(&b))
+    }
+
+    /// Picks LODs of objects by how much of the viewport height their bounding spheres
+    /// take up when seen from `eye`. Instances of objects changing their LOD are moved
+    /// to draws of the new meshes, the rest of the draws stays as it is.
+    pub fn update_lods(
+        &mut self,
+        gpu: &Gpu,
+        eye: &na::Point3<f32>,
+        perspective: &Perspective,
+    ) -> Result<()> {
+        let half_fov_tan = (perspective.fovy * 0.5).tan();
+
+        let changed: Vec<_> = self
+            .object_ids()
+            .filter_map(|id| {
+                let object = &self.scene_objects[id.0];
+                let lods = &self.model_descriptors[object.model_idx].lods;
+                if lods.is_empty() {
+                    return None;
+                }
+
+                let sphere = self.object_bounds(id)?.sphere;
+                let distance = na::distance(eye, &sphere.center).max(f32::EPSILON);
+                let screen_size = sphere.radius / (distance * half_fov_tan);
+
+                let lod_at = |scale: f32| {
+                    lods.iter()
+                        .take_while(|lod| screen_size < lod.max_screen_size * scale)
+                        .count()
+                };
+                // The current LOD is kept anywhere between thresholds moved both ways.
+                let lod = object
+                    .lod
+                    .clamp(lod_at(1.0 - LOD_HYSTERESIS), lod_at(1.0 + LOD_HYSTERESIS));
+
+                (lod != object.lod).then_some((id, lod))
+            })
+            .collect();
+
+        let mut rebuild = false;
+        for (id, lod) in changed {
+            let object = &mut self.scene_objects[id.0];
+            let descriptor = &self.model_descriptors[object.model_idx];
+            let (from, to) = (
+                descriptor.lod_mesh_r(object.lod),
+                descriptor.lod_mesh_r(lod),
+            );
+            object.lod = lod;
+
+            // Hidden objects have no instances to move.
+            if object.visible && !rebuild {
+                rebuild = !self
+                    .draws
+                    .move_instances(gpu, &self.instances, id.0, from, to);
+            }
         }
 
-        let vertex_buffers = VertexBuffers {
+        if rebuild {
+            self.rebuild_draws(gpu)?;
+        }
+        Ok(())
+    }
+
+    pub fn is_visible(&self, scene_object_id: SceneObjectId) -> bool {
+        self.scene_objects[scene_object_id.0].visible
+    }
+
+    /// Hidden objects are left out of draws, which get rebuilt like after material overrides.
+    pub fn set_visible(
+        &mut self,
+        gpu: &Gpu,
+        scene_object_id: SceneObjectId,
+        visible: bool,
+    ) -> Result<()> {
+        self.scene_objects[scene_object_id.0].visible = visible;
+        self.rebuild_draws(gpu)
+    }
+
+    /// Material slots of the object's model, one per mesh, in the order they were loaded.
+    pub fn mesh_slots(&self, scene_object_id: SceneObjectId) -> Vec<MeshSlot<'_>> {
+        let object = &self.scene_objects[scene_object_id.0];
+        let descriptor = &self.model_descriptors[object.model_idx];
+
+        let mut material_r = descriptor
+            .local_material_r
+            .map(|(s, e)| s..e)
+            .unwrap_or(0..0);
+
+        (descriptor.mesh_r.0..descriptor.mesh_r.1)
+            .enumerate()
+            .map(|(slot, mesh_idx)| {
+                let local_material = material_r.next().map(|idx| self.materials[idx]);
+                let overridden = object.material_overrides.get(&slot).copied();
+
+                MeshSlot {
+                    name: self.mesh_names[mesh_idx].as_deref(),
+                    vertex_array_type: self.mesh_descriptors[mesh_idx].vertex_array_type,
+                    material_id: overridden.or(local_material).or(object.material_idx),
+                    overridden: overridden.is_some(),
+                }
+            })
+            .collect()
+    }
+
+    /// Passing `None` restores the material the slot had when the scene was built.
+    /// Draw calls are regrouped by material, so instance and draw buffers get rebuilt.
+    pub fn override_material(
+        &mut self,
+        gpu: &Gpu,
+        scene_object_id: SceneObjectId,
+        slot: usize,
+        material_id: Option<MaterialId>,
+    ) -> Result<()> {
+        let overrides = &mut self.scene_objects[scene_object_id.0].material_overrides;
+
+        match material_id {
+            Some(material_id) => overrides.insert(slot, material_id),
+            None => overrides.remove(&slot),
+        };
+
+        self.rebuild_draws(gpu)
+    }
+
+    fn rebuild_draws(&mut self, gpu: &Gpu) -> Result<()> {
+        self.draws = SceneDraws::new(
+            gpu,
+            &self.instances,
+            &self.scene_objects,
+            &self.model_descriptors,
+            &self.materials,
+            &self.mesh_descriptors,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn index_buffer(&self) -> &wgpu::Buffer {
+        &self.index_buffer
+    }
+
+    /// Plants of the scene along with their count, if there are any.
+    pub fn foliage(&self) -> Option<(&wgpu::Buffer, usize)> {
+        self.foliage_buffer
+            .as_ref()
+            .map(|buffer| (buffer, self.num_plants))
+    }
+
+    /// Decals of the scene along with their count, if there are any.
+    pub fn decals(&self) -> Option<(&wgpu::Buffer, usize)> {
+        self.decal_buffer
+            .as_ref()
+            .map(|buffer| (buffer, self.decals.len()))
+    }
+
+    pub fn decal_ids(&self) -> impl Iterator<Item = DecalId> {
+        (0..self.decals.len()).map(DecalId)
+    }
+
+    pub fn decal(&self, decal_id: DecalId) -> &Decal {
+        &self.decals[decal_id.0]
+    }
+
+    pub fn update_decal<F>(&mut self, gpu: &Gpu, decal_id: DecalId, updater: F)
+    where
+        F: Fn(&mut Decal),
+    {
+        let decal = &mut self.decals[decal_id.0];
+        updater(decal);
+
+        let mut update = Vec::with_capacity(DECAL_STRIDE);
+        decal.copy_to(&mut update);
+        gpu.upload_buffer(
+            self.decal_buffer.as_ref().unwrap(),
+            (decal_id.0 * DECAL_STRIDE) as u64,
+            &update,
+        );
+    }
+
+    /// Draw calls ordered by vertex array type, and indexed ones after the rest of the same type.
+    pub fn draw_calls(&self) -> &[DrawCall] {
+        &self.draws.draw_calls
+    }
+
+    /// Runs of draw calls sharing vertex and instance array types and whether they're indexed, so
+    /// passes set pipelines and buffers once for every run rather than for every draw.
+    pub fn draw_batches(&self) -> impl Iterator<Item = &[DrawCall]> {
+        self.draws.draw_calls.chunk_by(|a, b| {
+            a.vertex_array_type == b.vertex_array_type
+                && a.instance_type == b.instance_type
+                && a.indexed == b.indexed
+        })
+    }
+
+    pub fn indexed_draw_buffer(&self) -> &wgpu::Buffer {
+        self.draws.draw_buffers.indexed_buffer.as_ref().unwrap()
+    }
+
+    /// Instances drawn by all draw calls together.
+    pub fn num_instances(&self) -> usize {
+        self.draws.num_instances
+    }
+
+    /// Size of all GPU buffers of the scene, including space reserved for growth.
+    pub fn buffer_size(&self) -> wgpu::BufferAddress {
+        let VertexBuffers {
             pntbuv_buffer,
             pnuv_buffer,
             pn_buffer,
-        };
+        } = &self.vertex_buffers;
+        let DrawBuffers {
+            indexed_buffer,
+            non_indexed_buffer,
+            ..
+        } = &self.draws.draw_buffers;
+
+        [
+            pntbuv_buffer,
+            pnuv_buffer,
+            pn_buffer,
+            &self.draws.instance_buffers.model_ib,
+            &self.draws.instance_buffers.previous_model_ib,
+            indexed_buffer,
+            non_indexed_buffer,
+        ]
+        .into_iter()
+        .flatten()
+        .map(wgpu::Buffer::size)
+        .sum::<wgpu::BufferAddress>()
+            + self.index_buffer.size()
+    }
+
+    pub fn non_indexed_draw_buffer(&self) -> &wgpu::Buffer {
+        self.draws.draw_buffers.non_indexed_buffer.as_ref().unwrap()
+    }
+}
+
+// Instance of a scene object's mesh, drawn with the other instances of the mesh.
+#[derive(Clone, Copy)]
+struct BankInstance {
+    material_idx: MaterialId,
+    scene_object_id: usize,
+    slot: usize,
+    // Into instances of the scene, the copy of the object instance kept for the slot.
+    instance_idx: usize,
+}
+
+impl BankInstance {
+    fn copy_to(&self, instances: &[Instance], target: &mut Vec<u8>) {
+        instances[self.instance_idx].copy_to(target);
+        target.extend(bytemuck::bytes_of(&self.material_idx.index()));
+    }
+}
+
+// Instances of a mesh, taking `capacity` instances of the instance buffer from `first_instance` on.
+// Only `instances` are drawn, the rest is room for objects switching to the mesh's LOD.
+struct InstanceBank {
+    draw_idx: usize,
+    first_instance: usize,
+    capacity: usize,
+    instances: Vec<BankInstance>,
+}
+
+struct SceneDraws {
+    instance_buffers: InstanceBuffers,
+    draw_buffers: DrawBuffers,
+    instance_offsets: Vec<Vec<wgpu::BufferAddress>>,
+    // Keyed by mesh reference, the same as `GpuScene::mesh_descriptors`.
+    banks: HashMap<usize, InstanceBank>,
+    draw_calls: Vec<DrawCall>,
+    num_instances: usize,
+}
 
-        /* IDEA: Let's keep the same mesh/bind group combos together so we can maximize instancing.
+impl SceneDraws {
+    fn new(
+        gpu: &Gpu,
+        instances: &[Instance],
+        scene_objects: &[SceneObject],
+        model_descriptors: &[ModelDescriptor],
+        local_materials: &[MaterialId],
+        mesh_descriptors: &[MeshDescriptor],
+    ) -> Result<Self> {
+        /* IDEA: Let's keep the same meshes together so we can maximize instancing.
+          Materials are picked per instance, so they don't split instances of a mesh.
           Instance buffer needs to grow (we potentially want to conditionally add / remove objects dynamically)
           so we allocate MAX_INSTANCE_BUFFER_GROWTH more.
           The same with draw buffers - newly added objects won't benefit from instancing.
@@ -424,94 +1348,172 @@ impl GpuScene {
            Reconstruction of all draw buffers will be needed every frame.
            Also keeping track of SceneObjectId <-> InstanceBuffer ranges is going to be required then, but YAGNI.
         */
-        use std::collections::BTreeMap;
-        let mut instance_banks: BTreeMap<(usize, MaterialId), Vec<u8>> = BTreeMap::new();
-        let mut instance_offsets = vec![vec![]; scene.objects.len()];
-        let mut instance_offsets_per_bank: HashMap<(usize, MaterialId), Vec<(usize, usize, u64)>> =
-            HashMap::new();
-
-        for (scene_object_id, scene_object) in scene.objects.iter().enumerate() {
-            let descriptor = &scene.storage.model_descriptors[scene_object.model_idx];
+        // Instances of every mesh along with their material, scene object and its mesh slot.
+        let mut instance_banks: HashMap<usize, Vec<BankInstance>> = HashMap::new();
+        // Objects with LODs can end up drawing any of them, so every LOD has room for all of them
+        // and objects switching LODs are moved between banks without rebuilding the rest.
+        let mut reserved: HashMap<usize, usize> = HashMap::new();
+        let mut instance_offsets = vec![vec![]; scene_objects.len()];
+
+        for (scene_object_id, scene_object) in scene_objects.iter().enumerate() {
+            let descriptor = &model_descriptors[scene_object.model_idx];
             instance_offsets[scene_object_id]
                 .resize(descriptor.mesh_r.1 - descriptor.mesh_r.0, std::u64::MAX);
 
-            let mesh_r = descriptor.mesh_r.0..descriptor.mesh_r.1;
+            if !scene_object.visible {
+                continue;
+            }
+
+            if !descriptor.lods.is_empty() {
+                for lod in 0..=descriptor.lods.len() {
+                    let (start, end) = descriptor.lod_mesh_r(lod);
+                    for mesh_idx in start..end {
+                        *reserved.entry(mesh_idx).or_default() += 1;
+                    }
+                }
+            }
+
+            let (mesh_start, mesh_end) = descriptor.lod_mesh_r(scene_object.lod);
             let mut material_r = descriptor
                 .local_material_r
                 .map(|(s, e)| s..e)
                 .unwrap_or(0..0);
 
-            let mesh_start = mesh_r.start;
-            for mesh_idx in mesh_r {
-                let material_idx = material_r
-                    .next()
-                    .map(|idx| scene.storage.local_materials[idx])
+            for (slot, mesh_idx) in (mesh_start..mesh_end).enumerate() {
+                let local_material = material_r.next().map(|idx| local_materials[idx]);
+                let material_idx = scene_object
+                    .material_overrides
+                    .get(&slot)
+                    .copied()
+                    .or(local_material)
                     .or(scene_object.material_idx)
                     .ok_or_else(|| anyhow::anyhow!("No material found for mesh"))?;
 
-                let instance_bank = instance_banks.entry((mesh_idx, material_idx)).or_default();
-
-                let instances_r = scene_object.mesh_instances_r.0..scene_object.mesh_instances_r.1;
                 // FIXIT: This is wrong if there are separate instance types for submeshes.
                 // Fine since we don't do any alteration of per-instance data (yet!).
                 // Instance bank needs to be determined per-mesh
                 // and instance_offsets needs to be parametrized by instance type.
-                for instance in &scene.storage.instances[instances_r] {
-                    let cur_len = instance_bank.len() as wgpu::BufferAddress;
-                    let per_bank_map = instance_offsets_per_bank
-                        .entry((mesh_idx, material_idx))
-                        .or_default();
-                    per_bank_map.push((scene_object_id, mesh_idx - mesh_start, cur_len));
-                    instance.copy_to(instance_bank);
-                }
+                instance_banks
+                    .entry(mesh_idx)
+                    .or_default()
+                    .push(BankInstance {
+                        material_idx,
+                        scene_object_id,
+                        slot,
+                        instance_idx: scene_object.mesh_instances_r.0 + slot,
+                    });
             }
         }
 
-        let draw_buffers_count = instance_banks.keys().len();
-        let mut instance_buffer_draws = Vec::with_capacity(draw_buffers_count);
-        let mut transform_ib_contents: Vec<u8> =
-            Vec::with_capacity(instance_banks.values().map(Vec::len).sum());
+        for &mesh_idx in reserved.keys() {
+            instance_banks.entry(mesh_idx).or_default();
+        }
+
+        /* Draws follow the pipeline their vertex array type needs, then whether they're indexed,
+          so passes switch pipelines and buffers only between runs of them.
+          Materials come from the atlas with every instance, so they don't take bind group switches,
+          but ordering instances and draws by them keeps the ones sampling the same textures together.
+        */
+        let mut instance_banks: Vec<_> = instance_banks.into_iter().collect();
+        for (_, instance_bank) in instance_banks.iter_mut() {
+            instance_bank.sort_by_key(|instance| instance.material_idx);
+        }
+        instance_banks.sort_by_key(|(mesh_idx, instance_bank)| {
+            let descriptor = &mesh_descriptors[*mesh_idx];
+            (
+                descriptor.vertex_array_type,
+                descriptor.index_buffer_index_no.is_some(),
+                instance_bank.first().map(|instance| instance.material_idx),
+                *mesh_idx,
+            )
+        });
 
-        for ((mesh_idx, material_id), instance_bank) in instance_banks.into_iter() {
-            let instance_bank_offset = transform_ib_contents.len();
-            for (scene_object_id, mesh_idx, offset) in instance_offsets_per_bank
-                [&(mesh_idx, material_id)]
+        let capacity = |mesh_idx: &usize, instance_bank: &Vec<BankInstance>| {
+            reserved
+                .get(mesh_idx)
+                .map_or(instance_bank.len(), |&reserved| {
+                    reserved.max(instance_bank.len())
+                })
+        };
+        let draw_buffers_count = instance_banks.len();
+        let mut instance_buffer_draws = Vec::with_capacity(draw_buffers_count);
+        let mut transform_ib_contents: Vec<u8> = Vec::with_capacity(
+            instance_banks
                 .iter()
-                .copied()
-            {
-                instance_offsets[scene_object_id][mesh_idx] =
-                    instance_bank_offset as wgpu::BufferAddress + offset;
+                .map(|(mesh_idx, bank)| capacity(mesh_idx, bank))
+                .sum::<usize>()
+                * MODEL_INSTANCE_STRIDE,
+        );
+        let mut banks = HashMap::with_capacity(draw_buffers_count);
+
+        for (draw_idx, (mesh_idx, instance_bank)) in instance_banks.into_iter().enumerate() {
+            let first_instance = transform_ib_contents.len() / MODEL_INSTANCE_STRIDE;
+            for bank_instance in &instance_bank {
+                instance_offsets[bank_instance.scene_object_id][bank_instance.slot] =
+                    transform_ib_contents.len() as wgpu::BufferAddress;
+                bank_instance.copy_to(instances, &mut transform_ib_contents);
             }
 
+            let capacity = capacity(&mesh_idx, &instance_bank);
+            transform_ib_contents.resize((first_instance + capacity) * MODEL_INSTANCE_STRIDE, 0);
+
             instance_buffer_draws.push((
-                instance_bank_offset / MODEL_INSTANCE_STRIDE,
-                instance_bank.len() / MODEL_INSTANCE_STRIDE,
+                first_instance,
+                instance_bank.len(),
                 &mesh_descriptors[mesh_idx],
-                material_id,
             ));
-            transform_ib_contents.extend(instance_bank);
+            banks.insert(
+                mesh_idx,
+                InstanceBank {
+                    draw_idx,
+                    first_instance,
+                    capacity,
+                    instances: instance_bank,
+                },
+            );
         }
 
+        let num_instances = banks
+            .values()
+            .map(|bank: &InstanceBank| bank.instances.len())
+            .sum();
         let mut transform_ib = None;
+        let mut previous_transform_ib = None;
 
         if !transform_ib_contents.is_empty() {
+            let size = (transform_ib_contents.len()
+                + MAX_INSTANCE_BUFFER_GROWTH * MODEL_INSTANCE_STRIDE)
+                as wgpu::BufferAddress;
+
             let ib = gpu.device.create_buffer(&wgpu::BufferDescriptor {
                 label: Some("InstanceBuffer:Transform"),
-                size: (transform_ib_contents.len()
-                    + MAX_INSTANCE_BUFFER_GROWTH * MODEL_INSTANCE_STRIDE)
-                    as wgpu::BufferAddress,
-                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                size,
+                usage: wgpu::BufferUsages::VERTEX
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+            // Starts out the same, so nothing is moving on the first frame.
+            let previous_ib = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("InstanceBuffer:PreviousTransform"),
+                size,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
                 mapped_at_creation: false,
             });
 
             gpu.queue
                 .write_buffer(&ib, 0, transform_ib_contents.as_slice());
+            gpu.queue
+                .write_buffer(&previous_ib, 0, transform_ib_contents.as_slice());
 
             transform_ib = Some(ib);
+            previous_transform_ib = Some(previous_ib);
         }
 
         let instance_buffers = InstanceBuffers {
             model_ib: transform_ib,
+            previous_model_ib: previous_transform_ib,
         };
 
         // Now let's create draw buffers...
@@ -519,7 +1521,7 @@ impl GpuScene {
         let mut non_indexed_draw_buffer_contents: Vec<u8> = vec![];
         let mut draw_calls = Vec::with_capacity(draw_buffers_count);
 
-        for (ib_first, ib_count, mesh_descriptor, material_id) in instance_buffer_draws {
+        for (ib_first, ib_count, mesh_descriptor) in instance_buffer_draws {
             let call = DrawCall {
                 indexed: mesh_descriptor.index_buffer_index_no.is_some(),
                 draw_buffer_offset: if mesh_descriptor.index_buffer_index_no.is_some() {
@@ -527,7 +1529,6 @@ impl GpuScene {
                 } else {
                     non_indexed_draw_buffer_contents.len()
                 } as wgpu::BufferAddress,
-                material_id,
                 vertex_array_type: mesh_descriptor.vertex_array_type,
                 instance_type: InstanceArrayType::Model,
             };
@@ -613,67 +1614,97 @@ impl GpuScene {
         };
 
         Ok(Self {
-            scene_objects: scene.objects,
-            instances: scene.storage.instances,
-            materials: scene.storage.local_materials,
-            vertex_buffers,
             instance_buffers,
-            instance_offsets,
-            index_buffer,
             draw_buffers,
-            mesh_descriptors,
+            instance_offsets,
+            banks,
             draw_calls,
+            num_instances,
         })
     }
 
-    pub fn instance_buffer_by_type(&self, instance_type: InstanceArrayType) -> &wgpu::Buffer {
-        match instance_type {
-            InstanceArrayType::Model => self.instance_buffers.model_ib.as_ref().unwrap(),
-        }
-    }
-
-    pub fn vertex_buffer_by_type(&self, vertex_type: MeshVertexArrayType) -> &wgpu::Buffer {
-        match vertex_type {
-            MeshVertexArrayType::PN => self.vertex_buffers.pn_buffer.as_ref().unwrap(),
-            MeshVertexArrayType::PNUV => self.vertex_buffers.pnuv_buffer.as_ref().unwrap(),
-            MeshVertexArrayType::PNTBUV => self.vertex_buffers.pntbuv_buffer.as_ref().unwrap(),
+    /// Moves instances of the object's meshes from banks of `from` meshes to banks of `to` meshes,
+    /// slot by slot. The last instance of a bank fills the place left behind, so only the moved
+    /// instances and draw counts are written. Returns `false` without moving anything when some
+    /// bank has no room left, draws need to be rebuilt then.
+    fn move_instances(
+        &mut self,
+        gpu: &Gpu,
+        instances: &[Instance],
+        scene_object_id: usize,
+        from: (usize, usize),
+        to: (usize, usize),
+    ) -> bool {
+        let has_room = (to.0..to.1).all(|mesh_idx| {
+            self.banks
+                .get(&mesh_idx)
+                .is_some_and(|bank| bank.instances.len() < bank.capacity)
+        });
+        if !has_room {
+            return false;
         }
-    }
 
-    pub fn update_instance<F>(&mut self, gpu: &Gpu, scene_object_id: SceneObjectId, updater: F)
-    where
-        F: Fn(&mut Instance),
-    {
-        let object = &self.scene_objects[scene_object_id.0];
+        for (slot, (from, to)) in (from.0..from.1).zip(to.0..to.1).enumerate() {
+            let offset = self.instance_offsets[scene_object_id][slot];
+            let bank = self.banks.get_mut(&from).unwrap();
+            let position = offset as usize / MODEL_INSTANCE_STRIDE - bank.first_instance;
+            let moved = bank.instances.swap_remove(position);
+            let filler = bank.instances.get(position).copied();
+            let first_instance = bank.first_instance;
 
-        let instance_idx = object.instance_idx;
-        updater(&mut self.instances[instance_idx]);
-
-        let mut update = Vec::new();
-        self.instances[instance_idx].copy_to(&mut update);
+            if let Some(filler) = filler {
+                self.write_instance(gpu, instances, filler, first_instance + position);
+            }
+            self.write_instance_count(gpu, from);
 
-        for offset in &self.instance_offsets[scene_object_id.0] {
-            gpu.queue.write_buffer(
-                self.instance_buffers.model_ib.as_ref().unwrap(),
-                *offset,
-                &update,
-            );
+            let bank = self.banks.get_mut(&to).unwrap();
+            bank.instances.push(moved);
+            let position = bank.first_instance + bank.instances.len() - 1;
+            self.write_instance(gpu, instances, moved, position);
+            self.write_instance_count(gpu, to);
         }
-    }
 
-    pub fn index_buffer(&self) -> &wgpu::Buffer {
-        &self.index_buffer
+        true
     }
 
-    pub fn draw_calls(&self) -> &[DrawCall] {
-        &self.draw_calls
-    }
+    fn write_instance(
+        &mut self,
+        gpu: &Gpu,
+        instances: &[Instance],
+        bank_instance: BankInstance,
+        position: usize,
+    ) {
+        let offset = (position * MODEL_INSTANCE_STRIDE) as wgpu::BufferAddress;
+        let mut contents = Vec::with_capacity(MODEL_INSTANCE_STRIDE);
+        bank_instance.copy_to(instances, &mut contents);
+
+        // Previous transforms at the new place belong to another instance, the moved one
+        // stands still for a frame instead.
+        let InstanceBuffers {
+            model_ib,
+            previous_model_ib,
+        } = &self.instance_buffers;
+        for buffer in [model_ib, previous_model_ib].into_iter().flatten() {
+            gpu.upload_buffer(buffer, offset, &contents);
+        }
 
-    pub fn indexed_draw_buffer(&self) -> &wgpu::Buffer {
-        self.draw_buffers.indexed_buffer.as_ref().unwrap()
+        self.instance_offsets[bank_instance.scene_object_id][bank_instance.slot] = offset;
     }
 
-    pub fn non_indexed_draw_buffer(&self) -> &wgpu::Buffer {
-        self.draw_buffers.non_indexed_buffer.as_ref().unwrap()
+    fn write_instance_count(&self, gpu: &Gpu, mesh_idx: usize) {
+        let bank = &self.banks[&mesh_idx];
+        let call = &self.draw_calls[bank.draw_idx];
+        let buffer = if call.indexed {
+            &self.draw_buffers.indexed_buffer
+        } else {
+            &self.draw_buffers.non_indexed_buffer
+        };
+
+        // Instance count follows the index or vertex count in both kinds of draw arguments.
+        gpu.upload_buffer(
+            buffer.as_ref().unwrap(),
+            call.draw_buffer_offset + std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            bytemuck::bytes_of(&(bank.instances.len() as u32)),
+        );
     }
 }
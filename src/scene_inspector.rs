@@ -0,0 +1,223 @@
+use std::sync::RwLock;
+
+use egui::ComboBox;
+use nalgebra as na;
+
+use gpu_basics::{
+    gpu::Gpu,
+    material::{MaterialAtlas, MaterialId},
+    scene::{GpuScene, SceneObjectId},
+};
+
+// Scale is kept away from zero, instances need invertible model matrices.
+const MIN_SCALE: f32 = 0.001;
+
+// Transform of the selected object split into parts, so editing rotation doesn't
+// drift from decomposing the model matrix every frame.
+#[derive(Clone, Copy, PartialEq)]
+struct Transform {
+    translation: na::Vector3<f32>,
+    // Roll, pitch and yaw in degrees.
+    rotation: na::Vector3<f32>,
+    scale: na::Vector3<f32>,
+}
+
+impl Transform {
+    fn from_model(model: &na::Matrix4<f32>) -> Self {
+        let linear = model.fixed_view::<3, 3>(0, 0).into_owned();
+        let scale = na::Vector3::from_fn(|i, _| linear.column(i).norm());
+        let rotation = na::Rotation3::from_matrix(
+            &(linear * na::Matrix3::from_diagonal(&scale.map(|s| 1.0 / s))),
+        );
+        let (roll, pitch, yaw) = rotation.euler_angles();
+
+        Self {
+            translation: model.fixed_view::<3, 1>(0, 3).into_owned(),
+            rotation: na::Vector3::new(roll, pitch, yaw).map(f32::to_degrees),
+            scale,
+        }
+    }
+
+    fn model(&self) -> na::Matrix4<f32> {
+        let [roll, pitch, yaw] = self.rotation.map(f32::to_radians).into();
+
+        na::Matrix4::new_translation(&self.translation)
+            * na::Rotation3::from_euler_angles(roll, pitch, yaw).to_homogeneous()
+            * na::Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+}
+
+#[derive(Default)]
+pub struct SceneInspector {
+    selected: Option<SceneObjectId>,
+    transform: Option<Transform>,
+    error: Option<String>,
+}
+
+impl SceneInspector {
+    pub fn select(&mut self, object_id: Option<SceneObjectId>) {
+        self.selected = object_id;
+        self.transform = None;
+    }
+
+    fn object_label(scene: &GpuScene, object_id: SceneObjectId) -> String {
+        match scene.object_name(object_id) {
+            Some(name) => format!("{} ({:?})", name, object_id),
+            None => format!("{:?}", object_id),
+        }
+    }
+
+    fn drag_row(ui: &mut egui::Ui, label: &str, value: &mut na::Vector3<f32>, speed: f32) {
+        ui.horizontal(|ui| {
+            for v in value.iter_mut() {
+                ui.add(egui::DragValue::new(v).speed(speed));
+            }
+            ui.label(label);
+        });
+    }
+
+    // Returns whether the transform was changed.
+    fn edit_transform(ui: &mut egui::Ui, transform: &mut Transform) -> bool {
+        let before = *transform;
+
+        Self::drag_row(ui, "Translation", &mut transform.translation, 0.05);
+        Self::drag_row(ui, "Rotation", &mut transform.rotation, 0.5);
+        Self::drag_row(ui, "Scale", &mut transform.scale, 0.01);
+        transform.scale = transform.scale.map(|s| s.max(MIN_SCALE));
+
+        *transform != before
+    }
+
+    pub fn render(
+        &mut self,
+        ctx: &egui::Context,
+        gpu: &Gpu,
+        scene: &RwLock<GpuScene>,
+        atlas: &RwLock<MaterialAtlas>,
+    ) {
+        egui::Window::new("Scene")
+            .default_open(false)
+            .show(ctx, |ui| {
+                let mut change: Option<(usize, Option<MaterialId>)> = None;
+                let mut visibility: Option<(SceneObjectId, bool)> = None;
+                let mut transform_changed = false;
+
+                {
+                    let scene = scene.read().unwrap();
+                    let atlas = atlas.read().unwrap();
+
+                    egui::ScrollArea::vertical()
+                        .max_height(200.0)
+                        .show(ui, |ui| {
+                            for id in scene.object_ids() {
+                                ui.horizontal(|ui| {
+                                    let mut visible = scene.is_visible(id);
+                                    if ui.checkbox(&mut visible, "").changed() {
+                                        visibility = Some((id, visible));
+                                    }
+
+                                    let label = Self::object_label(&scene, id);
+                                    if ui
+                                        .selectable_label(self.selected == Some(id), label)
+                                        .clicked()
+                                    {
+                                        self.selected = Some(id);
+                                        self.transform = None;
+                                    }
+                                });
+                            }
+                        });
+
+                    // Checkboxes above change visibility of any object, not just the selected one.
+                    if let Some(object_id) = self.selected {
+                        ui.separator();
+                        ui.label("Transform");
+
+                        let transform = self.transform.get_or_insert_with(|| {
+                            Transform::from_model(&scene.object_instance(object_id).model())
+                        });
+                        transform_changed = Self::edit_transform(ui, transform);
+
+                        ui.separator();
+                        ui.label("Material Slots");
+
+                        for (slot_idx, slot) in scene.mesh_slots(object_id).into_iter().enumerate()
+                        {
+                            ui.horizontal(|ui| {
+                                let name = slot
+                                    .name
+                                    .map(str::to_owned)
+                                    .unwrap_or_else(|| format!("Mesh {}", slot_idx));
+
+                                let mut selected = slot.material_id;
+
+                                ComboBox::from_id_source((object_id, slot_idx))
+                                    .selected_text(
+                                        selected
+                                            .map(|id| format!("{:?}", id))
+                                            .unwrap_or_else(|| "None".to_owned()),
+                                    )
+                                    .show_ui(ui, |ui| {
+                                        // Pipelines are picked by vertex layout, so only materials
+                                        // matching the mesh layout can be bound to it.
+                                        for material_id in atlas.material_ids().filter(|id| {
+                                            atlas.material(*id).vertex_array_type()
+                                                == slot.vertex_array_type
+                                        }) {
+                                            ui.selectable_value(
+                                                &mut selected,
+                                                Some(material_id),
+                                                format!("{:?}", material_id),
+                                            );
+                                        }
+                                    });
+
+                                ui.label(name);
+
+                                if selected != slot.material_id {
+                                    change = Some((slot_idx, selected));
+                                }
+
+                                if slot.overridden && ui.button("Reset").clicked() {
+                                    change = Some((slot_idx, None));
+                                }
+                            });
+                        }
+                    }
+                }
+
+                if let (Some(object_id), Some(transform), true) =
+                    (self.selected, self.transform, transform_changed)
+                {
+                    scene
+                        .write()
+                        .unwrap()
+                        .update_instance(gpu, object_id, |instance| {
+                            instance.set_model(transform.model())
+                        });
+                }
+
+                if let Some((object_id, visible)) = visibility {
+                    self.error = scene
+                        .write()
+                        .unwrap()
+                        .set_visible(gpu, object_id, visible)
+                        .err()
+                        .map(|e| e.to_string());
+                }
+
+                if let (Some(object_id), Some((slot, material_id))) = (self.selected, change) {
+                    self.error = scene
+                        .write()
+                        .unwrap()
+                        .override_material(gpu, object_id, slot, material_id)
+                        .err()
+                        .map(|e| e.to_string());
+                }
+
+                if let Some(error) = &self.error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+            });
+    }
+}
@@ -0,0 +1,1306 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use nalgebra as na;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets::{canonical_path, AssetManager},
+    camera::{Camera, GpuCamera},
+    camera_feed_pass::{CameraFeedPass, Mirror},
+    decal::Decal,
+    foliage::{self, FoliageScatter},
+    gpu::Gpu,
+    light_scene::{Light, LightScene},
+    loader::ObjLoaderSettings,
+    material::{Material, MaterialAtlas, MaterialId, SpecularTexture, SPLAT_LAYERS},
+    mesh::MeshBuilder,
+    projection::{GpuProjection, Perspective},
+    render_context::RenderContext,
+    scene::{GpuScene, Instance, Scene, SceneModel, SceneModelBuilder, SceneObjectId},
+    scene_validation::ValidationReport,
+    shapes::{Capsule, Cone, Cube, Cylinder, Icosphere, Plane, Torus, UVSphere},
+    terrain::{Terrain, TerrainSettings},
+    test_scenes::TestScene,
+};
+
+/// Test scene described in a RON file instead of code. Models and materials are declared
+/// by name and then referenced by objects:
+///
+/// ```ron
+/// SceneScript(
+///     models: { "teapot": Obj(path: "./models/teapot.obj") },
+///     materials: { "lily": Solid(ambient: (0.5, 0.5, 1.0, 0.0), ...) },
+///     objects: [
+///         (model: "teapot", material: Some("lily"), transform: (translation: (0.0, 0.0, -2.0))),
+///     ],
+///     lights: [Directional(direction: (-0.5, -0.5, -0.5), ...)],
+///     camera: (position: (0.0, 18.0, 14.0), pitch: -45.0, yaw: 270.0),
+/// )
+/// ```
+///
+/// Angles are in degrees. The camera can also be given a `roll` and an `up` axis other
+/// than Y, which its angles are measured relative to. A `terrain` built from a heightmap can be added next to objects,
+/// and `foliage` scattered over the terrain or named objects. `decals` project named
+/// materials down the Y axis of their boxes, which span a unit cube before being transformed.
+/// `camera_feeds` render the scene from cameras of their own, or reflected through
+/// a `mirror` plane, onto named objects, see `SceneScriptWatcher::add_camera_feeds`.
+///
+/// Scenes built from a script can be saved back with their current object transforms,
+/// material values, lights and camera, see `SceneScriptWatcher::save`.
+#[derive(Serialize, Deserialize)]
+pub struct SceneScript {
+    #[serde(default)]
+    models: BTreeMap<String, ModelSource>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialSource>,
+    #[serde(default)]
+    objects: Vec<ObjectSpec>,
+    #[serde(default)]
+    lights: Vec<LightSpec>,
+    camera: CameraSpec,
+    #[serde(default)]
+    projection: ProjectionSpec,
+    #[serde(default)]
+    terrain: Option<TerrainSpec>,
+    #[serde(default)]
+    foliage: Vec<FoliageSpec>,
+    #[serde(default)]
+    decals: Vec<DecalSpec>,
+    #[serde(default)]
+    camera_feeds: Vec<CameraFeedSpec>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum ModelSource {
+    Obj {
+        path: PathBuf,
+        #[serde(default)]
+        tangent_space: bool,
+    },
+    // Textured shapes get texture coordinates and tangent space for normal mapping.
+    Cube {
+        #[serde(default)]
+        textured: bool,
+    },
+    Plane {
+        #[serde(default)]
+        textured: bool,
+        #[serde(default = "one")]
+        uv_scale: f32,
+        #[serde(default = "one_subdivision")]
+        subdivisions: usize,
+    },
+    UVSphere {
+        slices: usize,
+        stacks: usize,
+        #[serde(default)]
+        textured: bool,
+    },
+    Icosphere {
+        subdivisions: usize,
+    },
+    Torus {
+        slices: usize,
+        sides: usize,
+        ring_radius: f32,
+        tube_radius: f32,
+        #[serde(default)]
+        textured: bool,
+    },
+    Cylinder {
+        slices: usize,
+        #[serde(default)]
+        textured: bool,
+    },
+    Cone {
+        slices: usize,
+        #[serde(default)]
+        textured: bool,
+    },
+    Capsule {
+        slices: usize,
+        stacks: usize,
+        height: f32,
+        #[serde(default)]
+        textured: bool,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+enum MaterialSource {
+    Solid {
+        ambient: [f32; 4],
+        diffuse: [f32; 4],
+        // w = shininess
+        specular: [f32; 4],
+        #[serde(default)]
+        reflectivity: f32,
+        #[serde(default)]
+        emissive: [f32; 4],
+    },
+    Textured {
+        diffuse: PathBuf,
+        specular: SpecularSource,
+        #[serde(default)]
+        reflectivity: f32,
+        #[serde(default)]
+        emissive: [f32; 4],
+    },
+    TexturedNormal {
+        diffuse: PathBuf,
+        specular: SpecularSource,
+        normal: PathBuf,
+        #[serde(default)]
+        reflectivity: f32,
+        #[serde(default)]
+        emissive: [f32; 4],
+    },
+}
+
+impl MaterialSource {
+    fn reflectivity(&self) -> f32 {
+        match self {
+            Self::Solid { reflectivity, .. }
+            | Self::Textured { reflectivity, .. }
+            | Self::TexturedNormal { reflectivity, .. } => *reflectivity,
+        }
+    }
+
+    fn emissive(&self) -> [f32; 4] {
+        match self {
+            Self::Solid { emissive, .. }
+            | Self::Textured { emissive, .. }
+            | Self::TexturedNormal { emissive, .. } => *emissive,
+        }
+    }
+
+    /// Takes over values of the material built from this source, which could be edited since.
+    /// Textures are kept, they're not tracked back to their files.
+    fn capture(&mut self, material: &Material) {
+        let (reflectivity, emissive) = match self {
+            Self::Solid {
+                ambient,
+                diffuse,
+                specular,
+                reflectivity,
+                emissive,
+            } => {
+                if let Material::PhongSolid {
+                    ambient: a,
+                    diffuse: d,
+                    specular: s,
+                    ..
+                } = material
+                {
+                    *ambient = (*a).into();
+                    *diffuse = (*d).into();
+                    *specular = (*s).into();
+                }
+
+                (reflectivity, emissive)
+            }
+            Self::Textured {
+                specular,
+                reflectivity,
+                emissive,
+                ..
+            }
+            | Self::TexturedNormal {
+                specular,
+                reflectivity,
+                emissive,
+                ..
+            } => {
+                let shininess = match material {
+                    Material::PhongTextured { specular, .. }
+                    | Material::PhongTexturedNormal { specular, .. }
+                    | Material::PhongSplat { specular, .. } => specular.shininess(),
+                    Material::PhongSolid { .. } => None,
+                };
+                if let (Some(captured), Some(shininess)) = (specular.shininess_mut(), shininess) {
+                    *captured = shininess;
+                }
+
+                (reflectivity, emissive)
+            }
+        };
+
+        *reflectivity = material.reflectivity();
+        *emissive = material.emissive().into();
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+enum SpecularSource {
+    Ideal(f32),
+    FullDiffuse,
+    Provided(String, f32),
+    Glossy(String, f32),
+}
+
+impl SpecularSource {
+    fn shininess_mut(&mut self) -> Option<&mut f32> {
+        match self {
+            Self::Ideal(shininess) | Self::Provided(_, shininess) | Self::Glossy(_, shininess) => {
+                Some(shininess)
+            }
+            Self::FullDiffuse => None,
+        }
+    }
+}
+
+impl From<SpecularSource> for SpecularTexture {
+    fn from(source: SpecularSource) -> Self {
+        match source {
+            SpecularSource::Ideal(shininess) => SpecularTexture::Ideal(shininess),
+            SpecularSource::FullDiffuse => SpecularTexture::FullDiffuse,
+            SpecularSource::Provided(path, shininess) => SpecularTexture::Provided(path, shininess),
+            SpecularSource::Glossy(path, shininess) => SpecularTexture::Glossy(path, shininess),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct ObjectSpec {
+    // Named objects are returned to the caller, like in hand written test scenes.
+    #[serde(default)]
+    name: Option<String>,
+    model: String,
+    // Overrides materials coming with the model.
+    #[serde(default)]
+    material: Option<String>,
+    #[serde(default)]
+    transform: TransformSpec,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+struct TransformSpec {
+    translation: [f32; 3],
+    // Euler angles around x, y and z axes.
+    rotation: [f32; 3],
+    scale: [f32; 3],
+}
+
+impl Default for TransformSpec {
+    fn default() -> Self {
+        Self {
+            translation: [0.0; 3],
+            rotation: [0.0; 3],
+            scale: [1.0; 3],
+        }
+    }
+}
+
+impl TransformSpec {
+    fn matrix(&self) -> na::Matrix4<f32> {
+        let [x, y, z] = self.rotation.map(f32::to_radians);
+
+        na::Matrix4::new_translation(&self.translation.into())
+            * na::Rotation3::from_euler_angles(x, y, z).to_homogeneous()
+            * na::Matrix4::new_nonuniform_scaling(&self.scale.into())
+    }
+
+    /// Reverses `matrix`. Shear can't be expressed, so it's lost.
+    fn from_matrix(matrix: &na::Matrix4<f32>) -> Self {
+        let linear = matrix.fixed_view::<3, 3>(0, 0);
+        let scale = [0, 1, 2].map(|i| linear.column(i).norm());
+        let unscaled = scale.map(|s| if s > 0.0 { 1.0 / s } else { 0.0 });
+        let rotation = na::Rotation3::from_matrix_unchecked(
+            linear * na::Matrix3::from_diagonal(&unscaled.into()),
+        );
+        let (x, y, z) = rotation.euler_angles();
+
+        Self {
+            translation: matrix.fixed_view::<3, 1>(0, 3).into(),
+            rotation: [x, y, z].map(f32::to_degrees),
+            scale,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+enum LightSpec {
+    Directional {
+        direction: [f32; 3],
+        ambient: [f32; 3],
+        diffuse: [f32; 3],
+        specular: [f32; 3],
+    },
+    Point {
+        position: [f32; 3],
+        ambient: [f32; 3],
+        diffuse: [f32; 3],
+        specular: [f32; 3],
+        attenuation: [f32; 3],
+    },
+    Spot {
+        position: [f32; 3],
+        direction: [f32; 3],
+        ambient: [f32; 3],
+        diffuse: [f32; 3],
+        specular: [f32; 3],
+        angle: f32,
+        attenuation: [f32; 3],
+    },
+}
+
+// Every chunk of the terrain becomes an object, added after objects of the script.
+#[derive(Serialize, Deserialize)]
+struct TerrainSpec {
+    heightmap: PathBuf,
+    size: f32,
+    height: f32,
+    #[serde(default = "default_chunk_quads")]
+    chunk_quads: u32,
+    // Diffuse maps blended by the splat map derived from the heightmap.
+    layers: [PathBuf; SPLAT_LAYERS],
+    #[serde(default = "one")]
+    tiling: f32,
+    #[serde(default)]
+    transform: TransformSpec,
+}
+
+impl TerrainSpec {
+    fn settings(&self) -> TerrainSettings {
+        TerrainSettings {
+            size: self.size,
+            height: self.height,
+            chunk_quads: self.chunk_quads,
+        }
+    }
+
+    // Only the header of the heightmap is read.
+    fn chunk_count(&self) -> Result<usize> {
+        let (width, depth) = image::image_dimensions(&self.heightmap)
+            .with_context(|| format!("failed to read heightmap {}", self.heightmap.display()))?;
+
+        Ok(Terrain::chunk_count(width, depth, self.chunk_quads))
+    }
+
+    fn add_to_scene(
+        &self,
+        gpu: &Gpu,
+        scene: &mut Scene,
+        material_atlas: &mut MaterialAtlas,
+    ) -> Result<Vec<SceneObjectId>> {
+        let terrain = Terrain::load(&self.heightmap, self.settings())?;
+        let material = terrain.add_material(gpu, material_atlas, &self.layers, self.tiling)?;
+        terrain.add_to_scene(
+            scene,
+            Instance::new_model(self.transform.matrix()),
+            material,
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct DecalSpec {
+    material: String,
+    #[serde(default)]
+    transform: TransformSpec,
+    #[serde(default = "one")]
+    opacity: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+enum FoliageSurface {
+    Terrain,
+    // Name of an object of the script.
+    Object(String),
+}
+
+// Plants don't become objects, so they don't show up when the scene is captured.
+#[derive(Serialize, Deserialize)]
+struct FoliageSpec {
+    surface: FoliageSurface,
+    count: usize,
+    #[serde(default)]
+    seed: u64,
+    #[serde(default = "default_foliage_scale")]
+    scale: [f32; 2],
+    #[serde(default = "default_max_slope")]
+    max_slope: f32,
+    // World space heights plants grow between.
+    #[serde(default)]
+    heights: Option<[f32; 2]>,
+}
+
+impl FoliageSpec {
+    fn scatter(&self) -> FoliageScatter {
+        FoliageScatter {
+            count: self.count,
+            seed: self.seed,
+            scale: self.scale,
+            max_slope: self.max_slope,
+            heights: self.heights,
+        }
+    }
+}
+
+/// Model of a built scene drawn as a placeholder cube while its file is loading,
+/// swapped for the loaded meshes by `SceneScriptWatcher::poll`.
+pub struct PendingModel {
+    name: String,
+    path: PathBuf,
+    settings: ObjLoaderSettings,
+    model: SceneModel,
+}
+
+// Feeds aren't objects either, they're kept as written when the scene is captured.
+#[derive(Serialize, Deserialize)]
+struct CameraFeedSpec {
+    // Either a fixed camera or a mirror following the main one.
+    #[serde(default)]
+    camera: Option<CameraSpec>,
+    #[serde(default)]
+    mirror: Option<MirrorSpec>,
+    // Layers of the atlas are square, so the aspect ratio is always 1.
+    #[serde(default)]
+    projection: ProjectionSpec,
+    #[serde(default = "default_feed_size")]
+    size: u32,
+    // Names of objects of the script showing the feed on all of their meshes,
+    // which need texture coordinates.
+    objects: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MirrorSpec {
+    center: [f32; 3],
+    // Side of the mirror facing the scene it reflects.
+    normal: [f32; 3],
+}
+
+#[derive(Serialize, Deserialize)]
+struct CameraSpec {
+    position: [f32; 3],
+    pitch: f32,
+    yaw: f32,
+    #[serde(default)]
+    roll: f32,
+    /// Axis yaw turns around, angles are measured relative to it.
+    #[serde(default = "default_up")]
+    up: [f32; 3],
+}
+
+impl CameraSpec {
+    fn camera(&self) -> Camera {
+        let position = self.position.into();
+        let mut camera = Camera::new(position, 0.0, 0.0);
+        camera.set_up(self.up.into());
+        camera.set_pose(
+            position,
+            self.pitch.to_radians(),
+            self.yaw.to_radians(),
+            self.roll.to_radians(),
+        );
+
+        camera
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct ProjectionSpec {
+    fov: f32,
+    near: f32,
+    far: f32,
+}
+
+impl Default for ProjectionSpec {
+    fn default() -> Self {
+        Self {
+            fov: 45.0,
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+}
+
+impl ProjectionSpec {
+    fn perspective(&self, aspect: f32) -> Perspective {
+        Perspective::new(aspect, self.fov.to_radians(), self.near, self.far)
+    }
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+fn one_subdivision() -> usize {
+    1
+}
+
+fn default_up() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_feed_size() -> u32 {
+    512
+}
+
+fn default_chunk_quads() -> u32 {
+    TerrainSettings::default().chunk_quads
+}
+
+fn default_foliage_scale() -> [f32; 2] {
+    FoliageScatter::default().scale
+}
+
+fn default_max_slope() -> f32 {
+    FoliageScatter::default().max_slope
+}
+
+impl SceneScript {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read scene script {}", path.display()))?;
+
+        ron::from_str(&source)
+            .with_context(|| format!("failed to parse scene script {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let source = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+
+        std::fs::write(path, source)
+            .with_context(|| format!("failed to write scene script {}", path.display()))
+    }
+
+    /// Replaces what the script describes with the current state of the scene built from it.
+    /// Objects of the scene have to come from the script, in the same order.
+    pub fn capture(
+        &mut self,
+        gpu_scene: &GpuScene,
+        material_atlas: &MaterialAtlas,
+        lights: &LightScene,
+        camera: &Camera,
+    ) -> Result<()> {
+        let object_ids: Vec<_> = gpu_scene.object_ids().collect();
+        let terrain_chunks = match &self.terrain {
+            Some(terrain) => terrain.chunk_count()?,
+            None => 0,
+        };
+        if object_ids.len() != self.objects.len() + terrain_chunks {
+            bail!(
+                "scene has {} objects, but the script describes {}",
+                object_ids.len(),
+                self.objects.len() + terrain_chunks
+            );
+        }
+
+        // Materials are named only in the script, so they're found through objects using them.
+        let mut materials = HashMap::new();
+        for (object, id) in self.objects.iter_mut().zip(object_ids) {
+            object.transform = TransformSpec::from_matrix(&gpu_scene.object_instance(id).model());
+
+            if let (Some(name), Some(material)) = (&object.material, gpu_scene.object_material(id))
+            {
+                materials.entry(name.clone()).or_insert(material);
+            }
+        }
+
+        for (decal, id) in self.decals.iter_mut().zip(gpu_scene.decal_ids()) {
+            let captured = gpu_scene.decal(id);
+            decal.transform = TransformSpec::from_matrix(&captured.model);
+            decal.opacity = captured.opacity;
+            materials
+                .entry(decal.material.clone())
+                .or_insert(captured.material);
+        }
+
+        for (name, source) in self.materials.iter_mut() {
+            if let Some(material) = materials.get(name) {
+                source.capture(material_atlas.material(*material));
+            }
+        }
+
+        self.lights = Self::capture_lights(lights);
+        self.camera = CameraSpec {
+            position: camera.eye().coords.into(),
+            pitch: camera.pitch().to_degrees(),
+            yaw: camera.yaw().to_degrees(),
+            roll: camera.roll().to_degrees(),
+            up: camera.up().into_inner().into(),
+        };
+
+        Ok(())
+    }
+
+    fn capture_lights(lights: &LightScene) -> Vec<LightSpec> {
+        fn xyz(v: na::Vector4<f32>) -> [f32; 3] {
+            [v.x, v.y, v.z]
+        }
+        // Constant, linear and quadratic terms are kept in w of light colors.
+        fn attenuation(light: &Light) -> [f32; 3] {
+            [light.ambient.w, light.diffuse.w, light.specular.w]
+        }
+
+        let directional = lights
+            .directional
+            .iter()
+            .map(|light| LightSpec::Directional {
+                direction: xyz(light.direction),
+                ambient: xyz(light.ambient),
+                diffuse: xyz(light.diffuse),
+                specular: xyz(light.specular),
+            });
+        let point = lights.point.iter().map(|light| LightSpec::Point {
+            position: xyz(light.position),
+            ambient: xyz(light.ambient),
+            diffuse: xyz(light.diffuse),
+            specular: xyz(light.specular),
+            attenuation: attenuation(light),
+        });
+        let spot = lights.spot.iter().map(|light| LightSpec::Spot {
+            position: xyz(light.position),
+            direction: xyz(light.direction),
+            ambient: xyz(light.ambient),
+            diffuse: xyz(light.diffuse),
+            specular: xyz(light.specular),
+            angle: light.position.w.to_degrees(),
+            attenuation: attenuation(light),
+        });
+
+        directional.chain(point).chain(spot).collect()
+    }
+
+    /// Builds the whole test scene, like functions in `test_scenes` do,
+    /// along with models drawn as placeholders until they're loaded.
+    pub fn build(
+        &self,
+        gpu: &Gpu,
+        assets: &mut AssetManager,
+    ) -> Result<(TestScene, Vec<PendingModel>)> {
+        let mut material_atlas = MaterialAtlas::new(gpu);
+        let (scene, named_objects, pending) = self.build_scene(gpu, &mut material_atlas, assets)?;
+
+        let projection = GpuProjection::new(self.projection.perspective(gpu.aspect_ratio()), gpu)?;
+        let camera = GpuCamera::new(self.camera.camera(), &gpu.device)?;
+
+        Ok((
+            (
+                scene,
+                material_atlas,
+                self.lights(),
+                camera,
+                projection,
+                named_objects,
+            ),
+            pending,
+        ))
+    }
+
+    /// Objects, models and materials of the scene. Materials are added to `material_atlas`.
+    /// Models which `assets` didn't load yet are drawn as plain cubes.
+    pub fn build_scene(
+        &self,
+        gpu: &Gpu,
+        material_atlas: &mut MaterialAtlas,
+        assets: &mut AssetManager,
+    ) -> Result<(Scene, HashMap<String, SceneObjectId>, Vec<PendingModel>)> {
+        let mut scene = Scene::default();
+        let mut pending = Vec::new();
+
+        let mut models = HashMap::new();
+        // Models read from the same file share meshes and materials.
+        let mut obj_models = HashMap::new();
+        for (name, source) in &self.models {
+            let obj_key = match source {
+                ModelSource::Obj {
+                    path,
+                    tangent_space,
+                } => Some((canonical_path(path), *tangent_space)),
+                _ => None,
+            };
+            if let Some(model) = obj_key.as_ref().and_then(|key| obj_models.get(key)) {
+                models.insert(name.as_str(), *model);
+                continue;
+            }
+
+            // Settings of the model if it's still loading, so it's swapped in later.
+            let mut loading = None;
+            let builder = match source {
+                ModelSource::Obj {
+                    path,
+                    tangent_space,
+                } => {
+                    let settings = ObjLoaderSettings {
+                        calculate_tangent_space: *tangent_space,
+                    };
+                    let model = assets
+                        .obj_model(path, settings)
+                        .and_then(|model| {
+                            model
+                                .map(|model| model.upload(gpu, material_atlas))
+                                .transpose()
+                        })
+                        .with_context(|| format!("failed to load model {name}"))?;
+
+                    match model {
+                        Some((meshes, materials)) => SceneModelBuilder::default()
+                            .with_meshes(meshes)
+                            .with_local_materials(materials),
+                        None => {
+                            loading = Some((path, settings));
+                            SceneModelBuilder::default().with_meshes(vec![MeshBuilder::new()
+                                .with_geometry(Cube::geometry())
+                                .build()?])
+                        }
+                    }
+                }
+                ModelSource::Cube { textured } => {
+                    let mesh = if *textured {
+                        MeshBuilder::new()
+                            .with_geometry(Cube::geometry_tan_space())
+                            .with_texture_uvs(Cube::uvs())
+                            .build()?
+                    } else {
+                        MeshBuilder::new().with_geometry(Cube::geometry()).build()?
+                    };
+
+                    SceneModelBuilder::default().with_meshes(vec![mesh])
+                }
+                ModelSource::Plane {
+                    textured,
+                    uv_scale,
+                    subdivisions,
+                } => {
+                    let mesh = if *textured {
+                        MeshBuilder::new()
+                            .with_geometry(Plane::geometry_tan_space(*subdivisions))
+                            .with_texture_uvs(
+                                Plane::uvs(*subdivisions)
+                                    .into_iter()
+                                    .map(|uv| uv * *uv_scale)
+                                    .collect(),
+                            )
+                            .build()?
+                    } else {
+                        MeshBuilder::new()
+                            .with_geometry(Plane::geometry(*subdivisions))
+                            .build()?
+                    };
+
+                    SceneModelBuilder::default().with_meshes(vec![mesh])
+                }
+                ModelSource::UVSphere {
+                    slices,
+                    stacks,
+                    textured,
+                } => {
+                    let mesh = if *textured {
+                        MeshBuilder::new()
+                            .with_geometry(UVSphere::geometry_tan_space(*slices, *stacks))
+                            .with_texture_uvs(UVSphere::uvs(*slices, *stacks))
+                            .build()?
+                    } else {
+                        MeshBuilder::new()
+                            .with_geometry(UVSphere::geometry(*slices, *stacks))
+                            .build()?
+                    };
+
+                    SceneModelBuilder::default().with_meshes(vec![mesh])
+                }
+                ModelSource::Icosphere { subdivisions } => {
+                    let mesh = MeshBuilder::new()
+                        .with_geometry(Icosphere::geometry(*subdivisions))
+                        .build()?;
+
+                    SceneModelBuilder::default().with_meshes(vec![mesh])
+                }
+                ModelSource::Torus {
+                    slices,
+                    sides,
+                    ring_radius,
+                    tube_radius,
+                    textured,
+                } => {
+                    let mesh = if *textured {
+                        MeshBuilder::new()
+                            .with_geometry(Torus::geometry_tan_space(
+                                *slices,
+                                *sides,
+                                *ring_radius,
+                                *tube_radius,
+                            ))
+                            .with_texture_uvs(Torus::uvs(
+                                *slices,
+                                *sides,
+                                *ring_radius,
+                                *tube_radius,
+                            ))
+                            .build()?
+                    } else {
+                        MeshBuilder::new()
+                            .with_geometry(Torus::geometry(
+                                *slices,
+                                *sides,
+                                *ring_radius,
+                                *tube_radius,
+                            ))
+                            .build()?
+                    };
+
+                    SceneModelBuilder::default().with_meshes(vec![mesh])
+                }
+                ModelSource::Cylinder { slices, textured } => {
+                    let mesh = if *textured {
+                        MeshBuilder::new()
+                            .with_geometry(Cylinder::geometry_tan_space(*slices))
+                            .with_texture_uvs(Cylinder::uvs(*slices))
+                            .build()?
+                    } else {
+                        MeshBuilder::new()
+                            .with_geometry(Cylinder::geometry(*slices))
+                            .build()?
+                    };
+
+                    SceneModelBuilder::default().with_meshes(vec![mesh])
+                }
+                ModelSource::Cone { slices, textured } => {
+                    let mesh = if *textured {
+                        MeshBuilder::new()
+                            .with_geometry(Cone::geometry_tan_space(*slices))
+                            .with_texture_uvs(Cone::uvs(*slices))
+                            .build()?
+                    } else {
+                        MeshBuilder::new()
+                            .with_geometry(Cone::geometry(*slices))
+                            .build()?
+                    };
+
+                    SceneModelBuilder::default().with_meshes(vec![mesh])
+                }
+                ModelSource::Capsule {
+                    slices,
+                    stacks,
+                    height,
+                    textured,
+                } => {
+                    let mesh = if *textured {
+                        MeshBuilder::new()
+                            .with_geometry(Capsule::geometry_tan_space(*slices, *stacks, *height))
+                            .with_texture_uvs(Capsule::uvs(*slices, *stacks, *height))
+                            .build()?
+                    } else {
+                        MeshBuilder::new()
+                            .with_geometry(Capsule::geometry(*slices, *stacks, *height))
+                            .build()?
+                    };
+
+                    SceneModelBuilder::default().with_meshes(vec![mesh])
+                }
+            };
+
+            let model = scene.load_model(builder)?;
+            if let Some(key) = obj_key {
+                obj_models.insert(key, model);
+            }
+            if let Some((path, settings)) = loading {
+                pending.push(PendingModel {
+                    name: name.clone(),
+                    path: path.clone(),
+                    settings,
+                    model,
+                });
+            }
+            models.insert(name.as_str(), model);
+        }
+
+        let mut materials: HashMap<&str, MaterialId> = HashMap::new();
+        for (name, source) in &self.materials {
+            let material = match source {
+                MaterialSource::Solid {
+                    ambient,
+                    diffuse,
+                    specular,
+                    ..
+                } => material_atlas.add_phong_solid(
+                    gpu,
+                    (*ambient).into(),
+                    (*diffuse).into(),
+                    (*specular).into(),
+                ),
+                MaterialSource::Textured {
+                    diffuse, specular, ..
+                } => material_atlas.add_phong_textured(gpu, diffuse, specular.clone().into()),
+                MaterialSource::TexturedNormal {
+                    diffuse,
+                    specular,
+                    normal,
+                    ..
+                } => material_atlas.add_phong_textured_normal(
+                    gpu,
+                    diffuse,
+                    specular.clone().into(),
+                    normal,
+                ),
+            }
+            .and_then(|material| {
+                material_atlas.set_reflectivity(gpu, material, source.reflectivity())?;
+                material_atlas.set_emissive(gpu, material, source.emissive().into())?;
+                Ok(material)
+            })
+            .with_context(|| format!("failed to create material {name}"))?;
+
+            materials.insert(name.as_str(), material);
+        }
+
+        let mut named_objects = HashMap::new();
+        for object in &self.objects {
+            let model = *models
+                .get(object.model.as_str())
+                .ok_or_else(|| anyhow!("unknown model {}", object.model))?;
+            let instance = Instance::new_model(object.transform.matrix());
+
+            let id = match &object.material {
+                Some(material) => {
+                    let material = *materials
+                        .get(material.as_str())
+                        .ok_or_else(|| anyhow!("unknown material {material}"))?;
+                    scene.add_object_with_material(model, instance, material)
+                }
+                None => scene.add_object(model, instance),
+            };
+
+            if let Some(name) = &object.name {
+                scene.set_object_name(id, name);
+                named_objects.insert(name.clone(), id);
+            }
+        }
+
+        let terrain_chunks = match &self.terrain {
+            Some(terrain) => terrain
+                .add_to_scene(gpu, &mut scene, material_atlas)
+                .context("failed to create terrain")?,
+            None => Vec::new(),
+        };
+
+        for spec in &self.foliage {
+            let surface = match &spec.surface {
+                FoliageSurface::Terrain if self.terrain.is_none() => {
+                    bail!("foliage is scattered over terrain, but the scene has none")
+                }
+                FoliageSurface::Terrain => terrain_chunks.clone(),
+                FoliageSurface::Object(name) => vec![*named_objects
+                    .get(name)
+                    .ok_or_else(|| anyhow!("foliage is scattered over unknown object {name}"))?],
+            };
+
+            let plants = foliage::scatter(
+                surface.iter().flat_map(|&id| scene.object_meshes(id)),
+                &spec.scatter(),
+            );
+            scene.add_foliage(plants);
+        }
+
+        for decal in &self.decals {
+            let material = *materials
+                .get(decal.material.as_str())
+                .ok_or_else(|| anyhow!("unknown decal material {}", decal.material))?;
+            scene.add_decal(
+                Decal::new(decal.transform.matrix(), material).with_opacity(decal.opacity),
+            );
+        }
+
+        Ok((scene, named_objects, pending))
+    }
+
+    pub fn lights(&self) -> LightScene {
+        let mut lights = LightScene::default();
+
+        for light in &self.lights {
+            match *light {
+                LightSpec::Directional {
+                    direction,
+                    ambient,
+                    diffuse,
+                    specular,
+                } => lights.new_directional(
+                    na::Vector3::from(direction).normalize(),
+                    ambient.into(),
+                    diffuse.into(),
+                    specular.into(),
+                ),
+                LightSpec::Point {
+                    position,
+                    ambient,
+                    diffuse,
+                    specular,
+                    attenuation,
+                } => lights.new_point(
+                    position.into(),
+                    ambient.into(),
+                    diffuse.into(),
+                    specular.into(),
+                    attenuation.into(),
+                ),
+                LightSpec::Spot {
+                    position,
+                    direction,
+                    ambient,
+                    diffuse,
+                    specular,
+                    angle,
+                    attenuation,
+                } => lights.new_spot(
+                    position.into(),
+                    na::Vector3::from(direction).normalize(),
+                    ambient.into(),
+                    diffuse.into(),
+                    specular.into(),
+                    angle.to_radians(),
+                    attenuation.into(),
+                ),
+            }
+        }
+
+        lights
+    }
+}
+
+/// Watches a scene script and swaps objects and materials of the rendered scene
+/// whenever the file changes.
+///
+/// Lights are re-uploaded in place whenever they change, but reloads leave them alone,
+/// so lights edited or animated while running keep those changes. The camera is controlled
+/// by the user, so the script's lights, camera and projection only apply at startup.
+pub struct SceneScriptWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    // Objects added at runtime, kept when the script is reloaded.
+    spawned: Vec<ObjectSpec>,
+    report: Option<ValidationReport>,
+    assets: AssetManager,
+    // Placeholders of the current scene, waiting for their models to load.
+    pending: Vec<PendingModel>,
+}
+
+impl SceneScriptWatcher {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let modified = Self::modified_at(&path);
+
+        Self {
+            path,
+            modified,
+            spawned: Vec::new(),
+            report: None,
+            assets: AssetManager::default(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Builds the scene of the watched script. Models keep loading in the background,
+    /// `poll` swaps them into the scene once they're done.
+    pub fn build(&mut self, gpu: &Gpu) -> Result<TestScene> {
+        let (scene, pending) = SceneScript::load(&self.path)?.build(gpu, &mut self.assets)?;
+        self.pending = pending;
+
+        Ok(scene)
+    }
+
+    fn modified_at(path: &Path) -> Option<SystemTime> {
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    /// Switches to another script, which is loaded on the next `poll`.
+    pub fn watch(&mut self, path: impl Into<PathBuf>) {
+        self.path = path.into();
+        self.modified = None;
+        self.spawned.clear();
+    }
+
+    /// Reloads the scene if the script changed since the last call. Returns whether
+    /// the scene was replaced. Models which finished loading in the meantime are swapped
+    /// into the current scene in place of their placeholders, which leaves it as it is otherwise.
+    pub fn poll(&mut self, render_ctx: &RenderContext) -> Result<bool> {
+        let modified = Self::modified_at(&self.path);
+        let models_loaded = self.assets.poll();
+        if modified.is_some() && modified != self.modified {
+            self.modified = modified;
+            self.rebuild(render_ctx)?;
+            return Ok(true);
+        }
+
+        if models_loaded {
+            self.swap_loaded_models(render_ctx)?;
+        }
+        Ok(false)
+    }
+
+    // Models which failed to load keep their placeholders and are reported all together.
+    fn swap_loaded_models(&mut self, render_ctx: &RenderContext) -> Result<()> {
+        let mut material_atlas = render_ctx.material_atlas.write().unwrap();
+        let mut gpu_scene = render_ctx.gpu_scene.write().unwrap();
+        let mut errors = Vec::new();
+
+        for pending in std::mem::take(&mut self.pending) {
+            let swapped = self
+                .assets
+                .obj_model(&pending.path, pending.settings)
+                .and_then(|model| {
+                    let Some(model) = model else {
+                        return Ok(false);
+                    };
+
+                    let (meshes, materials) = model.upload(&render_ctx.gpu, &mut material_atlas)?;
+                    gpu_scene.replace_model(
+                        &render_ctx.gpu,
+                        &mut material_atlas,
+                        pending.model,
+                        SceneModelBuilder::default()
+                            .with_meshes(meshes)
+                            .with_local_materials(materials),
+                    )?;
+                    Ok(true)
+                });
+
+            match swapped {
+                Ok(true) => {}
+                Ok(false) => self.pending.push(pending),
+                Err(e) => errors.push(format!("failed to load model {}: {e:#}", pending.name)),
+            }
+        }
+
+        if !errors.is_empty() {
+            bail!("{}", errors.join("\n"));
+        }
+        Ok(())
+    }
+
+    /// Warnings of models which finished loading since the last call.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        self.assets.take_warnings()
+    }
+
+    /// Validation report of the last rebuilt scene, if it wasn't taken yet.
+    pub fn take_report(&mut self) -> Option<ValidationReport> {
+        self.report.take()
+    }
+
+    /// Adds an instance of a model declared in the script to the scene.
+    pub fn spawn(
+        &mut self,
+        render_ctx: &RenderContext,
+        model: String,
+        translation: [f32; 3],
+        material: Option<String>,
+    ) -> Result<()> {
+        self.spawned.push(ObjectSpec {
+            name: None,
+            model,
+            material,
+            transform: TransformSpec {
+                translation,
+                ..Default::default()
+            },
+        });
+
+        self.rebuild(render_ctx).inspect_err(|_| {
+            self.spawned.pop();
+        })
+    }
+
+    /// Writes the watched script with the current state of the scene to `path`,
+    /// objects spawned at runtime included.
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        render_ctx: &RenderContext,
+        camera: &Camera,
+    ) -> Result<()> {
+        let mut script = SceneScript::load(&self.path)?;
+        script.objects.extend(self.spawned.iter().cloned());
+
+        script
+            .capture(
+                &render_ctx.gpu_scene.read().unwrap(),
+                &render_ctx.material_atlas.read().unwrap(),
+                &render_ctx.light_scene.read().unwrap(),
+                camera,
+            )
+            .with_context(|| format!("scene isn't built from {}", self.path.display()))?;
+
+        script.save(path)
+    }
+
+    /// Adds cameras and mirrors of the watched script to `camera_feed_pass` and puts their materials
+    /// on the objects showing them. Materials of feeds belong to the atlas of the scene,
+    /// so they're added again, after clearing the pass, whenever the scene is replaced.
+    pub fn add_camera_feeds(
+        &self,
+        render_ctx: &RenderContext,
+        camera_feed_pass: &mut CameraFeedPass,
+    ) -> Result<()> {
+        let script = SceneScript::load(&self.path)?;
+        let mut gpu_scene = render_ctx.gpu_scene.write().unwrap();
+
+        for feed in &script.camera_feeds {
+            let perspective = feed.projection.perspective(1.0);
+            let (_, material) = match (&feed.camera, &feed.mirror) {
+                (Some(camera), None) => {
+                    camera_feed_pass.add_feed(camera.camera(), perspective, feed.size)?
+                }
+                (None, Some(mirror)) => camera_feed_pass.add_mirror(
+                    Mirror {
+                        center: mirror.center.into(),
+                        normal: na::Unit::try_new(mirror.normal.into(), f32::EPSILON)
+                            .ok_or_else(|| anyhow!("mirror normal can't be zero"))?,
+                    },
+                    perspective,
+                    feed.size,
+                )?,
+                _ => bail!("camera feed needs either a camera or a mirror"),
+            };
+
+            for name in &feed.objects {
+                let id = gpu_scene
+                    .object_ids()
+                    .find(|&id| gpu_scene.object_name(id) == Some(name.as_str()))
+                    .ok_or_else(|| anyhow!("camera feed is shown on unknown object {name}"))?;
+
+                for slot in 0..gpu_scene.mesh_slots(id).len() {
+                    gpu_scene.override_material(&render_ctx.gpu, id, slot, Some(material))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn rebuild(&mut self, render_ctx: &RenderContext) -> Result<()> {
+        let RenderContext {
+            gpu,
+            gpu_scene,
+            material_atlas,
+            ..
+        } = render_ctx;
+
+        let mut script = SceneScript::load(&self.path)?;
+        script.objects.extend(self.spawned.iter().cloned());
+
+        // Built aside so a broken script leaves the current scene untouched.
+        let mut new_atlas = MaterialAtlas::sharing_layouts(gpu, &material_atlas.read().unwrap());
+        let (mut scene, _, pending) = script.build_scene(gpu, &mut new_atlas, &mut self.assets)?;
+        scene.assign_fallback_materials(gpu, &mut new_atlas)?;
+        let report = ValidationReport::new(&scene, &new_atlas);
+        let new_scene = GpuScene::new(gpu, scene)?;
+
+        *material_atlas.write().unwrap() = new_atlas;
+        *gpu_scene.write().unwrap() = new_scene;
+        self.report = Some(report);
+        self.pending = pending;
+
+        Ok(())
+    }
+}
@@ -1,59 +1,50 @@
-use crate::{camera::GpuCamera, gpu::Gpu, projection::GpuProjection};
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use encase::{ShaderSize, UniformBuffer};
+use nalgebra as na;
+
+use crate::{
+    bind_group_layouts::BindGroupLayouts, camera::GpuCamera, gpu::Gpu, projection::GpuProjection,
+    settings::FogSettings,
+};
+
+// Fields of `SceneFog` in the shader.
+type Fog = [na::Vector4<f32>; 2];
+
+// Fields of `SceneFrame` in the shader: columns of the inverse view projection,
+// camera position with time, and viewport size with delta time.
+type Frame = [na::Vector4<f32>; 6];
 
 pub struct SceneUniform {
     scene_bg: wgpu::BindGroup,
-    scene_bgl: wgpu::BindGroupLayout,
+    scene_bgl: Arc<wgpu::BindGroupLayout>,
+    fog_buf: wgpu::Buffer,
+    frame_buf: wgpu::Buffer,
 }
 
 impl SceneUniform {
-    pub fn new(gpu: &Gpu, camera: &GpuCamera, projection: &GpuProjection) -> Self {
-        let scene_bgl = gpu
-            .device
-            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("Scene::BindGroupLayout"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 3,
-                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                ],
-            });
+    pub fn new(
+        gpu: &Gpu,
+        bind_group_layouts: &BindGroupLayouts,
+        camera: &GpuCamera,
+        projection: &GpuProjection,
+    ) -> Self {
+        // Zeroed fog is turned off until settings are written.
+        let fog_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Scene::Fog"),
+            size: Fog::SHADER_SIZE.into(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let frame_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Scene::Frame"),
+            size: Frame::SHADER_SIZE.into(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let scene_bgl = bind_group_layouts.scene(gpu);
 
         let scene_bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Scene::BindGroup"),
@@ -75,15 +66,98 @@ impl SceneUniform {
                     binding: 3,
                     resource: projection.inverse_buffer().as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: fog_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: frame_buf.as_entire_binding(),
+                },
             ],
         });
 
         Self {
             scene_bg,
             scene_bgl,
+            fog_buf,
+            frame_buf,
         }
     }
 
+    // Camera and projection with their inverses, fog, then values changing every frame.
+    pub(crate) fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 6] {
+        [0, 1, 2, 3, 4, 5].map(|binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        })
+    }
+
+    /// Distance and height fog lighting shaders blend surfaces into.
+    pub fn write_fog(&self, queue: &wgpu::Queue, settings: &FogSettings) -> Result<()> {
+        let [r, g, b] = settings.color;
+        let fog: Fog = [
+            na::Vector4::new(
+                settings.density,
+                settings.height_density,
+                settings.height_falloff,
+                settings.base_height,
+            ),
+            na::Vector4::new(r, g, b, if settings.enabled { 1.0 } else { 0.0 }),
+        ];
+
+        let size: u64 = Fog::SHADER_SIZE.into();
+        let mut contents = UniformBuffer::new(Vec::with_capacity(size as usize));
+        contents.write(&fog)?;
+        queue.write_buffer(&self.fog_buf, 0, contents.into_inner().as_slice());
+
+        Ok(())
+    }
+
+    /// Time, viewport and what's derived from the camera and projection, so shaders
+    /// don't have to work it out per pixel. Written after both are updated for the frame.
+    pub fn write_frame(
+        &self,
+        gpu: &Gpu,
+        camera: &GpuCamera,
+        projection: &GpuProjection,
+        elapsed: Duration,
+        delta: Duration,
+    ) -> Result<()> {
+        let inverse = (projection.gpu_matrix() * camera.look_at_matrix())
+            .try_inverse()
+            .ok_or_else(|| anyhow!("failed to invert view projection matrix"))?;
+        let eye = camera.camera().eye();
+        let viewport = gpu.viewport_size();
+        let frame: Frame = [
+            inverse.column(0).into_owned(),
+            inverse.column(1).into_owned(),
+            inverse.column(2).into_owned(),
+            inverse.column(3).into_owned(),
+            na::Vector4::new(eye.x, eye.y, eye.z, elapsed.as_secs_f32()),
+            na::Vector4::new(
+                viewport.width as f32,
+                viewport.height as f32,
+                delta.as_secs_f32(),
+                0.0,
+            ),
+        ];
+
+        let size: u64 = Frame::SHADER_SIZE.into();
+        let mut contents = UniformBuffer::new(Vec::with_capacity(size as usize));
+        contents.write(&frame)?;
+        gpu.queue
+            .write_buffer(&self.frame_buf, 0, contents.into_inner().as_slice());
+
+        Ok(())
+    }
+
     pub fn bind_group(&self) -> &wgpu::BindGroup {
         &self.scene_bg
     }
@@ -0,0 +1,159 @@
+use std::collections::BTreeSet;
+
+use nalgebra as na;
+
+use crate::{
+    material::{Material, MaterialAtlas, MAX_SHININESS},
+    mesh::MeshVertexArrayType,
+    scene::Scene,
+};
+
+/// Statistics and problems found in a scene when it's loaded. None of the problems
+/// stop the scene from loading, but they usually end up rendering wrong.
+pub struct ValidationReport {
+    pub stats: Vec<String>,
+    pub issues: Vec<String>,
+}
+
+impl ValidationReport {
+    pub fn new(scene: &Scene, atlas: &MaterialAtlas) -> Self {
+        let mut issues = Vec::new();
+        let mut used = BTreeSet::new();
+        let mut mesh_uses = 0;
+
+        for mesh_use in scene.mesh_uses() {
+            mesh_uses += 1;
+
+            let mesh_name = match mesh_use.name {
+                Some(name) => format!("{:?} ({})", mesh_use.object_id, name),
+                None => format!("{:?} mesh {}", mesh_use.object_id, mesh_use.slot),
+            };
+
+            let Some(material_id) = mesh_use.material_id else {
+                issues.push(format!("{} has no material", mesh_name));
+                continue;
+            };
+            used.insert(material_id);
+
+            if atlas.is_fallback(material_id) {
+                issues.push(format!(
+                    "{} has no material, drawn with a fallback",
+                    mesh_name
+                ));
+                continue;
+            }
+
+            let mesh_type = mesh_use.mesh.vertex_array_type();
+            let material_type = atlas.material(material_id).vertex_array_type();
+            match (mesh_type, material_type) {
+                (
+                    MeshVertexArrayType::PN,
+                    MeshVertexArrayType::PNUV | MeshVertexArrayType::PNTBUV,
+                ) => issues.push(format!(
+                    "{} has no UVs, but {:?} is textured",
+                    mesh_name, material_id
+                )),
+                (MeshVertexArrayType::PNUV, MeshVertexArrayType::PNTBUV) => issues.push(format!(
+                    "{} has no tangents, but {:?} is normal mapped",
+                    mesh_name, material_id
+                )),
+                (mesh_type, material_type) if mesh_type != material_type => issues.push(format!(
+                    "{} has {:?} vertices, but {:?} is drawn with {:?}",
+                    mesh_name, mesh_type, material_id, material_type
+                )),
+                _ => {}
+            }
+        }
+
+        for (idx, mesh) in scene.unique_meshes().iter().enumerate() {
+            let degenerate = mesh.degenerate_tangents();
+            if degenerate > 0 {
+                issues.push(format!(
+                    "Mesh {} ({}) has {} of {} vertices with degenerate tangents",
+                    idx,
+                    mesh.name().unwrap_or("unnamed"),
+                    degenerate,
+                    mesh.num_vertices()
+                ));
+            }
+        }
+
+        for material_id in atlas.material_ids() {
+            if !used.contains(&material_id) && !atlas.is_fallback(material_id) {
+                issues.push(format!("{:?} is unused", material_id));
+            }
+
+            if let Some(shininess) = Self::shininess(atlas.material(material_id)) {
+                if !(1.0..=MAX_SHININESS).contains(&shininess) {
+                    issues.push(format!(
+                        "{:?} has shininess {}, outside of 1..={}",
+                        material_id, shininess, MAX_SHININESS
+                    ));
+                }
+            }
+        }
+
+        let meshes = scene.unique_meshes();
+        let triangles: usize = meshes
+            .iter()
+            .map(|mesh| mesh.num_indices().unwrap_or(mesh.num_vertices()) / 3)
+            .sum();
+
+        let stats = vec![
+            format!("Objects: {}", scene.num_objects()),
+            format!("Meshes: {} drawn, {} unique", mesh_uses, meshes.len()),
+            format!(
+                "Unique vertices: {}",
+                meshes.iter().map(|mesh| mesh.num_vertices()).sum::<usize>()
+            ),
+            format!("Unique triangles: {}", triangles),
+            format!(
+                "Materials: {} ({} used)",
+                atlas.material_ids().count(),
+                used.len()
+            ),
+        ];
+
+        Self { stats, issues }
+    }
+
+    // Materials without a specular term have nothing to validate.
+    fn shininess(material: &Material) -> Option<f32> {
+        match material {
+            Material::PhongSolid { specular, .. } => {
+                (specular.xyz() != na::Vector3::zeros()).then_some(specular.w)
+            }
+            Material::PhongTextured { specular, .. }
+            | Material::PhongTexturedNormal { specular, .. }
+            | Material::PhongSplat { specular, .. } => specular.shininess(),
+        }
+    }
+
+    pub fn render(&self, ctx: &egui::Context) {
+        let title = match self.issues.len() {
+            0 => "Scene Report".to_owned(),
+            count => format!("Scene Report ({} issues)", count),
+        };
+
+        egui::Window::new(title)
+            .id(egui::Id::new("scene_report"))
+            .default_open(false)
+            .show(ctx, |ui| {
+                for line in &self.stats {
+                    ui.label(line);
+                }
+
+                if !self.issues.is_empty() {
+                    ui.separator();
+                }
+
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        for issue in &self.issues {
+                            ui.colored_label(egui::Color32::YELLOW, issue);
+                        }
+                    });
+            });
+    }
+}
@@ -0,0 +1,121 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use crate::gpu::Gpu;
+
+/// Layout of an RGBA8 texture copied into a buffer. Rows of a texture to buffer copy
+/// have to be aligned, so they are padded in the buffer.
+#[derive(Clone, Copy)]
+pub struct Readback {
+    pub width: u32,
+    pub height: u32,
+    unpadded_row: u32,
+    padded_row: u32,
+    swizzle: bool,
+}
+
+impl Readback {
+    pub fn new(texture: &wgpu::Texture) -> Result<Self> {
+        let format = texture.format();
+        let swizzle = match format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            _ => bail!("readback of {:?} textures is not supported", format),
+        };
+
+        let (width, height) = (texture.width(), texture.height());
+        let unpadded_row = width * 4;
+
+        Ok(Self {
+            width,
+            height,
+            unpadded_row,
+            padded_row: unpadded_row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT),
+            swizzle,
+        })
+    }
+
+    pub fn buffer_size(&self) -> u64 {
+        (self.padded_row * self.height) as u64
+    }
+
+    pub fn copy(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        buffer: &wgpu::Buffer,
+    ) {
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_row),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            texture.size(),
+        );
+    }
+
+    /// Tightly packed RGBA pixels out of the mapped buffer.
+    pub fn pixels(&self, mapped: &[u8]) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity((self.unpadded_row * self.height) as usize);
+        for row in mapped.chunks(self.padded_row as usize) {
+            pixels.extend_from_slice(&row[..self.unpadded_row as usize]);
+        }
+
+        if self.swizzle {
+            pixels
+                .chunks_exact_mut(4)
+                .for_each(|pixel| pixel.swap(0, 2));
+        }
+
+        pixels
+    }
+
+    pub fn save(&self, pixels: Vec<u8>, path: impl AsRef<Path>) -> Result<()> {
+        image::RgbaImage::from_raw(self.width, self.height, pixels)
+            .expect("readback buffer matches the image size")
+            .save(path)?;
+
+        Ok(())
+    }
+}
+
+/// Reads back a swapchain texture and saves it as an image. Format is picked from the extension.
+/// Blocks until the GPU finishes all submitted work.
+pub fn save(gpu: &Gpu, texture: &wgpu::Texture, path: impl AsRef<Path>) -> Result<()> {
+    let readback = Readback::new(texture)?;
+
+    let buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Screenshot::ReadbackBuffer"),
+        size: readback.buffer_size(),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = gpu
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Screenshot::CommandEncoder"),
+        });
+
+    readback.copy(&mut encoder, texture, &buffer);
+    gpu.queue.submit(Some(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        sender.send(result).ok();
+    });
+    gpu.device.poll(wgpu::Maintain::Wait);
+    receiver.recv()??;
+
+    let pixels = readback.pixels(&slice.get_mapped_range());
+    buffer.unmap();
+
+    readback.save(pixels, path)
+}
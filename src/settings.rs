@@ -1,53 +1,567 @@
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
 use egui::ComboBox;
+use serde::{Deserialize, Serialize};
+use winit::window::{Fullscreen, Window};
 
-use crate::{deferred::DeferredDebug, postprocess_pass::PostprocessSettings};
+use crate::{
+    compute::GaussianKernel,
+    deferred::{DeferredDebug, SsaoPass},
+    gpu::{AdapterOptions, PresentMode},
+    light_shafts_pass::LightShaftsPass,
+    motion_blur_pass::MotionBlurPass,
+    perf_graph::PerfGraph,
+    postprocess_pass::PostprocessSettings,
+    shader_compiler::CompilationUnit,
+    shader_permutation::ShaderPermutation,
+    skybox_pass::{BackgroundMode, BackgroundSettings},
+};
 
-#[derive(Debug, Default, PartialEq, Eq)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, ValueEnum)]
 pub enum PipelineType {
     Forward,
     #[default]
     Deferred,
 }
 
-#[derive(Default)]
+// How the forward pipeline finds lights reaching a fragment.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum LightCulling {
+    // View space froxels, binned without looking at the depth buffer.
+    #[default]
+    Clustered,
+    // Screen tiles bounded by the depth of the prepass.
+    Tiled,
+}
+
+impl LightCulling {
+    pub const ALL: [Self; 2] = [Self::Clustered, Self::Tiled];
+
+    pub fn shader_defs(&self) -> &'static [&'static str] {
+        match self {
+            Self::Clustered => &[],
+            Self::Tiled => &["TILED"],
+        }
+    }
+}
+
+impl ShaderPermutation for LightCulling {
+    fn all() -> Vec<Self> {
+        Self::ALL.to_vec()
+    }
+
+    fn apply(&self, unit: CompilationUnit) -> CompilationUnit {
+        self.shader_defs()
+            .iter()
+            .fold(unit, |unit, def| unit.with_def(*def))
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AppSettings {
-    pub skybox_disabled: bool,
+    pub background: BackgroundSettings,
     pub depth_prepass_enabled: bool,
+    pub light_culling: LightCulling,
     postprocess: PostprocessSettings,
+    pub auto_exposure: AutoExposureSettings,
+    pub motion_blur: MotionBlurSettings,
+    pub light_shafts: LightShaftsSettings,
+    pub water: WaterSettings,
+    pub foliage: FoliageSettings,
+    pub decals: DecalSettings,
+    pub billboards: BillboardSettings,
+    pub volumetric_fog: VolumetricFogSettings,
+    pub fog: FogSettings,
     pub pipeline_type: PipelineType,
     pub postprocess_disabled: bool,
     pub ssao: SsaoSettings,
+    pub shadows: ShadowSettings,
     pub deferred_dbg: DeferredDebugState,
+    pub normals_dbg: NormalsDebugSettings,
+    // Applied to the surface by the frame loop before the next frame.
+    pub present_mode: PresentMode,
+    pub display: DisplaySettings,
+    pub adapter: AdapterOptions,
+    pub show_perf_graph: bool,
+    #[serde(skip)]
+    perf_graph: PerfGraph,
+    // Only read when creating the Gpu.
+    pub reverse_z: bool,
 }
 
-#[derive(Default, PartialEq, Eq)]
+#[derive(Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplaySettings {
+    pub fullscreen: bool,
+    // Switches the video mode of the monitor instead of covering it with a borderless window.
+    pub exclusive: bool,
+    // Name of the monitor to go fullscreen on, the one showing the window when missing.
+    pub monitor: Option<String>,
+}
+
+impl DisplaySettings {
+    /// What to pass to `Window::set_fullscreen`. Exclusive fullscreen uses the biggest
+    /// video mode with the highest refresh rate, monitors without any get a borderless window.
+    pub fn fullscreen(&self, window: &Window) -> Option<Fullscreen> {
+        if !self.fullscreen {
+            return None;
+        }
+
+        let monitor = self
+            .monitor
+            .as_ref()
+            .and_then(|name| {
+                window
+                    .available_monitors()
+                    .find(|monitor| monitor.name().as_ref() == Some(name))
+            })
+            .or_else(|| window.current_monitor());
+        let video_mode = monitor
+            .iter()
+            .flat_map(|monitor| monitor.video_modes())
+            .max_by_key(|mode| {
+                (
+                    mode.size().width * mode.size().height,
+                    mode.refresh_rate_millihertz(),
+                )
+            });
+
+        match video_mode {
+            Some(mode) if self.exclusive => Some(Fullscreen::Exclusive(mode)),
+            _ => Some(Fullscreen::Borderless(monitor)),
+        }
+    }
+}
+
+#[derive(Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct DeferredDebugState {
     pub enabled: bool,
     pub debug_type: DeferredDebug,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct NormalsDebugSettings {
+    pub enabled: bool,
+    // World space length of drawn vectors.
+    pub length: f32,
+}
+
+impl Default for NormalsDebugSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            length: 0.1,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum AoBackend {
+    #[default]
+    Ssao,
+    Gtao,
+}
+
+// Resolution occlusion is computed at, relative to the viewport.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum AoResolution {
+    #[default]
+    Full,
+    // Bilaterally upsampled to the viewport afterwards, roughly a quarter of the cost.
+    Half,
+}
+
+impl AoResolution {
+    // Viewport dimensions are divided by it.
+    pub fn divisor(&self) -> u32 {
+        match self {
+            Self::Full => 1,
+            Self::Half => 2,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum ShadowFiltering {
+    // Comparison sampler, filtering 2x2 texels.
+    Hardware,
+    #[default]
+    Pcf,
+    // Percentage-closer soft shadows.
+    Pcss,
+    // Variance shadow maps, blurred depth moments.
+    Vsm,
+}
+
+impl ShadowFiltering {
+    pub const ALL: [Self; 4] = [Self::Hardware, Self::Pcf, Self::Pcss, Self::Vsm];
+
+    pub fn shader_def(&self) -> &'static str {
+        match self {
+            Self::Hardware => "SHADOW_FILTER_HARDWARE",
+            Self::Pcf => "SHADOW_FILTER_PCF",
+            Self::Pcss => "SHADOW_FILTER_PCSS",
+            Self::Vsm => "SHADOW_FILTER_VSM",
+        }
+    }
+}
+
+impl ShaderPermutation for ShadowFiltering {
+    fn all() -> Vec<Self> {
+        Self::ALL.to_vec()
+    }
+
+    fn apply(&self, unit: CompilationUnit) -> CompilationUnit {
+        unit.with_def(self.shader_def())
+    }
+}
+
+// Choices for the resolution of a shadow cascade.
+const CASCADE_RESOLUTIONS: [u32; 5] = [256, 512, 1024, 2048, 4096];
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShadowSettings {
+    pub filtering: ShadowFiltering,
+    // From the nearest cascade to the farthest.
+    pub cascade_resolutions: [u32; 3],
+    pub pcf_kernel_size: u32,
+    pub light_size: f32,
+    pub vsm_blur_radius: u32,
+    pub vsm_blur_sigma: f32,
+    pub vsm_blur_iterations: u32,
+    // Part of the lowest light amounts cut off by VSM, hides light bleeding
+    // between overlapping occluders.
+    pub vsm_bleeding_reduction: f32,
+    // Tint fragments by cascade and outline light space bounds of cascades.
+    pub debug_cascades: bool,
+    pub debug_cascade_bounds: bool,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            filtering: ShadowFiltering::default(),
+            cascade_resolutions: [2048, 1024, 512],
+            pcf_kernel_size: 3,
+            light_size: 0.01,
+            vsm_blur_radius: 2,
+            vsm_blur_sigma: 1.0,
+            vsm_blur_iterations: 1,
+            vsm_bleeding_reduction: 0.2,
+            debug_cascades: false,
+            debug_cascade_bounds: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct SsaoSettings {
     enabled: bool,
-    num_samples: u32,
-    radius: f32,
-    blur_filter_size: u32,
-    blur_iterations: u32,
+    pub backend: AoBackend,
+    pub resolution: AoResolution,
+    pub num_samples: u32,
+    // Directions horizons are searched in by GTAO, samples are split between them.
+    pub gtao_slices: u32,
+    pub radius: f32,
+    pub bias: f32,
+    pub intensity: f32,
+    pub blur_radius: u32,
+    pub blur_sigma: f32,
+    pub blur_iterations: u32,
+    // Accumulate occlusion over frames, rotating the kernel every frame.
+    pub temporal: bool,
+    // Part of the accumulated occlusion kept every frame.
+    pub history_weight: f32,
 }
 
 impl Default for SsaoSettings {
     fn default() -> Self {
         Self {
             enabled: true,
+            backend: AoBackend::default(),
+            resolution: AoResolution::default(),
             num_samples: 64,
+            gtao_slices: 4,
             radius: 0.5,
-            blur_filter_size: 4,
-            blur_iterations: 1,
+            bias: 0.075,
+            intensity: 1.0,
+            blur_radius: 2,
+            blur_sigma: 1.5,
+            blur_iterations: 8,
+            temporal: false,
+            history_weight: 0.9,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoExposureSettings {
+    pub enabled: bool,
+    // In stops, on top of the measured exposure.
+    pub compensation: f32,
+    // How quickly the exposure follows the brightness of the scene, per second.
+    pub adaptation_speed: f32,
+    // Range of measured luminance, as log2 of it.
+    pub min_log_luminance: f32,
+    pub max_log_luminance: f32,
+}
+
+impl Default for AutoExposureSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            compensation: 0.0,
+            adaptation_speed: 1.5,
+            min_log_luminance: -8.0,
+            max_log_luminance: 4.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct MotionBlurSettings {
+    pub enabled: bool,
+    // Fraction of the motion since the previous frame that gets blurred.
+    pub intensity: f32,
+    pub samples: u32,
+}
+
+impl Default for MotionBlurSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intensity: 1.0,
+            samples: 8,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct LightShaftsSettings {
+    pub enabled: bool,
+    // Brightness of the shafts, relative to the diffuse color of the sun.
+    pub intensity: f32,
+    // Weight of every next sample towards the sun, shortening the shafts.
+    pub decay: f32,
+    pub samples: u32,
+}
+
+impl Default for LightShaftsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intensity: 0.5,
+            decay: 0.97,
+            samples: 64,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct WaterSettings {
+    pub enabled: bool,
+    pub height: f32,
+    // Side of the square water plane, centered on the origin.
+    pub size: f32,
+    // Color of deep water, seen where the bottom is too far to show through.
+    pub color: [f32; 3],
+    // Depth below the surface over which the bottom fades out.
+    pub clarity: f32,
+    // World units a tile of the wave normal map covers.
+    pub wave_scale: f32,
+    // Normal map tiles the waves move per second.
+    pub wave_speed: f32,
+    // Screen space offset of reflection and refraction by the waves.
+    pub distortion: f32,
+}
+
+impl Default for WaterSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            height: 0.0,
+            size: 200.0,
+            color: [0.02, 0.1, 0.12],
+            clarity: 2.0,
+            wave_scale: 8.0,
+            wave_speed: 0.02,
+            distortion: 0.02,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct FoliageSettings {
+    pub enabled: bool,
+    // Plants further from the camera are culled.
+    pub distance: f32,
+    // How far tops of plants sway, relative to their height.
+    pub wind: f32,
+    // Texels less opaque than that are discarded.
+    pub alpha_cutoff: f32,
+}
+
+impl Default for FoliageSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            distance: 80.0,
+            wind: 0.1,
+            alpha_cutoff: 0.5,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct DecalSettings {
+    pub enabled: bool,
+    // Degrees between normals of surfaces and the projection axis past which decals fade out.
+    pub fade_angle: f32,
+}
+
+impl Default for DecalSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            fade_angle: 60.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct BillboardSettings {
+    pub enabled: bool,
+    // World units in front of geometry over which sprites fade out instead of being cut off.
+    pub softness: f32,
+}
+
+impl Default for BillboardSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            softness: 0.5,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct VolumetricFogSettings {
+    pub enabled: bool,
+    // Extinction per world unit at the base height.
+    pub density: f32,
+    // How quickly the fog thins out above the base height.
+    pub height_falloff: f32,
+    pub base_height: f32,
+    // Henyey-Greenstein anisotropy, positive values scatter sunlight forward.
+    pub anisotropy: f32,
+    // Sunlight scattered by the fog, relative to the diffuse color of the sun.
+    pub intensity: f32,
+    // Unshadowed part of the scattered light.
+    pub ambient: f32,
+    // Distance from the camera the fog volume covers.
+    pub range: f32,
+}
+
+impl Default for VolumetricFogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            density: 0.02,
+            height_falloff: 0.1,
+            base_height: 0.0,
+            anisotropy: 0.6,
+            intensity: 1.0,
+            ambient: 0.1,
+            range: 100.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct FogSettings {
+    pub enabled: bool,
+    pub color: [f32; 3],
+    // Extinction per world unit everywhere.
+    pub density: f32,
+    // Extinction per world unit at the base height, thinning out above it.
+    pub height_density: f32,
+    pub height_falloff: f32,
+    pub base_height: f32,
+}
+
+impl Default for FogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            color: [0.6, 0.65, 0.7],
+            density: 0.005,
+            height_density: 0.05,
+            height_falloff: 0.2,
+            base_height: 0.0,
         }
     }
 }
 
 impl AppSettings {
-    pub fn render(&mut self, ctx: &egui::Context, time_delta: f32) {
+    /// Deferred debug output replaces the background and postprocessing.
+    pub fn deferred_debug_shown(&self) -> bool {
+        self.pipeline_type == PipelineType::Deferred && self.deferred_dbg.enabled
+    }
+
+    /// Tiles are bounded by the depth of the prepass, so without it lights are clustered.
+    pub fn active_light_culling(&self) -> LightCulling {
+        if self.depth_prepass_enabled {
+            self.light_culling
+        } else {
+            LightCulling::Clustered
+        }
+    }
+
+    /// Settings missing from the file keep their default values.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read settings {}", path.display()))?;
+
+        ron::from_str(&source)
+            .with_context(|| format!("failed to parse settings {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let source = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+
+        std::fs::write(path, source)
+            .with_context(|| format!("failed to write settings {}", path.display()))
+    }
+
+    /// `monitors` are names of monitors to choose from for fullscreen, `gpu_timings`
+    /// are milliseconds passes took on the GPU, for the performance graph.
+    pub fn render(
+        &mut self,
+        ctx: &egui::Context,
+        time_delta: f32,
+        monitors: &[String],
+        gpu_timings: &[(&'static str, f32)],
+    ) {
+        self.perf_graph.push(time_delta * 1000.0);
+
         egui::Window::new("General")
             .resizable(false)
             .show(ctx, |ui| {
@@ -70,8 +584,282 @@ impl AppSettings {
                         );
                     });
 
-                ui.checkbox(&mut self.skybox_disabled, "Disable Skybox");
                 ui.checkbox(&mut self.postprocess_disabled, "Disable Postprocess");
+                ui.checkbox(&mut self.depth_prepass_enabled, "Do Depth Prepass");
+                ComboBox::from_label("Light Culling")
+                    .selected_text(format!("{:?}", self.light_culling))
+                    .show_ui(ui, |ui| {
+                        for culling in LightCulling::ALL {
+                            ui.selectable_value(
+                                &mut self.light_culling,
+                                culling,
+                                format!("{culling:?}"),
+                            );
+                        }
+                    });
+                if self.light_culling == LightCulling::Tiled && !self.depth_prepass_enabled {
+                    ui.label("Tiled culling needs the depth prepass, lights are clustered.");
+                }
+                ui.checkbox(&mut self.reverse_z, "Reverse Z (after restart)");
+
+                ComboBox::from_label("Present Mode")
+                    .selected_text(format!("{:?}", self.present_mode))
+                    .show_ui(ui, |ui| {
+                        for mode in PresentMode::ALL {
+                            ui.selectable_value(
+                                &mut self.present_mode,
+                                mode,
+                                format!("{:?}", mode),
+                            );
+                        }
+                    });
+
+                ui.checkbox(&mut self.display.fullscreen, "Fullscreen");
+                ui.checkbox(&mut self.display.exclusive, "Exclusive Fullscreen");
+                ComboBox::from_label("Monitor")
+                    .selected_text(self.display.monitor.as_deref().unwrap_or("Current"))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.display.monitor, None, "Current");
+                        for name in monitors {
+                            ui.selectable_value(
+                                &mut self.display.monitor,
+                                Some(name.clone()),
+                                name,
+                            );
+                        }
+                    });
+            });
+
+        egui::Window::new("Background")
+            .default_open(false)
+            .show(ctx, |ui| {
+                ComboBox::from_label("Mode")
+                    .selected_text(match self.background.mode {
+                        BackgroundMode::Skybox => "Skybox",
+                        BackgroundMode::SolidColor => "Solid Color",
+                        BackgroundMode::Gradient => "Gradient",
+                        BackgroundMode::ProceduralSky => "Procedural Sky",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(
+                            &mut self.background.mode,
+                            BackgroundMode::Skybox,
+                            "Skybox",
+                        );
+                        ui.selectable_value(
+                            &mut self.background.mode,
+                            BackgroundMode::SolidColor,
+                            "Solid Color",
+                        );
+                        ui.selectable_value(
+                            &mut self.background.mode,
+                            BackgroundMode::Gradient,
+                            "Gradient",
+                        );
+                        ui.selectable_value(
+                            &mut self.background.mode,
+                            BackgroundMode::ProceduralSky,
+                            "Procedural Sky",
+                        );
+                    });
+
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_rgb(&mut self.background.clear_color);
+                    ui.label("Clear Color");
+                });
+
+                if self.background.mode == BackgroundMode::Gradient {
+                    ui.horizontal(|ui| {
+                        ui.color_edit_button_rgb(&mut self.background.gradient_top);
+                        ui.label("Sky");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.color_edit_button_rgb(&mut self.background.gradient_bottom);
+                        ui.label("Ground");
+                    });
+                }
+
+                if self.background.mode == BackgroundMode::ProceduralSky {
+                    let sky = &mut self.background.sky;
+                    ui.label("Drives the first directional light.");
+                    ui.add(egui::Slider::new(&mut sky.time_of_day, 0.0..=24.0).text("Time of Day"));
+                    ui.add(
+                        egui::Slider::new(&mut sky.day_length, 0.0..=600.0).text("Day Length (s)"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut sky.azimuth, 0.0..=360.0).text("Sunrise Azimuth"),
+                    );
+                    ui.add(egui::Slider::new(&mut sky.tilt, 0.0..=80.0).text("Noon Tilt"));
+                    ui.add(egui::Slider::new(&mut sky.turbidity, 1.7..=10.0).text("Turbidity"));
+                    ui.add(egui::Slider::new(&mut sky.intensity, 0.0..=4.0).text("Intensity"));
+                }
+            });
+
+        egui::Window::new("Shadows")
+            .default_open(false)
+            .show(ctx, |ui| {
+                ui.label("Cascade Resolutions");
+                for (i, resolution) in self.shadows.cascade_resolutions.iter_mut().enumerate() {
+                    ComboBox::from_id_source(("cascade_resolution", i))
+                        .selected_text(resolution.to_string())
+                        .show_ui(ui, |ui| {
+                            for option in CASCADE_RESOLUTIONS {
+                                ui.selectable_value(resolution, option, option.to_string());
+                            }
+                        });
+                }
+
+                ui.label("Filtering");
+                ComboBox::from_id_source("shadow_filtering")
+                    .selected_text(match self.shadows.filtering {
+                        ShadowFiltering::Hardware => "Hardware",
+                        ShadowFiltering::Pcf => "PCF",
+                        ShadowFiltering::Pcss => "PCSS",
+                        ShadowFiltering::Vsm => "VSM",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(
+                            &mut self.shadows.filtering,
+                            ShadowFiltering::Hardware,
+                            "Hardware",
+                        );
+                        ui.selectable_value(
+                            &mut self.shadows.filtering,
+                            ShadowFiltering::Pcf,
+                            "PCF",
+                        );
+                        ui.selectable_value(
+                            &mut self.shadows.filtering,
+                            ShadowFiltering::Pcss,
+                            "PCSS",
+                        );
+                        ui.selectable_value(
+                            &mut self.shadows.filtering,
+                            ShadowFiltering::Vsm,
+                            "VSM",
+                        );
+                    });
+
+                match self.shadows.filtering {
+                    ShadowFiltering::Hardware => {}
+                    ShadowFiltering::Pcf => {
+                        ui.label("Kernel Size");
+                        ui.add(
+                            egui::DragValue::new(&mut self.shadows.pcf_kernel_size)
+                                .speed(1)
+                                .clamp_range(1..=9),
+                        );
+                    }
+                    ShadowFiltering::Pcss => {
+                        ui.label("Light Size");
+                        ui.add(
+                            egui::DragValue::new(&mut self.shadows.light_size)
+                                .speed(0.0005)
+                                .clamp_range(0.0..=0.1),
+                        );
+                    }
+                    ShadowFiltering::Vsm => {
+                        ui.label("Blur Radius");
+                        ui.add(
+                            egui::DragValue::new(&mut self.shadows.vsm_blur_radius)
+                                .speed(1)
+                                .clamp_range(0..=7),
+                        );
+                        ui.label("Blur Sigma");
+                        ui.add(
+                            egui::DragValue::new(&mut self.shadows.vsm_blur_sigma)
+                                .speed(0.05)
+                                .clamp_range(0.1..=8.0),
+                        );
+                        ui.label("Blur Iterations");
+                        ui.add(
+                            egui::DragValue::new(&mut self.shadows.vsm_blur_iterations)
+                                .speed(1)
+                                .clamp_range(1..=4),
+                        );
+                        ui.label("Bleeding Reduction");
+                        ui.add(
+                            egui::DragValue::new(&mut self.shadows.vsm_bleeding_reduction)
+                                .speed(0.01)
+                                .clamp_range(0.0..=0.9),
+                        );
+                    }
+                }
+
+                ui.separator();
+                ui.checkbox(&mut self.shadows.debug_cascades, "Tint Cascades");
+                ui.checkbox(&mut self.shadows.debug_cascade_bounds, "Cascade Bounds");
+            });
+
+        egui::Window::new("Fog")
+            .default_open(false)
+            .show(ctx, |ui| {
+                ui.checkbox(&mut self.fog.enabled, "Enable");
+                ui.label("Color");
+                ui.color_edit_button_rgb(&mut self.fog.color);
+                ui.label("Density");
+                ui.add(
+                    egui::DragValue::new(&mut self.fog.density)
+                        .speed(0.0005)
+                        .clamp_range(0.0..=1.0),
+                );
+                ui.separator();
+                ui.label("Height Density");
+                ui.add(
+                    egui::DragValue::new(&mut self.fog.height_density)
+                        .speed(0.001)
+                        .clamp_range(0.0..=1.0),
+                );
+                ui.label("Height Falloff");
+                ui.add(
+                    egui::DragValue::new(&mut self.fog.height_falloff)
+                        .speed(0.005)
+                        .clamp_range(0.001..=10.0),
+                );
+                ui.label("Base Height");
+                ui.add(egui::DragValue::new(&mut self.fog.base_height).speed(0.1));
+            });
+
+        egui::Window::new("Volumetric Fog")
+            .default_open(false)
+            .show(ctx, |ui| {
+                ui.checkbox(&mut self.volumetric_fog.enabled, "Enable");
+                ui.label("Density");
+                ui.add(
+                    egui::DragValue::new(&mut self.volumetric_fog.density)
+                        .speed(0.001)
+                        .clamp_range(0.0..=1.0),
+                );
+                ui.label("Height Falloff");
+                ui.add(
+                    egui::DragValue::new(&mut self.volumetric_fog.height_falloff)
+                        .speed(0.005)
+                        .clamp_range(0.0..=10.0),
+                );
+                ui.label("Base Height");
+                ui.add(egui::DragValue::new(&mut self.volumetric_fog.base_height).speed(0.1));
+                ui.label("Anisotropy");
+                ui.add(egui::Slider::new(
+                    &mut self.volumetric_fog.anisotropy,
+                    -0.95..=0.95,
+                ));
+                ui.label("Intensity");
+                ui.add(
+                    egui::DragValue::new(&mut self.volumetric_fog.intensity)
+                        .speed(0.01)
+                        .clamp_range(0.0..=16.0),
+                );
+                ui.label("Ambient");
+                ui.add(egui::Slider::new(
+                    &mut self.volumetric_fog.ambient,
+                    0.0..=1.0,
+                ));
+                ui.label("Range");
+                ui.add(
+                    egui::DragValue::new(&mut self.volumetric_fog.range)
+                        .speed(0.5)
+                        .clamp_range(1.0..=1000.0),
+                );
             });
 
         if self.pipeline_type == PipelineType::Deferred {
@@ -79,23 +867,77 @@ impl AppSettings {
                 .default_open(false)
                 .show(ctx, |ui| {
                     ui.checkbox(&mut self.ssao.enabled, "Enable");
+                    ui.label("Method");
+                    ComboBox::from_id_source("ao_backend")
+                        .selected_text(match self.ssao.backend {
+                            AoBackend::Ssao => "SSAO",
+                            AoBackend::Gtao => "GTAO",
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.ssao.backend, AoBackend::Ssao, "SSAO");
+                            ui.selectable_value(&mut self.ssao.backend, AoBackend::Gtao, "GTAO");
+                        });
+                    ui.label("Resolution");
+                    ComboBox::from_id_source("ao_resolution")
+                        .selected_text(match self.ssao.resolution {
+                            AoResolution::Full => "Full",
+                            AoResolution::Half => "Half",
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(
+                                &mut self.ssao.resolution,
+                                AoResolution::Full,
+                                "Full",
+                            );
+                            ui.selectable_value(
+                                &mut self.ssao.resolution,
+                                AoResolution::Half,
+                                "Half",
+                            );
+                        });
                     ui.label("Kernel Size");
                     ui.add(
                         egui::DragValue::new(&mut self.ssao.num_samples)
                             .speed(1)
-                            .clamp_range(4..=256),
+                            .clamp_range(4..=SsaoPass::MAX_SAMPLES),
                     );
+                    if self.ssao.backend == AoBackend::Gtao {
+                        ui.label("Slices");
+                        ui.add(
+                            egui::DragValue::new(&mut self.ssao.gtao_slices)
+                                .speed(1)
+                                .clamp_range(1..=SsaoPass::MAX_GTAO_SLICES),
+                        );
+                    }
                     ui.label("Radius");
                     ui.add(
                         egui::DragValue::new(&mut self.ssao.radius)
                             .speed(0.01)
                             .clamp_range(0.0..=100.0),
                     );
-                    ui.label("Blur Filter Size");
+                    ui.label("Bias");
+                    ui.add(
+                        egui::DragValue::new(&mut self.ssao.bias)
+                            .speed(0.001)
+                            .clamp_range(0.0..=1.0),
+                    );
+                    ui.label("Intensity");
+                    ui.add(
+                        egui::DragValue::new(&mut self.ssao.intensity)
+                            .speed(0.01)
+                            .clamp_range(0.0..=10.0),
+                    );
+                    ui.label("Blur Radius");
                     ui.add(
-                        egui::DragValue::new(&mut self.ssao.blur_filter_size)
+                        egui::DragValue::new(&mut self.ssao.blur_radius)
                             .speed(1)
-                            .clamp_range(2..=128),
+                            .clamp_range(0..=GaussianKernel::MAX_RADIUS),
+                    );
+                    ui.label("Blur Sigma");
+                    ui.add(
+                        egui::DragValue::new(&mut self.ssao.blur_sigma)
+                            .speed(0.05)
+                            .clamp_range(0.1..=16.0),
                     );
                     ui.label("Blur Iterations");
                     ui.add(
@@ -103,6 +945,13 @@ impl AppSettings {
                             .speed(1)
                             .clamp_range(1..=100),
                     );
+                    ui.checkbox(&mut self.ssao.temporal, "Temporal Accumulation");
+                    ui.label("History Weight");
+                    ui.add(
+                        egui::DragValue::new(&mut self.ssao.history_weight)
+                            .speed(0.01)
+                            .clamp_range(0.0..=0.98),
+                    );
                 });
 
             egui::Window::new("Debug")
@@ -117,6 +966,7 @@ impl AppSettings {
                             DeferredDebug::Specular => "Specular",
                             DeferredDebug::Depth => "Depth",
                             DeferredDebug::AmbientOcclusion => "Ambient Occlusion",
+                            DeferredDebug::QuadSplit => "Quad Split",
                         })
                         .show_ui(ui, |ui| {
                             ui.selectable_value(
@@ -146,21 +996,54 @@ impl AppSettings {
                                     "SSAO",
                                 );
                             }
+                            ui.selectable_value(
+                                &mut self.deferred_dbg.debug_type,
+                                DeferredDebug::QuadSplit,
+                                "Quad Split",
+                            );
                         });
                 });
         }
 
-        if self.pipeline_type == PipelineType::Forward {
-            egui::Window::new("Forward")
-                .default_open(false)
-                .show(ctx, |ui| {
-                    ui.checkbox(&mut self.depth_prepass_enabled, "Do Depth Prepass");
-                });
-        }
+        egui::Window::new("Vertex Vectors")
+            .default_open(false)
+            .show(ctx, |ui| {
+                ui.checkbox(&mut self.normals_dbg.enabled, "Enable");
+                ui.label("Length");
+                ui.add(
+                    egui::DragValue::new(&mut self.normals_dbg.length)
+                        .speed(0.005)
+                        .clamp_range(0.001..=10.0),
+                );
+                ui.label("Normals are blue, tangents red and bitangents green.");
+            });
 
         egui::Window::new("Postprocess")
             .default_open(false)
             .show(ctx, |ui| {
+                ui.label("Effects, applied from the top");
+                let chain = self.postprocess.chain_mut();
+                let len = chain.len();
+                let mut swapped = None;
+                for (i, stage) in chain.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(i > 0, egui::Button::new("Up")).clicked() {
+                            swapped = Some(i - 1);
+                        }
+                        if ui
+                            .add_enabled(i + 1 < len, egui::Button::new("Down"))
+                            .clicked()
+                        {
+                            swapped = Some(i);
+                        }
+                        ui.checkbox(&mut stage.enabled, stage.effect.label());
+                    });
+                }
+                if let Some(i) = swapped {
+                    chain.swap(i, i + 1);
+                }
+
+                ui.separator();
                 ui.label("Saturation");
                 ui.add(egui::DragValue::new(self.postprocess.saturation_mut()).speed(0.01));
                 ui.label("Brightness");
@@ -169,14 +1052,433 @@ impl AppSettings {
                 ui.add(egui::DragValue::new(self.postprocess.contrast_mut()).speed(0.01));
                 ui.label("Gamma");
                 ui.add(egui::DragValue::new(self.postprocess.gamma_mut()).speed(0.01));
+                ui.label("Vignette");
+                ui.add(egui::Slider::new(
+                    self.postprocess.vignette_mut(),
+                    0.0..=1.0,
+                ));
+                ui.label("Film Grain");
+                ui.add(egui::Slider::new(
+                    self.postprocess.film_grain_mut(),
+                    0.0..=0.5,
+                ));
+                ui.label("Chromatic Aberration");
+                ui.add(egui::Slider::new(
+                    self.postprocess.chromatic_aberration_mut(),
+                    0.0..=0.05,
+                ));
+                ui.label("Sharpen");
+                ui.add(egui::Slider::new(self.postprocess.sharpen_mut(), 0.0..=2.0));
+
+                ui.separator();
+                ui.checkbox(&mut self.auto_exposure.enabled, "Auto Exposure");
+                ui.label("Measured from HDR frames of the deferred pipeline.");
+                ui.label("Compensation (stops)");
+                ui.add(
+                    egui::DragValue::new(&mut self.auto_exposure.compensation)
+                        .speed(0.05)
+                        .clamp_range(-8.0..=8.0),
+                );
+                ui.label("Adaptation Speed");
+                ui.add(
+                    egui::DragValue::new(&mut self.auto_exposure.adaptation_speed)
+                        .speed(0.05)
+                        .clamp_range(0.05..=20.0),
+                );
+                ui.label("Luminance Range (log2)");
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut self.auto_exposure.min_log_luminance)
+                            .speed(0.1)
+                            .clamp_range(-16.0..=self.auto_exposure.max_log_luminance - 1.0),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut self.auto_exposure.max_log_luminance)
+                            .speed(0.1)
+                            .clamp_range(self.auto_exposure.min_log_luminance + 1.0..=16.0),
+                    );
+                });
+
+                ui.separator();
+                ui.checkbox(&mut self.motion_blur.enabled, "Motion Blur");
+                ui.label("Blurs along motion vectors of the deferred pipeline.");
+                ui.label("Intensity");
+                ui.add(
+                    egui::DragValue::new(&mut self.motion_blur.intensity)
+                        .speed(0.01)
+                        .clamp_range(0.0..=4.0),
+                );
+                ui.label("Samples");
+                ui.add(egui::Slider::new(
+                    &mut self.motion_blur.samples,
+                    2..=MotionBlurPass::MAX_SAMPLES,
+                ));
+
+                ui.separator();
+                ui.checkbox(&mut self.light_shafts.enabled, "Light Shafts");
+                ui.label("Shafts of sunlight through gaps in geometry, deferred pipeline only.");
+                ui.label("Intensity");
+                ui.add(
+                    egui::DragValue::new(&mut self.light_shafts.intensity)
+                        .speed(0.01)
+                        .clamp_range(0.0..=4.0),
+                );
+                ui.label("Decay");
+                ui.add(egui::Slider::new(&mut self.light_shafts.decay, 0.8..=1.0));
+                ui.label("Samples");
+                ui.add(egui::Slider::new(
+                    &mut self.light_shafts.samples,
+                    8..=LightShaftsPass::MAX_SAMPLES,
+                ));
+
+                ui.separator();
+                ui.checkbox(&mut self.water.enabled, "Water");
+                ui.label("Reflective water plane, deferred pipeline only.");
+                ui.label("Height");
+                ui.add(egui::DragValue::new(&mut self.water.height).speed(0.05));
+                ui.label("Size");
+                ui.add(
+                    egui::DragValue::new(&mut self.water.size)
+                        .speed(1.0)
+                        .clamp_range(1.0..=10000.0),
+                );
+                ui.label("Color");
+                ui.color_edit_button_rgb(&mut self.water.color);
+                ui.label("Clarity");
+                ui.add(
+                    egui::DragValue::new(&mut self.water.clarity)
+                        .speed(0.05)
+                        .clamp_range(0.05..=100.0),
+                );
+                ui.label("Wave Scale");
+                ui.add(
+                    egui::DragValue::new(&mut self.water.wave_scale)
+                        .speed(0.1)
+                        .clamp_range(0.1..=100.0),
+                );
+                ui.label("Wave Speed");
+                ui.add(egui::Slider::new(&mut self.water.wave_speed, 0.0..=0.2));
+                ui.label("Distortion");
+                ui.add(egui::Slider::new(&mut self.water.distortion, 0.0..=0.1));
+
+                ui.separator();
+                ui.checkbox(&mut self.foliage.enabled, "Foliage");
+                ui.label("Plants scattered by the scene, culled on the GPU.");
+                ui.label("Distance");
+                ui.add(
+                    egui::DragValue::new(&mut self.foliage.distance)
+                        .speed(1.0)
+                        .clamp_range(1.0..=1000.0),
+                );
+                ui.label("Wind");
+                ui.add(egui::Slider::new(&mut self.foliage.wind, 0.0..=0.5));
+                ui.label("Alpha Cutoff");
+                ui.add(egui::Slider::new(
+                    &mut self.foliage.alpha_cutoff,
+                    0.05..=0.95,
+                ));
+
+                ui.separator();
+                ui.checkbox(&mut self.decals.enabled, "Decals");
+                ui.label("Projected onto G-Buffers, deferred pipeline only.");
+                ui.label("Fade Angle");
+                ui.add(egui::Slider::new(&mut self.decals.fade_angle, 0.0..=90.0));
+
+                ui.separator();
+                ui.checkbox(&mut self.billboards.enabled, "Billboards");
+                ui.label("Sprites of light icons and markers.");
+                ui.label("Softness");
+                ui.add(egui::Slider::new(&mut self.billboards.softness, 0.0..=2.0));
             });
 
         egui::Window::new("Info").show(ctx, |ui| {
             ui.label(format!("FPS: {:.2}", 1.0 / time_delta));
+            ui.checkbox(&mut self.show_perf_graph, "Performance Graph");
+            if self.show_perf_graph {
+                self.perf_graph.ui(ui, gpu_timings);
+            }
         });
     }
 
     pub fn postprocess_settings(&self) -> &PostprocessSettings {
         &self.postprocess
     }
+
+    /// Changes a setting by name, for use outside of the settings windows.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        fn parse<T: std::str::FromStr>(value: &str) -> Result<T> {
+            value.parse().map_err(|_| anyhow!("invalid value {value}"))
+        }
+
+        match name {
+            "pipeline" => {
+                self.pipeline_type = match value {
+                    "forward" => PipelineType::Forward,
+                    "deferred" => PipelineType::Deferred,
+                    _ => bail!("expected forward or deferred"),
+                }
+            }
+            "present_mode" => {
+                self.present_mode = match value {
+                    "fifo" => PresentMode::Fifo,
+                    "mailbox" => PresentMode::Mailbox,
+                    "immediate" => PresentMode::Immediate,
+                    _ => bail!("expected fifo, mailbox or immediate"),
+                }
+            }
+            "background" => {
+                self.background.mode = match value {
+                    "skybox" => BackgroundMode::Skybox,
+                    "solid" => BackgroundMode::SolidColor,
+                    "gradient" => BackgroundMode::Gradient,
+                    "sky" => BackgroundMode::ProceduralSky,
+                    _ => bail!("expected skybox, solid, gradient or sky"),
+                }
+            }
+            "light_culling" => {
+                self.light_culling = match value {
+                    "clustered" => LightCulling::Clustered,
+                    "tiled" => LightCulling::Tiled,
+                    _ => bail!("expected clustered or tiled"),
+                }
+            }
+            "shadow_filtering" => {
+                self.shadows.filtering = match value {
+                    "hardware" => ShadowFiltering::Hardware,
+                    "pcf" => ShadowFiltering::Pcf,
+                    "pcss" => ShadowFiltering::Pcss,
+                    "vsm" => ShadowFiltering::Vsm,
+                    _ => bail!("expected hardware, pcf, pcss or vsm"),
+                }
+            }
+            "cascade_resolutions" => {
+                let resolutions = value
+                    .split(',')
+                    .map(|v| {
+                        parse::<u32>(v).and_then(|v| {
+                            if CASCADE_RESOLUTIONS.contains(&v) {
+                                Ok(v)
+                            } else {
+                                bail!("resolution has to be one of {:?}", CASCADE_RESOLUTIONS)
+                            }
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                self.shadows.cascade_resolutions = resolutions
+                    .try_into()
+                    .map_err(|_| anyhow!("expected 3 comma separated resolutions"))?;
+            }
+            "pcf_kernel_size" => self.shadows.pcf_kernel_size = parse::<u32>(value)?.clamp(1, 9),
+            "light_size" => self.shadows.light_size = parse::<f32>(value)?.clamp(0.0, 0.1),
+            "vsm_blur_radius" => self.shadows.vsm_blur_radius = parse::<u32>(value)?.clamp(0, 7),
+            "vsm_blur_sigma" => self.shadows.vsm_blur_sigma = parse::<f32>(value)?.clamp(0.1, 8.0),
+            "vsm_blur_iterations" => {
+                self.shadows.vsm_blur_iterations = parse::<u32>(value)?.clamp(1, 4)
+            }
+            "vsm_bleeding_reduction" => {
+                self.shadows.vsm_bleeding_reduction = parse::<f32>(value)?.clamp(0.0, 0.9)
+            }
+            "ssao_samples" => {
+                self.ssao.num_samples = parse::<u32>(value)?.clamp(4, SsaoPass::MAX_SAMPLES)
+            }
+            "gtao_slices" => {
+                self.ssao.gtao_slices = parse::<u32>(value)?.clamp(1, SsaoPass::MAX_GTAO_SLICES)
+            }
+            "ssao_radius" => self.ssao.radius = parse(value)?,
+            "ssao_bias" => self.ssao.bias = parse(value)?,
+            "ssao_intensity" => self.ssao.intensity = parse(value)?,
+            "ssao_resolution" => {
+                self.ssao.resolution = match value {
+                    "full" => AoResolution::Full,
+                    "half" => AoResolution::Half,
+                    _ => bail!("expected full or half"),
+                }
+            }
+            "ssao_history_weight" => {
+                self.ssao.history_weight = parse::<f32>(value)?.clamp(0.0, 0.98)
+            }
+            "saturation" => *self.postprocess.saturation_mut() = parse(value)?,
+            "brightness" => *self.postprocess.brightness_mut() = parse(value)?,
+            "contrast" => *self.postprocess.contrast_mut() = parse(value)?,
+            "gamma" => *self.postprocess.gamma_mut() = parse(value)?,
+            "postprocess_chain" => self.postprocess.set_chain(value)?,
+            "vignette" => *self.postprocess.vignette_mut() = parse::<f32>(value)?.clamp(0.0, 1.0),
+            "film_grain" => {
+                *self.postprocess.film_grain_mut() = parse::<f32>(value)?.clamp(0.0, 0.5)
+            }
+            "chromatic_aberration" => {
+                *self.postprocess.chromatic_aberration_mut() = parse::<f32>(value)?.clamp(0.0, 0.05)
+            }
+            "sharpen" => *self.postprocess.sharpen_mut() = parse::<f32>(value)?.clamp(0.0, 2.0),
+            "exposure_compensation" => {
+                self.auto_exposure.compensation = parse::<f32>(value)?.clamp(-8.0, 8.0)
+            }
+            "motion_blur_intensity" => {
+                self.motion_blur.intensity = parse::<f32>(value)?.clamp(0.0, 4.0)
+            }
+            "motion_blur_samples" => {
+                self.motion_blur.samples =
+                    parse::<u32>(value)?.clamp(2, MotionBlurPass::MAX_SAMPLES)
+            }
+            "light_shafts_intensity" => {
+                self.light_shafts.intensity = parse::<f32>(value)?.clamp(0.0, 4.0)
+            }
+            "light_shafts_decay" => self.light_shafts.decay = parse::<f32>(value)?.clamp(0.8, 1.0),
+            "water_height" => self.water.height = parse(value)?,
+            "water_clarity" => self.water.clarity = parse::<f32>(value)?.clamp(0.05, 100.0),
+            "foliage_distance" => self.foliage.distance = parse::<f32>(value)?.clamp(1.0, 1000.0),
+            "distance_fog_density" => self.fog.density = parse::<f32>(value)?.clamp(0.0, 1.0),
+            "height_fog_density" => self.fog.height_density = parse::<f32>(value)?.clamp(0.0, 1.0),
+            "fog_density" => self.volumetric_fog.density = parse::<f32>(value)?.clamp(0.0, 1.0),
+            "fog_anisotropy" => {
+                self.volumetric_fog.anisotropy = parse::<f32>(value)?.clamp(-0.95, 0.95)
+            }
+            "fog_range" => self.volumetric_fog.range = parse::<f32>(value)?.clamp(1.0, 1000.0),
+            _ => bail!("unknown setting {name}"),
+        }
+
+        Ok(())
+    }
+
+    pub const SETTING_NAMES: [&'static str; 41] = [
+        "pipeline",
+        "present_mode",
+        "background",
+        "light_culling",
+        "shadow_filtering",
+        "cascade_resolutions",
+        "pcf_kernel_size",
+        "light_size",
+        "vsm_blur_radius",
+        "vsm_blur_sigma",
+        "vsm_blur_iterations",
+        "vsm_bleeding_reduction",
+        "ssao_samples",
+        "gtao_slices",
+        "ssao_radius",
+        "ssao_bias",
+        "ssao_intensity",
+        "ssao_resolution",
+        "ssao_history_weight",
+        "saturation",
+        "brightness",
+        "contrast",
+        "gamma",
+        "postprocess_chain",
+        "vignette",
+        "film_grain",
+        "chromatic_aberration",
+        "sharpen",
+        "exposure_compensation",
+        "motion_blur_intensity",
+        "motion_blur_samples",
+        "light_shafts_intensity",
+        "light_shafts_decay",
+        "water_height",
+        "water_clarity",
+        "foliage_distance",
+        "distance_fog_density",
+        "height_fog_density",
+        "fog_density",
+        "fog_anisotropy",
+        "fog_range",
+    ];
+
+    pub const TOGGLE_NAMES: [&'static str; 17] = [
+        "fullscreen",
+        "postprocess",
+        "depth_prepass",
+        "ssao",
+        "ssao_temporal",
+        "deferred_debug",
+        "cascades_debug",
+        "cascade_bounds",
+        "auto_exposure",
+        "motion_blur",
+        "light_shafts",
+        "water",
+        "foliage",
+        "decals",
+        "billboards",
+        "fog",
+        "volumetric_fog",
+    ];
+
+    /// Flips an optional pass, or fullscreen, on or off. Returns whether it's enabled now.
+    pub fn toggle(&mut self, name: &str) -> Result<bool> {
+        let enabled = match name {
+            "fullscreen" => {
+                self.display.fullscreen = !self.display.fullscreen;
+                self.display.fullscreen
+            }
+            "postprocess" => {
+                self.postprocess_disabled = !self.postprocess_disabled;
+                !self.postprocess_disabled
+            }
+            "depth_prepass" => {
+                self.depth_prepass_enabled = !self.depth_prepass_enabled;
+                self.depth_prepass_enabled
+            }
+            "ssao" => {
+                self.ssao.enabled = !self.ssao.enabled;
+                self.ssao.enabled
+            }
+            "ssao_temporal" => {
+                self.ssao.temporal = !self.ssao.temporal;
+                self.ssao.temporal
+            }
+            "deferred_debug" => {
+                self.deferred_dbg.enabled = !self.deferred_dbg.enabled;
+                self.deferred_dbg.enabled
+            }
+            "cascades_debug" => {
+                self.shadows.debug_cascades = !self.shadows.debug_cascades;
+                self.shadows.debug_cascades
+            }
+            "cascade_bounds" => {
+                self.shadows.debug_cascade_bounds = !self.shadows.debug_cascade_bounds;
+                self.shadows.debug_cascade_bounds
+            }
+            "auto_exposure" => {
+                self.auto_exposure.enabled = !self.auto_exposure.enabled;
+                self.auto_exposure.enabled
+            }
+            "motion_blur" => {
+                self.motion_blur.enabled = !self.motion_blur.enabled;
+                self.motion_blur.enabled
+            }
+            "light_shafts" => {
+                self.light_shafts.enabled = !self.light_shafts.enabled;
+                self.light_shafts.enabled
+            }
+            "water" => {
+                self.water.enabled = !self.water.enabled;
+                self.water.enabled
+            }
+            "foliage" => {
+                self.foliage.enabled = !self.foliage.enabled;
+                self.foliage.enabled
+            }
+            "decals" => {
+                self.decals.enabled = !self.decals.enabled;
+                self.decals.enabled
+            }
+            "billboards" => {
+                self.billboards.enabled = !self.billboards.enabled;
+                self.billboards.enabled
+            }
+            "fog" => {
+                self.fog.enabled = !self.fog.enabled;
+                self.fog.enabled
+            }
+            "volumetric_fog" => {
+                self.volumetric_fog.enabled = !self.volumetric_fog.enabled;
+                self.volumetric_fog.enabled
+            }
+            _ => bail!("unknown pass {name}"),
+        };
+
+        Ok(enabled)
+    }
 }
@@ -0,0 +1,80 @@
+use std::{
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use fnv::FnvHasher;
+use wgpu::naga;
+
+use crate::shader_compiler::ShaderKey;
+
+// Bumped whenever entries written before can't be read the same way anymore.
+const FORMAT_VERSION: u32 = 1;
+
+pub(crate) fn hash(value: &impl Hash) -> u64 {
+    let mut hasher = FnvHasher::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Composed shaders kept on disk between runs, so they don't have to go through
+/// naga_oil again. Modules are written back as flat WGSL, which is a lot cheaper
+/// to parse than composing it with all the imports and definitions.
+///
+/// There is a file for every shader and set of definitions. It remembers the hash
+/// of the sources it was composed from, imported modules included, and is only used
+/// while they stay the same, being overwritten once compiled again after changes.
+pub struct ShaderCache {
+    dir: PathBuf,
+}
+
+impl ShaderCache {
+    pub fn new(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_owned();
+        std::fs::create_dir_all(&dir).context(format!(
+            "failed to create shader cache in {}",
+            dir.display()
+        ))?;
+
+        Ok(Self { dir })
+    }
+
+    fn entry_path(&self, key: &ShaderKey) -> PathBuf {
+        self.dir.join(format!("{:016x}.wgsl", hash(key)))
+    }
+
+    fn header(source_hash: u64) -> String {
+        format!(
+            "// gpu-basics shader cache v{} {:016x}\n",
+            FORMAT_VERSION, source_hash
+        )
+    }
+
+    /// `None` when there's no entry, or it was written from different sources.
+    pub(crate) fn load(&self, key: &ShaderKey, source_hash: u64) -> Option<naga::Module> {
+        let contents = std::fs::read_to_string(self.entry_path(key)).ok()?;
+        let source = contents.strip_prefix(&Self::header(source_hash))?;
+
+        naga::front::wgsl::parse_str(source).ok()
+    }
+
+    pub(crate) fn store(
+        &self,
+        key: &ShaderKey,
+        source_hash: u64,
+        module: &naga::Module,
+    ) -> Result<()> {
+        let info = naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(module)?;
+        let source =
+            naga::back::wgsl::write_string(module, &info, naga::back::wgsl::WriterFlags::empty())?;
+
+        let path = self.entry_path(key);
+        std::fs::write(&path, Self::header(source_hash) + &source)
+            .context(format!("failed to write {}", path.display()))
+    }
+}
@@ -1,14 +1,27 @@
 use anyhow::{Context, Result};
 use naga_oil::compose::{
-    ComposableModuleDescriptor, Composer, NagaModuleDescriptor, ShaderDefValue,
+    ComposableModuleDescriptor, Composer, ComposerError, NagaModuleDescriptor, ShaderDefValue,
+};
+use wgpu::naga;
+
+use crate::{
+    shader_cache::{hash, ShaderCache},
+    shader_permutation::{ShaderPermutation, Variants},
 };
 
 struct ShaderCompilerInner {
     composer: Composer,
+    // Composable modules which failed to parse, every shader could be importing them.
+    module_errors: Vec<ShaderError>,
+    // Hashes of sources of every module along with modules it imports.
+    module_hashes: HashMap<String, u64>,
+    cache: Option<ShaderCache>,
+    // Shaders which compiled, but couldn't be written to the cache.
+    cache_errors: Vec<anyhow::Error>,
 }
 
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
@@ -39,6 +52,31 @@ fn topological_depth_first(
     Ok(())
 }
 
+// Imports of a shader as written, with the items imported from a module.
+fn imports(contents: &str) -> Vec<&str> {
+    let mut imports = vec![];
+    let mut pos = 0;
+    while let Some(import_pos) = contents[pos..].find("#import ") {
+        let import = contents[pos + import_pos + "#import ".len()..]
+            .split_terminator(';')
+            .next()
+            .unwrap();
+
+        imports.push(import);
+        pos += import_pos + "#import ".len();
+    }
+
+    imports
+}
+
+// Module an import is made from, when there's such module.
+fn imported_module<'a>(
+    import: &str,
+    mut modules: impl Iterator<Item = &'a String>,
+) -> Option<&'a String> {
+    modules.find(|module| import.starts_with(module.as_str()))
+}
+
 fn construct_graphs(
     root: impl AsRef<Path>,
 ) -> (HashMap<String, PathBuf>, HashMap<String, Vec<String>>) {
@@ -84,25 +122,18 @@ fn construct_graphs(
             module_to_file.insert(module_name.to_owned(), shader_file);
             module_graph.entry(module_name.to_owned()).or_default();
 
-            let mut pos = 0;
-            while let Some(import_pos) = contents[pos..].find("#import ") {
-                let import = contents[pos + import_pos + "#import ".len()..]
-                    .split_terminator(';')
-                    .next()
-                    .unwrap();
-
+            for import in imports(&contents) {
                 module_graph
                     .entry(module_name.to_owned())
                     .or_default()
                     .push(import.to_owned());
-                pos += import_pos + "#import ".len();
             }
         }
     }
 
     for (module, imports) in module_graph.iter_mut() {
         for import in imports.iter_mut() {
-            let proper_mod_name = module_to_file.keys().find(|x| import.starts_with(*x));
+            let proper_mod_name = imported_module(import, module_to_file.keys());
 
             if let Some(proper_mod_name) = proper_mod_name {
                 *import = proper_mod_name.clone();
@@ -140,6 +171,198 @@ fn sorted_modules(graph: &HashMap<String, Vec<String>>) -> Result<Vec<String>> {
     Ok(sorted_nodes.into_iter().collect())
 }
 
+/// Shader which failed to compose or validate, pointing at where in which file it went
+/// wrong. Returned by `CompilationUnit::compile` wrapped in `anyhow::Error`, so it can be
+/// found in the chain with `downcast_ref`.
+#[derive(Debug, Clone)]
+pub struct ShaderError {
+    /// File the error comes from, which can be a module imported by the compiled one.
+    pub path: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+    /// Definitions the shader was compiled with, as `NAME` or `NAME=value`.
+    pub defs: Vec<String>,
+    /// Diagnostic with the offending source lines, as naga_oil prints it.
+    pub report: String,
+}
+
+impl ShaderError {
+    fn new(
+        error: &ComposerError,
+        composer: &Composer,
+        defs: &HashMap<String, ShaderDefValue>,
+    ) -> Self {
+        let report = strip_ansi(&error.emit_to_string(composer));
+        let location = report
+            .lines()
+            .find_map(|line| line.trim_start().strip_prefix("┌─ "))
+            .map(|location| location.trim().rsplitn(3, ':').collect::<Vec<_>>());
+
+        let (path, line, column) = match location.as_deref() {
+            Some([column, line, path]) => {
+                (path.to_string(), line.parse().ok(), column.parse().ok())
+            }
+            _ => (error.source.path(composer).clone(), None, None),
+        };
+
+        let mut defs = defs
+            .iter()
+            .map(|(name, value)| match value {
+                ShaderDefValue::Bool(true) => name.clone(),
+                ShaderDefValue::Bool(false) => format!("{}=false", name),
+                ShaderDefValue::Int(value) => format!("{}={}", name, value),
+                ShaderDefValue::UInt(value) => format!("{}={}", name, value),
+            })
+            .collect::<Vec<_>>();
+        defs.sort();
+
+        Self {
+            path,
+            line,
+            column,
+            message: error.inner.to_string(),
+            defs,
+            report,
+        }
+    }
+}
+
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path)?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        if let Some(column) = self.column {
+            write!(f, ":{}", column)?;
+        }
+        write!(f, ": {}", self.message)?;
+        if !self.defs.is_empty() {
+            write!(f, " (defs: {})", self.defs.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+// Reports are colored for terminals, which is noise anywhere else.
+fn strip_ansi(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // Control sequences end with a letter, like `m` of colors.
+            chars.find(|c| c.is_ascii_alphabetic());
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+// naga can't parse `override` declarations yet, so they become private variables
+// initialized with the default value, by rewriting the source text line by line.
+// Unlike constants, uses of them aren't folded into literals, so `specialize` can
+// change the value after the shader is composed.
+fn lower_overrides(contents: &str) -> String {
+    contents
+        .lines()
+        .map(|line| {
+            let declaration = line.trim_start();
+            match declaration.strip_prefix("override ") {
+                Some(rest) => format!(
+                    "{}var<private> {}",
+                    &line[..line.len() - declaration.len()],
+                    rest
+                ),
+                None => line.to_owned(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Overrides of imported modules would be renamed by naga_oil along with everything else
+// they declare, so `specialize` couldn't find them.
+fn module_override(file: &Path, contents: &str) -> Option<ShaderError> {
+    let (line, column, source) = contents.lines().enumerate().find_map(|(idx, line)| {
+        let declaration = line.trim_start();
+        declaration
+            .starts_with("override ")
+            .then(|| (idx + 1, line.len() - declaration.len() + 1, line))
+    })?;
+
+    let path = file.display().to_string();
+    let message = "override declarations are only supported in shaders compiled directly, \
+        not in imported modules"
+        .to_string();
+    let report = format!(
+        "error: {}\n  ┌─ {}:{}:{}\n  │\n{:>3} │ {}\n",
+        message, path, line, column, line, source
+    );
+
+    Some(ShaderError {
+        path,
+        line: Some(line),
+        column: Some(column),
+        message,
+        defs: vec![],
+        report,
+    })
+}
+
+/// Sets values of pipeline-overridable constants, declared with `override` in the
+/// compiled shader itself, like `override GTAO_SLICES: u32 = 4u;`. Values are converted
+/// to the declared type, the same way `wgpu` converts pipeline constants.
+///
+/// This emulates pipeline constants, which wgpu 0.19 doesn't have. Overrides are private
+/// variables of the composed module, so every set of values gets a `wgpu::ShaderModule`
+/// of its own, made from a specialized copy of the module, see `PipelineCache`.
+/// Only shader files compiled directly can declare them, imported modules are rejected
+/// as naga_oil renames their variables.
+///
+/// Overrides can be used wherever a `var<private>` can, which leaves out array sizes
+/// and workgroup sizes. Those still need shader definitions.
+pub fn specialize(module: &mut naga::Module, constants: &[(&str, f64)]) -> Result<()> {
+    for (name, value) in constants {
+        let (_, global) = module
+            .global_variables
+            .iter_mut()
+            .find(|(_, global)| {
+                global.space == naga::AddressSpace::Private && global.name.as_deref() == Some(name)
+            })
+            .ok_or_else(|| anyhow::anyhow!("shader doesn't override {}", name))?;
+
+        let literal = match module.types[global.ty].inner {
+            naga::TypeInner::Scalar(naga::Scalar::BOOL) => naga::Literal::Bool(*value != 0.0),
+            naga::TypeInner::Scalar(naga::Scalar::F32) => naga::Literal::F32(*value as f32),
+            naga::TypeInner::Scalar(naga::Scalar::U32) => naga::Literal::U32(*value as u32),
+            naga::TypeInner::Scalar(naga::Scalar::I32) => naga::Literal::I32(*value as i32),
+            _ => anyhow::bail!("override {} isn't a bool or a 32 bit scalar", name),
+        };
+
+        global.init = Some(
+            module
+                .const_expressions
+                .append(naga::Expression::Literal(literal), naga::Span::UNDEFINED),
+        );
+    }
+
+    Ok(())
+}
+
+/// Shader file along with definitions it's compiled with, equal for units which
+/// compile to the same module.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderKey {
+    path: PathBuf,
+    defs: BTreeMap<String, ShaderDefValue>,
+}
+
 #[derive(Clone)]
 pub struct CompilationUnit {
     contents: String,
@@ -149,13 +372,13 @@ pub struct CompilationUnit {
 }
 
 impl CompilationUnit {
-    pub fn new(instance: ShaderCompilerInstance, path: impl AsRef<Path>) -> Result<Self> {
+    pub(crate) fn new(instance: ShaderCompilerInstance, path: impl AsRef<Path>) -> Result<Self> {
         let path = path.as_ref().to_owned();
         let contents = std::fs::read_to_string(&path)
             .context(format!("Failed to read shader file: {}", path.display()))?;
 
         Ok(Self {
-            contents,
+            contents: lower_overrides(&contents),
             defs: HashMap::new(),
             path,
             compiler: instance,
@@ -172,17 +395,32 @@ impl CompilationUnit {
         self
     }
 
-    pub fn compile(&self, variant_defs: &[&str]) -> Result<wgpu::naga::Module> {
+    fn final_defs(&self, variant_defs: &[&str]) -> HashMap<String, ShaderDefValue> {
         let mut final_defs = self.defs.clone();
         for def in variant_defs {
             final_defs.insert((*def).into(), ShaderDefValue::Bool(true));
         }
 
+        final_defs
+    }
+
+    /// Key of the module `compile` with `variant_defs` would result in.
+    pub fn key(&self, variant_defs: &[&str]) -> ShaderKey {
+        ShaderKey {
+            path: self.path.clone(),
+            defs: self.final_defs(variant_defs).into_iter().collect(),
+        }
+    }
+
+    pub fn compile(&self, variant_defs: &[&str]) -> Result<naga::Module> {
+        let final_defs = self.final_defs(variant_defs);
+
         self.compiler
             .lock()
             .map_err(|_| anyhow::anyhow!("failed to lock shader compiler instance"))?
             .compile(
-                &self.path.to_str().ok_or(anyhow::anyhow!(
+                &self.key(variant_defs),
+                self.path.to_str().ok_or(anyhow::anyhow!(
                     "failed to resolve path out of path buffer {}",
                     self.path.display()
                 ))?,
@@ -190,6 +428,26 @@ impl CompilationUnit {
                 final_defs,
             )
     }
+
+    /// This unit with definitions selecting `permutation` added.
+    pub fn variant<P: ShaderPermutation>(&self, permutation: P) -> CompilationUnit {
+        permutation.apply(self.clone())
+    }
+
+    /// Units of every variant in the permutation space `P`, e.g. every vertex type
+    /// with every shadow filtering for `(MeshVertexArrayType, ShadowFiltering)`.
+    pub fn variants<P: ShaderPermutation>(&self) -> Variants<P, CompilationUnit> {
+        Variants::new(|permutation| self.variant(permutation))
+    }
+
+    /// Compiles every variant in the permutation space `P`, failing on the first
+    /// one which doesn't compile.
+    pub fn compile_variants<P: ShaderPermutation>(
+        &self,
+        variant_defs: &[&str],
+    ) -> Result<Variants<P, naga::Module>> {
+        Variants::try_new(|permutation| self.variant(permutation).compile(variant_defs))
+    }
 }
 
 type ShaderCompilerInstance = Arc<Mutex<ShaderCompilerInner>>;
@@ -208,53 +466,135 @@ impl ShaderCompiler {
         })
     }
 
+    /// Keeps composed shaders in `cache`, and loads them from there when their
+    /// sources didn't change since.
+    pub fn with_cache(self, cache: ShaderCache) -> Self {
+        self.inner.lock().unwrap().cache = Some(cache);
+        self
+    }
+
     pub fn compilation_unit(&self, path: impl AsRef<Path>) -> Result<CompilationUnit> {
         CompilationUnit::new(self.inner.clone(), path)
     }
+
+    /// Failures to write compiled shaders to the cache since the last call. Shaders
+    /// are compiled either way, so these only mean the next start won't be faster.
+    pub fn take_cache_errors(&self) -> Vec<anyhow::Error> {
+        std::mem::take(&mut self.inner.lock().unwrap().cache_errors)
+    }
 }
 
 impl ShaderCompilerInner {
     pub fn new(module_repository: impl AsRef<Path>) -> Result<Self> {
-        let mut composer = Composer::default();
+        // Push constants are only used by shaders compiled for devices supporting them.
+        let mut composer =
+            Composer::default().with_capabilities(naga::valid::Capabilities::PUSH_CONSTANT);
+        let mut module_errors = vec![];
 
         let (module_to_file, module_graph) = construct_graphs(module_repository);
 
-        let files = sorted_modules(&module_graph)?
-            .into_iter()
-            .map(|module| module_to_file[&module].clone());
-
-        for file in files {
-            let content = std::fs::read_to_string(&file).context(format!(
+        let mut module_hashes = HashMap::new();
+        for module in sorted_modules(&module_graph)? {
+            let file = &module_to_file[&module];
+            let content = std::fs::read_to_string(file).context(format!(
                 "failed to read shader compilation unit: {}",
                 file.display()
             ))?;
-            composer.add_composable_module(ComposableModuleDescriptor {
+
+            // Dependencies come first, so hashes of their sources are already there.
+            let mut dependencies = module_graph[&module]
+                .iter()
+                .map(|dependency| module_hashes[dependency])
+                .collect::<Vec<u64>>();
+            dependencies.sort();
+            module_hashes.insert(module.clone(), hash(&(&content, dependencies)));
+
+            if let Some(error) = module_override(file, &content) {
+                module_errors.push(error);
+                continue;
+            }
+
+            let added = composer.add_composable_module(ComposableModuleDescriptor {
                 source: &content,
                 file_path: file.to_str().ok_or(anyhow::anyhow!("Invalid path"))?,
                 language: naga_oil::compose::ShaderLanguage::Wgsl,
                 ..Default::default()
-            })?;
+            });
+
+            // Reported once something is compiled, so it can be shown to the user.
+            if let Err(e) = added {
+                let error = ShaderError::new(&e, &composer, &HashMap::new());
+                eprintln!("{}", error.report);
+                module_errors.push(error);
+            }
         }
 
-        Ok(Self { composer })
+        Ok(Self {
+            composer,
+            module_errors,
+            module_hashes,
+            cache: None,
+            cache_errors: vec![],
+        })
+    }
+
+    // Hash of the shader along with every module it imports.
+    fn source_hash(&self, contents: &str) -> u64 {
+        let mut dependencies = imports(contents)
+            .into_iter()
+            .filter_map(|import| imported_module(import, self.module_hashes.keys()))
+            .map(|module| self.module_hashes[module])
+            .collect::<Vec<u64>>();
+        dependencies.sort();
+        dependencies.dedup();
+
+        hash(&(contents, dependencies))
     }
 
     fn compile(
         &mut self,
+        key: &ShaderKey,
         path: &str,
         contents: &str,
         shader_defs: HashMap<String, ShaderDefValue>,
-    ) -> Result<wgpu::naga::Module> {
+    ) -> Result<naga::Module> {
+        if let Some(error) = self.module_errors.first() {
+            return Err(anyhow::Error::new(error.clone()))
+                .context(format!("failed to compile {}", path));
+        }
+
+        let source_hash = self.source_hash(contents);
+        if let Some(module) = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.load(key, source_hash))
+        {
+            return Ok(module);
+        }
+
         let module = self
             .composer
             .make_naga_module(NagaModuleDescriptor {
-                source: &contents,
+                source: contents,
                 file_path: path,
                 shader_type: naga_oil::compose::ShaderType::Wgsl,
-                shader_defs: HashMap::from_iter(shader_defs),
+                shader_defs: shader_defs.clone(),
                 additional_imports: &[],
             })
-            .inspect_err(|e| eprintln!("{}", e.emit_to_string(&self.composer)))?;
+            .map_err(|e| {
+                let error = ShaderError::new(&e, &self.composer, &shader_defs);
+                eprintln!("{}", error.report);
+                anyhow::Error::new(error)
+            })
+            .context(format!("failed to compile {}", path))?;
+
+        // It's compiled either way, the cache is only there to make it faster.
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.store(key, source_hash, &module) {
+                self.cache_errors
+                    .push(e.context(format!("failed to cache {}", path)));
+            }
+        }
 
         Ok(module)
     }
@@ -0,0 +1,88 @@
+use std::{collections::HashMap, hash::Hash, ops::Index};
+
+use anyhow::Result;
+
+use crate::shader_compiler::CompilationUnit;
+
+/// Axis of a permutation space of a shader, like the vertex type or shadow filtering.
+/// Every value of it is compiled into a variant of its own. Tuples of axes span
+/// every combination of their values.
+pub trait ShaderPermutation: Copy + Eq + Hash {
+    fn all() -> Vec<Self>;
+
+    /// Adds definitions selecting this variant to `unit`.
+    fn apply(&self, unit: CompilationUnit) -> CompilationUnit;
+}
+
+impl<A: ShaderPermutation, B: ShaderPermutation> ShaderPermutation for (A, B) {
+    fn all() -> Vec<Self> {
+        A::all()
+            .into_iter()
+            .flat_map(|a| B::all().into_iter().map(move |b| (a, b)))
+            .collect()
+    }
+
+    fn apply(&self, unit: CompilationUnit) -> CompilationUnit {
+        self.1.apply(self.0.apply(unit))
+    }
+}
+
+impl<A: ShaderPermutation, B: ShaderPermutation, C: ShaderPermutation> ShaderPermutation
+    for (A, B, C)
+{
+    fn all() -> Vec<Self> {
+        <((A, B), C)>::all()
+            .into_iter()
+            .map(|((a, b), c)| (a, b, c))
+            .collect()
+    }
+
+    fn apply(&self, unit: CompilationUnit) -> CompilationUnit {
+        self.2.apply(self.1.apply(self.0.apply(unit)))
+    }
+}
+
+/// A value for every variant of a permutation space, like compilation units of
+/// a shader or pipelines made of them. Indexed with the permutation.
+pub struct Variants<P, T> {
+    variants: HashMap<P, T>,
+}
+
+impl<P: ShaderPermutation, T> Variants<P, T> {
+    pub fn new(mut create: impl FnMut(P) -> T) -> Self {
+        Self {
+            variants: P::all().into_iter().map(|p| (p, create(p))).collect(),
+        }
+    }
+
+    pub fn try_new(mut create: impl FnMut(P) -> Result<T>) -> Result<Self> {
+        Ok(Self {
+            variants: P::all()
+                .into_iter()
+                .map(|p| Ok((p, create(p)?)))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    pub fn map<U>(self, mut f: impl FnMut(P, T) -> U) -> Variants<P, U> {
+        Variants {
+            variants: self
+                .variants
+                .into_iter()
+                .map(|(p, value)| (p, f(p, value)))
+                .collect(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (P, &T)> {
+        self.variants.iter().map(|(p, value)| (*p, value))
+    }
+}
+
+impl<P: ShaderPermutation, T> Index<P> for Variants<P, T> {
+    type Output = T;
+
+    fn index(&self, permutation: P) -> &T {
+        &self.variants[&permutation]
+    }
+}
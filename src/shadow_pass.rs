@@ -1,37 +1,113 @@
-use std::{num::NonZeroU64, sync::Arc};
+use std::sync::Arc;
 
 use anyhow::Result;
 use encase::{ShaderSize, ShaderType, UniformBuffer};
 use nalgebra as na;
 
 use crate::{
+    bind_group_layouts::BindGroupLayouts,
     camera::GpuCamera,
+    compute::{BlurPass, GaussianKernel},
     gpu::Gpu,
     light_scene::Light,
-    mesh::{Mesh, MeshVertexArrayType},
+    mesh::MeshVertexArrayType,
+    pipeline_cache::RenderPipelineDesc,
     projection::wgpu_projection,
     render_context::RenderContext,
-    scene::{GpuScene, Instance},
+    render_pass::{FrameContext, RenderPass},
+    scene::Instance,
+    settings::{ShadowFiltering, ShadowSettings},
+    shader_permutation::Variants,
+    uniform_ring::UniformRing,
 };
 
 pub struct DirectionalShadowPass<'window> {
     render_ctx: Arc<RenderContext<'window>>,
     splits: [f32; SPLIT_COUNT],
-    pipeline: wgpu::RenderPipeline,
-    pnuv_pipeline: wgpu::RenderPipeline,
-    pntbuv_pipeline: wgpu::RenderPipeline,
+    pipelines: Variants<MeshVertexArrayType, Arc<wgpu::RenderPipeline>>,
     bg: wgpu::BindGroup,
-    depth_tex: wgpu::Texture,
-    proj_mat_buf: wgpu::Buffer,
-    view_mat_buf: wgpu::Buffer,
+    cascades: [Cascade; SPLIT_COUNT],
+    sampler: wgpu::Sampler,
+    cmp_sampler: wgpu::Sampler,
     out_buf: wgpu::Buffer,
-    out_bg: wgpu::BindGroup,
-    out_bgl: wgpu::BindGroupLayout,
+    out_bg: Arc<wgpu::BindGroup>,
+    out_bgl: Arc<wgpu::BindGroupLayout>,
+    spass_config_buf: wgpu::Buffer,
+    filter_buf: wgpu::Buffer,
+    vsm_pipelines: Variants<MeshVertexArrayType, Arc<wgpu::RenderPipeline>>,
+    // Light space view projection of every cascade from the last `render`.
+    light_mats: [na::Matrix4<f32>; SPLIT_COUNT],
+}
+
+/// Shadow map of a single split. Cascades covering bigger parts of the view frustum
+/// can use a lower resolution, so every one of them has its own textures.
+struct Cascade {
+    resolution: u32,
+    depth_tex: wgpu::Texture,
+    // VSM moments are rendered here and blurred, the blur output is sampled.
+    vsm_target: wgpu::Texture,
+    vsm_blur: BlurPass,
+}
+
+impl Cascade {
+    fn new(render_ctx: &RenderContext, resolution: u32) -> Result<Self> {
+        let RenderContext {
+            gpu,
+            shader_compiler,
+            bind_group_layouts,
+            ..
+        } = render_ctx;
+
+        let size = wgpu::Extent3d {
+            width: resolution,
+            height: resolution,
+            depth_or_array_layers: 1,
+        };
+
+        let depth_tex = gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("DirectionalShadowPass::CascadeDepth"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Depth32Float,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let vsm_target = gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("DirectionalShadowPass::VsmTarget"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: VSM_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let vsm_blur = BlurPass::new(gpu, shader_compiler, bind_group_layouts, size, VSM_FORMAT)?;
+
+        Ok(Self {
+            resolution,
+            depth_tex,
+            vsm_target,
+            vsm_blur,
+        })
+    }
 }
 
-const MIN_UNIFORM_BUFFER_OFFSET_ALIGNMENT: u64 = 256;
-const SPLIT_COUNT: usize = 3;
-const SHADOW_MAP_SIZE: u32 = 2048;
+// `CascadeConstants` of the shader, just the index of the cascade.
+const CASCADE_INDEX_RANGE: wgpu::PushConstantRange = wgpu::PushConstantRange {
+    stages: wgpu::ShaderStages::VERTEX,
+    range: 0..std::mem::size_of::<u32>() as u32,
+};
+pub const SPLIT_COUNT: usize = 3;
+// Bindings of cascade textures in the output bind group.
+const DEPTH_BINDINGS: [u32; SPLIT_COUNT] = [2, 6, 7];
+const MOMENTS_BINDINGS: [u32; SPLIT_COUNT] = [8, 9, 10];
+// Filterable and supported by `BlurPass`. Only depth and squared depth are stored.
+const VSM_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
 
 #[derive(ShaderType)]
 struct ShadowMapResult {
@@ -106,242 +182,397 @@ impl<'window> DirectionalShadowPass<'window> {
         render_ctx: Arc<RenderContext<'window>>,
         splits: [f32; SPLIT_COUNT],
         projection_mat: &na::Matrix4<f32>,
+        resolutions: [u32; SPLIT_COUNT],
     ) -> Result<Self> {
         let RenderContext {
             gpu,
+            bind_group_layouts,
             shader_compiler,
+            pipeline_cache,
+            uniform_ring,
             ..
         } = render_ctx.as_ref();
 
-        let depth_texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: wgpu::Extent3d {
-                width: SHADOW_MAP_SIZE,
-                height: SHADOW_MAP_SIZE,
-                depth_or_array_layers: SPLIT_COUNT as u32,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth32Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
+        let cascades = Self::create_cascades(&render_ctx, resolutions)?;
 
-        let module =
-            shader_compiler.compilation_unit("./shaders/forward/cascaded_shadow_map.wgsl")?;
-        let (shader, pnuv_shader, pntbuv_shader) = gpu.shader_per_vertex_type(&module)?;
+        let module = gpu.with_push_constant_defs(
+            shader_compiler
+                .compilation_unit("./shaders/forward/cascaded_shadow_map.wgsl")?
+                .with_integer_def("CASCADE_COUNT", SPLIT_COUNT as u32)
+                .with_integer_def("CASCADE_MATRIX_COUNT", SPLIT_COUNT as u32 * 2),
+        );
 
         let mat4_size: u64 = na::Matrix4::<f32>::SHADER_SIZE.into();
-        let offset = mat4_size.max(MIN_UNIFORM_BUFFER_OFFSET_ALIGNMENT);
 
-        let bgl = gpu
-            .device
-            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: true,
-                            min_binding_size: NonZeroU64::new(offset),
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::VERTEX,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: true,
-                            min_binding_size: NonZeroU64::new(offset),
-                        },
-                        count: None,
-                    },
-                ],
-            });
+        let out_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: mat4_size * SPLIT_COUNT as u64 * 2,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
 
-        let pipelinel = gpu
-            .device
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[&bgl],
-                push_constant_ranges: &[],
-            });
+        let (bgl, bg) =
+            Self::create_cascade_bindings(gpu, bind_group_layouts, uniform_ring, &out_buf);
+        let push_constant_ranges: &[_] = if gpu.push_constants() {
+            &[CASCADE_INDEX_RANGE]
+        } else {
+            &[]
+        };
 
-        let pnuv_pipeline = gpu
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                layout: Some(&pipelinel),
-                vertex: wgpu::VertexState {
-                    module: &pnuv_shader,
-                    entry_point: "vs_main",
-                    buffers: &[
-                        Mesh::pnuv_vertex_layout(),
-                        Instance::pnuv_model_instance_layout(),
-                    ],
-                },
-                fragment: None,
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    cull_mode: Some(wgpu::Face::Back),
-                    ..Default::default()
+        // Variance shadow maps need depth moments on top of the depth test.
+        let vsm_targets = [Some(VSM_FORMAT.into())];
+        let create_pipeline = |ty: MeshVertexArrayType, vsm: bool| {
+            pipeline_cache.render_pipeline(
+                gpu,
+                &RenderPipelineDesc {
+                    label: vsm.then_some("DirectionalShadowPass::VsmPipeline"),
+                    shader: &module.variant(ty),
+                    variant_defs: &[],
+                    constants: &[],
+                    bind_group_layouts: &[&bgl],
+                    push_constant_ranges,
+                    vertex_entry: "vs_main",
+                    vertex_buffers: &Instance::draw_buffer_layouts(ty),
+                    fragment_entry: vsm.then_some("fs_moments"),
+                    targets: if vsm { &vsm_targets } else { &[] },
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        cull_mode: Some(wgpu::Face::Back),
+                        ..Default::default()
+                    },
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: wgpu::TextureFormat::Depth32Float,
+                        depth_write_enabled: true,
+                        depth_compare: wgpu::CompareFunction::LessEqual,
+                        stencil: Default::default(),
+                        bias: Default::default(),
+                    }),
+                    multisample: wgpu::MultisampleState::default(),
                 },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: wgpu::TextureFormat::Depth32Float,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::LessEqual,
-                    stencil: Default::default(),
-                    bias: Default::default(),
-                }),
-                multisample: wgpu::MultisampleState::default(),
-                multiview: None,
-            });
+            )
+        };
 
-        let pntbuv_pipeline = gpu
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                layout: Some(&pipelinel),
-                vertex: wgpu::VertexState {
-                    module: &pntbuv_shader,
-                    entry_point: "vs_main",
-                    buffers: &[
-                        Mesh::pntbuv_vertex_layout(),
-                        Instance::pntbuv_model_instance_layout(),
-                    ],
-                },
-                fragment: None,
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    cull_mode: Some(wgpu::Face::Back),
-                    ..Default::default()
-                },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: wgpu::TextureFormat::Depth32Float,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::LessEqual,
-                    stencil: Default::default(),
-                    bias: Default::default(),
-                }),
-                multisample: wgpu::MultisampleState::default(),
-                multiview: None,
-            });
+        let pipelines = Variants::try_new(|ty| create_pipeline(ty, false))?;
+        let vsm_pipelines = Variants::try_new(|ty| create_pipeline(ty, true))?;
 
-        let pipeline = gpu
+        let out_bgl = bind_group_layouts.shadow(gpu);
+
+        use wgpu::util::DeviceExt;
+        let spass_config_buf = gpu
             .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: None,
-                layout: Some(&pipelinel),
-                vertex: wgpu::VertexState {
-                    module: &shader,
-                    entry_point: "vs_main",
-                    buffers: &[
-                        Mesh::pn_vertex_layout(),
-                        Instance::pn_model_instance_layout(),
-                    ],
-                },
-                fragment: None,
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    cull_mode: Some(wgpu::Face::Back),
-                    ..Default::default()
-                },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: wgpu::TextureFormat::Depth32Float,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::LessEqual,
-                    stencil: Default::default(),
-                    bias: Default::default(),
-                }),
-                multisample: wgpu::MultisampleState::default(),
-                multiview: None,
+                contents: Self::split_distances(&splits, projection_mat)?.as_slice(),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             });
 
-        let view_mat_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+        let depth_tex_sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
             label: None,
-            size: offset * splits.len() as u64,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
+            address_mode_u: wgpu::AddressMode::ClampToBorder,
+            address_mode_v: wgpu::AddressMode::ClampToBorder,
+            address_mode_w: wgpu::AddressMode::ClampToBorder,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            border_color: Some(wgpu::SamplerBorderColor::OpaqueWhite),
+            ..Default::default()
         });
 
-        let proj_mat_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: offset * splits.len() as u64,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        let depth_tex_cmp_sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("DirectionalShadowPass::ComparisonSampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let filter_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("DirectionalShadowPass::FilterBuffer"),
+            size: std::mem::size_of::<[f32; 4]>() as u64,
             mapped_at_creation: false,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
+        let out_bg = Arc::new(Self::create_out_bg(
+            gpu,
+            &out_bgl,
+            &out_buf,
+            &spass_config_buf,
+            &filter_buf,
+            (&depth_tex_sampler, &depth_tex_cmp_sampler),
+            &cascades,
+        ));
+
+        Ok(Self {
+            render_ctx,
+            splits,
+            pipelines,
+            bg,
+            cascades,
+            sampler: depth_tex_sampler,
+            cmp_sampler: depth_tex_cmp_sampler,
+            out_bg,
+            out_bgl,
+            out_buf,
+            spass_config_buf,
+            filter_buf,
+            vsm_pipelines,
+            light_mats: [na::Matrix4::identity(); SPLIT_COUNT],
+        })
+    }
+
+    // With push constants every cascade reads its matrices straight from `out_buf`.
+    // Otherwise they're written to `UniformRing` and bound at their offsets.
+    fn create_cascade_bindings(
+        gpu: &Gpu,
+        bind_group_layouts: &BindGroupLayouts,
+        uniform_ring: &UniformRing,
+        out_buf: &wgpu::Buffer,
+    ) -> (Arc<wgpu::BindGroupLayout>, wgpu::BindGroup) {
+        if gpu.push_constants() {
+            let bgl = bind_group_layouts.get(
+                gpu,
+                "DirectionalShadowPass::CascadeBindGroupLayout",
+                &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            );
+
+            let bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("DirectionalShadowPass::CascadeBindGroup"),
+                layout: &bgl,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: out_buf.as_entire_binding(),
+                }],
+            });
+
+            return (bgl, bg);
+        }
+
+        let mat4_size: u64 = na::Matrix4::<f32>::SHADER_SIZE.into();
+
+        let bgl = bind_group_layouts.get(
+            gpu,
+            "DirectionalShadowPass::CascadeBindGroupLayout",
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: UniformRing::binding_type(mat4_size),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: UniformRing::binding_type(mat4_size),
+                    count: None,
+                },
+            ],
+        );
+
         let bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
+            label: Some("DirectionalShadowPass::CascadeBindGroup"),
             layout: &bgl,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &view_mat_buf,
-                        offset: 0,
-                        size: NonZeroU64::new(offset),
-                    }),
+                    resource: uniform_ring.binding(mat4_size),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &proj_mat_buf,
-                        offset: 0,
-                        size: NonZeroU64::new(offset),
-                    }),
+                    resource: uniform_ring.binding(mat4_size),
                 },
             ],
         });
 
-        let out_bgl = gpu
-            .device
-            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Depth,
-                            view_dimension: wgpu::TextureViewDimension::D2Array,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 3,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                ],
-            });
+        (bgl, bg)
+    }
+
+    fn create_cascades(
+        render_ctx: &RenderContext,
+        resolutions: [u32; SPLIT_COUNT],
+    ) -> Result<[Cascade; SPLIT_COUNT]> {
+        let [a, b, c] = resolutions;
+
+        Ok([
+            Cascade::new(render_ctx, a)?,
+            Cascade::new(render_ctx, b)?,
+            Cascade::new(render_ctx, c)?,
+        ])
+    }
+
+    /// Entries of the output bind group, see `BindGroupLayouts::shadow`.
+    pub(crate) fn out_layout_entries() -> Vec<wgpu::BindGroupLayoutEntry> {
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 5,
+                visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                count: None,
+            },
+        ]
+        .into_iter()
+        .chain(Self::cascade_layout_entries())
+        .collect::<Vec<_>>()
+    }
+
+    // Depth and VSM moments of every cascade, `smap_a`..`smap_c` and
+    // `smap_moments_a`..`smap_moments_c` in shaders.
+    fn cascade_layout_entries() -> impl Iterator<Item = wgpu::BindGroupLayoutEntry> {
+        let depth = (0..SPLIT_COUNT).map(|i| wgpu::BindGroupLayoutEntry {
+            binding: DEPTH_BINDINGS[i],
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Depth,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        });
+
+        let moments = (0..SPLIT_COUNT).map(|i| wgpu::BindGroupLayoutEntry {
+            binding: MOMENTS_BINDINGS[i],
+            visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        });
 
+        depth.chain(moments)
+    }
+
+    fn create_out_bg(
+        gpu: &Gpu,
+        layout: &wgpu::BindGroupLayout,
+        out_buf: &wgpu::Buffer,
+        spass_config_buf: &wgpu::Buffer,
+        filter_buf: &wgpu::Buffer,
+        (sampler, cmp_sampler): (&wgpu::Sampler, &wgpu::Sampler),
+        cascades: &[Cascade; SPLIT_COUNT],
+    ) -> wgpu::BindGroup {
+        let depth_views = cascades
+            .each_ref()
+            .map(|cascade| cascade.depth_tex.create_view(&Default::default()));
+        let moments_views = cascades
+            .each_ref()
+            .map(|cascade| cascade.vsm_blur.output().create_view(&Default::default()));
+
+        let entries: Vec<wgpu::BindGroupEntry> = [
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(out_buf.as_entire_buffer_binding()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Buffer(
+                    spass_config_buf.as_entire_buffer_binding(),
+                ),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: filter_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: wgpu::BindingResource::Sampler(cmp_sampler),
+            },
+        ]
+        .into_iter()
+        .chain(
+            depth_views
+                .iter()
+                .zip(DEPTH_BINDINGS)
+                .chain(moments_views.iter().zip(MOMENTS_BINDINGS))
+                .map(|(view, binding)| wgpu::BindGroupEntry {
+                    binding,
+                    resource: wgpu::BindingResource::TextureView(view),
+                }),
+        )
+        .collect();
+
+        gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("DirectionalShadowPass::OutBindGroup"),
+            layout,
+            entries: &entries,
+        })
+    }
+
+    /// Reallocates cascades whose resolution changed.
+    fn resize_cascades(&mut self, resolutions: [u32; SPLIT_COUNT]) -> Result<()> {
+        if self.cascades.each_ref().map(|c| c.resolution) == resolutions {
+            return Ok(());
+        }
+
+        self.cascades = Self::create_cascades(&self.render_ctx, resolutions)?;
+        let gpu = &self.render_ctx.gpu;
+        self.out_bg = Arc::new(Self::create_out_bg(
+            gpu,
+            &self.out_bgl,
+            &self.out_buf,
+            &self.spass_config_buf,
+            &self.filter_buf,
+            (&self.sampler, &self.cmp_sampler),
+            &self.cascades,
+        ));
+
+        Ok(())
+    }
+
+    fn split_distances(splits: &[f32], projection_mat: &na::Matrix4<f32>) -> Result<Vec<u8>> {
         let near_far_ratio = (projection_mat[(2, 2)] + 1.0) / (projection_mat[(2, 2)] - 1.0);
         let z_near =
             (projection_mat[(2, 3)] * (near_far_ratio / 2.0) - projection_mat[(2, 3)] / 2.0) * 2.0;
@@ -364,86 +595,32 @@ impl<'window> DirectionalShadowPass<'window> {
             UniformBuffer::new(Vec::with_capacity(spass_config_size as usize));
         spass_config_contents.write(&spass_config)?;
 
-        use wgpu::util::DeviceExt;
-        let spass_config_buf = gpu
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: None,
-                contents: spass_config_contents.into_inner().as_slice(),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            });
-
-        let depth_tex_sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
-            label: None,
-            address_mode_u: wgpu::AddressMode::ClampToBorder,
-            address_mode_v: wgpu::AddressMode::ClampToBorder,
-            address_mode_w: wgpu::AddressMode::ClampToBorder,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Linear,
-            border_color: Some(wgpu::SamplerBorderColor::OpaqueWhite),
-            ..Default::default()
-        });
+        Ok(spass_config_contents.into_inner())
+    }
 
-        let mat4_size: u64 = na::Matrix4::<f32>::SHADER_SIZE.into();
+    /// Split distances are baked from the camera projection, so they have to follow
+    /// any change of it. Cascade frusta themselves are recomputed every frame in `render`.
+    pub fn on_resize(&self, projection_mat: &na::Matrix4<f32>) -> Result<()> {
+        let RenderContext { gpu, .. } = self.render_ctx.as_ref();
 
-        let out_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: mat4_size * SPLIT_COUNT as u64 * 2,
-            mapped_at_creation: false,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let out_bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &out_bgl,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::Buffer(out_buf.as_entire_buffer_binding()),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&depth_tex_sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::TextureView(
-                        &depth_texture.create_view(&wgpu::TextureViewDescriptor::default()),
-                    ),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: wgpu::BindingResource::Buffer(
-                        spass_config_buf.as_entire_buffer_binding(),
-                    ),
-                },
-            ],
-        });
+        gpu.queue.write_buffer(
+            &self.spass_config_buf,
+            0,
+            Self::split_distances(&self.splits, projection_mat)?.as_slice(),
+        );
 
-        Ok(Self {
-            render_ctx,
-            splits,
-            pntbuv_pipeline,
-            pnuv_pipeline,
-            pipeline,
-            bg,
-            proj_mat_buf,
-            view_mat_buf,
-            depth_tex: depth_texture,
-            out_bg,
-            out_bgl,
-            out_buf,
-        })
+        Ok(())
     }
 
-    pub fn out_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
-        &self.out_bgl
+    /// Light space view projection of every cascade, as of the last `render`.
+    pub fn light_matrices(&self) -> &[na::Matrix4<f32>; SPLIT_COUNT] {
+        &self.light_mats
     }
 
     fn calculate_proj_view_mats(
         light: &Light,
         frustum: &[na::Point3<f32>],
+        resolution: u32,
     ) -> (na::Matrix4<f32>, na::Matrix4<f32>) {
         let near_plane_center = frustum[0] + ((frustum[3] - frustum[0]) / 2.0);
         let far_plane_center = frustum[4] + ((frustum[7] - frustum[4]) / 2.0);
@@ -452,7 +629,7 @@ impl<'window> DirectionalShadowPass<'window> {
 
         let radius = (frustum[7] - frustum[0]).norm() / 2.0;
 
-        let tex_per_unit = SHADOW_MAP_SIZE as f32 / (radius * 2.0);
+        let tex_per_unit = resolution as f32 / (radius * 2.0);
         let scaling = na::Matrix4::new_scaling(tex_per_unit);
 
         let smap_cam_nonadjusted = na::Matrix4::look_at_rh(
@@ -482,38 +659,61 @@ impl<'window> DirectionalShadowPass<'window> {
     }
 
     pub fn render(
-        &self,
+        &mut self,
         light: &Light,
         camera: &GpuCamera,
         projection_mat: &na::Matrix4<f32>,
-    ) -> Result<&wgpu::BindGroup> {
+        settings: &ShadowSettings,
+    ) -> Result<()> {
+        self.resize_cascades(settings.cascade_resolutions)?;
+
         let RenderContext {
             gpu,
-            gpu_scene: scene,
+            gpu_scene,
+            profiler,
+            uniform_ring,
+            texture_pool,
             ..
         } = self.render_ctx.as_ref();
+        let scene = gpu_scene.read().unwrap();
+
+        gpu.queue.write_buffer(
+            &self.filter_buf,
+            0,
+            bytemuck::cast_slice(&[
+                settings.pcf_kernel_size as f32,
+                settings.light_size,
+                settings.vsm_bleeding_reduction,
+                settings.debug_cascades as u32 as f32,
+            ]),
+        );
+
+        let vsm = settings.filtering == ShadowFiltering::Vsm;
+        let pipelines = if vsm {
+            &self.vsm_pipelines
+        } else {
+            &self.pipelines
+        };
 
         let full_frustum = calculate_frustum(&camera.look_at_matrix(), projection_mat)?;
 
         let frustum_splits = split_frustum(&full_frustum, &self.splits);
 
         let mat4_size: u64 = na::Matrix4::<f32>::SHADER_SIZE.into();
-        let offset = mat4_size.max(MIN_UNIFORM_BUFFER_OFFSET_ALIGNMENT);
 
-        for (i, frustum) in frustum_splits.iter().enumerate() {
-            let (smap_cam_mat, smap_proj_mat) = Self::calculate_proj_view_mats(light, frustum);
+        for (i, (frustum, cascade)) in frustum_splits.iter().zip(&self.cascades).enumerate() {
+            let (smap_cam_mat, smap_proj_mat) =
+                Self::calculate_proj_view_mats(light, frustum, cascade.resolution);
+            self.light_mats[i] = smap_proj_mat * smap_cam_mat;
 
-            gpu.queue.write_buffer(
-                &self.view_mat_buf,
-                i as u64 * offset,
-                bytemuck::cast_slice(smap_cam_mat.as_slice()),
-            );
-
-            gpu.queue.write_buffer(
-                &self.proj_mat_buf,
-                i as u64 * offset,
-                bytemuck::cast_slice(smap_proj_mat.as_slice()),
-            );
+            let ring_offsets = if gpu.push_constants() {
+                None
+            } else {
+                Some([
+                    uniform_ring.write(gpu, bytemuck::cast_slice(smap_cam_mat.as_slice()))?,
+                    uniform_ring.write(gpu, bytemuck::cast_slice(smap_proj_mat.as_slice()))?,
+                ])
+            };
 
             gpu.queue.write_buffer(
                 &self.out_buf,
@@ -527,21 +727,36 @@ impl<'window> DirectionalShadowPass<'window> {
                 bytemuck::cast_slice(smap_proj_mat.as_slice()),
             );
 
-            let depth_view = self.depth_tex.create_view(&wgpu::TextureViewDescriptor {
-                base_array_layer: i as u32,
-                array_layer_count: Some(1),
-                dimension: Some(wgpu::TextureViewDimension::D2),
-                ..Default::default()
-            });
+            let depth_view = cascade.depth_tex.create_view(&Default::default());
+            let vsm_view = cascade.vsm_target.create_view(&Default::default());
 
             let mut encoder = gpu
                 .device
                 .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
+            let color_attachments = if vsm {
+                vec![Some(wgpu::RenderPassColorAttachment {
+                    view: &vsm_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        // Depth of 1 is never in shadow.
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: 1.0,
+                            g: 1.0,
+                            b: 0.0,
+                            a: 0.0,
+                        }),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })]
+            } else {
+                vec![]
+            };
+
             {
                 let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                     label: None,
-                    color_attachments: &[],
+                    color_attachments: &color_attachments,
                     depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                         view: &depth_view,
                         depth_ops: Some(wgpu::Operations {
@@ -550,64 +765,94 @@ impl<'window> DirectionalShadowPass<'window> {
                         }),
                         stencil_ops: None,
                     }),
-                    timestamp_writes: None,
+                    timestamp_writes: profiler.render_pass_writes("Shadows"),
                     occlusion_query_set: None,
                 });
 
-                rpass.set_bind_group(
-                    0,
-                    &self.bg,
-                    &[(i as u64 * offset) as u32, (i as u64 * offset) as u32],
-                );
-
-                for draw_call in scene.draw_calls() {
-                    match draw_call.vertex_array_type {
-                        MeshVertexArrayType::PN => {
-                            rpass.set_pipeline(&self.pipeline);
-                        }
-                        MeshVertexArrayType::PNUV => {
-                            rpass.set_pipeline(&self.pnuv_pipeline);
-                        }
-                        MeshVertexArrayType::PNTBUV => {
-                            rpass.set_pipeline(&self.pntbuv_pipeline);
-                        }
+                match ring_offsets {
+                    Some(offsets) => rpass.set_bind_group(0, &self.bg, &offsets),
+                    None => {
+                        rpass.set_bind_group(0, &self.bg, &[]);
+                        rpass.set_push_constants(
+                            wgpu::ShaderStages::VERTEX,
+                            0,
+                            bytemuck::bytes_of(&(i as u32)),
+                        );
                     }
+                }
+
+                for batch in scene.draw_batches() {
+                    let first = &batch[0];
+                    rpass.set_pipeline(&pipelines[first.vertex_array_type]);
 
                     rpass.set_vertex_buffer(
                         0,
                         scene
-                            .vertex_buffer_by_type(draw_call.vertex_array_type)
+                            .vertex_buffer_by_type(first.vertex_array_type)
                             .slice(..),
                     );
                     rpass.set_vertex_buffer(
                         1,
-                        scene
-                            .instance_buffer_by_type(draw_call.instance_type)
-                            .slice(..),
+                        scene.instance_buffer_by_type(first.instance_type).slice(..),
                     );
 
-                    if draw_call.indexed {
+                    if first.indexed {
                         rpass.set_index_buffer(
                             scene.index_buffer().slice(..),
                             wgpu::IndexFormat::Uint32,
                         );
+                    }
 
-                        rpass.draw_indexed_indirect(
-                            scene.indexed_draw_buffer(),
-                            draw_call.draw_buffer_offset,
-                        );
-                    } else {
-                        rpass.draw_indirect(
-                            scene.non_indexed_draw_buffer(),
-                            draw_call.draw_buffer_offset,
-                        );
+                    for draw_call in batch {
+                        if draw_call.indexed {
+                            rpass.draw_indexed_indirect(
+                                scene.indexed_draw_buffer(),
+                                draw_call.draw_buffer_offset,
+                            );
+                        } else {
+                            rpass.draw_indirect(
+                                scene.non_indexed_draw_buffer(),
+                                draw_call.draw_buffer_offset,
+                            );
+                        }
                     }
                 }
             }
 
             gpu.queue.submit(Some(encoder.finish()));
+
+            if vsm {
+                cascade.vsm_blur.perform(
+                    gpu,
+                    texture_pool,
+                    &cascade.vsm_target,
+                    settings.vsm_blur_iterations.max(1),
+                    &GaussianKernel::new(settings.vsm_blur_radius, settings.vsm_blur_sigma),
+                )?;
+            }
         }
 
-        Ok(&self.out_bg)
+        Ok(())
+    }
+}
+
+impl RenderPass for DirectionalShadowPass<'_> {
+    fn name(&self) -> &'static str {
+        "DirectionalShadowPass"
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        Self::render(
+            self,
+            &ctx.sun,
+            ctx.camera,
+            &ctx.projection,
+            &ctx.settings.shadows,
+        )?;
+
+        ctx.shadows = Some(self.out_bg.clone());
+        ctx.light_matrices = self.light_mats;
+
+        Ok(())
     }
 }
@@ -1,131 +1,162 @@
+use std::collections::HashMap;
+
 use nalgebra as na;
 type FVec3 = na::Vector3<f32>;
 type FVec2 = na::Vector2<f32>;
 
 use crate::mesh::{Geometry, NormalSource, TangentSpaceInformation};
 
+/// Sphere with radius of 1, split into `slices` around the y axis and `stacks` from pole to pole.
 pub struct UVSphere;
 
 impl UVSphere {
-    pub fn geometry(slices: usize, stacks: usize) -> Geometry {
+    fn raw_geometry(slices: usize, stacks: usize) -> RevolvedShape {
         let stack_angle = std::f32::consts::PI / stacks as f32;
-        let slice_angle = 2.0 * std::f32::consts::PI / slices as f32;
-
-        let mut mesh = vec![na::Vector3::new(0.0, 1.0, 0.0)];
 
-        for i in 0..(stacks - 1) {
-            let angle = (i + 1) as f32 * stack_angle;
-            let y = angle.cos();
-            let r = angle.sin();
+        let profile = (0..=stacks)
+            .map(|i| {
+                let (sin, cos) = (i as f32 * stack_angle).sin_cos();
+                let normal = FVec2::new(sin, cos);
+                ProfilePoint::new(normal.x, normal.y, normal)
+            })
+            .collect::<Vec<_>>();
 
-            for i in 0..slices {
-                let angle = i as f32 * slice_angle;
-                let x = r * angle.cos();
-                let z = r * angle.sin();
-
-                mesh.push(na::Vector3::new(x, y, z));
-            }
-        }
-
-        mesh.push(na::Vector3::new(0.0, -1.0, 0.0));
-
-        let mut faces: Vec<u32> = vec![];
-        let top_vert = 0;
-        let bottom_vert = (mesh.len() - 1) as u32;
+        RevolvedShape::new(&[profile], slices)
+    }
 
-        for i in 0..slices {
-            let i0 = i + 1;
-            let i1 = (i + 1) % slices + 1;
+    pub fn geometry(slices: usize, stacks: usize) -> Geometry {
+        Self::raw_geometry(slices, stacks).into_geometry(false)
+    }
 
-            faces.push(top_vert);
-            faces.push(i1 as u32);
-            faces.push(i0 as u32);
+    pub fn geometry_tan_space(slices: usize, stacks: usize) -> Geometry {
+        Self::raw_geometry(slices, stacks).into_geometry(true)
+    }
 
-            faces.push(bottom_vert);
-            faces.push(bottom_vert - i1 as u32);
-            faces.push(bottom_vert - i0 as u32);
-        }
+    pub fn uvs(slices: usize, stacks: usize) -> Vec<FVec2> {
+        Self::raw_geometry(slices, stacks).uvs
+    }
+}
 
-        for i in 1..(stacks - 1) {
-            for j in 0..slices {
-                let t0 = (i - 1) * slices + j + 1;
-                let t1 = (i - 1) * slices + (j + 1) % slices + 1;
-                let b0 = i * slices + j + 1;
-                let b1 = i * slices + (j + 1) % slices + 1;
-
-                faces.push(t0 as u32);
-                faces.push(b1 as u32);
-                faces.push(b0 as u32);
-                faces.push(b1 as u32);
-                faces.push(t0 as u32);
-                faces.push(t1 as u32);
-            }
+/// Sphere with radius of 1 made by subdividing an icosahedron. Its triangles are
+/// close to equal in size, unlike these of `UVSphere` which shrink at the poles.
+pub struct Icosphere;
+
+impl Icosphere {
+    // Every subdivision splits each triangle into four.
+    pub fn geometry(subdivisions: usize) -> Geometry {
+        let t = (1.0 + 5.0f32.sqrt()) * 0.5;
+
+        let mut mesh = [
+            (-1.0, t, 0.0),
+            (1.0, t, 0.0),
+            (-1.0, -t, 0.0),
+            (1.0, -t, 0.0),
+            (0.0, -1.0, t),
+            (0.0, 1.0, t),
+            (0.0, -1.0, -t),
+            (0.0, 1.0, -t),
+            (t, 0.0, -1.0),
+            (t, 0.0, 1.0),
+            (-t, 0.0, -1.0),
+            (-t, 0.0, 1.0),
+        ]
+        .map(|(x, y, z)| FVec3::new(x, y, z).normalize())
+        .to_vec();
+
+        let mut faces: Vec<u32> = vec![
+            0, 11, 5, 0, 5, 1, 0, 1, 7, 0, 7, 10, 0, 10, 11, 1, 5, 9, 5, 11, 4, 11, 10, 2, 10, 7,
+            6, 7, 1, 8, 3, 9, 4, 3, 4, 2, 3, 2, 6, 3, 6, 8, 3, 8, 9, 4, 9, 5, 2, 4, 11, 6, 2, 10,
+            8, 6, 7, 9, 8, 1,
+        ];
+
+        for _ in 0..subdivisions {
+            // Edges are shared by two triangles, which have to use the same midpoint.
+            let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+            let mut midpoint = |a: u32, b: u32| {
+                *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    mesh.push((mesh[a as usize] + mesh[b as usize]).normalize());
+                    (mesh.len() - 1) as u32
+                })
+            };
+
+            faces = faces
+                .chunks(3)
+                .flat_map(|triangle| {
+                    let [a, b, c] = [triangle[0], triangle[1], triangle[2]];
+                    let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+
+                    [a, ab, ca, b, bc, ab, c, ca, bc, ab, bc, ca]
+                })
+                .collect();
         }
 
-        let normals = mesh.iter().map(|v| v.normalize()).collect::<Vec<_>>();
+        let normals = mesh.clone();
 
         Geometry::new_indexed(mesh, NormalSource::Provided(normals), faces, None)
     }
 }
 
+/// Unit plane facing up, split into `subdivisions` x `subdivisions` quads.
 pub struct Plane;
 
 impl Plane {
-    pub(self) fn raw_geometry() -> (Vec<na::Vector3<f32>>, Vec<na::Vector3<f32>>, Vec<u32>) {
-        let mut mesh = vec![];
-        let mut faces = vec![];
-
-        let normal = na::Vector3::<f32>::y();
-        let normals = [normal; 4];
-        let x = na::Vector3::<f32>::x();
-        let z = na::Vector3::<f32>::z();
-
-        let tl = -0.5 * x - 0.5 * z;
-        let tr = 0.5 * x - 0.5 * z;
-        let bl = -0.5 * x + 0.5 * z;
-        let br = 0.5 * x + 0.5 * z;
-
-        mesh.push(tl);
-        mesh.push(tr);
-        mesh.push(bl);
-        mesh.push(br);
-
-        faces.push(2);
-        faces.push(3);
-        faces.push(0);
-        faces.push(0);
-        faces.push(3);
-        faces.push(1);
+    pub(self) fn raw_geometry(
+        subdivisions: usize,
+    ) -> (Vec<na::Vector3<f32>>, Vec<na::Vector3<f32>>, Vec<u32>) {
+        let subdivisions = subdivisions.max(1);
+        let row = subdivisions + 1;
+
+        // Rows go from -z to +z, vertices in a row from -x to +x.
+        let mesh = (0..row * row)
+            .map(|i| {
+                let (x, z) = ((i % row) as f32, (i / row) as f32);
+                na::Vector3::new(x, 0.0, z) / subdivisions as f32 - na::Vector3::new(0.5, 0.0, 0.5)
+            })
+            .collect::<Vec<_>>();
+        let normals = vec![na::Vector3::<f32>::y(); mesh.len()];
+
+        let mut faces = Vec::with_capacity(subdivisions * subdivisions * 6);
+        for r in 0..subdivisions {
+            for c in 0..subdivisions {
+                let tl = (r * row + c) as u32;
+                let tr = tl + 1;
+                let bl = tl + row as u32;
+                let br = bl + 1;
+
+                faces.extend([bl, br, tl, tl, br, tr]);
+            }
+        }
 
-        (mesh, normals.to_vec(), faces)
+        (mesh, normals, faces)
     }
 
-    pub fn geometry_tan_space() -> Geometry {
-        let (mesh, normals, faces) = Self::raw_geometry();
+    pub fn geometry_tan_space(subdivisions: usize) -> Geometry {
+        let (mesh, normals, faces) = Self::raw_geometry(subdivisions);
 
         Geometry::new_indexed(
             mesh,
-            NormalSource::Provided(normals.to_vec()),
+            NormalSource::Provided(normals),
             faces,
             Some(TangentSpaceInformation {
-                texture_uvs: Self::uvs(),
+                texture_uvs: Self::uvs(subdivisions),
             }),
         )
     }
 
-    pub fn geometry() -> Geometry {
-        let (mesh, normals, faces) = Self::raw_geometry();
+    pub fn geometry(subdivisions: usize) -> Geometry {
+        let (mesh, normals, faces) = Self::raw_geometry(subdivisions);
 
-        Geometry::new_indexed(mesh, NormalSource::Provided(normals.to_vec()), faces, None)
+        Geometry::new_indexed(mesh, NormalSource::Provided(normals), faces, None)
     }
 
-    pub fn uvs() -> Vec<FVec2> {
-        vec![
-            FVec2::new(0.0, 0.0),
-            FVec2::new(1.0, 0.0),
-            FVec2::new(0.0, 1.0),
-            FVec2::new(1.0, 1.0),
-        ]
+    // The whole plane is covered by the texture once.
+    pub fn uvs(subdivisions: usize) -> Vec<FVec2> {
+        let subdivisions = subdivisions.max(1);
+        let row = subdivisions + 1;
+
+        (0..row * row)
+            .map(|i| FVec2::new((i % row) as f32, (i / row) as f32) / subdivisions as f32)
+            .collect()
     }
 }
 
@@ -152,7 +183,7 @@ impl Cube {
     }
 
     fn raw_geometry() -> (Vec<FVec3>, Vec<FVec3>, Vec<u32>) {
-        let (face_v, face_normals, face_indexes) = Plane::raw_geometry();
+        let (face_v, face_normals, face_indexes) = Plane::raw_geometry(1);
         let half_size = 0.5;
 
         let mut mesh = Vec::with_capacity(24);
@@ -283,3 +314,277 @@ impl Cube {
         ]
     }
 }
+
+// Point of a profile which is revolved around the y axis, `radius` away from it.
+// With radius growing to the right and y up, normals point to the left of the way
+// a profile goes, e.g. it goes down along the outside of a shape. Faces then wind
+// counter-clockwise when looked at from the side of normals.
+#[derive(Clone, Copy)]
+struct ProfilePoint {
+    radius: f32,
+    y: f32,
+    // Outward in x, upward in y.
+    normal: FVec2,
+}
+
+impl ProfilePoint {
+    fn new(radius: f32, y: f32, normal: FVec2) -> Self {
+        Self { radius, y, normal }
+    }
+}
+
+struct RevolvedShape {
+    mesh: Vec<FVec3>,
+    normals: Vec<FVec3>,
+    faces: Vec<u32>,
+    uvs: Vec<FVec2>,
+}
+
+impl RevolvedShape {
+    // Every segment is revolved separately, which keeps a hard edge between segments.
+    // U goes around the shape, V down the whole profile, proportionally to its length.
+    fn new(segments: &[Vec<ProfilePoint>], slices: usize) -> Self {
+        let slice_angle = 2.0 * std::f32::consts::PI / slices as f32;
+        let distance =
+            |a: &ProfilePoint, b: &ProfilePoint| FVec2::new(b.radius - a.radius, b.y - a.y).norm();
+
+        let profile_length = segments
+            .iter()
+            .flat_map(|segment| segment.windows(2))
+            .map(|pair| distance(&pair[0], &pair[1]))
+            .sum::<f32>()
+            .max(f32::EPSILON);
+
+        let mut shape = Self {
+            mesh: vec![],
+            normals: vec![],
+            faces: vec![],
+            uvs: vec![],
+        };
+
+        let mut travelled = 0.0;
+        for segment in segments {
+            let first_vertex = shape.mesh.len() as u32;
+
+            for (i, point) in segment.iter().enumerate() {
+                if i > 0 {
+                    travelled += distance(&segment[i - 1], point);
+                }
+
+                // The seam is duplicated, so U can reach 1 on it.
+                for j in 0..=slices {
+                    let (sin, cos) = (j as f32 * slice_angle).sin_cos();
+
+                    shape
+                        .mesh
+                        .push(FVec3::new(point.radius * cos, point.y, point.radius * sin));
+                    shape.normals.push(
+                        FVec3::new(point.normal.x * cos, point.normal.y, point.normal.x * sin)
+                            .normalize(),
+                    );
+                    // Angles grow clockwise when looked at from outside.
+                    shape.uvs.push(FVec2::new(
+                        1.0 - j as f32 / slices as f32,
+                        travelled / profile_length,
+                    ));
+                }
+            }
+
+            let ring = slices as u32 + 1;
+            for (i, rows) in segment.windows(2).enumerate() {
+                let top = first_vertex + i as u32 * ring;
+                let bottom = top + ring;
+
+                for j in 0..slices as u32 {
+                    let (t0, t1) = (top + j, top + j + 1);
+                    let (b0, b1) = (bottom + j, bottom + j + 1);
+
+                    // Rings on the axis collapse into a point, leaving one triangle per quad.
+                    if rows[1].radius > f32::EPSILON {
+                        shape.faces.extend([t0, b1, b0]);
+                    }
+                    if rows[0].radius > f32::EPSILON {
+                        shape.faces.extend([b1, t0, t1]);
+                    }
+                }
+            }
+        }
+
+        shape
+    }
+
+    fn into_geometry(self, tangent_space: bool) -> Geometry {
+        let tangent_space_information = tangent_space.then_some(TangentSpaceInformation {
+            texture_uvs: self.uvs,
+        });
+
+        Geometry::new_indexed(
+            self.mesh,
+            NormalSource::Provided(self.normals),
+            self.faces,
+            tangent_space_information,
+        )
+    }
+}
+
+// Flat disc at `y` facing up or down, from the axis to `radius`.
+fn disc(radius: f32, y: f32, up: bool) -> Vec<ProfilePoint> {
+    let (normal, from, to) = if up {
+        (FVec2::y(), 0.0, radius)
+    } else {
+        (-FVec2::y(), radius, 0.0)
+    };
+
+    vec![
+        ProfilePoint::new(from, y, normal),
+        ProfilePoint::new(to, y, normal),
+    ]
+}
+
+/// Torus lying in the xz plane, with the center of its tube `ring_radius` away from the origin.
+pub struct Torus;
+
+impl Torus {
+    fn raw_geometry(
+        slices: usize,
+        sides: usize,
+        ring_radius: f32,
+        tube_radius: f32,
+    ) -> RevolvedShape {
+        let side_angle = 2.0 * std::f32::consts::PI / sides as f32;
+
+        // Starts on the inside of the tube and goes over its top, so it's seen from outside.
+        let profile = (0..=sides)
+            .map(|i| {
+                let (sin, cos) = (i as f32 * side_angle).sin_cos();
+                let normal = FVec2::new(-cos, sin);
+
+                ProfilePoint::new(
+                    ring_radius + tube_radius * normal.x,
+                    tube_radius * normal.y,
+                    normal,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        RevolvedShape::new(&[profile], slices)
+    }
+
+    pub fn geometry(slices: usize, sides: usize, ring_radius: f32, tube_radius: f32) -> Geometry {
+        Self::raw_geometry(slices, sides, ring_radius, tube_radius).into_geometry(false)
+    }
+
+    pub fn geometry_tan_space(
+        slices: usize,
+        sides: usize,
+        ring_radius: f32,
+        tube_radius: f32,
+    ) -> Geometry {
+        Self::raw_geometry(slices, sides, ring_radius, tube_radius).into_geometry(true)
+    }
+
+    pub fn uvs(slices: usize, sides: usize, ring_radius: f32, tube_radius: f32) -> Vec<FVec2> {
+        Self::raw_geometry(slices, sides, ring_radius, tube_radius).uvs
+    }
+}
+
+/// Closed cylinder along the y axis, with radius of 0.5 and height of 1, fitting a `Cube`.
+pub struct Cylinder;
+
+impl Cylinder {
+    fn raw_geometry(slices: usize) -> RevolvedShape {
+        let side = vec![
+            ProfilePoint::new(0.5, 0.5, FVec2::x()),
+            ProfilePoint::new(0.5, -0.5, FVec2::x()),
+        ];
+
+        RevolvedShape::new(
+            &[disc(0.5, 0.5, true), side, disc(0.5, -0.5, false)],
+            slices,
+        )
+    }
+
+    pub fn geometry(slices: usize) -> Geometry {
+        Self::raw_geometry(slices).into_geometry(false)
+    }
+
+    pub fn geometry_tan_space(slices: usize) -> Geometry {
+        Self::raw_geometry(slices).into_geometry(true)
+    }
+
+    pub fn uvs(slices: usize) -> Vec<FVec2> {
+        Self::raw_geometry(slices).uvs
+    }
+}
+
+/// Cone along the y axis with its apex at the top, fitting a `Cube` like `Cylinder`.
+pub struct Cone;
+
+impl Cone {
+    fn raw_geometry(slices: usize) -> RevolvedShape {
+        // Perpendicular to the slope going from the apex down to the base.
+        let normal = FVec2::new(1.0, 0.5).normalize();
+        let side = vec![
+            ProfilePoint::new(0.0, 0.5, normal),
+            ProfilePoint::new(0.5, -0.5, normal),
+        ];
+
+        RevolvedShape::new(&[side, disc(0.5, -0.5, false)], slices)
+    }
+
+    pub fn geometry(slices: usize) -> Geometry {
+        Self::raw_geometry(slices).into_geometry(false)
+    }
+
+    pub fn geometry_tan_space(slices: usize) -> Geometry {
+        Self::raw_geometry(slices).into_geometry(true)
+    }
+
+    pub fn uvs(slices: usize) -> Vec<FVec2> {
+        Self::raw_geometry(slices).uvs
+    }
+}
+
+/// Cylinder with hemispheres on both ends, along the y axis. It has radius of 0.5
+/// and is `height` tall including the hemispheres, which is at least 1.
+pub struct Capsule;
+
+impl Capsule {
+    fn raw_geometry(slices: usize, stacks: usize, height: f32) -> RevolvedShape {
+        let radius = 0.5;
+        let half_body = (height * 0.5 - radius).max(0.0);
+        let stack_angle = std::f32::consts::FRAC_PI_2 / stacks as f32;
+
+        let hemisphere_point = |angle: f32, center: f32| {
+            let normal = FVec2::new(angle.sin(), angle.cos());
+            ProfilePoint::new(radius * normal.x, center + radius * normal.y, normal)
+        };
+
+        // Both hemispheres meet the body with normals pointing outward, so it's
+        // one smooth segment. Without the body, hemispheres share their rims.
+        let bottom_start = usize::from(half_body == 0.0);
+        let profile = (0..=stacks)
+            .map(|i| hemisphere_point(i as f32 * stack_angle, half_body))
+            .chain((bottom_start..=stacks).map(|i| {
+                hemisphere_point(
+                    std::f32::consts::FRAC_PI_2 + i as f32 * stack_angle,
+                    -half_body,
+                )
+            }))
+            .collect::<Vec<_>>();
+
+        RevolvedShape::new(&[profile], slices)
+    }
+
+    pub fn geometry(slices: usize, stacks: usize, height: f32) -> Geometry {
+        Self::raw_geometry(slices, stacks, height).into_geometry(false)
+    }
+
+    pub fn geometry_tan_space(slices: usize, stacks: usize, height: f32) -> Geometry {
+        Self::raw_geometry(slices, stacks, height).into_geometry(true)
+    }
+
+    pub fn uvs(slices: usize, stacks: usize, height: f32) -> Vec<FVec2> {
+        Self::raw_geometry(slices, stacks, height).uvs
+    }
+}
@@ -1,11 +1,147 @@
 use std::sync::Arc;
 
 use crate::{
+    light_scene::Light,
     mesh::{Mesh, MeshBuilder},
     render_context::RenderContext,
+    render_pass::{FrameContext, RenderPass},
     shapes::Cube,
 };
-use anyhow::Result;
+use anyhow::{Context, Result};
+use nalgebra as na;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackgroundMode {
+    #[default]
+    Skybox,
+    SolidColor,
+    Gradient,
+    ProceduralSky,
+}
+
+/// Sun position and atmosphere of the procedural sky. The sun rises in the east at 6:00,
+/// is highest at 12:00 and sets at 18:00.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SkySettings {
+    /// Hours, in `0.0..24.0`.
+    pub time_of_day: f32,
+    /// Seconds a whole day takes, the sun stands still when 0.
+    pub day_length: f32,
+    /// Degrees from +X around the vertical axis to where the sun rises.
+    pub azimuth: f32,
+    /// Degrees between the zenith and the sun at noon.
+    pub tilt: f32,
+    /// Haziness of the atmosphere, from 2 for a clear sky to 10 for haze.
+    pub turbidity: f32,
+    pub intensity: f32,
+}
+
+impl Default for SkySettings {
+    fn default() -> Self {
+        Self {
+            time_of_day: 10.0,
+            day_length: 0.0,
+            azimuth: 0.0,
+            tilt: 30.0,
+            turbidity: 3.0,
+            intensity: 1.0,
+        }
+    }
+}
+
+impl SkySettings {
+    pub fn advance(&mut self, dt: f32) {
+        if self.day_length > 0.0 {
+            self.time_of_day = (self.time_of_day + dt * 24.0 / self.day_length).rem_euclid(24.0);
+        }
+    }
+
+    /// Direction towards the sun.
+    pub fn sun_direction(&self) -> na::Vector3<f32> {
+        let (sin_az, cos_az) = self.azimuth.to_radians().sin_cos();
+        let (sin_tilt, cos_tilt) = self.tilt.to_radians().sin_cos();
+        let east = na::Vector3::new(cos_az, 0.0, sin_az);
+        let noon = na::Vector3::new(-sin_az * sin_tilt, cos_tilt, cos_az * sin_tilt);
+
+        let angle = (self.time_of_day - 6.0) / 12.0 * std::f32::consts::PI;
+        (east * angle.cos() + noon * angle.sin()).normalize()
+    }
+
+    /// Points a directional light along the sun, reddened near the horizon and
+    /// fading out at night, so the scene is lit the way the sky looks.
+    pub fn light_sun(&self, light: &mut Light) {
+        let sun = self.sun_direction();
+        let t = ((sun.y + 0.1) / 0.15).clamp(0.0, 1.0);
+        let daylight = t * t * (3.0 - 2.0 * t);
+        let low = (1.0 - sun.y.max(0.0)).powi(4);
+        let tint = na::Vector3::new(1.0, 1.0 - 0.35 * low, 1.0 - 0.65 * low);
+
+        light.direction = (-sun).push(0.0);
+        light.ambient =
+            (na::Vector3::new(0.04, 0.05, 0.07) * (0.2 + 0.8 * daylight)).push(light.ambient.w);
+        light.diffuse = (tint * 0.8 * daylight).push(light.diffuse.w);
+        light.specular = (tint * 0.5 * daylight).push(light.specular.w);
+    }
+
+    fn uniform(&self) -> [[f32; 4]; 2] {
+        let sun = self.sun_direction();
+
+        [
+            [sun.x, sun.y, sun.z, self.turbidity],
+            [self.intensity, 0.0, 0.0, 0.0],
+        ]
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackgroundSettings {
+    pub mode: BackgroundMode,
+    pub clear_color: [f32; 3],
+    pub gradient_top: [f32; 3],
+    pub gradient_bottom: [f32; 3],
+    pub sky: SkySettings,
+}
+
+impl Default for BackgroundSettings {
+    fn default() -> Self {
+        Self {
+            mode: BackgroundMode::Skybox,
+            clear_color: [0.0, 0.0, 0.0],
+            gradient_top: [0.25, 0.45, 0.8],
+            gradient_bottom: [0.8, 0.85, 0.9],
+            sky: SkySettings::default(),
+        }
+    }
+}
+
+impl BackgroundSettings {
+    pub fn clear_color(&self) -> wgpu::Color {
+        let [r, g, b] = self.clear_color;
+
+        wgpu::Color {
+            r: r as f64,
+            g: g as f64,
+            b: b as f64,
+            a: 1.0,
+        }
+    }
+
+    // Solid color is just a gradient with both ends being the same.
+    fn gradient(&self) -> [[f32; 4]; 2] {
+        let (top, bottom) = match self.mode {
+            BackgroundMode::Gradient => (self.gradient_top, self.gradient_bottom),
+            _ => (self.clear_color, self.clear_color),
+        };
+
+        [
+            [top[0], top[1], top[2], 1.0],
+            [bottom[0], bottom[1], bottom[2], 1.0],
+        ]
+    }
+}
 
 pub struct SkyboxPass<'window> {
     render_ctx: Arc<RenderContext<'window>>,
@@ -14,12 +150,21 @@ pub struct SkyboxPass<'window> {
     rgba16_pipeline: wgpu::RenderPipeline,
     vbuf: wgpu::Buffer,
     ibuf: wgpu::Buffer,
+    gradient_buf: wgpu::Buffer,
+    gradient_bg: wgpu::BindGroup,
+    gradient_rgba8_pipeline: wgpu::RenderPipeline,
+    gradient_rgba16_pipeline: wgpu::RenderPipeline,
+    sky_buf: wgpu::Buffer,
+    sky_bg: wgpu::BindGroup,
+    sky_rgba8_pipeline: wgpu::RenderPipeline,
+    sky_rgba16_pipeline: wgpu::RenderPipeline,
 }
 
 impl<'window> SkyboxPass<'window> {
     pub fn new(render_ctx: Arc<RenderContext<'window>>, skybox_tex: wgpu::Texture) -> Result<Self> {
         let RenderContext {
             gpu,
+            bind_group_layouts,
             shader_compiler,
             scene_uniform,
             ..
@@ -65,29 +210,28 @@ impl<'window> SkyboxPass<'window> {
             ..Default::default()
         });
 
-        let bgl = gpu
-            .device
-            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                            view_dimension: wgpu::TextureViewDimension::Cube,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
+        let bgl = bind_group_layouts.get(
+            gpu,
+            "SkyboxPass::BindGroupLayout",
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
                     },
-                ],
-            });
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        );
 
         let bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
@@ -105,8 +249,7 @@ impl<'window> SkyboxPass<'window> {
         });
 
         let shader = gpu.shader_from_module(
-            shader_compiler
-                .compilation_unit("./shaders/skybox/simple.wgsl")?
+            gpu.with_depth_defs(shader_compiler.compilation_unit("./shaders/skybox/simple.wgsl")?)
                 .compile(&[])?,
         );
 
@@ -135,7 +278,7 @@ impl<'window> SkyboxPass<'window> {
                 depth_stencil: Some(wgpu::DepthStencilState {
                     format: wgpu::TextureFormat::Depth32Float,
                     depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::LessEqual,
+                    depth_compare: gpu.depth_compare(wgpu::CompareFunction::LessEqual),
                     stencil: Default::default(),
                     bias: Default::default(),
                 }),
@@ -165,7 +308,7 @@ impl<'window> SkyboxPass<'window> {
                 depth_stencil: Some(wgpu::DepthStencilState {
                     format: wgpu::TextureFormat::Depth32Float,
                     depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::LessEqual,
+                    depth_compare: gpu.depth_compare(wgpu::CompareFunction::LessEqual),
                     stencil: Default::default(),
                     bias: Default::default(),
                 }),
@@ -182,6 +325,128 @@ impl<'window> SkyboxPass<'window> {
                 multiview: None,
             });
 
+        let gradient_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("SkyboxPass::Gradient"),
+            size: std::mem::size_of::<[[f32; 4]; 2]>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let gradient_bgl = bind_group_layouts.get(
+            gpu,
+            "SkyboxPass::GradientBindGroupLayout",
+            &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        );
+
+        let gradient_bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &gradient_bgl,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: gradient_buf.as_entire_binding(),
+            }],
+        });
+
+        let gradient_shader = gpu.shader_from_module(
+            gpu.with_depth_defs(
+                shader_compiler.compilation_unit("./shaders/skybox/gradient.wgsl")?,
+            )
+            .compile(&[])?,
+        );
+
+        let gradient_pipelinel =
+            gpu.device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &[scene_uniform.layout(), &gradient_bgl],
+                    push_constant_ranges: &[],
+                });
+
+        let sky_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("SkyboxPass::Sky"),
+            size: std::mem::size_of::<[[f32; 4]; 2]>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // The sky needs a single uniform as well, so it shares the gradient layout.
+        let sky_bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &gradient_bgl,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: sky_buf.as_entire_binding(),
+            }],
+        });
+
+        let sky_shader = gpu.shader_from_module(
+            gpu.with_depth_defs(shader_compiler.compilation_unit("./shaders/skybox/sky.wgsl")?)
+                .compile(&[])?,
+        );
+
+        let fullscreen_pipeline =
+            |label: &str, shader: &wgpu::ShaderModule, format: wgpu::TextureFormat| {
+                gpu.device
+                    .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                        label: Some(label),
+                        layout: Some(&gradient_pipelinel),
+                        vertex: wgpu::VertexState {
+                            module: shader,
+                            entry_point: "vs_main",
+                            buffers: &[],
+                        },
+                        primitive: wgpu::PrimitiveState {
+                            topology: wgpu::PrimitiveTopology::TriangleStrip,
+                            ..Default::default()
+                        },
+                        depth_stencil: Some(wgpu::DepthStencilState {
+                            format: wgpu::TextureFormat::Depth32Float,
+                            depth_write_enabled: false,
+                            depth_compare: gpu.depth_compare(wgpu::CompareFunction::LessEqual),
+                            stencil: Default::default(),
+                            bias: Default::default(),
+                        }),
+                        multisample: wgpu::MultisampleState::default(),
+                        fragment: Some(wgpu::FragmentState {
+                            module: shader,
+                            entry_point: "fs_main",
+                            targets: &[Some(wgpu::ColorTargetState {
+                                format,
+                                blend: Some(wgpu::BlendState::REPLACE),
+                                write_mask: wgpu::ColorWrites::ALL,
+                            })],
+                        }),
+                        multiview: None,
+                    })
+            };
+
+        let gradient_rgba8_pipeline = fullscreen_pipeline(
+            "SkyboxPass::Gradient",
+            &gradient_shader,
+            gpu.swapchain_format(),
+        );
+        let gradient_rgba16_pipeline = fullscreen_pipeline(
+            "SkyboxPass::Gradient",
+            &gradient_shader,
+            wgpu::TextureFormat::Rgba16Float,
+        );
+        let sky_rgba8_pipeline =
+            fullscreen_pipeline("SkyboxPass::Sky", &sky_shader, gpu.swapchain_format());
+        let sky_rgba16_pipeline = fullscreen_pipeline(
+            "SkyboxPass::Sky",
+            &sky_shader,
+            wgpu::TextureFormat::Rgba16Float,
+        );
+
         Ok(Self {
             render_ctx,
             bg,
@@ -189,14 +454,45 @@ impl<'window> SkyboxPass<'window> {
             rgba16_pipeline,
             vbuf,
             ibuf,
+            gradient_buf,
+            gradient_bg,
+            gradient_rgba8_pipeline,
+            gradient_rgba16_pipeline,
+            sky_buf,
+            sky_bg,
+            sky_rgba8_pipeline,
+            sky_rgba16_pipeline,
         })
     }
 
-    pub fn render(&self, output_tv: wgpu::TextureView, hdr: bool) {
+    pub fn render(
+        &self,
+        output_tv: &wgpu::TextureView,
+        hdr: bool,
+        background: &BackgroundSettings,
+    ) {
         let RenderContext {
             gpu, scene_uniform, ..
         } = self.render_ctx.as_ref();
 
+        match background.mode {
+            BackgroundMode::Skybox => {}
+            BackgroundMode::SolidColor | BackgroundMode::Gradient => {
+                gpu.queue.write_buffer(
+                    &self.gradient_buf,
+                    0,
+                    bytemuck::cast_slice(&background.gradient()),
+                );
+            }
+            BackgroundMode::ProceduralSky => {
+                gpu.queue.write_buffer(
+                    &self.sky_buf,
+                    0,
+                    bytemuck::cast_slice(&background.sky.uniform()),
+                );
+            }
+        }
+
         let mut encoder = gpu
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
@@ -208,7 +504,7 @@ impl<'window> SkyboxPass<'window> {
             let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &frame_view,
+                    view: frame_view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Load,
@@ -227,20 +523,65 @@ impl<'window> SkyboxPass<'window> {
                 occlusion_query_set: None,
             });
 
-            if hdr {
-                rpass.set_pipeline(&self.rgba16_pipeline);
-            } else {
-                rpass.set_pipeline(&self.rgba8_pipeline);
-            }
-
             rpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
-            rpass.set_bind_group(1, &self.bg, &[]);
 
-            rpass.set_vertex_buffer(0, self.vbuf.slice(..));
-            rpass.set_index_buffer(self.ibuf.slice(..), wgpu::IndexFormat::Uint32);
-            rpass.draw_indexed(0..36, 0, 0..1);
+            match background.mode {
+                BackgroundMode::Skybox => {
+                    if hdr {
+                        rpass.set_pipeline(&self.rgba16_pipeline);
+                    } else {
+                        rpass.set_pipeline(&self.rgba8_pipeline);
+                    }
+
+                    rpass.set_bind_group(1, &self.bg, &[]);
+
+                    rpass.set_vertex_buffer(0, self.vbuf.slice(..));
+                    rpass.set_index_buffer(self.ibuf.slice(..), wgpu::IndexFormat::Uint32);
+                    rpass.draw_indexed(0..36, 0, 0..1);
+                }
+                BackgroundMode::SolidColor | BackgroundMode::Gradient => {
+                    if hdr {
+                        rpass.set_pipeline(&self.gradient_rgba16_pipeline);
+                    } else {
+                        rpass.set_pipeline(&self.gradient_rgba8_pipeline);
+                    }
+
+                    rpass.set_bind_group(1, &self.gradient_bg, &[]);
+                    rpass.draw(0..4, 0..1);
+                }
+                BackgroundMode::ProceduralSky => {
+                    if hdr {
+                        rpass.set_pipeline(&self.sky_rgba16_pipeline);
+                    } else {
+                        rpass.set_pipeline(&self.sky_rgba8_pipeline);
+                    }
+
+                    rpass.set_bind_group(1, &self.sky_bg, &[]);
+                    rpass.draw(0..4, 0..1);
+                }
+            }
         }
 
         gpu.queue.submit(Some(encoder.finish()));
     }
 }
+
+impl RenderPass for SkyboxPass<'_> {
+    fn name(&self) -> &'static str {
+        "SkyboxPass"
+    }
+
+    fn enabled(&self, ctx: &FrameContext) -> bool {
+        !ctx.settings.deferred_debug_shown()
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        let (scene_color, hdr) = ctx
+            .scene_color
+            .as_ref()
+            .context("background is drawn behind a lit scene")?;
+        Self::render(self, scene_color, *hdr, &ctx.settings.background);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use nalgebra as na;
+
+use crate::{
+    gpu::Gpu,
+    material::{MaterialAtlas, MaterialId, SpecularTexture, SPLAT_LAYERS},
+    mesh::{Geometry, Mesh, MeshBuilder, NormalSource},
+    scene::{Instance, Scene, SceneModelBuilder, SceneObjectId},
+};
+
+type FVec3 = na::Vector3<f32>;
+type FVec2 = na::Vector2<f32>;
+
+#[derive(Clone, Copy, Debug)]
+pub struct TerrainSettings {
+    /// Extent of the longer side of the heightmap in world units.
+    pub size: f32,
+    /// Height of the brightest texels, the darkest ones lie at zero.
+    pub height: f32,
+    /// Quads along a side of a chunk.
+    pub chunk_quads: u32,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        Self {
+            size: 100.0,
+            height: 10.0,
+            chunk_quads: 32,
+        }
+    }
+}
+
+/// Grid mesh with a vertex for every texel of a heightmap, centered on the origin.
+/// It's split into square chunks, so each can be culled using its own bounds.
+pub struct Terrain {
+    // Normalized to 0..1, row by row from -z to +z.
+    heights: Vec<f32>,
+    width: u32,
+    depth: u32,
+    settings: TerrainSettings,
+}
+
+impl Terrain {
+    /// Reads a grayscale heightmap, colors are converted to luminance.
+    pub fn load(path: impl AsRef<Path>, settings: TerrainSettings) -> Result<Self> {
+        let path = path.as_ref();
+        let heightmap = image::open(path)
+            .with_context(|| format!("failed to load heightmap {}", path.display()))?
+            .to_luma16();
+
+        let (width, depth) = heightmap.dimensions();
+        if width < 2 || depth < 2 {
+            anyhow::bail!("heightmap {} is smaller than 2x2 texels", path.display());
+        }
+
+        let heights = heightmap
+            .pixels()
+            .map(|pixel| pixel.0[0] as f32 / u16::MAX as f32)
+            .collect();
+
+        Ok(Self {
+            heights,
+            width,
+            depth,
+            settings,
+        })
+    }
+
+    /// Chunks a heightmap of the given size is split into.
+    pub fn chunk_count(width: u32, depth: u32, chunk_quads: u32) -> usize {
+        let chunk_quads = chunk_quads.max(1);
+        ((width - 1).div_ceil(chunk_quads) * (depth - 1).div_ceil(chunk_quads)) as usize
+    }
+
+    fn spacing(&self) -> f32 {
+        self.settings.size / (self.width.max(self.depth) - 1) as f32
+    }
+
+    fn height(&self, x: u32, z: u32) -> f32 {
+        self.heights[(z * self.width + x) as usize]
+    }
+
+    fn position(&self, x: u32, z: u32) -> FVec3 {
+        let spacing = self.spacing();
+        FVec3::new(
+            (x as f32 - (self.width - 1) as f32 * 0.5) * spacing,
+            self.height(x, z) * self.settings.height,
+            (z as f32 - (self.depth - 1) as f32 * 0.5) * spacing,
+        )
+    }
+
+    // Central differences over the whole heightmap, so normals match along chunk borders.
+    fn normal(&self, x: u32, z: u32) -> FVec3 {
+        let (x0, x1) = (x.saturating_sub(1), (x + 1).min(self.width - 1));
+        let (z0, z1) = (z.saturating_sub(1), (z + 1).min(self.depth - 1));
+        let spacing = self.spacing();
+
+        let dx = (self.height(x1, z) - self.height(x0, z)) * self.settings.height
+            / ((x1 - x0) as f32 * spacing);
+        let dz = (self.height(x, z1) - self.height(x, z0)) * self.settings.height
+            / ((z1 - z0) as f32 * spacing);
+
+        FVec3::new(-dx, 1.0, -dz).normalize()
+    }
+
+    // UVs span the whole terrain, so a single splat map covers all chunks.
+    fn uv(&self, x: u32, z: u32) -> FVec2 {
+        FVec2::new(
+            x as f32 / (self.width - 1) as f32,
+            z as f32 / (self.depth - 1) as f32,
+        )
+    }
+
+    /// Meshes of all chunks, in world space of the terrain.
+    pub fn chunks(&self) -> Result<Vec<Mesh>> {
+        let chunk_quads = self.settings.chunk_quads.max(1);
+        let mut chunks = Vec::with_capacity(Self::chunk_count(self.width, self.depth, chunk_quads));
+
+        for z0 in (0..self.depth - 1).step_by(chunk_quads as usize) {
+            for x0 in (0..self.width - 1).step_by(chunk_quads as usize) {
+                // Border vertices are repeated in neighbouring chunks.
+                let x1 = (x0 + chunk_quads).min(self.width - 1);
+                let z1 = (z0 + chunk_quads).min(self.depth - 1);
+                let row = x1 - x0 + 1;
+
+                let texels = (z0..=z1).flat_map(|z| (x0..=x1).map(move |x| (x, z)));
+                let mesh = texels.clone().map(|(x, z)| self.position(x, z)).collect();
+                let normals = texels.clone().map(|(x, z)| self.normal(x, z)).collect();
+                let uvs = texels.map(|(x, z)| self.uv(x, z)).collect();
+
+                let mut faces = Vec::with_capacity(((x1 - x0) * (z1 - z0) * 6) as usize);
+                for r in 0..z1 - z0 {
+                    for c in 0..x1 - x0 {
+                        let tl = r * row + c;
+                        let tr = tl + 1;
+                        let bl = tl + row;
+                        let br = bl + 1;
+
+                        faces.extend([bl, br, tl, tl, br, tr]);
+                    }
+                }
+
+                chunks.push(
+                    MeshBuilder::new()
+                        .with_geometry(Geometry::new_indexed(
+                            mesh,
+                            NormalSource::Provided(normals),
+                            faces,
+                            None,
+                        ))
+                        .with_texture_uvs(uvs)
+                        .with_name(format!("Terrain chunk ({x0}, {z0})"))
+                        .build()?,
+                );
+            }
+        }
+
+        Ok(chunks)
+    }
+
+    /// Weights of splat layers for every texel of the heightmap: low ground, slopes
+    /// in between, steep slopes and peaks, in channels from red to alpha.
+    pub fn splat_map(&self) -> image::RgbaImage {
+        fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+            let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        }
+
+        image::RgbaImage::from_fn(self.width, self.depth, |x, z| {
+            let height = self.height(x, z);
+            let steepness = 1.0 - self.normal(x, z).y;
+
+            let steep = smoothstep(0.2, 0.4, steepness);
+            let peak = smoothstep(0.6, 0.8, height) * (1.0 - steep);
+            let low = (1.0 - smoothstep(0.15, 0.3, height)) * (1.0 - steep);
+            let middle = (1.0 - steep - peak - low).max(0.0);
+
+            image::Rgba([low, middle, steep, peak].map(|weight| (weight * 255.0).round() as u8))
+        })
+    }
+
+    /// Splat material using the terrain's splat map, with diffuse maps at `layers`
+    /// repeating `tiling` times across the terrain.
+    pub fn add_material(
+        &self,
+        gpu: &Gpu,
+        material_atlas: &mut MaterialAtlas,
+        layers: &[PathBuf; SPLAT_LAYERS],
+        tiling: f32,
+    ) -> Result<MaterialId> {
+        material_atlas.add_phong_splat(
+            gpu,
+            self.splat_map(),
+            layers,
+            tiling,
+            SpecularTexture::FullDiffuse,
+        )
+    }
+
+    /// Adds every chunk as a separate object placed by `instance`.
+    pub fn add_to_scene(
+        &self,
+        scene: &mut Scene,
+        instance: Instance,
+        material: MaterialId,
+    ) -> Result<Vec<SceneObjectId>> {
+        self.chunks()?
+            .into_iter()
+            .map(|chunk| {
+                let name = chunk.name().map(str::to_owned);
+                let model =
+                    scene.load_model(SceneModelBuilder::default().with_meshes(vec![chunk]))?;
+                let id = scene.add_object_with_material(model, instance, material);
+                if let Some(name) = name {
+                    scene.set_object_name(id, name);
+                }
+
+                Ok(id)
+            })
+            .collect()
+    }
+}
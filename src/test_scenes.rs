@@ -1,11 +1,11 @@
 use crate::{
     camera::{Camera, GpuCamera},
     gpu::Gpu,
+    light_scene::LightScene,
     loader::{ObjLoader, ObjLoaderSettings},
     material::{MaterialAtlas, SpecularTexture},
     mesh::MeshBuilder,
-    light_scene::LightScene,
-    projection::{wgpu_projection, GpuProjection},
+    projection::{GpuProjection, Perspective},
     scene::{Instance, Scene, SceneModelBuilder, SceneObjectId},
     shapes::{Cube, Plane, UVSphere},
 };
@@ -14,13 +14,12 @@ use image::EncodableLayout;
 use nalgebra as na;
 use std::collections::HashMap;
 
-type TestScene = (
+pub type TestScene = (
     Scene,
     MaterialAtlas,
     LightScene,
     GpuCamera,
     GpuProjection,
-    na::Matrix4<f32>,
     HashMap<String, SceneObjectId>,
 );
 
@@ -78,11 +77,11 @@ pub fn blinn_phong_scene(gpu: &Gpu) -> Result<TestScene> {
     let mut material_atlas = MaterialAtlas::new(gpu);
 
     let plane_uv = MeshBuilder::new()
-        .with_geometry(Plane::geometry())
-        .with_texture_uvs(Plane::uvs().into_iter().map(|uv| uv * 10.0).collect())
+        .with_geometry(Plane::geometry(1))
+        .with_texture_uvs(Plane::uvs(1).into_iter().map(|uv| uv * 10.0).collect())
         .build()?;
 
-    let plane_uv = scene.load_model(SceneModelBuilder::default().with_meshes(vec![plane_uv]));
+    let plane_uv = scene.load_model(SceneModelBuilder::default().with_meshes(vec![plane_uv]))?;
     let woodfloor = material_atlas.add_phong_textured(
         gpu,
         "./textures/woodfloor_detail.jpg",
@@ -95,11 +94,10 @@ pub fn blinn_phong_scene(gpu: &Gpu) -> Result<TestScene> {
         woodfloor,
     );
 
-    let projection_mat =
-        na::Matrix4::new_perspective(gpu.aspect_ratio(), 45.0f32.to_radians(), 0.1, 100.0);
-
-    let projection: GpuProjection = GpuProjection::new(projection_mat, &gpu.device)?;
-    let projection_mat = wgpu_projection(projection_mat);
+    let projection = GpuProjection::new(
+        Perspective::new(gpu.aspect_ratio(), 45.0f32.to_radians(), 0.1, 100.0),
+        gpu,
+    )?;
 
     let mut lights = LightScene::default();
 
@@ -126,7 +124,6 @@ pub fn blinn_phong_scene(gpu: &Gpu) -> Result<TestScene> {
         lights,
         camera,
         projection,
-        wgpu_projection(projection_mat),
         HashMap::default(),
     ))
 }
@@ -142,12 +139,17 @@ pub fn teapot_scene(gpu: &Gpu) -> Result<TestScene> {
         .build()?;
 
     let plane_mesh = MeshBuilder::new()
-        .with_geometry(Plane::geometry())
+        .with_geometry(Plane::geometry(1))
         .build()?;
 
     let sphere_mesh = MeshBuilder::new()
         .with_geometry(UVSphere::geometry(32, 32))
         .build()?;
+    let sphere_lod_mesh = |subdivisions| {
+        MeshBuilder::new()
+            .with_geometry(UVSphere::geometry(subdivisions, subdivisions))
+            .build()
+    };
 
     let (teapot_mesh, _) = ObjLoader::load(
         "./models/teapot.obj",
@@ -167,19 +169,24 @@ pub fn teapot_scene(gpu: &Gpu) -> Result<TestScene> {
         },
     )?;
 
-    let teapot = scene.load_model(SceneModelBuilder::default().with_meshes(teapot_mesh));
-    let cube = scene.load_model(SceneModelBuilder::default().with_meshes(vec![cube_mesh]));
-    let plane = scene.load_model(SceneModelBuilder::default().with_meshes(vec![plane_mesh]));
-    let uv_sphere = scene.load_model(SceneModelBuilder::default().with_meshes(vec![sphere_mesh]));
+    let teapot = scene.load_model(SceneModelBuilder::default().with_meshes(teapot_mesh))?;
+    let cube = scene.load_model(SceneModelBuilder::default().with_meshes(vec![cube_mesh]))?;
+    let plane = scene.load_model(SceneModelBuilder::default().with_meshes(vec![plane_mesh]))?;
+    let uv_sphere = scene.load_model(
+        SceneModelBuilder::default()
+            .with_meshes(vec![sphere_mesh])
+            .with_lod(vec![sphere_lod_mesh(16)?], 0.3)
+            .with_lod(vec![sphere_lod_mesh(8)?], 0.1),
+    )?;
 
     let cube_uv_nmap =
-        scene.load_model(SceneModelBuilder::default().with_meshes(vec![cube_uvtb_mesh]));
+        scene.load_model(SceneModelBuilder::default().with_meshes(vec![cube_uvtb_mesh]))?;
 
     let maya = scene.load_model(
         SceneModelBuilder::default()
             .with_meshes(maya_mesh)
             .with_local_materials(maya_materials),
-    );
+    )?;
 
     let light_gray = material_atlas.add_phong_solid(
         gpu,
@@ -194,6 +201,7 @@ pub fn teapot_scene(gpu: &Gpu) -> Result<TestScene> {
         na::Vector4::new(0.5, 0.5, 1.0, 0.0),
         na::Vector4::new(0.5, 0.5, 1.0, 32.0),
     )?;
+    material_atlas.set_reflectivity(gpu, lily, 0.4)?;
 
     let quite_red = material_atlas.add_phong_solid(
         gpu,
@@ -313,11 +321,10 @@ pub fn teapot_scene(gpu: &Gpu) -> Result<TestScene> {
         ))),
     );
 
-    let projection_mat =
-        na::Matrix4::new_perspective(gpu.aspect_ratio(), 45.0f32.to_radians(), 0.1, 100.0);
-
-    let projection: GpuProjection = GpuProjection::new(projection_mat, &gpu.device)?;
-    let projection_mat = wgpu_projection(projection_mat);
+    let projection = GpuProjection::new(
+        Perspective::new(gpu.aspect_ratio(), 45.0f32.to_radians(), 0.1, 100.0),
+        gpu,
+    )?;
 
     let mut camera = GpuCamera::new(
         Camera::new(
@@ -361,7 +368,6 @@ pub fn teapot_scene(gpu: &Gpu) -> Result<TestScene> {
         lights,
         camera,
         projection,
-        wgpu_projection(projection_mat),
         HashMap::default(),
     ))
 }
@@ -371,12 +377,12 @@ pub fn normal_mapping_test(gpu: &Gpu) -> Result<TestScene> {
     let mut material_atlas = MaterialAtlas::new(gpu);
 
     let plane_uv = MeshBuilder::new()
-        .with_geometry(Plane::geometry_tan_space())
-        .with_texture_uvs(Plane::uvs())
+        .with_geometry(Plane::geometry_tan_space(1))
+        .with_texture_uvs(Plane::uvs(1))
         .build()?;
 
     let plane = MeshBuilder::new()
-        .with_geometry(Plane::geometry())
+        .with_geometry(Plane::geometry(1))
         .build()?;
 
     let brickwall_material = material_atlas.add_phong_textured_normal(
@@ -386,13 +392,13 @@ pub fn normal_mapping_test(gpu: &Gpu) -> Result<TestScene> {
         "./textures/brickwall_normal.jpg",
     )?;
 
-    let plane = scene.load_model(SceneModelBuilder::default().with_meshes(vec![plane]));
+    let plane = scene.load_model(SceneModelBuilder::default().with_meshes(vec![plane]))?;
 
     let brickwall = scene.load_model(
         SceneModelBuilder::default()
             .with_meshes(vec![plane_uv])
             .with_local_materials(vec![brickwall_material]),
-    );
+    )?;
 
     let yellow = material_atlas.add_phong_solid(
         gpu,
@@ -436,10 +442,10 @@ pub fn normal_mapping_test(gpu: &Gpu) -> Result<TestScene> {
         &gpu.device,
     )?;
 
-    let projection_mat =
-        na::Matrix4::new_perspective(gpu.aspect_ratio(), 45.0f32.to_radians(), 0.1, 100.0);
-
-    let projection: GpuProjection = GpuProjection::new(projection_mat, &gpu.device)?;
+    let projection = GpuProjection::new(
+        Perspective::new(gpu.aspect_ratio(), 45.0f32.to_radians(), 0.1, 100.0),
+        gpu,
+    )?;
 
     let mut scene_stuff = HashMap::new();
     scene_stuff.insert("brickwall".to_string(), wall);
@@ -450,7 +456,6 @@ pub fn normal_mapping_test(gpu: &Gpu) -> Result<TestScene> {
         lights,
         camera,
         projection,
-        wgpu_projection(projection_mat),
         scene_stuff,
     ))
 }
@@ -0,0 +1,152 @@
+use std::{collections::HashMap, ops::Deref, sync::Mutex};
+
+use crate::gpu::Gpu;
+
+// Frames a released texture is kept around for without being acquired again.
+const MAX_IDLE_FRAMES: u64 = 2;
+
+/// Size of a transient texture, worked out whenever it's acquired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetSize {
+    /// The viewport with both dimensions divided by the divisor, rounded up.
+    Viewport(u32),
+    Fixed(wgpu::Extent3d),
+}
+
+impl TargetSize {
+    pub fn resolve(&self, gpu: &Gpu) -> wgpu::Extent3d {
+        match *self {
+            Self::Viewport(divisor) => {
+                let viewport = gpu.viewport_size();
+                wgpu::Extent3d {
+                    width: viewport.width.div_ceil(divisor),
+                    height: viewport.height.div_ceil(divisor),
+                    depth_or_array_layers: 1,
+                }
+            }
+            Self::Fixed(size) => size,
+        }
+    }
+}
+
+/// Texture to get out of `TexturePool`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransientDesc {
+    pub size: TargetSize,
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct TextureKey {
+    size: wgpu::Extent3d,
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsages,
+}
+
+#[derive(Default)]
+struct PoolState {
+    frame: u64,
+    // Released textures, with the frame they were released in.
+    free: HashMap<TextureKey, Vec<(wgpu::Texture, u64)>>,
+}
+
+/// Textures passes only need while they render, like intermediate targets of SSAO
+/// or ping-pong targets of blurs and postprocessing. Released ones are handed out
+/// again to passes asking for the same size, format and usage, later in the frame
+/// or in the next ones. They keep the label they were created with.
+///
+/// Sizes following the viewport are worked out on every acquire, so targets are
+/// created anew after resizes. Ones left with the old size are let go by `end_frame`
+/// along with the rest of textures nobody asked for in a while.
+#[derive(Default)]
+pub struct TexturePool {
+    state: Mutex<PoolState>,
+}
+
+impl TexturePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Released texture matching `desc`, or a new one when there's none.
+    pub fn acquire(&self, gpu: &Gpu, label: &str, desc: &TransientDesc) -> TransientTexture<'_> {
+        let size = desc.size.resolve(gpu);
+        let key = TextureKey {
+            size,
+            format: desc.format,
+            usage: desc.usage,
+        };
+
+        let released = self
+            .state
+            .lock()
+            .unwrap()
+            .free
+            .get_mut(&key)
+            .and_then(|textures| textures.pop());
+        let texture = released.map_or_else(
+            || {
+                gpu.device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: desc.format,
+                    usage: desc.usage,
+                    view_formats: &[],
+                })
+            },
+            |(texture, _)| texture,
+        );
+
+        TransientTexture {
+            pool: self,
+            key,
+            texture: Some(texture),
+        }
+    }
+
+    fn release(&self, key: TextureKey, texture: wgpu::Texture) {
+        let mut state = self.state.lock().unwrap();
+        let frame = state.frame;
+        state.free.entry(key).or_default().push((texture, frame));
+    }
+
+    /// Lets go of textures which weren't acquired for a few frames.
+    pub fn end_frame(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.frame += 1;
+
+        let frame = state.frame;
+        state.free.retain(|_, textures| {
+            textures.retain(|(_, released)| frame - released <= MAX_IDLE_FRAMES);
+            !textures.is_empty()
+        });
+    }
+}
+
+/// Texture of `TexturePool`, going back to it when dropped. Passes acquiring it after
+/// that overwrite its contents, so it has to live until commands using it are submitted.
+pub struct TransientTexture<'a> {
+    pool: &'a TexturePool,
+    key: TextureKey,
+    texture: Option<wgpu::Texture>,
+}
+
+impl Deref for TransientTexture<'_> {
+    type Target = wgpu::Texture;
+
+    fn deref(&self) -> &wgpu::Texture {
+        self.texture.as_ref().unwrap()
+    }
+}
+
+impl Drop for TransientTexture<'_> {
+    fn drop(&mut self) {
+        if let Some(texture) = self.texture.take() {
+            self.pool.release(self.key, texture);
+        }
+    }
+}
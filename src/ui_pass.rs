@@ -111,4 +111,20 @@ impl<'window> UiPass<'window> {
 
         frame
     }
+
+    /// Takes in textures of a frame which wasn't rendered, later frames rely on them.
+    pub fn skip(&mut self, output: egui::FullOutput) {
+        let RenderContext { gpu, window, .. } = self.render_ctx.as_ref();
+
+        self.state
+            .handle_platform_output(window, output.platform_output);
+
+        for (tid, delta) in output.textures_delta.set {
+            self.renderer
+                .update_texture(&gpu.device, &gpu.queue, tid, &delta);
+        }
+        for tid in output.textures_delta.free {
+            self.renderer.free_texture(&tid);
+        }
+    }
 }
@@ -0,0 +1,111 @@
+use std::{num::NonZeroU64, sync::Mutex};
+
+use anyhow::{bail, Result};
+use encase::{internal::WriteInto, ShaderType, UniformBuffer};
+
+use crate::gpu::Gpu;
+
+#[derive(Default)]
+struct RingState {
+    // Where the next write starts.
+    head: u64,
+    // Bytes taken since the frame began, with padding and the tail skipped when wrapping.
+    frame_used: u64,
+}
+
+/// Uniforms written anew every frame, like matrices of shadow cascades or postprocess
+/// parameters, packed into one buffer and bound at dynamic offsets returned by writes.
+/// Offsets are aligned to `min_uniform_buffer_offset_alignment` of the device.
+///
+/// Writes go around the buffer. They're queued with `Queue::write_buffer`, so slots
+/// can be taken again once commands reading them were submitted, but everything
+/// written during a single frame has to fit.
+pub struct UniformRing {
+    buffer: wgpu::Buffer,
+    alignment: u64,
+    state: Mutex<RingState>,
+}
+
+impl UniformRing {
+    pub fn new(gpu: &Gpu, size: u64) -> Self {
+        let buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("UniformRing::Buffer"),
+            size,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            buffer,
+            alignment: gpu.device.limits().min_uniform_buffer_offset_alignment as u64,
+            state: Mutex::new(RingState::default()),
+        }
+    }
+
+    /// Type of layout entries bound to the ring, `size` being the size of a single write.
+    pub fn binding_type(size: u64) -> wgpu::BindingType {
+        wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: true,
+            min_binding_size: NonZeroU64::new(size),
+        }
+    }
+
+    /// Resource of bind group entries bound to the ring, see `binding_type`.
+    pub fn binding(&self, size: u64) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: &self.buffer,
+            offset: 0,
+            size: NonZeroU64::new(size),
+        })
+    }
+
+    fn allocate(&self, size: u64) -> Result<u64> {
+        let capacity = self.buffer.size();
+        let taken = wgpu::util::align_to(size, self.alignment);
+
+        let mut state = self.state.lock().unwrap();
+        let (offset, skipped) = if state.head + taken > capacity {
+            (0, capacity - state.head)
+        } else {
+            (state.head, 0)
+        };
+
+        if state.frame_used + skipped + taken > capacity {
+            bail!(
+                "uniforms written this frame don't fit into {} bytes of the ring",
+                capacity
+            );
+        }
+
+        state.frame_used += skipped + taken;
+        state.head = offset + taken;
+        Ok(offset)
+    }
+
+    /// Writes `contents` to a free slot and returns its dynamic offset.
+    /// The size must be a multiple of `wgpu::COPY_BUFFER_ALIGNMENT`.
+    pub fn write(&self, gpu: &Gpu, contents: &[u8]) -> Result<wgpu::DynamicOffset> {
+        let offset = self.allocate(contents.len() as u64)?;
+        gpu.queue.write_buffer(&self.buffer, offset, contents);
+
+        Ok(offset as wgpu::DynamicOffset)
+    }
+
+    /// Writes `value` laid out as a uniform, see `write`.
+    pub fn write_uniform<T: ShaderType + WriteInto>(
+        &self,
+        gpu: &Gpu,
+        value: &T,
+    ) -> Result<wgpu::DynamicOffset> {
+        let mut contents = UniformBuffer::new(Vec::with_capacity(value.size().get() as usize));
+        contents.write(value)?;
+
+        self.write(gpu, contents.into_inner().as_slice())
+    }
+
+    /// Slots written before can be taken again by the next frame.
+    pub fn end_frame(&self) {
+        self.state.lock().unwrap().frame_used = 0;
+    }
+}
@@ -0,0 +1,80 @@
+use std::num::NonZeroU64;
+
+use crate::gpu::Gpu;
+
+// Amount of data staged before the pending copies are submitted and staging memory is reused.
+const UPLOAD_CHUNK_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Uploads buffer contents through a bounded staging belt, so big meshes
+/// never need a CPU-side copy of the whole buffer.
+pub struct ChunkedUpload<'a, 'window> {
+    gpu: &'a Gpu<'window>,
+    belt: wgpu::util::StagingBelt,
+    encoder: wgpu::CommandEncoder,
+    pending: u64,
+}
+
+impl<'a, 'window> ChunkedUpload<'a, 'window> {
+    pub fn new(gpu: &'a Gpu<'window>) -> Self {
+        Self {
+            gpu,
+            belt: wgpu::util::StagingBelt::new(UPLOAD_CHUNK_SIZE),
+            encoder: Self::create_encoder(gpu),
+            pending: 0,
+        }
+    }
+
+    pub fn chunk_size() -> u64 {
+        UPLOAD_CHUNK_SIZE
+    }
+
+    /// Stages `size` bytes at `offset` of `buffer`, filled in by `writer`.
+    /// Both `offset` and `size` must be multiples of `wgpu::COPY_BUFFER_ALIGNMENT`.
+    pub fn write<F>(&mut self, buffer: &wgpu::Buffer, offset: u64, size: u64, writer: F)
+    where
+        F: FnOnce(&mut [u8]),
+    {
+        let Some(size) = NonZeroU64::new(size) else {
+            return;
+        };
+
+        let mut view =
+            self.belt
+                .write_buffer(&mut self.encoder, buffer, offset, size, &self.gpu.device);
+        writer(&mut view);
+        drop(view);
+
+        self.pending += size.get();
+        if self.pending >= UPLOAD_CHUNK_SIZE {
+            self.flush();
+        }
+    }
+
+    /// Submits all staged copies and waits for the staging memory to be available again.
+    pub fn flush(&mut self) {
+        if self.pending == 0 {
+            return;
+        }
+
+        self.belt.finish();
+        let encoder = std::mem::replace(&mut self.encoder, Self::create_encoder(self.gpu));
+        self.gpu.queue.submit(Some(encoder.finish()));
+        self.belt.recall();
+        self.gpu.device.poll(wgpu::Maintain::Wait);
+
+        self.pending = 0;
+    }
+
+    fn create_encoder(gpu: &Gpu) -> wgpu::CommandEncoder {
+        gpu.device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("ChunkedUpload::Encoder"),
+            })
+    }
+}
+
+impl Drop for ChunkedUpload<'_, '_> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
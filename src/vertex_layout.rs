@@ -0,0 +1,153 @@
+use std::ops::BitOr;
+
+use crate::shader_compiler::CompilationUnit;
+
+/// Optional per-vertex attributes. Position is always present.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VertexAttributes(u32);
+
+impl VertexAttributes {
+    pub const NORMAL: Self = Self(1 << 0);
+    // Tangent and bitangent vectors.
+    pub const TANGENT_SPACE: Self = Self(1 << 1);
+    pub const UV: Self = Self(1 << 2);
+    pub const COLOR: Self = Self(1 << 3);
+    // Four joint indices and their weights.
+    pub const SKIN: Self = Self(1 << 4);
+
+    pub const fn with(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for VertexAttributes {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        self.with(rhs)
+    }
+}
+
+type AttributeComponent = (&'static str, wgpu::VertexFormat);
+
+// Attributes in the order they are interleaved. Names are used in shader definitions:
+// the attribute name marks its presence, component names carry shader locations.
+const ATTRIBUTE_ORDER: [(VertexAttributes, &str, &[AttributeComponent]); 5] = [
+    (
+        VertexAttributes::NORMAL,
+        "NORMAL",
+        &[("NORMAL", wgpu::VertexFormat::Float32x3)],
+    ),
+    (
+        VertexAttributes::TANGENT_SPACE,
+        "TANGENT_SPACE",
+        &[
+            ("TANGENT", wgpu::VertexFormat::Float32x3),
+            ("BITANGENT", wgpu::VertexFormat::Float32x3),
+        ],
+    ),
+    (
+        VertexAttributes::UV,
+        "UV",
+        &[("UV", wgpu::VertexFormat::Float32x2)],
+    ),
+    (
+        VertexAttributes::COLOR,
+        "COLOR",
+        &[("COLOR", wgpu::VertexFormat::Float32x4)],
+    ),
+    (
+        VertexAttributes::SKIN,
+        "SKIN",
+        &[
+            ("JOINTS", wgpu::VertexFormat::Uint32x4),
+            ("WEIGHTS", wgpu::VertexFormat::Float32x4),
+        ],
+    ),
+];
+
+/// Vertex buffer layout generated from a set of attributes, together with shader
+/// definitions describing it, so new attribute combinations don't need hand-written
+/// layouts and shader variants.
+#[derive(Debug, Clone)]
+pub struct VertexLayout {
+    attributes: VertexAttributes,
+    vertex_attributes: Vec<wgpu::VertexAttribute>,
+    stride: u64,
+}
+
+impl VertexLayout {
+    pub fn new(attributes: VertexAttributes) -> Self {
+        let mut vertex_attributes = vec![wgpu::VertexAttribute {
+            format: wgpu::VertexFormat::Float32x3,
+            offset: 0,
+            shader_location: 0,
+        }];
+        let mut stride = wgpu::VertexFormat::Float32x3.size();
+
+        for (attribute, _, components) in ATTRIBUTE_ORDER {
+            if !attributes.contains(attribute) {
+                continue;
+            }
+
+            for &(_, format) in components {
+                vertex_attributes.push(wgpu::VertexAttribute {
+                    format,
+                    offset: stride,
+                    shader_location: vertex_attributes.len() as u32,
+                });
+                stride += format.size();
+            }
+        }
+
+        Self {
+            attributes,
+            vertex_attributes,
+            stride,
+        }
+    }
+
+    pub fn stride(&self) -> usize {
+        self.stride as usize
+    }
+
+    /// Number of shader locations taken. Instance attributes start right after them.
+    pub fn slots(&self) -> u32 {
+        self.vertex_attributes.len() as u32
+    }
+
+    pub fn buffer_layout(&self) -> wgpu::VertexBufferLayout<'_> {
+        wgpu::VertexBufferLayout {
+            array_stride: self.stride,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &self.vertex_attributes,
+        }
+    }
+
+    /// Defines `VERTEX_ATTRIBUTES`, `VERTEX_<ATTRIBUTE>` for every present attribute,
+    /// `VERTEX_LOCATION_<COMPONENT>` with shader locations of their parts and `VERTEX_SLOTS`.
+    pub fn with_shader_defs(&self, mut unit: CompilationUnit) -> CompilationUnit {
+        unit = unit
+            .with_def("VERTEX_ATTRIBUTES")
+            .with_integer_def("VERTEX_SLOTS", self.slots());
+
+        let mut location = 1;
+        for (attribute, name, components) in ATTRIBUTE_ORDER {
+            if !self.attributes.contains(attribute) {
+                continue;
+            }
+
+            unit = unit.with_def(format!("VERTEX_{}", name));
+            for (component, _) in components {
+                unit = unit.with_integer_def(format!("VERTEX_LOCATION_{}", component), location);
+                location += 1;
+            }
+        }
+
+        unit
+    }
+}
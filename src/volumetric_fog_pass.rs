@@ -0,0 +1,387 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use encase::{ShaderSize, UniformBuffer};
+use nalgebra as na;
+
+use crate::{
+    compute::{dispatch, Kernel},
+    gpu::Gpu,
+    light_scene::Light,
+    render_context::RenderContext,
+    render_pass::{FrameContext, RenderPass},
+    settings::VolumetricFogSettings,
+};
+
+/// Froxels of the fog volume, across the screen and in depth slices up to the fog range.
+pub const FOG_GRID: [u32; 3] = [160, 90, 64];
+const KERNEL: Kernel = Kernel::new([8, 8, 1]);
+
+// Fields of `FogParams` in the shader.
+type Params = [na::Vector4<f32>; 4];
+
+/// Light in-scattered by fog and transmittance between the camera and every froxel,
+/// sampled by lighting passes next to their lights.
+pub struct FogVolume {
+    integrated: wgpu::Texture,
+    view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    params_buf: wgpu::Buffer,
+}
+
+impl FogVolume {
+    fn new(gpu: &Gpu) -> Self {
+        let integrated = create_froxels(gpu, "FogVolume::Integrated");
+        let view = integrated.create_view(&Default::default());
+
+        let sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("FogVolume::Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let params_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("FogVolume::Params"),
+            size: Params::SHADER_SIZE.into(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            integrated,
+            view,
+            sampler,
+            params_buf,
+        }
+    }
+
+    /// Volume, sampler and parameters, in bindings following `first_binding`.
+    pub fn layout_entries(
+        first_binding: u32,
+        visibility: wgpu::ShaderStages,
+    ) -> [wgpu::BindGroupLayoutEntry; 3] {
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding: first_binding,
+                visibility,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D3,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: first_binding + 1,
+                visibility,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: first_binding + 2,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ]
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn sampler(&self) -> &wgpu::Sampler {
+        &self.sampler
+    }
+
+    pub fn params_buffer(&self) -> &wgpu::Buffer {
+        &self.params_buf
+    }
+}
+
+fn create_froxels(gpu: &Gpu, label: &str) -> wgpu::Texture {
+    gpu.device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: FOG_GRID[0],
+            height: FOG_GRID[1],
+            depth_or_array_layers: FOG_GRID[2],
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D3,
+        format: wgpu::TextureFormat::Rgba16Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+        view_formats: &[],
+    })
+}
+
+/// Lights fog in a froxel volume with the sun, shadowed by the cascaded shadow maps,
+/// then accumulates it front to back for lighting passes to apply.
+pub struct VolumetricFogPass<'window> {
+    render_ctx: Arc<RenderContext<'window>>,
+    volume: Arc<FogVolume>,
+    scatter_pipeline: wgpu::ComputePipeline,
+    integrate_pipeline: wgpu::ComputePipeline,
+    scatter_bg: wgpu::BindGroup,
+    integrate_bg: wgpu::BindGroup,
+}
+
+impl<'window> VolumetricFogPass<'window> {
+    pub fn new(render_ctx: Arc<RenderContext<'window>>) -> Result<Self> {
+        let RenderContext {
+            gpu,
+            bind_group_layouts,
+            shader_compiler,
+            scene_uniform,
+            ..
+        } = render_ctx.as_ref();
+        let shadow_bgl = bind_group_layouts.shadow(gpu);
+
+        let volume = FogVolume::new(gpu);
+        // Light scattered in every froxel, before it's accumulated along the view.
+        let scattering = create_froxels(gpu, "VolumetricFogPass::Scattering");
+        let scattering_view = scattering.create_view(&Default::default());
+
+        let params_entry = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let storage = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: wgpu::TextureFormat::Rgba16Float,
+                view_dimension: wgpu::TextureViewDimension::D3,
+            },
+            count: None,
+        };
+
+        let scatter_bgl = bind_group_layouts.get(
+            gpu,
+            "VolumetricFogPass::ScatterBindGroupLayout",
+            &[params_entry, storage(1)],
+        );
+
+        let integrate_bgl = bind_group_layouts.get(
+            gpu,
+            "VolumetricFogPass::IntegrateBindGroupLayout",
+            &[
+                params_entry,
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                storage(3),
+            ],
+        );
+
+        let scatter_bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("VolumetricFogPass::ScatterBindGroup"),
+            layout: &scatter_bgl,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: volume.params_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&scattering_view),
+                },
+            ],
+        });
+
+        let integrate_bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("VolumetricFogPass::IntegrateBindGroup"),
+            layout: &integrate_bgl,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: volume.params_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&scattering_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(
+                        &volume.integrated.create_view(&Default::default()),
+                    ),
+                },
+            ],
+        });
+
+        // Shadow maps are bound in the group deferred lighting has them in.
+        let module = KERNEL
+            .with_defs(gpu.with_depth_defs(
+                shader_compiler.compilation_unit("./shaders/fog/volumetric_fog.wgsl")?,
+            ))
+            .with_def("DEFERRED")
+            .compile(&[])?;
+        let shader = gpu.shader_from_module(module);
+
+        let pipeline = |label, entry_point, bind_group_layouts: &[&wgpu::BindGroupLayout]| {
+            let layout = gpu
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some(label),
+                    bind_group_layouts,
+                    push_constant_ranges: &[],
+                });
+
+            gpu.device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(&layout),
+                    module: &shader,
+                    entry_point,
+                })
+        };
+
+        Ok(Self {
+            scatter_pipeline: pipeline(
+                "VolumetricFogPass::Scatter",
+                "scatter",
+                &[scene_uniform.layout(), &scatter_bgl, &shadow_bgl],
+            ),
+            integrate_pipeline: pipeline(
+                "VolumetricFogPass::Integrate",
+                "integrate",
+                &[scene_uniform.layout(), &integrate_bgl],
+            ),
+            volume: Arc::new(volume),
+            render_ctx,
+            scatter_bg,
+            integrate_bg,
+        })
+    }
+
+    /// Fog for lighting passes to sample, ready once this pass renders.
+    pub fn volume(&self) -> Arc<FogVolume> {
+        self.volume.clone()
+    }
+
+    fn write_params(&self, settings: &VolumetricFogSettings, sun: &Light) -> Result<()> {
+        let RenderContext { gpu, .. } = self.render_ctx.as_ref();
+
+        let towards_sun = -sun
+            .direction
+            .xyz()
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_default();
+        let color = sun.diffuse.xyz() * settings.intensity;
+
+        let params: Params = [
+            na::Vector4::new(
+                settings.density,
+                settings.height_falloff,
+                settings.base_height,
+                settings.anisotropy,
+            ),
+            na::Vector4::new(
+                towards_sun.x,
+                towards_sun.y,
+                towards_sun.z,
+                if settings.enabled { 1.0 } else { 0.0 },
+            ),
+            na::Vector4::new(color.x, color.y, color.z, settings.ambient),
+            na::Vector4::new(settings.range, 0.0, 0.0, 0.0),
+        ];
+
+        let size: u64 = Params::SHADER_SIZE.into();
+        let mut contents = UniformBuffer::new(Vec::with_capacity(size as usize));
+        contents.write(&params)?;
+        gpu.queue
+            .write_buffer(&self.volume.params_buf, 0, contents.into_inner().as_slice());
+
+        Ok(())
+    }
+
+    pub fn render(
+        &self,
+        settings: &VolumetricFogSettings,
+        sun: &Light,
+        shadow_bg: &wgpu::BindGroup,
+    ) -> Result<()> {
+        let RenderContext {
+            gpu,
+            scene_uniform,
+            profiler,
+            ..
+        } = self.render_ctx.as_ref();
+
+        self.write_params(settings, sun)?;
+        if !settings.enabled {
+            return Ok(());
+        }
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("VolumetricFogPass::CommandEncoder"),
+            });
+
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("VolumetricFogPass::ComputePass"),
+                timestamp_writes: profiler.compute_pass_writes("Volumetric Fog"),
+            });
+
+            cpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
+
+            cpass.set_pipeline(&self.scatter_pipeline);
+            cpass.set_bind_group(1, &self.scatter_bg, &[]);
+            cpass.set_bind_group(2, shadow_bg, &[]);
+            dispatch(&mut cpass, &KERNEL, FOG_GRID);
+
+            cpass.set_pipeline(&self.integrate_pipeline);
+            cpass.set_bind_group(1, &self.integrate_bg, &[]);
+            dispatch(&mut cpass, &KERNEL, [FOG_GRID[0], FOG_GRID[1], 1]);
+        }
+
+        gpu.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
+
+impl RenderPass for VolumetricFogPass<'_> {
+    fn name(&self) -> &'static str {
+        "VolumetricFogPass"
+    }
+
+    // Runs with fog turned off too, to let lighting passes know they should skip it.
+    fn enabled(&self, _ctx: &FrameContext) -> bool {
+        true
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        let shadows = ctx
+            .shadows
+            .as_deref()
+            .context("fog is shadowed by the shadow maps, which have to be rendered first")?;
+
+        Self::render(self, &ctx.settings.volumetric_fog, &ctx.sun, shadows)
+    }
+}
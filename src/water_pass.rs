@@ -0,0 +1,509 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use encase::{ShaderSize, UniformBuffer};
+use nalgebra as na;
+
+use crate::{
+    gpu::Gpu,
+    light_scene::Light,
+    material::PhongVariant,
+    render_context::RenderContext,
+    render_graph::{Binding, GraphResources, PassIo, TextureDesc},
+    render_pass::{FrameContext, RenderPass},
+    scene::Instance,
+    settings::{PipelineType, WaterSettings},
+    shader_permutation::Variants,
+};
+
+pub const REFLECTION: &str = "WaterPass::Reflection";
+pub const REFLECTION_DEPTH: &str = "WaterPass::ReflectionDepth";
+
+const NORMAL_MAP: &str = "./textures/water/normal.png";
+
+// Fields of `Water` in the shader.
+type Params = [na::Vector4<f32>; 7];
+
+/// Water plane drawn over the HDR scene color. The scene is rendered once more,
+/// mirrored through the plane, for reflections, while refraction distorts the scene
+/// color behind the surface and darkens it with depth read from the depth buffer.
+/// Fresnel term blends the two.
+pub struct WaterPass<'window> {
+    render_ctx: Arc<RenderContext<'window>>,
+    params_buf: wgpu::Buffer,
+    reflection_bg: wgpu::BindGroup,
+    reflection_pipelines: Variants<PhongVariant, wgpu::RenderPipeline>,
+    surface_bgl: Arc<wgpu::BindGroupLayout>,
+    copy_pipeline: wgpu::RenderPipeline,
+    surface_pipeline: wgpu::RenderPipeline,
+    normal_map_view: wgpu::TextureView,
+    environment_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    output_tex: wgpu::Texture,
+}
+
+impl<'window> WaterPass<'window> {
+    pub fn new(
+        render_ctx: Arc<RenderContext<'window>>,
+        environment: &wgpu::Texture,
+    ) -> Result<Self> {
+        let RenderContext {
+            gpu,
+            bind_group_layouts,
+            shader_compiler,
+            scene_uniform,
+            material_atlas,
+            ..
+        } = render_ctx.as_ref();
+
+        let params_buf = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("WaterPass::Params"),
+            size: Params::SHADER_SIZE.into(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let params_entry = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let reflection_bgl =
+            bind_group_layouts.get(gpu, "WaterPass::ReflectionBindGroupLayout", &[params_entry]);
+
+        let reflection_bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("WaterPass::ReflectionBindGroup"),
+            layout: &reflection_bgl,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params_buf.as_entire_binding(),
+            }],
+        });
+
+        let texture = |binding, sample_type, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let filterable = wgpu::TextureSampleType::Float { filterable: true };
+
+        let surface_bgl = bind_group_layouts.get(
+            gpu,
+            "WaterPass::SurfaceBindGroupLayout",
+            &[
+                params_entry,
+                texture(1, filterable, wgpu::TextureViewDimension::D2),
+                texture(
+                    2,
+                    wgpu::TextureSampleType::Depth,
+                    wgpu::TextureViewDimension::D2,
+                ),
+                texture(3, filterable, wgpu::TextureViewDimension::D2),
+                texture(4, filterable, wgpu::TextureViewDimension::D2),
+                texture(5, filterable, wgpu::TextureViewDimension::Cube),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        );
+
+        let reflection_layout =
+            gpu.device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("WaterPass::ReflectionPipelineLayout"),
+                    bind_group_layouts: &[
+                        scene_uniform.layout(),
+                        &reflection_bgl,
+                        material_atlas.read().unwrap().layout(),
+                    ],
+                    push_constant_ranges: &[],
+                });
+
+        let module = shader_compiler
+            .compilation_unit("./shaders/water/reflection.wgsl")?
+            .with_integer_def("MATERIAL_GROUP", 2);
+        let reflection_shaders = gpu.shader_variants::<PhongVariant>(&module)?;
+
+        // Mirroring flips the winding of every triangle, so clockwise ones face the camera.
+        let reflection_pipelines = reflection_shaders.map(|PhongVariant(ty), shader| {
+            gpu.device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("WaterPass::ReflectionPipeline"),
+                    layout: Some(&reflection_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &Instance::draw_buffer_layouts(ty),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fs_main",
+                        targets: &[Some(wgpu::TextureFormat::Rgba16Float.into())],
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        front_face: wgpu::FrontFace::Cw,
+                        cull_mode: Some(wgpu::Face::Back),
+                        ..Default::default()
+                    },
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: wgpu::TextureFormat::Depth32Float,
+                        depth_write_enabled: true,
+                        depth_compare: gpu.depth_compare(wgpu::CompareFunction::Less),
+                        stencil: Default::default(),
+                        bias: Default::default(),
+                    }),
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                })
+        });
+
+        let surface_layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("WaterPass::SurfacePipelineLayout"),
+                bind_group_layouts: &[scene_uniform.layout(), &surface_bgl],
+                push_constant_ranges: &[],
+            });
+
+        let surface_shader = gpu.shader_from_module(
+            shader_compiler
+                .compilation_unit("./shaders/water/surface.wgsl")?
+                .compile(&[])?,
+        );
+
+        // Both draw a quad as a strip, the surface is seen from above and below.
+        let surface_pipeline = |label, vertex_entry, fragment_entry| {
+            gpu.device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(&surface_layout),
+                    vertex: wgpu::VertexState {
+                        module: &surface_shader,
+                        entry_point: vertex_entry,
+                        buffers: &[],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &surface_shader,
+                        entry_point: fragment_entry,
+                        targets: &[Some(wgpu::TextureFormat::Rgba16Float.into())],
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleStrip,
+                        ..Default::default()
+                    },
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                })
+        };
+
+        let copy_pipeline = surface_pipeline("WaterPass::CopyPipeline", "vs_fullscreen", "fs_copy");
+        let surface_pipeline = surface_pipeline("WaterPass::SurfacePipeline", "vs_main", "fs_main");
+
+        let normal_map_view = Self::load_normal_map(gpu)?.create_view(&Default::default());
+        let environment_view = environment.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+
+        let sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("WaterPass::Sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Ok(Self {
+            output_tex: Self::create_output(gpu),
+            render_ctx,
+            params_buf,
+            reflection_bg,
+            reflection_pipelines,
+            surface_bgl,
+            copy_pipeline,
+            surface_pipeline,
+            normal_map_view,
+            environment_view,
+            sampler,
+        })
+    }
+
+    fn load_normal_map(gpu: &Gpu) -> Result<wgpu::Texture> {
+        use wgpu::util::DeviceExt;
+
+        let image = image::open(NORMAL_MAP)
+            .with_context(|| format!("failed to load water normal map {NORMAL_MAP}"))?
+            .to_rgba8();
+
+        Ok(gpu.device.create_texture_with_data(
+            &gpu.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("WaterPass::NormalMap"),
+                size: wgpu::Extent3d {
+                    width: image.width(),
+                    height: image.height(),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            image.as_raw(),
+        ))
+    }
+
+    fn create_output(gpu: &Gpu) -> wgpu::Texture {
+        gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("WaterPass::Output"),
+            size: gpu.viewport_size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        })
+    }
+
+    pub fn output_tex_view(&self) -> wgpu::TextureView {
+        self.output_tex.create_view(&Default::default())
+    }
+
+    fn write_params(&self, gpu: &Gpu, settings: &WaterSettings, sun: &Light) -> Result<()> {
+        let [r, g, b] = settings.color;
+        let params: Params = [
+            na::Vector4::new(settings.height, settings.size, 0.0, settings.wave_scale),
+            na::Vector4::new(
+                settings.wave_speed,
+                settings.distortion,
+                settings.clarity,
+                0.0,
+            ),
+            na::Vector4::new(r, g, b, 1.0),
+            sun.direction,
+            sun.ambient,
+            sun.diffuse,
+            sun.specular,
+        ];
+
+        let size: u64 = Params::SHADER_SIZE.into();
+        let mut contents = UniformBuffer::new(Vec::with_capacity(size as usize));
+        contents.write(&params)?;
+        gpu.queue
+            .write_buffer(&self.params_buf, 0, contents.into_inner().as_slice());
+
+        Ok(())
+    }
+
+    pub fn render(
+        &self,
+        scene_color: &wgpu::TextureView,
+        settings: &WaterSettings,
+        sun: &Light,
+        resources: &GraphResources,
+    ) -> Result<()> {
+        let RenderContext {
+            gpu,
+            scene_uniform,
+            gpu_scene,
+            material_atlas,
+            profiler,
+            ..
+        } = self.render_ctx.as_ref();
+
+        self.write_params(gpu, settings, sun)?;
+
+        let depth = gpu.depth_texture_view();
+        let surface_bg = resources.bind_group(
+            gpu,
+            "WaterPass::SurfaceBindGroup",
+            &self.surface_bgl,
+            &[
+                Binding::Buffer(&self.params_buf),
+                Binding::View(scene_color),
+                Binding::View(&depth),
+                Binding::Resource(REFLECTION),
+                Binding::View(&self.normal_map_view),
+                Binding::View(&self.environment_view),
+                Binding::Sampler(&self.sampler),
+            ],
+        )?;
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("WaterPass::CommandEncoder"),
+            });
+
+        {
+            let scene = gpu_scene.read().unwrap();
+            let atlas = material_atlas.read().unwrap();
+
+            // Sky is left transparent, the surface reflects the environment map there.
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("WaterPass::ReflectionRenderPass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: resources.view(REFLECTION)?,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: resources.view(REFLECTION_DEPTH)?,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(gpu.far_depth()),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: profiler.render_pass_writes("Water Reflection"),
+                occlusion_query_set: None,
+            });
+
+            rpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
+            rpass.set_bind_group(1, &self.reflection_bg, &[]);
+            rpass.set_bind_group(2, atlas.bind_group(), &[]);
+
+            for batch in scene.draw_batches() {
+                let first = &batch[0];
+                rpass.set_pipeline(
+                    &self.reflection_pipelines[PhongVariant(first.vertex_array_type)],
+                );
+
+                rpass.set_vertex_buffer(
+                    0,
+                    scene
+                        .vertex_buffer_by_type(first.vertex_array_type)
+                        .slice(..),
+                );
+                rpass.set_vertex_buffer(
+                    1,
+                    scene.instance_buffer_by_type(first.instance_type).slice(..),
+                );
+
+                if first.indexed {
+                    rpass.set_index_buffer(
+                        scene.index_buffer().slice(..),
+                        wgpu::IndexFormat::Uint32,
+                    );
+                }
+
+                for draw_call in batch {
+                    if draw_call.indexed {
+                        rpass.draw_indexed_indirect(
+                            scene.indexed_draw_buffer(),
+                            draw_call.draw_buffer_offset,
+                        );
+                    } else {
+                        rpass.draw_indirect(
+                            scene.non_indexed_draw_buffer(),
+                            draw_call.draw_buffer_offset,
+                        );
+                    }
+                }
+            }
+        }
+
+        {
+            let output = self.output_tex_view();
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("WaterPass::SurfaceRenderPass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: profiler.render_pass_writes("Water"),
+                occlusion_query_set: None,
+            });
+
+            rpass.set_bind_group(0, scene_uniform.bind_group(), &[]);
+            rpass.set_bind_group(1, &surface_bg, &[]);
+
+            rpass.set_pipeline(&self.copy_pipeline);
+            rpass.draw(0..4, 0..1);
+            rpass.set_pipeline(&self.surface_pipeline);
+            rpass.draw(0..4, 0..1);
+        }
+
+        gpu.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
+
+impl RenderPass for WaterPass<'_> {
+    fn name(&self) -> &'static str {
+        "WaterPass"
+    }
+
+    fn enabled(&self, ctx: &FrameContext) -> bool {
+        let settings = ctx.settings;
+        settings.water.enabled
+            && settings.pipeline_type == PipelineType::Deferred
+            && !settings.deferred_debug_shown()
+    }
+
+    fn io(&self) -> PassIo {
+        PassIo::default()
+            .write(
+                REFLECTION,
+                TextureDesc::target(wgpu::TextureFormat::Rgba16Float),
+            )
+            .write(
+                REFLECTION_DEPTH,
+                TextureDesc::target(wgpu::TextureFormat::Depth32Float),
+            )
+    }
+
+    fn resize(&mut self, _size: (u32, u32)) -> Result<()> {
+        self.output_tex = Self::create_output(&self.render_ctx.gpu);
+
+        Ok(())
+    }
+
+    fn render(&mut self, ctx: &mut FrameContext) -> Result<()> {
+        let (scene_color, _) = ctx
+            .scene_color
+            .as_ref()
+            .filter(|(_, hdr)| *hdr)
+            .context("water is drawn over a HDR scene color")?;
+        Self::render(
+            self,
+            scene_color,
+            &ctx.settings.water,
+            &ctx.sun,
+            &ctx.resources,
+        )?;
+        ctx.scene_color = Some((self.output_tex_view(), true));
+
+        Ok(())
+    }
+}